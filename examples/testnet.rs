@@ -25,8 +25,8 @@ use exonum::node::Node;
 use exonum::storage::{RocksDB, RocksDBOptions};
 use exonum::helpers::{generate_testnet_config, init_logger};
 
-use exonum_btc_anchoring::{AnchoringRpcConfig, AnchoringService, BitcoinNetwork,
-                           gen_anchoring_testnet_config, RpcClient};
+use exonum_btc_anchoring::{AnchoringRpcConfig, AnchoringService, BitcoinChain,
+                           gen_anchoring_testnet_config, RpcClient, ScriptType};
 
 fn main() {
     // Init crypto engine and pretty logger.
@@ -41,6 +41,7 @@ fn main() {
             .unwrap(),
         username: env::var("ANCHORING_USER").ok(),
         password: env::var("ANCHORING_PASSWORD").ok(),
+        ..Default::default()
     };
 
     // Blockchain params
@@ -53,8 +54,14 @@ fn main() {
 
     // Generate blockchain configuration
     let client = RpcClient::from(rpc_config.clone());
-    let (anchoring_common, anchoring_nodes) =
-        gen_anchoring_testnet_config(&client, BitcoinNetwork::Testnet, count, total_funds);
+    let (anchoring_common, anchoring_nodes) = gen_anchoring_testnet_config(
+        &client,
+        BitcoinChain::Testnet,
+        ScriptType::P2sh,
+        None,
+        count,
+        total_funds,
+    );
     let node_cfgs = generate_testnet_config(count, start_port);
 
     // Create testnet threads
@@ -0,0 +1,27 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate exonum_btc_anchoring;
+
+use exonum_btc_anchoring::details::btc::transactions::TxKind;
+
+fuzz_target!(|data: &[u8]| if let Some(TxKind::Anchoring(tx)) = TxKind::from_bytes(data) {
+    // The methods of the classified anchoring transaction must not panic too.
+    tx.payload();
+    tx.prev_hash();
+    tx.output_address(exonum_btc_anchoring::BitcoinNetwork::Testnet);
+});
@@ -0,0 +1,799 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification primitives for the light clients.
+//!
+//! The functions of this crate check the anchoring proofs returned by the api, i.e. decode
+//! the anchoring payload, recompute the `txid` of the anchoring transaction and its merkle
+//! root and check the headers of the bitcoin blocks. They work with the raw bytes,
+//! depend neither on the `Exonum` node, the storage nor on the bitcoin backend and never
+//! allocate, so the crate is `no_std`. The anchoring service re-exports it as
+//! the `light` module.
+//!
+//! All bitcoin hashes are in the internal byte order, which is reversed relative to
+//! the hex strings displayed by `bitcoind`.
+
+#![no_std]
+#![deny(missing_docs, missing_debug_implementations)]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+extern crate sha2;
+
+use sha2::{Digest, Sha256};
+
+/// Size of the hashes in bytes.
+pub const HASH_SIZE: usize = 32;
+/// Size of the bitcoin block header in bytes.
+pub const HEADER_SIZE: usize = 80;
+
+/// The `SHA-256` hash or the bitcoin double `SHA-256` hash.
+pub type Hash256 = [u8; HASH_SIZE];
+
+const PAYLOAD_PREFIX: &[u8] = b"EXONUM";
+const PAYLOAD_HEADER_LEN: usize = 8;
+const OP_RETURN: u8 = 0x6a;
+
+/// Error of the anchoring proof verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofError {
+    /// The script is not an anchoring payload.
+    NotPayload,
+    /// The anchoring payload has the unknown format version.
+    UnknownPayloadVersion(u8),
+    /// The anchoring payload of the known format version is malformed.
+    MalformedPayload,
+    /// The bitcoin transaction cannot be decoded.
+    MalformedTx,
+    /// The transaction has no output with the anchoring payload.
+    NoPayload,
+    /// The merkle block cannot be decoded.
+    MalformedMerkleBlock,
+    /// The merkle root of the merkle block differs from the one of its header.
+    MerkleRootMismatch,
+    /// The transaction is not matched by the merkle block.
+    TxNotIncluded,
+    /// The header cannot be decoded.
+    MalformedHeader,
+    /// The header does not follow the expected block.
+    BrokenHeaders,
+    /// The header hash does not satisfy the target of its `bits` field.
+    InsufficientWork,
+}
+
+/// Anchoring payload decoded from the `OP_RETURN` output of the anchoring transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnchorPayload {
+    /// Anchored block height.
+    pub block_height: u64,
+    /// Anchored block hash.
+    pub block_hash: Hash256,
+    /// `Txid` of previous transactions chain if it has been lost.
+    pub prev_tx_chain: Option<Hash256>,
+    /// State hash of the anchored block if it is committed by the payload.
+    pub state_hash: Option<Hash256>,
+    /// Root of the application commitments if they are committed by the payload,
+    /// see [`commitments_root`](fn.commitments_root.html).
+    pub commitments: Option<Hash256>,
+    /// Identifier of the anchored blockchain if it is committed by the payload.
+    pub chain_id: Option<u32>,
+}
+
+/// Decodes the anchoring payload of any known format version from the serialized
+/// `script_pubkey`, see [`Payload`](../details/btc/payload/struct.Payload.html)
+/// for the layouts.
+pub fn parse_payload(script_pubkey: &[u8]) -> Result<AnchorPayload, ProofError> {
+    let bytes = match script_pubkey.split_first() {
+        Some((&OP_RETURN, rest)) => {
+            match split_push_data(rest) {
+                Some(bytes) => bytes,
+                None => return Err(ProofError::NotPayload),
+            }
+        }
+        _ => return Err(ProofError::NotPayload),
+    };
+    if bytes.len() < PAYLOAD_HEADER_LEN || &bytes[0..6] != PAYLOAD_PREFIX {
+        return Err(ProofError::NotPayload);
+    }
+
+    let (version, kind, data) = (bytes[6], bytes[7], &bytes[8..]);
+    let hash_at = |offset: usize| {
+        let mut hash = [0; HASH_SIZE];
+        hash.copy_from_slice(&data[offset..offset + HASH_SIZE]);
+        hash
+    };
+    let mut payload = AnchorPayload {
+        block_height: 0,
+        block_hash: [0; HASH_SIZE],
+        prev_tx_chain: None,
+        state_hash: None,
+        commitments: None,
+        chain_id: None,
+    };
+    match (version, kind, data.len()) {
+        (1, 0, 40) | (1, 1, 72) | (2, 0, 72) | (2, 1, 72) => {
+            payload.block_height = read_u64(&data[0..8]);
+            payload.block_hash = hash_at(8);
+            match (version, kind) {
+                (1, 1) => payload.prev_tx_chain = Some(hash_at(40)),
+                (2, 0) => payload.state_hash = Some(hash_at(40)),
+                (2, 1) => payload.commitments = Some(hash_at(40)),
+                _ => {}
+            }
+        }
+        (3, 0, 40) | (3, 1, 72) | (3, 2, 72) | (3, 3, 72) => {
+            payload.chain_id = Some(read_u32(&data[0..4]));
+            payload.block_height = u64::from(read_u32(&data[4..8]));
+            payload.block_hash = hash_at(8);
+            match kind {
+                1 => payload.prev_tx_chain = Some(hash_at(40)),
+                2 => payload.state_hash = Some(hash_at(40)),
+                3 => payload.commitments = Some(hash_at(40)),
+                _ => {}
+            }
+        }
+        (1, _, _) | (2, _, _) | (3, _, _) => return Err(ProofError::MalformedPayload),
+        _ => return Err(ProofError::UnknownPayloadVersion(version)),
+    }
+    Ok(payload)
+}
+
+/// Bitcoin transaction decoded from its raw bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct RawTx<'a> {
+    txid: Hash256,
+    outputs: &'a [u8],
+    outputs_count: u64,
+}
+
+impl<'a> RawTx<'a> {
+    /// Decodes the transaction in the legacy or the segwit serialization and computes its `txid`.
+    pub fn parse(raw: &'a [u8]) -> Result<RawTx<'a>, ProofError> {
+        let mut reader = Reader::new(raw);
+        let version = reader.bytes(4)?;
+        let segwit = reader.data.get(reader.pos) == Some(&0);
+        if segwit && reader.bytes(2)?[1] != 1 {
+            return Err(ProofError::MalformedTx);
+        }
+
+        let inputs_start = reader.pos;
+        let inputs_count = reader.varint()?;
+        for _ in 0..inputs_count {
+            reader.bytes(36)?;
+            let script_len = reader.varint()?;
+            reader.skip(script_len)?;
+            reader.bytes(4)?;
+        }
+        let outputs_count = reader.varint()?;
+        let outputs_start = reader.pos;
+        for _ in 0..outputs_count {
+            reader.bytes(8)?;
+            let script_len = reader.varint()?;
+            reader.skip(script_len)?;
+        }
+        let outputs_end = reader.pos;
+        if segwit {
+            for _ in 0..inputs_count {
+                let items_count = reader.varint()?;
+                for _ in 0..items_count {
+                    let item_len = reader.varint()?;
+                    reader.skip(item_len)?;
+                }
+            }
+        }
+        let lock_time = reader.bytes(4)?;
+        if reader.pos != raw.len() {
+            return Err(ProofError::MalformedTx);
+        }
+
+        // The `txid` does not commit to the witnesses.
+        let mut hasher = Sha256::default();
+        hasher.input(version);
+        hasher.input(&raw[inputs_start..outputs_end]);
+        hasher.input(lock_time);
+        Ok(RawTx {
+            txid: sha256(&finish(hasher)),
+            outputs: &raw[outputs_start..outputs_end],
+            outputs_count,
+        })
+    }
+
+    /// Returns the `txid` of the transaction.
+    pub fn txid(&self) -> Hash256 {
+        self.txid
+    }
+
+    /// Returns the iterator over the values and the `script_pubkey`s of the outputs.
+    pub fn outputs(&self) -> TxOutputs<'a> {
+        TxOutputs {
+            reader: Reader::new(self.outputs),
+            remaining: self.outputs_count,
+        }
+    }
+
+    /// Checks that the first output of the anchoring transaction pays to the given
+    /// `script_pubkey` of the anchoring address.
+    pub fn pays_to(&self, script_pubkey: &[u8]) -> bool {
+        self.outputs().next().map(|(_, script)| script) == Some(script_pubkey)
+    }
+
+    /// Decodes the anchoring payload from the first output which contains it.
+    pub fn payload(&self) -> Result<AnchorPayload, ProofError> {
+        for (_, script) in self.outputs() {
+            match parse_payload(script) {
+                Err(ProofError::NotPayload) => {}
+                result => return result,
+            }
+        }
+        Err(ProofError::NoPayload)
+    }
+}
+
+/// Iterator over the outputs of the [`RawTx`](struct.RawTx.html).
+#[derive(Debug, Clone)]
+pub struct TxOutputs<'a> {
+    reader: Reader<'a>,
+    remaining: u64,
+}
+
+impl<'a> Iterator for TxOutputs<'a> {
+    type Item = (u64, &'a [u8]);
+
+    fn next(&mut self) -> Option<(u64, &'a [u8])> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // The outputs are checked by `RawTx::parse`.
+        let value = match self.reader.bytes(8) {
+            Ok(value) => read_u64(value),
+            Err(_) => return None,
+        };
+        match self.reader.varint().and_then(|len| self.reader.skip(len)) {
+            Ok(script) => Some((value, script)),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Computes the merkle root of the bitcoin block from the `txid` of the transaction with
+/// the given `index` in the block and its merkle `branch` in the format of the
+/// `blockchain.transaction.get_merkle` call of the `Electrum` servers.
+pub fn merkle_root(txid: &Hash256, index: u32, branch: &[Hash256]) -> Hash256 {
+    let mut hash = *txid;
+    let mut index = index;
+    for sibling in branch {
+        hash = if index & 1 == 0 {
+            merkle_parent(&hash, sibling)
+        } else {
+            merkle_parent(sibling, &hash)
+        };
+        index >>= 1;
+    }
+    hash
+}
+
+/// Verifies that the merkle block in the format of the `gettxoutproof` call of `bitcoind`
+/// includes the transaction with the given `txid` and the hash of its header satisfies
+/// the target of its `bits`, returns the hash of the block.
+///
+/// As in the [`verify_header`](fn.verify_header.html), the `bits` are not checked against
+/// the difficulty adjustment rules.
+pub fn verify_merkle_block(merkle_block: &[u8], txid: &Hash256) -> Result<Hash256, ProofError> {
+    let malformed = |_| ProofError::MalformedMerkleBlock;
+    let mut reader = Reader::new(merkle_block);
+    let header = reader.bytes(HEADER_SIZE).map_err(malformed)?;
+    let total = read_u32(reader.bytes(4).map_err(malformed)?);
+    let hashes_count = reader.varint().map_err(malformed)?;
+    if hashes_count > u64::from(total) {
+        return Err(ProofError::MalformedMerkleBlock);
+    }
+    let hashes = reader.skip(hashes_count * HASH_SIZE as u64).map_err(malformed)?;
+    let flags_len = reader.varint().map_err(malformed)?;
+    let flags = reader.skip(flags_len).map_err(malformed)?;
+    if total == 0 || reader.pos != merkle_block.len() || (flags.len() as u64) * 8 < hashes_count {
+        return Err(ProofError::MalformedMerkleBlock);
+    }
+
+    let mut tree = PartialMerkleTree {
+        total: u64::from(total),
+        hashes,
+        flags,
+        hashes_used: 0,
+        bits_used: 0,
+        txid,
+        matched: false,
+    };
+    let mut height = 0;
+    while tree.width(height) > 1 {
+        height += 1;
+    }
+    let root = tree.traverse(height, 0)?;
+    if tree.hashes_used * HASH_SIZE != hashes.len() || (tree.bits_used + 7) / 8 != flags.len() {
+        return Err(ProofError::MalformedMerkleBlock);
+    }
+    if root[..] != header[36..68] {
+        return Err(ProofError::MerkleRootMismatch);
+    }
+    if !tree.matched {
+        return Err(ProofError::TxNotIncluded);
+    }
+    check_work(header)
+}
+
+/// Verifies that the bitcoin block `header` follows the block with the `prev_hash` and
+/// its hash satisfies the target encoded by its `bits`, returns the hash of the header.
+///
+/// The `bits` are not checked against the difficulty adjustment rules, so the client
+/// should compare them with the difficulty it expects.
+pub fn verify_header(header: &[u8], prev_hash: &Hash256) -> Result<Hash256, ProofError> {
+    if header.len() != HEADER_SIZE {
+        return Err(ProofError::MalformedHeader);
+    }
+    if header[4..36] != prev_hash[..] {
+        return Err(ProofError::BrokenHeaders);
+    }
+    check_work(header)
+}
+
+// Checks that the hash of the header satisfies the target encoded by its `bits`,
+// returns the hash of the header.
+fn check_work(header: &[u8]) -> Result<Hash256, ProofError> {
+    let target = match compact_target(read_u32(&header[72..76])) {
+        Some(target) => target,
+        None => return Err(ProofError::MalformedHeader),
+    };
+    let hash = sha256d(header);
+    // The hash is compared as the little endian number.
+    for (hash_byte, target_byte) in hash.iter().rev().zip(target.iter()) {
+        if hash_byte != target_byte {
+            return if hash_byte < target_byte {
+                Ok(hash)
+            } else {
+                Err(ProofError::InsufficientWork)
+            };
+        }
+    }
+    Ok(hash)
+}
+
+/// Computes the `SHA-256` hash of the data, it is used by `Exonum` for the block hashes.
+pub fn sha256(data: &[u8]) -> Hash256 {
+    let mut hasher = Sha256::default();
+    hasher.input(data);
+    finish(hasher)
+}
+
+/// Computes the double `SHA-256` hash of the data, it is used by bitcoin for the `txid`s,
+/// the merkle trees and the block hashes.
+pub fn sha256d(data: &[u8]) -> Hash256 {
+    sha256(&sha256(data))
+}
+
+/// Computes the root of the application commitments in the same way as the anchoring
+/// service does, `None` is returned if there are no commitments.
+pub fn commitments_root(commitments: &[Hash256]) -> Option<Hash256> {
+    match commitments.len() {
+        0 => None,
+        1 => Some(commitments[0]),
+        len => {
+            let mut split = 1;
+            while split * 2 < len {
+                split *= 2;
+            }
+            let (left, right) = commitments.split_at(split);
+            let mut hasher = Sha256::default();
+            hasher.input(&[1]);
+            hasher.input(&commitments_root(left).unwrap());
+            hasher.input(&commitments_root(right).unwrap());
+            Some(finish(hasher))
+        }
+    }
+}
+
+fn merkle_parent(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut hasher = Sha256::default();
+    hasher.input(left);
+    hasher.input(right);
+    sha256(&finish(hasher))
+}
+
+fn finish(hasher: Sha256) -> Hash256 {
+    let mut hash = [0; HASH_SIZE];
+    hash.copy_from_slice(&hasher.result());
+    hash
+}
+
+// Decodes the compact representation of the target into the big endian number,
+// returns `None` for the negative or the overflowing targets.
+fn compact_target(bits: u32) -> Option<Hash256> {
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+    let exponent = (bits >> 24) as usize;
+    let mantissa = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+    let mut target = [0; HASH_SIZE];
+    for (i, &byte) in mantissa.iter().enumerate() {
+        // The mantissa byte `i` is placed at the `HASH_SIZE - exponent + i` position.
+        let position = HASH_SIZE + i;
+        if position < exponent {
+            if byte != 0 {
+                return None;
+            }
+        } else if position - exponent < HASH_SIZE {
+            target[position - exponent] = byte;
+        }
+    }
+    Some(target)
+}
+
+// Traversal of the partial merkle tree in the depth-first order, as it is done
+// by `CPartialMerkleTree` of `bitcoind`.
+struct PartialMerkleTree<'a> {
+    total: u64,
+    hashes: &'a [u8],
+    flags: &'a [u8],
+    hashes_used: usize,
+    bits_used: usize,
+    txid: &'a Hash256,
+    matched: bool,
+}
+
+impl<'a> PartialMerkleTree<'a> {
+    fn width(&self, height: u32) -> u64 {
+        (self.total + (1 << height) - 1) >> height
+    }
+
+    fn traverse(&mut self, height: u32, position: u64) -> Result<Hash256, ProofError> {
+        let byte = match self.flags.get(self.bits_used / 8) {
+            Some(byte) => *byte,
+            None => return Err(ProofError::MalformedMerkleBlock),
+        };
+        let is_parent_of_match = byte & (1 << (self.bits_used % 8)) != 0;
+        self.bits_used += 1;
+
+        if height == 0 || !is_parent_of_match {
+            let offset = self.hashes_used * HASH_SIZE;
+            if offset + HASH_SIZE > self.hashes.len() {
+                return Err(ProofError::MalformedMerkleBlock);
+            }
+            self.hashes_used += 1;
+            let mut hash = [0; HASH_SIZE];
+            hash.copy_from_slice(&self.hashes[offset..offset + HASH_SIZE]);
+            if height == 0 && is_parent_of_match && hash == *self.txid {
+                self.matched = true;
+            }
+            return Ok(hash);
+        }
+
+        let left = self.traverse(height - 1, position * 2)?;
+        let right = if position * 2 + 1 < self.width(height - 1) {
+            let right = self.traverse(height - 1, position * 2 + 1)?;
+            // The identical children allow to forge the merkle trees with the duplicated
+            // transactions, see CVE-2012-2459.
+            if right == left {
+                return Err(ProofError::MalformedMerkleBlock);
+            }
+            right
+        } else {
+            left
+        };
+        Ok(merkle_parent(&left, &right))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProofError> {
+        if self.data.len() - self.pos < len {
+            return Err(ProofError::MalformedTx);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: u64) -> Result<&'a [u8], ProofError> {
+        if ((self.data.len() - self.pos) as u64) < len {
+            return Err(ProofError::MalformedTx);
+        }
+        self.bytes(len as usize)
+    }
+
+    fn varint(&mut self) -> Result<u64, ProofError> {
+        let prefix = self.bytes(1)?[0];
+        let value = match prefix {
+            0xfd => u64::from(read_u16(self.bytes(2)?)),
+            0xfe => u64::from(read_u32(self.bytes(4)?)),
+            0xff => read_u64(self.bytes(8)?),
+            _ => u64::from(prefix),
+        };
+        Ok(value)
+    }
+}
+
+// Splits the data of the first push instruction of the script, the same as the anchoring
+// payload parser does.
+fn split_push_data(script: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(script);
+    let opcode = match reader.bytes(1) {
+        Ok(opcode) => opcode[0],
+        Err(_) => return None,
+    };
+    let len = match opcode {
+        0x01...0x4b => Ok(u64::from(opcode)),
+        0x4c => reader.bytes(1).map(|len| u64::from(len[0])),
+        0x4d => reader.bytes(2).map(|len| u64::from(read_u16(len))),
+        0x4e => reader.bytes(4).map(|len| u64::from(read_u32(len))),
+        _ => return None,
+    };
+    len.and_then(|len| reader.skip(len)).ok()
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) | u16::from(bytes[1]) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(read_u16(&bytes[0..2])) | u32::from(read_u16(&bytes[2..4])) << 16
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from(read_u32(&bytes[0..4])) | u64::from(read_u32(&bytes[4..8])) << 32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len() / 2)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn reversed(hash: &Hash256) -> Hash256 {
+        let mut reversed = *hash;
+        reversed.reverse();
+        reversed
+    }
+
+    fn payload_script(data: &[u8]) -> Vec<u8> {
+        let mut script = vec![OP_RETURN];
+        if data.len() > 0x4b {
+            script.push(0x4c);
+        }
+        script.push(data.len() as u8);
+        script.extend_from_slice(data);
+        script
+    }
+
+    #[test]
+    fn test_light_sha256() {
+        assert_eq!(
+            sha256(b"")[..],
+            from_hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")[..]
+        );
+        assert_eq!(
+            sha256(b"abc")[..],
+            from_hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")[..]
+        );
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")[..],
+            from_hex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")[..]
+        );
+    }
+
+    #[test]
+    fn test_light_payload_versions() {
+        let mut data = b"EXONUM\x01\x00".to_vec();
+        data.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[1; 32]);
+        let payload = parse_payload(&payload_script(&data)).unwrap();
+        assert_eq!(payload.block_height, 10);
+        assert_eq!(payload.block_hash, [1; 32]);
+        assert_eq!(payload.chain_id, None);
+        assert_eq!(payload.state_hash, None);
+
+        let mut data = b"EXONUM\x03\x02".to_vec();
+        data.extend_from_slice(&[7, 0, 0, 0, 20, 0, 0, 0]);
+        data.extend_from_slice(&[1; 32]);
+        data.extend_from_slice(&[2; 32]);
+        let payload = parse_payload(&payload_script(&data)).unwrap();
+        assert_eq!(payload.block_height, 20);
+        assert_eq!(payload.chain_id, Some(7));
+        assert_eq!(payload.state_hash, Some([2; 32]));
+        assert_eq!(payload.prev_tx_chain, None);
+
+        data.pop();
+        assert_eq!(
+            parse_payload(&payload_script(&data)),
+            Err(ProofError::MalformedPayload)
+        );
+        data[6] = 42;
+        assert_eq!(
+            parse_payload(&payload_script(&data)),
+            Err(ProofError::UnknownPayloadVersion(42))
+        );
+        assert_eq!(parse_payload(&[OP_RETURN, 0x4c]), Err(ProofError::NotPayload));
+        assert_eq!(parse_payload(b"EXONUM"), Err(ProofError::NotPayload));
+    }
+
+    #[test]
+    fn test_light_commitments() {
+        let mut data = b"EXONUM\x03\x03".to_vec();
+        data.extend_from_slice(&[7, 0, 0, 0, 20, 0, 0, 0]);
+        data.extend_from_slice(&[1; 32]);
+        data.extend_from_slice(&[2; 32]);
+        let payload = parse_payload(&payload_script(&data)).unwrap();
+        assert_eq!(payload.commitments, Some([2; 32]));
+        assert_eq!(payload.state_hash, None);
+    }
+
+    #[test]
+    fn test_light_anchoring_txid() {
+        let raw = from_hex(
+            "010000000195a4472606ae658f1b9cbebd43f440def00c94341a3515024855\
+             a1da8d80932800000000fd3d020047304402204e11d63db849f253095e1e0a400f2f0c01894083e97bfa\
+             ef644b1407b9fe5c4102207cc99ca986dfd99230e6641564d1f70009c5ec9a37da815c4e024c3ba837c0\
+             1301483045022100d32536daa6e13989ebc7c908c27a0608517d5d967c8b6069dc047faa01e2a0960220\
+             30f9c46738d9b701dd944ce3e31af9898b9266460b2de6ff3319f2a8c51f7b430147304402206b8e4491\
+             e3b98861ba06cf64e78f425cc553110535310f56f71dcd37de590b7f022051f0fa53cb74a1c732472241\
+             80cf026b61b7959d587ab6365dd19a279d14cf45014830450221009fa024c767d8004eef882c6cffe960\
+             2f781c60d1a7c629d58576e3de41833a5b02206d3b8dc86d052e112305e1fb32f61de77236f057523e22\
+             d58d82cbe37222e8fa01483045022100f1784c5e321fb2753fe725381d6f922d3f0edb94ff2eef52063f\
+             9c812489f61802202bec2903af6a5405db484ac73ab844707382f39a0b286a0453f2ed41d217c89e014c\
+             cf5521027b3e1c603ead09953bd0a8bd13a7a4830a1446289969220b96515dd1745e06f521026b64f403\
+             914e43b7ebe9aa23017eb75eef1bc74469f8b1fa342e622565ab28db2103503745e14331dac53528e666\
+             f1abab2c6b6e28767539a2827fe080bb475ec25021030a2ff505279a0e58cc3951ada56bcf323955550d\
+             1b993c4cb1b7e94a672b31252102ebb5a22d5ec3c2bc36ab7e104553a89c69684a4dfb3c8ea8fe2cb785\
+             c63425872102d9fea63c62d7cafcd4a3d20d77e06cf80cb25f3277ffce27d99c98f439323cee56aeffff\
+             ffff02000000000000000017a914ab6db56dbd716114594a0d3f072ec447f6d8fc698700000000000000\
+             002c6a2a0128020000000000000062467691cf583d4fa78b18fafaf9801f505e0ef03baf0603fd4b0cd0\
+             04cd1e7500000000",
+        );
+        let tx = RawTx::parse(&raw).unwrap();
+        assert_eq!(
+            reversed(&tx.txid())[..],
+            from_hex("0e4167aeb4769de5ad8d64d1b2342330c2b6aadc0ed9ad0d26ae8eafb18d9c87")[..]
+        );
+        assert_eq!(tx.outputs().count(), 2);
+        assert!(tx.pays_to(&from_hex("a914ab6db56dbd716114594a0d3f072ec447f6d8fc6987")));
+        assert!(!tx.pays_to(&from_hex("a914ab6db56dbd716114594a0d3f072ec447f6d8fc6988")));
+        // The payload of this transaction precedes the current format.
+        assert_eq!(tx.payload(), Err(ProofError::NoPayload));
+
+        assert_eq!(RawTx::parse(&raw[..raw.len() - 1]).unwrap_err(), ProofError::MalformedTx);
+    }
+
+    #[test]
+    fn test_light_segwit_txid() {
+        let mut data = b"EXONUM\x01\x00".to_vec();
+        data.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[1; 32]);
+        let payload = payload_script(&data);
+
+        let mut inputs = vec![1];
+        inputs.extend_from_slice(&[3; 36]);
+        inputs.extend_from_slice(&[0, 0xff, 0xff, 0xff, 0xff]);
+        let mut outputs = vec![2];
+        outputs.extend_from_slice(&[0x10, 0x27, 0, 0, 0, 0, 0, 0, 3, 0x00, 0x01, 0x02]);
+        outputs.extend_from_slice(&[0; 8]);
+        outputs.push(payload.len() as u8);
+        outputs.extend_from_slice(&payload);
+
+        let mut legacy = vec![2, 0, 0, 0];
+        legacy.extend_from_slice(&inputs);
+        legacy.extend_from_slice(&outputs);
+        legacy.extend_from_slice(&[0; 4]);
+        let mut segwit = vec![2, 0, 0, 0, 0, 1];
+        segwit.extend_from_slice(&inputs);
+        segwit.extend_from_slice(&outputs);
+        segwit.extend_from_slice(&[2, 3, 4, 5, 6, 0]);
+        segwit.extend_from_slice(&[0; 4]);
+
+        let legacy_tx = RawTx::parse(&legacy).unwrap();
+        let segwit_tx = RawTx::parse(&segwit).unwrap();
+        assert_eq!(legacy_tx.txid(), sha256d(&legacy));
+        assert_eq!(segwit_tx.txid(), legacy_tx.txid());
+        assert!(segwit_tx.pays_to(&[0x00, 0x01, 0x02]));
+        assert_eq!(segwit_tx.payload().unwrap().block_height, 10);
+        assert_eq!(
+            segwit_tx.outputs().collect::<Vec<_>>(),
+            vec![(10_000, &[0x00, 0x01, 0x02][..]), (0, &payload[..])]
+        );
+    }
+
+    #[test]
+    fn test_light_merkle_block() {
+        let (a, b, c) = (sha256d(b"a"), sha256d(b"b"), sha256d(b"c"));
+        let root = merkle_parent(&merkle_parent(&a, &b), &merkle_parent(&c, &c));
+        assert_eq!(merkle_root(&c, 2, &[c, merkle_parent(&a, &b)]), root);
+        assert_eq!(merkle_root(&b, 1, &[a, merkle_parent(&c, &c)]), root);
+        assert_eq!(merkle_root(&a, 0, &[]), a);
+
+        let merkle_block = |root: &Hash256| {
+            let mut header = vec![1, 0, 0, 0];
+            header.extend_from_slice(&[0; 32]);
+            header.extend_from_slice(root);
+            // Almost any hash satisfies the maximal target.
+            header.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0x00, 0x21, 0, 0, 0, 0]);
+            let mut merkle_block = header.clone();
+            merkle_block.extend_from_slice(&[3, 0, 0, 0, 3]);
+            merkle_block.extend_from_slice(&a);
+            merkle_block.extend_from_slice(&b);
+            merkle_block.extend_from_slice(&merkle_parent(&c, &c));
+            // The root and the left node are the parents of the match, `b` is the match.
+            merkle_block.extend_from_slice(&[1, 0b01011]);
+            (header, merkle_block)
+        };
+        let (header, proof) = merkle_block(&root);
+        assert_eq!(verify_merkle_block(&proof, &b), Ok(sha256d(&header)));
+        assert_eq!(verify_merkle_block(&proof, &a), Err(ProofError::TxNotIncluded));
+        let (_, proof) = merkle_block(&a);
+        assert_eq!(verify_merkle_block(&proof, &b), Err(ProofError::MerkleRootMismatch));
+        let (_, mut proof) = merkle_block(&root);
+        proof.push(0);
+        assert_eq!(verify_merkle_block(&proof, &b), Err(ProofError::MalformedMerkleBlock));
+        let (_, mut proof) = merkle_block(&root);
+        proof[72..76].copy_from_slice(&[0x01, 0x00, 0x00, 0x03]);
+        assert_eq!(verify_merkle_block(&proof, &b), Err(ProofError::InsufficientWork));
+    }
+
+    #[test]
+    fn test_light_headers() {
+        let prev_hash = sha256d(b"prev");
+        let header_with_bits = |bits: [u8; 4]| {
+            let mut header = vec![1, 0, 0, 0];
+            header.extend_from_slice(&prev_hash);
+            header.extend_from_slice(&[0; 36]);
+            header.extend_from_slice(&bits);
+            header.extend_from_slice(&[0; 4]);
+            header
+        };
+        // Almost any hash satisfies the maximal target.
+        let header = header_with_bits([0xff, 0xff, 0x00, 0x21]);
+        assert_eq!(verify_header(&header, &prev_hash), Ok(sha256d(&header)));
+        assert_eq!(
+            verify_header(&header, &sha256d(b"other")),
+            Err(ProofError::BrokenHeaders)
+        );
+        let header = header_with_bits([0x01, 0x00, 0x00, 0x03]);
+        assert_eq!(
+            verify_header(&header, &prev_hash),
+            Err(ProofError::InsufficientWork)
+        );
+        let header = header_with_bits([0xff, 0xff, 0x80, 0x21]);
+        assert_eq!(
+            verify_header(&header, &prev_hash),
+            Err(ProofError::MalformedHeader)
+        );
+        assert_eq!(
+            verify_header(&header[1..], &prev_hash),
+            Err(ProofError::MalformedHeader)
+        );
+    }
+}
@@ -18,15 +18,31 @@ use std::fmt;
 use exonum::storage::Error as StorageError;
 use exonum::api::ApiError;
 
+use details::btc::TxId;
+
 #[derive(Debug)]
 pub enum Error {
     UnknownValidatorId(u32),
+    UnknownBlockHeight(u64),
+    UnknownAnchoringTx(TxId),
+    NoBitcoinBackend,
+    BitcoinBackend(String),
+    NoHdKey,
+    HdKey(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::UnknownValidatorId(id) => write!(f, "Unknown validator id={}", id),
+            Error::UnknownBlockHeight(height) => write!(f, "Unknown block height={}", height),
+            Error::UnknownAnchoringTx(ref txid) => {
+                write!(f, "Unknown anchoring transaction txid={}", txid.to_string())
+            }
+            Error::NoBitcoinBackend => write!(f, "Bitcoin backend is not configured"),
+            Error::BitcoinBackend(ref msg) => write!(f, "Bitcoin backend error: {}", msg),
+            Error::NoHdKey => write!(f, "Extended anchoring key is not configured"),
+            Error::HdKey(ref msg) => write!(f, "Extended anchoring key error: {}", msg),
         }
     }
 }
@@ -35,6 +51,12 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::UnknownValidatorId(_) => "UnknownValidatorId",
+            Error::UnknownBlockHeight(_) => "UnknownBlockHeight",
+            Error::UnknownAnchoringTx(_) => "UnknownAnchoringTx",
+            Error::NoBitcoinBackend => "NoBitcoinBackend",
+            Error::BitcoinBackend(_) => "BitcoinBackend",
+            Error::NoHdKey => "NoHdKey",
+            Error::HdKey(_) => "HdKey",
         }
     }
 }
@@ -45,6 +67,16 @@ impl Into<ApiError> for Error {
             Error::UnknownValidatorId(id) => {
                 ApiError::Storage(StorageError::new(format!("Unknown validator id={}", id)))
             }
+            Error::UnknownBlockHeight(height) => {
+                ApiError::Storage(StorageError::new(
+                    format!("Unknown block height={}", height),
+                ))
+            }
+            e @ Error::UnknownAnchoringTx(_) |
+            e @ Error::NoBitcoinBackend |
+            e @ Error::BitcoinBackend(_) |
+            e @ Error::NoHdKey |
+            e @ Error::HdKey(_) => ApiError::Storage(StorageError::new(e.to_string())),
         }
     }
 }
@@ -14,29 +14,67 @@
 
 //! Anchoring rest api implementation.
 
+use std::cmp;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use router::Router;
 use iron::prelude::*;
-use bitcoin::util::base58::ToBase58;
+use iron::headers::ContentType;
+use iron::status;
+use serde_json;
 
-use exonum::blockchain::Blockchain;
+use exonum::blockchain::{Block, Blockchain, Schema, StoredConfiguration};
+use exonum::storage::{ListProof, Snapshot};
 use exonum::crypto::Hash;
+use exonum::helpers::{Height, ValidatorId};
+use exonum::encoding::serialize::{FromHex, ToHex};
 use exonum::api::{Api, ApiError};
 
 use details::btc;
 use details::btc::TxId;
-use details::btc::transactions::{AnchoringTx, BitcoinTx, TxKind};
+use details::btc::transactions::{AnchoringTx, BitcoinTx, TxKind, DUST_THRESHOLD};
+use details::rpc::Error as RpcError;
 use blockchain::schema::AnchoringSchema;
-use blockchain::dto::LectContent;
+use certificate::{NotarizationCertificate, CERTIFICATE_VERSION};
+use blockchain::dto::{LectContent, MisbehaviorEvidence, MisbehaviorKind, TxLogEntry};
+use handler::event::AnchoringEventEntry;
+use handler::policy::PendingApproval;
+use handler::shared::{HandlerRequest, SharedHandler};
+use handler::state::{AnchoringPhase, HandlerState};
+use light;
+use local_storage::AnchoringNodeConfig;
+use service::bitcoin_relay;
 
 pub use details::btc::payload::Payload;
 
 mod error;
 
+/// The maximum number of the entries returned by the `tx_log` request.
+pub const TX_LOG_PAGE_SIZE: u64 = 100;
+/// The maximum number of the entries returned by the `events` request.
+pub const EVENTS_PAGE_SIZE: usize = 100;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
 /// Public api implementation.
 #[derive(Debug, Clone)]
 pub struct PublicApi {
     /// Exonum blockchain instance.
     pub blockchain: Blockchain,
+    /// Shared state of the anchoring handler, its bitcoin backend is used to build
+    /// the SPV proofs.
+    pub shared: Arc<SharedHandler>,
+}
+
+/// Private api implementation.
+#[derive(Debug, Clone)]
+pub struct PrivateApi {
+    /// Exonum blockchain instance.
+    pub blockchain: Blockchain,
+    /// Shared state of the anchoring handler.
+    pub shared: Arc<SharedHandler>,
 }
 
 /// Public information about the anchoring transaction in bitcoin.
@@ -57,6 +95,316 @@ pub struct LectInfo {
     pub content: AnchoringInfo,
 }
 
+/// Proof that the `Exonum` block is anchored to the bitcoin blockchain.
+///
+/// Each block contains the hash of the previous one, thus the `path` leads from the hash
+/// of the requested `block` to the hash written to the anchoring transaction payload.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringProof {
+    /// `Txid` of the anchoring transaction.
+    pub txid: TxId,
+    /// Nearest following anchoring transaction.
+    pub tx: AnchoringTx,
+    /// The requested block.
+    pub block: Block,
+    /// Links of the blocks following the requested one up to the anchored one inclusive.
+    pub path: Vec<BlockLink>,
+    /// Application commitments whose root is written to the anchoring transaction payload,
+    /// in the order of their registration.
+    #[serde(default)]
+    pub commitments: Vec<Hash>,
+}
+
+/// Link of the path to the anchored block, that is the block without the `prev_hash`
+/// which is the hash of the previous link.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockLink {
+    /// Information schema version.
+    pub schema_version: u16,
+    /// Identifier of the block proposer.
+    pub proposer_id: u16,
+    /// Height of the block.
+    pub height: u64,
+    /// Number of the transactions in the block.
+    pub tx_count: u32,
+    /// Root hash of the merkle tree of the block transactions.
+    pub tx_hash: Hash,
+    /// Hash of the blockchain state after the block.
+    pub state_hash: Hash,
+}
+
+impl BlockLink {
+    /// Creates the link of the given block.
+    pub fn new(block: &Block) -> BlockLink {
+        BlockLink {
+            schema_version: block.schema_version(),
+            proposer_id: block.proposer_id().0,
+            height: block.height().0,
+            tx_count: block.tx_count(),
+            tx_hash: *block.tx_hash(),
+            state_hash: *block.state_hash(),
+        }
+    }
+
+    /// Restores the block that follows the block with the given hash.
+    pub fn to_block(&self, prev_hash: &Hash) -> Block {
+        Block::new(
+            self.schema_version,
+            ValidatorId(self.proposer_id),
+            Height(self.height),
+            self.tx_count,
+            prev_hash,
+            &self.tx_hash,
+            &self.state_hash,
+        )
+    }
+}
+
+impl AnchoringProof {
+    /// Restores the blocks from the requested one up to the anchored one inclusive.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks = vec![self.block.clone()];
+        for link in &self.path {
+            let prev_hash = blocks[blocks.len() - 1].hash();
+            blocks.push(link.to_block(&prev_hash));
+        }
+        blocks
+    }
+
+    /// Checks that the `path` leads from the requested block to the block anchored
+    /// by the transaction.
+    ///
+    /// The `tx` itself should be checked against the bitcoin blockchain, for example
+    /// by the SPV proof of its `txid`.
+    pub fn verify(&self) -> bool {
+        let blocks = self.blocks();
+        let consecutive = blocks.windows(2).all(|pair| {
+            pair[1].height().0 == pair[0].height().0 + 1
+        });
+        let payload = self.tx.payload();
+        let anchored_block = &blocks[blocks.len() - 1];
+        consecutive && self.tx.id() == self.txid &&
+            anchored_block.height() == payload.block_height &&
+            anchored_block.hash() == payload.block_hash
+    }
+}
+
+/// Proof that the `Exonum` transaction is included into the blockchain and anchored
+/// to the bitcoin blockchain.
+///
+/// The `tx_proof` connects the transaction hash with the `tx_hash` field of the `block`,
+/// the `anchoring_proof` connects the block with the payload of the anchoring transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TxExistenceProof {
+    /// Hash of the `Exonum` transaction.
+    pub tx_hash: Hash,
+    /// Block that includes the transaction.
+    pub block: Block,
+    /// Position of the transaction in the block.
+    pub position: u64,
+    /// Proof of the transaction hash in the merkle tree of the block transactions.
+    pub tx_proof: ListProof<Hash>,
+    /// Proof that the block is anchored, it is `None` until the block is covered
+    /// by a known anchoring transaction.
+    pub anchoring_proof: Option<AnchoringProof>,
+    /// Number of the anchoring transaction confirmations if the bitcoin backend knows it.
+    pub confirmations: Option<u64>,
+    /// Unix time when the anchoring chain observer of this node has found
+    /// the anchoring transaction confirmed.
+    pub confirmed_at: Option<u64>,
+}
+
+/// Proof that the anchoring transaction is included into the bitcoin blockchain.
+///
+/// It allows a light client to verify the anchoring transaction with the knowledge
+/// of the bitcoin block headers only. The merkle block contains the header of the block
+/// that includes the transaction and the merkle branch of the transaction. The `headers`
+/// follow this block and prove the depth of the transaction in the best chain.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SpvProof {
+    /// `Txid` of the anchoring transaction.
+    pub txid: TxId,
+    /// Anchoring transaction.
+    pub tx: AnchoringTx,
+    /// Hash of the bitcoin block that includes the transaction.
+    pub block_hash: String,
+    /// Hex-encoded merkle block in the format of the `gettxoutproof` call of `bitcoind`.
+    pub merkle_block: String,
+    /// Hex-encoded headers of the blocks following the one that includes the transaction.
+    pub headers: Vec<String>,
+}
+
+/// Funds of the actual anchoring address.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringBalance {
+    /// Actual anchoring address.
+    pub address: btc::Address,
+    /// Confirmed balance of the address in satoshis.
+    pub balance: u64,
+    /// Expected fee of the anchoring transaction in satoshis.
+    pub anchor_fee: u64,
+    /// Number of the following anchoring transactions which can be funded by the balance.
+    pub anchors_left: u64,
+}
+
+/// Anchoring transaction that covers the requested `Exonum` block.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CoveringAnchor {
+    /// `Txid` of the anchoring transaction.
+    pub txid: TxId,
+    /// Height of the block anchored by the transaction.
+    pub anchored_height: u64,
+    /// Number of the transaction confirmations if the bitcoin backend knows it.
+    pub confirmations: Option<u64>,
+    /// Unix time when the anchoring chain observer of this node has found
+    /// the transaction confirmed.
+    pub confirmed_at: Option<u64>,
+}
+
+/// Fees paid by the anchoring chain observed by this node, they are intended for
+/// the budgeting of the anchoring wallet and the tuning of the `frequency` and `fee`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringCosts {
+    /// Number of the anchoring transactions with the known fee.
+    pub anchors: u64,
+    /// Total fee of these transactions in satoshis.
+    pub total_fee: u64,
+    /// Average fee per anchoring transaction.
+    pub fee_per_anchor: Option<u64>,
+    /// Average fee per block up to the latest anchored one.
+    pub fee_per_block: Option<f64>,
+    /// Total fee of the anchoring transactions confirmed during the last day.
+    pub last_day_fee: u64,
+    /// Total fee of the anchoring transactions confirmed during the last week.
+    pub last_week_fee: u64,
+}
+
+/// Anchoring key derived from the extended key of this node.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DerivedAnchoringKey {
+    /// Derivation index of the key.
+    pub index: u32,
+    /// Anchoring public key.
+    pub public_key: btc::PublicKey,
+}
+
+/// Percentiles of the anchoring latency in seconds, that is the time from the commit
+/// of the anchored block until the anchoring transaction is confirmed.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringLatency {
+    /// Number of the anchoring transactions with the known latency.
+    pub count: u64,
+    /// Median latency.
+    pub p50: Option<u64>,
+    /// 90th percentile of the latency.
+    pub p90: Option<u64>,
+    /// 99th percentile of the latency.
+    pub p99: Option<u64>,
+    /// The maximum latency.
+    pub max: Option<u64>,
+}
+
+/// Status of the anchoring process on this node, it is intended for the dashboards
+/// and health checks.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringStatus {
+    /// Current phase of the anchoring handler state machine.
+    pub phase: AnchoringPhase,
+    /// `Txid` of the lect agreed by +2/3 validators if there is one.
+    pub lect: Option<TxId>,
+    /// Number of the lect confirmations if it is the lect of this node.
+    pub lect_confirmations: Option<u64>,
+    /// Height of the next block to anchor.
+    pub next_anchoring_height: u64,
+    /// Whether the anchoring is paused by the majority of the validators.
+    pub paused: bool,
+    /// `Txid` of the transfer transaction to the address of the following configuration
+    /// which waits for enough confirmations.
+    pub pending_transfer: Option<TxId>,
+    /// Whether the change of the agreed lect is below the dust threshold, for example,
+    /// the lect is the final anchoring transaction which has burned the rest of the funds.
+    pub wallet_empty: bool,
+    /// Identifiers of the validators whose signatures are collected for the agreed lect.
+    pub signers: Vec<u16>,
+    /// Errors of the anchoring handler since the latest successful block commit.
+    pub errors: Vec<String>,
+}
+
+/// Health of the anchoring node, it is intended for the load balancers and the liveness
+/// probes which treat any status other than `200 OK` as a failure.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnchoringHealth {
+    /// Whether the bitcoin backend is reachable and watches the actual anchoring address.
+    pub healthy: bool,
+    /// Whether the bitcoin backend responds to the requests.
+    pub bitcoind_reachable: bool,
+    /// Height of the best block known by the bitcoin backend.
+    pub tip_height: Option<u64>,
+    /// Progress of the block chain verification by the bitcoin backend.
+    pub verification_progress: Option<f64>,
+    /// Actual anchoring address.
+    pub address: btc::Address,
+    /// Whether the actual anchoring address is imported to the wallet of the bitcoin backend.
+    pub address_watched: bool,
+    /// Whether the latest anchoring round is completed, it is `false` while the signatures
+    /// of the anchoring proposal are being collected.
+    pub last_round_completed: bool,
+    /// Height of the latest anchored block.
+    pub anchored_height: Option<u64>,
+    /// Errors of the bitcoin backend and of the anchoring handler since the latest
+    /// successful block commit.
+    pub errors: Vec<String>,
+}
+
+/// Kind of the bitcoin transaction in the anchoring transactions log.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxLogKind {
+    /// Transaction which funds the anchoring address.
+    Funding,
+    /// Anchoring transaction which continues the chain on the same address.
+    Anchoring,
+    /// Anchoring transaction which moves the funds to the new anchoring address.
+    Transfer,
+}
+
+/// Entry of the anchoring transactions log.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TxLogInfo {
+    /// Index of the entry in the log.
+    pub index: u64,
+    /// Height of the `Exonum` block in which the transaction is observed first.
+    pub height: u64,
+    /// `Txid` of the transaction.
+    pub txid: TxId,
+    /// Kind of the transaction.
+    pub kind: TxLogKind,
+    /// Payload of the anchoring transaction.
+    pub payload: Option<Payload>,
+    /// Fee of the anchoring transaction in satoshis if all its inputs are known.
+    pub fee: Option<u64>,
+    /// Unix time when the anchoring chain observer of this node has found
+    /// the anchoring transaction confirmed.
+    pub confirmed_at: Option<u64>,
+}
+
+/// Evidence of the validator misbehavior.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MisbehaviorInfo {
+    /// Index of the evidence in the list.
+    pub index: u64,
+    /// Height of the `Exonum` block in which the misbehavior is detected.
+    pub height: u64,
+    /// Identifier of the misbehaving validator.
+    pub validator: u16,
+    /// Kind of the misbehavior.
+    pub kind: MisbehaviorKind,
+    /// Hash of the `Exonum` message of the validator which proves the misbehavior.
+    pub msg_hash: Hash,
+    /// Hash of the earlier conflicting message of the validator.
+    pub conflicting_msg_hash: Option<Hash>,
+}
+
 impl From<BitcoinTx> for AnchoringInfo {
     fn from(tx: BitcoinTx) -> AnchoringInfo {
         match TxKind::from(tx) {
@@ -134,22 +482,524 @@ impl PublicApi {
         Ok(following_addr)
     }
 
+    /// Returns the configuration proposal which rotates the anchoring key approved by
+    /// the validators, it should be submitted to the configuration service.
+    ///
+    /// `GET /{api_prefix}/v1/key_rotation/proposal`
+    pub fn key_rotation_proposal(&self) -> Result<Option<StoredConfiguration>, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        Ok(AnchoringSchema::new(snapshot).key_rotation_proposal())
+    }
+
     /// Returns hex of the anchoring transaction for the nearest block with a height greater
     /// or equal than the given.
     ///
     /// `GET /{api_prefix}/v1/nearest_lect/:height`
     pub fn nearest_lect(&self, height: u64) -> Result<Option<AnchoringTx>, ApiError> {
+        Ok(self.nearest_anchoring_tx(height).map(|(_, tx)| tx))
+    }
+
+    /// Returns proof that the block with the given height is anchored by the nearest
+    /// following anchoring transaction.
+    ///
+    /// `GET /{api_prefix}/v1/anchoring_proof/:height`
+    pub fn anchoring_proof(&self, height: u64) -> Result<Option<AnchoringProof>, ApiError> {
+        let (anchored_height, tx) = match self.nearest_anchoring_tx(height) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
         let snapshot = self.blockchain.snapshot();
-        let anchoring_schema = AnchoringSchema::new(&snapshot);
-        let tx_chain = anchoring_schema.anchoring_tx_chain();
+        let schema = Schema::new(&snapshot);
+        let block_hashes = schema.block_hashes_by_height();
+        let blocks_table = schema.blocks();
 
-        // TODO use binary find.
-        for (tx_height, tx) in &tx_chain {
-            if tx_height >= height {
-                return Ok(Some(tx));
+        let mut blocks = Vec::new();
+        for block_height in height..anchored_height + 1 {
+            let block = block_hashes.get(block_height).and_then(
+                |hash| blocks_table.get(&hash),
+            );
+            match block {
+                Some(block) => blocks.push(block),
+                None => return Err(error::Error::UnknownBlockHeight(block_height).into()),
             }
         }
-        Ok(None)
+        let block = blocks.remove(0);
+        let path = blocks.iter().map(BlockLink::new).collect();
+
+        let commitments = if tx.payload().commitments.is_some() {
+            let schema = AnchoringSchema::new(&snapshot);
+            let prev_tx = schema.prev_anchoring_tx(&tx);
+            schema.anchored_commitments(prev_tx.as_ref(), tx.payload().block_height)
+        } else {
+            Vec::new()
+        };
+
+        Ok(Some(AnchoringProof {
+            txid: tx.id(),
+            tx,
+            block,
+            path,
+            commitments,
+        }))
+    }
+
+    /// Returns proof that the `Exonum` transaction with the given hash is included into
+    /// the blockchain and anchored by the nearest following anchoring transaction together
+    /// with the confirmations of the latter.
+    ///
+    /// `GET /{api_prefix}/v1/tx_proof/:hash`
+    pub fn tx_existence_proof(
+        &self,
+        tx_hash: Hash,
+    ) -> Result<Option<TxExistenceProof>, ApiError> {
+        let (block, position, tx_proof) = match self.tx_inclusion(tx_hash)? {
+            Some(inclusion) => inclusion,
+            None => return Ok(None),
+        };
+
+        let anchoring_proof = self.anchoring_proof(block.height().0)?;
+        let (confirmations, confirmed_at) = match anchoring_proof {
+            Some(ref proof) => {
+                let confirmed_at = {
+                    let snapshot = self.blockchain.snapshot();
+                    AnchoringSchema::new(&snapshot).confirmation_times().get(&proof.txid)
+                };
+                let confirmations = match self.shared.client() {
+                    Some(client) => {
+                        client.get_transaction_confirmations(proof.txid).map_err(
+                            backend_error,
+                        )?
+                    }
+                    None => None,
+                };
+                (confirmations, confirmed_at)
+            }
+            None => (None, None),
+        };
+        Ok(Some(TxExistenceProof {
+            tx_hash,
+            block,
+            position,
+            tx_proof,
+            anchoring_proof,
+            confirmations,
+            confirmed_at,
+        }))
+    }
+
+    /// Returns the notarization certificate of the `Exonum` transaction with the given hash
+    /// if its block is anchored by a confirmed anchoring transaction.
+    ///
+    /// `GET /{api_prefix}/v1/certificate/:hash`
+    pub fn certificate(&self, tx_hash: Hash) -> Result<Option<NotarizationCertificate>, ApiError> {
+        let (block, position, tx_proof) = match self.tx_inclusion(tx_hash)? {
+            Some(inclusion) => inclusion,
+            None => return Ok(None),
+        };
+        let anchoring_proof = match self.anchoring_proof(block.height().0)? {
+            Some(proof) => proof,
+            None => return Ok(None),
+        };
+        let spv_proof = match self.spv_proof(anchoring_proof.txid)? {
+            Some(proof) => proof,
+            None => return Ok(None),
+        };
+        Ok(Some(NotarizationCertificate {
+            version: CERTIFICATE_VERSION,
+            tx_hash,
+            position,
+            tx_proof,
+            blocks: anchoring_proof.blocks(),
+            anchoring_tx: anchoring_proof.tx,
+            merkle_block: spv_proof.merkle_block,
+            headers: spv_proof.headers,
+        }))
+    }
+
+    /// Returns the SPV proof of the inclusion of the anchoring transaction with the given
+    /// `txid` into the bitcoin blockchain if the transaction is confirmed.
+    /// The proof contains up to `anchor_confirmations` headers of the following blocks.
+    /// The merkle block received from the bitcoin backend is verified to include the `txid`.
+    ///
+    /// `GET /{api_prefix}/v1/spv_proof/:txid`
+    pub fn spv_proof(&self, txid: TxId) -> Result<Option<SpvProof>, ApiError> {
+        let (tx, depth) = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = AnchoringSchema::new(&snapshot);
+            let tx = match schema.known_txs().get(&txid).map(TxKind::from) {
+                Some(TxKind::Anchoring(tx)) => tx,
+                _ => return Err(error::Error::UnknownAnchoringTx(txid).into()),
+            };
+            (tx, schema.actual_anchoring_config().required_anchor_confirmations())
+        };
+
+        let client = match self.shared.client() {
+            Some(client) => client,
+            None => return Err(error::Error::NoBitcoinBackend.into()),
+        };
+
+        let confirmations = client.get_transaction_confirmations(txid).map_err(
+            backend_error,
+        )?;
+        if confirmations.unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+        let merkle_block = match client.get_transaction_proof(txid).map_err(backend_error)? {
+            Some(merkle_block) => merkle_block,
+            None => return Ok(None),
+        };
+        let block_hash = verify_merkle_block(&merkle_block, txid)?;
+        let headers = client.get_block_headers(&block_hash, depth).map_err(
+            backend_error,
+        )?;
+
+        Ok(Some(SpvProof {
+            txid,
+            tx,
+            block_hash,
+            merkle_block,
+            headers,
+        }))
+    }
+
+    /// Returns the confirmed balance of the actual anchoring address and the number
+    /// of the anchoring transactions which it can fund.
+    ///
+    /// The expected fee is the fee of the latest anchoring transaction, but not less than
+    /// the `fee` from the anchoring configuration.
+    ///
+    /// `GET /{api_prefix}/v1/address/balance`
+    pub fn actual_balance(&self) -> Result<AnchoringBalance, ApiError> {
+        let cfg = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).actual_anchoring_config()
+        };
+        let address = cfg.redeem_script().1;
+
+        let client = match self.shared.client() {
+            Some(client) => client,
+            None => return Err(error::Error::NoBitcoinBackend.into()),
+        };
+        let unspent_txs = client.unspent_transactions(&address).map_err(
+            backend_error,
+        )?;
+
+        let script_pubkey = address.script_pubkey();
+        let balance: u64 = unspent_txs
+            .iter()
+            .filter(|info| info.confirmations.unwrap_or(0) > 0)
+            .flat_map(|info| info.body.output.iter())
+            .filter(|out| out.script_pubkey == script_pubkey)
+            .map(|out| out.value)
+            .sum();
+        let anchor_fee = self.shared.with_snapshot(|handler| handler.metrics.anchor_fee);
+        let anchor_fee = anchor_fee.map_or(
+            cfg.fee,
+            |fee| cmp::max(fee, cfg.fee),
+        );
+        let anchors_left = if anchor_fee > 0 {
+            balance / anchor_fee
+        } else {
+            0
+        };
+        Ok(AnchoringBalance {
+            address,
+            balance,
+            anchor_fee,
+            anchors_left,
+        })
+    }
+
+    /// Returns up to `TX_LOG_PAGE_SIZE` entries of the anchoring transactions log
+    /// starting from the given index.
+    ///
+    /// `GET /{api_prefix}/v1/tx_log/:start`
+    pub fn tx_log(&self, start: u64) -> Result<Vec<TxLogInfo>, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let tx_log = schema.tx_log();
+        let end = cmp::min(tx_log.len(), start.saturating_add(TX_LOG_PAGE_SIZE));
+
+        let mut entries = Vec::new();
+        for index in start..end {
+            let entry = tx_log.get(index).unwrap();
+            entries.push(tx_log_info(&schema, index, &entry));
+        }
+        Ok(entries)
+    }
+
+    /// Returns up to `TX_LOG_PAGE_SIZE` evidences of the validators misbehavior
+    /// starting from the given index.
+    ///
+    /// `GET /{api_prefix}/v1/misbehavior/:start`
+    pub fn misbehavior(&self, start: u64) -> Result<Vec<MisbehaviorInfo>, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let evidence = schema.misbehavior_evidence();
+        let end = cmp::min(evidence.len(), start.saturating_add(TX_LOG_PAGE_SIZE));
+
+        let mut entries = Vec::new();
+        for index in start..end {
+            let entry = evidence.get(index).unwrap();
+            entries.push(misbehavior_info(index, &entry));
+        }
+        Ok(entries)
+    }
+
+    /// Returns the anchoring transaction that covers the block with the given height,
+    /// that is the first one whose anchored height is greater or equal than the given,
+    /// with its confirmations.
+    ///
+    /// `GET /{api_prefix}/v1/anchor/:height`
+    pub fn covering_anchor(&self, height: u64) -> Result<Option<CoveringAnchor>, ApiError> {
+        let anchor = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).anchoring_tx_covering(height)
+        };
+        match anchor {
+            Some((anchored_height, tx)) => self.anchor_info(anchored_height, &tx).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the anchoring transaction with the given `txid` from the anchoring chain
+    /// observed by this node together with the anchored height and its confirmations.
+    ///
+    /// `GET /{api_prefix}/v1/anchor/txid/:txid`
+    pub fn anchor_by_txid(&self, txid: TxId) -> Result<Option<CoveringAnchor>, ApiError> {
+        let anchor = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).anchoring_tx_by_txid(&txid)
+        };
+        match anchor {
+            Some((anchored_height, tx)) => self.anchor_info(anchored_height, &tx).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the percentiles of the anchoring latency measured by the anchoring chain
+    /// observer of this node.
+    ///
+    /// `GET /{api_prefix}/v1/latency`
+    pub fn anchoring_latency(&self) -> Result<AnchoringLatency, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let mut latencies = schema
+            .anchoring_latencies()
+            .values()
+            .collect::<Vec<_>>();
+        latencies.sort();
+        Ok(AnchoringLatency {
+            count: latencies.len() as u64,
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: latencies.last().cloned(),
+        })
+    }
+
+    /// Returns the fees paid by the anchoring chain observed by this node.
+    ///
+    /// `GET /{api_prefix}/v1/costs`
+    pub fn anchoring_costs(&self) -> Result<AnchoringCosts, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let mut costs = AnchoringCosts {
+            anchors: 0,
+            total_fee: 0,
+            fee_per_anchor: None,
+            fee_per_block: None,
+            last_day_fee: 0,
+            last_week_fee: 0,
+        };
+        let mut latest_height = None;
+        let chain = schema.anchoring_tx_chain();
+        let confirmation_times = schema.confirmation_times();
+        for (height, fee) in schema.anchoring_fees().iter() {
+            costs.anchors += 1;
+            costs.total_fee += fee;
+            latest_height = Some(height);
+
+            let confirmed_at = chain.get(&height).and_then(
+                |tx| confirmation_times.get(&tx.id()),
+            );
+            if let Some(confirmed_at) = confirmed_at {
+                let age = now.saturating_sub(confirmed_at);
+                if age < SECONDS_PER_DAY {
+                    costs.last_day_fee += fee;
+                }
+                if age < 7 * SECONDS_PER_DAY {
+                    costs.last_week_fee += fee;
+                }
+            }
+        }
+        if costs.anchors > 0 {
+            costs.fee_per_anchor = Some(costs.total_fee / costs.anchors);
+        }
+        if let Some(height) = latest_height {
+            costs.fee_per_block = Some(costs.total_fee as f64 / (height + 1) as f64);
+        }
+        Ok(costs)
+    }
+
+    /// Returns the status of the anchoring process on this node.
+    ///
+    /// `GET /{api_prefix}/v1/status`
+    pub fn status(&self) -> Result<AnchoringStatus, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let cfg = schema.actual_anchoring_config();
+        // Height of the latest committed block.
+        let blocks_count = Schema::new(&snapshot).block_hashes_by_height().len();
+        let height = Height(blocks_count.saturating_sub(1));
+        let lect = schema.collect_lects(&cfg);
+        let paused = schema.is_paused();
+        let pending_transfer = schema.pending_transfer().map(|tx| tx.id());
+
+        let mut next_anchoring_height = cfg.latest_anchoring_height(height);
+        let mut signers = Vec::new();
+        let mut wallet_empty = false;
+        if let Some(TxKind::Anchoring(tx)) = lect.clone().map(TxKind::from) {
+            wallet_empty = tx.amount() < DUST_THRESHOLD;
+            let anchored_height = tx.payload().block_height;
+            if anchored_height >= next_anchoring_height {
+                next_anchoring_height = Height(next_anchoring_height.0 + cfg.frequency);
+            } else {
+                next_anchoring_height = cfg.catch_up_height(anchored_height, next_anchoring_height);
+            }
+            for msg in schema.signatures(&tx.nid()).iter() {
+                if !signers.contains(&msg.validator().0) {
+                    signers.push(msg.validator().0);
+                }
+            }
+            signers.sort();
+        }
+        let lect = lect.map(|tx| tx.id());
+
+        Ok(self.shared.with_snapshot(|handler| {
+            let lect_confirmations = match handler.lect_confirmations {
+                Some((txid, confirmations)) if Some(txid) == lect => Some(confirmations),
+                _ => None,
+            };
+            AnchoringStatus {
+                phase: handler.phase(),
+                lect,
+                lect_confirmations,
+                next_anchoring_height: next_anchoring_height.0,
+                paused,
+                pending_transfer,
+                wallet_empty,
+                signers,
+                errors: handler.pending_errors.clone(),
+            }
+        }))
+    }
+
+    /// Returns the health of this node, the errors of the bitcoin backend are reported
+    /// by the `errors` field instead of the api error.
+    ///
+    /// `GET /{api_prefix}/v1/health`
+    pub fn health(&self) -> AnchoringHealth {
+        let address = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).actual_anchoring_config().redeem_script().1
+        };
+
+        let mut errors = Vec::new();
+        let (bitcoind_reachable, tip_height, verification_progress) = match self.shared.client() {
+            Some(client) => {
+                match client.tip_height().and_then(|height| {
+                    client.verification_progress().map(|progress| (height, progress))
+                }) {
+                    Ok((height, progress)) => (true, height, progress),
+                    Err(e) => {
+                        errors.push(error::Error::BitcoinBackend(e.to_string()).to_string());
+                        (false, None, None)
+                    }
+                }
+            }
+            None => {
+                errors.push(error::Error::NoBitcoinBackend.to_string());
+                (false, None, None)
+            }
+        };
+
+        self.shared.with_snapshot(|handler| {
+            let address_watched = handler.watched_addresses.contains(&address.to_string());
+            errors.extend(handler.pending_errors.iter().cloned());
+            AnchoringHealth {
+                healthy: bitcoind_reachable && address_watched,
+                bitcoind_reachable,
+                tip_height,
+                verification_progress,
+                address,
+                address_watched,
+                last_round_completed: handler.metrics.round_started.is_none(),
+                anchored_height: handler.metrics.anchored_height,
+                errors,
+            }
+        })
+    }
+
+    fn anchor_info(
+        &self,
+        anchored_height: u64,
+        tx: &AnchoringTx,
+    ) -> Result<CoveringAnchor, ApiError> {
+        let confirmed_at = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).confirmation_times().get(&tx.id())
+        };
+        let confirmations = match self.shared.client() {
+            Some(client) => {
+                client.get_transaction_confirmations(tx.id()).map_err(
+                    backend_error,
+                )?
+            }
+            None => None,
+        };
+        Ok(CoveringAnchor {
+            txid: tx.id(),
+            anchored_height,
+            confirmations,
+            confirmed_at,
+        })
+    }
+
+    // Returns the block that includes the transaction with the given hash, the position
+    // of the transaction in the block and its merkle proof.
+    fn tx_inclusion(
+        &self,
+        tx_hash: Hash,
+    ) -> Result<Option<(Block, u64, ListProof<Hash>)>, ApiError> {
+        let snapshot = self.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        let location = match schema.tx_location_by_tx_hash().get(&tx_hash) {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let height = location.block_height();
+        let block = schema.block_hash_by_height(height).and_then(
+            |hash| schema.blocks().get(&hash),
+        );
+        let block = match block {
+            Some(block) => block,
+            None => return Err(error::Error::UnknownBlockHeight(height.0).into()),
+        };
+        let position = location.position_in_block();
+        let tx_proof = schema.block_txs(height).get_proof(position);
+        Ok(Some((block, position, tx_proof)))
+    }
+
+    fn nearest_anchoring_tx(&self, height: u64) -> Option<(u64, AnchoringTx)> {
+        let snapshot = self.blockchain.snapshot();
+        AnchoringSchema::new(&snapshot).anchoring_tx_covering(height)
     }
 }
 
@@ -185,16 +1035,22 @@ impl Api for PublicApi {
 
         let _self = self.clone();
         let actual_address = move |_: &mut Request| -> IronResult<Response> {
-            let addr = _self.actual_address()?.to_base58check();
+            let addr = _self.actual_address()?.to_string();
             _self.ok_response(&json!(addr))
         };
 
         let _self = self.clone();
         let following_address = move |_: &mut Request| -> IronResult<Response> {
-            let addr = _self.following_address()?.map(|addr| addr.to_base58check());
+            let addr = _self.following_address()?.map(|addr| addr.to_string());
             _self.ok_response(&json!(addr))
         };
 
+        let _self = self.clone();
+        let key_rotation_proposal = move |_: &mut Request| -> IronResult<Response> {
+            let proposal = _self.key_rotation_proposal()?;
+            _self.ok_response(&json!(proposal))
+        };
+
         let _self = self.clone();
         let nearest_lect = move |req: &mut Request| -> IronResult<Response> {
             let map = req.extensions.get::<Router>().unwrap();
@@ -217,12 +1073,226 @@ impl Api for PublicApi {
             }
         };
 
+        let _self = self.clone();
+        let anchoring_proof = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("height") {
+                Some(height_str) => {
+                    let height: u64 = height_str.parse().map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the block height occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let proof = _self.anchoring_proof(height)?;
+                    _self.ok_response(&json!(proof))
+                }
+                None => {
+                    let msg = "The block height is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let spv_proof = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("txid") {
+                Some(txid_str) => {
+                    let txid = TxId::from_hex(txid_str).map_err(|e| {
+                        let msg = format!("An error during parsing of the txid occurred: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let proof = _self.spv_proof(txid)?;
+                    _self.ok_response(&json!(proof))
+                }
+                None => {
+                    let msg = "The txid is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let tx_existence_proof = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("hash") {
+                Some(hash_str) => {
+                    let hash = Hash::from_hex(hash_str).map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the transaction hash occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let proof = _self.tx_existence_proof(hash)?;
+                    _self.ok_response(&json!(proof))
+                }
+                None => {
+                    let msg = "The transaction hash is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let certificate = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("hash") {
+                Some(hash_str) => {
+                    let hash = Hash::from_hex(hash_str).map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the transaction hash occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let certificate = _self.certificate(hash)?;
+                    _self.ok_response(&json!(certificate))
+                }
+                None => {
+                    let msg = "The transaction hash is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let covering_anchor = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("height") {
+                Some(height_str) => {
+                    let height: u64 = height_str.parse().map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the block height occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let anchor = _self.covering_anchor(height)?;
+                    _self.ok_response(&json!(anchor))
+                }
+                None => {
+                    let msg = "The block height is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let anchor_by_txid = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("txid") {
+                Some(txid_str) => {
+                    let txid = TxId::from_hex(txid_str).map_err(|e| {
+                        let msg = format!("An error during parsing of the txid occurred: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let anchor = _self.anchor_by_txid(txid)?;
+                    _self.ok_response(&json!(anchor))
+                }
+                None => {
+                    let msg = "The txid is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let tx_log = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("start") {
+                Some(start_str) => {
+                    let start: u64 = start_str.parse().map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the log index occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let entries = _self.tx_log(start)?;
+                    _self.ok_response(&json!(entries))
+                }
+                None => {
+                    let msg = "The log index is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let misbehavior = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("start") {
+                Some(start_str) => {
+                    let start: u64 = start_str.parse().map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the evidence index occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    let entries = _self.misbehavior(start)?;
+                    _self.ok_response(&json!(entries))
+                }
+                None => {
+                    let msg = "The evidence index is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let anchoring_latency = move |_: &mut Request| -> IronResult<Response> {
+            let latency = _self.anchoring_latency()?;
+            _self.ok_response(&json!(latency))
+        };
+
+        let _self = self.clone();
+        let anchoring_costs = move |_: &mut Request| -> IronResult<Response> {
+            let costs = _self.anchoring_costs()?;
+            _self.ok_response(&json!(costs))
+        };
+
+        let _self = self.clone();
+        let actual_balance = move |_: &mut Request| -> IronResult<Response> {
+            let balance = _self.actual_balance()?;
+            _self.ok_response(&json!(balance))
+        };
+
+        let _self = self.clone();
+        let status = move |_: &mut Request| -> IronResult<Response> {
+            let status = _self.status()?;
+            _self.ok_response(&json!(status))
+        };
+
+        let _self = self.clone();
+        let health = move |_: &mut Request| -> IronResult<Response> {
+            let health = _self.health();
+            let code = if health.healthy {
+                status::Ok
+            } else {
+                status::ServiceUnavailable
+            };
+            let body = serde_json::to_string_pretty(&health).unwrap();
+            let mut response = Response::with((code, body));
+            response.headers.set(ContentType::json());
+            Ok(response)
+        };
+
         router.get("/v1/address/actual", actual_address, "actual_address");
+        router.get("/v1/address/balance", actual_balance, "actual_balance");
         router.get(
             "/v1/address/following",
             following_address,
             "following_address",
         );
+        router.get(
+            "/v1/key_rotation/proposal",
+            key_rotation_proposal,
+            "key_rotation_proposal",
+        );
         router.get("/v1/actual_lect/", actual_lect, "actual_lect");
         router.get(
             "/v1/actual_lect/:id",
@@ -230,5 +1300,486 @@ impl Api for PublicApi {
             "current_lect_of_validator",
         );
         router.get("/v1/nearest_lect/:height", nearest_lect, "nearest_lect");
+        router.get(
+            "/v1/anchoring_proof/:height",
+            anchoring_proof,
+            "anchoring_proof",
+        );
+        router.get("/v1/anchor/:height", covering_anchor, "covering_anchor");
+        router.get("/v1/anchor/txid/:txid", anchor_by_txid, "anchor_by_txid");
+        router.get("/v1/spv_proof/:txid", spv_proof, "spv_proof");
+        router.get(
+            "/v1/tx_proof/:hash",
+            tx_existence_proof,
+            "tx_existence_proof",
+        );
+        router.get("/v1/certificate/:hash", certificate, "certificate");
+        router.get("/v1/tx_log/:start", tx_log, "tx_log");
+        router.get("/v1/misbehavior/:start", misbehavior, "misbehavior");
+        router.get("/v1/latency", anchoring_latency, "anchoring_latency");
+        router.get("/v1/costs", anchoring_costs, "anchoring_costs");
+        router.get("/v1/status", status, "status");
+        router.get("/v1/health", health, "health");
+    }
+}
+
+fn parse_validator_id(id: Option<&str>) -> Result<u16, ApiError> {
+    match id {
+        Some(id_str) => {
+            id_str.parse().map_err(|e| {
+                let msg = format!("An error during parsing of the validator id occurred: {}", e);
+                ApiError::IncorrectRequest(msg.into())
+            })
+        }
+        None => {
+            let msg = "The identifier of the validator is not specified.";
+            Err(ApiError::IncorrectRequest(msg.into()))
+        }
+    }
+}
+
+fn backend_error(e: RpcError) -> ApiError {
+    error::Error::BitcoinBackend(e.to_string()).into()
+}
+
+// Returns the nearest-rank percentile of the sorted values.
+fn percentile(sorted: &[u64], percent: u64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent * sorted.len() as u64 + 99) / 100;
+    let index = cmp::max(rank, 1) - 1;
+    sorted.get(index as usize).cloned()
+}
+
+// The transfer transaction pays to another address than its previous anchoring transaction.
+fn tx_log_info<T>(schema: &AnchoringSchema<T>, index: u64, entry: &TxLogEntry) -> TxLogInfo
+where
+    T: AsRef<Snapshot>,
+{
+    let tx = entry.tx();
+    let txid = tx.id();
+    let (kind, payload, fee) = match TxKind::from(tx) {
+        TxKind::Anchoring(tx) => {
+            let prev_index = tx.input[0].prev_index as usize;
+            let is_transfer = schema.spent_tx(&tx, 0).map_or(false, |prev_tx| {
+                prev_tx.output.get(prev_index).map(|out| &out.script_pubkey) !=
+                    Some(&tx.output[0].script_pubkey)
+            });
+            let kind = if is_transfer {
+                TxLogKind::Transfer
+            } else {
+                TxLogKind::Anchoring
+            };
+            (kind, Some(tx.payload()), schema.anchoring_tx_fee(&tx))
+        }
+        _ => (TxLogKind::Funding, None, None),
+    };
+    TxLogInfo {
+        index,
+        height: entry.height(),
+        txid,
+        kind,
+        payload,
+        fee,
+        confirmed_at: schema.confirmation_times().get(&txid),
+    }
+}
+
+fn misbehavior_info(index: u64, entry: &MisbehaviorEvidence) -> MisbehaviorInfo {
+    let conflicting_msg_hash = if *entry.conflicting_msg_hash() == Hash::zero() {
+        None
+    } else {
+        Some(*entry.conflicting_msg_hash())
+    };
+    MisbehaviorInfo {
+        index,
+        height: entry.height(),
+        validator: entry.validator().0,
+        kind: MisbehaviorKind::from_code(entry.kind()).expect("Unknown misbehavior kind"),
+        msg_hash: *entry.msg_hash(),
+        conflicting_msg_hash,
+    }
+}
+
+// The merkle block starts with the header of the block that includes the transaction.
+// Checks that the merkle block includes the transaction under the merkle root of its header
+// and returns the hash of the block.
+fn verify_merkle_block(merkle_block: &str, txid: TxId) -> Result<String, ApiError> {
+    let bytes = Vec::<u8>::from_hex(merkle_block).unwrap_or_default();
+    let mut txid_bytes = [0; light::HASH_SIZE];
+    txid_bytes.copy_from_slice(txid.as_bytes());
+    let mut block_hash = light::verify_merkle_block(&bytes, &txid_bytes).map_err(
+        |e| -> ApiError {
+            let msg = format!(
+                "Merkle block does not prove the inclusion of txid={}: {:?}",
+                txid.to_string(),
+                e
+            );
+            error::Error::BitcoinBackend(msg).into()
+        },
+    )?;
+    // The hashes are displayed in the reversed byte order.
+    block_hash.reverse();
+    Ok(block_hash.to_hex())
+}
+
+impl PrivateApi {
+    /// Returns the anchoring handler metrics in the Prometheus text format.
+    ///
+    /// `GET /{api_prefix}/v1/metrics`
+    pub fn metrics(&self) -> String {
+        self.shared.with_snapshot(|handler| handler.metrics.to_prometheus())
+    }
+
+    /// Returns the state of the anchoring handler state machine on this node.
+    ///
+    /// `GET /{api_prefix}/v1/state`
+    pub fn handler_state(&self) -> HandlerState {
+        self.shared.with_snapshot(|handler| handler.state.clone())
+    }
+
+    /// Returns up to `EVENTS_PAGE_SIZE` entries of the anchoring lifecycle events log
+    /// starting from the event with the given `start` id. Clients poll the following
+    /// page starting from the id next to the latest received one.
+    ///
+    /// `GET /{api_prefix}/v1/events/:start`
+    pub fn events(&self, start: u64) -> Vec<AnchoringEventEntry> {
+        self.shared.with_snapshot(|handler| handler.events_since(start, EVENTS_PAGE_SIZE))
+    }
+
+    /// Reloads the node configuration of the anchoring handler without the node restart,
+    /// so the `bitcoind` endpoints, credentials and private keys can be rotated.
+    /// The new bitcoin backend is checked to be connected to the anchoring network,
+    /// the configuration is applied by the following commit.
+    ///
+    /// `POST /{api_prefix}/v1/node_config`
+    pub fn reload_node_config(&self, node: AnchoringNodeConfig) -> Result<(), ApiError> {
+        let network = {
+            let snapshot = self.blockchain.snapshot();
+            AnchoringSchema::new(&snapshot).actual_anchoring_config().network
+        };
+        let client = match bitcoin_relay(&node, network) {
+            Ok(client) => client,
+            Err(e) => return Err(error::Error::BitcoinBackend(e.to_string()).into()),
+        };
+        self.shared.push_request(HandlerRequest::NodeConfig(node, client));
+        Ok(())
+    }
+
+    /// Returns the anchoring key of this node for the following change of the validators set,
+    /// which is derived from the extended key with the index next to the one of the key
+    /// in the actual or the following anchoring configuration.
+    ///
+    /// `GET /{api_prefix}/v1/derived_key/next`
+    pub fn next_derived_key(&self) -> Result<DerivedAnchoringKey, ApiError> {
+        let (actual, following) = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = AnchoringSchema::new(&snapshot);
+            (schema.actual_anchoring_config(), schema.following_anchoring_config())
+        };
+        let hd_key = match self.shared.with_snapshot(|handler| handler.hd_key.clone()) {
+            Some(hd_key) => hd_key,
+            None => return Err(error::Error::NoHdKey.into()),
+        };
+        let index = ::std::iter::once(actual)
+            .chain(following)
+            .filter_map(|cfg| hd_key.find_index(&cfg.anchoring_keys))
+            .max()
+            .map_or(0, |index| index + 1);
+        let public_key = match hd_key.public_key(index) {
+            Ok(public_key) => public_key,
+            Err(e) => return Err(error::Error::HdKey(e).into()),
+        };
+        Ok(DerivedAnchoringKey { index, public_key })
+    }
+
+    /// Schedules the notice of the funding transaction with the given `txid`, so the validators
+    /// spend it even if their bitcoin backends have not discovered it.
+    ///
+    /// `POST /{api_prefix}/v1/funding/:txid`
+    pub fn notice_funding(&self, txid: TxId) {
+        self.shared.push_request(HandlerRequest::FundingNotice(txid))
+    }
+
+    /// Returns the anchoring proposals which wait for the approval of the node operator
+    /// by the signing policies of this node.
+    ///
+    /// `GET /{api_prefix}/v1/approvals`
+    pub fn pending_approvals(&self) -> Vec<PendingApproval> {
+        self.shared.with_snapshot(|handler| handler.pending_approvals.clone())
+    }
+
+    /// Schedules the approval of the pending proposals for the given anchored `height`,
+    /// the proposal is signed by this node on the following commit. The approval also
+    /// covers the proposals rebuilt for the same height.
+    ///
+    /// `POST /{api_prefix}/v1/approvals/:height`
+    pub fn approve_proposal(&self, height: u64) {
+        self.shared.push_request(HandlerRequest::ApproveProposal(height))
+    }
+
+    /// Schedules the vote of this validator to sweep all anchoring funds to the given
+    /// refund `address`, the `None` address revokes the vote.
+    ///
+    /// `POST /{api_prefix}/v1/sweep/:address`
+    ///
+    /// `DELETE /{api_prefix}/v1/sweep`
+    pub fn vote_sweep(&self, address: Option<btc::Address>) {
+        self.shared.push_request(HandlerRequest::SweepVote(address))
+    }
+
+    /// Schedules the vote of this validator to pause the anchoring for the given `reason`,
+    /// the `None` reason revokes the vote. After the majority of validators revoke their
+    /// votes the anchoring is resumed from the latest anchoring height.
+    ///
+    /// `POST /{api_prefix}/v1/pause/:reason`
+    ///
+    /// `DELETE /{api_prefix}/v1/pause`
+    pub fn vote_pause(&self, reason: Option<String>) {
+        self.shared.push_request(HandlerRequest::PauseVote(reason))
+    }
+
+    /// Schedules the request of this validator to anchor the block in which the request
+    /// is committed regardless of the anchoring frequency.
+    ///
+    /// `POST /{api_prefix}/v1/trigger`
+    pub fn trigger_anchoring(&self) {
+        self.shared.push_request(HandlerRequest::AnchoringTrigger)
+    }
+
+    /// Schedules the vote of this validator to replace the anchoring key of the validator
+    /// with the given `id` in the actual configuration, the `None` key revokes the vote.
+    /// The rotation is approved once the target validator and the majority of validators
+    /// vote for the same key.
+    ///
+    /// `POST /{api_prefix}/v1/key_rotation/:id/:key`
+    ///
+    /// `DELETE /{api_prefix}/v1/key_rotation/:id`
+    pub fn vote_key_rotation(&self, id: u16, key: Option<btc::PublicKey>) {
+        let request = HandlerRequest::KeyRotationVote(ValidatorId(id), key);
+        self.shared.push_request(request)
+    }
+}
+
+impl Api for PrivateApi {
+    fn wire(&self, router: &mut Router) {
+        let _self = self.clone();
+        let metrics = move |_: &mut Request| -> IronResult<Response> {
+            let mut response = Response::with((status::Ok, _self.metrics()));
+            response.headers.set(ContentType::plaintext());
+            Ok(response)
+        };
+
+        let _self = self.clone();
+        let handler_state = move |_: &mut Request| -> IronResult<Response> {
+            _self.ok_response(&json!(_self.handler_state()))
+        };
+
+        let _self = self.clone();
+        let events = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("start") {
+                Some(start_str) => {
+                    let start: u64 = start_str.parse().map_err(|e| {
+                        let msg =
+                            format!("An error during parsing of the event id occurred: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    _self.ok_response(&json!(_self.events(start)))
+                }
+                None => {
+                    let msg = "The event id is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let reload_node_config = move |req: &mut Request| -> IronResult<Response> {
+            let mut body = String::new();
+            req.body.read_to_string(&mut body).map_err(|e| {
+                let msg = format!("Unable to read the request body: {}", e);
+                ApiError::IncorrectRequest(msg.into())
+            })?;
+            let node: AnchoringNodeConfig = serde_json::from_str(&body).map_err(|e| {
+                let msg = format!("An error during parsing of the node config occurred: {}", e);
+                ApiError::IncorrectRequest(msg.into())
+            })?;
+            _self.reload_node_config(node)?;
+            _self.ok_response(&json!(null))
+        };
+
+        let _self = self.clone();
+        let next_derived_key = move |_: &mut Request| -> IronResult<Response> {
+            _self.ok_response(&json!(_self.next_derived_key()?))
+        };
+
+        let _self = self.clone();
+        let notice_funding = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("txid") {
+                Some(txid_str) => {
+                    let txid = TxId::from_hex(txid_str).map_err(|e| {
+                        let msg = format!("An error during parsing of the txid occurred: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    _self.notice_funding(txid);
+                    _self.ok_response(&json!(txid))
+                }
+                None => {
+                    let msg = "The txid is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let pending_approvals = move |_: &mut Request| -> IronResult<Response> {
+            _self.ok_response(&json!(_self.pending_approvals()))
+        };
+
+        let _self = self.clone();
+        let approve_proposal = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("height") {
+                Some(height_str) => {
+                    let height: u64 = height_str.parse().map_err(|e| {
+                        let msg = format!(
+                            "An error during parsing of the block height occurred: {}",
+                            e
+                        );
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    _self.approve_proposal(height);
+                    _self.ok_response(&json!(height))
+                }
+                None => {
+                    let msg = "The block height is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let vote_sweep = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("address") {
+                Some(address_str) => {
+                    let address = address_str.parse::<btc::Address>().map_err(|e| {
+                        let msg = format!("An error during parsing of the address: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    _self.vote_sweep(Some(address.clone()));
+                    _self.ok_response(&json!(address))
+                }
+                None => {
+                    let msg = "The address is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let revoke_sweep = move |_: &mut Request| -> IronResult<Response> {
+            _self.vote_sweep(None);
+            _self.ok_response(&json!(null))
+        };
+
+        let _self = self.clone();
+        let vote_pause = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            match map.find("reason") {
+                Some(reason) if !reason.is_empty() => {
+                    _self.vote_pause(Some(reason.to_string()));
+                    _self.ok_response(&json!(reason))
+                }
+                _ => {
+                    let msg = "The reason is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let revoke_pause = move |_: &mut Request| -> IronResult<Response> {
+            _self.vote_pause(None);
+            _self.ok_response(&json!(null))
+        };
+
+        let _self = self.clone();
+        let trigger_anchoring = move |_: &mut Request| -> IronResult<Response> {
+            _self.trigger_anchoring();
+            _self.ok_response(&json!(null))
+        };
+
+        let _self = self.clone();
+        let vote_key_rotation = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            let id = parse_validator_id(map.find("id"))?;
+            match map.find("key") {
+                Some(key_str) => {
+                    let key = btc::PublicKey::from_hex(key_str).map_err(|e| {
+                        let msg = format!("An error during parsing of the public key: {}", e);
+                        ApiError::IncorrectRequest(msg.into())
+                    })?;
+                    _self.vote_key_rotation(id, Some(key));
+                    _self.ok_response(&json!(key))
+                }
+                None => {
+                    let msg = "The public key is not specified.";
+                    Err(ApiError::IncorrectRequest(msg.into()))?
+                }
+            }
+        };
+
+        let _self = self.clone();
+        let revoke_key_rotation = move |req: &mut Request| -> IronResult<Response> {
+            let map = req.extensions.get::<Router>().unwrap();
+            let id = parse_validator_id(map.find("id"))?;
+            _self.vote_key_rotation(id, None);
+            _self.ok_response(&json!(null))
+        };
+
+        router.get("/v1/metrics", metrics, "metrics");
+        router.get("/v1/state", handler_state, "handler_state");
+        router.get("/v1/events/:start", events, "events");
+        router.post("/v1/node_config", reload_node_config, "reload_node_config");
+        router.get("/v1/derived_key/next", next_derived_key, "next_derived_key");
+        router.post("/v1/funding/:txid", notice_funding, "notice_funding");
+        router.get("/v1/approvals", pending_approvals, "pending_approvals");
+        router.post("/v1/approvals/:height", approve_proposal, "approve_proposal");
+        router.post("/v1/sweep/:address", vote_sweep, "vote_sweep");
+        router.delete("/v1/sweep", revoke_sweep, "revoke_sweep");
+        router.post("/v1/pause/:reason", vote_pause, "vote_pause");
+        router.delete("/v1/pause", revoke_pause, "revoke_pause");
+        router.post("/v1/trigger", trigger_anchoring, "trigger_anchoring");
+        router.post(
+            "/v1/key_rotation/:id/:key",
+            vote_key_rotation,
+            "vote_key_rotation",
+        );
+        router.delete(
+            "/v1/key_rotation/:id",
+            revoke_key_rotation,
+            "revoke_key_rotation",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::percentile;
+
+    #[test]
+    fn test_latency_percentile() {
+        assert_eq!(percentile(&[], 50), None);
+        assert_eq!(percentile(&[10], 99), Some(10));
+
+        let latencies = (1..101).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50), Some(50));
+        assert_eq!(percentile(&latencies, 90), Some(90));
+        assert_eq!(percentile(&latencies, 99), Some(99));
+        assert_eq!(percentile(&[1, 2, 3], 50), Some(2));
     }
 }
@@ -0,0 +1,322 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Maintenance utility of the anchoring deployments.
+
+extern crate bitcoin;
+extern crate clap;
+extern crate exonum_btc_anchoring;
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use exonum_btc_anchoring::{AnchoringConfig, AnchoringRpcConfig, BitcoinRelay, EsploraClient,
+                           EsploraConfig, RpcClient};
+use exonum_btc_anchoring::details::btc;
+use exonum_btc_anchoring::tool::{format_chain_table, fund_anchoring_address,
+                                 gen_anchoring_config, inspect_anchoring_chain};
+
+fn main() {
+    let relay_args = vec![
+        Arg::with_name("host")
+            .long("host")
+            .takes_value(true)
+            .help("Host of bitcoind."),
+        Arg::with_name("user")
+            .long("user")
+            .takes_value(true)
+            .help("User to login into bitcoind."),
+        Arg::with_name("password")
+            .long("password")
+            .takes_value(true)
+            .help("Password to login into bitcoind."),
+        Arg::with_name("esplora")
+            .long("esplora")
+            .takes_value(true)
+            .conflicts_with("host")
+            .help("Base url of the Esplora API used instead of bitcoind."),
+    ];
+    let matches = App::new("anchoring_tool")
+        .about("Maintenance utility of the bitcoin anchoring deployments.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Follows the anchoring chain and prints its transactions.")
+                .args(&relay_args)
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Actual anchoring address."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["table", "json"])
+                        .default_value("table")
+                        .help("Output format."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("gen-config")
+                .about(
+                    "Generates the anchoring keys of the validators, the common anchoring \
+                     configuration and the configurations of the nodes.",
+                )
+                .args(&relay_args[..3])
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Number of the validators."),
+                )
+                .arg(
+                    Arg::with_name("network")
+                        .long("network")
+                        .takes_value(true)
+                        .possible_values(&["mainnet", "testnet", "signet", "regtest"])
+                        .default_value("testnet")
+                        .help("Bitcoin chain of the anchoring transactions."),
+                )
+                .arg(
+                    Arg::with_name("fee")
+                        .long("fee")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .help("Fee of the anchoring transactions in satoshis."),
+                )
+                .arg(
+                    Arg::with_name("frequency")
+                        .long("frequency")
+                        .takes_value(true)
+                        .default_value("500")
+                        .help("Anchoring frequency in blocks."),
+                )
+                .arg(
+                    Arg::with_name("output-dir")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .help(
+                            "Directory of the generated TOML files, the configuration \
+                             is printed as JSON if it is not specified.",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fund")
+                .about(
+                    "Sends the funding transaction from the bitcoind wallet to the anchoring \
+                     address and waits for its confirmations.",
+                )
+                .args(&relay_args[..3])
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .takes_value(true)
+                        .required_unless("config")
+                        .conflicts_with("config")
+                        .help("Anchoring address to fund."),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .takes_value(true)
+                        .help(
+                            "Common anchoring configuration in the TOML format, its anchoring \
+                             address is funded and its `funding_tx` is updated.",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Amount of the funding transaction in satoshis."),
+                )
+                .arg(
+                    Arg::with_name("confirmations")
+                        .long("confirmations")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Number of the confirmations to wait for."),
+                )
+                .arg(
+                    Arg::with_name("poll-interval")
+                        .long("poll-interval")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("Interval in seconds between the confirmations checks."),
+                ),
+        )
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("inspect", Some(matches)) => inspect(matches),
+        ("gen-config", Some(matches)) => gen_config(matches),
+        ("fund", Some(matches)) => fund(matches),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn inspect(matches: &ArgMatches) -> Result<(), String> {
+    let relay = relay(matches)?;
+    let addr = parse_address(matches.value_of("address").unwrap())?;
+    let chain = inspect_anchoring_chain(&*relay, &addr).map_err(|e| e.to_string())?;
+    match matches.value_of("format") {
+        Some("json") => println!("{}", serde_json::to_string_pretty(&chain).unwrap()),
+        _ => print!("{}", format_chain_table(&chain)),
+    }
+    Ok(())
+}
+
+fn gen_config(matches: &ArgMatches) -> Result<(), String> {
+    let count = parse_number(matches, "count")?;
+    if count == 0 || count > u64::from(u8::max_value()) {
+        return Err(format!("Incorrect number of the validators: {}", count));
+    }
+    let network = matches.value_of("network").unwrap().parse::<btc::Chain>()?;
+    let fee = parse_number(matches, "fee")?;
+    let frequency = parse_number(matches, "frequency")?;
+    if frequency == 0 {
+        return Err("Anchoring frequency must be positive".to_string());
+    }
+    let rpc = matches.value_of("host").map(|host| AnchoringRpcConfig {
+        host: host.to_string(),
+        username: matches.value_of("user").map(ToString::to_string),
+        password: matches.value_of("password").map(ToString::to_string),
+        ..Default::default()
+    });
+
+    let config = gen_anchoring_config(network, count as u8, fee, frequency, rpc);
+    let dir = match matches.value_of("output-dir") {
+        Some(dir) => Path::new(dir),
+        None => {
+            println!("{}", serde_json::to_string_pretty(&config).unwrap());
+            return Ok(());
+        }
+    };
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    write_toml(&dir.join("anchoring.toml"), &config.genesis)?;
+    for (idx, node) in config.nodes.iter().enumerate() {
+        write_toml(&dir.join(format!("anchoring_node_{}.toml", idx)), node)?;
+    }
+    println!("Anchoring address: {}", config.address.to_string());
+    println!("Redeem script: {}", config.redeem_script.to_string());
+    println!(
+        "Send the funding transaction to the anchoring address and put it into the \
+         `funding_tx` of {} before the genesis block is created.",
+        dir.join("anchoring.toml").display()
+    );
+    Ok(())
+}
+
+fn fund(matches: &ArgMatches) -> Result<(), String> {
+    let relay = relay(matches)?;
+    let amount = parse_number(matches, "amount")?;
+    let confirmations = parse_number(matches, "confirmations")?;
+    let poll_interval = Duration::from_secs(parse_number(matches, "poll-interval")?);
+
+    let mut config = match matches.value_of("config") {
+        Some(path) => Some(read_toml::<AnchoringConfig>(Path::new(path))?),
+        None => None,
+    };
+    let addr = match config {
+        Some(ref config) => config.redeem_script().1,
+        None => parse_address(matches.value_of("address").unwrap())?,
+    };
+
+    println!(
+        "Funding {} with {} satoshis, waiting for {} confirmations",
+        addr.to_string(),
+        amount,
+        confirmations
+    );
+    let tx = fund_anchoring_address(&*relay, &addr, amount, confirmations, poll_interval)
+        .map_err(|e| e.to_string())?;
+    println!("Funding transaction: {}", tx.id().to_string());
+    println!("Raw funding transaction: {}", tx.to_string());
+
+    if let Some(ref mut config) = config {
+        let path = Path::new(matches.value_of("config").unwrap());
+        config.funding_tx = Some(tx);
+        write_toml(path, config)?;
+        println!("The `funding_tx` of {} is updated", path.display());
+    }
+    Ok(())
+}
+
+fn read_toml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    toml::from_str(&contents).map_err(|e| {
+        format!("Unable to parse {}: {}", path.display(), e)
+    })
+}
+
+fn write_toml<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let value = toml::Value::try_from(value).map_err(|e| e.to_string())?;
+    let mut file = File::create(path).map_err(|e| {
+        format!("Unable to create {}: {}", path.display(), e)
+    })?;
+    file.write_all(value.to_string().as_bytes()).map_err(
+        |e| e.to_string(),
+    )
+}
+
+fn parse_number(matches: &ArgMatches, name: &str) -> Result<u64, String> {
+    let value = matches.value_of(name).unwrap();
+    value.parse::<u64>().map_err(|e| {
+        format!("Incorrect value {} of the `{}`: {}", value, name, e)
+    })
+}
+
+fn relay(matches: &ArgMatches) -> Result<Box<BitcoinRelay>, String> {
+    if let Some(url) = matches.value_of("esplora") {
+        let config = EsploraConfig { url: url.to_string() };
+        return Ok(Box::new(EsploraClient::new(config)));
+    }
+    let host = matches.value_of("host").ok_or_else(|| {
+        "Either the bitcoind host or the Esplora url must be specified".to_string()
+    })?;
+    let config = AnchoringRpcConfig {
+        host: host.to_string(),
+        username: matches.value_of("user").map(ToString::to_string),
+        password: matches.value_of("password").map(ToString::to_string),
+        ..Default::default()
+    };
+    Ok(Box::new(RpcClient::from(config)))
+}
+
+fn parse_address(addr: &str) -> Result<btc::Address, String> {
+    addr.parse::<btc::Address>().map_err(|e| {
+        format!("An error during parsing of the address: {}", e)
+    })
+}
@@ -13,48 +13,293 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 
 use serde_json;
 use serde::{Deserialize, Deserializer};
 
+use exonum::blockchain::ValidatorKeys;
 use exonum::storage::StorageValue;
-use exonum::crypto::{Hash, hash};
+use exonum::crypto::{self, Hash, hash};
 use exonum::helpers::Height;
 
 use details::btc;
-use details::btc::transactions::FundingTx;
+use details::btc::selection::CoinSelection;
+use details::btc::transactions::{FundingTx, DUST_THRESHOLD};
 
 /// Public part of anchoring service configuration stored in blockchain.
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct AnchoringConfig {
     /// Validators' public keys from which the current anchoring address can be calculated.
     pub anchoring_keys: Vec<btc::PublicKey>,
+    /// The number of the signatures required to spend the funds of the anchoring address,
+    /// that is `m` of the `m-of-n` multisig. It may differ from the consensus majority
+    /// of the validators, but it must be within the [`threshold_bounds`][1].
+    /// If it is not set, the majority of the anchoring keys is used.
+    ///
+    /// [1]: struct.AnchoringConfig.html#method.threshold_bounds
+    #[serde(default)]
+    pub threshold: Option<u8>,
     /// The transaction that funds anchoring address.
     /// If the anchoring transactions chain is empty, it will be the first transaction in the chain.
     /// Note: you must specify a suitable transaction before the network launching.
     pub funding_tx: Option<FundingTx>,
     /// Fee for each transaction in chain.
+    /// If the `fee_conf_target` is set, it is the minimal fee of the anchoring transaction.
+    ///
+    /// Can be changed by the configuration update, the new fee is used for all anchoring
+    /// transactions proposed since the `actual_from` height.
     pub fee: u64,
     /// The frequency in blocks with which the generation of new anchoring
     /// transactions in the chain occurs.
+    ///
+    /// Can be changed by the configuration update, since the `actual_from` height
+    /// the anchored heights are multiples of the new frequency.
     pub frequency: u64,
-    /// The minimum number of confirmations in bitcoin network for the transition to a
-    /// new anchoring address.
+    /// The minimum number of confirmations in bitcoin network of the funding transactions
+    /// before they are spent by the anchoring transactions.
+    ///
+    /// It is also used for the anchoring transactions if the `anchor_confirmations`
+    /// is not set.
     pub utxo_confirmations: u64,
-    /// The current bitcoin network type.
+    /// The minimum number of confirmations in bitcoin network of the anchoring transactions
+    /// before they are considered final, so the transition to a new anchoring address
+    /// is continued and the transactions are added to the anchoring chain by the observer.
+    #[serde(default)]
+    pub anchor_confirmations: Option<u64>,
+    /// The bitcoin chain to which the anchoring transactions are published.
     #[serde(serialize_with = "btc_network_to_str", deserialize_with = "btc_network_from_str")]
-    pub network: btc::Network,
+    pub network: btc::Chain,
+    /// Type of the anchoring multisig address.
+    #[serde(default)]
+    pub script_type: btc::ScriptType,
+    /// The maximum number of replace-by-fee bumps for the stuck anchoring transaction.
+    /// If it is zero, anchoring transactions do not signal replaceability.
+    #[serde(default)]
+    pub max_fee_bumps: u64,
+    /// The maximum fee rate of the replacement or estimated anchoring transaction
+    /// in satoshis per byte.
+    #[serde(default = "default_max_fee_rate")]
+    pub max_fee_rate: u64,
+    /// The minimal fee of the anchoring transaction which the validators sign.
+    /// The signatures of the proposals with a lower fee are rejected, so the anchoring
+    /// chain is not stalled by a transaction which is never mined.
+    #[serde(default)]
+    pub min_fee: u64,
+    /// The maximal fee of the anchoring transaction which the validators sign.
+    /// The signatures of the proposals with a higher fee are rejected, so a faulty
+    /// proposer cannot drain the anchoring funds. If it is not set, the fee is limited
+    /// only by the `max_fee_rate`.
+    #[serde(default)]
+    pub max_fee: Option<u64>,
+    /// Whether the anchoring proposal spending the stuck lect pays for it (child pays
+    /// for parent), if the lect cannot be replaced by the transaction with a higher fee.
+    /// The lect is considered stuck while it pays less than the fee rate recorded
+    /// in the blockchain, so it has no effect without the `fee_conf_target`.
+    #[serde(default)]
+    pub cpfp: bool,
+    /// The confirmation target in blocks for the fee estimation.
+    /// If it is set, the validators record the fee rates estimated by their bitcoin nodes
+    /// in the blockchain and the anchoring proposals use the median of them, otherwise
+    /// or until the majority of the validators have recorded the estimations the static
+    /// `fee` is used.
+    #[serde(default)]
+    pub fee_conf_target: Option<u16>,
+    /// Whether the anchoring transactions commit to the `state_hash` of the anchored block
+    /// in addition to its hash.
+    ///
+    /// Can be changed by the configuration update.
+    #[serde(default)]
+    pub anchor_state_hash: bool,
+    /// Identifier of the anchored blockchain which is committed by the anchoring transactions.
+    ///
+    /// If it is set, the lects and signatures of the anchoring transactions with another
+    /// identifier are rejected, so the deployments sharing the bitcoin wallet cannot
+    /// confuse their anchors.
+    #[serde(default)]
+    pub chain_id: Option<u32>,
+    /// The minimal number of the anchoring transactions which the lect funds should cover.
+    /// If the funds are lower, the handler alerts about them. If it is zero, the alerts
+    /// are disabled.
+    #[serde(default)]
+    pub low_funds_anchors: u64,
+    /// The frequency of the anchoring while the lect funds are lower than
+    /// the `low_funds_anchors` transactions cover, it should be a multiple of the `frequency`.
+    /// If it is not set, the anchoring frequency does not depend on the funds.
+    #[serde(default)]
+    pub low_funds_frequency: Option<u64>,
+    /// Policy of anchoring the intervals missed while the anchoring has been stalled,
+    /// for example, by the bitcoin node outage or the lack of funds.
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    /// The maximum time in seconds between the anchoring transactions. Once it is exceeded
+    /// by the local clock of the validator, the validator triggers the anchoring of the latest
+    /// block regardless of the `frequency`. If it is not set, only the `frequency` is used.
+    #[serde(default)]
+    pub max_anchoring_interval: Option<u64>,
+    /// Strategy of choosing the funding transactions spent by the anchoring proposals.
+    #[serde(default)]
+    pub coin_selection: CoinSelection,
+    /// The minimal value of the change output of the anchoring proposal, the funding
+    /// transactions are spent until the change reaches it.
+    /// It is not used by the `consolidate_all` strategy.
+    #[serde(default)]
+    pub min_change: u64,
+    /// The frequency of the consolidating anchoring proposals, it should be a multiple
+    /// of the `frequency`. The proposal for the multiple height spends the remaining
+    /// funding transactions with the smallest values first up to the `max_consolidation_inputs`
+    /// inputs, so the following anchoring transactions stay small.
+    /// If it is not set, the funding transactions are spent only by the `coin_selection`.
+    #[serde(default)]
+    pub consolidation_frequency: Option<u64>,
+    /// The maximum number of inputs of the consolidating anchoring proposal.
+    #[serde(default = "default_max_consolidation_inputs")]
+    pub max_consolidation_inputs: u64,
+    /// The maximum number of inputs of any anchoring proposal. The number of inputs is also
+    /// limited by the size of the standard bitcoin transaction, the funding transactions
+    /// beyond the limit are spent by the following proposals.
+    #[serde(default = "default_max_proposal_inputs")]
+    pub max_proposal_inputs: u64,
+    /// The timelocked recovery branch of the anchoring redeem script.
+    ///
+    /// If it is set, the recovery key can reclaim the anchoring funds without the validators
+    /// once the anchoring output is `timeout` blocks deep. The branch changes the anchoring
+    /// address, so it can be changed only together with the anchoring address transition.
+    #[serde(default)]
+    pub recovery: Option<btc::RecoveryBranch>,
+    /// Pruning policy of the signatures of the obsolete anchoring rounds.
+    /// If it is not set, the signatures are kept forever.
+    #[serde(default)]
+    pub signatures_pruning: Option<SignaturesPruning>,
+    /// The number of blocks after the approval of the anchoring key rotation
+    /// till the activation of the rotated configuration, the transition to the new
+    /// anchoring address should be finished in that time.
+    #[serde(default = "default_key_rotation_delay")]
+    pub key_rotation_delay: u64,
+    /// The number of blocks after which the transfer transaction to the following address
+    /// without confirmations is replaced by the one with the bumped fee, or is restarted
+    /// from the actual unspent outputs if the fee cannot be bumped. It should be less than
+    /// the delay of the following configuration activation.
+    /// If it is not set, the transfer transaction is only resent.
+    #[serde(default)]
+    pub transfer_timeout: Option<u64>,
+    /// The number of blocks before the activation of the following configuration with
+    /// a new anchoring address at which the funds are transferred to that address.
+    /// Until then the anchoring transactions are produced on the actual address as usual.
+    /// If it is not set, the funds are transferred once the following configuration
+    /// is committed.
+    #[serde(default)]
+    pub transfer_lead: Option<u64>,
+    /// Policy of sending the surplus of the anchoring funds to another address.
+    /// If it is not set, all the change stays on the anchoring address.
+    #[serde(default)]
+    pub change_policy: Option<ChangePolicy>,
+    /// Handling of the change of the anchoring proposal below the dust threshold.
+    #[serde(default)]
+    pub dust_policy: DustPolicy,
+}
+
+/// Policy of the change output of the anchoring proposals.
+///
+/// The proposal keeps `retain` satoshis on the anchoring address and sends the rest
+/// of the change to the `address` by the additional output, e.g. to the cold storage.
+/// The surplus below the dust threshold stays on the anchoring address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangePolicy {
+    /// Address to which the surplus is sent.
+    pub address: btc::Address,
+    /// Funds in satoshis kept on the anchoring address.
+    pub retain: u64,
+}
+
+/// Pruning policy of the signatures of the anchoring proposals.
+///
+/// The signatures are pruned once the common lect anchors a block which is more than
+/// `retain_rounds` anchoring rounds newer than the block anchored by their proposal,
+/// so with zero `retain_rounds` only the rounds newer than the common lect are kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignaturesPruning {
+    /// Number of the anchoring rounds older than the common lect whose signatures are kept.
+    pub retain_rounds: u64,
+    /// Whether the hashes of the pruned signature messages are kept in the
+    /// [`archived_signatures`](../schema/struct.AnchoringSchema.html#method.archived_signatures)
+    /// table for the auditing.
+    #[serde(default)]
+    pub archive: bool,
+}
+
+/// Policy of anchoring the heights missed while the anchoring has been stalled
+/// for several intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// Anchors only the latest anchoring height, it covers all of the missed blocks.
+    Latest,
+    /// Anchors each missed anchoring height by its own transaction, one per round.
+    AllMissed,
+}
+
+/// Handling of the change of the anchoring proposal which falls below the dust threshold
+/// once the anchoring address runs dry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DustPolicy {
+    /// Keeps the change output anyway, such transaction may be not relayed
+    /// by the bitcoin nodes.
+    Keep,
+    /// Burns the change into the fee. The final anchoring transaction has no change output,
+    /// so the anchoring chain continues only from a new funding transaction.
+    Burn,
+}
+
+impl Default for DustPolicy {
+    fn default() -> DustPolicy {
+        DustPolicy::Keep
+    }
+}
+
+impl Default for CatchUpPolicy {
+    fn default() -> CatchUpPolicy {
+        CatchUpPolicy::Latest
+    }
 }
 
 impl Default for AnchoringConfig {
     fn default() -> AnchoringConfig {
         AnchoringConfig {
             anchoring_keys: vec![],
+            threshold: None,
             funding_tx: None,
             fee: 1000,
             frequency: 500,
             utxo_confirmations: 5,
-            network: btc::Network::Testnet,
+            anchor_confirmations: None,
+            network: btc::Chain::Testnet,
+            script_type: btc::ScriptType::P2sh,
+            max_fee_bumps: 0,
+            max_fee_rate: default_max_fee_rate(),
+            min_fee: 0,
+            max_fee: None,
+            cpfp: false,
+            fee_conf_target: None,
+            anchor_state_hash: false,
+            chain_id: None,
+            low_funds_anchors: 0,
+            low_funds_frequency: None,
+            catch_up: CatchUpPolicy::Latest,
+            max_anchoring_interval: None,
+            coin_selection: CoinSelection::ConsolidateAll,
+            min_change: 0,
+            consolidation_frequency: None,
+            max_consolidation_inputs: default_max_consolidation_inputs(),
+            max_proposal_inputs: default_max_proposal_inputs(),
+            recovery: None,
+            signatures_pruning: None,
+            key_rotation_delay: default_key_rotation_delay(),
+            transfer_timeout: None,
+            transfer_lead: None,
+            change_policy: None,
+            dust_policy: DustPolicy::Keep,
         }
     }
 }
@@ -65,57 +310,275 @@ impl AnchoringConfig {
     /// the public configuration before launching.
     /// Do not forget to send funding transaction to the final multisig address
     /// and add it to the final configuration.
-    pub fn new<I>(network: btc::Network, anchoring_keys: I) -> AnchoringConfig
+    pub fn new<N, I>(network: N, anchoring_keys: I) -> AnchoringConfig
     where
+        N: Into<btc::Chain>,
         I: IntoIterator<Item = btc::PublicKey>,
     {
         AnchoringConfig {
             anchoring_keys: anchoring_keys.into_iter().collect(),
-            network: network,
+            network: network.into(),
             ..Default::default()
         }
     }
 
     /// Creates default anchoring configuration from given public keys and funding transaction
     /// which were created earlier by other way.
-    pub fn new_with_funding_tx<I>(
-        network: btc::Network,
+    pub fn new_with_funding_tx<N, I>(
+        network: N,
         anchoring_keys: I,
         tx: FundingTx,
     ) -> AnchoringConfig
     where
+        N: Into<btc::Chain>,
         I: IntoIterator<Item = btc::PublicKey>,
     {
         AnchoringConfig {
             anchoring_keys: anchoring_keys.into_iter().collect(),
             funding_tx: Some(tx),
-            network: network,
+            network: network.into(),
             ..Default::default()
         }
     }
 
+    /// Creates the configuration for the `following` validators from this configuration
+    /// of the `actual` validators. The anchoring keys of the validators which remain
+    /// in the set are reused, the keys of the added validators are taken from the `added_keys`
+    /// by their service keys. The majority count and the anchoring address of the resulting
+    /// configuration are derived from the updated anchoring keys as usual.
+    ///
+    /// Returns the service key of the added validator without the anchoring key as an error.
+    pub fn with_validators(
+        &self,
+        actual: &[ValidatorKeys],
+        following: &[ValidatorKeys],
+        added_keys: &BTreeMap<crypto::PublicKey, btc::PublicKey>,
+    ) -> Result<AnchoringConfig, crypto::PublicKey> {
+        let actual_keys = actual
+            .iter()
+            .zip(&self.anchoring_keys)
+            .map(|(keys, anchoring_key)| (keys.service_key, *anchoring_key))
+            .collect::<BTreeMap<_, _>>();
+        let mut anchoring_keys = Vec::with_capacity(following.len());
+        for keys in following {
+            let anchoring_key = actual_keys
+                .get(&keys.service_key)
+                .or_else(|| added_keys.get(&keys.service_key));
+            match anchoring_key {
+                Some(anchoring_key) => anchoring_keys.push(*anchoring_key),
+                None => return Err(keys.service_key),
+            }
+        }
+        Ok(AnchoringConfig {
+            anchoring_keys,
+            ..self.clone()
+        })
+    }
+
     #[doc(hidden)]
     /// Creates compressed `RedeemScript` from public keys in config.
+    /// The script includes the recovery branch if it is configured.
     pub fn redeem_script(&self) -> (btc::RedeemScript, btc::Address) {
-        let majority_count = self.majority_count();
-        let redeem_script =
-            btc::RedeemScript::from_pubkeys(self.anchoring_keys.iter(), majority_count)
-                .compressed(self.network);
-        let addr = btc::Address::from_script(&redeem_script, self.network);
+        let threshold = self.signature_threshold();
+        let network = self.btc_network();
+        let mut redeem_script =
+            btc::RedeemScript::from_pubkeys(self.anchoring_keys.iter(), threshold)
+                .compressed(network);
+        if let Some(ref recovery) = self.recovery {
+            redeem_script = redeem_script.with_recovery(recovery);
+        }
+        let addr = match self.script_type {
+            btc::ScriptType::P2sh => btc::Address::from_script(&redeem_script, network),
+            btc::ScriptType::P2shP2wsh => redeem_script.to_witness_address(network),
+            btc::ScriptType::P2wsh => redeem_script.to_native_witness_address(self.network),
+        };
         (redeem_script, addr)
     }
 
+    /// Returns the output descriptor of the anchoring address, which is imported
+    /// to the `bitcoind` wallet. The scripts with the recovery branch are described
+    /// by the address itself.
+    pub fn output_descriptor(&self) -> String {
+        if self.recovery.is_some() {
+            let (_, addr) = self.redeem_script();
+            return btc::descriptor::address(&addr);
+        }
+        btc::descriptor::multisig(
+            self.signature_threshold(),
+            self.anchoring_keys.iter(),
+            self.script_type,
+        )
+    }
+
+    /// Returns the bitcoin network which defines the base58 format of the anchoring addresses.
+    pub fn btc_network(&self) -> btc::Network {
+        self.network.base58_network()
+    }
+
+    /// Returns the native segwit form of the segwit anchoring address encoded
+    /// for the configured chain, for example with the `bcrt` prefix on `regtest`.
+    pub fn native_witness_address(&self) -> Option<String> {
+        match self.script_type {
+            btc::ScriptType::P2sh => None,
+            btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                let (redeem_script, _) = self.redeem_script();
+                Some(redeem_script.to_native_witness_address(self.network).to_string())
+            }
+        }
+    }
+
     #[doc(hidden)]
     /// Returns the latest height below the given `height` which needs to be anchored.
     pub fn latest_anchoring_height(&self, height: Height) -> Height {
         Height(height.0 - height.0 % self.frequency as u64)
     }
 
+    /// Returns true if the given `funds` cover less than `low_funds_anchors` anchoring
+    /// transactions with the configured fee.
+    pub fn is_low_funds(&self, funds: u64) -> bool {
+        funds < self.low_funds_anchors.saturating_mul(self.fee)
+    }
+
+    #[doc(hidden)]
+    /// Returns the latest height to anchor with the stretched frequency if the
+    /// funds are low.
+    pub fn latest_anchoring_height_for_funds(&self, height: Height, funds: u64) -> Height {
+        match self.low_funds_frequency {
+            Some(frequency) if frequency > 0 && self.is_low_funds(funds) => {
+                Height(height.0 - height.0 % frequency)
+            }
+            _ => self.latest_anchoring_height(height),
+        }
+    }
+
+    #[doc(hidden)]
+    /// Returns the height which the following proposal anchors after the `anchored` height
+    /// if the `latest` anchoring height is above it.
+    pub fn catch_up_height(&self, anchored: Height, latest: Height) -> Height {
+        match self.catch_up {
+            CatchUpPolicy::Latest => latest,
+            CatchUpPolicy::AllMissed => {
+                let next = self.latest_anchoring_height(anchored).0 + self.frequency;
+                Height(::std::cmp::min(next, latest.0))
+            }
+        }
+    }
+
+    /// Returns the surplus address and the retained funds of the `change_policy`
+    /// in the form accepted by the transaction builder.
+    pub fn surplus_policy(&self) -> Option<(btc::Address, u64)> {
+        self.change_policy
+            .as_ref()
+            .map(|policy| (policy.address.clone(), policy.retain))
+    }
+
+    /// Returns the given fee of the anchoring transaction limited by the `min_fee`
+    /// and `max_fee` bounds.
+    pub fn bounded_fee(&self, fee: u64) -> u64 {
+        let fee = ::std::cmp::max(fee, self.min_fee);
+        match self.max_fee {
+            Some(max_fee) => ::std::cmp::min(fee, max_fee),
+            None => fee,
+        }
+    }
+
+    /// Returns true if the given fee of the anchoring transaction is within
+    /// the `min_fee` and `max_fee` bounds.
+    pub fn is_fee_in_bounds(&self, fee: u64) -> bool {
+        fee >= self.min_fee && self.max_fee.map_or(true, |max_fee| fee <= max_fee)
+    }
+
+    /// Returns true if the given fee of the final anchoring transaction is within the bounds,
+    /// its fee also includes the burned change below the dust threshold.
+    pub fn is_final_fee_in_bounds(&self, fee: u64) -> bool {
+        fee >= self.min_fee && self.max_fee.map_or(true, |max_fee| fee < max_fee + DUST_THRESHOLD)
+    }
+
+    /// Returns true if the transfer transaction which anchors the `anchored_height` has
+    /// no confirmations for longer than the `transfer_timeout` at the given `height`.
+    pub fn is_transfer_timed_out(
+        &self,
+        anchored_height: Height,
+        confirmations: Option<u64>,
+        height: Height,
+    ) -> bool {
+        match self.transfer_timeout {
+            Some(timeout) => {
+                confirmations.unwrap_or(0) == 0 && height.0 >= anchored_height.0 + timeout
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the transfer to the address of the following configuration which
+    /// becomes actual at the `activation_height` is postponed at the given `height`.
+    pub fn is_transfer_postponed(&self, activation_height: Height, height: Height) -> bool {
+        match self.transfer_lead {
+            Some(lead) => height.0 + lead < activation_height.0,
+            None => false,
+        }
+    }
+
+    /// Returns true if the anchoring proposal for the given height consolidates the funds.
+    pub fn is_consolidation_height(&self, height: Height) -> bool {
+        match self.consolidation_frequency {
+            Some(frequency) if frequency > 0 => height.0 % frequency == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns the number of confirmations required for the funding transactions.
+    pub fn required_funding_confirmations(&self) -> u64 {
+        self.utxo_confirmations
+    }
+
+    /// Returns the number of confirmations required for the anchoring transactions.
+    pub fn required_anchor_confirmations(&self) -> u64 {
+        self.anchor_confirmations.unwrap_or(self.utxo_confirmations)
+    }
+
     #[doc(hidden)]
     pub fn majority_count(&self) -> u8 {
         ::majority_count(self.anchoring_keys.len() as u8)
     }
 
+    /// Returns the number of the signatures required to spend the funds of the anchoring
+    /// address, it is the `threshold` if it is set or the majority of the anchoring keys.
+    pub fn signature_threshold(&self) -> u8 {
+        self.threshold.unwrap_or_else(|| self.majority_count())
+    }
+
+    /// Returns the minimum and the maximum safe thresholds for the number of the anchoring
+    /// keys. The minimum exceeds the number of the faulty validators tolerated by
+    /// the consensus, so they cannot spend the anchoring funds on their own, and the maximum
+    /// leaves them out, so the rest of the validators are able to continue the anchoring.
+    pub fn threshold_bounds(&self) -> (u8, u8) {
+        let count = self.anchoring_keys.len() as u8;
+        let faulty = count.saturating_sub(1) / 3;
+        (faulty + 1, count - faulty)
+    }
+
+    /// Checks that the configured `threshold` is within the [`threshold_bounds`][1].
+    ///
+    /// [1]: struct.AnchoringConfig.html#method.threshold_bounds
+    pub fn check_threshold(&self) -> Result<(), String> {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return Ok(()),
+        };
+        let (min, max) = self.threshold_bounds();
+        if threshold < min || threshold > max {
+            return Err(format!(
+                "Threshold {} of {} anchoring keys is beyond the safe bounds [{}, {}]",
+                threshold,
+                self.anchoring_keys.len(),
+                min,
+                max
+            ));
+        }
+        Ok(())
+    }
+
     /// Returns the funding transaction.
     ///
     /// # Panics
@@ -129,28 +592,39 @@ impl AnchoringConfig {
     }
 }
 
-fn btc_network_to_str<S>(network: &btc::Network, ser: S) -> Result<S::Ok, S::Error>
+fn default_max_fee_rate() -> u64 {
+    100
+}
+
+fn default_key_rotation_delay() -> u64 {
+    1000
+}
+
+fn default_max_consolidation_inputs() -> u64 {
+    50
+}
+
+fn default_max_proposal_inputs() -> u64 {
+    100
+}
+
+fn btc_network_to_str<S>(network: &btc::Chain, ser: S) -> Result<S::Ok, S::Error>
 where
     S: ::serde::Serializer,
 {
-    match *network {
-        btc::Network::Bitcoin => ser.serialize_str("bitcoin"),
-        btc::Network::Testnet => ser.serialize_str("testnet"),
-    }
+    ser.serialize_str(&network.to_string())
 }
 
-fn btc_network_from_str<'de, D>(deserializer: D) -> Result<btc::Network, D::Error>
+fn btc_network_from_str<'de, D>(deserializer: D) -> Result<btc::Chain, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
 
-    const VARIANTS: &[&str] = &["bitcoin", "testnet"];
-    match s.as_str() {
-        "bitcoin" => Ok(btc::Network::Bitcoin),
-        "testnet" => Ok(btc::Network::Testnet),
-        other => Err(::serde::de::Error::unknown_variant(other, VARIANTS)),
-    }
+    const VARIANTS: &[&str] = &["bitcoin", "mainnet", "testnet", "signet", "regtest"];
+    s.parse().map_err(
+        |_| ::serde::de::Error::unknown_variant(&s, VARIANTS),
+    )
 }
 
 impl StorageValue for AnchoringConfig {
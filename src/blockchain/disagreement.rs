@@ -0,0 +1,168 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Detection and resolution of the disagreement between the validators lects.
+//!
+//! Lects of the validators may lag behind each other, but the lects that are not
+//! ancestors of each other mean that the validators follow the different anchoring chains.
+//! In this case the canonical lect is the one which continues the lects of the most
+//! validators, the ties are broken by the anchored height and then by the txid.
+
+use exonum::blockchain::Schema;
+use exonum::helpers::Height;
+use exonum::storage::{Fork, Snapshot};
+
+use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::dto::LectResolution;
+use blockchain::schema::AnchoringSchema;
+use details::btc::transactions::{BitcoinTx, TxKind};
+
+/// Returns the resolution of the disagreement between the lects of the validators from
+/// the given `cfg` at the given `height`, or `None` if the lects do not conflict.
+pub fn detect_lect_disagreement<T>(
+    schema: &AnchoringSchema<T>,
+    cfg: &AnchoringConfig,
+    height: Height,
+) -> Option<LectResolution>
+where
+    T: AsRef<Snapshot>,
+{
+    let validators_lects = cfg.anchoring_keys
+        .iter()
+        .filter_map(|key| schema.lect(key))
+        .collect::<Vec<_>>();
+    // Distinct lects in the order of the anchoring keys.
+    let mut lects: Vec<BitcoinTx> = Vec::new();
+    for lect in &validators_lects {
+        if !lects.iter().any(|tx| tx.id() == lect.id()) {
+            lects.push(lect.clone());
+        }
+    }
+
+    let has_conflict = lects.iter().enumerate().any(|(i, a)| {
+        lects[i + 1..].iter().any(|b| {
+            !is_ancestor(schema, a, b) && !is_ancestor(schema, b, a)
+        })
+    });
+    if !has_conflict {
+        return None;
+    }
+
+    let (canonical_lect, support) = lects
+        .iter()
+        .map(|lect| {
+            let support = validators_lects
+                .iter()
+                .filter(|tx| is_ancestor(schema, tx, lect))
+                .count() as u64;
+            (lect, support)
+        })
+        .max_by(|&(a, a_support), &(b, b_support)| {
+            (a_support, anchored_height(a), a.txid()).cmp(
+                &(b_support, anchored_height(b), b.txid()),
+            )
+        })
+        .map(|(lect, support)| (lect.clone(), support))
+        .unwrap();
+    Some(LectResolution::new(
+        height.0,
+        lects,
+        canonical_lect,
+        support,
+    ))
+}
+
+/// Records the resolution of the disagreement between the actual lects if it differs from
+/// the latest recorded one.
+pub fn resolve_lect_disagreement(fork: &mut Fork) {
+    let height = Height(Schema::new(&*fork).block_hashes_by_height().len());
+    let resolution = {
+        let schema = AnchoringSchema::new(&*fork);
+        let cfg = schema.actual_anchoring_config();
+        match detect_lect_disagreement(&schema, &cfg, height) {
+            Some(resolution) => {
+                let latest = schema.lect_resolutions().last();
+                if latest.map_or(false, |latest| is_same_resolution(&latest, &resolution)) {
+                    return;
+                }
+                resolution
+            }
+            None => return,
+        }
+    };
+
+    warn!(
+        "Validators lects disagree, lects={:?}, canonical lect txid={}, support={}",
+        resolution
+            .lects()
+            .iter()
+            .map(|tx| tx.txid())
+            .collect::<Vec<_>>(),
+        resolution.canonical_lect().txid(),
+        resolution.support()
+    );
+    AnchoringSchema::new(fork).lect_resolutions_mut().push(
+        resolution,
+    );
+}
+
+fn is_same_resolution(a: &LectResolution, b: &LectResolution) -> bool {
+    let ids = |resolution: &LectResolution| {
+        resolution
+            .lects()
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<Vec<_>>()
+    };
+    a.canonical_lect().id() == b.canonical_lect().id() && ids(a) == ids(b)
+}
+
+// Returns true if the `ancestor` is the same transaction as `tx` or precedes it
+// in the anchoring chain.
+fn is_ancestor<T>(schema: &AnchoringSchema<T>, ancestor: &BitcoinTx, tx: &BitcoinTx) -> bool
+where
+    T: AsRef<Snapshot>,
+{
+    let ancestor_height = anchored_height(ancestor);
+    let mut tx = tx.clone();
+    loop {
+        if tx.id() == ancestor.id() {
+            return true;
+        }
+        let prev_txid = match TxKind::from(tx) {
+            TxKind::Anchoring(tx) => {
+                // The ancestors anchor the lower heights.
+                if tx.payload().block_height < ancestor_height {
+                    return false;
+                }
+                tx.payload().prev_tx_chain.unwrap_or_else(|| tx.prev_hash())
+            }
+            TxKind::FundingTx(_) |
+            TxKind::Other(_) => return false,
+        };
+        tx = match schema.known_txs().get(&prev_txid) {
+            Some(tx) => tx,
+            None => return false,
+        };
+    }
+}
+
+fn anchored_height(tx: &BitcoinTx) -> Height {
+    match TxKind::from(tx.clone()) {
+        TxKind::Anchoring(tx) => tx.payload().block_height,
+        TxKind::FundingTx(_) |
+        TxKind::Other(_) => Height::zero(),
+    }
+}
@@ -20,6 +20,12 @@ use service::ANCHORING_SERVICE_ID;
 
 pub const ANCHORING_MESSAGE_SIGNATURE: u16 = 0;
 pub const ANCHORING_MESSAGE_LATEST: u16 = 1;
+pub const ANCHORING_MESSAGE_FUNDING: u16 = 2;
+pub const ANCHORING_MESSAGE_SWEEP: u16 = 3;
+pub const ANCHORING_MESSAGE_KEY_ROTATION: u16 = 4;
+pub const ANCHORING_MESSAGE_PAUSE: u16 = 5;
+pub const ANCHORING_MESSAGE_TRIGGER: u16 = 6;
+pub const ANCHORING_MESSAGE_FEE_RATE: u16 = 7;
 
 message! {
     /// Exonum message with the signature for the given input of the anchoring transaction.
@@ -59,6 +65,118 @@ message! {
     }
 }
 
+message! {
+    /// Exonum message with the funding transaction to the anchoring address noticed
+    /// by the validator, it is used even if the bitcoin backend has not discovered it.
+    struct MsgAnchoringFunding {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_FUNDING;
+        const SIZE = 42;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Funding transaction content.
+        field tx:             BitcoinTx    [34 => 42]
+    }
+}
+
+message! {
+    /// Exonum message with the vote of the validator to sweep all anchoring funds
+    /// to the refund address. The empty address revokes the vote.
+    struct MsgAnchoringSweep {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_SWEEP;
+        const SIZE = 42;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Refund address in the `base58check` format.
+        field address:        &str         [34 => 42]
+    }
+}
+
+message! {
+    /// Exonum message with the vote of the validator to replace the anchoring key of
+    /// the `target` validator in the actual configuration. The empty key revokes the vote.
+    struct MsgAnchoringKeyRotation {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_KEY_ROTATION;
+        const SIZE = 76;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Index of the rotated key in anchoring public keys list.
+        field target:         ValidatorId  [34 => 36]
+        /// Hash of the actual configuration, the vote is ignored once it changes.
+        field cfg_hash:       &Hash        [36 => 68]
+        /// New anchoring public key in hex.
+        field key:            &str         [68 => 76]
+    }
+}
+
+message! {
+    /// Exonum message with the vote of the validator to pause the anchoring, for example,
+    /// during the bitcoin fee spikes or the wallet maintenance. The empty reason revokes
+    /// the vote.
+    struct MsgAnchoringPause {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_PAUSE;
+        const SIZE = 50;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Reason of the pause.
+        field reason:         &str         [34 => 42]
+        /// Height of the block at which the vote is sent.
+        field height:         u64          [42 => 50]
+    }
+}
+
+message! {
+    /// Exonum message with the request of the validator to anchor the block in which
+    /// the message is committed regardless of the anchoring frequency.
+    struct MsgAnchoringTrigger {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_TRIGGER;
+        const SIZE = 42;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Height of the block at which the request is sent.
+        field height:         u64          [34 => 42]
+    }
+}
+
+message! {
+    /// Exonum message with the fee rate of the anchoring transactions estimated
+    /// by the bitcoin backend of the validator. The anchoring proposals use the median
+    /// of the estimations of the validators, so all of them build the same proposal.
+    struct MsgAnchoringFeeRate {
+        const TYPE = ANCHORING_SERVICE_ID;
+        const ID = ANCHORING_MESSAGE_FEE_RATE;
+        const SIZE = 50;
+
+        /// Public key of validator.
+        field from:           &PublicKey   [00 => 32]
+        /// Public key index in anchoring public keys list.
+        field validator:      ValidatorId  [32 => 34]
+        /// Estimated fee rate in satoshis per byte.
+        field fee_rate:       u64          [34 => 42]
+        /// Height of the block at which the fee rate is estimated.
+        field height:         u64          [42 => 50]
+    }
+}
+
 encoding_struct! {
     /// Lect content
     struct LectContent {
@@ -70,3 +188,86 @@ encoding_struct! {
         field tx:             BitcoinTx   [32 => 40]
     }
 }
+
+encoding_struct! {
+    /// Entry of the log of the bitcoin transactions observed by the anchoring service.
+    struct TxLogEntry {
+        const SIZE = 16;
+
+        /// Height of the Exonum block in which the transaction is observed first.
+        field height:         u64         [00 => 08]
+        /// Bitcoin transaction content.
+        field tx:             BitcoinTx   [08 => 16]
+    }
+}
+
+encoding_struct! {
+    /// Resolution of the disagreement between the validators lects.
+    struct LectResolution {
+        const SIZE = 32;
+
+        /// Height of the Exonum block in which the disagreement is detected.
+        field height:         u64             [00 => 08]
+        /// Distinct lects of the validators in the order of the anchoring keys.
+        field lects:          Vec<BitcoinTx>  [08 => 16]
+        /// Lect which continues the lects of the most validators.
+        field canonical_lect: BitcoinTx       [16 => 24]
+        /// Number of the validators whose lects are the canonical lect or its ancestors.
+        field support:        u64             [24 => 32]
+    }
+}
+
+encoding_struct! {
+    /// Evidence of the validator misbehavior, it is intended for the governance decisions
+    /// of the surrounding application.
+    struct MisbehaviorEvidence {
+        const SIZE = 75;
+
+        /// Height of the Exonum block in which the misbehavior is detected.
+        field height:         u64          [00 => 08]
+        /// Identifier of the misbehaving validator.
+        field validator:      ValidatorId  [08 => 10]
+        /// Kind of the misbehavior, see [`MisbehaviorKind`](enum.MisbehaviorKind.html).
+        field kind:           u8           [10 => 11]
+        /// Hash of the `Exonum` message of the validator which proves the misbehavior.
+        field msg_hash:       &Hash        [11 => 43]
+        /// Hash of the earlier message of the validator which conflicts with the given one,
+        /// it is zero for other kinds of the misbehavior.
+        field conflicting_msg_hash: &Hash  [43 => 75]
+    }
+}
+
+/// Kind of the validator misbehavior.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MisbehaviorKind {
+    /// Signature of the input spending the anchoring address which is not valid
+    /// for the anchoring key of the validator.
+    InvalidSignature,
+    /// Signature of the anchoring proposal which spends the same lect and anchors
+    /// the same block as another proposal signed by the validator but differs from it.
+    ConflictingProposal,
+    /// Lect whose payload does not match the anchored `Exonum` block.
+    InvalidLect,
+}
+
+impl MisbehaviorKind {
+    /// Returns the kind by its code in the `MisbehaviorEvidence`.
+    pub fn from_code(code: u8) -> Option<MisbehaviorKind> {
+        match code {
+            0 => Some(MisbehaviorKind::InvalidSignature),
+            1 => Some(MisbehaviorKind::ConflictingProposal),
+            2 => Some(MisbehaviorKind::InvalidLect),
+            _ => None,
+        }
+    }
+
+    /// Returns the code of the kind.
+    pub fn code(self) -> u8 {
+        match self {
+            MisbehaviorKind::InvalidSignature => 0,
+            MisbehaviorKind::ConflictingProposal => 1,
+            MisbehaviorKind::InvalidLect => 2,
+        }
+    }
+}
@@ -22,8 +22,15 @@ pub mod dto;
 pub mod transactions;
 #[doc(hidden)]
 pub mod consensus_storage;
+#[doc(hidden)]
+pub mod disagreement;
+pub mod verification;
 #[cfg(test)]
 mod tests;
 
 pub use self::schema::{AnchoringSchema, KnownSignatureId};
-pub use self::dto::{LectContent, MsgAnchoringSignature, MsgAnchoringUpdateLatest};
+pub use self::dto::{LectContent, LectResolution, MsgAnchoringSignature,
+                    MsgAnchoringUpdateLatest};
+pub use self::verification::{verify_anchoring_chain, verify_anchoring_chain_by_relay,
+                             verify_anchoring_txs, AnchoringChainIssue, AnchoringChainReport,
+                             VerifiedAnchor};
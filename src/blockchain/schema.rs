@@ -12,20 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::collections::hash_map::{Entry, HashMap};
+use std::time::SystemTime;
 
 use byteorder::{BigEndian, ByteOrder};
-use serde_json::value::from_value;
+use serde_json::value::{from_value, to_value};
 
 use exonum::blockchain::{gen_prefix, Schema, StoredConfiguration};
-use exonum::storage::{Fork, ListIndex, MapIndex, ProofListIndex, Snapshot, StorageKey};
-use exonum::crypto::Hash;
+use exonum::storage::{Fork, ListIndex, MapIndex, ProofListIndex, ProofMapIndex, Snapshot,
+                      StorageKey};
+use exonum::crypto::{self, hash, Hash};
+use exonum::encoding::serialize::FromHex;
 use exonum::helpers::{Height, ValidatorId};
+use exonum::messages::Message;
 
 use blockchain::consensus_storage::AnchoringConfig;
-use blockchain::dto::{LectContent, MsgAnchoringSignature};
+use blockchain::dto::{LectContent, LectResolution, MisbehaviorEvidence, MsgAnchoringFeeRate,
+                      MsgAnchoringKeyRotation, MsgAnchoringPause, MsgAnchoringSignature,
+                      MsgAnchoringSweep, TxLogEntry};
 use details::btc;
-use details::btc::transactions::{AnchoringTx, BitcoinTx};
+use details::btc::transactions::{AnchoringTx, BitcoinTx, FundingTx, RawBitcoinTx, TxKind};
 use service::ANCHORING_SERVICE_NAME;
 
 /// Unique identifier of signature for the `AnchoringTx`.
@@ -62,6 +69,16 @@ impl StorageKey for KnownSignatureId {
     }
 }
 
+impl KnownSignatureId {
+    /// Returns the hash of the identifier that is used as a key in the
+    /// [`known_signatures`](struct.AnchoringSchema.html#method.known_signatures) index.
+    pub fn hash(&self) -> Hash {
+        let mut buffer = vec![0; self.size()];
+        self.write(&mut buffer);
+        hash(&buffer)
+    }
+}
+
 impl<'a> From<&'a MsgAnchoringSignature> for KnownSignatureId {
     fn from(msg: &'a MsgAnchoringSignature) -> KnownSignatureId {
         KnownSignatureId {
@@ -72,6 +89,26 @@ impl<'a> From<&'a MsgAnchoringSignature> for KnownSignatureId {
     }
 }
 
+/// Identifier of the output of the bitcoin transaction spent by the anchoring chain.
+#[derive(Debug)]
+pub struct OutpointId {
+    /// Txid of the spent transaction.
+    pub txid: btc::TxId,
+    /// Index of the spent output.
+    pub vout: u32,
+}
+
+impl OutpointId {
+    /// Returns the hash of the identifier that is used as a key in the
+    /// [`outpoint_spends`](struct.AnchoringSchema.html#method.outpoint_spends) index.
+    pub fn hash(&self) -> Hash {
+        let mut buffer = vec![0; 36];
+        buffer[0..32].copy_from_slice(self.txid.as_bytes());
+        BigEndian::write_u32(&mut buffer[32..36], self.vout);
+        hash(&buffer)
+    }
+}
+
 /// Anchoring information schema.
 #[derive(Debug)]
 pub struct AnchoringSchema<T> {
@@ -108,16 +145,389 @@ where
         )
     }
 
-    /// Returns the table of known signatures, where key is the tuple `(txid, validator_id, input)`.
+    /// Returns the table of known signatures, where key is the hash of the tuple
+    /// `(txid, validator_id, input)`.
     ///
     /// [Read more](struct.KnownSignatureId.html).
-    pub fn known_signatures(&self) -> MapIndex<&T, KnownSignatureId, MsgAnchoringSignature> {
-        MapIndex::new("btc_anchoring.known_signatures", &self.view)
+    pub fn known_signatures(&self) -> ProofMapIndex<&T, Hash, MsgAnchoringSignature> {
+        ProofMapIndex::new("btc_anchoring.known_signatures", &self.view)
+    }
+
+    /// Returns the table of the resolutions of the disagreement between the validators lects.
+    ///
+    /// [Read more](../disagreement/index.html).
+    pub fn lect_resolutions(&self) -> ProofListIndex<&T, LectResolution> {
+        ProofListIndex::new("btc_anchoring.lect_resolutions", &self.view)
     }
 
     /// Returns the table that keeps the anchoring transaction for any known txid.
-    pub fn known_txs(&self) -> MapIndex<&T, btc::TxId, BitcoinTx> {
-        MapIndex::new("btc_anchoring.known_txs", &self.view)
+    pub fn known_txs(&self) -> ProofMapIndex<&T, btc::TxId, BitcoinTx> {
+        ProofMapIndex::new("btc_anchoring.known_txs", &self.view)
+    }
+
+    /// Returns the table of the funding transactions noticed by the validators which
+    /// are not spent by the anchoring chain yet.
+    pub fn funding_notices(&self) -> ProofMapIndex<&T, btc::TxId, BitcoinTx> {
+        ProofMapIndex::new("btc_anchoring.funding_notices", &self.view)
+    }
+
+    /// Returns the table of the heights of the blocks in which the unspent funding
+    /// transactions noticed by the validators are recorded.
+    pub fn funding_notice_heights(&self) -> MapIndex<&T, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.funding_notice_heights", &self.view)
+    }
+
+    /// Returns the table of the funding transactions spent by the lects, where value
+    /// is the txid of the spending lect.
+    pub fn spent_funding(&self) -> MapIndex<&T, btc::TxId, btc::TxId> {
+        MapIndex::new("btc_anchoring.spent_funding", &self.view)
+    }
+
+    /// Returns the table of the numbers of the known transactions spending the outpoint,
+    /// where key is the hash of the [`OutpointId`](struct.OutpointId.html).
+    pub fn outpoint_spends(&self) -> MapIndex<&T, Hash, u64> {
+        MapIndex::new("btc_anchoring.outpoint_spends", &self.view)
+    }
+
+    /// Returns the number of the fee bumps of the anchoring transaction, that is the number
+    /// of the other known transactions spending the same outpoint by the first input.
+    /// The number is taken from the blockchain state, so it survives the node restart
+    /// and is the same for all validators.
+    pub fn fee_bumps(&self, tx: &AnchoringTx) -> u64 {
+        let outpoint = OutpointId {
+            txid: tx.prev_hash(),
+            vout: tx.input[0].prev_index,
+        };
+        let spends = self.outpoint_spends().get(&outpoint.hash()).unwrap_or(0);
+        if self.known_txs().contains(&tx.id()) {
+            spends.saturating_sub(1)
+        } else {
+            spends
+        }
+    }
+
+    /// Returns the table of the validators votes to sweep the anchoring funds, where key
+    /// is the hash of the validator service key.
+    pub fn sweep_votes(&self) -> ProofMapIndex<&T, Hash, MsgAnchoringSweep> {
+        ProofMapIndex::new("btc_anchoring.sweep_votes", &self.view)
+    }
+
+    /// Returns the refund address to which the majority of the validators of the actual
+    /// configuration have voted to sweep the anchoring funds.
+    pub fn sweep_address(&self) -> Option<btc::Address> {
+        let cfg = self.actual_anchoring_config();
+        let validators = Schema::new(&self.view).actual_configuration().validator_keys;
+        let sweep_votes = self.sweep_votes();
+        let mut votes = HashMap::new();
+        for (id, keys) in validators.iter().enumerate() {
+            let vote = match sweep_votes.get(&hash(keys.service_key.as_ref())) {
+                Some(vote) => vote,
+                None => continue,
+            };
+            if vote.validator().0 as usize == id && !vote.address().is_empty() {
+                *votes.entry(vote.address().to_string()).or_insert(0) += 1;
+            }
+        }
+        votes
+            .into_iter()
+            .find(|&(_, count)| count >= cfg.majority_count())
+            .and_then(|(address, _)| address.parse().ok())
+    }
+
+    /// Returns the table of the validators votes to pause the anchoring, where key
+    /// is the hash of the validator service key.
+    pub fn pause_votes(&self) -> ProofMapIndex<&T, Hash, MsgAnchoringPause> {
+        ProofMapIndex::new("btc_anchoring.pause_votes", &self.view)
+    }
+
+    /// Returns true if the majority of the validators of the actual configuration
+    /// have voted to pause the anchoring.
+    pub fn is_paused(&self) -> bool {
+        let cfg = self.actual_anchoring_config();
+        let validators = Schema::new(&self.view).actual_configuration().validator_keys;
+        let pause_votes = self.pause_votes();
+        let votes = validators
+            .iter()
+            .enumerate()
+            .filter(|&(id, keys)| match pause_votes.get(&hash(keys.service_key.as_ref())) {
+                Some(vote) => vote.validator().0 as usize == id && !vote.reason().is_empty(),
+                None => false,
+            })
+            .count();
+        votes >= cfg.majority_count() as usize
+    }
+
+    /// Returns the table of the fee rates estimated by the validators, where key
+    /// is the hash of the validator service key.
+    pub fn fee_rate_votes(&self) -> ProofMapIndex<&T, Hash, MsgAnchoringFeeRate> {
+        ProofMapIndex::new("btc_anchoring.fee_rate_votes", &self.view)
+    }
+
+    /// Returns the median of the fee rates estimated by the validators of the actual
+    /// configuration, or `None` if less than the majority of them have sent the estimations.
+    /// The rate is taken from the blockchain state, so all validators build the anchoring
+    /// proposals with the same fee.
+    pub fn consensus_fee_rate(&self) -> Option<u64> {
+        let cfg = self.actual_anchoring_config();
+        let validators = Schema::new(&self.view).actual_configuration().validator_keys;
+        let fee_rate_votes = self.fee_rate_votes();
+        let mut fee_rates = validators
+            .iter()
+            .enumerate()
+            .filter_map(|(id, keys)| {
+                fee_rate_votes.get(&hash(keys.service_key.as_ref())).and_then(
+                    |vote| if vote.validator().0 as usize == id {
+                        Some(vote.fee_rate())
+                    } else {
+                        None
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        if fee_rates.len() < cfg.majority_count() as usize {
+            return None;
+        }
+        fee_rates.sort();
+        Some(fee_rates[(fee_rates.len() - 1) / 2])
+    }
+
+    /// Returns the list of the heights of the blocks whose anchoring has been triggered
+    /// by the [`MsgAnchoringTrigger`](../dto/struct.MsgAnchoringTrigger.html) messages.
+    pub fn anchoring_triggers(&self) -> ProofListIndex<&T, u64> {
+        ProofListIndex::new("btc_anchoring.anchoring_triggers", &self.view)
+    }
+
+    /// Returns the height of the latest block whose anchoring has been triggered.
+    pub fn latest_anchoring_trigger(&self) -> Option<Height> {
+        self.anchoring_triggers().last().map(Height)
+    }
+
+    /// Returns the list of the application commitments registered by the companion services
+    /// with the [`add_commitment`][1] method in the order of their registration.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.add_commitment
+    pub fn commitments(&self) -> ProofListIndex<&T, Hash> {
+        ProofListIndex::new("btc_anchoring.commitments", &self.view)
+    }
+
+    /// Returns the heights of the blocks in which the [`commitments`][1] have been registered,
+    /// the list is aligned with the list of the commitments.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.commitments
+    pub fn commitment_heights(&self) -> ProofListIndex<&T, u64> {
+        ProofListIndex::new("btc_anchoring.commitment_heights", &self.view)
+    }
+
+    /// Returns the commitments registered in the blocks after the `from` height up to
+    /// the `to` height inclusive. They are anchored by the anchoring transaction of
+    /// the `to` height which follows the one of the `from` height.
+    pub fn commitments_between(&self, from: Option<Height>, to: Height) -> Vec<Hash> {
+        let heights = self.commitment_heights();
+        // Index of the first commitment registered after the given height.
+        let position = |height: Height| {
+            let (mut lo, mut hi) = (0, heights.len());
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if heights.get(mid).unwrap() <= height.0 {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            lo
+        };
+        let start = from.map_or(0, &position);
+        let end = position(to);
+        let commitments = self.commitments();
+        (start..end).filter_map(|index| commitments.get(index)).collect()
+    }
+
+    /// Returns the commitments anchored by the transaction which spends the `prev_tx`
+    /// of the anchoring chain and anchors the block at the given `height`, that is the ones
+    /// registered after the block anchored by the `prev_tx`. The recovery payload has
+    /// no room for the commitments, so the transaction which spends it as well as the first
+    /// transaction of a new chain anchors the commitments since the genesis block.
+    pub fn anchored_commitments(
+        &self,
+        prev_tx: Option<&AnchoringTx>,
+        height: Height,
+    ) -> Vec<Hash> {
+        let from = match prev_tx.map(|tx| tx.payload()) {
+            Some(ref payload) if payload.prev_tx_chain.is_none() => Some(payload.block_height),
+            _ => None,
+        };
+        self.commitments_between(from, height)
+    }
+
+    /// Returns the known anchoring transaction spent by one of the inputs of `tx`.
+    pub fn prev_anchoring_tx(&self, tx: &AnchoringTx) -> Option<AnchoringTx> {
+        tx.inputs()
+            .filter_map(|input| self.spent_tx(tx, input))
+            .filter_map(|prev_tx| match TxKind::from(prev_tx) {
+                TxKind::Anchoring(prev_tx) => Some(prev_tx),
+                _ => None,
+            })
+            .next()
+    }
+
+    /// Returns the table of the transfer transactions agreed by the validators, where key
+    /// is the hash of the configuration to whose anchoring address the funds are transferred.
+    pub fn config_transfers(&self) -> ProofMapIndex<&T, Hash, AnchoringTx> {
+        ProofMapIndex::new("btc_anchoring.config_transfers", &self.view)
+    }
+
+    /// Returns the transfer transaction which waits for enough confirmations, so the
+    /// anchoring chain is not continued on the new anchoring address yet.
+    ///
+    /// The transfer is pending until the following configuration becomes actual and
+    /// the agreed lect of its validators moves beyond the transfer transaction.
+    pub fn pending_transfer(&self) -> Option<AnchoringTx> {
+        if let Some(following_hash) = self.following_config_hash() {
+            return self.config_transfers().get(&following_hash);
+        }
+        let transfer = match self.config_transfers().get(&self.actual_config_hash()) {
+            Some(transfer) => transfer,
+            None => return None,
+        };
+        let cfg = self.actual_anchoring_config();
+        match self.collect_lects(&cfg) {
+            Some(lect) if lect.id() == transfer.id() => Some(transfer),
+            _ => None,
+        }
+    }
+
+    /// Returns the table of the validators votes to rotate the anchoring key of one of
+    /// the validators, where key is the hash of the validator service key.
+    pub fn key_rotation_votes(&self) -> ProofMapIndex<&T, Hash, MsgAnchoringKeyRotation> {
+        ProofMapIndex::new("btc_anchoring.key_rotation_votes", &self.view)
+    }
+
+    /// Returns the table that maps the hashes of the approved key rotation votes of
+    /// the target validators to the heights of their approval.
+    pub fn key_rotation_heights(&self) -> MapIndex<&T, Hash, u64> {
+        MapIndex::new("btc_anchoring.key_rotation_heights", &self.view)
+    }
+
+    /// Returns the vote of the target validator for the rotation of its own anchoring key
+    /// which is supported by the majority of the validators of the actual configuration.
+    pub fn approved_key_rotation(&self) -> Option<MsgAnchoringKeyRotation> {
+        let cfg = self.actual_anchoring_config();
+        let actual = Schema::new(&self.view).actual_configuration();
+        let cfg_hash = self.actual_config_hash();
+        let rotation_votes = self.key_rotation_votes();
+        let mut votes = HashMap::new();
+        let mut proposals = Vec::new();
+        for (id, keys) in actual.validator_keys.iter().enumerate() {
+            let vote = match rotation_votes.get(&hash(keys.service_key.as_ref())) {
+                Some(vote) => vote,
+                None => continue,
+            };
+            if vote.validator().0 as usize != id || *vote.cfg_hash() != cfg_hash ||
+                vote.key().is_empty()
+            {
+                continue;
+            }
+            *votes.entry((vote.target(), vote.key().to_string())).or_insert(0) += 1;
+            // Only the target validator can propose the rotation of its key.
+            if vote.target() == vote.validator() {
+                proposals.push(vote);
+            }
+        }
+        proposals.into_iter().find(|vote| {
+            let count = votes[&(vote.target(), vote.key().to_string())];
+            count >= cfg.majority_count()
+        })
+    }
+
+    /// Returns the configuration proposal with the anchoring key replaced by the approved
+    /// key rotation. The proposal is the same for all validators, it activates
+    /// `key_rotation_delay` blocks after the approval and transfers the anchoring chain
+    /// to the new address as any other change of the anchoring keys.
+    ///
+    /// Returns `None` if there is no approved rotation or another configuration
+    /// is already scheduled.
+    pub fn key_rotation_proposal(&self) -> Option<StoredConfiguration> {
+        let schema = Schema::new(&self.view);
+        if schema.following_configuration().is_some() {
+            return None;
+        }
+        let rotation = match self.approved_key_rotation() {
+            Some(rotation) => rotation,
+            None => return None,
+        };
+        let height = match self.key_rotation_heights().get(&rotation.hash()) {
+            Some(height) => height,
+            None => return None,
+        };
+
+        let actual = schema.actual_configuration();
+        let mut anchoring_cfg = self.parse_config(&actual);
+        let key = btc::PublicKey::from_hex(rotation.key()).expect(
+            "Approved key rotation has an incorrect key",
+        );
+        anchoring_cfg.anchoring_keys[rotation.target().0 as usize] = key;
+
+        let mut proposal = actual.clone();
+        proposal.previous_cfg_hash = self.actual_config_hash();
+        proposal.actual_from = Height(height + anchoring_cfg.key_rotation_delay);
+        proposal.services.insert(
+            ANCHORING_SERVICE_NAME.to_string(),
+            to_value(&anchoring_cfg).unwrap(),
+        );
+        Some(proposal)
+    }
+
+    /// Returns the configuration `proposal` with the anchoring keys updated for its set
+    /// of validators. The anchoring keys of the validators of the actual configuration which
+    /// remain in the set are reused, so only the keys of the added validators are specified.
+    ///
+    /// Returns the service key of the added validator without the anchoring key as an error.
+    pub fn validators_change_proposal(
+        &self,
+        proposal: &StoredConfiguration,
+        added_keys: &BTreeMap<crypto::PublicKey, btc::PublicKey>,
+    ) -> Result<StoredConfiguration, crypto::PublicKey> {
+        let actual = Schema::new(&self.view).actual_configuration();
+        let following_cfg = self.parse_config(&actual).with_validators(
+            &actual.validator_keys,
+            &proposal.validator_keys,
+            added_keys,
+        )?;
+        let mut anchoring_cfg = self.parse_config(proposal);
+        anchoring_cfg.anchoring_keys = following_cfg.anchoring_keys;
+
+        let mut proposal = proposal.clone();
+        proposal.services.insert(
+            ANCHORING_SERVICE_NAME.to_string(),
+            to_value(&anchoring_cfg).unwrap(),
+        );
+        Ok(proposal)
+    }
+
+    /// Returns the list of the evidences of the validators misbehavior.
+    pub fn misbehavior_evidence(&self) -> ProofListIndex<&T, MisbehaviorEvidence> {
+        ProofListIndex::new("btc_anchoring.misbehavior_evidence", &self.view)
+    }
+
+    /// Returns the table of the first signatures of the validators for the anchoring rounds,
+    /// where key is the hash of the validator identifier, the lect spent by the proposal,
+    /// the anchored height and the proposal amount. It is used to detect the signatures of
+    /// conflicting proposals.
+    pub fn signed_proposals(&self) -> MapIndex<&T, Hash, MsgAnchoringSignature> {
+        MapIndex::new("btc_anchoring.signed_proposals", &self.view)
+    }
+
+    /// Returns table that maps the normalized txids of the signed proposals to the heights
+    /// of the blocks anchored by them, it is used to prune the signatures of obsolete rounds.
+    pub fn signature_rounds(&self) -> MapIndex<&T, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.signature_rounds", &self.view)
+    }
+
+    /// Returns the table of the hashes of the pruned signature messages, where key is
+    /// the hash of the [`KnownSignatureId`](struct.KnownSignatureId.html). It is filled
+    /// only if the `archive` flag of the `signatures_pruning` policy is set.
+    pub fn archived_signatures(&self) -> ProofMapIndex<&T, Hash, Hash> {
+        ProofMapIndex::new("btc_anchoring.archived_signatures", &self.view)
     }
 
     /// Returns table that maps anchoring transactions to their heights.
@@ -125,6 +535,89 @@ where
         MapIndex::new("btc_anchoring.tx_chain", &self.view)
     }
 
+    /// Returns the first anchoring transaction from the [`anchoring_tx_chain`][1] whose
+    /// anchored height is greater or equal than the given `height` together with
+    /// the anchored height.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn anchoring_tx_covering(&self, height: u64) -> Option<(u64, AnchoringTx)> {
+        self.anchoring_tx_chain().iter_from(&height).next()
+    }
+
+    /// Returns table that maps the `txid`s of the transactions from the
+    /// [`anchoring_tx_chain`][1] to their anchored heights.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn anchoring_tx_heights(&self) -> MapIndex<&T, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.tx_chain_heights", &self.view)
+    }
+
+    /// Returns table that keeps the fees in satoshis of the transactions from the
+    /// [`anchoring_tx_chain`][1] by their anchored heights, the transactions with
+    /// the unknown inputs are skipped.
+    ///
+    /// Like the `anchoring_tx_chain`, it is filled by the observer of each node
+    /// and is not a part of the anchoring state.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn anchoring_fees(&self) -> MapIndex<&T, u64, u64> {
+        MapIndex::new("btc_anchoring.fees", &self.view)
+    }
+
+    /// Returns the anchoring transaction with the given `txid` from the
+    /// [`anchoring_tx_chain`][1] together with the anchored height.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn anchoring_tx_by_txid(&self, txid: &btc::TxId) -> Option<(u64, AnchoringTx)> {
+        let height = match self.anchoring_tx_heights().get(txid) {
+            Some(height) => height,
+            None => return None,
+        };
+        self.anchoring_tx_chain().get(&height).map(
+            |tx| (height, tx),
+        )
+    }
+
+    /// Returns table that keeps the anchoring latency in seconds for the anchored heights,
+    /// that is the time from the commit of the anchored block until the anchoring chain
+    /// observer of this node has found the anchoring transaction confirmed.
+    ///
+    /// It is not a part of the anchoring state.
+    pub fn anchoring_latencies(&self) -> MapIndex<&T, u64, u64> {
+        MapIndex::new("btc_anchoring.latencies", &self.view)
+    }
+
+    /// Returns the commit time of the block with the given height, that is the time
+    /// of its latest precommit.
+    pub fn block_commit_time(&self, height: Height) -> Option<SystemTime> {
+        let schema = Schema::new(&self.view);
+        match schema.block_hash_by_height(height) {
+            Some(block_hash) => {
+                schema
+                    .precommits(&block_hash)
+                    .iter()
+                    .map(|precommit| precommit.time())
+                    .max()
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the log of all bitcoin transactions observed by the anchoring service
+    /// in the order of their appearance in the lects.
+    pub fn tx_log(&self) -> ProofListIndex<&T, TxLogEntry> {
+        ProofListIndex::new("btc_anchoring.tx_log", &self.view)
+    }
+
+    /// Returns table that keeps the unix time when the anchoring chain observer has found
+    /// the anchoring transaction with the given txid confirmed.
+    ///
+    /// Like the `anchoring_tx_chain`, it is filled by the observer of each node
+    /// and is not a part of the anchoring state.
+    pub fn confirmation_times(&self) -> MapIndex<&T, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.confirmation_times", &self.view)
+    }
+
     /// Returns the actual anchoring configuration.
     pub fn actual_anchoring_config(&self) -> AnchoringConfig {
         let schema = Schema::new(&self.view);
@@ -132,6 +625,19 @@ where
         self.parse_config(&actual)
     }
 
+    /// Returns the hash of the actual configuration.
+    pub fn actual_config_hash(&self) -> Hash {
+        let actual = Schema::new(&self.view).actual_configuration();
+        ::exonum::storage::StorageValue::hash(&actual)
+    }
+
+    /// Returns the hash of the following configuration if it exists.
+    pub fn following_config_hash(&self) -> Option<Hash> {
+        Schema::new(&self.view).following_configuration().map(|following| {
+            ::exonum::storage::StorageValue::hash(&following)
+        })
+    }
+
     /// Returns the nearest following configuration if it exists.
     pub fn following_anchoring_config(&self) -> Option<AnchoringConfig> {
         let schema = Schema::new(&self.view);
@@ -142,6 +648,13 @@ where
         }
     }
 
+    /// Returns the activation height of the following configuration if it exists.
+    pub fn following_config_height(&self) -> Option<Height> {
+        Schema::new(&self.view).following_configuration().map(|following| {
+            following.actual_from
+        })
+    }
+
     /// Returns the previous anchoring configuration if it exists.
     pub fn previous_anchoring_config(&self) -> Option<AnchoringConfig> {
         let schema = Schema::new(&self.view);
@@ -219,16 +732,69 @@ where
         self.lect_indexes(anchoring_key).get(txid)
     }
 
+    /// Returns the transaction spent by the given `input` of the transaction `tx`
+    /// if it is known.
+    pub fn spent_tx(&self, tx: &AnchoringTx, input: u32) -> Option<RawBitcoinTx> {
+        let prev_txid = btc::TxId::from(tx.input[input as usize].prev_hash);
+        if let Some(prev_tx) = self.known_txs().get(&prev_txid) {
+            return Some(prev_tx.0);
+        }
+        match self.actual_anchoring_config().funding_tx {
+            Some(ref funding_tx) if funding_tx.id() == prev_txid => Some(funding_tx.0.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the output spent by the given `input` of the transaction `tx`
+    /// if the spent transaction is known.
+    pub fn spent_output_value(&self, tx: &AnchoringTx, input: u32) -> Option<u64> {
+        let prev_index = tx.input[input as usize].prev_index;
+        self.spent_tx(tx, input).and_then(|prev_tx| {
+            prev_tx.output.get(prev_index as usize).map(|out| out.value)
+        })
+    }
+
+    /// Returns the fee of the anchoring transaction if all its inputs are known.
+    pub fn anchoring_tx_fee(&self, tx: &AnchoringTx) -> Option<u64> {
+        let mut spent_value = 0;
+        for input in 0..tx.input.len() as u32 {
+            match self.spent_output_value(tx, input) {
+                Some(value) => spent_value += value,
+                None => return None,
+            }
+        }
+        Some(spent_value.saturating_sub(tx.total_amount()))
+    }
+
     /// Returns the `state_hash` for anchoring tables.
     ///
-    /// It contains a list of `root_hash` of the actual `lects` tables.
+    /// It contains a list of `root_hash` of the actual `lects` tables followed by
+    /// the `root_hash` of the `known_txs`, `known_signatures`, `lect_resolutions`,
+    /// `tx_log`, `funding_notices`, `sweep_votes`, `archived_signatures`, `key_rotation_votes`,
+    /// `misbehavior_evidence`, `pause_votes`, `anchoring_triggers`, `commitments`,
+    /// `commitment_heights`, `config_transfers` and `fee_rate_votes` tables.
     pub fn state_hash(&self) -> Vec<Hash> {
         let cfg = self.actual_anchoring_config();
-        let mut lect_hashes = Vec::new();
+        let mut hashes = Vec::new();
         for key in &cfg.anchoring_keys {
-            lect_hashes.push(self.lects(key).root_hash());
+            hashes.push(self.lects(key).root_hash());
         }
-        lect_hashes
+        hashes.push(self.known_txs().root_hash());
+        hashes.push(self.known_signatures().root_hash());
+        hashes.push(self.lect_resolutions().root_hash());
+        hashes.push(self.tx_log().root_hash());
+        hashes.push(self.funding_notices().root_hash());
+        hashes.push(self.sweep_votes().root_hash());
+        hashes.push(self.archived_signatures().root_hash());
+        hashes.push(self.key_rotation_votes().root_hash());
+        hashes.push(self.misbehavior_evidence().root_hash());
+        hashes.push(self.pause_votes().root_hash());
+        hashes.push(self.anchoring_triggers().root_hash());
+        hashes.push(self.commitments().root_hash());
+        hashes.push(self.commitment_heights().root_hash());
+        hashes.push(self.config_transfers().root_hash());
+        hashes.push(self.fee_rate_votes().root_hash());
+        hashes
     }
 
     fn parse_config(&self, cfg: &StoredConfiguration) -> AnchoringConfig {
@@ -281,15 +847,143 @@ impl<'a> AnchoringSchema<&'a mut Fork> {
     /// [1]: struct.AnchoringSchema.html#method.known_signatures
     pub fn known_signatures_mut(
         &mut self,
-    ) -> MapIndex<&mut Fork, KnownSignatureId, MsgAnchoringSignature> {
-        MapIndex::new("btc_anchoring.known_signatures", &mut self.view)
+    ) -> ProofMapIndex<&mut Fork, Hash, MsgAnchoringSignature> {
+        ProofMapIndex::new("btc_anchoring.known_signatures", &mut self.view)
+    }
+
+    /// Mutable variant of the [`lect_resolutions`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.lect_resolutions
+    pub fn lect_resolutions_mut(&mut self) -> ProofListIndex<&mut Fork, LectResolution> {
+        ProofListIndex::new("btc_anchoring.lect_resolutions", &mut self.view)
     }
 
     /// Mutable variant of the [`known_txs`][1] index.
     ///
     /// [1]: struct.AnchoringSchema.html#method.known_txs
-    pub fn known_txs_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, BitcoinTx> {
-        MapIndex::new("btc_anchoring.known_txs", &mut self.view)
+    pub fn known_txs_mut(&mut self) -> ProofMapIndex<&mut Fork, btc::TxId, BitcoinTx> {
+        ProofMapIndex::new("btc_anchoring.known_txs", &mut self.view)
+    }
+
+    /// Mutable variant of the [`funding_notices`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.funding_notices
+    pub fn funding_notices_mut(&mut self) -> ProofMapIndex<&mut Fork, btc::TxId, BitcoinTx> {
+        ProofMapIndex::new("btc_anchoring.funding_notices", &mut self.view)
+    }
+
+    /// Mutable variant of the [`funding_notice_heights`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.funding_notice_heights
+    pub fn funding_notice_heights_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.funding_notice_heights", &mut self.view)
+    }
+
+    /// Mutable variant of the [`spent_funding`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.spent_funding
+    pub fn spent_funding_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, btc::TxId> {
+        MapIndex::new("btc_anchoring.spent_funding", &mut self.view)
+    }
+
+    /// Mutable variant of the [`outpoint_spends`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.outpoint_spends
+    pub fn outpoint_spends_mut(&mut self) -> MapIndex<&mut Fork, Hash, u64> {
+        MapIndex::new("btc_anchoring.outpoint_spends", &mut self.view)
+    }
+
+    /// Mutable variant of the [`sweep_votes`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.sweep_votes
+    pub fn sweep_votes_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, MsgAnchoringSweep> {
+        ProofMapIndex::new("btc_anchoring.sweep_votes", &mut self.view)
+    }
+
+    /// Mutable variant of the [`misbehavior_evidence`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.misbehavior_evidence
+    pub fn misbehavior_evidence_mut(&mut self) -> ProofListIndex<&mut Fork, MisbehaviorEvidence> {
+        ProofListIndex::new("btc_anchoring.misbehavior_evidence", &mut self.view)
+    }
+
+    /// Mutable variant of the [`signed_proposals`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.signed_proposals
+    pub fn signed_proposals_mut(&mut self) -> MapIndex<&mut Fork, Hash, MsgAnchoringSignature> {
+        MapIndex::new("btc_anchoring.signed_proposals", &mut self.view)
+    }
+
+    /// Mutable variant of the [`pause_votes`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.pause_votes
+    pub fn pause_votes_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, MsgAnchoringPause> {
+        ProofMapIndex::new("btc_anchoring.pause_votes", &mut self.view)
+    }
+
+    /// Mutable variant of the [`fee_rate_votes`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.fee_rate_votes
+    pub fn fee_rate_votes_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, MsgAnchoringFeeRate> {
+        ProofMapIndex::new("btc_anchoring.fee_rate_votes", &mut self.view)
+    }
+
+    /// Mutable variant of the [`anchoring_triggers`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_triggers
+    pub fn anchoring_triggers_mut(&mut self) -> ProofListIndex<&mut Fork, u64> {
+        ProofListIndex::new("btc_anchoring.anchoring_triggers", &mut self.view)
+    }
+
+    /// Mutable variant of the [`commitments`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.commitments
+    pub fn commitments_mut(&mut self) -> ProofListIndex<&mut Fork, Hash> {
+        ProofListIndex::new("btc_anchoring.commitments", &mut self.view)
+    }
+
+    /// Mutable variant of the [`commitment_heights`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.commitment_heights
+    pub fn commitment_heights_mut(&mut self) -> ProofListIndex<&mut Fork, u64> {
+        ProofListIndex::new("btc_anchoring.commitment_heights", &mut self.view)
+    }
+
+    /// Mutable variant of the [`key_rotation_votes`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.key_rotation_votes
+    pub fn key_rotation_votes_mut(
+        &mut self,
+    ) -> ProofMapIndex<&mut Fork, Hash, MsgAnchoringKeyRotation> {
+        ProofMapIndex::new("btc_anchoring.key_rotation_votes", &mut self.view)
+    }
+
+    /// Mutable variant of the [`key_rotation_heights`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.key_rotation_heights
+    pub fn key_rotation_heights_mut(&mut self) -> MapIndex<&mut Fork, Hash, u64> {
+        MapIndex::new("btc_anchoring.key_rotation_heights", &mut self.view)
+    }
+
+    /// Mutable variant of the [`config_transfers`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.config_transfers
+    pub fn config_transfers_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, AnchoringTx> {
+        ProofMapIndex::new("btc_anchoring.config_transfers", &mut self.view)
+    }
+
+    /// Mutable variant of the [`signature_rounds`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.signature_rounds
+    pub fn signature_rounds_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.signature_rounds", &mut self.view)
+    }
+
+    /// Mutable variant of the [`archived_signatures`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.archived_signatures
+    pub fn archived_signatures_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Hash> {
+        ProofMapIndex::new("btc_anchoring.archived_signatures", &mut self.view)
     }
 
     /// Mutable variant of the [`signatures`][1] index.
@@ -299,14 +993,94 @@ impl<'a> AnchoringSchema<&'a mut Fork> {
         MapIndex::new("btc_anchoring.tx_chain", &mut self.view)
     }
 
+    /// Mutable variant of the [`anchoring_tx_heights`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_heights
+    pub fn anchoring_tx_heights_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.tx_chain_heights", &mut self.view)
+    }
+
+    /// Mutable variant of the [`anchoring_fees`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_fees
+    pub fn anchoring_fees_mut(&mut self) -> MapIndex<&mut Fork, u64, u64> {
+        MapIndex::new("btc_anchoring.fees", &mut self.view)
+    }
+
+    /// Puts the anchoring transaction of the given anchored `height` to the
+    /// [`anchoring_tx_chain`][1], indexes it by its `txid` and keeps its fee.
+    /// The index entry of the transaction replaced by the new one is removed.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn add_to_anchoring_tx_chain(&mut self, height: u64, tx: AnchoringTx) {
+        if let Some(replaced) = self.anchoring_tx_chain().get(&height) {
+            self.anchoring_tx_heights_mut().remove(&replaced.id());
+        }
+        self.index_anchoring_tx(height, &tx);
+        self.anchoring_tx_chain_mut().put(&height, tx);
+    }
+
+    /// Indexes the transactions of the [`anchoring_tx_chain`][1] by their `txid`s and
+    /// keeps their fees if the chain has been observed before the indices were introduced.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_tx_chain
+    pub fn reindex_anchoring_tx_chain(&mut self) {
+        if self.anchoring_tx_heights().iter().next().is_some() {
+            return;
+        }
+        let entries = self.anchoring_tx_chain().iter().collect::<Vec<_>>();
+        for (height, tx) in entries {
+            self.index_anchoring_tx(height, &tx);
+        }
+    }
+
+    fn index_anchoring_tx(&mut self, height: u64, tx: &AnchoringTx) {
+        self.anchoring_tx_heights_mut().put(&tx.id(), height);
+        match self.anchoring_tx_fee(tx) {
+            Some(fee) => self.anchoring_fees_mut().put(&height, fee),
+            None => self.anchoring_fees_mut().remove(&height),
+        }
+    }
+
+    /// Mutable variant of the [`tx_log`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.tx_log
+    pub fn tx_log_mut(&mut self) -> ProofListIndex<&mut Fork, TxLogEntry> {
+        ProofListIndex::new("btc_anchoring.tx_log", &mut self.view)
+    }
+
+    /// Mutable variant of the [`confirmation_times`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.confirmation_times
+    pub fn confirmation_times_mut(&mut self) -> MapIndex<&mut Fork, btc::TxId, u64> {
+        MapIndex::new("btc_anchoring.confirmation_times", &mut self.view)
+    }
+
+    /// Mutable variant of the [`anchoring_latencies`][1] index.
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.anchoring_latencies
+    pub fn anchoring_latencies_mut(&mut self) -> MapIndex<&mut Fork, u64, u64> {
+        MapIndex::new("btc_anchoring.latencies", &mut self.view)
+    }
+
     /// Creates and commits the genesis anchoring configuration from the proposed `cfg`.
+    ///
+    /// # Panics
+    ///
+    /// If the `threshold` of the `cfg` is beyond the safe bounds.
     pub fn create_genesis_config(&mut self, cfg: &AnchoringConfig) {
+        if let Err(reason) = cfg.check_threshold() {
+            panic!("Incorrect genesis anchoring config: {}", reason);
+        }
         for validator_key in &cfg.anchoring_keys {
             self.add_lect(validator_key, cfg.funding_tx().clone(), Hash::zero());
         }
     }
 
     /// Adds `lect` from validator with the given `public key`.
+    /// The previously unknown lect is appended to the [`tx_log`][1].
+    ///
+    /// [1]: struct.AnchoringSchema.html#method.tx_log
     pub fn add_lect<Tx>(&mut self, validator_key: &btc::PublicKey, tx: Tx, msg_hash: Hash)
     where
         Tx: Into<BitcoinTx>,
@@ -320,24 +1094,148 @@ impl<'a> AnchoringSchema<&'a mut Fork> {
             (tx, txid, idx)
         };
 
+        if !self.known_txs().contains(&txid) {
+            // Height of the block which is being executed.
+            let height = Schema::new(&self.view).block_hashes_by_height().len();
+            self.tx_log_mut().push(TxLogEntry::new(height, tx.clone()));
+            for input in &tx.input {
+                let outpoint = OutpointId {
+                    txid: btc::TxId::from(input.prev_hash),
+                    vout: input.prev_index,
+                }.hash();
+                let spends = self.outpoint_spends().get(&outpoint).unwrap_or(0);
+                self.outpoint_spends_mut().put(&outpoint, spends + 1);
+            }
+        }
+        // The noticed funding transactions spent by the lect are not available anymore.
+        for input in &tx.input {
+            let prev_txid = btc::TxId::from(input.prev_hash);
+            self.funding_notices_mut().remove(&prev_txid);
+            self.funding_notice_heights_mut().remove(&prev_txid);
+            self.spent_funding_mut().put(&prev_txid, txid);
+        }
         self.known_txs_mut().put(&txid, tx.clone());
         self.lect_indexes_mut(validator_key).put(&txid, idx)
     }
 
+    /// Records the lect agreed by the validators of the actual configuration as
+    /// the transfer transaction of the following configuration if it moves the funds
+    /// to the following anchoring address. The replacement of the transfer transaction
+    /// overwrites the recorded one.
+    pub fn record_config_transfer(&mut self) {
+        let (following, following_hash) =
+            match (self.following_anchoring_config(), self.following_config_hash()) {
+                (Some(following), Some(following_hash)) => (following, following_hash),
+                _ => return,
+            };
+        let actual = self.actual_anchoring_config();
+        let following_addr = following.redeem_script().1;
+        if actual.redeem_script().1 == following_addr {
+            return;
+        }
+        if let Some(TxKind::Anchoring(tx)) = self.collect_lects(&actual).map(TxKind::from) {
+            if !tx.is_final() && tx.output_address(actual.network) == following_addr {
+                self.config_transfers_mut().put(&following_hash, tx);
+            }
+        }
+    }
+
+    /// Registers the application `commitment`, for example, the root of a Merkle tree
+    /// of the companion service, so it is included into the payload of the following
+    /// anchoring transaction. It is intended to be called from the execution of
+    /// the transactions of the companion services.
+    pub fn add_commitment(&mut self, commitment: Hash) {
+        // Height of the block which is being executed.
+        let height = Schema::new(&self.view).block_hashes_by_height().len();
+        self.commitments_mut().push(commitment);
+        self.commitment_heights_mut().push(height);
+    }
+
+    /// Adds the funding transaction noticed by the validator, it also becomes known,
+    /// so the signatures of its outputs spending can be verified.
+    pub fn add_funding_notice(&mut self, tx: FundingTx) {
+        let txid = tx.id();
+        let tx = BitcoinTx::from(tx);
+        // Height of the block which is being executed.
+        let height = Schema::new(&self.view).block_hashes_by_height().len();
+        self.funding_notices_mut().put(&txid, tx.clone());
+        self.funding_notice_heights_mut().put(&txid, height);
+        self.known_txs_mut().put(&txid, tx);
+    }
+
+    /// Records the signature of the anchoring round and returns the earlier signature
+    /// of the same validator for the conflicting proposal of this round if it exists.
+    pub fn add_signed_proposal(
+        &mut self,
+        msg: &MsgAnchoringSignature,
+    ) -> Option<MsgAnchoringSignature> {
+        let tx = msg.tx();
+        let mut buffer = vec![0; 54];
+        BigEndian::write_u16(&mut buffer[0..2], msg.validator().0);
+        buffer[2..34].copy_from_slice(tx.prev_hash().as_bytes());
+        BigEndian::write_u32(&mut buffer[34..38], tx.input[0].prev_index);
+        BigEndian::write_u64(&mut buffer[38..46], tx.payload().block_height.0);
+        BigEndian::write_u64(&mut buffer[46..54], tx.amount());
+        let round = hash(&buffer);
+
+        match self.signed_proposals().get(&round) {
+            Some(ref signed) if signed.tx().nid() != tx.nid() => Some(signed.clone()),
+            Some(_) => None,
+            None => {
+                self.signed_proposals_mut().put(&round, msg.clone());
+                None
+            }
+        }
+    }
+
     /// Adds signature to known if it is correct.
     pub fn add_known_signature(&mut self, msg: MsgAnchoringSignature) {
         let ntxid = msg.tx().nid();
-        let signature_id = KnownSignatureId::from(&msg);
+        let signature_id = KnownSignatureId::from(&msg).hash();
         if let Some(sign_msg) = self.known_signatures().get(&signature_id) {
             warn!(
                 "Received another signature for given tx propose msg={:#?}",
                 sign_msg
             );
         } else {
+            let height = msg.tx().payload().block_height.0;
+            self.signature_rounds_mut().put(&ntxid, height);
             self.signatures_mut(&ntxid).push(msg.clone());
             self.known_signatures_mut().put(&signature_id, msg);
         }
     }
+
+    /// Removes the signatures of the rounds which are obsolete according to the
+    /// `signatures_pruning` policy of the given configuration.
+    pub fn prune_signatures(&mut self, cfg: &AnchoringConfig) {
+        let pruning = match cfg.signatures_pruning {
+            Some(ref pruning) => pruning.clone(),
+            None => return,
+        };
+        let lect_height = match self.collect_lects(cfg).map(TxKind::from) {
+            Some(TxKind::Anchoring(lect)) => lect.payload().block_height.0,
+            _ => return,
+        };
+        let retained_heights = pruning.retain_rounds.saturating_mul(cfg.frequency);
+        let horizon = lect_height.saturating_sub(retained_heights);
+        let obsolete = self.signature_rounds()
+            .iter()
+            .filter(|&(_, height)| height < horizon)
+            .map(|(ntxid, _)| ntxid)
+            .collect::<Vec<_>>();
+        for ntxid in obsolete {
+            let signatures = self.signatures(&ntxid).iter().collect::<Vec<_>>();
+            for msg in signatures {
+                let signature_id = KnownSignatureId::from(&msg).hash();
+                self.known_signatures_mut().remove(&signature_id);
+                if pruning.archive {
+                    self.archived_signatures_mut().put(&signature_id, msg.hash());
+                }
+            }
+            self.signatures_mut(&ntxid).clear();
+            self.signature_rounds_mut().remove(&ntxid);
+        }
+    }
 }
 
 impl<T> AnchoringSchema<T> {
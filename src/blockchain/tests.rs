@@ -12,17 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use exonum::crypto::{PublicKey, Signature, hash};
-use exonum::storage::StorageValue;
-use exonum::helpers::ValidatorId;
+use std::collections::BTreeMap;
+
+use exonum::blockchain::ValidatorKeys;
+use exonum::crypto::{gen_keypair, Hash, PublicKey, Signature, hash};
+use exonum::storage::{Database, MemoryDB, StorageValue};
+use exonum::helpers::{Height, ValidatorId};
 use exonum::encoding::serialize::FromHex;
+use exonum::messages::Message;
 
 use bitcoin::blockdata::transaction::SigHashType;
 use bitcoin::network::constants::Network;
 use serde_json;
 
 use details::btc;
-use details::btc::transactions::{AnchoringTx, BitcoinTx};
+use details::btc::transactions::{AnchoringTx, BitcoinTx, TransactionBuilder};
+use blockchain::consensus_storage::{AnchoringConfig, CatchUpPolicy, SignaturesPruning};
+use blockchain::disagreement::detect_lect_disagreement;
+use blockchain::schema::{AnchoringSchema, KnownSignatureId};
 use blockchain::dto::{LectContent, MsgAnchoringSignature, MsgAnchoringUpdateLatest};
 use details::tests::{dummy_anchoring_tx, gen_anchoring_keys, make_signatures};
 
@@ -154,3 +161,446 @@ fn test_nonexistent_input_in_msg_signature_tx_body() {
     );
     assert!(!msg.verify_content());
 }
+
+#[test]
+fn test_anchoring_config_network_serde() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Bitcoin, keys);
+    assert_eq!(cfg.network, btc::Chain::Mainnet);
+
+    // The main chain keeps its legacy name.
+    let json = serde_json::to_value(&cfg).unwrap();
+    assert_eq!(json["network"], "bitcoin");
+
+    cfg.network = btc::Chain::Regtest;
+    let json = serde_json::to_string(&cfg).unwrap();
+    let cfg2: AnchoringConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(cfg2.network, btc::Chain::Regtest);
+    assert_eq!(cfg2.btc_network(), Network::Testnet);
+    assert_eq!(cfg2.native_witness_address(), None);
+
+    // The segwit addresses are encoded for the configured chain.
+    let mut witness_cfg = cfg2.clone();
+    witness_cfg.script_type = btc::ScriptType::P2shP2wsh;
+    assert!(witness_cfg.native_witness_address().unwrap().starts_with("bcrt1q"));
+    witness_cfg.network = btc::Chain::Testnet;
+    assert!(witness_cfg.native_witness_address().unwrap().starts_with("tb1q"));
+
+    let json = json.replace("\"regtest\"", "\"mainnet\"");
+    let cfg3: AnchoringConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(cfg3.network, btc::Chain::Mainnet);
+
+    let json = json.replace("\"mainnet\"", "\"litecoin\"");
+    assert!(serde_json::from_str::<AnchoringConfig>(&json).is_err());
+}
+
+#[test]
+fn test_anchoring_config_low_funds() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    cfg.frequency = 10;
+    cfg.fee = 1000;
+    assert!(!cfg.is_low_funds(0));
+    assert_eq!(cfg.latest_anchoring_height_for_funds(Height(25), 0), Height(20));
+
+    cfg.low_funds_anchors = 5;
+    assert!(cfg.is_low_funds(4999));
+    assert!(!cfg.is_low_funds(5000));
+    assert_eq!(cfg.latest_anchoring_height_for_funds(Height(25), 0), Height(20));
+
+    cfg.low_funds_frequency = Some(30);
+    assert_eq!(cfg.latest_anchoring_height_for_funds(Height(25), 4999), Height(0));
+    assert_eq!(cfg.latest_anchoring_height_for_funds(Height(25), 5000), Height(20));
+    assert_eq!(cfg.latest_anchoring_height_for_funds(Height(35), 4999), Height(30));
+}
+
+#[test]
+fn test_anchoring_config_catch_up_height() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    cfg.frequency = 10;
+    assert_eq!(cfg.catch_up_height(Height(10), Height(50)), Height(50));
+
+    cfg.catch_up = CatchUpPolicy::AllMissed;
+    assert_eq!(cfg.catch_up_height(Height(10), Height(50)), Height(20));
+    assert_eq!(cfg.catch_up_height(Height(40), Height(50)), Height(50));
+    // The height anchored with the stretched frequency is aligned to the regular one.
+    assert_eq!(cfg.catch_up_height(Height(0), Height(30)), Height(10));
+}
+
+#[test]
+fn test_anchoring_config_fee_bounds() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    assert_eq!(cfg.bounded_fee(0), 0);
+    assert_eq!(cfg.bounded_fee(1_000_000), 1_000_000);
+    assert!(cfg.is_fee_in_bounds(0));
+    assert!(cfg.is_fee_in_bounds(1_000_000));
+
+    cfg.min_fee = 500;
+    cfg.max_fee = Some(10_000);
+    assert_eq!(cfg.bounded_fee(100), 500);
+    assert_eq!(cfg.bounded_fee(1000), 1000);
+    assert_eq!(cfg.bounded_fee(1_000_000), 10_000);
+    assert!(!cfg.is_fee_in_bounds(499));
+    assert!(cfg.is_fee_in_bounds(500));
+    assert!(cfg.is_fee_in_bounds(10_000));
+    assert!(!cfg.is_fee_in_bounds(10_001));
+}
+
+#[test]
+fn test_anchoring_config_transfer_timeout() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    assert!(!cfg.is_transfer_timed_out(Height(10), None, Height(1000)));
+
+    cfg.transfer_timeout = Some(20);
+    assert!(!cfg.is_transfer_timed_out(Height(10), None, Height(29)));
+    assert!(cfg.is_transfer_timed_out(Height(10), None, Height(30)));
+    assert!(cfg.is_transfer_timed_out(Height(10), Some(0), Height(30)));
+    assert!(!cfg.is_transfer_timed_out(Height(10), Some(1), Height(30)));
+}
+
+#[test]
+fn test_anchoring_config_threshold() {
+    let keys = gen_anchoring_keys(7).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys.clone());
+    assert_eq!(cfg.signature_threshold(), 5);
+    assert_eq!(cfg.threshold_bounds(), (3, 5));
+    assert_eq!(cfg.check_threshold(), Ok(()));
+
+    let majority_addr = cfg.redeem_script().1;
+    cfg.threshold = Some(3);
+    assert_eq!(cfg.signature_threshold(), 3);
+    assert_eq!(cfg.check_threshold(), Ok(()));
+    let redeem_script =
+        btc::RedeemScript::from_pubkeys(keys.iter(), 3).compressed(cfg.btc_network());
+    assert_eq!(cfg.redeem_script().0, redeem_script);
+    assert_ne!(cfg.redeem_script().1, majority_addr);
+
+    cfg.threshold = Some(2);
+    assert!(cfg.check_threshold().is_err());
+    cfg.threshold = Some(6);
+    assert!(cfg.check_threshold().is_err());
+
+    let cfg = AnchoringConfig::new(Network::Testnet, gen_anchoring_keys(4).0);
+    assert_eq!(cfg.threshold_bounds(), (2, 3));
+}
+
+#[test]
+#[should_panic(expected = "Incorrect genesis anchoring config")]
+fn test_genesis_config_unsafe_threshold() {
+    let mut cfg = AnchoringConfig::new(Network::Testnet, gen_anchoring_keys(4).0);
+    cfg.threshold = Some(4);
+
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    AnchoringSchema::new(&mut fork).create_genesis_config(&cfg);
+}
+
+#[test]
+fn test_anchoring_config_with_validators() {
+    let (anchoring_keys, _) = gen_anchoring_keys(4);
+    let cfg = AnchoringConfig::new(Network::Testnet, anchoring_keys.clone());
+    let validators = (0..5)
+        .map(|_| {
+            ValidatorKeys {
+                consensus_key: gen_keypair().0,
+                service_key: gen_keypair().0,
+            }
+        })
+        .collect::<Vec<_>>();
+    let actual = &validators[0..4];
+
+    // The second validator is removed and the fifth one is added.
+    let following = vec![
+        validators[0].clone(),
+        validators[2].clone(),
+        validators[3].clone(),
+        validators[4].clone(),
+    ];
+    let added_key = gen_anchoring_keys(1).0[0];
+    let mut added_keys = BTreeMap::new();
+    assert_eq!(
+        cfg.with_validators(actual, &following, &added_keys),
+        Err(validators[4].service_key)
+    );
+
+    added_keys.insert(validators[4].service_key, added_key);
+    let following_cfg = cfg.with_validators(actual, &following, &added_keys).unwrap();
+    assert_eq!(
+        following_cfg.anchoring_keys,
+        vec![anchoring_keys[0], anchoring_keys[2], anchoring_keys[3], added_key]
+    );
+    assert_ne!(following_cfg.redeem_script().1, cfg.redeem_script().1);
+
+    // The removed validator is not replaced, the majority count is recomputed.
+    let following_cfg = cfg.with_validators(actual, &following[0..3], &added_keys).unwrap();
+    assert_eq!(following_cfg.anchoring_keys.len(), 3);
+    assert_eq!(following_cfg.majority_count(), 3);
+
+    // The unchanged set of validators keeps the anchoring address.
+    let following_cfg = cfg.with_validators(actual, actual, &BTreeMap::new()).unwrap();
+    assert_eq!(following_cfg, cfg);
+}
+
+#[test]
+fn test_anchoring_config_transfer_lead() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    assert!(!cfg.is_transfer_postponed(Height(1000), Height(10)));
+
+    cfg.transfer_lead = Some(100);
+    assert!(cfg.is_transfer_postponed(Height(1000), Height(10)));
+    assert!(cfg.is_transfer_postponed(Height(1000), Height(899)));
+    assert!(!cfg.is_transfer_postponed(Height(1000), Height(900)));
+    assert!(!cfg.is_transfer_postponed(Height(1000), Height(1000)));
+}
+
+#[test]
+fn test_anchoring_config_confirmations() {
+    let keys = gen_anchoring_keys(4).0;
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys);
+    cfg.utxo_confirmations = 3;
+    assert_eq!(cfg.required_funding_confirmations(), 3);
+    assert_eq!(cfg.required_anchor_confirmations(), 3);
+
+    cfg.anchor_confirmations = Some(6);
+    assert_eq!(cfg.required_funding_confirmations(), 3);
+    assert_eq!(cfg.required_anchor_confirmations(), 6);
+
+    // The configurations without the separate depth of the anchors are still accepted.
+    let mut json = serde_json::to_value(&cfg).unwrap();
+    json.as_object_mut().unwrap().remove("anchor_confirmations");
+    let cfg2: AnchoringConfig = serde_json::from_value(json).unwrap();
+    assert_eq!(cfg2.anchor_confirmations, None);
+    assert_eq!(cfg2.required_anchor_confirmations(), 3);
+}
+
+#[test]
+fn test_anchoring_config_recovery_branch() {
+    let (keys, _) = gen_anchoring_keys(5);
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys[0..4].to_vec());
+    let (redeem_script, addr) = cfg.redeem_script();
+    assert_eq!(redeem_script.recovery_branch(), None);
+
+    // Configurations without the recovery branch are still accepted.
+    let json = serde_json::to_value(&cfg).unwrap();
+    assert_eq!(json["recovery"], serde_json::Value::Null);
+
+    let recovery = btc::RecoveryBranch {
+        key: keys[4],
+        timeout: 1000,
+    };
+    cfg.recovery = Some(recovery.clone());
+    let (recovery_script, recovery_addr) = cfg.redeem_script();
+    assert_eq!(recovery_script.recovery_branch(), Some(recovery));
+    assert!(recovery_addr != addr);
+
+    let json = serde_json::to_string(&cfg).unwrap();
+    let cfg2: AnchoringConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(cfg2, cfg);
+
+    // Configurations with the too small timeouts are rejected.
+    for timeout in &[0, 1, btc::MIN_RECOVERY_TIMEOUT - 1] {
+        let mut json = serde_json::to_value(&cfg).unwrap();
+        json["recovery"]["timeout"] = json!(timeout);
+        assert!(serde_json::from_value::<AnchoringConfig>(json).is_err());
+    }
+}
+
+#[test]
+fn test_lect_disagreement() {
+    let (keys, _) = gen_anchoring_keys(4);
+    let cfg = AnchoringConfig::new(Network::Testnet, keys.clone());
+    let (redeem_script, addr) = cfg.redeem_script();
+
+    let next_tx = |prev_tx: &AnchoringTx, height: u64, block_hash: Hash| -> BitcoinTx {
+        TransactionBuilder::with_prev_tx(prev_tx, 0)
+            .fee(1000)
+            .payload(Height(height), block_hash)
+            .send_to(addr.clone())
+            .into_transaction()
+            .unwrap()
+            .into()
+    };
+    let root = dummy_anchoring_tx(&redeem_script);
+    let first = next_tx(&root, 10, hash(&[1]));
+    let second = next_tx(&AnchoringTx::from(first.0.clone()), 20, hash(&[2]));
+    let fork_tx = next_tx(&root, 10, hash(&[3]));
+
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    let set_lects = |fork: &mut ::exonum::storage::Fork, lects: &[&BitcoinTx]| {
+        let mut schema = AnchoringSchema::new(fork);
+        for (key, lect) in keys.iter().zip(lects) {
+            schema.add_lect(key, (*lect).clone(), Hash::zero());
+        }
+    };
+
+    // Lagging lects do not conflict.
+    set_lects(&mut fork, &[&BitcoinTx::from(root.0.clone()); 4]);
+    set_lects(&mut fork, &[&second, &first, &first, &BitcoinTx::from(root.0.clone())]);
+    assert!(detect_lect_disagreement(&AnchoringSchema::new(&fork), &cfg, Height(5)).is_none());
+
+    set_lects(&mut fork, &[&second, &fork_tx, &first, &fork_tx]);
+    let resolution = detect_lect_disagreement(&AnchoringSchema::new(&fork), &cfg, Height(6))
+        .unwrap();
+    assert_eq!(resolution.height(), 6);
+    assert_eq!(resolution.lects(), vec![second.clone(), fork_tx.clone(), first.clone()]);
+    // The forked lect has the same support, but anchors the lower height.
+    assert_eq!(resolution.canonical_lect(), second);
+    assert_eq!(resolution.support(), 2);
+}
+
+#[test]
+fn test_signatures_pruning() {
+    let (keys, _) = gen_anchoring_keys(4);
+    let mut cfg = AnchoringConfig::new(Network::Testnet, keys.clone());
+    cfg.frequency = 10;
+    cfg.signatures_pruning = Some(SignaturesPruning {
+        retain_rounds: 1,
+        archive: true,
+    });
+    let (redeem_script, addr) = cfg.redeem_script();
+
+    let next_tx = |prev_tx: &AnchoringTx, height: u64| -> AnchoringTx {
+        TransactionBuilder::with_prev_tx(prev_tx, 0)
+            .fee(1000)
+            .payload(Height(height), hash(&[height as u8]))
+            .send_to(addr.clone())
+            .into_transaction()
+            .unwrap()
+    };
+    let first = next_tx(&dummy_anchoring_tx(&redeem_script), 10);
+    let second = next_tx(&first, 20);
+    let third = next_tx(&second, 30);
+    let (public_key, secret_key) = gen_keypair();
+    let sign = |tx: &AnchoringTx| {
+        MsgAnchoringSignature::new(&public_key, ValidatorId(0), tx.clone(), 0, &[1], &secret_key)
+    };
+
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    {
+        let mut schema = AnchoringSchema::new(&mut fork);
+        for tx in &[&first, &second, &third] {
+            schema.add_known_signature(sign(tx));
+        }
+        for key in &keys {
+            schema.add_lect(key, third.clone(), Hash::zero());
+        }
+        schema.prune_signatures(&cfg);
+    }
+
+    // The round of the common lect and the previous one are retained.
+    let schema = AnchoringSchema::new(&fork);
+    assert_eq!(schema.signatures(&first.nid()).len(), 0);
+    assert_eq!(schema.signatures(&second.nid()).len(), 1);
+    assert_eq!(schema.signatures(&third.nid()).len(), 1);
+    let signature_id = KnownSignatureId::from(&sign(&first)).hash();
+    assert!(!schema.known_signatures().contains(&signature_id));
+    assert_eq!(
+        schema.archived_signatures().get(&signature_id),
+        Some(sign(&first).hash())
+    );
+}
+
+#[test]
+fn test_commitments_between() {
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    let commitments = (0..4).map(|i| hash(&[i])).collect::<Vec<_>>();
+    {
+        let mut schema = AnchoringSchema::new(&mut fork);
+        schema.add_commitment(commitments[0]);
+        for (commitment, height) in commitments[1..].iter().zip(&[3, 3, 7]) {
+            schema.commitments_mut().push(*commitment);
+            schema.commitment_heights_mut().push(*height);
+        }
+    }
+
+    let schema = AnchoringSchema::new(&fork);
+    assert_eq!(schema.commitment_heights().get(0), Some(0));
+    assert_eq!(schema.commitments_between(None, Height(0)), &commitments[..1]);
+    assert_eq!(schema.commitments_between(None, Height(5)), &commitments[..3]);
+    assert_eq!(schema.commitments_between(Some(Height(0)), Height(3)), &commitments[1..3]);
+    assert!(schema.commitments_between(Some(Height(3)), Height(6)).is_empty());
+    assert_eq!(schema.commitments_between(Some(Height(3)), Height(10)), &commitments[3..]);
+}
+
+#[test]
+fn test_anchoring_tx_heights() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Bitcoin);
+    let addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+    let first = dummy_anchoring_tx(&redeem_script);
+    let second = TransactionBuilder::with_prev_tx(&first, 0)
+        .fee(1000)
+        .payload(Height(10), Hash::zero())
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    let third = TransactionBuilder::with_prev_tx(&first, 0)
+        .fee(2000)
+        .payload(Height(10), Hash::zero())
+        .send_to(addr)
+        .into_transaction()
+        .unwrap();
+
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    {
+        let mut schema = AnchoringSchema::new(&mut fork);
+        schema.anchoring_tx_chain_mut().put(&0, first.clone());
+        schema.reindex_anchoring_tx_chain();
+        schema.add_to_anchoring_tx_chain(10, second.clone());
+        // The transaction of the same height is replaced after the reorg.
+        schema.add_to_anchoring_tx_chain(10, third.clone());
+    }
+
+    let schema = AnchoringSchema::new(&fork);
+    assert_eq!(schema.anchoring_tx_by_txid(&first.id()), Some((0, first)));
+    assert_eq!(schema.anchoring_tx_by_txid(&second.id()), None);
+    assert_eq!(schema.anchoring_tx_by_txid(&third.id()), Some((10, third)));
+}
+
+#[test]
+fn test_anchoring_tx_fee_bumps() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Bitcoin);
+    let addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+    let first = dummy_anchoring_tx(&redeem_script);
+    let second = TransactionBuilder::with_prev_tx(&first, 0)
+        .fee(1000)
+        .payload(Height(10), Hash::zero())
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    let third = TransactionBuilder::with_prev_tx(&first, 0)
+        .fee(2000)
+        .payload(Height(10), Hash::zero())
+        .send_to(addr)
+        .into_transaction()
+        .unwrap();
+
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    {
+        let mut schema = AnchoringSchema::new(&mut fork);
+        schema.add_lect(&pub_keys[0], first.clone(), Hash::zero());
+        schema.add_lect(&pub_keys[0], second.clone(), Hash::zero());
+        assert_eq!(schema.fee_bumps(&second), 0);
+        // The proposal replacing the lect is counted before it is recorded.
+        assert_eq!(schema.fee_bumps(&third), 1);
+
+        schema.add_lect(&pub_keys[0], third.clone(), Hash::zero());
+        // The same lect of the other validator is not a replacement.
+        schema.add_lect(&pub_keys[1], second.clone(), Hash::zero());
+    }
+
+    let schema = AnchoringSchema::new(&fork);
+    assert_eq!(schema.fee_bumps(&first), 0);
+    assert_eq!(schema.fee_bumps(&second), 1);
+    assert_eq!(schema.fee_bumps(&third), 1);
+}
@@ -17,12 +17,19 @@ use bitcoin::blockdata::transaction::SigHashType;
 use exonum::blockchain::{Schema, Transaction};
 use exonum::messages::Message;
 use exonum::storage::{Fork, Snapshot};
-use exonum::helpers::Height;
+use exonum::crypto::{hash, Hash};
+use exonum::helpers::{Height, ValidatorId};
+use exonum::encoding::serialize::{FromHex, ToHex};
 
-use blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest};
-use blockchain::schema::AnchoringSchema;
-use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::dto::{MisbehaviorEvidence, MisbehaviorKind, MsgAnchoringFeeRate,
+                      MsgAnchoringFunding, MsgAnchoringKeyRotation, MsgAnchoringPause,
+                      MsgAnchoringSignature, MsgAnchoringSweep, MsgAnchoringTrigger,
+                      MsgAnchoringUpdateLatest};
+use blockchain::schema::{AnchoringSchema, KnownSignatureId};
+use blockchain::consensus_storage::{AnchoringConfig, DustPolicy};
+use blockchain::disagreement::resolve_lect_disagreement;
 use details::btc;
+use details::btc::canonical;
 use details::btc::transactions::{AnchoringTx, BitcoinTx, FundingTx, TxKind};
 
 impl MsgAnchoringSignature {
@@ -37,6 +44,14 @@ impl MsgAnchoringSignature {
             return false;
         }
         let tx = self.tx();
+        // Check that all validators sign the same transaction
+        if !canonical::is_canonical(&tx) {
+            warn!(
+                "Received msg for non-canonical transaction, content={:#?}",
+                self
+            );
+            return false;
+        }
         // Check that the signature is provided for an existing anchoring tx input
         if tx.input.len() as u32 <= self.input() {
             warn!(
@@ -55,6 +70,14 @@ impl MsgAnchoringSignature {
                 return false;
             }
         }
+        // Check that input witnesses are empty
+        if tx.witness.iter().any(|witness| !witness.is_empty()) {
+            warn!(
+                "Received msg with non empty input witnesses, content={:#?}",
+                self
+            );
+            return false;
+        }
         true
     }
 
@@ -70,30 +93,133 @@ impl MsgAnchoringSignature {
             warn!("Received msg from non-validator, content={:#?}", self);
             return false;
         }
+        // Only the first signature for the input is stored.
+        let signature_id = KnownSignatureId::from(self).hash();
+        if anchoring_schema.known_signatures().contains(&signature_id) ||
+            anchoring_schema.archived_signatures().contains(&signature_id)
+        {
+            warn!("Received msg with already known signature, content={:#?}", self);
+            return false;
+        }
 
-        // Verify signature
         let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+        if !verify_anchoring_tx_prev_tx(&tx, &anchoring_schema) {
+            warn!("Received msg for unknown proposal, content={:#?}", self);
+            return false;
+        }
+        if is_stale_proposal(&tx, &anchoring_cfg, &anchoring_schema) {
+            warn!("Received msg for stale proposal, content={:#?}", self);
+            return false;
+        }
+
+        // Verify signature
         if let Some(pub_key) = anchoring_cfg.anchoring_keys.get(id) {
             let (redeem_script, addr) = anchoring_cfg.redeem_script();
-            let tx_addr = tx.output_address(anchoring_cfg.network);
-            // Use following address if it exists
-            let addr = if let Some(following) = anchoring_schema.following_anchoring_config() {
-                following.redeem_script().1
+            if tx.is_final() {
+                // The final proposal burns the dust change of the actual address.
+                if anchoring_cfg.dust_policy != DustPolicy::Burn {
+                    warn!(
+                        "Received msg for final proposal without the dust burning, content={:#?}",
+                        self
+                    );
+                    return false;
+                }
             } else {
-                addr
-            };
-            if tx_addr != addr {
+                let tx_addr = tx.output_address(anchoring_cfg.network);
+                // Use following address if it exists
+                let addr = if let Some(following) = anchoring_schema.following_anchoring_config() {
+                    // The funds are never transferred to the address with the unsafe threshold.
+                    if let Err(reason) = following.check_threshold() {
+                        warn!(
+                            "Received msg for transfer to incorrect config: {}, content={:#?}",
+                            reason,
+                            self
+                        );
+                        return false;
+                    }
+                    following.redeem_script().1
+                } else {
+                    addr
+                };
+                // The funds are sent to the refund address once the sweep is agreed.
+                let sweep_addr = anchoring_schema.sweep_address();
+                if tx_addr != addr && Some(&tx_addr) != sweep_addr.as_ref() {
+                    warn!(
+                        "Received msg with incorrect output address, content={:#?}",
+                        self
+                    );
+                    return false;
+                }
+            }
+            if !verify_anchoring_tx_payload(&tx, &core_schema) {
+                warn!("Received msg with incorrect payload, content={:#?}", self);
+                return false;
+            }
+            if !verify_anchoring_tx_chain_id(&tx, &anchoring_schema) {
                 warn!(
-                    "Received msg with incorrect output address, content={:#?}",
+                    "Received msg with foreign chain identifier, content={:#?}",
                     self
                 );
                 return false;
             }
-            if !verify_anchoring_tx_payload(&tx, &core_schema) {
-                warn!("Received msg with incorrect payload, content={:#?}", self);
+            if let Some(fee) = anchoring_schema.anchoring_tx_fee(&tx) {
+                let in_bounds = if tx.is_final() {
+                    anchoring_cfg.is_final_fee_in_bounds(fee)
+                } else {
+                    anchoring_cfg.is_fee_in_bounds(fee)
+                };
+                if !in_bounds {
+                    warn!(
+                        "Received msg for proposal with fee={} beyond bounds, content={:#?}",
+                        fee,
+                        self
+                    );
+                    return false;
+                }
+            }
+            // The signature is useless if the input does not spend the anchoring address,
+            // which cannot be checked for the unknown spent transaction.
+            let prev_tx = match anchoring_schema.spent_tx(&tx, self.input()) {
+                Some(prev_tx) => prev_tx,
+                None => {
+                    warn!(
+                        "Received msg for input which spends unknown transaction, \
+                         content={:#?}",
+                        self
+                    );
+                    return false;
+                }
+            };
+            let prev_index = tx.input[self.input() as usize].prev_index as usize;
+            let spent_output = prev_tx.output.get(prev_index);
+            let script_pubkey = anchoring_cfg.redeem_script().1.script_pubkey();
+            if spent_output.map(|out| &out.script_pubkey) != Some(&script_pubkey) {
+                warn!(
+                    "Received msg for input which does not spend the anchoring address, \
+                     content={:#?}",
+                    self
+                );
                 return false;
             }
-            if !tx.verify_input(&redeem_script, self.input(), pub_key, self.signature()) {
+            let signature_is_correct = match anchoring_cfg.script_type {
+                btc::ScriptType::P2sh => {
+                    tx.verify_input(&redeem_script, self.input(), pub_key, self.signature())
+                }
+                btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                    if let Some(value) = anchoring_schema.spent_output_value(&tx, self.input()) {
+                        tx.verify_witness_input(
+                            &redeem_script,
+                            self.input(),
+                            value,
+                            pub_key,
+                            self.signature(),
+                        )
+                    } else {
+                        false
+                    }
+                }
+            };
+            if !signature_is_correct {
                 warn!("Received msg with incorrect signature, content={:#?}", self);
                 return false;
             }
@@ -102,6 +228,49 @@ impl MsgAnchoringSignature {
             return false;
         }
     }
+
+    // The rejected signature proves the misbehavior only if it is incorrect for the input
+    // which spends the actual anchoring address with the known value.
+    fn has_incorrect_signature(&self, view: &Fork) -> bool {
+        let core_schema = Schema::new(&view);
+        let anchoring_schema = AnchoringSchema::new(&view);
+
+        let tx = self.tx();
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            return false;
+        }
+        let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+        let pub_key = match anchoring_cfg.anchoring_keys.get(id) {
+            Some(pub_key) => pub_key,
+            None => return false,
+        };
+        let (redeem_script, addr) = anchoring_cfg.redeem_script();
+        let prev_tx = match anchoring_schema.spent_tx(&tx, self.input()) {
+            Some(prev_tx) => prev_tx,
+            None => return false,
+        };
+        let prev_index = tx.input[self.input() as usize].prev_index as usize;
+        let spent_output = match prev_tx.output.get(prev_index) {
+            Some(output) if output.script_pubkey == addr.script_pubkey() => output,
+            _ => return false,
+        };
+        match anchoring_cfg.script_type {
+            btc::ScriptType::P2sh => {
+                !tx.verify_input(&redeem_script, self.input(), pub_key, self.signature())
+            }
+            btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                !tx.verify_witness_input(
+                    &redeem_script,
+                    self.input(),
+                    spent_output.value,
+                    pub_key,
+                    self.signature(),
+                )
+            }
+        }
+    }
 }
 
 impl Transaction for MsgAnchoringSignature {
@@ -111,9 +280,25 @@ impl Transaction for MsgAnchoringSignature {
 
     fn execute(&self, fork: &mut Fork) {
         if !self.validate(fork) {
+            if self.has_incorrect_signature(fork) {
+                let kind = MisbehaviorKind::InvalidSignature;
+                add_misbehavior_evidence(fork, self.validator(), kind, self.hash(), None);
+            }
             return;
         }
 
+        let conflicting = AnchoringSchema::new(&mut *fork).add_signed_proposal(self);
+        if let Some(conflicting) = conflicting {
+            let kind = MisbehaviorKind::ConflictingProposal;
+            let conflicting_hash = conflicting.hash();
+            add_misbehavior_evidence(
+                fork,
+                self.validator(),
+                kind,
+                self.hash(),
+                Some(conflicting_hash),
+            );
+        }
         let mut anchoring_schema = AnchoringSchema::new(fork);
         anchoring_schema.add_known_signature(self.clone())
     }
@@ -135,13 +320,26 @@ impl MsgAnchoringUpdateLatest {
         }
 
         let anchoring_cfg = anchoring_schema.actual_anchoring_config();
-        let key = &anchoring_cfg.anchoring_keys[id];
+        let key = match anchoring_cfg.anchoring_keys.get(id) {
+            Some(key) => key,
+            None => {
+                warn!("Received lect from validator without anchoring key, content={:#?}", self);
+                return None;
+            }
+        };
         match TxKind::from(tx.clone()) {
             TxKind::Anchoring(tx) => {
                 if !verify_anchoring_tx_payload(&tx, &core_schema) {
                     warn!("Received lect with incorrect payload, content={:#?}", self);
                     return None;
                 }
+                if !verify_anchoring_tx_chain_id(&tx, &anchoring_schema) {
+                    warn!(
+                        "Received lect with foreign chain identifier, content={:#?}",
+                        self
+                    );
+                    return None;
+                }
                 if !verify_anchoring_tx_prev_hash(&tx, &anchoring_schema) {
                     warn!(
                         "Received lect with prev_lect without 2/3+ confirmations, content={:#?}",
@@ -160,7 +358,10 @@ impl MsgAnchoringUpdateLatest {
                     return None;
                 }
             }
-            TxKind::Other(_) => panic!("Incorrect fields deserialization."),
+            TxKind::Other(_) => {
+                warn!("Received lect with unknown transaction kind, content={:#?}", self);
+                return None;
+            }
         }
 
         if anchoring_schema.lects(key).len() != self.lect_count() {
@@ -170,6 +371,21 @@ impl MsgAnchoringUpdateLatest {
 
         Some((*key, tx))
     }
+
+    // The lect of the validator claims to anchor the block which differs from
+    // the `Exonum` block at the anchored height.
+    fn has_incorrect_payload(&self, view: &Fork) -> bool {
+        let core_schema = Schema::new(view);
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            return false;
+        }
+        match TxKind::from(self.tx()) {
+            TxKind::Anchoring(tx) => !verify_anchoring_tx_payload(&tx, &core_schema),
+            _ => false,
+        }
+    }
 }
 
 impl Transaction for MsgAnchoringUpdateLatest {
@@ -179,9 +395,342 @@ impl Transaction for MsgAnchoringUpdateLatest {
 
     fn execute(&self, view: &mut Fork) {
         if let Some((key, tx)) = self.validate(view) {
+            AnchoringSchema::new(&mut *view).add_lect(&key, tx, self.hash());
+            resolve_lect_disagreement(view);
             let mut anchoring_schema = AnchoringSchema::new(view);
-            anchoring_schema.add_lect(&key, tx, self.hash())
+            anchoring_schema.record_config_transfer();
+            let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+            anchoring_schema.prune_signatures(&anchoring_cfg);
+        } else if self.has_incorrect_payload(view) {
+            let kind = MisbehaviorKind::InvalidLect;
+            add_misbehavior_evidence(view, self.validator(), kind, self.hash(), None);
+        }
+    }
+}
+
+impl MsgAnchoringFunding {
+    pub fn validate(&self, view: &Fork) -> Option<FundingTx> {
+        let anchoring_schema = AnchoringSchema::new(view);
+        let core_schema = Schema::new(view);
+
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received funding notice from non validator, content={:#?}", self);
+            return None;
+        }
+
+        let tx = match TxKind::from(self.tx()) {
+            TxKind::FundingTx(tx) => tx,
+            _ => {
+                warn!(
+                    "Received funding notice with non funding transaction, content={:#?}",
+                    self
+                );
+                return None;
+            }
+        };
+        // The known transactions are either already noticed or spent by the anchoring chain.
+        if anchoring_schema.known_txs().contains(&tx.id()) {
+            warn!("Received funding notice with known transaction, content={:#?}", self);
+            return None;
+        }
+        // Funds may be sent to the following address before the transition.
+        let mut addrs = vec![anchoring_schema.actual_anchoring_config().redeem_script().1];
+        if let Some(following) = anchoring_schema.following_anchoring_config() {
+            addrs.push(following.redeem_script().1);
+        }
+        if addrs.iter().all(|addr| tx.find_out(addr).is_none()) {
+            warn!(
+                "Received funding notice for foreign address, content={:#?}",
+                self
+            );
+            return None;
         }
+        Some(tx)
+    }
+}
+
+impl Transaction for MsgAnchoringFunding {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if let Some(tx) = self.validate(view) {
+            AnchoringSchema::new(&mut *view).add_funding_notice(tx);
+        }
+    }
+}
+
+impl MsgAnchoringSweep {
+    pub fn validate(&self, view: &Fork) -> bool {
+        let anchoring_schema = AnchoringSchema::new(view);
+        let core_schema = Schema::new(view);
+
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received sweep vote from non validator, content={:#?}", self);
+            return false;
+        }
+        // The vote is revoked by the empty address.
+        if self.address().is_empty() {
+            return true;
+        }
+
+        let addr = match self.address().parse::<btc::Address>() {
+            Ok(addr) => addr,
+            Err(_) => {
+                warn!("Received sweep vote with incorrect address, content={:#?}", self);
+                return false;
+            }
+        };
+        // The anchoring transactions can be sent to the `p2sh` or `p2wsh` addresses only.
+        let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+        if !addr.is_script_hash() || !addr.is_valid_for(anchoring_cfg.network) {
+            warn!(
+                "Received sweep vote with unsupported address, content={:#?}",
+                self
+            );
+            return false;
+        }
+        true
+    }
+}
+
+impl Transaction for MsgAnchoringSweep {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if self.validate(view) {
+            let key = hash(self.from().as_ref());
+            AnchoringSchema::new(&mut *view).sweep_votes_mut().put(
+                &key,
+                self.clone(),
+            );
+        }
+    }
+}
+
+impl MsgAnchoringPause {
+    pub fn validate(&self, view: &Fork) -> bool {
+        let core_schema = Schema::new(view);
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received pause vote from non validator, content={:#?}", self);
+            return false;
+        }
+        true
+    }
+}
+
+impl Transaction for MsgAnchoringPause {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if self.validate(view) {
+            let key = hash(self.from().as_ref());
+            AnchoringSchema::new(&mut *view).pause_votes_mut().put(
+                &key,
+                self.clone(),
+            );
+        }
+    }
+}
+
+impl MsgAnchoringFeeRate {
+    pub fn validate(&self, view: &Fork) -> bool {
+        let core_schema = Schema::new(view);
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received fee rate from non validator, content={:#?}", self);
+            return false;
+        }
+        if self.fee_rate() == 0 {
+            warn!("Received zero fee rate, content={:#?}", self);
+            return false;
+        }
+        true
+    }
+}
+
+impl Transaction for MsgAnchoringFeeRate {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if self.validate(view) {
+            let key = hash(self.from().as_ref());
+            AnchoringSchema::new(&mut *view).fee_rate_votes_mut().put(
+                &key,
+                self.clone(),
+            );
+        }
+    }
+}
+
+impl MsgAnchoringTrigger {
+    pub fn validate(&self, view: &Fork) -> bool {
+        let anchoring_schema = AnchoringSchema::new(view);
+        let core_schema = Schema::new(view);
+
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received anchoring trigger from non validator, content={:#?}", self);
+            return false;
+        }
+        // The trigger is ignored until the previous one is anchored by the common lect.
+        if let Some(trigger_height) = anchoring_schema.latest_anchoring_trigger() {
+            let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+            let lect = anchoring_schema.collect_lects(&anchoring_cfg).map(TxKind::from);
+            let anchored_height = match lect {
+                Some(TxKind::Anchoring(lect)) => Some(lect.payload().block_height),
+                _ => None,
+            };
+            if anchored_height.map_or(true, |height| height < trigger_height) {
+                warn!(
+                    "Received anchoring trigger while the previous one for height={} \
+                     is not anchored yet, content={:#?}",
+                    trigger_height,
+                    self
+                );
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Transaction for MsgAnchoringTrigger {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if self.validate(view) {
+            let height = Schema::new(&*view).block_hashes_by_height().len();
+            info!("Anchoring of the block at height={} is triggered", height);
+            AnchoringSchema::new(&mut *view)
+                .anchoring_triggers_mut()
+                .push(height);
+        }
+    }
+}
+
+impl MsgAnchoringKeyRotation {
+    pub fn validate(&self, view: &Fork) -> bool {
+        let anchoring_schema = AnchoringSchema::new(view);
+        let core_schema = Schema::new(view);
+
+        let id = self.validator().0 as usize;
+        let actual_cfg = core_schema.actual_configuration();
+        if actual_cfg.validator_keys.get(id).map(|k| k.service_key) != Some(*self.from()) {
+            warn!("Received key rotation vote from non validator, content={:#?}", self);
+            return false;
+        }
+        let anchoring_cfg = anchoring_schema.actual_anchoring_config();
+        if self.target().0 as usize >= anchoring_cfg.anchoring_keys.len() {
+            warn!("Received key rotation vote with unknown target, content={:#?}", self);
+            return false;
+        }
+        if *self.cfg_hash() != anchoring_schema.actual_config_hash() {
+            warn!("Received key rotation vote for obsolete config, content={:#?}", self);
+            return false;
+        }
+        // The vote is revoked by the empty key.
+        if self.key().is_empty() {
+            return true;
+        }
+
+        match btc::PublicKey::from_hex(self.key()) {
+            Ok(ref key) if !anchoring_cfg.anchoring_keys.contains(key) => true,
+            Ok(_) => {
+                warn!("Received key rotation vote with used key, content={:#?}", self);
+                false
+            }
+            Err(_) => {
+                warn!("Received key rotation vote with incorrect key, content={:#?}", self);
+                false
+            }
+        }
+    }
+}
+
+impl Transaction for MsgAnchoringKeyRotation {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, view: &mut Fork) {
+        if !self.validate(view) {
+            return;
+        }
+        let height = Schema::new(&*view).block_hashes_by_height().len();
+        let mut anchoring_schema = AnchoringSchema::new(&mut *view);
+        let key = hash(self.from().as_ref());
+        anchoring_schema.key_rotation_votes_mut().put(
+            &key,
+            self.clone(),
+        );
+        // The height of the approval defines the activation height of the rotated config.
+        if let Some(rotation) = anchoring_schema.approved_key_rotation() {
+            let id = rotation.hash();
+            if !anchoring_schema.key_rotation_heights().contains(&id) {
+                info!(
+                    "Approved rotation of the anchoring key of validator={}, key={}",
+                    rotation.target().0,
+                    rotation.key()
+                );
+                anchoring_schema.key_rotation_heights_mut().put(&id, height);
+            }
+        }
+    }
+}
+
+// The proposal either continues the known anchoring chain or starts a new one
+// from the funding transaction of the actual or following configuration.
+fn verify_anchoring_tx_prev_tx<T>(tx: &AnchoringTx, anchoring_schema: &AnchoringSchema<T>) -> bool
+where
+    T: AsRef<Snapshot>,
+{
+    let prev_txid = tx.prev_hash();
+    if anchoring_schema.known_txs().contains(&prev_txid) ||
+        anchoring_schema.funding_notices().contains(&prev_txid)
+    {
+        return true;
+    }
+    let mut cfgs = vec![anchoring_schema.actual_anchoring_config()];
+    if let Some(following) = anchoring_schema.following_anchoring_config() {
+        cfgs.push(following);
+    }
+    cfgs.iter().any(|cfg| {
+        cfg.funding_tx.as_ref().map(|funding_tx| funding_tx.id()) == Some(prev_txid)
+    })
+}
+
+// The proposal is stale if it anchors an older block than the common lect does.
+fn is_stale_proposal<T>(
+    tx: &AnchoringTx,
+    anchoring_cfg: &AnchoringConfig,
+    anchoring_schema: &AnchoringSchema<T>,
+) -> bool
+where
+    T: AsRef<Snapshot>,
+{
+    match anchoring_schema.collect_lects(anchoring_cfg).map(TxKind::from) {
+        Some(TxKind::Anchoring(lect)) => {
+            tx.payload().block_height < lect.payload().block_height
+        }
+        _ => false,
     }
 }
 
@@ -194,12 +743,14 @@ where
     // Get `AnchoringConfig` for prev_tx
     let anchoring_cfg = {
         let cfg_height = anchoring_schema.known_txs().get(&prev_txid).and_then(|tx| {
-            let height = match TxKind::from(tx) {
-                TxKind::Anchoring(tx) => tx.payload().block_height,
-                TxKind::FundingTx(_) => Height::zero(),
-                TxKind::Other(tx) => panic!("Incorrect lect content={:#?}", tx),
-            };
-            Some(height)
+            match TxKind::from(tx) {
+                TxKind::Anchoring(tx) => Some(tx.payload().block_height),
+                TxKind::FundingTx(_) => Some(Height::zero()),
+                TxKind::Other(tx) => {
+                    warn!("Prev lect has incorrect content={:#?}", tx);
+                    None
+                }
+            }
         });
 
         if let Some(height) = cfg_height {
@@ -240,12 +791,57 @@ where
     prev_lects_count >= anchoring_cfg.majority_count()
 }
 
+fn add_misbehavior_evidence(
+    view: &mut Fork,
+    validator: ValidatorId,
+    kind: MisbehaviorKind,
+    msg_hash: Hash,
+    conflicting_msg_hash: Option<Hash>,
+) {
+    warn!(
+        "Recorded misbehavior {:?} of validator={}, msg_hash={}",
+        kind,
+        validator.0,
+        msg_hash.to_hex()
+    );
+    let height = Schema::new(&*view).block_hashes_by_height().len();
+    let evidence = MisbehaviorEvidence::new(
+        height,
+        validator,
+        kind.code(),
+        &msg_hash,
+        &conflicting_msg_hash.unwrap_or_else(Hash::zero),
+    );
+    AnchoringSchema::new(view).misbehavior_evidence_mut().push(evidence);
+}
+
 fn verify_anchoring_tx_payload<T>(tx: &AnchoringTx, schema: &Schema<T>) -> bool
 where
     T: AsRef<Snapshot>,
 {
     let payload = tx.payload();
-    schema.block_hashes_by_height().get(payload.block_height.0) == Some(payload.block_hash)
+    if schema.block_hashes_by_height().get(payload.block_height.0) != Some(payload.block_hash) {
+        return false;
+    }
+    match payload.state_hash {
+        Some(state_hash) => {
+            let block = schema.blocks().get(&payload.block_hash);
+            block.map(|block| *block.state_hash()) == Some(state_hash)
+        }
+        None => true,
+    }
+}
+
+// The transaction may be built by the actual configuration as well as by the configuration
+// of the anchored height, if the lect is updated after the configuration change.
+fn verify_anchoring_tx_chain_id<T>(tx: &AnchoringTx, anchoring_schema: &AnchoringSchema<T>) -> bool
+where
+    T: AsRef<Snapshot>,
+{
+    let payload = tx.payload();
+    let actual_cfg = anchoring_schema.actual_anchoring_config();
+    let anchored_cfg = anchoring_schema.anchoring_config_by_height(payload.block_height);
+    payload.chain_id == actual_cfg.chain_id || payload.chain_id == anchored_cfg.chain_id
 }
 
 fn verify_funding_tx(tx: &FundingTx, anchoring_cfg: &AnchoringConfig) -> bool {
@@ -0,0 +1,543 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of the anchoring transactions chain for the external auditors.
+//!
+//! The chain can be verified by the data of the `Exonum` node or without it, by the bitcoin
+//! transactions and the genesis anchoring configuration only.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use exonum::blockchain::Schema;
+use exonum::crypto::Hash;
+use exonum::helpers::Height;
+use exonum::storage::Snapshot;
+
+use details::btc;
+use details::btc::transactions::{AnchoringTx, BitcoinTx, TxKind};
+use details::rpc::{BitcoinRelay, Result as RpcResult};
+use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::schema::AnchoringSchema;
+
+/// Anchoring transaction verified as a part of the anchoring chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerifiedAnchor {
+    /// `Txid` of the anchoring transaction.
+    pub txid: btc::TxId,
+    /// Anchored block height.
+    pub block_height: Height,
+    /// Anchored block hash.
+    pub block_hash: Hash,
+}
+
+/// Inconsistency found in the anchoring chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AnchoringChainIssue {
+    /// There is no lect agreed by the majority of the actual validators.
+    NoLect,
+    /// The lect agreed by the majority is not an anchoring transaction.
+    UnexpectedLect {
+        /// `Txid` of the lect.
+        txid: btc::TxId,
+    },
+    /// The transaction spent by the anchoring transaction is unknown, so the chain
+    /// can not be followed towards the funding transaction.
+    Gap {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// `Txid` of the unknown spent transaction.
+        prev_txid: btc::TxId,
+    },
+    /// The anchoring transaction spends an unexpected output of the previous transaction.
+    WrongInput {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// `Txid` of the spent transaction.
+        prev_txid: btc::TxId,
+        /// Index of the spent output.
+        prev_index: u32,
+    },
+    /// The anchoring transaction pays to an unexpected address.
+    WrongAddress {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// Address of the anchoring transaction output.
+        address: String,
+        /// Expected anchoring addresses.
+        expected: Vec<String>,
+    },
+    /// The payload block hash differs from the hash of the block at the anchored height.
+    BlockHashMismatch {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// Anchored block height.
+        block_height: Height,
+        /// Anchored block hash.
+        block_hash: Hash,
+        /// Hash of the block at the anchored height, `None` if there is no such block.
+        expected: Option<Hash>,
+    },
+    /// The payload state hash differs from the state hash of the anchored block.
+    StateHashMismatch {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// Anchored block height.
+        block_height: Height,
+        /// Anchored state hash.
+        state_hash: Hash,
+        /// State hash of the anchored block, `None` if there is no such block.
+        expected: Option<Hash>,
+    },
+    /// The payload chain identifier differs from the identifier of the anchored blockchain.
+    ForeignChainId {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// Chain identifier of the payload.
+        chain_id: Option<u32>,
+        /// Chain identifier of the anchoring configuration.
+        expected: Option<u32>,
+    },
+    /// The anchored height is less than the height anchored by the previous transaction.
+    HeightDecreased {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+        /// Anchored block height.
+        block_height: Height,
+        /// Height anchored by the previous transaction.
+        prev_height: Height,
+    },
+    /// The chain starts from the funding transaction other than the one
+    /// of the genesis anchoring configuration.
+    ForeignFunding {
+        /// `Txid` of the funding transaction.
+        txid: btc::TxId,
+        /// `Txid` of the funding transaction of the genesis configuration.
+        expected: Option<btc::TxId>,
+    },
+}
+
+/// Result of the anchoring chain verification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnchoringChainReport {
+    /// `Txid` of the funding transaction which starts the chain, `None` if it is not reached.
+    pub funding_tx: Option<btc::TxId>,
+    /// Anchoring transactions from the first one up to the lect.
+    pub anchors: Vec<VerifiedAnchor>,
+    /// Found inconsistencies, the chain is correct if there are none.
+    pub issues: Vec<AnchoringChainIssue>,
+}
+
+impl AnchoringChainReport {
+    /// Returns `true` if the chain has no inconsistencies.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walks the anchoring chain from the lect agreed by the majority of the actual validators
+/// back to the funding transaction and verifies that each anchoring transaction spends
+/// the funds output of the previous one, pays to the anchoring address of the corresponding
+/// configuration and anchors the hash of the block at its height.
+///
+/// The chain is followed through the `prev_tx_chain` field of the recovering transactions.
+/// Only the `Exonum` blockchain data is used, so the transactions are not checked to be
+/// confirmed in the bitcoin blockchain.
+pub fn verify_anchoring_chain(snapshot: &Snapshot) -> AnchoringChainReport {
+    let schema = AnchoringSchema::new(snapshot);
+    let core_schema = Schema::new(snapshot);
+    let actual_cfg = schema.actual_anchoring_config();
+
+    let mut report = AnchoringChainReport {
+        funding_tx: None,
+        anchors: Vec::new(),
+        issues: Vec::new(),
+    };
+    let mut tx = match schema.collect_lects(&actual_cfg).map(TxKind::from) {
+        Some(TxKind::Anchoring(tx)) => tx,
+        Some(TxKind::FundingTx(tx)) => {
+            report.funding_tx = Some(tx.id());
+            return report;
+        }
+        Some(TxKind::Other(tx)) => {
+            report.issues.push(AnchoringChainIssue::UnexpectedLect { txid: tx.id() });
+            return report;
+        }
+        None => {
+            report.issues.push(AnchoringChainIssue::NoLect);
+            return report;
+        }
+    };
+
+    // The lect may pay to the address of the actual or the following configuration.
+    let mut next_addrs = vec![actual_cfg.redeem_script().1];
+    if let Some(cfg) = schema.following_anchoring_config() {
+        next_addrs.push(cfg.redeem_script().1);
+    }
+
+    let mut issues = Vec::new();
+    let mut visited = HashSet::new();
+    loop {
+        let txid = tx.id();
+        if !visited.insert(txid) {
+            break;
+        }
+
+        let payload = tx.payload();
+        let cfg = schema.anchoring_config_by_height(payload.block_height);
+        let addr = cfg.redeem_script().1;
+
+        let block_hash = core_schema.block_hash_by_height(payload.block_height);
+        if block_hash != Some(payload.block_hash) {
+            issues.push(AnchoringChainIssue::BlockHashMismatch {
+                txid,
+                block_height: payload.block_height,
+                block_hash: payload.block_hash,
+                expected: block_hash,
+            });
+        }
+        if let Some(state_hash) = payload.state_hash {
+            let expected = core_schema.blocks().get(&payload.block_hash).map(
+                |block| *block.state_hash(),
+            );
+            if expected != Some(state_hash) {
+                issues.push(AnchoringChainIssue::StateHashMismatch {
+                    txid,
+                    block_height: payload.block_height,
+                    state_hash,
+                    expected,
+                });
+            }
+        }
+        if payload.chain_id != cfg.chain_id && payload.chain_id != actual_cfg.chain_id {
+            issues.push(AnchoringChainIssue::ForeignChainId {
+                txid,
+                chain_id: payload.chain_id,
+                expected: cfg.chain_id,
+            });
+        }
+
+        // Transition transactions pay to the address of the next configuration,
+        // the final transaction does not pay to any address.
+        if !tx.is_final() {
+            let tx_addr = tx.output_address(cfg.network);
+            let mut expected = next_addrs.clone();
+            expected.push(addr.clone());
+            if !expected.contains(&tx_addr) {
+                issues.push(AnchoringChainIssue::WrongAddress {
+                    txid,
+                    address: tx_addr.to_string(),
+                    expected: expected.iter().map(|addr| addr.to_string()).collect(),
+                });
+            }
+        }
+        report.anchors.push(VerifiedAnchor {
+            txid,
+            block_height: payload.block_height,
+            block_hash: payload.block_hash,
+        });
+        next_addrs = vec![addr.clone()];
+
+        let prev_txid = tx.prev_hash();
+        let prev_index = tx.input[0].prev_index;
+        let prev_tx = match find_tx(&schema, &actual_cfg, &prev_txid) {
+            Some(prev_tx) => prev_tx,
+            None => {
+                issues.push(AnchoringChainIssue::Gap { txid, prev_txid });
+                break;
+            }
+        };
+        let wrong_input = AnchoringChainIssue::WrongInput {
+            txid,
+            prev_txid,
+            prev_index,
+        };
+        match TxKind::from(prev_tx) {
+            TxKind::Anchoring(prev_tx) => {
+                if prev_index != 0 {
+                    issues.push(wrong_input);
+                }
+                check_height(&mut issues, &tx, &prev_tx);
+                tx = prev_tx;
+            }
+            TxKind::FundingTx(funding_tx) => {
+                if funding_tx.find_out(&addr) != Some(prev_index) {
+                    issues.push(wrong_input);
+                }
+                // The recovering transaction continues the chain which has been lost.
+                let lost_tx = payload.prev_tx_chain.and_then(|txid| {
+                    find_tx(&schema, &actual_cfg, &txid)
+                });
+                match lost_tx.map(TxKind::from) {
+                    Some(TxKind::Anchoring(lost_tx)) => {
+                        check_height(&mut issues, &tx, &lost_tx);
+                        tx = lost_tx;
+                    }
+                    Some(_) | None => {
+                        report.funding_tx = Some(funding_tx.id());
+                        break;
+                    }
+                }
+            }
+            TxKind::Other(_) => {
+                issues.push(wrong_input);
+                break;
+            }
+        }
+    }
+
+    // The chain is walked backwards, but the report lists it from the funding transaction.
+    report.anchors.reverse();
+    issues.reverse();
+    report.issues = issues;
+    report
+}
+
+/// Verifies the anchoring chain ending with the `lect` transaction by the given bitcoin
+/// transactions without the `Exonum` node.
+///
+/// The chain is walked back to the funding transaction of the `genesis_cfg` as by the
+/// [`verify_anchoring_chain`](fn.verify_anchoring_chain.html). The addresses of the following
+/// configurations are unknown, so only the first anchoring transaction is checked to pay to
+/// the genesis anchoring address. The anchored block hashes are checked against the given
+/// `block_hashes` by heights, for example, obtained from a trusted blockchain explorer,
+/// the heights absent in them are not checked.
+pub fn verify_anchoring_txs(
+    genesis_cfg: &AnchoringConfig,
+    lect: &btc::TxId,
+    txs: &[BitcoinTx],
+    block_hashes: &BTreeMap<u64, Hash>,
+) -> AnchoringChainReport {
+    let txs = txs.iter()
+        .map(|tx| (tx.id(), tx.clone()))
+        .collect::<HashMap<_, _>>();
+    let genesis_addr = genesis_cfg.redeem_script().1;
+    let genesis_funding = genesis_cfg.funding_tx.as_ref().map(|tx| tx.id());
+
+    let mut report = AnchoringChainReport {
+        funding_tx: None,
+        anchors: Vec::new(),
+        issues: Vec::new(),
+    };
+    let mut tx = match txs.get(lect).cloned().map(TxKind::from) {
+        Some(TxKind::Anchoring(tx)) => tx,
+        Some(TxKind::FundingTx(tx)) => {
+            if Some(tx.id()) != genesis_funding {
+                report.issues.push(AnchoringChainIssue::ForeignFunding {
+                    txid: tx.id(),
+                    expected: genesis_funding,
+                });
+            }
+            report.funding_tx = Some(tx.id());
+            return report;
+        }
+        Some(TxKind::Other(_)) => {
+            report.issues.push(AnchoringChainIssue::UnexpectedLect { txid: *lect });
+            return report;
+        }
+        None => {
+            report.issues.push(AnchoringChainIssue::NoLect);
+            return report;
+        }
+    };
+
+    let mut issues = Vec::new();
+    let mut visited = HashSet::new();
+    loop {
+        let txid = tx.id();
+        if !visited.insert(txid) {
+            break;
+        }
+
+        let payload = tx.payload();
+        if let Some(block_hash) = block_hashes.get(&payload.block_height.0) {
+            if *block_hash != payload.block_hash {
+                issues.push(AnchoringChainIssue::BlockHashMismatch {
+                    txid,
+                    block_height: payload.block_height,
+                    block_hash: payload.block_hash,
+                    expected: Some(*block_hash),
+                });
+            }
+        }
+        if payload.chain_id != genesis_cfg.chain_id {
+            issues.push(AnchoringChainIssue::ForeignChainId {
+                txid,
+                chain_id: payload.chain_id,
+                expected: genesis_cfg.chain_id,
+            });
+        }
+        report.anchors.push(VerifiedAnchor {
+            txid,
+            block_height: payload.block_height,
+            block_hash: payload.block_hash,
+        });
+
+        let prev_txid = tx.prev_hash();
+        let prev_index = tx.input[0].prev_index;
+        let prev_tx = match txs.get(&prev_txid) {
+            Some(prev_tx) => prev_tx.clone(),
+            None => {
+                issues.push(AnchoringChainIssue::Gap { txid, prev_txid });
+                break;
+            }
+        };
+        let wrong_input = AnchoringChainIssue::WrongInput {
+            txid,
+            prev_txid,
+            prev_index,
+        };
+        match TxKind::from(prev_tx) {
+            TxKind::Anchoring(prev_tx) => {
+                if prev_index != 0 {
+                    issues.push(wrong_input);
+                }
+                check_height(&mut issues, &tx, &prev_tx);
+                tx = prev_tx;
+            }
+            TxKind::FundingTx(funding_tx) => {
+                // The recovering transaction continues the chain which has been lost.
+                let lost_tx = payload.prev_tx_chain.and_then(|txid| txs.get(&txid).cloned());
+                if let Some(TxKind::Anchoring(lost_tx)) = lost_tx.map(TxKind::from) {
+                    check_height(&mut issues, &tx, &lost_tx);
+                    tx = lost_tx;
+                    continue;
+                }
+
+                if Some(funding_tx.id()) != genesis_funding {
+                    issues.push(AnchoringChainIssue::ForeignFunding {
+                        txid: funding_tx.id(),
+                        expected: genesis_funding,
+                    });
+                } else if funding_tx.find_out(&genesis_addr) != Some(prev_index) {
+                    issues.push(wrong_input);
+                }
+                let tx_addr = tx.output_address(genesis_cfg.network);
+                if tx_addr != genesis_addr {
+                    issues.push(AnchoringChainIssue::WrongAddress {
+                        txid,
+                        address: tx_addr.to_string(),
+                        expected: vec![genesis_addr.to_string()],
+                    });
+                }
+                report.funding_tx = Some(funding_tx.id());
+                break;
+            }
+            TxKind::Other(_) => {
+                issues.push(wrong_input);
+                break;
+            }
+        }
+    }
+
+    report.anchors.reverse();
+    issues.reverse();
+    report.issues = issues;
+    report
+}
+
+/// Fetches the anchoring chain from the given bitcoin `relay`, for example,
+/// the [`EsploraClient`](../../struct.EsploraClient.html), and verifies it by the
+/// [`verify_anchoring_txs`](fn.verify_anchoring_txs.html).
+///
+/// The chain ends with the latest anchoring transaction among the unspent outputs of the
+/// anchoring address `addr`, which is the address of the actual anchoring configuration.
+pub fn verify_anchoring_chain_by_relay(
+    relay: &BitcoinRelay,
+    genesis_cfg: &AnchoringConfig,
+    addr: &btc::Address,
+    block_hashes: &BTreeMap<u64, Hash>,
+) -> RpcResult<AnchoringChainReport> {
+    let lect = match find_latest_anchoring_tx(relay, addr)? {
+        Some(lect) => lect,
+        None => {
+            let report = AnchoringChainReport {
+                funding_tx: None,
+                anchors: Vec::new(),
+                issues: vec![AnchoringChainIssue::NoLect],
+            };
+            return Ok(report);
+        }
+    };
+
+    let lect_id = lect.id();
+    let mut txs = Vec::new();
+    let mut pending = vec![BitcoinTx::from(lect)];
+    let mut visited = HashSet::new();
+    while let Some(tx) = pending.pop() {
+        if !visited.insert(tx.id()) {
+            continue;
+        }
+        if let TxKind::Anchoring(tx) = TxKind::from(tx.clone()) {
+            let mut prev_txids = vec![tx.prev_hash()];
+            prev_txids.extend(tx.payload().prev_tx_chain);
+            for prev_txid in prev_txids {
+                if let Some(prev_tx) = relay.get_transaction(prev_txid)? {
+                    pending.push(prev_tx);
+                }
+            }
+        }
+        txs.push(tx);
+    }
+    Ok(verify_anchoring_txs(genesis_cfg, &lect_id, &txs, block_hashes))
+}
+
+/// Returns the anchoring transaction with the highest anchored height among the unspent
+/// outputs of the anchoring address `addr`.
+pub fn find_latest_anchoring_tx(
+    relay: &BitcoinRelay,
+    addr: &btc::Address,
+) -> RpcResult<Option<AnchoringTx>> {
+    let lect = relay
+        .unspent_transactions(addr)?
+        .into_iter()
+        .filter_map(|info| match TxKind::from(info.body) {
+            TxKind::Anchoring(tx) => Some(tx),
+            _ => None,
+        })
+        .max_by_key(|tx| tx.payload().block_height.0);
+    Ok(lect)
+}
+
+fn check_height(issues: &mut Vec<AnchoringChainIssue>, tx: &AnchoringTx, prev_tx: &AnchoringTx) {
+    let block_height = tx.payload().block_height;
+    let prev_height = prev_tx.payload().block_height;
+    if block_height < prev_height {
+        issues.push(AnchoringChainIssue::HeightDecreased {
+            txid: tx.id(),
+            block_height,
+            prev_height,
+        });
+    }
+}
+
+// Funding transactions of the configurations may be absent in the `known_txs` table.
+fn find_tx(
+    schema: &AnchoringSchema<&Snapshot>,
+    actual_cfg: &AnchoringConfig,
+    txid: &btc::TxId,
+) -> Option<BitcoinTx> {
+    if let Some(tx) = schema.known_txs().get(txid) {
+        return Some(tx);
+    }
+    let cfgs = vec![
+        Some(schema.genesis_anchoring_config()),
+        Some(actual_cfg.clone()),
+        schema.previous_anchoring_config(),
+        schema.following_anchoring_config(),
+    ];
+    cfgs.into_iter()
+        .filter_map(|cfg| cfg.and_then(|cfg| cfg.funding_tx))
+        .find(|funding_tx| &funding_tx.id() == txid)
+        .map(BitcoinTx::from)
+}
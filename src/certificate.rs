@@ -0,0 +1,459 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notarization certificates of the `Exonum` transactions.
+//!
+//! The certificate bundles everything needed to check that the transaction is included
+//! into the `Exonum` blockchain and anchored to the bitcoin blockchain: the merkle proof
+//! of the transaction in its block, the blocks up to the anchored one, the anchoring
+//! transaction and its SPV proof. It can be stored offline in the JSON or the binary
+//! encoding and verified by the [`verify`](struct.NotarizationCertificate.html#method.verify)
+//! method without the node and the bitcoin backend against the anchoring address
+//! known to the client.
+//!
+//! The binary encoding starts with the `EXOCERT` magic and the format version followed by
+//! the fields in the order of their declaration. The hashes are written as is, the integers
+//! in the little endian order, the lists and the byte strings are prefixed by their `u32`
+//! length. The nodes of the transaction proof are prefixed by the tag of their kind.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+
+use serde_json;
+
+use exonum::blockchain::Block;
+use exonum::crypto::{Hash, HASH_SIZE};
+use exonum::encoding::serialize::{FromHex, ToHex};
+use exonum::storage::{ListProof, StorageValue};
+
+use details::btc::{Address, TxId};
+use details::btc::transactions::{AnchoringTx, TxKind};
+use light::{self, Hash256, ProofError};
+
+/// Version of the certificate format.
+pub const CERTIFICATE_VERSION: u8 = 1;
+
+const CERTIFICATE_MAGIC: &[u8] = b"EXOCERT";
+// Size of the serialized `Exonum` block.
+const BLOCK_SIZE: usize = 112;
+// Maximum depth of the transaction proof, it is enough for any number of the block
+// transactions and bounds the recursion of the decoder.
+const MAX_PROOF_DEPTH: usize = 64;
+
+const PROOF_FULL: u8 = 0;
+const PROOF_LEFT: u8 = 1;
+const PROOF_RIGHT: u8 = 2;
+const PROOF_LEAF: u8 = 3;
+
+/// Error of the certificate decoding or verification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CertificateError {
+    /// The certificate cannot be decoded.
+    Malformed(String),
+    /// The certificate has the unknown format version.
+    UnknownVersion(u8),
+    /// The certificate contains no blocks.
+    NoBlocks,
+    /// The transaction is not included into the first block of the certificate.
+    TxNotIncluded,
+    /// The blocks of the certificate do not form a chain.
+    BrokenBlocks,
+    /// The anchoring transaction does not anchor the latest block of the certificate.
+    PayloadMismatch,
+    /// The anchoring transaction does not pay to the expected anchoring address.
+    AddressMismatch,
+    /// The anchoring transaction or its SPV proof is invalid.
+    Proof(ProofError),
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CertificateError::Malformed(ref msg) => write!(f, "Malformed certificate: {}", msg),
+            CertificateError::UnknownVersion(version) => {
+                write!(f, "Unknown certificate version={}", version)
+            }
+            CertificateError::NoBlocks => write!(f, "Certificate contains no blocks"),
+            CertificateError::TxNotIncluded => {
+                write!(f, "Transaction is not included into the block")
+            }
+            CertificateError::BrokenBlocks => write!(f, "Blocks do not form a chain"),
+            CertificateError::PayloadMismatch => {
+                write!(f, "Anchoring transaction does not anchor the latest block")
+            }
+            CertificateError::AddressMismatch => {
+                write!(f, "Anchoring transaction does not pay to the anchoring address")
+            }
+            CertificateError::Proof(ref e) => write!(f, "Invalid anchoring proof: {:?}", e),
+        }
+    }
+}
+
+impl error::Error for CertificateError {
+    fn description(&self) -> &str {
+        match *self {
+            CertificateError::Malformed(_) => "Malformed",
+            CertificateError::UnknownVersion(_) => "UnknownVersion",
+            CertificateError::NoBlocks => "NoBlocks",
+            CertificateError::TxNotIncluded => "TxNotIncluded",
+            CertificateError::BrokenBlocks => "BrokenBlocks",
+            CertificateError::PayloadMismatch => "PayloadMismatch",
+            CertificateError::AddressMismatch => "AddressMismatch",
+            CertificateError::Proof(_) => "Proof",
+        }
+    }
+}
+
+impl From<ProofError> for CertificateError {
+    fn from(e: ProofError) -> CertificateError {
+        CertificateError::Proof(e)
+    }
+}
+
+/// Self-contained proof that the `Exonum` transaction is anchored to the bitcoin blockchain.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct NotarizationCertificate {
+    /// Version of the certificate format.
+    pub version: u8,
+    /// Hash of the `Exonum` transaction.
+    pub tx_hash: Hash,
+    /// Position of the transaction in its block.
+    pub position: u64,
+    /// Proof of the transaction hash in the merkle tree of the block transactions.
+    pub tx_proof: ListProof<Hash>,
+    /// Blocks from the one that includes the transaction up to the anchored one inclusive.
+    pub blocks: Vec<Block>,
+    /// Anchoring transaction of the latest block.
+    pub anchoring_tx: AnchoringTx,
+    /// Hex-encoded merkle block of the bitcoin block that includes the anchoring transaction.
+    pub merkle_block: String,
+    /// Hex-encoded headers of the bitcoin blocks following the one that includes
+    /// the anchoring transaction.
+    pub headers: Vec<String>,
+}
+
+/// Facts established by the certificate verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedCertificate {
+    /// Height of the block that includes the transaction.
+    pub block_height: u64,
+    /// Height of the anchored block.
+    pub anchored_height: u64,
+    /// `Txid` of the anchoring transaction.
+    pub txid: TxId,
+    /// Hash of the bitcoin block that includes the anchoring transaction
+    /// in the internal byte order.
+    pub bitcoin_block_hash: Hash256,
+    /// Number of the bitcoin blocks proved by the certificate, starting with the one
+    /// that includes the anchoring transaction.
+    pub confirmations: u64,
+}
+
+impl NotarizationCertificate {
+    /// Checks the certificate and returns the facts proved by it.
+    ///
+    /// The anchoring transaction must pay to the `anchoring_addr` which the client trusts,
+    /// otherwise anyone could publish a bitcoin transaction with the same payload.
+    /// For the transfer transactions it is the address of the following configuration.
+    ///
+    /// The certificate does not prove that its bitcoin blocks belong to the best chain,
+    /// so the client should find the `bitcoin_block_hash` in the headers of a trusted
+    /// source and compare the `bits` of the headers with the difficulty it expects.
+    pub fn verify(
+        &self,
+        anchoring_addr: &Address,
+    ) -> Result<VerifiedCertificate, CertificateError> {
+        if self.version != CERTIFICATE_VERSION {
+            return Err(CertificateError::UnknownVersion(self.version));
+        }
+        let (first, last) = match (self.blocks.first(), self.blocks.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(CertificateError::NoBlocks),
+        };
+
+        let entries = self.tx_proof
+            .validate(*first.tx_hash(), u64::from(first.tx_count()))
+            .map_err(|_| CertificateError::TxNotIncluded)?;
+        if entries != vec![(self.position, &self.tx_hash)] {
+            return Err(CertificateError::TxNotIncluded);
+        }
+        for pair in self.blocks.windows(2) {
+            if pair[1].prev_hash() != &pair[0].hash() ||
+                pair[1].height().0 != pair[0].height().0 + 1
+            {
+                return Err(CertificateError::BrokenBlocks);
+            }
+        }
+
+        let raw_tx = self.anchoring_tx.clone().into_bytes();
+        let tx = light::RawTx::parse(&raw_tx)?;
+        let payload = tx.payload()?;
+        if payload.block_height != last.height().0 ||
+            payload.block_hash[..] != last.hash().as_ref()[..]
+        {
+            return Err(CertificateError::PayloadMismatch);
+        }
+        let script_pubkey = anchoring_addr.script_pubkey();
+        if !self.anchoring_tx.output.iter().any(|output| output.script_pubkey == script_pubkey) {
+            return Err(CertificateError::AddressMismatch);
+        }
+
+        let merkle_block = decode_hex(&self.merkle_block)?;
+        let bitcoin_block_hash = light::verify_merkle_block(&merkle_block, &tx.txid())?;
+        let mut prev_hash = bitcoin_block_hash;
+        for header in &self.headers {
+            prev_hash = light::verify_header(&decode_hex(header)?, &prev_hash)?;
+        }
+
+        Ok(VerifiedCertificate {
+            block_height: first.height().0,
+            anchored_height: last.height().0,
+            txid: self.anchoring_tx.id(),
+            bitcoin_block_hash,
+            confirmations: self.headers.len() as u64 + 1,
+        })
+    }
+
+    /// Returns the JSON encoding of the certificate.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Decodes the certificate from the JSON encoding.
+    pub fn from_json(json: &str) -> Result<NotarizationCertificate, CertificateError> {
+        serde_json::from_str(json).map_err(|e| CertificateError::Malformed(e.to_string()))
+    }
+
+    /// Returns the binary encoding of the certificate, it fails only if the merkle block
+    /// or the headers are not valid hex strings.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CertificateError> {
+        let mut buf = CERTIFICATE_MAGIC.to_vec();
+        buf.push(self.version);
+        buf.extend_from_slice(self.tx_hash.as_ref());
+        write_u64(&mut buf, self.position);
+        write_proof(&mut buf, &self.tx_proof);
+        write_u32(&mut buf, self.blocks.len() as u32);
+        for block in &self.blocks {
+            write_bytes(&mut buf, &block.clone().into_bytes());
+        }
+        write_bytes(&mut buf, &self.anchoring_tx.clone().into_bytes());
+        write_bytes(&mut buf, &decode_hex(&self.merkle_block)?);
+        write_u32(&mut buf, self.headers.len() as u32);
+        for header in &self.headers {
+            write_bytes(&mut buf, &decode_hex(header)?);
+        }
+        Ok(buf)
+    }
+
+    /// Decodes the certificate from the binary encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<NotarizationCertificate, CertificateError> {
+        let mut reader = Reader { data: bytes, pos: 0 };
+        if reader.bytes(CERTIFICATE_MAGIC.len())? != CERTIFICATE_MAGIC {
+            return Err(CertificateError::Malformed("Unknown magic".to_string()));
+        }
+        let version = reader.bytes(1)?[0];
+        if version != CERTIFICATE_VERSION {
+            return Err(CertificateError::UnknownVersion(version));
+        }
+        let tx_hash = reader.hash()?;
+        let position = reader.u64()?;
+        let tx_proof = reader.proof(0)?;
+
+        let mut blocks = Vec::new();
+        for _ in 0..reader.u32()? {
+            let block = reader.sized_bytes()?;
+            if block.len() != BLOCK_SIZE {
+                return Err(CertificateError::Malformed("Malformed block".to_string()));
+            }
+            blocks.push(Block::from_bytes(Cow::Borrowed(block)));
+        }
+        let anchoring_tx = match TxKind::from_bytes(reader.sized_bytes()?) {
+            Some(TxKind::Anchoring(tx)) => tx,
+            _ => return Err(CertificateError::Malformed("Malformed anchoring tx".to_string())),
+        };
+        let merkle_block = reader.sized_bytes()?.to_hex();
+        let mut headers = Vec::new();
+        for _ in 0..reader.u32()? {
+            headers.push(reader.sized_bytes()?.to_hex());
+        }
+        if reader.pos != bytes.len() {
+            return Err(CertificateError::Malformed("Trailing bytes".to_string()));
+        }
+
+        Ok(NotarizationCertificate {
+            version,
+            tx_hash,
+            position,
+            tx_proof,
+            blocks,
+            anchoring_tx,
+            merkle_block,
+            headers,
+        })
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, CertificateError> {
+    Vec::<u8>::from_hex(hex).map_err(|e| CertificateError::Malformed(e.to_string()))
+}
+
+fn write_u32(buf: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        buf.push((value >> (8 * i)) as u8);
+    }
+}
+
+fn write_u64(buf: &mut Vec<u8>, value: u64) {
+    write_u32(buf, value as u32);
+    write_u32(buf, (value >> 32) as u32);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(buf, bytes.len() as u32);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_proof(buf: &mut Vec<u8>, proof: &ListProof<Hash>) {
+    match *proof {
+        ListProof::Full(ref left, ref right) => {
+            buf.push(PROOF_FULL);
+            write_proof(buf, left);
+            write_proof(buf, right);
+        }
+        ListProof::Left(ref left, ref right) => {
+            buf.push(PROOF_LEFT);
+            write_proof(buf, left);
+            match *right {
+                Some(ref hash) => {
+                    buf.push(1);
+                    buf.extend_from_slice(hash.as_ref());
+                }
+                None => buf.push(0),
+            }
+        }
+        ListProof::Right(ref left, ref right) => {
+            buf.push(PROOF_RIGHT);
+            buf.extend_from_slice(left.as_ref());
+            write_proof(buf, right);
+        }
+        ListProof::Leaf(ref value) => {
+            buf.push(PROOF_LEAF);
+            buf.extend_from_slice(value.as_ref());
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CertificateError> {
+        if self.data.len() - self.pos < len {
+            return Err(CertificateError::Malformed("Unexpected end".to_string()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, CertificateError> {
+        let bytes = self.bytes(4)?;
+        Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | u32::from(byte)))
+    }
+
+    fn u64(&mut self) -> Result<u64, CertificateError> {
+        let low = self.u32()?;
+        let high = self.u32()?;
+        Ok(u64::from(high) << 32 | u64::from(low))
+    }
+
+    fn sized_bytes(&mut self) -> Result<&'a [u8], CertificateError> {
+        let len = self.u32()?;
+        self.bytes(len as usize)
+    }
+
+    fn hash(&mut self) -> Result<Hash, CertificateError> {
+        Ok(Hash::from_slice(self.bytes(HASH_SIZE)?).unwrap())
+    }
+
+    fn proof(&mut self, depth: usize) -> Result<ListProof<Hash>, CertificateError> {
+        if depth > MAX_PROOF_DEPTH {
+            return Err(CertificateError::Malformed("Too deep proof".to_string()));
+        }
+        let proof = match self.bytes(1)?[0] {
+            PROOF_FULL => {
+                let left = self.proof(depth + 1)?;
+                let right = self.proof(depth + 1)?;
+                ListProof::Full(Box::new(left), Box::new(right))
+            }
+            PROOF_LEFT => {
+                let left = self.proof(depth + 1)?;
+                let right = match self.bytes(1)?[0] {
+                    0 => None,
+                    1 => Some(self.hash()?),
+                    _ => return Err(CertificateError::Malformed("Malformed proof".to_string())),
+                };
+                ListProof::Left(Box::new(left), right)
+            }
+            PROOF_RIGHT => {
+                let left = self.hash()?;
+                let right = self.proof(depth + 1)?;
+                ListProof::Right(left, Box::new(right))
+            }
+            PROOF_LEAF => ListProof::Leaf(self.hash()?),
+            _ => return Err(CertificateError::Malformed("Malformed proof".to_string())),
+        };
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::crypto::hash;
+    use exonum::storage::{Database, MemoryDB, ProofListIndex};
+
+    use super::*;
+
+    #[test]
+    fn test_certificate_proof_encoding() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut index = ProofListIndex::new("txs", &mut fork);
+        for i in 0..5 {
+            index.push(hash(&[i]));
+        }
+        for position in 0..5 {
+            let proof = index.get_proof(position);
+            let mut buf = Vec::new();
+            write_proof(&mut buf, &proof);
+            let mut reader = Reader { data: &buf, pos: 0 };
+            assert_eq!(reader.proof(0).unwrap(), proof);
+            assert_eq!(reader.pos, buf.len());
+
+            buf.pop();
+            let mut reader = Reader { data: &buf, pos: 0 };
+            assert!(reader.proof(0).is_err());
+        }
+
+        let buf = vec![PROOF_FULL; MAX_PROOF_DEPTH + 2];
+        let mut reader = Reader { data: &buf, pos: 0 };
+        assert_eq!(
+            reader.proof(0),
+            Err(CertificateError::Malformed("Too deep proof".to_string()))
+        );
+    }
+}
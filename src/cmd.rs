@@ -22,7 +22,6 @@ use std::str::FromStr;
 
 use toml::Value;
 use bitcoin::util::base58::ToBase58;
-use bitcoin::network::constants::Network;
 
 use exonum::helpers::fabric::{AbstractConfig, Argument, CommandExtension, CommandName,
                               CommonConfigTemplate, Context, NodePublicConfig, ServiceFactory};
@@ -75,6 +74,38 @@ impl CommandExtension for GenerateNodeConfig {
                 "anchoring-password",
                 false
             ),
+            Argument::new_named(
+                "ANCHORING_RPC_COOKIE_FILE",
+                false,
+                "Path to the bitcoind cookie file used instead of the user and password.",
+                None,
+                "anchoring-cookie-file",
+                false
+            ),
+            Argument::new_named(
+                "ANCHORING_RPC_CA_CERTIFICATE",
+                false,
+                "Path to the certificate of the authority of the bitcoind https endpoint.",
+                None,
+                "anchoring-ca-certificate",
+                false
+            ),
+            Argument::new_named(
+                "ANCHORING_RPC_WALLET",
+                false,
+                "Name of the dedicated watch-only bitcoind wallet for the anchoring addresses.",
+                None,
+                "anchoring-wallet",
+                false
+            ),
+            Argument::new_named(
+                "ANCHORING_RPC_RESCAN_HEIGHT",
+                false,
+                "Height from which bitcoind rescans the blockchain after the address import.",
+                None,
+                "anchoring-rescan-height",
+                false
+            ),
             Argument::new_named(
                 "ANCHORING_OBSERVER_CHECK_INTERVAL",
                 false,
@@ -93,6 +124,10 @@ impl CommandExtension for GenerateNodeConfig {
         );
         let user = context.arg("ANCHORING_RPC_USER").ok();
         let passwd = context.arg("ANCHORING_RPC_PASSWD").ok();
+        let cookie_file = context.arg("ANCHORING_RPC_COOKIE_FILE").ok();
+        let ca_certificate = context.arg("ANCHORING_RPC_CA_CERTIFICATE").ok();
+        let wallet = context.arg("ANCHORING_RPC_WALLET").ok();
+        let rescan_height = context.arg("ANCHORING_RPC_RESCAN_HEIGHT").ok();
         let observer_check_interval = context.arg("ANCHORING_OBSERVER_CHECK_INTERVAL").ok();
 
         let config: CommonConfigTemplate = context.get("common_config").unwrap();
@@ -104,13 +139,9 @@ impl CommandExtension for GenerateNodeConfig {
             .clone()
             .try_into()
             .unwrap();
-        let network = match network.as_str() {
-            "testnet" => Network::Testnet,
-            "bitcoin" => Network::Bitcoin,
-            _ => panic!("Wrong network type"),
-        };
+        let network: btc::Chain = network.parse().expect("Wrong network type");
 
-        let (p, s) = gen_btc_keypair(network);
+        let (p, s) = gen_btc_keypair(network.base58_network());
         let mut services_public_configs: BTreeMap<String, Value> =
             context.get("services_public_configs").unwrap_or_default();
         services_public_configs.extend(
@@ -126,6 +157,11 @@ impl CommandExtension for GenerateNodeConfig {
             host: host,
             username: user,
             password: passwd,
+            cookie_file,
+            ca_certificate,
+            wallet,
+            rescan_height,
+            ..Default::default()
         };
         let observer_config = {
             let mut observer_config = AnchoringObserverConfig::default();
@@ -181,11 +217,19 @@ impl CommandExtension for GenerateCommonConfig {
             Argument::new_named(
                 "ANCHORING_UTXO_CONFIRMATIONS",
                 false,
-                "The minimum number of confirmations for anchoring transactions",
+                "The minimum number of confirmations for funding transactions",
                 None,
                 "anchoring-utxo-confirmations",
                 false
             ),
+            Argument::new_named(
+                "ANCHORING_ANCHOR_CONFIRMATIONS",
+                false,
+                "The minimum number of confirmations for anchoring transactions",
+                None,
+                "anchoring-anchor-confirmations",
+                false
+            ),
             Argument::new_named(
                 "ANCHORING_FEE",
                 true,
@@ -194,7 +238,27 @@ impl CommandExtension for GenerateCommonConfig {
                 "anchoring-fee",
                 false
             ),
-            Argument::new_positional("NETWORK", true, "Anchoring network name."),
+            Argument::new_named(
+                "ANCHORING_THRESHOLD",
+                false,
+                "The number of the signatures required to spend the anchoring funds",
+                None,
+                "anchoring-threshold",
+                false
+            ),
+            Argument::new_named(
+                "ANCHORING_SCRIPT_TYPE",
+                false,
+                "The type of the anchoring address: p2sh, p2sh_p2wsh or p2wsh",
+                None,
+                "anchoring-script-type",
+                false
+            ),
+            Argument::new_positional(
+                "NETWORK",
+                true,
+                "Anchoring network name: bitcoin, testnet, signet or regtest.",
+            ),
         ]
     }
 
@@ -203,6 +267,9 @@ impl CommandExtension for GenerateCommonConfig {
         let anchoring_utxo_confirmations: u64 = context
             .arg::<u64>("ANCHORING_UTXO_CONFIRMATIONS")
             .unwrap_or(5);
+        let anchoring_anchor_confirmations = context
+            .arg::<u64>("ANCHORING_ANCHOR_CONFIRMATIONS")
+            .ok();
         let fee: u64 = context.arg::<u64>("ANCHORING_FEE").expect(
             "Expected `ANCHORING_FEE` \
              in cmd.",
@@ -210,6 +277,11 @@ impl CommandExtension for GenerateCommonConfig {
         let network = context.arg::<String>("NETWORK").expect(
             "No network name found.",
         );
+        let threshold = context.arg::<u8>("ANCHORING_THRESHOLD").ok();
+        let script_type = context.arg::<String>("ANCHORING_SCRIPT_TYPE").unwrap_or_else(
+            |_| "p2sh".to_owned(),
+        );
+        btc::ScriptType::from_str(&script_type)?;
 
         let mut values: BTreeMap<String, Value> = context.get("services_config").expect(
             "Expected services_config \
@@ -231,8 +303,24 @@ impl CommandExtension for GenerateCommonConfig {
                     "anchoring_network".to_owned(),
                     Value::try_from(network).unwrap()
                 ),
+                (
+                    "anchoring_script_type".to_owned(),
+                    Value::try_from(script_type).unwrap()
+                ),
             ].into_iter(),
         );
+        if let Some(anchor_confirmations) = anchoring_anchor_confirmations {
+            values.insert(
+                "anchoring_anchor_confirmations".to_owned(),
+                Value::try_from(anchor_confirmations).unwrap(),
+            );
+        }
+        if let Some(threshold) = threshold {
+            values.insert(
+                "anchoring_threshold".to_owned(),
+                Value::try_from(threshold).unwrap(),
+            );
+        }
         context.set("services_config", values);
         Ok(context)
     }
@@ -305,6 +393,13 @@ impl CommandExtension for Finalize {
             .expect("Anchoring utxo confirmations not fount")
             .clone()
             .try_into()?;
+        let anchor_confirmations = common_config.services_config.get(
+            "anchoring_anchor_confirmations",
+        );
+        let anchor_confirmations: Option<u64> = match anchor_confirmations {
+            Some(value) => Some(value.clone().try_into()?),
+            None => None,
+        };
         let frequency: u64 = common_config
             .services_config
             .get("anchoring_frequency")
@@ -317,12 +412,18 @@ impl CommandExtension for Finalize {
             .expect("Anchoring fee not fount")
             .clone()
             .try_into()?;
-
-        let network = match network.as_str() {
-            "testnet" => Network::Testnet,
-            "bitcoin" => Network::Bitcoin,
-            _ => panic!("Wrong network type"),
+        let threshold = common_config.services_config.get("anchoring_threshold");
+        let threshold: Option<u8> = match threshold {
+            Some(value) => Some(value.clone().try_into()?),
+            None => None,
         };
+        let script_type = common_config.services_config.get("anchoring_script_type");
+        let script_type = match script_type {
+            Some(value) => btc::ScriptType::from_str(&value.clone().try_into::<String>()?)?,
+            None => btc::ScriptType::P2sh,
+        };
+
+        let network: btc::Chain = network.parse().expect("Wrong network type");
 
         let priv_key: PrivateKey = PrivateKey::from_base58check(&sec_key).unwrap();
         //TODO: validate config keys
@@ -343,16 +444,22 @@ impl CommandExtension for Finalize {
         let mut anchoring_config = AnchoringNodeConfig::new(Some(rpc));
         anchoring_config.observer = observer;
 
-        let majority_count = ::majority_count(public_config_list.len() as u8);
-        let address = btc::RedeemScript::from_pubkeys(&pub_keys, majority_count)
-            .compressed(network)
-            .to_address(network);
+        // The address is derived by the same rules as the one of the genesis config.
+        let mut genesis_cfg = AnchoringConfig {
+            network,
+            anchoring_keys: pub_keys,
+            threshold,
+            script_type,
+            ..Default::default()
+        };
+        genesis_cfg.check_threshold()?;
+        let (_, address) = genesis_cfg.redeem_script();
 
-        let mut genesis_cfg = if let Some(total_funds) = create_funding_tx_with_amount {
+        let tx = if let Some(total_funds) = create_funding_tx_with_amount {
             client.watch_address(&address, false).unwrap();
             let tx = client.send_to_address(&address, total_funds).unwrap();
             println!("Created funding tx with txid {}", tx.txid());
-            AnchoringConfig::new_with_funding_tx(network, pub_keys, tx)
+            tx
         } else {
             let txid = funding_txid.expect("Funding txid not fount");
             let txid = btc::TxId::from_str(&txid).expect("Unable to parse funding txid");
@@ -360,17 +467,19 @@ impl CommandExtension for Finalize {
                 "Funding tx with the \
                  given id not fount",
             );
-            AnchoringConfig::new_with_funding_tx(network, pub_keys, tx.into())
+            tx.into()
         };
+        genesis_cfg.funding_tx = Some(tx);
 
         anchoring_config.private_keys.insert(
-            address.to_base58check(),
+            address.to_string(),
             priv_key.clone(),
         );
 
         genesis_cfg.fee = fee;
         genesis_cfg.frequency = frequency;
         genesis_cfg.utxo_confirmations = utxo_confirmations;
+        genesis_cfg.anchor_confirmations = anchor_confirmations;
 
         node_config.services_configs.insert(
             "anchoring_service".to_owned(),
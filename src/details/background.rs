@@ -0,0 +1,447 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin backend which performs requests on a background thread.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{AnchoringRpcConfig, BitcoinRelay, Error, Result, TxInfo};
+use details::rpc::pending_error;
+
+/// Bitcoin backend that does not block the caller by the read requests to the wrapped backend.
+///
+/// Read requests return the latest received result and enqueue its update, if there is
+/// no result yet the [pending][1] error is returned, so the anchoring handler retries
+/// the current round and consumes the result on the following commits. The results older
+/// than the maximum age are evicted and never returned. The failure of a read request is
+/// returned once by the following same request. The watching requests are enqueued and
+/// performed in the order of their arrival, the failure of such a request is returned
+/// by the following same request. Transactions are sent synchronously, so the caller
+/// knows whether the transaction is accepted by the backend.
+///
+/// [1]: ../rpc/fn.is_pending_error.html
+pub struct BackgroundRelay {
+    config: AnchoringRpcConfig,
+    relay: Arc<Box<BitcoinRelay>>,
+    intents: Mutex<mpsc::Sender<Intent>>,
+    results: Arc<Mutex<Results>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Request {
+    Transaction(String),
+    TransactionInfo(String),
+    UnspentTransactions(String),
+    UnspentOutputs(String),
+    FeeRate(u16),
+}
+
+#[derive(Debug)]
+enum Intent {
+    Read(Request, ReadAction),
+    WatchAddress(btc::Address, bool),
+    WatchDescriptor(btc::Address, String, Option<btc::TxId>),
+}
+
+#[derive(Debug)]
+enum ReadAction {
+    Transaction(btc::TxId),
+    TransactionInfo(btc::TxId),
+    UnspentTransactions(btc::Address),
+    UnspentOutputs(btc::Address),
+    FeeRate(u16),
+}
+
+#[derive(Debug, Clone)]
+enum Response {
+    Transaction(Option<BitcoinTx>),
+    TransactionInfo(Option<TxInfo>),
+    UnspentTransactions(Vec<TxInfo>),
+    UnspentOutputs(Vec<(btc::TxId, u32)>),
+    FeeRate(Option<u64>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Write {
+    WatchAddress(String),
+}
+
+#[derive(Debug)]
+struct Results {
+    max_age: Duration,
+    responses: HashMap<Request, (Response, Instant)>,
+    pending: HashSet<Request>,
+    read_failures: HashMap<Request, Error>,
+    failures: HashMap<Write, Error>,
+}
+
+impl Results {
+    fn new(max_age: Duration) -> Results {
+        Results {
+            max_age,
+            responses: HashMap::new(),
+            pending: HashSet::new(),
+            read_failures: HashMap::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    fn evict_outdated(&mut self) {
+        let max_age = self.max_age;
+        self.responses.retain(
+            |_, &mut (_, received)| received.elapsed() < max_age,
+        );
+    }
+}
+
+/// Default maximum age of the results of the read requests in seconds.
+pub const DEFAULT_MAX_RESPONSE_AGE: u64 = 60;
+
+impl BackgroundRelay {
+    /// Creates a background backend for the given `relay` and spawns its worker thread.
+    pub fn new(relay: Box<BitcoinRelay>) -> BackgroundRelay {
+        Self::with_max_age(relay, Duration::from_secs(DEFAULT_MAX_RESPONSE_AGE))
+    }
+
+    /// Creates a background backend for the given `relay` which returns the results
+    /// of the read requests not older than `max_age` and spawns its worker thread.
+    pub fn with_max_age(relay: Box<BitcoinRelay>, max_age: Duration) -> BackgroundRelay {
+        let (background, receiver) = Self::channel(relay, max_age);
+        let worker_relay = Arc::clone(&background.relay);
+        let worker_results = Arc::clone(&background.results);
+        thread::spawn(move || for intent in receiver {
+            process_intent(&**worker_relay, &worker_results, intent);
+        });
+        background
+    }
+
+    fn channel(
+        relay: Box<BitcoinRelay>,
+        max_age: Duration,
+    ) -> (BackgroundRelay, mpsc::Receiver<Intent>) {
+        let (sender, receiver) = mpsc::channel();
+        let background = BackgroundRelay {
+            config: relay.config(),
+            relay: Arc::new(relay),
+            intents: Mutex::new(sender),
+            results: Arc::new(Mutex::new(Results::new(max_age))),
+        };
+        (background, receiver)
+    }
+
+    fn enqueue(&self, intent: Intent) -> Result<()> {
+        self.intents.lock().unwrap().send(intent).map_err(|_| {
+            Error::NoInformation("Background worker is stopped".to_string())
+        })
+    }
+
+    fn read(&self, request: Request, action: ReadAction) -> Result<Response> {
+        let response = {
+            let mut results = self.results.lock().unwrap();
+            if let Some(e) = results.read_failures.remove(&request) {
+                return Err(e);
+            }
+            results.evict_outdated();
+            let response = results.responses.get(&request).map(
+                |&(ref response, _)| response.clone(),
+            );
+            if !results.pending.insert(request.clone()) {
+                return response.ok_or_else(|| in_progress_error(&request));
+            }
+            response
+        };
+        if let Err(e) = self.enqueue(Intent::Read(request.clone(), action)) {
+            self.results.lock().unwrap().pending.remove(&request);
+            return Err(e);
+        }
+        response.ok_or_else(|| in_progress_error(&request))
+    }
+
+    fn write(&self, write: Write, intent: Intent) -> Result<()> {
+        if let Some(e) = self.results.lock().unwrap().failures.remove(&write) {
+            return Err(e);
+        }
+        self.enqueue(intent)
+    }
+}
+
+impl fmt::Debug for BackgroundRelay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BackgroundRelay")
+            .field("relay", &self.relay)
+            .finish()
+    }
+}
+
+impl BitcoinRelay for BackgroundRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        let request = Request::Transaction(txid.to_string());
+        match self.read(request, ReadAction::Transaction(txid))? {
+            Response::Transaction(tx) => Ok(tx),
+            response => unreachable!("Unexpected response={:?}", response),
+        }
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        let request = Request::TransactionInfo(txid.to_string());
+        match self.read(request, ReadAction::TransactionInfo(txid))? {
+            Response::TransactionInfo(info) => Ok(info),
+            response => unreachable!("Unexpected response={:?}", response),
+        }
+    }
+
+    fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
+        let write = Write::WatchAddress(addr.to_string());
+        self.write(write, Intent::WatchAddress(addr.clone(), rescan))
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        let write = Write::WatchAddress(addr.to_string());
+        let intent = Intent::WatchDescriptor(addr.clone(), descriptor.to_string(), rescan_from);
+        self.write(write, intent)
+    }
+
+    // The anchoring handler relies on the result of the sending to update its lect.
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.relay.send_transaction(tx)
+    }
+
+    fn send_to_address(&self, addr: &btc::Address, satoshis: u64) -> Result<FundingTx> {
+        // Funding transactions are created only by the maintainers tools.
+        self.relay.send_to_address(addr, satoshis)
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        let request = Request::UnspentTransactions(addr.to_string());
+        match self.read(
+            request,
+            ReadAction::UnspentTransactions(addr.clone()),
+        )? {
+            Response::UnspentTransactions(txs) => Ok(txs),
+            response => unreachable!("Unexpected response={:?}", response),
+        }
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let request = Request::UnspentOutputs(addr.to_string());
+        match self.read(request, ReadAction::UnspentOutputs(addr.clone()))? {
+            Response::UnspentOutputs(outputs) => Ok(outputs),
+            response => unreachable!("Unexpected response={:?}", response),
+        }
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        let request = Request::FeeRate(conf_target);
+        match self.read(request, ReadAction::FeeRate(conf_target))? {
+            Response::FeeRate(fee_rate) => Ok(fee_rate),
+            response => unreachable!("Unexpected response={:?}", response),
+        }
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        // The chain is checked only once on the node start.
+        self.relay.chain()
+    }
+
+    // The progress is requested by the api handlers instead of the block commit.
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        self.relay.verification_progress()
+    }
+
+    // Proofs are requested by the api handlers instead of the block commit.
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        self.relay.get_transaction_proof(txid)
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        self.relay.get_block_headers(block_hash, count)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        self.config.clone()
+    }
+}
+
+fn in_progress_error(request: &Request) -> Error {
+    pending_error(format!("{:?}", request))
+}
+
+fn process_intent(relay: &BitcoinRelay, results: &Mutex<Results>, intent: Intent) {
+    match intent {
+        Intent::Read(request, action) => {
+            let response = match action {
+                ReadAction::Transaction(txid) => {
+                    relay.get_transaction(txid).map(Response::Transaction)
+                }
+                ReadAction::TransactionInfo(txid) => {
+                    relay.get_transaction_info(txid).map(Response::TransactionInfo)
+                }
+                ReadAction::UnspentTransactions(addr) => {
+                    relay.unspent_transactions(&addr).map(
+                        Response::UnspentTransactions,
+                    )
+                }
+                ReadAction::UnspentOutputs(addr) => {
+                    relay.unspent_outputs(&addr).map(Response::UnspentOutputs)
+                }
+                ReadAction::FeeRate(conf_target) => {
+                    relay.estimate_fee_rate(conf_target).map(Response::FeeRate)
+                }
+            };
+            let mut results = results.lock().unwrap();
+            results.pending.remove(&request);
+            match response {
+                Ok(response) => {
+                    results.read_failures.remove(&request);
+                    results.responses.insert(request, (response, Instant::now()));
+                }
+                Err(e) => {
+                    warn!("Background request {:?} failed: {}", request, e);
+                    results.read_failures.insert(request, e);
+                }
+            }
+            results.evict_outdated();
+        }
+        Intent::WatchAddress(addr, rescan) => {
+            if let Err(e) = relay.watch_address(&addr, rescan) {
+                error!("Unable to watch address={}: {}", addr.to_string(), e);
+                let write = Write::WatchAddress(addr.to_string());
+                results.lock().unwrap().failures.insert(write, e);
+            }
+        }
+        Intent::WatchDescriptor(addr, descriptor, rescan_from) => {
+            if let Err(e) = relay.watch_descriptor(&addr, &descriptor, rescan_from) {
+                error!("Unable to watch address={}: {}", addr.to_string(), e);
+                let write = Write::WatchAddress(addr.to_string());
+                results.lock().unwrap().failures.insert(write, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use details::btc;
+    use details::mock::{sample_tx, MockRelay};
+    use details::rpc::{is_pending_error, BitcoinRelay};
+
+    use super::{process_intent, BackgroundRelay, Intent};
+
+    // Performs the enqueued intents on the current thread instead of the worker one.
+    fn process_intents(relay: &BackgroundRelay, intents: &mpsc::Receiver<Intent>) {
+        for intent in intents.try_iter() {
+            process_intent(&**relay.relay, &relay.results, intent);
+        }
+    }
+
+    #[test]
+    fn test_background_relay() {
+        let tx = sample_tx();
+        let mock = MockRelay::new("mock");
+        let (relay, intents) =
+            BackgroundRelay::channel(Box::new(mock.clone()), Duration::from_secs(60));
+
+        // The result is not ready yet.
+        match relay.get_transaction(tx.id()) {
+            Err(ref e) if is_pending_error(e) => {}
+            other => panic!("Unexpected result={:?}", other),
+        }
+
+        relay.send_transaction(tx.clone()).unwrap();
+        assert_eq!(mock.transactions(), vec![tx.clone()]);
+        process_intents(&relay, &intents);
+        // The result has been requested before the transaction is sent.
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), None);
+
+        process_intents(&relay, &intents);
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx));
+    }
+
+    #[test]
+    fn test_background_relay_outdated_results() {
+        let tx = sample_tx();
+        let mock = MockRelay::new("mock").with_tx(tx.clone());
+        let (relay, intents) = BackgroundRelay::channel(Box::new(mock), Duration::from_secs(0));
+
+        assert!(relay.get_transaction(tx.id()).is_err());
+        process_intents(&relay, &intents);
+        // The received result is already outdated.
+        assert!(relay.results.lock().unwrap().responses.is_empty());
+        match relay.get_transaction(tx.id()) {
+            Err(ref e) if is_pending_error(e) => {}
+            other => panic!("Unexpected result={:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_background_relay_send_failure() {
+        let tx = sample_tx();
+        let mock = MockRelay::new("mock").with_tx(tx.clone());
+        let (relay, _intents) = BackgroundRelay::channel(Box::new(mock), Duration::from_secs(60));
+
+        // The failure is returned by the sending itself.
+        let e = relay.send_transaction(tx).unwrap_err();
+        assert!(e.to_string().contains("already in block chain"));
+    }
+
+    #[test]
+    fn test_background_relay_read_failure() {
+        let tx = sample_tx();
+        let mock = MockRelay::new("mock").with_height(None);
+        let (relay, intents) = BackgroundRelay::channel(Box::new(mock), Duration::from_secs(60));
+
+        assert!(relay.get_transaction(tx.id()).is_err());
+        process_intents(&relay, &intents);
+        // The failure of the previous request is returned once by the following one.
+        match relay.get_transaction(tx.id()) {
+            Err(ref e) if !is_pending_error(e) => {}
+            other => panic!("Unexpected result={:?}", other),
+        }
+        match relay.get_transaction(tx.id()) {
+            Err(ref e) if is_pending_error(e) => {}
+            other => panic!("Unexpected result={:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_background_relay_stopped_worker() {
+        let tx = sample_tx();
+        let relay = Box::new(MockRelay::new("mock"));
+        let (relay, intents) = BackgroundRelay::channel(relay, Duration::from_secs(60));
+        drop(intents);
+
+        match relay.get_transaction(tx.id()) {
+            Err(ref e) if !is_pending_error(e) => {}
+            other => panic!("Unexpected result={:?}", other),
+        }
+        assert!(relay.results.lock().unwrap().pending.is_empty());
+        let addr = "2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA".parse::<btc::Address>().unwrap();
+        assert!(relay.watch_address(&addr, false).is_err());
+    }
+}
@@ -12,12 +12,320 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::blockdata::opcodes::All;
+use bitcoin::blockdata::script::{Builder, Instruction, Script};
 use bitcoin::network::constants::Network;
+use bitcoin::util::address::Type;
+use bitcoin::util::base58::{FromBase58, ToBase58};
+
+use exonum::crypto::Hash;
 
+use super::Chain;
 use super::types::{Address, RawAddress, RawScript};
 
 impl Address {
     pub fn from_script(script: &RawScript, network: Network) -> Address {
-        RawAddress::from_script(network, script).into()
+        Address::Base58(RawAddress::from_script(network, script))
+    }
+
+    /// Returns the `script_pubkey` of the outputs which pay to this address.
+    pub fn script_pubkey(&self) -> Script {
+        match *self {
+            Address::Base58(ref raw) => raw.script_pubkey(),
+            Address::Witness { ref script_hash, .. } => {
+                Builder::new()
+                    .push_opcode(All::OP_PUSHBYTES_0)
+                    .push_slice(script_hash.as_ref())
+                    .into_script()
+            }
+        }
+    }
+
+    /// Returns true if this address is the `p2sh` or the native `p2wsh` one.
+    pub fn is_script_hash(&self) -> bool {
+        match *self {
+            Address::Base58(ref raw) => raw.ty == Type::ScriptHash,
+            Address::Witness { .. } => true,
+        }
+    }
+
+    /// Returns true if this address has the format of the given chain.
+    pub fn is_valid_for(&self, chain: Chain) -> bool {
+        match *self {
+            Address::Base58(ref raw) => raw.network == chain.base58_network(),
+            Address::Witness { chain: ref own, .. } => own.bech32_hrp() == chain.bech32_hrp(),
+        }
+    }
+}
+
+/// Returns the script hash of the version 0 witness program `OP_0 <sha256(script)>`,
+/// if the given `script_pubkey` is the native `p2wsh` one.
+pub fn witness_script_hash(script_pubkey: &Script) -> Option<Hash> {
+    let mut instructions = script_pubkey.into_iter();
+    match (instructions.next(), instructions.next(), instructions.next()) {
+        (Some(Instruction::PushBytes(version)), Some(Instruction::PushBytes(bytes)), None)
+            if version.is_empty() && bytes.len() == 32 => Hash::from_slice(bytes),
+        _ => None,
+    }
+}
+
+// The addresses of the `testnet` and `signet` chains are the same.
+impl PartialEq for Address {
+    fn eq(&self, other: &Address) -> bool {
+        match (self, other) {
+            (&Address::Base58(ref raw), &Address::Base58(ref other)) => raw == other,
+            (
+                &Address::Witness {
+                    ref chain,
+                    ref script_hash,
+                },
+                &Address::Witness {
+                    chain: ref other_chain,
+                    script_hash: ref other_hash,
+                },
+            ) => chain.bech32_hrp() == other_chain.bech32_hrp() && script_hash == other_hash,
+            _ => false,
+        }
+    }
+}
+
+impl From<RawAddress> for Address {
+    fn from(raw: RawAddress) -> Address {
+        Address::Base58(raw)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Address::Base58(ref raw) => f.write_str(&raw.to_base58check()),
+            Address::Witness {
+                ref chain,
+                ref script_hash,
+            } => f.write_str(&encode_segwit_address(chain.bech32_hrp(), 0, script_hash.as_ref())),
+        }
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"Address({})\"", self)
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    /// Parses the base58 or the native segwit `p2wsh` address.
+    fn from_str(s: &str) -> Result<Address, String> {
+        if let Some((hrp, version, program)) = decode_segwit_address(s) {
+            let chain = Chain::from_bech32_hrp(&hrp).ok_or_else(|| {
+                format!("Unknown segwit address prefix {}", hrp)
+            })?;
+            return match (version, Hash::from_slice(&program)) {
+                (0, Some(script_hash)) => Ok(Address::Witness { chain, script_hash }),
+                _ => Err(format!("Unsupported segwit address {}", s)),
+            };
+        }
+        RawAddress::from_base58check(s).map(Address::Base58).map_err(
+            |e| {
+                format!("Incorrect address {}: {}", s, e)
+            },
+        )
+    }
+}
+
+impl ::serde::Serialize for Address {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        ser.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for Address {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        struct AddressVisitor;
+
+        impl<'v> ::serde::de::Visitor<'v> for AddressVisitor {
+            type Value = Address;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+                write!(fmt, "Expected base58 or segwit address string")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Address, E>
+            where
+                E: ::serde::de::Error,
+            {
+                s.parse().map_err(::serde::de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_str(AddressVisitor)
+    }
+}
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_GENERATORS: [u32; 5] =
+    [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    let mut chk = 1u32;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x01ff_ffff) << 5) ^ u32::from(*value);
+        for (i, generator) in BECH32_GENERATORS.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= *generator;
+            }
+        }
+    }
+    chk
+}
+
+/// Encodes the witness program to the segwit address with the given human readable part
+/// as described in BIP 173.
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    // Regroups the program bytes into the 5-bit words.
+    let mut data = vec![version];
+    let (mut acc, mut bits) = (0u32, 0);
+    for byte in program {
+        acc = ((acc << 8) | u32::from(*byte)) & 0x1fff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            data.push(((acc >> bits) & 0x1f) as u8);
+        }
+    }
+    if bits > 0 {
+        data.push(((acc << (5 - bits)) & 0x1f) as u8);
+    }
+
+    let mut values = hrp.bytes().map(|b| b >> 5).collect::<Vec<_>>();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 0x1f));
+    values.extend(&data);
+    values.extend(&[0; 6]);
+    let polymod = bech32_polymod(&values) ^ 1;
+    data.extend((0..6).map(|i| ((polymod >> (5 * (5 - i))) & 0x1f) as u8));
+
+    let mut addr = format!("{}1", hrp);
+    addr.extend(data.iter().map(|word| BECH32_CHARSET[*word as usize] as char));
+    addr
+}
+
+/// Decodes the segwit address into the human readable part, the witness version
+/// and the witness program, returns `None` if the address is not the valid BIP 173 one.
+pub fn decode_segwit_address(addr: &str) -> Option<(String, u8, Vec<u8>)> {
+    // Mixed case addresses are invalid.
+    if addr.to_lowercase() != addr && addr.to_uppercase() != addr {
+        return None;
+    }
+    let addr = addr.to_lowercase();
+    let separator = match addr.rfind('1') {
+        Some(separator) if separator > 0 && separator + 7 <= addr.len() => separator,
+        _ => return None,
+    };
+    let (hrp, data) = (&addr[..separator], &addr[separator + 1..]);
+    let mut words = Vec::with_capacity(data.len());
+    for c in data.bytes() {
+        match BECH32_CHARSET.iter().position(|&x| x == c) {
+            Some(word) => words.push(word as u8),
+            None => return None,
+        }
+    }
+
+    let mut values = hrp.bytes().map(|b| b >> 5).collect::<Vec<_>>();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 0x1f));
+    values.extend(&words);
+    if bech32_polymod(&values) != 1 {
+        return None;
+    }
+
+    // Regroups the 5-bit words without the checksum into the program bytes.
+    let words = &words[..words.len() - 6];
+    let (version, words) = match words.split_first() {
+        Some((&version, words)) if version <= 16 => (version, words),
+        _ => return None,
+    };
+    let mut program = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for word in words {
+        acc = ((acc << 5) | u32::from(*word)) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            program.push(((acc >> bits) & 0xff) as u8);
+        }
+    }
+    if bits >= 5 || (acc << (8 - bits)) & 0xff != 0 {
+        return None;
+    }
+    if program.len() < 2 || program.len() > 40 {
+        return None;
+    }
+    Some((hrp.to_string(), version, program))
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::encoding::serialize::FromHex;
+
+    use super::{decode_segwit_address, encode_segwit_address};
+
+    // Test vectors from BIP 173.
+    #[test]
+    fn test_encode_segwit_address() {
+        let program = Vec::<u8>::from_hex("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(
+            encode_segwit_address("bc", 0, &program),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+
+        let program = Vec::<u8>::from_hex(
+            "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+        ).unwrap();
+        assert_eq!(
+            encode_segwit_address("tb", 0, &program),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+    }
+
+    #[test]
+    fn test_decode_segwit_address() {
+        let program = Vec::<u8>::from_hex("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(
+            decode_segwit_address("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4"),
+            Some(("bc".to_string(), 0, program))
+        );
+        let addr = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+        let (hrp, version, program) = decode_segwit_address(addr).unwrap();
+        assert_eq!(encode_segwit_address(&hrp, version, &program), addr);
+
+        let invalid = [
+            // Invalid checksum.
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+            // Mixed case.
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sL5k7",
+            // Invalid program length.
+            "bc1rw5uspcuh",
+            "bc10w508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kw5rljs90",
+            // Non-zero padding.
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3pjxtptv",
+            // Empty data section.
+            "bc1gmk9yu",
+        ];
+        for addr in &invalid {
+            assert_eq!(decode_segwit_address(addr), None, "{}", addr);
+        }
     }
 }
@@ -0,0 +1,131 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canonical layout of the anchoring transactions.
+//!
+//! The layout does not depend on the order in which the unspent outputs are given, so
+//! the validators which select the same inputs and the same fee build the same proposal.
+//! This module does not select them: the handler takes the funding inputs and the fee rate
+//! from the blockchain state, the module only fixes the rest of the transaction.
+//!
+//! The canonical anchoring transaction has the version `1`, zero lock time, the same
+//! sequence number in all inputs, the input which continues the anchoring chain first
+//! followed by the funding inputs sorted by their outpoints, and two outputs: the change
+//! output and the payload one, optionally followed by the surplus output of the change policy.
+//! The final anchoring transaction which burns the dust change has only the payload output.
+
+use bitcoin::blockdata::transaction::TxIn;
+use bitcoin::network::serialize::BitcoinHash;
+
+use details::btc::TxId;
+use details::btc::transactions::RawBitcoinTx;
+
+/// Version of the canonical anchoring transaction.
+pub const TX_VERSION: u32 = 1;
+/// Lock time of the canonical anchoring transaction.
+pub const TX_LOCK_TIME: u32 = 0;
+
+/// Sorts the funding inputs of the anchoring transaction and removes duplicates.
+/// The first input continues the anchoring chain and keeps its position.
+pub fn sort_inputs(inputs: &mut Vec<(RawBitcoinTx, u32)>) {
+    if inputs.len() < 2 {
+        return;
+    }
+    let first = inputs.remove(0);
+    let first_key = outpoint_key(TxId::from(first.0.bitcoin_hash()), first.1);
+    inputs.sort_by_key(|&(ref tx, out)| outpoint_key(TxId::from(tx.bitcoin_hash()), out));
+    inputs.dedup_by_key(|&mut (ref tx, out)| outpoint_key(TxId::from(tx.bitcoin_hash()), out));
+    inputs.retain(|&(ref tx, out)| {
+        outpoint_key(TxId::from(tx.bitcoin_hash()), out) != first_key
+    });
+    inputs.insert(0, first);
+}
+
+/// Returns true if the given transaction is in the canonical form.
+pub fn is_canonical(tx: &RawBitcoinTx) -> bool {
+    if tx.version != TX_VERSION || tx.lock_time != TX_LOCK_TIME ||
+        tx.output.is_empty() || tx.output.len() > 3
+    {
+        return false;
+    }
+    let sequence = match tx.input.first() {
+        Some(input) => input.sequence,
+        None => return false,
+    };
+    if tx.input.iter().any(|input| input.sequence != sequence) {
+        return false;
+    }
+    let first_key = input_key(&tx.input[0]);
+    let keys = tx.input[1..].iter().map(input_key).collect::<Vec<_>>();
+    keys.windows(2).all(|pair| pair[0] < pair[1]) && !keys.contains(&first_key)
+}
+
+/// Rounds the fee rate down to the nearest power of two, so the slight changes of the fee
+/// estimation of the validator do not produce new fee rate transactions.
+pub fn round_fee_rate(fee_rate: u64) -> u64 {
+    if fee_rate == 0 {
+        0
+    } else {
+        1 << (63 - fee_rate.leading_zeros())
+    }
+}
+
+fn input_key(input: &TxIn) -> (String, u32) {
+    outpoint_key(TxId::from(input.prev_hash), input.prev_index)
+}
+
+// Outpoints are ordered by the txids in the usual hex form.
+fn outpoint_key(txid: TxId, out: u32) -> (String, u32) {
+    (txid.to_string(), out)
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc::HexValueEx;
+    use details::btc::transactions::RawBitcoinTx;
+
+    use super::{is_canonical, round_fee_rate, sort_inputs};
+
+    #[test]
+    fn test_canonical_inputs_order() {
+        let tx = RawBitcoinTx::from_hex(
+            "01000000019aaf09d7e73a5f9ab394f1358bfb3dbde7b15b983d715f\
+             5c98f369a3f0a288a70000000000ffffffff02b80b00000000000017a914f18eb74087f751109cc9052befd417\
+             7a52c9a30a8700000000000000002c6a2a012800000000000000007fab6f66a0f7a747c820cd01fa30d7bdebd2\
+             6b91c6e03f742abac0b3108134d900000000",
+        ).unwrap();
+        assert!(is_canonical(&tx));
+
+        let mut inputs = vec![(tx.clone(), 1), (tx.clone(), 1), (tx.clone(), 0), (tx.clone(), 1)];
+        sort_inputs(&mut inputs);
+        assert_eq!(
+            inputs.iter().map(|&(_, out)| out).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+
+        let mut unsorted_tx = tx.clone();
+        let mut input = unsorted_tx.input[0].clone();
+        input.prev_index = 2;
+        unsorted_tx.input.push(input.clone());
+        input.prev_index = 1;
+        unsorted_tx.input.push(input);
+        assert!(!is_canonical(&unsorted_tx));
+        unsorted_tx.input.swap(1, 2);
+        assert!(is_canonical(&unsorted_tx));
+
+        assert_eq!(round_fee_rate(0), 0);
+        assert_eq!(round_fee_rate(1), 1);
+        assert_eq!(round_fee_rate(100), 64);
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::network::constants::Network;
+
+/// Bitcoin chain to which the anchoring transactions are published.
+///
+/// The `signet` and `regtest` chains use the same base58 prefixes as the `testnet` one,
+/// but the segwit addresses of the `regtest` chain have their own `bcrt` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chain {
+    /// Bitcoin main chain.
+    Mainnet,
+    /// Bitcoin test chain.
+    Testnet,
+    /// Bitcoin signet chain.
+    Signet,
+    /// Local regression test chain.
+    Regtest,
+}
+
+impl Chain {
+    /// Returns the chain name reported by the `getblockchaininfo` call of `bitcoind`.
+    pub fn bitcoind_name(&self) -> &'static str {
+        match *self {
+            Chain::Mainnet => "main",
+            Chain::Testnet => "test",
+            Chain::Signet => "signet",
+            Chain::Regtest => "regtest",
+        }
+    }
+
+    /// Returns the network which defines the base58 prefixes of the addresses and keys.
+    pub fn base58_network(&self) -> Network {
+        match *self {
+            Chain::Mainnet => Network::Bitcoin,
+            Chain::Testnet | Chain::Signet | Chain::Regtest => Network::Testnet,
+        }
+    }
+
+    /// Returns the human readable part of the segwit addresses.
+    pub fn bech32_hrp(&self) -> &'static str {
+        match *self {
+            Chain::Mainnet => "bc",
+            Chain::Testnet | Chain::Signet => "tb",
+            Chain::Regtest => "bcrt",
+        }
+    }
+
+    /// Returns the chain with the given human readable part of the segwit addresses,
+    /// the `tb` one is taken for the `testnet` chain.
+    pub fn from_bech32_hrp(hrp: &str) -> Option<Chain> {
+        match hrp {
+            "bc" => Some(Chain::Mainnet),
+            "tb" => Some(Chain::Testnet),
+            "bcrt" => Some(Chain::Regtest),
+            _ => None,
+        }
+    }
+}
+
+impl From<Network> for Chain {
+    fn from(network: Network) -> Chain {
+        match network {
+            Network::Bitcoin => Chain::Mainnet,
+            Network::Testnet => Chain::Testnet,
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The main chain is named `bitcoin` for compatibility with the existing configs.
+        let name = match *self {
+            Chain::Mainnet => "bitcoin",
+            Chain::Testnet => "testnet",
+            Chain::Signet => "signet",
+            Chain::Regtest => "regtest",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Chain {
+    type Err = String;
+
+    /// Parses the chain from its config name or from the name reported by `bitcoind`.
+    fn from_str(s: &str) -> Result<Chain, String> {
+        match s {
+            "bitcoin" | "mainnet" | "main" => Ok(Chain::Mainnet),
+            "testnet" | "test" => Ok(Chain::Testnet),
+            "signet" => Ok(Chain::Signet),
+            "regtest" => Ok(Chain::Regtest),
+            other => Err(format!("Unknown bitcoin chain {}", other)),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output script descriptors of the anchoring addresses.
+//!
+//! See [BIP 380](https://github.com/bitcoin/bips/blob/master/bip-0380.mediawiki)
+//! for the descriptors language and the checksum algorithm.
+
+use secp256k1::Secp256k1;
+
+use exonum::encoding::serialize::encode_hex;
+
+use super::{Address, PublicKey, ScriptType};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~\
+                             ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [
+    0xf5_dee5_1989,
+    0xa9_fdca_3312,
+    0x1b_ab10_e32d,
+    0x37_06b1_677a,
+    0x64_4d62_6ffd,
+];
+
+/// Returns the descriptor of the multisig address with the given keys:
+/// `sh(multi(m,keys))`, `sh(wsh(multi(m,keys)))` for the nested segwit address
+/// or `wsh(multi(m,keys))` for the native one.
+///
+/// The keys keep their order, since the multisig script is not sorted.
+pub fn multisig<'a, I>(majority_count: u8, keys: I, script_type: ScriptType) -> String
+where
+    I: IntoIterator<Item = &'a PublicKey>,
+{
+    let context = Secp256k1::without_caps();
+    let mut multi = format!("multi({}", majority_count);
+    for key in keys {
+        multi.push(',');
+        multi.push_str(&encode_hex(key.serialize_vec(&context, true).as_slice()));
+    }
+    multi.push(')');
+    let desc = match script_type {
+        ScriptType::P2sh => format!("sh({})", multi),
+        ScriptType::P2shP2wsh => format!("sh(wsh({}))", multi),
+        ScriptType::P2wsh => format!("wsh({})", multi),
+    };
+    with_checksum(&desc)
+}
+
+/// Returns the `addr(address)` descriptor, which is used for the scripts that
+/// the descriptors language is unable to express, for example, with the recovery branch.
+pub fn address(addr: &Address) -> String {
+    with_checksum(&format!("addr({})", addr.to_string()))
+}
+
+/// Appends the checksum to the descriptor.
+///
+/// # Panics
+///
+/// If the descriptor contains characters beyond the descriptors charset.
+pub fn with_checksum(desc: &str) -> String {
+    let checksum = checksum(desc).expect("Unexpected character in the descriptor");
+    format!("{}#{}", desc, checksum)
+}
+
+/// Computes the checksum of the descriptor or returns `None` if it has
+/// characters beyond the descriptors charset.
+pub fn checksum(desc: &str) -> Option<String> {
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in desc.chars() {
+        let position = match INPUT_CHARSET.find(ch) {
+            Some(position) => position as u64,
+            None => return None,
+        };
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    let checksum = (0..8)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect();
+    Some(checksum)
+}
+
+fn polymod(c: u64, value: u64) -> u64 {
+    let top = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+    for (i, generator) in GENERATOR.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            c ^= *generator;
+        }
+    }
+    c
+}
@@ -0,0 +1,135 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hierarchical deterministic anchoring keys.
+//!
+//! See [BIP 32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki).
+
+use bitcoin::util::base58::FromBase58;
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
+use secp256k1::Secp256k1;
+
+use super::{PrivateKey, PublicKey};
+
+/// Number of the derivation indexes which are checked for the anchoring key of the validator.
+pub const HD_KEY_LOOKAHEAD: u32 = 100;
+
+/// Extended key from which the anchoring keys of the validator are derived.
+///
+/// The anchoring key of the validator is the normal child `path/index` of the extended key.
+/// The index is incremented on each change of the validators set, so only the extended key
+/// has to be backed up instead of the keys of each anchoring address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HdKeyConfig {
+    /// Base58 encoded extended private key, or the extended public key
+    /// if the node only derives the public keys.
+    pub key: String,
+    /// Derivation path of the parent of the anchoring keys, for example `m/45'/0`.
+    /// The hardened indexes are available only for the extended private key.
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+enum ExtendedKey {
+    Private(ExtendedPrivKey),
+    Public(ExtendedPubKey),
+}
+
+impl HdKeyConfig {
+    /// Returns the anchoring public key with the given derivation `index`.
+    pub fn public_key(&self, index: u32) -> Result<PublicKey, String> {
+        let context = Secp256k1::new();
+        match self.derive(index)? {
+            ExtendedKey::Private(key) => {
+                let key = ExtendedPubKey::from_private(&context, &key);
+                Ok(PublicKey::from(key.public_key))
+            }
+            ExtendedKey::Public(key) => Ok(PublicKey::from(key.public_key)),
+        }
+    }
+
+    /// Returns the anchoring private key with the given derivation `index` or `None`
+    /// if this is the extended public key.
+    pub fn private_key(&self, index: u32) -> Result<Option<PrivateKey>, String> {
+        match self.derive(index)? {
+            ExtendedKey::Private(key) => {
+                Ok(Some(PrivateKey::from_key(key.network, key.secret_key, true)))
+            }
+            ExtendedKey::Public(_) => Ok(None),
+        }
+    }
+
+    /// Returns the lowest derivation index below the `HD_KEY_LOOKAHEAD` of the key
+    /// which is one of the given anchoring `keys`.
+    pub fn find_index(&self, keys: &[PublicKey]) -> Option<u32> {
+        (0..HD_KEY_LOOKAHEAD).find(|index| match self.public_key(*index) {
+            Ok(key) => keys.contains(&key),
+            Err(_) => false,
+        })
+    }
+
+    // The children are derived from the parent key at the path.
+    fn derive(&self, index: u32) -> Result<ExtendedKey, String> {
+        let context = Secp256k1::new();
+        let mut path = parse_path(&self.path)?;
+        path.push(ChildNumber::Normal(index));
+
+        if let Ok(key) = ExtendedPrivKey::from_base58check(&self.key) {
+            return ExtendedPrivKey::from_path(&context, &key, &path)
+                .map(ExtendedKey::Private)
+                .map_err(|e| format!("Unable to derive the private key: {:?}", e));
+        }
+        let mut key = ExtendedPubKey::from_base58check(&self.key).map_err(|e| {
+            format!("Incorrect extended key: {:?}", e)
+        })?;
+        for child in path {
+            key = key.ckd_pub(&context, child).map_err(|e| {
+                format!("Unable to derive the public key: {:?}", e)
+            })?;
+        }
+        Ok(ExtendedKey::Public(key))
+    }
+}
+
+/// Parses the derivation path like `m/45'/0/1h`.
+pub fn parse_path(path: &str) -> Result<Vec<ChildNumber>, String> {
+    let mut parts = path.split('/');
+    if parts.next() != Some("m") {
+        return Err(format!("Derivation path {} does not start with `m`", path));
+    }
+    parts
+        .map(|part| {
+            let (index, hardened) = if part.ends_with('\'') || part.ends_with('h') {
+                (&part[..part.len() - 1], true)
+            } else {
+                (part, false)
+            };
+            let index = index.parse::<u32>().map_err(|e| {
+                format!("Incorrect index {} in the derivation path: {}", part, e)
+            })?;
+            if index >= 1 << 31 {
+                return Err(format!("Index {} in the derivation path is too large", part));
+            }
+            if hardened {
+                Ok(ChildNumber::Hardened(index))
+            } else {
+                Ok(ChildNumber::Normal(index))
+            }
+        })
+        .collect()
+}
+
+fn default_path() -> String {
+    "m".to_string()
+}
@@ -19,7 +19,15 @@ mod redeem_script;
 mod address;
 mod private_key;
 mod public_key;
+mod chain;
+pub mod canonical;
+pub mod descriptor;
+pub mod hd;
 pub mod payload;
+pub mod psbt;
+pub mod selection;
+pub mod sighash;
+pub mod signature;
 pub mod transactions;
 
 use rand;
@@ -34,6 +42,8 @@ use exonum::encoding::serialize::FromHexError;
 /// For test purpose only
 pub use self::types::{Address, PrivateKey, PublicKey, RawTransaction, RedeemScript, Signature,
                       TxId};
+pub use self::redeem_script::{RecoveryBranch, ScriptType, MIN_RECOVERY_TIMEOUT};
+pub use self::chain::Chain;
 pub use bitcoin::network::constants::Network;
 
 #[doc(hidden)]
@@ -14,19 +14,28 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 
-use bitcoin::blockdata::script::{Builder, Instruction, Script};
+use bitcoin::blockdata::script::{Builder, Script};
 use bitcoin::blockdata::opcodes::All;
 
-use exonum::crypto::Hash;
+use exonum::crypto::{self, Hash, HASH_SIZE};
 use exonum::helpers::Height;
 
 use details::btc;
+use details::error::Error;
 
 const PAYLOAD_PREFIX: &[u8] = b"EXONUM";
 const PAYLOAD_HEADER_LEN: usize = 8;
 const PAYLOAD_V1: u8 = 1;
 const PAYLOAD_V1_KIND_REGULAR: u8 = 0;
 const PAYLOAD_V1_KIND_RECOVER: u8 = 1;
+const PAYLOAD_V2: u8 = 2;
+const PAYLOAD_V2_KIND_REGULAR: u8 = 0;
+const PAYLOAD_V2_KIND_COMMITMENTS: u8 = 1;
+const PAYLOAD_V3: u8 = 3;
+const PAYLOAD_V3_KIND_REGULAR: u8 = 0;
+const PAYLOAD_V3_KIND_RECOVER: u8 = 1;
+const PAYLOAD_V3_KIND_STATE: u8 = 2;
+const PAYLOAD_V3_KIND_COMMITMENTS: u8 = 3;
 
 /// Anchoring transaction payload.
 ///
@@ -42,6 +51,44 @@ const PAYLOAD_V1_KIND_RECOVER: u8 = 1;
 /// | 48..80 (Optionally)   | Txid of previous tx chain (only for recover kind) |
 ///
 /// In this way the length of `regular` payload is 48, and for `recover` is 80.
+///
+/// `Payload` v.2 additionally commits to the `state_hash` of the anchored block:
+///
+/// | Position in bytes 	| Description                   	                |
+/// |-------------------	|----------------------------------------------     |
+/// | 0..6               	| ASCII-encoded prefix `EXONUM` 	                |
+/// | 6                 	| Version byte, is 2                     	        |
+/// | 7                     | Payload kind: (0 is regular, 1 is commitments)    |
+/// | 8..16                 | Block height                                      |
+/// | 16..48                | Block hash                                        |
+/// | 48..80                | State hash of the block or the commitments root   |
+///
+/// The `recover` payload has no room for the state hash within the 80 bytes limit of
+/// the standard `OP_RETURN` output, so it is always encoded as v.1.
+///
+/// The `commitments` kind carries the root of the application commitments registered
+/// since the previous anchor, see [`commitments_root`](fn.commitments_root.html),
+/// instead of the state hash. The commitments take precedence over the state hash,
+/// but not over the txid of the previous tx chain.
+///
+/// `Payload` v.3 additionally commits to the identifier of the anchored blockchain,
+/// so the anchors of the different deployments cannot be confused with each other:
+///
+/// | Position in bytes 	| Description                   	                |
+/// |-------------------	|----------------------------------------------     |
+/// | 0..6               	| ASCII-encoded prefix `EXONUM` 	                |
+/// | 6                 	| Version byte, is 3                     	        |
+/// | 7                     | Payload kind: (0 regular, 1 recover, 2 state, 3 commitments) |
+/// | 8..12                 | Chain identifier                                  |
+/// | 12..16                | Block height                                      |
+/// | 16..48                | Block hash                                        |
+/// | 48..80 (Optionally)   | Txid of previous tx chain (only for recover kind) |
+/// | 48..80 (Optionally)   | State hash of the block (only for state kind)     |
+/// | 48..80 (Optionally)   | Commitments root (only for commitments kind)      |
+///
+/// The chain identifier takes the place of the upper half of the block height,
+/// so the payloads of the all kinds fit into the `OP_RETURN` output. The blocks with
+/// the heights which do not fit `u32` cannot be anchored with the chain identifier.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Payload {
     /// Anchored block height
@@ -50,6 +97,25 @@ pub struct Payload {
     pub block_hash: Hash,
     /// `Txid` of previous transactions chain if it has been lost.
     pub prev_tx_chain: Option<btc::TxId>,
+    /// State hash of the anchored block if it is committed by the payload.
+    #[serde(default)]
+    pub state_hash: Option<Hash>,
+    /// Identifier of the anchored blockchain if it is committed by the payload.
+    #[serde(default)]
+    pub chain_id: Option<u32>,
+    /// Root of the application commitments anchored by the payload.
+    #[serde(default)]
+    pub commitments: Option<Hash>,
+}
+
+/// Result of the anchoring payload parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedPayload {
+    /// Payload of one of the known format versions.
+    Known(Payload),
+    /// Anchoring payload of the unknown format version, for example, created
+    /// by a newer version of the anchoring service.
+    UnknownVersion(u8),
 }
 
 #[derive(Debug)]
@@ -58,20 +124,39 @@ enum PayloadV1 {
     Recover(Height, Hash, btc::TxId),
 }
 
+#[derive(Debug)]
+enum PayloadV2 {
+    Regular(Height, Hash, Hash),
+    Commitments(Height, Hash, Hash),
+}
+
+#[derive(Debug)]
+struct PayloadV3 {
+    chain_id: u32,
+    block_height: Height,
+    block_hash: Hash,
+    prev_tx_chain: Option<btc::TxId>,
+    state_hash: Option<Hash>,
+    commitments: Option<Hash>,
+}
+
 #[derive(Debug, Default)]
-pub struct PayloadV1Builder {
+pub struct PayloadBuilder {
     block_hash: Option<Hash>,
     block_height: Option<Height>,
     prev_tx_chain: Option<btc::TxId>,
+    state_hash: Option<Hash>,
+    chain_id: Option<u32>,
+    commitments: Option<Hash>,
 }
 
-pub type PayloadBuilder = PayloadV1Builder;
-
 #[cfg_attr(feature = "cargo-clippy", allow(len_without_is_empty))]
 impl PayloadV1 {
     fn read(bytes: &[u8]) -> Option<PayloadV1> {
-        let kind = bytes[0];
-        let data = &bytes[1..];
+        let (kind, data) = match bytes.split_first() {
+            Some((&kind, data)) => (kind, data),
+            None => return None,
+        };
         match kind {
             PAYLOAD_V1_KIND_REGULAR => {
                 if data.len() != 40 {
@@ -79,8 +164,9 @@ impl PayloadV1 {
                 }
 
                 let block_height = LittleEndian::read_u64(&data[0..8]);
-                let block_hash = Hash::from_slice(&data[8..40]).unwrap();
-                Some(PayloadV1::Regular(Height(block_height), block_hash))
+                Hash::from_slice(&data[8..40]).map(|block_hash| {
+                    PayloadV1::Regular(Height(block_height), block_hash)
+                })
             }
             PAYLOAD_V1_KIND_RECOVER => {
                 if data.len() != 72 {
@@ -88,9 +174,14 @@ impl PayloadV1 {
                 }
 
                 let block_height = LittleEndian::read_u64(&data[0..8]);
-                let block_hash = Hash::from_slice(&data[8..40]).unwrap();
-                let txid = btc::TxId::from_slice(&data[40..72]).unwrap();
-                Some(PayloadV1::Recover(Height(block_height), block_hash, txid))
+                let block_hash = Hash::from_slice(&data[8..40]);
+                let txid = btc::TxId::from_slice(&data[40..72]);
+                match (block_hash, txid) {
+                    (Some(block_hash), Some(txid)) => {
+                        Some(PayloadV1::Recover(Height(block_height), block_hash, txid))
+                    }
+                    _ => None,
+                }
             }
             _ => None,
         }
@@ -131,88 +222,315 @@ impl PayloadV1 {
     }
 
     fn into_script(self) -> Script {
-        let len = self.len() + PAYLOAD_HEADER_LEN;
-        let mut buf = vec![0; len];
-        // Serialize header
-        buf[0..6].copy_from_slice(PAYLOAD_PREFIX);
-        buf[6] = PAYLOAD_V1;
+        let mut buf = vec![0; self.len() + PAYLOAD_HEADER_LEN];
         self.write(&mut buf[7..]);
-        // Build script
-        Builder::new()
-            .push_opcode(All::OP_RETURN)
-            .push_slice(buf.as_ref())
-            .into_script()
+        payload_script(PAYLOAD_V1, buf)
+    }
+}
+
+impl PayloadV2 {
+    fn read(bytes: &[u8]) -> Option<PayloadV2> {
+        let (kind, data) = match bytes.split_first() {
+            Some((&kind, data)) => (kind, data),
+            None => return None,
+        };
+        if data.len() != 72 {
+            return None;
+        }
+
+        let block_height = Height(LittleEndian::read_u64(&data[0..8]));
+        let (block_hash, extra) = match (
+            Hash::from_slice(&data[8..40]),
+            Hash::from_slice(&data[40..72]),
+        ) {
+            (Some(block_hash), Some(extra)) => (block_hash, extra),
+            _ => return None,
+        };
+        match kind {
+            PAYLOAD_V2_KIND_REGULAR => Some(PayloadV2::Regular(block_height, block_hash, extra)),
+            PAYLOAD_V2_KIND_COMMITMENTS => {
+                Some(PayloadV2::Commitments(block_height, block_hash, extra))
+            }
+            _ => None,
+        }
+    }
+
+    fn into_script(self) -> Script {
+        let mut buf = vec![0; 72 + PAYLOAD_HEADER_LEN];
+        let (kind, height, block_hash, extra) = match self {
+            PayloadV2::Regular(height, block_hash, state_hash) => {
+                (PAYLOAD_V2_KIND_REGULAR, height, block_hash, state_hash)
+            }
+            PayloadV2::Commitments(height, block_hash, commitments) => {
+                (PAYLOAD_V2_KIND_COMMITMENTS, height, block_hash, commitments)
+            }
+        };
+        buf[7] = kind;
+        LittleEndian::write_u64(&mut buf[8..16], height.0);
+        buf[16..48].copy_from_slice(block_hash.as_ref());
+        buf[48..80].copy_from_slice(extra.as_ref());
+        payload_script(PAYLOAD_V2, buf)
     }
 }
 
-impl PayloadV1Builder {
-    pub fn new() -> PayloadV1Builder {
-        PayloadV1Builder {
+impl PayloadV3 {
+    fn read(bytes: &[u8]) -> Option<PayloadV3> {
+        let (kind, data) = match bytes.split_first() {
+            Some((&kind, data)) => (kind, data),
+            None => return None,
+        };
+        let len = match kind {
+            PAYLOAD_V3_KIND_REGULAR => 40,
+            PAYLOAD_V3_KIND_RECOVER |
+            PAYLOAD_V3_KIND_STATE |
+            PAYLOAD_V3_KIND_COMMITMENTS => 72,
+            _ => return None,
+        };
+        if data.len() != len {
+            return None;
+        }
+
+        let block_hash = match Hash::from_slice(&data[8..40]) {
+            Some(block_hash) => block_hash,
+            None => return None,
+        };
+        let mut payload = PayloadV3 {
+            chain_id: LittleEndian::read_u32(&data[0..4]),
+            block_height: Height(u64::from(LittleEndian::read_u32(&data[4..8]))),
+            block_hash,
+            prev_tx_chain: None,
+            state_hash: None,
+            commitments: None,
+        };
+        match kind {
+            PAYLOAD_V3_KIND_RECOVER => {
+                payload.prev_tx_chain = btc::TxId::from_slice(&data[40..72]);
+                if payload.prev_tx_chain.is_none() {
+                    return None;
+                }
+            }
+            PAYLOAD_V3_KIND_STATE => {
+                payload.state_hash = Hash::from_slice(&data[40..72]);
+                if payload.state_hash.is_none() {
+                    return None;
+                }
+            }
+            PAYLOAD_V3_KIND_COMMITMENTS => {
+                payload.commitments = Hash::from_slice(&data[40..72]);
+                if payload.commitments.is_none() {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+        Some(payload)
+    }
+
+    // The height is written as the `u32` number, so the payload fits the standard
+    // `OP_RETURN` output together with the chain identifier.
+    fn into_script(self) -> Result<Script, Error> {
+        if self.block_height.0 > u64::from(::std::u32::MAX) {
+            return Err(Error::PayloadHeightOverflow);
+        }
+
+        let (kind, extra) = match (self.prev_tx_chain, self.commitments, self.state_hash) {
+            (Some(txid), _, _) => (PAYLOAD_V3_KIND_RECOVER, Some(txid.as_bytes().to_vec())),
+            (None, Some(root), _) => (PAYLOAD_V3_KIND_COMMITMENTS, Some(root.as_ref().to_vec())),
+            (None, None, Some(hash)) => (PAYLOAD_V3_KIND_STATE, Some(hash.as_ref().to_vec())),
+            (None, None, None) => (PAYLOAD_V3_KIND_REGULAR, None),
+        };
+        let len = if extra.is_some() { 72 } else { 40 };
+        let mut buf = vec![0; len + PAYLOAD_HEADER_LEN];
+        buf[7] = kind;
+        LittleEndian::write_u32(&mut buf[8..12], self.chain_id);
+        LittleEndian::write_u32(&mut buf[12..16], self.block_height.0 as u32);
+        buf[16..48].copy_from_slice(self.block_hash.as_ref());
+        if let Some(extra) = extra {
+            buf[48..80].copy_from_slice(&extra);
+        }
+        Ok(payload_script(PAYLOAD_V3, buf))
+    }
+}
+
+impl PayloadBuilder {
+    pub fn new() -> PayloadBuilder {
+        PayloadBuilder {
             block_hash: None,
             block_height: None,
             prev_tx_chain: None,
+            state_hash: None,
+            chain_id: None,
+            commitments: None,
         }
     }
 
-    pub fn block_height(mut self, height: Height) -> PayloadV1Builder {
+    pub fn block_height(mut self, height: Height) -> PayloadBuilder {
         self.block_height = Some(height);
         self
     }
 
-    pub fn block_hash(mut self, hash: Hash) -> PayloadV1Builder {
+    pub fn block_hash(mut self, hash: Hash) -> PayloadBuilder {
         self.block_hash = Some(hash);
         self
     }
 
-    pub fn prev_tx_chain(mut self, txid: Option<btc::TxId>) -> PayloadV1Builder {
+    pub fn prev_tx_chain(mut self, txid: Option<btc::TxId>) -> PayloadBuilder {
         self.prev_tx_chain = txid;
         self
     }
 
-    pub fn into_script(self) -> Script {
+    pub fn state_hash(mut self, hash: Option<Hash>) -> PayloadBuilder {
+        self.state_hash = hash;
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: Option<u32>) -> PayloadBuilder {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn commitments(mut self, root: Option<Hash>) -> PayloadBuilder {
+        self.commitments = root;
+        self
+    }
+
+    /// Serializes the payload into the `OP_RETURN` script, returns an error if the block
+    /// height does not fit the payload of version 3.
+    pub fn into_script(self) -> Result<Script, Error> {
         let block_height = self.block_height.expect("Block height is not set");
         let block_hash = self.block_hash.expect("Block hash is not set");
 
-        let payload = match self.prev_tx_chain {
-            Some(txid) => PayloadV1::Recover(block_height, block_hash, txid),
-            None => PayloadV1::Regular(block_height, block_hash),
+        if let Some(chain_id) = self.chain_id {
+            let payload = PayloadV3 {
+                chain_id,
+                block_height,
+                block_hash,
+                prev_tx_chain: self.prev_tx_chain,
+                state_hash: self.state_hash,
+                commitments: self.commitments,
+            };
+            return payload.into_script();
+        }
+        let script = match (self.prev_tx_chain, self.commitments, self.state_hash) {
+            (Some(txid), _, _) => {
+                PayloadV1::Recover(block_height, block_hash, txid).into_script()
+            }
+            (None, Some(root), _) => {
+                PayloadV2::Commitments(block_height, block_hash, root).into_script()
+            }
+            (None, None, Some(state_hash)) => {
+                PayloadV2::Regular(block_height, block_hash, state_hash).into_script()
+            }
+            (None, None, None) => PayloadV1::Regular(block_height, block_hash).into_script(),
         };
-        payload.into_script()
+        Ok(script)
     }
 }
 
+// Fills the payload header of the serialized payload and wraps it into the `OP_RETURN` script.
+fn payload_script(version: u8, mut buf: Vec<u8>) -> Script {
+    buf[0..6].copy_from_slice(PAYLOAD_PREFIX);
+    buf[6] = version;
+    Builder::new()
+        .push_opcode(All::OP_RETURN)
+        .push_slice(buf.as_ref())
+        .into_script()
+}
+
 impl Payload {
     /// Tries to extract payload from given `Script`
+    ///
+    /// Payloads of the unknown format versions are skipped, use [`parse`](#method.parse)
+    /// to distinguish them from the other `OP_RETURN` scripts.
     pub fn from_script(script: &Script) -> Option<Payload> {
-        let mut instructions = script.into_iter();
-        instructions
-            .next()
-            .and_then(|instr| if instr == Instruction::Op(All::OP_RETURN) {
-                instructions.next()
-            } else {
-                None
-            })
-            .and_then(|instr| {
-                if let Instruction::PushBytes(bytes) = instr {
-                    if bytes.len() < PAYLOAD_HEADER_LEN {
-                        return None;
-                    }
-                    if &bytes[0..6] != PAYLOAD_PREFIX {
-                        return None;
-                    }
-                    // Parse metadata
-                    let version = bytes[6];
-                    match version {
-                        PAYLOAD_V1 => PayloadV1::read(&bytes[7..]).map(Payload::from),
-                        _ => None,
-                    }
-                } else {
-                    None
+        match Payload::parse(script) {
+            Some(ParsedPayload::Known(payload)) => Some(payload),
+            _ => None,
+        }
+    }
+
+    /// Tries to parse the anchoring payload of any format version from given `Script`.
+    ///
+    /// Returns `None` if the script is not the anchoring payload or if the payload of
+    /// the known version is malformed.
+    pub fn parse(script: &Script) -> Option<ParsedPayload> {
+        Payload::parse_bytes(&script[..])
+    }
+
+    /// Same as [`parse`](#method.parse) but works with the serialized `script_pubkey`.
+    ///
+    /// The scripts are taken from the transactions on the bitcoin blockchain, so the parsing
+    /// never panics and does not allocate for any input.
+    pub fn parse_bytes(script: &[u8]) -> Option<ParsedPayload> {
+        let (bytes, _) = match script.split_first() {
+            Some((&opcode, rest)) if opcode == All::OP_RETURN as u8 => {
+                match split_push_data(rest) {
+                    Some(push) => push,
+                    None => return None,
                 }
-            })
+            }
+            _ => return None,
+        };
+        if bytes.len() < PAYLOAD_HEADER_LEN || &bytes[0..6] != PAYLOAD_PREFIX {
+            return None;
+        }
+        // Parse metadata
+        let version = bytes[6];
+        let payload = match version {
+            PAYLOAD_V1 => PayloadV1::read(&bytes[7..]).map(Payload::from),
+            PAYLOAD_V2 => PayloadV2::read(&bytes[7..]).map(Payload::from),
+            PAYLOAD_V3 => PayloadV3::read(&bytes[7..]).map(Payload::from),
+            _ => return Some(ParsedPayload::UnknownVersion(version)),
+        };
+        payload.map(ParsedPayload::Known)
+    }
+}
+
+/// Returns the root of the Merkle tree of the application `commitments` or `None` if there
+/// are no commitments.
+///
+/// The root of a single commitment is the commitment itself. Otherwise the commitments
+/// are split at the largest power of two less than their number and the root is
+/// `sha256(0x01 || left_root || right_root)`.
+pub fn commitments_root(commitments: &[Hash]) -> Option<Hash> {
+    match commitments.len() {
+        0 => None,
+        1 => Some(commitments[0]),
+        len => {
+            let mut split = 1;
+            while split * 2 < len {
+                split *= 2;
+            }
+            let (left, right) = commitments.split_at(split);
+            let mut node = [0; 1 + 2 * HASH_SIZE];
+            node[0] = 1;
+            node[1..1 + HASH_SIZE].copy_from_slice(commitments_root(left).unwrap().as_ref());
+            node[1 + HASH_SIZE..].copy_from_slice(commitments_root(right).unwrap().as_ref());
+            Some(crypto::hash(&node))
+        }
     }
 }
 
+// Splits the script into the data of its first push instruction and the remaining script.
+// Returns `None` if the script does not start with the complete push instruction.
+fn split_push_data(script: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (opcode, rest) = match script.split_first() {
+        Some((&opcode, rest)) => (opcode, rest),
+        None => return None,
+    };
+    let (len, rest) = match opcode {
+        0x01...0x4b => (opcode as usize, rest),
+        0x4c if !rest.is_empty() => (rest[0] as usize, &rest[1..]),
+        0x4d if rest.len() >= 2 => (LittleEndian::read_u16(&rest[0..2]) as usize, &rest[2..]),
+        0x4e if rest.len() >= 4 => (LittleEndian::read_u32(&rest[0..4]) as usize, &rest[4..]),
+        _ => return None,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
 impl From<PayloadV1> for Payload {
     fn from(v1: PayloadV1) -> Payload {
         match v1 {
@@ -221,6 +539,9 @@ impl From<PayloadV1> for Payload {
                     block_height: height,
                     block_hash: hash,
                     prev_tx_chain: None,
+                    state_hash: None,
+                    chain_id: None,
+                    commitments: None,
                 }
             }
             PayloadV1::Recover(height, hash, txid) => {
@@ -228,23 +549,68 @@ impl From<PayloadV1> for Payload {
                     block_height: height,
                     block_hash: hash,
                     prev_tx_chain: Some(txid),
+                    state_hash: None,
+                    chain_id: None,
+                    commitments: None,
+                }
+            }
+        }
+    }
+}
+
+impl From<PayloadV2> for Payload {
+    fn from(v2: PayloadV2) -> Payload {
+        match v2 {
+            PayloadV2::Regular(height, block_hash, state_hash) => {
+                Payload {
+                    block_height: height,
+                    block_hash,
+                    prev_tx_chain: None,
+                    state_hash: Some(state_hash),
+                    chain_id: None,
+                    commitments: None,
+                }
+            }
+            PayloadV2::Commitments(height, block_hash, commitments) => {
+                Payload {
+                    block_height: height,
+                    block_hash,
+                    prev_tx_chain: None,
+                    state_hash: None,
+                    chain_id: None,
+                    commitments: Some(commitments),
                 }
             }
         }
     }
 }
 
+impl From<PayloadV3> for Payload {
+    fn from(v3: PayloadV3) -> Payload {
+        Payload {
+            block_height: v3.block_height,
+            block_hash: v3.block_hash,
+            prev_tx_chain: v3.prev_tx_chain,
+            state_hash: v3.state_hash,
+            chain_id: Some(v3.chain_id),
+            commitments: v3.commitments,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bitcoin::blockdata::script::Script;
 
-    use exonum::crypto::hash;
+    use exonum::crypto::{hash, Hash};
     use exonum::helpers::Height;
 
+    use light;
+
     use details::btc;
     use details::btc::HexValueEx;
 
-    use super::{Payload, PayloadBuilder};
+    use super::{commitments_root, ParsedPayload, Payload, PayloadBuilder};
 
     #[test]
     fn test_payload_regular_serialize() {
@@ -252,7 +618,8 @@ mod tests {
         let payload_script = PayloadBuilder::new()
             .block_hash(block_hash)
             .block_height(Height(1234))
-            .into_script();
+            .into_script()
+            .unwrap();
 
         assert_eq!(
             payload_script.to_hex(),
@@ -283,7 +650,8 @@ mod tests {
             .block_hash(block_hash)
             .block_height(Height(1234))
             .prev_tx_chain(Some(prev_txid))
-            .into_script();
+            .into_script()
+            .unwrap();
 
         assert_eq!(
             payload_script.to_hex(),
@@ -310,6 +678,41 @@ mod tests {
         assert_eq!(payload.prev_tx_chain, Some(prev_txid));
     }
 
+    #[test]
+    fn test_payload_state_hash_serialize() {
+        let block_hash = hash(&[]);
+        let payload_script = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .state_hash(Some(block_hash))
+            .into_script()
+            .unwrap();
+
+        assert_eq!(
+            payload_script.to_hex(),
+            "6a4c5045584f4e554d0200d204000000000000e3b0c44298fc1c149afbf4c8996fb92427ae41e46\
+                   49b934ca495991b7852b855e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7\
+                   852b855"
+        );
+    }
+
+    #[test]
+    fn test_payload_state_hash_deserialize() {
+        let payload_script = Script::from_hex(
+            "6a4c5045584f4e554d0200d204000000000000e3b0c44298fc1c\
+                                               149afbf4c8996fb92427ae41e4649b934ca495991b7852b855e3\
+                                               b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca49599\
+                                               1b7852b855",
+        ).unwrap();
+
+        let block_hash = hash(&[]);
+        let payload = Payload::from_script(&payload_script).unwrap();
+        assert_eq!(payload.block_hash, block_hash);
+        assert_eq!(payload.block_height, Height(1234));
+        assert_eq!(payload.prev_tx_chain, None);
+        assert_eq!(payload.state_hash, Some(block_hash));
+    }
+
     #[test]
     fn test_payload_incorrect_deserialize() {
         // Payload from old anchoring transaction
@@ -320,6 +723,151 @@ mod tests {
         assert_eq!(Payload::from_script(&payload_script), None);
     }
 
+    #[test]
+    fn test_payload_chain_id_roundtrip() {
+        let block_hash = hash(&[]);
+        let state_hash = hash(&[1]);
+        let prev_txid = btc::TxId::from_slice(block_hash.as_ref()).unwrap();
+
+        let regular = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .chain_id(Some(7))
+            .into_script()
+            .unwrap();
+        assert_eq!(regular.len(), 50);
+        let payload = Payload::from_script(&regular).unwrap();
+        assert_eq!(payload.block_hash, block_hash);
+        assert_eq!(payload.block_height, Height(1234));
+        assert_eq!(payload.chain_id, Some(7));
+        assert_eq!(payload.state_hash, None);
+
+        let recover = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .prev_tx_chain(Some(prev_txid))
+            .chain_id(Some(7))
+            .into_script()
+            .unwrap();
+        assert_eq!(recover.len(), 83);
+        let payload = Payload::from_script(&recover).unwrap();
+        assert_eq!(payload.prev_tx_chain, Some(prev_txid));
+        assert_eq!(payload.chain_id, Some(7));
+
+        let state = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .state_hash(Some(state_hash))
+            .chain_id(Some(7))
+            .into_script()
+            .unwrap();
+        let payload = Payload::from_script(&state).unwrap();
+        assert_eq!(payload.state_hash, Some(state_hash));
+        assert_eq!(payload.prev_tx_chain, None);
+        assert_eq!(payload.chain_id, Some(7));
+    }
+
+    #[test]
+    fn test_payload_commitments_roundtrip() {
+        let block_hash = hash(&[]);
+        let state_hash = hash(&[1]);
+        let root = hash(&[2]);
+        let prev_txid = btc::TxId::from_slice(block_hash.as_ref()).unwrap();
+
+        for chain_id in vec![None, Some(7)] {
+            let script = PayloadBuilder::new()
+                .block_hash(block_hash)
+                .block_height(Height(1234))
+                .state_hash(Some(state_hash))
+                .commitments(Some(root))
+                .chain_id(chain_id)
+                .into_script()
+                .unwrap();
+            assert_eq!(script.len(), 83);
+            let payload = Payload::from_script(&script).unwrap();
+            assert_eq!(payload.block_hash, block_hash);
+            assert_eq!(payload.block_height, Height(1234));
+            assert_eq!(payload.commitments, Some(root));
+            assert_eq!(payload.state_hash, None);
+            assert_eq!(payload.chain_id, chain_id);
+
+            // The recover payload has no room for the commitments.
+            let script = PayloadBuilder::new()
+                .block_hash(block_hash)
+                .block_height(Height(1234))
+                .prev_tx_chain(Some(prev_txid))
+                .commitments(Some(root))
+                .chain_id(chain_id)
+                .into_script()
+                .unwrap();
+            let payload = Payload::from_script(&script).unwrap();
+            assert_eq!(payload.prev_tx_chain, Some(prev_txid));
+            assert_eq!(payload.commitments, None);
+        }
+    }
+
+    #[test]
+    fn test_payload_chain_id_height_overflow() {
+        let builder = PayloadBuilder::new()
+            .block_hash(hash(&[]))
+            .block_height(Height(u64::from(::std::u32::MAX) + 1))
+            .chain_id(Some(7));
+        assert!(builder.into_script().is_err());
+    }
+
+    #[test]
+    fn test_commitments_root() {
+        let leaves = (0..5).map(|i| hash(&[i])).collect::<Vec<_>>();
+        let node = |left: &[u8], right: &[u8]| {
+            let mut bytes = vec![1];
+            bytes.extend_from_slice(left);
+            bytes.extend_from_slice(right);
+            hash(&bytes)
+        };
+
+        assert_eq!(commitments_root(&[]), None);
+        assert_eq!(commitments_root(&leaves[..1]), Some(leaves[0]));
+        let root_2 = node(leaves[0].as_ref(), leaves[1].as_ref());
+        assert_eq!(commitments_root(&leaves[..2]), Some(root_2));
+        let root_3 = node(root_2.as_ref(), leaves[2].as_ref());
+        assert_eq!(commitments_root(&leaves[..3]), Some(root_3));
+        let root_4 = node(
+            root_2.as_ref(),
+            node(leaves[2].as_ref(), leaves[3].as_ref()).as_ref(),
+        );
+        let root_5 = node(root_4.as_ref(), leaves[4].as_ref());
+        assert_eq!(commitments_root(&leaves), Some(root_5));
+    }
+
+    #[test]
+    fn test_commitments_root_light() {
+        let commitments = (0..5).map(|i| [i; 32]).collect::<Vec<_>>();
+        for len in 0..commitments.len() + 1 {
+            let hashes = commitments[..len]
+                .iter()
+                .map(|commitment| Hash::new(*commitment))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                light::commitments_root(&commitments[..len]).map(|root| root.to_vec()),
+                commitments_root(&hashes).map(|root| root.as_ref().to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_payload_unknown_version() {
+        // Regular payload with the version byte set to 255.
+        let payload_script = Script::from_hex(
+            "6a3045584f4e554dff00d204000000000000e3b0c44298fc1c14\
+                                               9afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        ).unwrap();
+        assert_eq!(
+            Payload::parse(&payload_script),
+            Some(ParsedPayload::UnknownVersion(255))
+        );
+        assert_eq!(Payload::from_script(&payload_script), None);
+    }
+
     #[test]
     fn test_payload_non_op_return() {
         // Payload from old anchoring transaction
@@ -327,4 +875,40 @@ mod tests {
             .unwrap();
         assert_eq!(Payload::from_script(&script_pubkey), None);
     }
+
+    #[test]
+    fn test_payload_malformed_bytes() {
+        let block_hash = hash(&[]);
+        let prev_txid = btc::TxId::from_slice(block_hash.as_ref()).unwrap();
+        let scripts = vec![
+            PayloadBuilder::new()
+                .block_hash(block_hash)
+                .block_height(Height(1234))
+                .into_script()
+                .unwrap(),
+            PayloadBuilder::new()
+                .block_hash(block_hash)
+                .block_height(Height(1234))
+                .prev_tx_chain(Some(prev_txid))
+                .chain_id(Some(7))
+                .into_script()
+                .unwrap(),
+        ];
+
+        for script in scripts {
+            let bytes = script[..].to_vec();
+            assert!(Payload::parse_bytes(&bytes).is_some());
+            // Truncated scripts and scripts with the corrupted push length.
+            for len in 0..bytes.len() {
+                assert_eq!(Payload::parse_bytes(&bytes[..len]), None);
+            }
+            for push_len in 0..0xff {
+                let mut corrupted = bytes.clone();
+                corrupted[1] = push_len;
+                Payload::parse_bytes(&corrupted);
+            }
+        }
+        assert_eq!(Payload::parse_bytes(&[0x6a, 0x4e, 0xff]), None);
+        assert_eq!(Payload::parse_bytes(&[0x6a, 0x4d, 0xff, 0xff, 0x00]), None);
+    }
 }
@@ -0,0 +1,425 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partially signed bitcoin transactions according to the [BIP 174][1].
+//!
+//! Anchoring proposals are converted into the `Psbt`, validators' signatures are added
+//! as the partial signatures, and the finalized anchoring transaction is extracted from it.
+//! The binary and `base64` encodings are compatible with the standard bitcoin tooling.
+//!
+//! [1]: https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::{Cursor, Read};
+
+use base64;
+use bitcoin::blockdata::script::{Instruction, Script};
+use bitcoin::blockdata::transaction::{SigHashType, TxOut};
+use bitcoin::network::encodable::VarInt;
+use bitcoin::network::serialize::{deserialize, serialize};
+use bitcoin::util::Error as BitcoinError;
+use bitcoin::util::hash::Sha256dHash;
+use byteorder::{LittleEndian, ReadBytesExt};
+use secp256k1::Secp256k1;
+
+use details::btc;
+use details::btc::RedeemScript;
+use details::btc::transactions::{witness_signature_hash, AnchoringTx, RawBitcoinTx};
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+/// Partially signed bitcoin transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psbt {
+    /// Transaction with empty scripts and witnesses.
+    pub unsigned_tx: RawBitcoinTx,
+    /// Per input data.
+    pub inputs: Vec<PsbtInput>,
+    /// Per output data, only unknown records are kept.
+    pub outputs: Vec<Vec<(Vec<u8>, Vec<u8>)>>,
+    /// Unknown global records.
+    pub unknown: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Data of the partially signed transaction input.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PsbtInput {
+    /// Transaction which output is spent by the `p2sh` input.
+    pub non_witness_utxo: Option<RawBitcoinTx>,
+    /// Output which is spent by the segwit input.
+    pub witness_utxo: Option<TxOut>,
+    /// Signatures by the compressed public keys.
+    pub partial_sigs: BTreeMap<Vec<u8>, btc::Signature>,
+    /// Signature hash type which should be used.
+    pub sighash_type: Option<u32>,
+    /// Redeem script of the `p2sh` input.
+    pub redeem_script: Option<Script>,
+    /// Witness script of the segwit input.
+    pub witness_script: Option<Script>,
+    /// Finalized script signature.
+    pub final_script_sig: Option<Script>,
+    /// Finalized witness.
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Unknown records.
+    pub unknown: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Psbt {
+    /// Creates the `Psbt` for the anchoring transaction that spends the multisig outputs
+    /// with the given `redeem_script`.
+    pub fn from_anchoring_tx(
+        tx: &AnchoringTx,
+        redeem_script: &RedeemScript,
+        script_type: btc::ScriptType,
+    ) -> Psbt {
+        let mut unsigned_tx = tx.0.clone();
+        for input in &mut unsigned_tx.input {
+            input.script_sig = Script::new();
+        }
+        unsigned_tx.witness = Vec::new();
+
+        let inputs = unsigned_tx
+            .input
+            .iter()
+            .map(|_| {
+                let mut input = PsbtInput::default();
+                match script_type {
+                    btc::ScriptType::P2sh => {
+                        input.redeem_script = Some(redeem_script.0.clone());
+                    }
+                    btc::ScriptType::P2shP2wsh => {
+                        input.redeem_script = Some(redeem_script.witness_program());
+                        input.witness_script = Some(redeem_script.0.clone());
+                    }
+                    btc::ScriptType::P2wsh => {
+                        input.witness_script = Some(redeem_script.0.clone());
+                    }
+                }
+                input
+            })
+            .collect();
+        let outputs = vec![Vec::new(); unsigned_tx.output.len()];
+        Psbt {
+            unsigned_tx,
+            inputs,
+            outputs,
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Adds the signature of the given public key for the `input`.
+    pub fn add_signature(&mut self, input: u32, pub_key: &btc::PublicKey, signature: &[u8]) {
+        let pub_key = pub_key.serialize_vec(&Secp256k1::without_caps(), true);
+        self.inputs[input as usize].partial_sigs.insert(
+            pub_key.as_slice().to_vec(),
+            signature.to_vec(),
+        );
+    }
+
+    /// Returns the `SIGHASH_ALL` signature hash of the given `input`, or `None` if the input
+    /// has no redeem script or the value of its spent `p2wsh` output is unknown.
+    pub fn signature_hash(&self, input: u32) -> Option<Sha256dHash> {
+        let psbt_input = match self.inputs.get(input as usize) {
+            Some(psbt_input) => psbt_input,
+            None => return None,
+        };
+        if let Some(ref script) = psbt_input.witness_script {
+            psbt_input.witness_utxo.as_ref().map(|out| {
+                witness_signature_hash(&self.unsigned_tx, input as usize, script, out.value)
+            })
+        } else {
+            psbt_input.redeem_script.as_ref().map(|script| {
+                self.unsigned_tx.signature_hash(
+                    input as usize,
+                    script,
+                    SigHashType::All.as_u32(),
+                )
+            })
+        }
+    }
+
+    /// Merges the partial signatures from the `other` `Psbt` of the same transaction.
+    ///
+    /// # Panics
+    ///
+    /// If the unsigned transactions differ.
+    pub fn combine(&mut self, other: Psbt) {
+        assert_eq!(self.unsigned_tx, other.unsigned_tx);
+        for (input, other_input) in self.inputs.iter_mut().zip(other.inputs) {
+            input.partial_sigs.extend(other_input.partial_sigs);
+            if input.non_witness_utxo.is_none() {
+                input.non_witness_utxo = other_input.non_witness_utxo;
+            }
+            if input.witness_utxo.is_none() {
+                input.witness_utxo = other_input.witness_utxo;
+            }
+        }
+    }
+
+    /// Returns the finalized anchoring transaction if each input has at least
+    /// `majority_count` partial signatures.
+    ///
+    /// Signatures are taken in the order of public keys in the multisig script.
+    pub fn finalize(&self, majority_count: u8) -> Option<AnchoringTx> {
+        let mut signatures = HashMap::new();
+        let mut multisig_script = None;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let script = match input.witness_script.as_ref().or(input.redeem_script.as_ref()) {
+                Some(script) => script,
+                None => return None,
+            };
+            let input_signatures = script
+                .into_iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::PushBytes(pub_key) => input.partial_sigs.get(pub_key).cloned(),
+                    _ => None,
+                })
+                .take(majority_count as usize)
+                .collect::<Vec<_>>();
+            if input_signatures.len() < majority_count as usize {
+                return None;
+            }
+            signatures.insert(index as u32, input_signatures);
+            multisig_script = Some(RedeemScript(script.clone()));
+        }
+
+        let redeem_script = match multisig_script {
+            Some(script) => script,
+            None => return None,
+        };
+        let tx = AnchoringTx(self.unsigned_tx.clone());
+        // The nested segwit inputs have both the redeem script and the witness script.
+        let is_witness = self.inputs.iter().any(|input| input.witness_script.is_some());
+        let is_nested = self.inputs.iter().any(|input| input.redeem_script.is_some());
+        match (is_witness, is_nested) {
+            (true, true) => Some(tx.finalize_witness(&redeem_script, signatures)),
+            (true, false) => Some(tx.finalize_native_witness(&redeem_script, signatures)),
+            (false, _) => Some(tx.finalize(&redeem_script, signatures)),
+        }
+    }
+
+    /// Serializes `Psbt` into the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = PSBT_MAGIC.to_vec();
+
+        write_record(
+            &mut data,
+            &[PSBT_GLOBAL_UNSIGNED_TX],
+            &serialize(&self.unsigned_tx).unwrap(),
+        );
+        write_unknown(&mut data, &self.unknown);
+        data.push(0x00);
+
+        for input in &self.inputs {
+            if let Some(ref tx) = input.non_witness_utxo {
+                write_record(&mut data, &[PSBT_IN_NON_WITNESS_UTXO], &serialize(tx).unwrap());
+            }
+            if let Some(ref out) = input.witness_utxo {
+                write_record(&mut data, &[PSBT_IN_WITNESS_UTXO], &serialize(out).unwrap());
+            }
+            for (pub_key, signature) in &input.partial_sigs {
+                let mut key = vec![PSBT_IN_PARTIAL_SIG];
+                key.extend_from_slice(pub_key);
+                write_record(&mut data, &key, signature);
+            }
+            if let Some(sighash_type) = input.sighash_type {
+                write_record(
+                    &mut data,
+                    &[PSBT_IN_SIGHASH_TYPE],
+                    &serialize(&sighash_type).unwrap(),
+                );
+            }
+            if let Some(ref script) = input.redeem_script {
+                write_record(&mut data, &[PSBT_IN_REDEEM_SCRIPT], &script.clone().into_vec());
+            }
+            if let Some(ref script) = input.witness_script {
+                write_record(&mut data, &[PSBT_IN_WITNESS_SCRIPT], &script.clone().into_vec());
+            }
+            if let Some(ref script) = input.final_script_sig {
+                write_record(&mut data, &[PSBT_IN_FINAL_SCRIPTSIG], &script.clone().into_vec());
+            }
+            if let Some(ref witness) = input.final_script_witness {
+                let mut value = Vec::new();
+                write_compact_size(&mut value, witness.len() as u64);
+                for item in witness {
+                    write_compact_size(&mut value, item.len() as u64);
+                    value.extend_from_slice(item);
+                }
+                write_record(&mut data, &[PSBT_IN_FINAL_SCRIPTWITNESS], &value);
+            }
+            write_unknown(&mut data, &input.unknown);
+            data.push(0x00);
+        }
+
+        for output in &self.outputs {
+            write_unknown(&mut data, output);
+            data.push(0x00);
+        }
+        data
+    }
+
+    /// Deserializes `Psbt` from the binary format.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Psbt> {
+        if !data.starts_with(PSBT_MAGIC) {
+            return Err(invalid_data("Incorrect psbt magic bytes"));
+        }
+        let mut reader = Cursor::new(&data[PSBT_MAGIC.len()..]);
+
+        let mut unsigned_tx = None;
+        let mut unknown = Vec::new();
+        for (key, value) in read_map(&mut reader)? {
+            if key == [PSBT_GLOBAL_UNSIGNED_TX] {
+                unsigned_tx = Some(deserialize(&value).map_err(decode_error)?);
+            } else {
+                unknown.push((key, value));
+            }
+        }
+        let unsigned_tx = unsigned_tx.ok_or_else(
+            || invalid_data("Psbt has no unsigned transaction"),
+        )?;
+
+        let mut inputs = Vec::new();
+        for _ in 0..unsigned_tx.input.len() {
+            let mut input = PsbtInput::default();
+            for (key, value) in read_map(&mut reader)? {
+                match key[0] {
+                    PSBT_IN_NON_WITNESS_UTXO if key.len() == 1 => {
+                        input.non_witness_utxo = Some(deserialize(&value).map_err(decode_error)?);
+                    }
+                    PSBT_IN_WITNESS_UTXO if key.len() == 1 => {
+                        input.witness_utxo = Some(deserialize(&value).map_err(decode_error)?);
+                    }
+                    PSBT_IN_PARTIAL_SIG => {
+                        input.partial_sigs.insert(key[1..].to_vec(), value);
+                    }
+                    PSBT_IN_SIGHASH_TYPE if key.len() == 1 => {
+                        input.sighash_type = Some(deserialize(&value).map_err(decode_error)?);
+                    }
+                    PSBT_IN_REDEEM_SCRIPT if key.len() == 1 => {
+                        input.redeem_script = Some(Script::from(value));
+                    }
+                    PSBT_IN_WITNESS_SCRIPT if key.len() == 1 => {
+                        input.witness_script = Some(Script::from(value));
+                    }
+                    PSBT_IN_FINAL_SCRIPTSIG if key.len() == 1 => {
+                        input.final_script_sig = Some(Script::from(value));
+                    }
+                    PSBT_IN_FINAL_SCRIPTWITNESS if key.len() == 1 => {
+                        let mut value = Cursor::new(value);
+                        let count = read_compact_size(&mut value)?;
+                        let mut witness = Vec::new();
+                        for _ in 0..count {
+                            witness.push(read_bytes(&mut value)?);
+                        }
+                        input.final_script_witness = Some(witness);
+                    }
+                    _ => input.unknown.push((key, value)),
+                }
+            }
+            inputs.push(input);
+        }
+
+        let mut outputs = Vec::new();
+        for _ in 0..unsigned_tx.output.len() {
+            outputs.push(read_map(&mut reader)?);
+        }
+
+        Ok(Psbt {
+            unsigned_tx,
+            inputs,
+            outputs,
+            unknown,
+        })
+    }
+
+    /// Serializes `Psbt` into the `base64` string.
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.to_bytes())
+    }
+
+    /// Deserializes `Psbt` from the `base64` string.
+    pub fn from_base64(s: &str) -> io::Result<Psbt> {
+        let data = base64::decode(s).map_err(|e| invalid_data(e.to_string()))?;
+        Psbt::from_bytes(&data)
+    }
+}
+
+fn invalid_data<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn decode_error(e: BitcoinError) -> io::Error {
+    invalid_data(format!("{:?}", e))
+}
+
+fn write_compact_size(data: &mut Vec<u8>, n: u64) {
+    data.extend(serialize(&VarInt(n)).unwrap());
+}
+
+fn read_compact_size<R: Read>(reader: &mut R) -> io::Result<u64> {
+    Ok(match reader.read_u8()? {
+        0xFF => reader.read_u64::<LittleEndian>()?,
+        0xFE => u64::from(reader.read_u32::<LittleEndian>()?),
+        0xFD => u64::from(reader.read_u16::<LittleEndian>()?),
+        n => u64::from(n),
+    })
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_compact_size(reader)?;
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(invalid_data("Psbt is truncated"));
+    }
+    Ok(bytes)
+}
+
+fn write_record(data: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    write_compact_size(data, key.len() as u64);
+    data.extend_from_slice(key);
+    write_compact_size(data, value.len() as u64);
+    data.extend_from_slice(value);
+}
+
+fn write_unknown(data: &mut Vec<u8>, records: &[(Vec<u8>, Vec<u8>)]) {
+    for &(ref key, ref value) in records {
+        write_record(data, key, value);
+    }
+}
+
+// Reads key-value records until the separator.
+fn read_map<R: Read>(reader: &mut R) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut records = Vec::new();
+    loop {
+        let key = read_bytes(reader)?;
+        if key.is_empty() {
+            return Ok(records);
+        }
+        let value = read_bytes(reader)?;
+        records.push((key, value));
+    }
+}
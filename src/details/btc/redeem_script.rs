@@ -12,16 +12,89 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::str::FromStr;
+
 use bitcoin::blockdata::script::{Builder, Script};
 use bitcoin::blockdata::opcodes::All;
-use bitcoin::blockdata::script::Instruction;
+use bitcoin::blockdata::script::{Instruction, read_scriptint};
 use bitcoin::util::base58::FromBase58;
 use bitcoin::util::address::Address as RawAddress;
 use bitcoin::network::constants::Network;
 use secp256k1::key::PublicKey as RawPublicKey;
 use secp256k1::Secp256k1;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+use exonum::crypto::hash;
+
+use super::{Address, Chain, PublicKey, RedeemScript};
+
+/// Type of the anchoring multisig address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    /// Legacy `p2sh` multisig address.
+    P2sh,
+    /// Segwit `p2wsh` multisig address nested into the `p2sh` one.
+    P2shP2wsh,
+    /// Native segwit `p2wsh` multisig address.
+    P2wsh,
+}
 
-use super::{PublicKey, RedeemScript, Address};
+impl Default for ScriptType {
+    fn default() -> ScriptType {
+        ScriptType::P2sh
+    }
+}
+
+impl FromStr for ScriptType {
+    type Err = String;
+
+    /// Parses the script type from its config name.
+    fn from_str(s: &str) -> Result<ScriptType, String> {
+        match s {
+            "p2sh" => Ok(ScriptType::P2sh),
+            "p2sh_p2wsh" => Ok(ScriptType::P2shP2wsh),
+            "p2wsh" => Ok(ScriptType::P2wsh),
+            other => Err(format!("Unknown script type {}", other)),
+        }
+    }
+}
+
+/// The minimum relative timelock of the recovery branch in blocks, that is about a day.
+///
+/// The shorter timelocks let the recovery key spend the anchoring outputs before
+/// the validators have a chance to extend the anchoring chain.
+pub const MIN_RECOVERY_TIMEOUT: u16 = 144;
+
+/// Timelocked recovery branch of the anchoring redeem script.
+///
+/// The `key` alone can spend the anchoring funds once the spent output is `timeout` blocks
+/// deep, so the funds are not lost if the validators quorum is lost permanently.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecoveryBranch {
+    /// Public key of the recovery branch.
+    pub key: PublicKey,
+    /// Relative timelock of the branch in blocks which is checked by `OP_CHECKSEQUENCEVERIFY`,
+    /// it must not be less than the [`MIN_RECOVERY_TIMEOUT`](constant.MIN_RECOVERY_TIMEOUT.html).
+    #[serde(deserialize_with = "recovery_timeout_from_u16")]
+    pub timeout: u16,
+}
+
+fn recovery_timeout_from_u16<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timeout: u16 = Deserialize::deserialize(deserializer)?;
+    if timeout < MIN_RECOVERY_TIMEOUT {
+        return Err(D::Error::custom(format!(
+            "Recovery timeout {} is less than the minimum {} blocks",
+            timeout,
+            MIN_RECOVERY_TIMEOUT
+        )));
+    }
+    Ok(timeout)
+}
 
 // TODO implement errors
 
@@ -70,6 +143,26 @@ impl RedeemScript {
         RawAddress::from_script(network, self).into()
     }
 
+    /// Returns version 0 witness program for this script: `OP_0 <sha256(script)>`.
+    pub fn witness_program(&self) -> Script {
+        let script_hash = hash(self.0.clone().into_vec().as_ref());
+        Builder::new()
+            .push_opcode(All::OP_PUSHBYTES_0)
+            .push_slice(script_hash.as_ref())
+            .into_script()
+    }
+
+    /// Returns `p2sh` address of the witness program for this script.
+    pub fn to_witness_address(&self, network: Network) -> Address {
+        RawAddress::from_script(network, &self.witness_program()).into()
+    }
+
+    /// Returns native segwit `p2wsh` address of this script on the given chain.
+    pub fn to_native_witness_address(&self, chain: Chain) -> Address {
+        let script_hash = hash(self.0.clone().into_vec().as_ref());
+        Address::Witness { chain, script_hash }
+    }
+
     pub fn compressed(&self, network: Network) -> RedeemScript {
         let mut builder = Builder::new();
         let context = Secp256k1::without_caps();
@@ -92,6 +185,93 @@ impl RedeemScript {
         RedeemScript(builder.into_script())
     }
 
+    /// Wraps this multisig script into the script with the recovery branch:
+    /// `OP_IF <multisig> OP_ELSE <timeout> OP_CSV OP_DROP <key> OP_CHECKSIG OP_ENDIF`.
+    ///
+    /// The multisig branch is selected by the `OP_TRUE` pushed right before the script.
+    ///
+    /// # Panics
+    ///
+    /// If the `timeout` of the branch is less than the `MIN_RECOVERY_TIMEOUT`.
+    pub fn with_recovery(&self, recovery: &RecoveryBranch) -> RedeemScript {
+        assert!(
+            recovery.timeout >= MIN_RECOVERY_TIMEOUT,
+            "Recovery timeout {} is less than the minimum {} blocks",
+            recovery.timeout,
+            MIN_RECOVERY_TIMEOUT
+        );
+        let context = Secp256k1::without_caps();
+        let key = recovery.key.serialize_vec(&context, true);
+
+        let mut bytes = vec![All::OP_IF as u8];
+        bytes.extend(self.0.clone().into_vec());
+        let branch = Builder::from(bytes)
+            .push_opcode(All::OP_ELSE)
+            .push_int(i64::from(recovery.timeout))
+            // OP_CHECKSEQUENCEVERIFY
+            .push_opcode(All::OP_NOP3)
+            .push_opcode(All::OP_DROP)
+            .push_slice(key.as_slice())
+            .push_opcode(All::OP_CHECKSIG)
+            .push_opcode(All::OP_ENDIF)
+            .into_script();
+        RedeemScript(branch)
+    }
+
+    /// Returns the recovery branch of the script if it has one.
+    ///
+    /// The scripts are taken from the bitcoin transactions, so parsing never panics.
+    /// The branches with the timeout less than the `MIN_RECOVERY_TIMEOUT` are not recognized,
+    /// so the timeout is always the number push and never the small integer opcode.
+    pub fn recovery_branch(&self) -> Option<RecoveryBranch> {
+        let instructions = self.0.into_iter().collect::<Vec<_>>();
+        let len = instructions.len();
+        if len < 8 {
+            return None;
+        }
+        match instructions[0] {
+            Instruction::Op(All::OP_IF) => {}
+            _ => return None,
+        }
+        match (
+            &instructions[len - 7],
+            &instructions[len - 5],
+            &instructions[len - 4],
+            &instructions[len - 2],
+            &instructions[len - 1],
+        ) {
+            (&Instruction::Op(All::OP_ELSE),
+             &Instruction::Op(All::OP_NOP3),
+             &Instruction::Op(All::OP_DROP),
+             &Instruction::Op(All::OP_CHECKSIG),
+             &Instruction::Op(All::OP_ENDIF)) => {}
+            _ => return None,
+        }
+        // The empty push of the `OP_0` is read as zero and rejected with the other
+        // small timeouts.
+        let timeout = match instructions[len - 6] {
+            Instruction::PushBytes(bytes) => {
+                match read_scriptint(bytes) {
+                    Ok(n) if n >= i64::from(MIN_RECOVERY_TIMEOUT) &&
+                             n <= i64::from(u16::max_value()) => n as u16,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let key = match instructions[len - 3] {
+            Instruction::PushBytes(bytes) => {
+                let context = Secp256k1::without_caps();
+                match RawPublicKey::from_slice(&context, bytes) {
+                    Ok(key) => PublicKey::from(key),
+                    Err(_) => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(RecoveryBranch { key, timeout })
+    }
+
     pub fn script_pubkey(&self, network: Network) -> Script {
         let addr = RawAddress::from_script(network, self);
         addr.script_pubkey()
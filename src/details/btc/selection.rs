@@ -0,0 +1,163 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Strategies of choosing the funding transactions spent by the anchoring proposals.
+
+use std::cmp::Reverse;
+
+use details::btc::transactions::FundingTx;
+
+/// Strategy of choosing the funding transactions spent by the anchoring proposal
+/// in addition to the previous anchoring transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSelection {
+    /// Spends all available funding transactions.
+    ConsolidateAll,
+    /// Spends the funding transactions recorded in the blockchain first
+    /// until the proposal is funded.
+    OldestFirst,
+    /// Spends the funding transactions with the largest values first
+    /// until the proposal is funded.
+    LargestFirst,
+}
+
+impl Default for CoinSelection {
+    fn default() -> CoinSelection {
+        CoinSelection::ConsolidateAll
+    }
+}
+
+/// Unspent output of the funding transaction which can be spent by the anchoring proposal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingCandidate {
+    /// Funding transaction.
+    pub tx: FundingTx,
+    /// Index of the output to the anchoring address.
+    pub out: u32,
+    /// Height of the Exonum block in which the funding transaction is recorded, that is
+    /// the height of its notice or the activation height of the configuration with it.
+    pub recorded_height: u64,
+}
+
+impl FundingCandidate {
+    /// Returns the value of the output.
+    pub fn value(&self) -> u64 {
+        self.tx.output[self.out as usize].value
+    }
+
+    // Candidates with the equal priority are ordered by their outpoints.
+    fn outpoint_key(&self) -> (String, u32) {
+        (self.tx.txid(), self.out)
+    }
+}
+
+impl CoinSelection {
+    /// Selects the candidates to spend in addition to the inputs with the `available`
+    /// funds. The `required` function returns the funds required by the transaction
+    /// with the given number of the selected candidates.
+    ///
+    /// If the candidates are not enough, all of them are selected. The candidates
+    /// are returned in the order of their priority, so the validators which limit
+    /// the number of the spent candidates choose the same ones.
+    pub fn select(
+        &self,
+        candidates: &[FundingCandidate],
+        available: u64,
+        required: &Fn(usize) -> u64,
+    ) -> Vec<FundingCandidate> {
+        let mut candidates = candidates.to_vec();
+        match *self {
+            CoinSelection::ConsolidateAll => {
+                candidates.sort_by_key(|c| c.outpoint_key());
+                return candidates;
+            }
+            CoinSelection::OldestFirst => {
+                candidates.sort_by_key(|c| (c.recorded_height, c.outpoint_key()))
+            }
+            CoinSelection::LargestFirst => {
+                candidates.sort_by_key(|c| (Reverse(c.value()), c.outpoint_key()))
+            }
+        }
+
+        let mut funds = available;
+        let mut selected = Vec::new();
+        for candidate in candidates {
+            if funds >= required(selected.len()) {
+                break;
+            }
+            funds += candidate.value();
+            selected.push(candidate);
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc::HexValueEx;
+    use details::btc::transactions::FundingTx;
+
+    use super::{CoinSelection, FundingCandidate};
+
+    fn candidates() -> Vec<FundingCandidate> {
+        let tx = FundingTx::from_hex(
+            "01000000019532a4022a22226a6f694c3f21216b2c9f5c1c79007eb7\
+             d3be06bc2f1f9e52fb000000006a47304402203661efd05ca422fad958b534dbad2e1c7db42bbd1e73e9b91f43\
+             a2f7be2f92040220740cf883273978358f25ca5dd5700cce5e65f4f0a0be2e1a1e19a8f168095400012102ae1b\
+             03b0f596be41a247080437a50f4d8e825b170770dcb4e5443a2eb2ecab2afeffffff02a00f00000000000017a9\
+             14bff50e89fa259d83f78f2e796f57283ca10d6e678716e1ff05000000001976a91402f5d7475a10a9c24cea32\
+             575bd8993d3fabbfd388ac089e1000",
+        ).unwrap();
+        vec![
+            FundingCandidate {
+                tx: tx.clone(),
+                out: 0,
+                recorded_height: 2,
+            },
+            FundingCandidate {
+                tx,
+                out: 1,
+                recorded_height: 10,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_coin_selection_strategies() {
+        let candidates = candidates();
+        assert_eq!(candidates[0].value(), 4000);
+        assert_eq!(candidates[1].value(), 100_655_382);
+
+        let outs = |selection: CoinSelection, available: u64, required: u64| {
+            selection
+                .select(&candidates, available, &|_| required)
+                .into_iter()
+                .map(|c| c.out)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(outs(CoinSelection::ConsolidateAll, 10_000, 1000), vec![0, 1]);
+        assert_eq!(outs(CoinSelection::OldestFirst, 0, 1000), vec![0]);
+        assert_eq!(outs(CoinSelection::LargestFirst, 0, 1000), vec![1]);
+        assert_eq!(outs(CoinSelection::OldestFirst, 0, 5000), vec![0, 1]);
+        // The previous anchoring transaction already covers the fee.
+        assert_eq!(outs(CoinSelection::OldestFirst, 10_000, 1000), Vec::<u32>::new());
+        // Insufficient funds.
+        assert_eq!(
+            outs(CoinSelection::LargestFirst, 0, 200_000_000),
+            vec![1, 0]
+        );
+    }
+}
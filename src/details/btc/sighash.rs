@@ -0,0 +1,185 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Caches of the witness signature hash components and of the verified signatures.
+//!
+//! Each validator signs each input of the anchoring proposal, so the same transaction is checked
+//! `inputs * validators` times, and the same signature message is checked again whenever
+//! the block with it is executed anew. The `BIP-143` hashes of the inputs and the outputs
+//! are computed once per transaction and the verification results are reused, so the cost
+//! of the signature checks grows only linearly with the number of the validators.
+//! The cached values depend only on their keys, so the caches never affect the results.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash as StdHash;
+use std::sync::Mutex;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::SigHashType;
+use bitcoin::network::serialize::{BitcoinHash, serialize};
+use bitcoin::util::hash::Sha256dHash;
+use exonum::crypto::{self, Hash};
+use secp256k1::key::PublicKey;
+use secp256k1::Secp256k1;
+
+use details::btc::transactions::RawBitcoinTx;
+
+/// Maximal number of the transactions with the cached witness signature hash components.
+pub const SIGHASH_CACHE_SIZE: usize = 64;
+/// Maximal number of the cached signature verification results.
+pub const SIGNATURES_CACHE_SIZE: usize = 4096;
+
+lazy_static! {
+    static ref SIGHASH_COMPONENTS: Mutex<BoundedCache<Sha256dHash, SighashComponents>> =
+        Mutex::new(BoundedCache::new(SIGHASH_CACHE_SIZE));
+    static ref VERIFIED_SIGNATURES: Mutex<BoundedCache<Hash, bool>> =
+        Mutex::new(BoundedCache::new(SIGNATURES_CACHE_SIZE));
+}
+
+/// Hashes of the `BIP-143` signature hash which are common for all inputs of the transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SighashComponents {
+    hash_prevouts: Sha256dHash,
+    hash_sequence: Sha256dHash,
+    hash_outputs: Sha256dHash,
+}
+
+impl SighashComponents {
+    /// Computes the common hashes of the given transaction.
+    pub fn new(tx: &RawBitcoinTx) -> SighashComponents {
+        let mut prevouts = Vec::new();
+        let mut sequences = Vec::new();
+        for txin in &tx.input {
+            prevouts.extend(serialize(&txin.prev_hash).unwrap());
+            prevouts.extend(serialize(&txin.prev_index).unwrap());
+            sequences.extend(serialize(&txin.sequence).unwrap());
+        }
+        let mut outputs = Vec::new();
+        for txout in &tx.output {
+            outputs.extend(serialize(txout).unwrap());
+        }
+        SighashComponents {
+            hash_prevouts: Sha256dHash::from_data(&prevouts),
+            hash_sequence: Sha256dHash::from_data(&sequences),
+            hash_outputs: Sha256dHash::from_data(&outputs),
+        }
+    }
+
+    /// Returns the cached common hashes of the given transaction.
+    pub fn cached(tx: &RawBitcoinTx) -> SighashComponents {
+        let txid = tx.bitcoin_hash();
+        if let Some(components) = SIGHASH_COMPONENTS.lock().unwrap().get(&txid) {
+            return components;
+        }
+        let components = SighashComponents::new(tx);
+        SIGHASH_COMPONENTS.lock().unwrap().insert(
+            txid,
+            components.clone(),
+        );
+        components
+    }
+
+    /// Computes the `BIP-143` signature hash with the `SIGHASH_ALL` type for the given `input`
+    /// of the transaction from which the components were computed.
+    pub fn sighash(
+        &self,
+        tx: &RawBitcoinTx,
+        input: usize,
+        script_code: &Script,
+        value: u64,
+    ) -> Sha256dHash {
+        let txin = &tx.input[input];
+        let mut data = Vec::new();
+        data.extend(serialize(&tx.version).unwrap());
+        data.extend_from_slice(&self.hash_prevouts[..]);
+        data.extend_from_slice(&self.hash_sequence[..]);
+        data.extend(serialize(&txin.prev_hash).unwrap());
+        data.extend(serialize(&txin.prev_index).unwrap());
+        data.extend(serialize(script_code).unwrap());
+        data.extend(serialize(&value).unwrap());
+        data.extend(serialize(&txin.sequence).unwrap());
+        data.extend_from_slice(&self.hash_outputs[..]);
+        data.extend(serialize(&tx.lock_time).unwrap());
+        data.extend(serialize(&SigHashType::All.as_u32()).unwrap());
+        Sha256dHash::from_data(&data)
+    }
+}
+
+/// Returns the cached result of the verification of the `signature` of the given `input`
+/// or performs the verification by `verify` if there is no result for it yet.
+///
+/// The `value` of the spent output is taken into account only by the witness signatures.
+pub fn verify_cached<F>(
+    tx: &RawBitcoinTx,
+    input: usize,
+    script_code: &Script,
+    value: Option<u64>,
+    pub_key: &PublicKey,
+    signature: &[u8],
+    verify: F,
+) -> bool
+where
+    F: FnOnce() -> bool,
+{
+    let context = Secp256k1::without_caps();
+    let mut data = Vec::new();
+    data.extend_from_slice(&tx.bitcoin_hash()[..]);
+    data.extend(serialize(&(input as u32)).unwrap());
+    data.extend(serialize(script_code).unwrap());
+    data.extend(serialize(&value.unwrap_or(0)).unwrap());
+    data.push(value.is_some() as u8);
+    data.extend_from_slice(pub_key.serialize_vec(&context, true).as_slice());
+    data.extend_from_slice(signature);
+    let key = crypto::hash(&data);
+
+    if let Some(result) = VERIFIED_SIGNATURES.lock().unwrap().get(&key) {
+        return result;
+    }
+    let result = verify();
+    VERIFIED_SIGNATURES.lock().unwrap().insert(key, result);
+    result
+}
+
+// The oldest entries are evicted when the capacity is reached.
+struct BoundedCache<K, V> {
+    entries: HashMap<K, V>,
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: StdHash + Eq + Clone, V: Clone> BoundedCache<K, V> {
+    fn new(capacity: usize) -> BoundedCache<K, V> {
+        BoundedCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.entries.insert(key.clone(), value).is_some() {
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(key) = self.order.pop_front() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Low `s` form of the `DER` encoded `ECDSA` signatures.
+//!
+//! Both `(r, s)` and `(r, n - s)` are valid signatures of the same message, so anybody who
+//! relays the anchoring transaction is able to change its txid by negating `s`.
+//! See [BIP 146](https://github.com/bitcoin/bips/blob/master/bip-0146.mediawiki).
+//! The anchoring signatures are kept in the low `s` form, in which `s` is not greater
+//! than the half of the curve order.
+
+/// Order of the `secp256k1` curve.
+const CURVE_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+/// Half of the order of the `secp256k1` curve.
+const HALF_CURVE_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Returns `true` if the `DER` encoded signature is in the low `s` form.
+pub fn is_low_s(der: &[u8]) -> bool {
+    match parse_der(der) {
+        Some((_, s)) => s <= HALF_CURVE_ORDER,
+        None => false,
+    }
+}
+
+/// Converts the `DER` encoded signature into the low `s` form,
+/// returns `None` if the signature cannot be decoded.
+pub fn normalize_s(der: &[u8]) -> Option<Vec<u8>> {
+    if is_low_s(der) {
+        Some(der.to_vec())
+    } else {
+        negate_s(der)
+    }
+}
+
+/// Replaces `s` of the `DER` encoded signature with `n - s`,
+/// returns `None` if the signature cannot be decoded.
+pub fn negate_s(der: &[u8]) -> Option<Vec<u8>> {
+    let (r, s) = match parse_der(der) {
+        Some(parts) => parts,
+        None => return None,
+    };
+    let r = &der[4..4 + r];
+    let s = encode_integer(&sub(&CURVE_ORDER, &s));
+
+    let mut out = vec![0x30, (4 + r.len() + s.len()) as u8, 0x02, r.len() as u8];
+    out.extend_from_slice(r);
+    out.push(0x02);
+    out.push(s.len() as u8);
+    out.extend_from_slice(&s);
+    Some(out)
+}
+
+// Returns the length of `r` and the value of `s` of the `DER` encoded signature
+// `0x30 len 0x02 r_len r 0x02 s_len s`.
+fn parse_der(der: &[u8]) -> Option<(usize, [u8; 32])> {
+    if der.len() < 8 || der[0] != 0x30 || der[1] as usize != der.len() - 2 || der[2] != 0x02 {
+        return None;
+    }
+    let r_len = der[3] as usize;
+    if 6 + r_len > der.len() || der[4 + r_len] != 0x02 {
+        return None;
+    }
+    let s_len = der[5 + r_len] as usize;
+    if 6 + r_len + s_len != der.len() {
+        return None;
+    }
+
+    let s = &der[6 + r_len..];
+    let start = s.iter().position(|byte| *byte != 0).unwrap_or_else(|| s.len());
+    let s = &s[start..];
+    if s.len() > 32 {
+        return None;
+    }
+    let mut value = [0; 32];
+    value[32 - s.len()..].copy_from_slice(s);
+    Some((r_len, value))
+}
+
+// The minimal big-endian encoding of the positive `DER` integer.
+fn encode_integer(value: &[u8; 32]) -> Vec<u8> {
+    let start = value.iter().position(|byte| *byte != 0).unwrap_or(31);
+    let mut out = Vec::with_capacity(33);
+    if value[start] & 0x80 != 0 {
+        out.push(0);
+    }
+    out.extend_from_slice(&value[start..]);
+    out
+}
+
+// `a - b` for the big-endian numbers where `a >= b`.
+fn sub(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut diff = i16::from(a[i]) - i16::from(b[i]) - borrow;
+        if diff < 0 {
+            diff += 256;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        out[i] = diff as u8;
+    }
+    out
+}
@@ -18,13 +18,11 @@ use std::ops::Deref;
 
 use bitcoin::blockdata::script::Instruction;
 use bitcoin::blockdata::opcodes::All;
-use bitcoin::util::hash::Hash160;
+use bitcoin::util::hash::{Hash160, Sha256dHash};
 use bitcoin::network::serialize::{BitcoinHash, deserialize, serialize, serialize_hex};
 use bitcoin::blockdata::transaction::{TxIn, TxOut};
 use bitcoin::blockdata::script::{Builder, Script};
-use bitcoin::util::base58::ToBase58;
 use bitcoin::util::address::{Address, Privkey, Type};
-use bitcoin::network::constants::Network;
 use bitcoin::blockdata::transaction::SigHashType;
 use secp256k1::key::{PublicKey, SecretKey};
 use secp256k1::{Message, Secp256k1, Signature};
@@ -38,18 +36,36 @@ use exonum::storage::StorageValue;
 use details::rpc::{Error as RpcError, RpcClient};
 use details::btc;
 use details::btc::{HexValueEx, RedeemScript, TxId};
+use details::btc::address::witness_script_hash;
 use details::error::Error as InternalError;
-use details::btc::payload::{Payload, PayloadBuilder};
+use details::btc::canonical;
+use details::btc::sighash::{self, SighashComponents};
+use details::btc::signature;
+use details::btc::payload::{ParsedPayload, Payload, PayloadBuilder};
+use details::btc::selection::{CoinSelection, FundingCandidate};
 
 pub type RawBitcoinTx = ::bitcoin::blockdata::transaction::Transaction;
 
 const ANCHORING_TX_FUNDS_OUTPUT: u32 = 0;
 const ANCHORING_TX_DATA_OUTPUT: u32 = 1;
+const ANCHORING_TX_SURPLUS_OUTPUT: u32 = 2;
+/// Minimal value of the output which is relayed by the bitcoin nodes.
+pub const DUST_THRESHOLD: u64 = 546;
+/// Maximal weight of the transaction which is relayed by the bitcoin nodes.
+pub const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+/// Input sequence number that signals the transaction replaceability according to the BIP 125.
+pub const REPLACEABLE_SEQUENCE: u32 = 0xFFFF_FFFD;
+/// Version of the recovery transaction, the relative timelocks are enforced since the version 2.
+pub const RECOVERY_TX_VERSION: u32 = 2;
 
 /// Anchoring transaction struct is strict:
 /// - Zero input is previous anchoring tx or initial funding tx
 /// - Zero output is next anchoring tx
 /// - First output is anchored metadata
+/// - Optional second output is the surplus sent to the address of the change policy
+///
+/// The final anchoring transaction which burns the rest of the funds has only
+/// the metadata output.
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct AnchoringTx(pub RawBitcoinTx);
 /// Funding transaction always has an output to `p2sh` address
@@ -77,6 +93,13 @@ pub struct TransactionBuilder {
     fee: Option<u64>,
     payload: Option<(Height, Hash)>,
     prev_tx_chain: Option<TxId>,
+    state_hash: Option<Hash>,
+    chain_id: Option<u32>,
+    commitments: Option<Hash>,
+    surplus: Option<(btc::Address, u64)>,
+    burn_dust: bool,
+    max_inputs: Option<usize>,
+    replaceable: bool,
 }
 
 impl HexValueEx for RawBitcoinTx {
@@ -106,17 +129,11 @@ implement_serde_hex! {BitcoinTx}
 
 impl FundingTx {
     pub fn find_out(&self, addr: &btc::Address) -> Option<u32> {
-        let redeem_script_hash = addr.hash;
+        let script_pubkey = addr.script_pubkey();
         self.0
             .output
             .iter()
-            .position(|output| if let Some(Instruction::PushBytes(bytes)) =
-                output.script_pubkey.into_iter().nth(1)
-            {
-                Hash160::from(bytes) == redeem_script_hash
-            } else {
-                false
-            })
+            .position(|output| output.script_pubkey == script_pubkey)
             .map(|x| x as u32)
     }
 
@@ -129,7 +146,7 @@ impl FundingTx {
         let txs = client.listunspent(
             0,
             9_999_999,
-            [addr.to_base58check().as_ref()],
+            [addr.to_string().as_ref()],
         )?;
         Ok(txs.into_iter().find(|txinfo| txinfo.txid == txid))
     }
@@ -137,11 +154,44 @@ impl FundingTx {
 
 impl AnchoringTx {
     pub fn amount(&self) -> u64 {
+        if self.is_final() {
+            return 0;
+        }
         self.0.output[ANCHORING_TX_FUNDS_OUTPUT as usize].value
     }
 
-    pub fn output_address(&self, network: Network) -> btc::Address {
+    /// Returns true if the transaction has burned the rest of the anchoring funds
+    /// into the fee, so the anchoring chain cannot be continued from it.
+    pub fn is_final(&self) -> bool {
+        self.0.output.len() == 1
+    }
+
+    /// Returns the total value of the transaction outputs including the surplus output.
+    pub fn total_amount(&self) -> u64 {
+        self.0.output.iter().map(|output| output.value).sum()
+    }
+
+    /// Returns the output which sends the surplus of the anchoring funds
+    /// to the address of the change policy.
+    pub fn surplus_output(&self) -> Option<&TxOut> {
+        self.0.output.get(ANCHORING_TX_SURPLUS_OUTPUT as usize)
+    }
+
+    /// Returns the address of the funds output, the native segwit addresses are encoded
+    /// for the given chain.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction is [final](#method.is_final).
+    pub fn output_address<N>(&self, network: N) -> btc::Address
+    where
+        N: Into<btc::Chain>,
+    {
+        let chain = network.into();
         let script = &self.0.output[ANCHORING_TX_FUNDS_OUTPUT as usize].script_pubkey;
+        if let Some(script_hash) = witness_script_hash(script) {
+            return btc::Address::Witness { chain, script_hash };
+        }
         let bytes = script
             .into_iter()
             .filter_map(|instruction| if let Instruction::PushBytes(bytes) =
@@ -156,7 +206,7 @@ impl AnchoringTx {
 
         Address {
             ty: Type::ScriptHash,
-            network: network,
+            network: chain.base58_network(),
             hash: Hash160::from(bytes),
         }.into()
     }
@@ -204,6 +254,59 @@ impl AnchoringTx {
     ) -> AnchoringTx {
         finalize_anchoring_transaction(self, redeem_script, signatures)
     }
+
+    /// Signs the given `input` that spends the `p2wsh` output with the given `value`.
+    pub fn sign_witness_input(
+        &self,
+        redeem_script: &btc::RedeemScript,
+        input: u32,
+        value: u64,
+        priv_key: &Privkey,
+    ) -> btc::Signature {
+        let mut sign_data = sign_witness_tx_input(
+            self,
+            input as usize,
+            redeem_script,
+            value,
+            priv_key.secret_key(),
+        );
+        sign_data.push(SigHashType::All.as_u32() as u8);
+        sign_data
+    }
+
+    /// Verifies the signature for the given `input` that spends the `p2wsh` output
+    /// with the given `value`.
+    pub fn verify_witness_input(
+        &self,
+        redeem_script: &RedeemScript,
+        input: u32,
+        value: u64,
+        pub_key: &PublicKey,
+        signature: &[u8],
+    ) -> bool {
+        // Cuts off btc related sighash type byte
+        let signature = &signature[0..signature.len() - 1];
+        verify_witness_tx_input(self, input as usize, redeem_script, value, pub_key, signature)
+    }
+
+    /// Same as [`finalize`](#method.finalize) but puts signatures into the inputs witnesses.
+    pub fn finalize_witness(
+        self,
+        redeem_script: &btc::RedeemScript,
+        signatures: HashMap<u32, Vec<btc::Signature>>,
+    ) -> AnchoringTx {
+        finalize_witness_anchoring_transaction(self, redeem_script, signatures, true)
+    }
+
+    /// Same as [`finalize_witness`](#method.finalize_witness) but leaves the `scriptSig`
+    /// of the inputs empty, since they spend the native `p2wsh` outputs.
+    pub fn finalize_native_witness(
+        self,
+        redeem_script: &btc::RedeemScript,
+        signatures: HashMap<u32, Vec<btc::Signature>>,
+    ) -> AnchoringTx {
+        finalize_witness_anchoring_transaction(self, redeem_script, signatures, false)
+    }
 }
 
 impl fmt::Debug for AnchoringTx {
@@ -228,18 +331,61 @@ impl fmt::Debug for FundingTx {
     }
 }
 
+impl TxKind {
+    /// Classifies the serialized bitcoin transaction, returns `None` if the bytes are
+    /// not the valid transaction.
+    ///
+    /// The transactions are taken from the bitcoin blockchain, so the classification
+    /// never panics for any input.
+    pub fn from_bytes(bytes: &[u8]) -> Option<TxKind> {
+        deserialize::<RawBitcoinTx>(bytes).ok().map(TxKind::from)
+    }
+}
+
 impl From<RawBitcoinTx> for TxKind {
     fn from(tx: RawBitcoinTx) -> TxKind {
-        if find_payload(&tx).is_some() {
-            TxKind::Anchoring(AnchoringTx::from(tx))
-        } else {
-            // Find output with funds and p2sh script_pubkey
-            for out in &tx.output {
-                if out.value > 0 && out.script_pubkey.is_p2sh() {
-                    return TxKind::FundingTx(FundingTx::from(tx.clone()));
+        match parse_payload(&tx) {
+            Some(ParsedPayload::Known(_)) if has_anchoring_layout(&tx) => {
+                TxKind::Anchoring(AnchoringTx::from(tx))
+            }
+            // The methods of the anchoring transaction rely on its layout.
+            Some(ParsedPayload::Known(_)) => {
+                let tx = BitcoinTx::from(tx);
+                warn!(
+                    "Transaction txid={} has the anchoring payload, but not the anchoring layout",
+                    tx.txid()
+                );
+                TxKind::Other(tx)
+            }
+            // The anchoring transaction of the newer format must not be taken
+            // for the funding transaction.
+            Some(ParsedPayload::UnknownVersion(version)) => {
+                let tx = BitcoinTx::from(tx);
+                warn!(
+                    "Anchoring transaction txid={} has unknown payload version={}",
+                    tx.txid(),
+                    version
+                );
+                TxKind::Other(tx)
+            }
+            // The funds reclaimed by the recovery branch leave the anchoring chain.
+            None if spends_recovery_branch(&tx) => {
+                let tx = BitcoinTx::from(tx);
+                warn!(
+                    "Transaction txid={} spends the anchoring funds by the recovery branch",
+                    tx.txid()
+                );
+                TxKind::Other(tx)
+            }
+            None => {
+                // Find output with funds and p2sh or p2wsh script_pubkey
+                for out in &tx.output {
+                    if out.value > 0 && is_script_hash(&out.script_pubkey) {
+                        return TxKind::FundingTx(FundingTx::from(tx.clone()));
+                    }
                 }
+                TxKind::Other(BitcoinTx::from(tx))
             }
-            TxKind::Other(BitcoinTx::from(tx))
         }
     }
 }
@@ -284,6 +430,13 @@ impl TransactionBuilder {
             payload: None,
             fee: None,
             prev_tx_chain: None,
+            state_hash: None,
+            chain_id: None,
+            commitments: None,
+            surplus: None,
+            burn_dust: false,
+            max_inputs: None,
+            replaceable: false,
         }
     }
 
@@ -312,11 +465,120 @@ impl TransactionBuilder {
         self
     }
 
-    pub fn into_transaction(mut self) -> Result<AnchoringTx, InternalError> {
-        let available_funds: u64 = self.inputs
+    pub fn state_hash(mut self, hash: Option<Hash>) -> TransactionBuilder {
+        self.state_hash = hash;
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: Option<u32>) -> TransactionBuilder {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn commitments(mut self, root: Option<Hash>) -> TransactionBuilder {
+        self.commitments = root;
+        self
+    }
+
+    /// Keeps only `retain` satoshis on the anchoring address and sends the rest of the funds
+    /// to the given address, unless the rest is below the dust threshold.
+    pub fn change_policy(mut self, policy: Option<(btc::Address, u64)>) -> TransactionBuilder {
+        self.surplus = policy;
+        self
+    }
+
+    /// Burns the change below the dust threshold into the fee,
+    /// the transaction is built without the change output then.
+    pub fn burn_dust(mut self, burn_dust: bool) -> TransactionBuilder {
+        self.burn_dust = burn_dust;
+        self
+    }
+
+    /// Limits the number of the transaction inputs added by the `select_funds` and
+    /// the `consolidate_funds`, the rest of the funds is left for the following transactions.
+    pub fn max_inputs(mut self, max_inputs: usize) -> TransactionBuilder {
+        self.max_inputs = Some(max_inputs);
+        self
+    }
+
+    pub fn replaceable(mut self, replaceable: bool) -> TransactionBuilder {
+        self.replaceable = replaceable;
+        self
+    }
+
+    /// Adds the funding inputs chosen by the given `selection` strategy from the `candidates`.
+    /// The `required` function returns the funds required by the transaction with
+    /// the given number of the additional inputs.
+    pub fn select_funds(
+        mut self,
+        selection: CoinSelection,
+        candidates: &[FundingCandidate],
+        required: &Fn(usize) -> u64,
+    ) -> TransactionBuilder {
+        let available = self.available_funds();
+        let max_selected = self.max_inputs.map_or(candidates.len(), |max_inputs| {
+            max_inputs.saturating_sub(self.inputs.len())
+        });
+        let selected = selection.select(candidates, available, required);
+        for candidate in selected.into_iter().take(max_selected) {
+            self.inputs.push((candidate.tx.0, candidate.out));
+        }
+        self
+    }
+
+    /// Adds the remaining `candidates` with the smallest values first until the transaction
+    /// has `max_inputs` inputs. The candidates which do not cover the fee of their input
+    /// calculated with the `required` function are skipped.
+    pub fn consolidate_funds(
+        mut self,
+        candidates: &[FundingCandidate],
+        max_inputs: usize,
+        required: &Fn(usize) -> u64,
+    ) -> TransactionBuilder {
+        let max_inputs = self.max_inputs.map_or(max_inputs, |limit| {
+            ::std::cmp::min(limit, max_inputs)
+        });
+        let mut candidates = candidates
+            .iter()
+            .filter(|candidate| {
+                !self.inputs.iter().any(|&(ref tx, out)| {
+                    out == candidate.out && tx.bitcoin_hash() == candidate.tx.bitcoin_hash()
+                })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|candidate| {
+            (candidate.value(), candidate.tx.txid(), candidate.out)
+        });
+
+        for candidate in candidates {
+            if self.inputs.len() >= max_inputs {
+                break;
+            }
+            let funds = self.inputs.len() - 1;
+            let input_fee = required(funds + 1).saturating_sub(required(funds));
+            if candidate.value() > input_fee {
+                self.inputs.push((candidate.tx.0, candidate.out));
+            }
+        }
+        self
+    }
+
+    /// Returns the number of the transaction inputs.
+    pub fn inputs_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn available_funds(&self) -> u64 {
+        self.inputs
             .iter()
             .map(|&(ref tx, out)| tx.output[out as usize].value)
-            .sum();
+            .sum()
+    }
+
+    pub fn into_transaction(mut self) -> Result<AnchoringTx, InternalError> {
+        canonical::sort_inputs(&mut self.inputs);
+        let available_funds = self.available_funds();
 
         let addr = self.output.take().expect("Output address is not set");
         let fee = self.fee.expect("Fee is not set");
@@ -325,60 +587,165 @@ impl TransactionBuilder {
             return Err(InternalError::InsufficientFunds);
         }
         let total_funds = available_funds - fee;
+        let (out_funds, surplus) = match self.surplus.take() {
+            Some((ref surplus_addr, retain))
+                if total_funds.saturating_sub(retain) >= DUST_THRESHOLD => {
+                let surplus = TxOut {
+                    value: total_funds - retain,
+                    script_pubkey: surplus_addr.script_pubkey(),
+                };
+                (retain, Some(surplus))
+            }
+            _ => (total_funds, None),
+        };
+        let funds_output = if self.burn_dust && surplus.is_none() && out_funds < DUST_THRESHOLD {
+            None
+        } else {
+            Some(TxOut {
+                value: out_funds,
+                script_pubkey: addr.script_pubkey(),
+            })
+        };
 
+        let metadata_script = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(height)
+            .prev_tx_chain(self.prev_tx_chain)
+            .state_hash(self.state_hash)
+            .chain_id(self.chain_id)
+            .commitments(self.commitments)
+            .into_script()?;
         let tx = create_anchoring_transaction(
-            &addr,
-            height,
-            block_hash,
+            funds_output,
+            metadata_script,
             self.inputs.iter(),
-            total_funds,
-            self.prev_tx_chain,
+            surplus,
+            self.replaceable,
         );
         Ok(tx)
     }
 }
 
+/// Returns the virtual size of the raw transaction in bytes, that is its weight
+/// divided by four, so the witness data is counted with a quarter of its size.
+pub fn tx_vsize(tx: &RawBitcoinTx) -> u64 {
+    let size = serialize(tx).unwrap().len() as u64;
+    let mut stripped = tx.clone();
+    stripped.witness = Vec::new();
+    let base_size = serialize(&stripped).unwrap().len() as u64;
+    (base_size * 3 + size + 3) / 4
+}
+
+/// Returns the upper bound of the signed anchoring transaction virtual size in bytes
+/// for the given number of multisig inputs of the `script_type`. The witness data
+/// of the segwit inputs is counted with a quarter of its size.
+pub fn estimate_anchoring_tx_size(
+    inputs: usize,
+    redeem_script: &RedeemScript,
+    script_type: btc::ScriptType,
+    majority_count: u8,
+) -> u64 {
+    (estimate_anchoring_tx_weight(inputs, redeem_script, script_type, majority_count) + 3) / 4
+}
+
+/// Returns the upper bound of the signed anchoring transaction weight for the given number
+/// of multisig inputs of the `script_type`, that is the size of the transaction without
+/// the witness data multiplied by four plus the size of the witness data.
+pub fn estimate_anchoring_tx_weight(
+    inputs: usize,
+    redeem_script: &RedeemScript,
+    script_type: btc::ScriptType,
+    majority_count: u8,
+) -> u64 {
+    // Version, lock time, inputs and outputs counts, funds output
+    // and the data output with the longest payload.
+    const BASE_SIZE: u64 = 10 + 32 + 90;
+    // Outpoint, sequence and script length.
+    const INPUT_BASE_SIZE: u64 = 36 + 4 + 3;
+    // Push of the DER encoded signature with the sighash type.
+    const SIGNATURE_SIZE: u64 = 74;
+    // Push of the witness program of the nested segwit input.
+    const WITNESS_PROGRAM_PUSH_SIZE: u64 = 35;
+    // Segwit marker and flag.
+    const WITNESS_HEADER_SIZE: u64 = 2;
+
+    let has_recovery_branch = redeem_script.recovery_branch().is_some();
+    let signatures_size = SIGNATURE_SIZE * u64::from(majority_count);
+    let redeem_script_size = 3 + redeem_script.0.len() as u64;
+    let inputs = inputs as u64;
+    match script_type {
+        btc::ScriptType::P2sh => {
+            // The multisig branch selector is a single opcode.
+            let selector_size = if has_recovery_branch { 1 } else { 0 };
+            let script_sig_size = 1 + signatures_size + selector_size + redeem_script_size;
+            (BASE_SIZE + (INPUT_BASE_SIZE + script_sig_size) * inputs) * 4
+        }
+        btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+            // Items count and the dummy item, the multisig branch selector is a pushed byte.
+            let selector_size = if has_recovery_branch { 2 } else { 0 };
+            let witness_size = 3 + 1 + signatures_size + selector_size + redeem_script_size;
+            // The `scriptSig` of the native segwit input is empty.
+            let script_sig_size = if script_type == btc::ScriptType::P2shP2wsh {
+                WITNESS_PROGRAM_PUSH_SIZE
+            } else {
+                0
+            };
+            let base_size = BASE_SIZE + (INPUT_BASE_SIZE + script_sig_size) * inputs;
+            base_size * 4 + WITNESS_HEADER_SIZE + witness_size * inputs
+        }
+    }
+}
+
+/// Returns the maximum number of the multisig inputs of the `script_type` of the anchoring
+/// transaction whose estimated weight does not exceed the `MAX_STANDARD_TX_WEIGHT`.
+pub fn max_anchoring_tx_inputs(
+    redeem_script: &RedeemScript,
+    script_type: btc::ScriptType,
+    majority_count: u8,
+) -> usize {
+    let weight =
+        |inputs| estimate_anchoring_tx_weight(inputs, redeem_script, script_type, majority_count);
+    let base_weight = weight(0);
+    let input_weight = weight(1) - base_weight;
+    (MAX_STANDARD_TX_WEIGHT.saturating_sub(base_weight) / input_weight) as usize
+}
+
 fn create_anchoring_transaction<'a, I>(
-    addr: &btc::Address,
-    block_height: Height,
-    block_hash: Hash,
+    funds_output: Option<TxOut>,
+    metadata_script: Script,
     inputs: I,
-    out_funds: u64,
-    prev_chain_txid: Option<TxId>,
+    surplus: Option<TxOut>,
+    replaceable: bool,
 ) -> AnchoringTx
 where
     I: Iterator<Item = &'a (RawBitcoinTx, u32)>,
 {
+    let sequence = if replaceable {
+        REPLACEABLE_SEQUENCE
+    } else {
+        0xFFFF_FFFF
+    };
     let inputs = inputs
         .map(|&(ref unspent_tx, utxo_vout)| {
             TxIn {
                 prev_hash: unspent_tx.bitcoin_hash(),
                 prev_index: utxo_vout,
                 script_sig: Script::new(),
-                sequence: 0xFFFF_FFFF,
+                sequence,
             }
         })
         .collect::<Vec<_>>();
 
-    let metadata_script = PayloadBuilder::new()
-        .block_hash(block_hash)
-        .block_height(block_height)
-        .prev_tx_chain(prev_chain_txid)
-        .into_script();
-    let outputs = vec![
-        TxOut {
-            value: out_funds,
-            script_pubkey: addr.script_pubkey(),
-        },
-        TxOut {
-            value: 0,
-            script_pubkey: metadata_script,
-        },
-    ];
+    let mut outputs = funds_output.into_iter().collect::<Vec<_>>();
+    outputs.push(TxOut {
+        value: 0,
+        script_pubkey: metadata_script,
+    });
+    outputs.extend(surplus);
 
     let tx = RawBitcoinTx {
-        version: 1,
-        lock_time: 0,
+        version: canonical::TX_VERSION,
+        lock_time: canonical::TX_LOCK_TIME,
         input: inputs,
         output: outputs,
         witness: vec![],
@@ -393,12 +760,7 @@ pub fn sign_tx_input(
     sec_key: &SecretKey,
 ) -> Vec<u8> {
     let sighash = tx.signature_hash(input, subscript, SigHashType::All.as_u32());
-    // Make signature
-    let context = Secp256k1::new();
-    let msg = Message::from_slice(&sighash[..]).unwrap();
-    let sign = context.sign(&msg, sec_key).unwrap();
-    // Serialize signature
-    sign.serialize_der(&context)
+    sign_sighash(&sighash, sec_key)
 }
 
 pub fn verify_tx_input(
@@ -408,23 +770,82 @@ pub fn verify_tx_input(
     pub_key: &PublicKey,
     signature: &[u8],
 ) -> bool {
-    let sighash = tx.signature_hash(input, subscript, SigHashType::All.as_u32());
+    sighash::verify_cached(tx, input, subscript, None, pub_key, signature, || {
+        let sighash = tx.signature_hash(input, subscript, SigHashType::All.as_u32());
+        let msg = Message::from_slice(&sighash[..]).unwrap();
+        verify_signature(&msg, pub_key, signature)
+    })
+}
+
+// The nonce is derived from the key and the sighash by `RFC 6979`, so the same proposal
+// is signed to the same bytes after the restart of the node.
+fn sign_sighash(sighash: &Sha256dHash, sec_key: &SecretKey) -> Vec<u8> {
+    let context = Secp256k1::new();
     let msg = Message::from_slice(&sighash[..]).unwrap();
+    let sign = context.sign(&msg, sec_key).unwrap();
+    // Serialize signature
+    let der = sign.serialize_der(&context);
+    signature::normalize_s(&der).expect("Unable to decode the serialized signature")
+}
 
+// Only the strict `DER` encoded signatures in the low `s` form are accepted, otherwise
+// the txid of the finalized anchoring transaction can be changed by anybody.
+fn verify_signature(msg: &Message, pub_key: &PublicKey, signature: &[u8]) -> bool {
     let context = Secp256k1::new();
-    if let Ok(sign) = Signature::from_der(&context, signature) {
-        context.verify(&msg, &sign, pub_key).is_ok()
-    } else {
-        false
+    match Signature::from_der(&context, signature) {
+        Ok(sign) => {
+            sign.serialize_der(&context) == signature && signature::is_low_s(signature) &&
+                context.verify(msg, &sign, pub_key).is_ok()
+        }
+        Err(_) => false,
     }
 }
 
+/// Computes the `BIP-143` signature hash with the `SIGHASH_ALL` type for the given `input`.
+///
+/// The hashes of the inputs and the outputs are computed once per transaction.
+pub fn witness_signature_hash(
+    tx: &RawBitcoinTx,
+    input: usize,
+    script_code: &Script,
+    value: u64,
+) -> Sha256dHash {
+    SighashComponents::cached(tx).sighash(tx, input, script_code, value)
+}
+
+pub fn sign_witness_tx_input(
+    tx: &RawBitcoinTx,
+    input: usize,
+    subscript: &Script,
+    value: u64,
+    sec_key: &SecretKey,
+) -> Vec<u8> {
+    let sighash = witness_signature_hash(tx, input, subscript, value);
+    sign_sighash(&sighash, sec_key)
+}
+
+pub fn verify_witness_tx_input(
+    tx: &RawBitcoinTx,
+    input: usize,
+    subscript: &Script,
+    value: u64,
+    pub_key: &PublicKey,
+    signature: &[u8],
+) -> bool {
+    sighash::verify_cached(tx, input, subscript, Some(value), pub_key, signature, || {
+        let sighash = witness_signature_hash(tx, input, subscript, value);
+        let msg = Message::from_slice(&sighash[..]).unwrap();
+        verify_signature(&msg, pub_key, signature)
+    })
+}
+
 fn finalize_anchoring_transaction(
     mut anchoring_tx: AnchoringTx,
     redeem_script: &btc::RedeemScript,
     signatures: HashMap<u32, Vec<btc::Signature>>,
 ) -> AnchoringTx {
     let redeem_script_bytes = redeem_script.0.clone().into_vec();
+    let has_recovery_branch = redeem_script.recovery_branch().is_some();
     // build scriptSig
     for (out, signatures) in signatures {
         anchoring_tx.0.input[out as usize].script_sig = {
@@ -433,6 +854,9 @@ fn finalize_anchoring_transaction(
             for sign in &signatures {
                 builder = builder.push_slice(sign.as_ref());
             }
+            if has_recovery_branch {
+                builder = builder.push_opcode(All::OP_PUSHNUM_1);
+            }
             builder
                 .push_slice(redeem_script_bytes.as_ref())
                 .into_script()
@@ -441,11 +865,188 @@ fn finalize_anchoring_transaction(
     anchoring_tx
 }
 
+fn finalize_witness_anchoring_transaction(
+    mut anchoring_tx: AnchoringTx,
+    redeem_script: &btc::RedeemScript,
+    signatures: HashMap<u32, Vec<btc::Signature>>,
+    nested: bool,
+) -> AnchoringTx {
+    let redeem_script_bytes = redeem_script.0.clone().into_vec();
+    let witness_program = redeem_script.witness_program().into_vec();
+    let has_recovery_branch = redeem_script.recovery_branch().is_some();
+    let inputs_count = anchoring_tx.0.input.len();
+    anchoring_tx.0.witness = vec![Vec::new(); inputs_count];
+    // build witness and scriptSig with the witness program of the nested input
+    for (out, signatures) in signatures {
+        let mut witness = vec![Vec::new()];
+        witness.extend(signatures);
+        if has_recovery_branch {
+            witness.push(vec![1]);
+        }
+        witness.push(redeem_script_bytes.clone());
+        anchoring_tx.0.witness[out as usize] = witness;
+        if nested {
+            anchoring_tx.0.input[out as usize].script_sig = Builder::new()
+                .push_slice(witness_program.as_ref())
+                .into_script();
+        }
+    }
+    anchoring_tx
+}
+
+/// Creates the transaction which reclaims the given anchoring outputs to the `addr` by
+/// the recovery branch of the `redeem_script` and signs it by the recovery `priv_key`.
+///
+/// The transaction is accepted by the bitcoin network only after the spent outputs
+/// are `timeout` blocks deep.
+///
+/// # Panics
+///
+/// If the `redeem_script` has no recovery branch.
+pub fn create_recovery_tx(
+    redeem_script: &RedeemScript,
+    script_type: btc::ScriptType,
+    inputs: &[(RawBitcoinTx, u32)],
+    addr: &btc::Address,
+    fee: u64,
+    priv_key: &Privkey,
+) -> Result<BitcoinTx, InternalError> {
+    let recovery = redeem_script.recovery_branch().expect(
+        "Redeem script has no recovery branch",
+    );
+    let funds = inputs
+        .iter()
+        .map(|&(ref tx, out)| tx.output[out as usize].value)
+        .sum::<u64>();
+    if funds < fee {
+        return Err(InternalError::InsufficientFunds);
+    }
+
+    let mut tx = RawBitcoinTx {
+        version: RECOVERY_TX_VERSION,
+        lock_time: 0,
+        input: inputs
+            .iter()
+            .map(|&(ref prev_tx, out)| {
+                TxIn {
+                    prev_hash: prev_tx.bitcoin_hash(),
+                    prev_index: out,
+                    script_sig: Script::new(),
+                    sequence: u32::from(recovery.timeout),
+                }
+            })
+            .collect(),
+        output: vec![
+            TxOut {
+                value: funds - fee,
+                script_pubkey: addr.script_pubkey(),
+            },
+        ],
+        witness: vec![],
+    };
+
+    let secret_key = priv_key.secret_key();
+    let signatures = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, &(ref prev_tx, out))| {
+            let mut signature = match script_type {
+                btc::ScriptType::P2sh => sign_tx_input(&tx, index, redeem_script, secret_key),
+                btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                    let value = prev_tx.output[out as usize].value;
+                    sign_witness_tx_input(&tx, index, redeem_script, value, secret_key)
+                }
+            };
+            signature.push(SigHashType::All.as_u32() as u8);
+            signature
+        })
+        .collect::<Vec<_>>();
+
+    // The empty push selects the recovery branch.
+    let redeem_script_bytes = redeem_script.0.clone().into_vec();
+    match script_type {
+        btc::ScriptType::P2sh => {
+            for (input, signature) in tx.input.iter_mut().zip(signatures) {
+                input.script_sig = Builder::new()
+                    .push_slice(signature.as_ref())
+                    .push_opcode(All::OP_PUSHBYTES_0)
+                    .push_slice(redeem_script_bytes.as_ref())
+                    .into_script();
+            }
+        }
+        btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+            if script_type == btc::ScriptType::P2shP2wsh {
+                let witness_program = redeem_script.witness_program().into_vec();
+                for input in &mut tx.input {
+                    input.script_sig = Builder::new()
+                        .push_slice(witness_program.as_ref())
+                        .into_script();
+                }
+            }
+            tx.witness = signatures
+                .into_iter()
+                .map(|signature| {
+                    vec![signature, Vec::new(), redeem_script_bytes.clone()]
+                })
+                .collect();
+        }
+    }
+    Ok(BitcoinTx::from(tx))
+}
+
+// The recovery branch is selected by the empty push right before the redeem script
+// in the witness or in the `scriptSig`.
+fn spends_recovery_branch(tx: &RawBitcoinTx) -> bool {
+    tx.input.iter().enumerate().any(|(index, input)| {
+        let witness = tx.witness.get(index).map_or(&[][..], |witness| &witness[..]);
+        let pushes = if witness.len() >= 2 {
+            witness.iter().map(|item| &item[..]).collect::<Vec<_>>()
+        } else {
+            let mut pushes = Vec::new();
+            for instruction in &input.script_sig {
+                match instruction {
+                    Instruction::PushBytes(bytes) => pushes.push(bytes),
+                    _ => return false,
+                }
+            }
+            pushes
+        };
+        if pushes.len() < 2 || !pushes[pushes.len() - 2].is_empty() {
+            return false;
+        }
+        let script = RedeemScript(Script::from(pushes[pushes.len() - 1].to_vec()));
+        script.recovery_branch().is_some()
+    })
+}
 
 fn find_payload(tx: &RawBitcoinTx) -> Option<Payload> {
-    tx.output.get(ANCHORING_TX_DATA_OUTPUT as usize).and_then(
-        |output| {
-            Payload::from_script(&output.script_pubkey)
-        },
-    )
+    data_output(tx).and_then(|output| Payload::from_script(&output.script_pubkey))
+}
+
+fn parse_payload(tx: &RawBitcoinTx) -> Option<ParsedPayload> {
+    data_output(tx).and_then(|output| Payload::parse(&output.script_pubkey))
+}
+
+// The final anchoring transaction has only the data output.
+fn data_output(tx: &RawBitcoinTx) -> Option<&TxOut> {
+    if tx.output.len() == 1 {
+        tx.output.first()
+    } else {
+        tx.output.get(ANCHORING_TX_DATA_OUTPUT as usize)
+    }
+}
+
+// The anchoring transaction spends at least one output and sends its funds to the `p2sh`
+// or the native `p2wsh` address, the nested `p2wsh` addresses are the `p2sh` ones.
+// The final anchoring transaction does not send the funds anywhere.
+fn has_anchoring_layout(tx: &RawBitcoinTx) -> bool {
+    !tx.input.is_empty() &&
+        (tx.output.len() == 1 ||
+             tx.output
+                 .get(ANCHORING_TX_FUNDS_OUTPUT as usize)
+                 .map_or(false, |output| is_script_hash(&output.script_pubkey)))
+}
+
+fn is_script_hash(script_pubkey: &Script) -> bool {
+    script_pubkey.is_p2sh() || witness_script_hash(script_pubkey).is_some()
 }
@@ -31,7 +31,7 @@ use exonum::encoding::serialize::{encode_hex, FromHex, FromHexError, ToHex};
 use exonum::encoding::Field;
 use exonum::storage::{StorageKey, StorageValue};
 
-use super::HexValueEx;
+use super::{Chain, HexValueEx};
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
 pub struct TxId(Sha256dHash);
@@ -39,8 +39,19 @@ pub struct TxId(Sha256dHash);
 pub struct PrivateKey(pub RawPrivkey);
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
 pub struct PublicKey(pub RawPublicKey);
-#[derive(Clone, PartialEq, Eq)]
-pub struct Address(pub RawAddress);
+/// Bitcoin address, either the base58 one or the native segwit one.
+#[derive(Clone, Eq)]
+pub enum Address {
+    /// Base58 address, for example, the `p2sh` one.
+    Base58(RawAddress),
+    /// Native segwit `p2wsh` address.
+    Witness {
+        /// Chain which defines the human readable part of the address.
+        chain: Chain,
+        /// `sha256` hash of the witness script.
+        script_hash: Hash,
+    },
+}
 #[derive(Debug, Clone, PartialEq)]
 pub struct RedeemScript(pub RawScript);
 
@@ -48,17 +59,14 @@ pub type Signature = Vec<u8>;
 
 implement_wrapper! {Sha256dHash, TxId}
 implement_wrapper! {RawPublicKey, PublicKey}
-implement_wrapper! {RawAddress, Address}
 implement_wrapper! {RawPrivkey, PrivateKey}
 implement_wrapper! {RawScript, RedeemScript}
 
-implement_base58_wrapper! {RawAddress, Address}
 implement_base58_wrapper! {RawPrivkey, PrivateKey}
 
 implement_serde_hex! {PublicKey}
 implement_serde_hex! {RedeemScript}
 implement_serde_hex! {TxId}
-implement_serde_base58check! {Address}
 implement_serde_base58check! {PrivateKey}
 
 implement_pod_as_ref_field! { TxId }
@@ -0,0 +1,173 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin backend which never broadcasts the transactions.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{other_error, AnchoringRpcConfig, BitcoinRelay, Result, TxInfo};
+
+/// Bitcoin backend that forwards all requests to the underlying backend except
+/// the broadcasting of the transactions.
+///
+/// The transactions are logged with their raw bodies instead of the broadcasting, and
+/// afterwards they are returned by this backend as unconfirmed ones, so the anchoring
+/// pipeline proceeds as if the transactions were sent to the bitcoin network.
+#[derive(Debug)]
+pub struct DryRunRelay {
+    relay: Box<BitcoinRelay>,
+    sent: Mutex<HashMap<btc::TxId, BitcoinTx>>,
+}
+
+impl DryRunRelay {
+    /// Creates a backend that never broadcasts the transactions by the given `relay`.
+    pub fn new(relay: Box<BitcoinRelay>) -> DryRunRelay {
+        DryRunRelay {
+            relay,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the transactions which would have been broadcast.
+    pub fn sent_transactions(&self) -> Vec<BitcoinTx> {
+        self.sent.lock().unwrap().values().cloned().collect()
+    }
+
+    fn sent_transaction(&self, txid: btc::TxId) -> Option<BitcoinTx> {
+        self.sent.lock().unwrap().get(&txid).cloned()
+    }
+}
+
+impl BitcoinRelay for DryRunRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        match self.sent_transaction(txid) {
+            Some(tx) => Ok(Some(tx)),
+            None => self.relay.get_transaction(txid),
+        }
+    }
+
+    fn get_transactions(&self, txids: &[btc::TxId]) -> Result<Vec<Option<BitcoinTx>>> {
+        let mut txs = self.relay.get_transactions(txids)?;
+        for (tx, txid) in txs.iter_mut().zip(txids) {
+            if tx.is_none() {
+                *tx = self.sent_transaction(*txid);
+            }
+        }
+        Ok(txs)
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        match self.sent_transaction(txid) {
+            Some(body) => Ok(Some(TxInfo {
+                body,
+                confirmations: None,
+            })),
+            None => self.relay.get_transaction_info(txid),
+        }
+    }
+
+    fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
+        self.relay.watch_address(addr, rescan)
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.relay.watch_descriptor(addr, descriptor, rescan_from)
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        info!(
+            "Dry run, the transaction is not broadcast, txid={}, raw={}",
+            tx.id(),
+            tx.to_string()
+        );
+        self.sent.lock().unwrap().insert(tx.id(), tx);
+        Ok(())
+    }
+
+    fn send_to_address(&self, _addr: &btc::Address, _satoshis: u64) -> Result<FundingTx> {
+        Err(other_error("Funds are never sent in the dry run mode"))
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        self.relay.unspent_transactions(addr)
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        self.relay.unspent_outputs(addr)
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        self.relay.estimate_fee_rate(conf_target)
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.relay.tip_height()
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        self.relay.chain()
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        self.relay.verification_progress()
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        self.relay.get_transaction_proof(txid)
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        self.relay.get_block_headers(block_hash, count)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        self.relay.config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc;
+    use details::mock::{sample_tx, MockRelay};
+    use details::rpc::BitcoinRelay;
+
+    use super::DryRunRelay;
+
+    #[test]
+    fn test_dry_run_send_transaction() {
+        let tx = sample_tx();
+        let mock = MockRelay::new("mock");
+        let relay = DryRunRelay::new(Box::new(mock.clone()));
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), None);
+
+        relay.send_transaction(tx.clone()).unwrap();
+        assert_eq!(relay.sent_transactions(), vec![tx.clone()]);
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx.clone()));
+        assert_eq!(relay.get_transactions(&[tx.id()]).unwrap(), vec![Some(tx.clone())]);
+        assert_eq!(relay.get_transaction_confirmations(tx.id()).unwrap(), None);
+
+        let addr = "2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA".parse::<btc::Address>().unwrap();
+        assert!(relay.send_to_address(&addr, 1000).is_err());
+        // Nothing reaches the bitcoin node.
+        assert!(mock.transactions().is_empty());
+    }
+}
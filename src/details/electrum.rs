@@ -0,0 +1,403 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client for the [Electrum protocol][1] servers.
+//!
+//! [1]: https://electrumx.readthedocs.io/en/latest/protocol.html
+
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use serde_json;
+use serde_json::value::{from_value, Value};
+
+use exonum::crypto::hash;
+use exonum::encoding::serialize::{encode_hex, FromHex};
+
+use details::btc;
+use details::btc::types::RawScript;
+use details::btc::transactions::{BitcoinTx, FundingTx, TxKind};
+use details::rpc::{AnchoringRpcConfig, BitcoinRelay, Error, Result, TxInfo};
+use details::rpc::{fee_rate_from_btc_per_kb, io_error, json_error, other_error};
+
+/// Electrum server configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ElectrumConfig {
+    /// Address of the Electrum server in the `host:port` format.
+    pub host: String,
+}
+
+/// Client for the Electrum server.
+///
+/// Electrum server has no wallet, so this client can only follow and verify the anchoring
+/// chain and broadcast anchoring transactions, but it cannot create funding transactions.
+#[derive(Debug)]
+pub struct ElectrumClient {
+    config: ElectrumConfig,
+    connection: Mutex<Option<Connection>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: BufReader<TcpStream>,
+    next_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnspentEntry {
+    tx_hash: String,
+    tx_pos: u32,
+    height: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryEntry {
+    tx_hash: String,
+    height: i64,
+}
+
+impl Connection {
+    fn open(host: &str) -> Result<Connection> {
+        let stream = TcpStream::connect(host).map_err(io_error)?;
+        Ok(Connection {
+            stream: BufReader::new(stream),
+            next_id: 0,
+        })
+    }
+
+    fn call(&mut self, method: &str, params: &[Value]) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        let mut request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }).to_string();
+        request.push('\n');
+        self.stream.get_mut().write_all(request.as_bytes()).map_err(
+            io_error,
+        )?;
+
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).map_err(io_error)? == 0 {
+                let e = io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed by the Electrum server",
+                );
+                return Err(io_error(e));
+            }
+
+            let response: Value = serde_json::from_str(&line).map_err(json_error)?;
+            // Skip subscription notifications.
+            if response.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                if !error.is_null() {
+                    let message = error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .map(ToOwned::to_owned)
+                        .unwrap_or_else(|| error.to_string());
+                    // Electrum servers do not provide error codes compatible with `bitcoind`.
+                    return Err(Error::NoInformation(message));
+                }
+            }
+            return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+}
+
+impl ElectrumClient {
+    /// Creates a client for the Electrum server with the given configuration.
+    /// Connection is established lazily on the first request.
+    pub fn new(config: ElectrumConfig) -> ElectrumClient {
+        ElectrumClient {
+            config,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Returns the height of the best block known by the server.
+    pub fn best_block_height(&self) -> Result<u64> {
+        let header: Value = self.request("blockchain.headers.subscribe", vec![])?;
+        header
+            .get("height")
+            .or_else(|| header.get("block_height"))
+            .and_then(Value::as_u64)
+            .ok_or_else(|| {
+                other_error(format!("Unexpected block header format: {}", header))
+            })
+    }
+
+    fn request<T>(&self, method: &str, params: Vec<Value>) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let value = {
+            let mut connection = self.connection.lock().unwrap();
+            if connection.is_none() {
+                *connection = Some(Connection::open(&self.config.host)?);
+            }
+
+            let result = connection.as_mut().unwrap().call(method, &params);
+            // Reconnect on the next request if the connection is broken.
+            if let Err(Error::Other(_)) = result {
+                *connection = None;
+            }
+            result?
+        };
+        trace!(
+            "Electrum method: {}, params={:?}, response={:#}",
+            method,
+            params,
+            value
+        );
+        from_value(value).map_err(json_error)
+    }
+
+    fn confirmations(&self, height: i64) -> Result<Option<u64>> {
+        // Zero or negative heights are used for the mempool transactions.
+        if height <= 0 {
+            return Ok(None);
+        }
+        let best_height = self.best_block_height()?;
+        Ok(Some((best_height + 1).saturating_sub(height as u64)))
+    }
+}
+
+impl From<ElectrumConfig> for ElectrumClient {
+    fn from(config: ElectrumConfig) -> ElectrumClient {
+        ElectrumClient::new(config)
+    }
+}
+
+impl BitcoinRelay for ElectrumClient {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        let params = vec![Value::String(txid.to_string())];
+        match self.request::<String>("blockchain.transaction.get", params) {
+            Ok(tx) => {
+                let tx = BitcoinTx::from_hex(&tx).map_err(|e| {
+                    other_error(format!("Unable to decode transaction {}: {}", txid, e))
+                })?;
+                Ok(Some(tx))
+            }
+            Err(Error::NoInformation(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        let tx = match self.get_transaction(txid)? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+
+        // Electrum servers only know the heights of transactions that belong to the history
+        // of some script, the anchoring output is not necessarily the first one.
+        let txid_hex = txid.to_string();
+        let mut confirmations = None;
+        for output in &tx.output {
+            let params = vec![Value::String(script_hash(&output.script_pubkey))];
+            let history: Vec<HistoryEntry> =
+                self.request("blockchain.scripthash.get_history", params)?;
+            if let Some(entry) = history.iter().find(|entry| entry.tx_hash == txid_hex) {
+                confirmations = self.confirmations(entry.height)?;
+                break;
+            }
+        }
+        Ok(Some(TxInfo {
+            body: tx,
+            confirmations,
+        }))
+    }
+
+    fn watch_address(&self, addr: &btc::Address, _rescan: bool) -> Result<()> {
+        // Address state notifications are not necessary for the anchoring, since both
+        // handler and observer poll the server. Subscription only checks the script hash.
+        let params = vec![Value::String(script_hash(&addr.script_pubkey()))];
+        let _: Value = self.request("blockchain.scripthash.subscribe", params)?;
+        Ok(())
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        let params = vec![Value::String(tx.to_hex())];
+        let _: String = self.request("blockchain.transaction.broadcast", params)?;
+        Ok(())
+    }
+
+    fn send_to_address(&self, _addr: &btc::Address, _satoshis: u64) -> Result<FundingTx> {
+        Err(Error::NoInformation(
+            "Electrum server is unable to send funds".to_string(),
+        ))
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        let params = vec![Value::String(script_hash(&addr.script_pubkey()))];
+        let unspent_txs: Vec<UnspentEntry> =
+            self.request("blockchain.scripthash.listunspent", params)?;
+
+        let mut txs = Vec::new();
+        for info in unspent_txs {
+            let txid = btc::TxId::from_hex(&info.tx_hash).map_err(|e| {
+                other_error(format!("Unexpected txid {}: {}", info.tx_hash, e))
+            })?;
+            // Unlike `bitcoind` Electrum reports mempool transactions with negative heights.
+            let confirmations = self.confirmations(info.height)?.or(Some(0));
+            if let Some(raw_tx) = self.get_transaction(txid)? {
+                match TxKind::from(raw_tx) {
+                    TxKind::Anchoring(tx) => {
+                        txs.push(TxInfo {
+                            body: tx.into(),
+                            confirmations,
+                        })
+                    }
+                    TxKind::FundingTx(tx) => {
+                        txs.push(TxInfo {
+                            body: tx.into(),
+                            confirmations,
+                        })
+                    }
+                    TxKind::Other(_) => {}
+                }
+            }
+        }
+        Ok(txs)
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let params = vec![Value::String(script_hash(&addr.script_pubkey()))];
+        let unspent_txs: Vec<UnspentEntry> =
+            self.request("blockchain.scripthash.listunspent", params)?;
+        unspent_txs
+            .into_iter()
+            .map(|info| {
+                let txid = btc::TxId::from_hex(&info.tx_hash).map_err(|e| {
+                    other_error(format!("Unexpected txid {}: {}", info.tx_hash, e))
+                })?;
+                Ok((txid, info.tx_pos))
+            })
+            .collect()
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        // Electrum server returns `-1` if it is unable to estimate the fee rate.
+        let fee_rate: f64 = self.request("blockchain.estimatefee", vec![json!(conf_target)])?;
+        if fee_rate < 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(fee_rate_from_btc_per_kb(fee_rate)))
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.best_block_height().map(Some)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        AnchoringRpcConfig {
+            host: self.config.host.clone(),
+            username: None,
+            password: None,
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the Electrum script hash, which is the reversed `sha256` hash of the script.
+pub fn script_hash(script: &RawScript) -> String {
+    let mut bytes = hash(&script.clone().into_vec()).as_ref().to_vec();
+    bytes.reverse();
+    encode_hex(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use bitcoin::blockdata::script::Script;
+    use serde_json;
+
+    use exonum::encoding::serialize::FromHex;
+
+    use details::btc::HexValueEx;
+    use details::btc::transactions::BitcoinTx;
+    use details::rpc::BitcoinRelay;
+
+    use super::{script_hash, ElectrumClient, ElectrumConfig};
+
+    #[test]
+    fn test_electrum_script_hash() {
+        // Example from the Electrum protocol documentation.
+        let script = Script::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac")
+            .unwrap();
+        assert_eq!(
+            script_hash(&script),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+
+    #[test]
+    fn test_electrum_transaction_info_by_any_output() {
+        let tx = BitcoinTx::from_hex(
+            "01000000019aaf09d7e73a5f9ab394f1358bfb3dbde7b15b983d715f\
+             5c98f369a3f0a288a70000000000ffffffff02b80b00000000000017a914f18eb74087f751109cc9052befd417\
+             7a52c9a30a8700000000000000002c6a2a012800000000000000007fab6f66a0f7a747c820cd01fa30d7bdebd2\
+             6b91c6e03f742abac0b3108134d900000000",
+        ).unwrap();
+        let txid = tx.id();
+
+        // Emulates the server which knows the transaction only by its second output.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let known_script = script_hash(&tx.output[1].script_pubkey);
+        let (tx_hex, txid_hex) = (tx.to_hex(), txid.to_string());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let is_known = request["params"][0] == json!(known_script);
+                let result = match request["method"].as_str().unwrap() {
+                    "blockchain.transaction.get" => json!(tx_hex),
+                    "blockchain.scripthash.get_history" if is_known => {
+                        json!([{ "tx_hash": txid_hex, "height": 100 }])
+                    }
+                    "blockchain.scripthash.get_history" => json!([]),
+                    "blockchain.headers.subscribe" => json!({ "height": 109 }),
+                    method => panic!("Unexpected method {}", method),
+                };
+                let response = json!({ "id": request["id"], "result": result });
+                let response = format!("{}\n", response);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                line.clear();
+            }
+        });
+
+        let client = ElectrumClient::new(ElectrumConfig { host });
+        let info = client.get_transaction_info(txid).unwrap().unwrap();
+        assert_eq!(info.body, tx);
+        assert_eq!(info.confirmations, Some(10));
+        drop(client);
+        server.join().unwrap();
+    }
+}
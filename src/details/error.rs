@@ -23,6 +23,8 @@ pub enum Error {
     Rpc(RpcError),
     /// Insufficient funds to create anchoring transaction.
     InsufficientFunds,
+    /// Block height does not fit the payload of anchoring transaction.
+    PayloadHeightOverflow,
     /// An input output error.
     Io(io::Error),
 }
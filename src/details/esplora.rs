@@ -0,0 +1,298 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client for the [Esplora][1] HTTP API.
+//!
+//! [1]: https://github.com/Blockstream/esplora/blob/master/API.md
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use hyper;
+use hyper::client::Response;
+use hyper::net::HttpsConnector;
+use hyper::status::StatusCode;
+use hyper_native_tls::NativeTlsClient;
+use serde_json;
+
+use exonum::encoding::serialize::FromHex;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx, TxKind};
+use details::rpc::{AnchoringRpcConfig, BitcoinRelay, Result, TxInfo};
+use details::rpc::{io_error, json_error, other_error};
+
+/// Esplora API configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EsploraConfig {
+    /// Base url of the API, for example `https://blockstream.info/testnet/api`.
+    pub url: String,
+}
+
+/// Client for the Esplora HTTP API.
+///
+/// Esplora indexes all addresses, so there is no need to import the anchoring addresses,
+/// but it has no wallet and cannot create funding transactions.
+pub struct EsploraClient {
+    config: EsploraConfig,
+    client: hyper::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxStatus {
+    confirmed: bool,
+    block_height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Utxo {
+    txid: String,
+    vout: u32,
+    status: TxStatus,
+}
+
+impl EsploraClient {
+    /// Creates a client for the Esplora API with the given configuration.
+    pub fn new(config: EsploraConfig) -> EsploraClient {
+        let tls = NativeTlsClient::new().expect("Unable to initialize TLS client");
+        let client = hyper::Client::with_connector(HttpsConnector::new(tls));
+        EsploraClient { config, client }
+    }
+
+    /// Returns the height of the best block known by the API.
+    pub fn best_block_height(&self) -> Result<u64> {
+        let height = self.get("blocks/tip/height")?.ok_or_else(|| {
+            other_error("Tip height is not found")
+        })?;
+        height.trim().parse().map_err(|_| {
+            other_error(format!("Unexpected tip height format: {}", height))
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.config.url.trim_right_matches('/'), path)
+    }
+
+    fn get(&self, path: &str) -> Result<Option<String>> {
+        let response = self.client.get(&self.url(path)).send().map_err(
+            other_error,
+        )?;
+        read_response(path, response)
+    }
+
+    fn post(&self, path: &str, body: &str) -> Result<Option<String>> {
+        let response = self.client
+            .post(&self.url(path))
+            .body(body)
+            .send()
+            .map_err(other_error)?;
+        read_response(path, response)
+    }
+
+    fn confirmations(&self, status: &TxStatus) -> Result<Option<u64>> {
+        match (status.confirmed, status.block_height) {
+            (true, Some(height)) => {
+                let best_height = self.best_block_height()?;
+                Ok(Some((best_height + 1).saturating_sub(height)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for EsploraClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EsploraClient")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl From<EsploraConfig> for EsploraClient {
+    fn from(config: EsploraConfig) -> EsploraClient {
+        EsploraClient::new(config)
+    }
+}
+
+impl BitcoinRelay for EsploraClient {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        let tx = match self.get(&format!("tx/{}/hex", txid.to_string()))? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let tx = BitcoinTx::from_hex(tx.trim()).map_err(|e| {
+            other_error(format!("Unable to decode transaction {}: {}", txid, e))
+        })?;
+        Ok(Some(tx))
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        let tx = match self.get_transaction(txid)? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let status = self.get(&format!("tx/{}/status", txid.to_string()))?
+            .ok_or_else(|| other_error("Transaction status is not found"))?;
+        let status: TxStatus = serde_json::from_str(&status).map_err(json_error)?;
+        Ok(Some(TxInfo {
+            body: tx,
+            confirmations: self.confirmations(&status)?,
+        }))
+    }
+
+    fn watch_address(&self, _addr: &btc::Address, _rescan: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.post("tx", &tx.to_hex())?.ok_or_else(|| {
+            other_error("Transaction broadcast endpoint is not found")
+        })?;
+        Ok(())
+    }
+
+    fn send_to_address(&self, _addr: &btc::Address, _satoshis: u64) -> Result<FundingTx> {
+        Err(other_error("Esplora API is unable to send funds"))
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        let unspent_txs = match self.get(&format!("address/{}/utxo", addr.to_string()))? {
+            Some(body) => serde_json::from_str::<Vec<Utxo>>(&body).map_err(json_error)?,
+            None => Vec::new(),
+        };
+
+        let mut txs = Vec::new();
+        for info in unspent_txs {
+            let txid = btc::TxId::from_hex(&info.txid).map_err(|e| {
+                other_error(format!("Unexpected txid {}: {}", info.txid, e))
+            })?;
+            // Unlike `getrawtransaction` the `listunspent` reports zero confirmations
+            // for the mempool transactions.
+            let confirmations = self.confirmations(&info.status)?.or(Some(0));
+            if let Some(raw_tx) = self.get_transaction(txid)? {
+                match TxKind::from(raw_tx) {
+                    TxKind::Anchoring(tx) => {
+                        txs.push(TxInfo {
+                            body: tx.into(),
+                            confirmations,
+                        })
+                    }
+                    TxKind::FundingTx(tx) => {
+                        txs.push(TxInfo {
+                            body: tx.into(),
+                            confirmations,
+                        })
+                    }
+                    TxKind::Other(_) => {}
+                }
+            }
+        }
+        Ok(txs)
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let unspent_txs = match self.get(&format!("address/{}/utxo", addr.to_string()))? {
+            Some(body) => serde_json::from_str::<Vec<Utxo>>(&body).map_err(json_error)?,
+            None => Vec::new(),
+        };
+        unspent_txs
+            .into_iter()
+            .map(|info| {
+                let txid = btc::TxId::from_hex(&info.txid).map_err(|e| {
+                    other_error(format!("Unexpected txid {}: {}", info.txid, e))
+                })?;
+                Ok((txid, info.vout))
+            })
+            .collect()
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        let estimates = match self.get("fee-estimates")? {
+            Some(body) => {
+                serde_json::from_str::<HashMap<String, f64>>(&body).map_err(json_error)?
+            }
+            None => return Ok(None),
+        };
+        // Esplora reports fee rates in satoshis per virtual byte.
+        let fee_rate = estimates.get(&conf_target.to_string()).map(|rate| rate.ceil() as u64);
+        Ok(fee_rate)
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.best_block_height().map(Some)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        AnchoringRpcConfig {
+            host: self.config.url.clone(),
+            username: None,
+            password: None,
+            ..Default::default()
+        }
+    }
+}
+
+fn read_response(path: &str, mut response: Response) -> Result<Option<String>> {
+    let mut body = String::new();
+    response.read_to_string(&mut body).map_err(io_error)?;
+    trace!(
+        "Esplora path: {}, status={}, response={}",
+        path,
+        response.status,
+        body
+    );
+    match response.status {
+        StatusCode::Ok => Ok(Some(body)),
+        StatusCode::NotFound => Ok(None),
+        status => Err(other_error(format!("{}: {}", status, body))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::Utxo;
+
+    #[test]
+    fn test_esplora_utxo_deserialize() {
+        let utxos: Vec<Utxo> = serde_json::from_str(
+            r#"[
+                {
+                    "txid": "fea0a3b7e4c9d2b4cb0d4d8ab7f4c2e16ad7a6b1e4d1ce9a26d5b4e1b7a3c2d1",
+                    "vout": 0,
+                    "status": {
+                        "confirmed": true,
+                        "block_height": 1257760,
+                        "block_time": 1513160232
+                    },
+                    "value": 400000
+                },
+                {
+                    "txid": "a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90",
+                    "vout": 1,
+                    "status": { "confirmed": false },
+                    "value": 10000
+                }
+            ]"#,
+        ).unwrap();
+
+        assert_eq!(utxos.len(), 2);
+        assert!(utxos[0].status.confirmed);
+        assert_eq!(utxos[0].status.block_height, Some(1_257_760));
+        assert!(!utxos[1].status.confirmed);
+        assert_eq!(utxos[1].status.block_height, None);
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin backend with several endpoints and automatic failover.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{is_transient_error, other_error, AnchoringRpcConfig, BitcoinRelay, Result,
+                   TxInfo};
+
+/// Interval between the health checks of the endpoints in seconds.
+pub const HEALTH_CHECK_INTERVAL: u64 = 60;
+/// Maximal number of blocks an endpoint may lag behind the best known one.
+pub const MAX_TIP_LAG: u64 = 2;
+
+/// Bitcoin backend that forwards requests to the active endpoint and switches
+/// to the next one if the active endpoint is unreachable.
+///
+/// Endpoints are ordered by priority. The health check periodically selects the first
+/// reachable endpoint which does not lag behind the others by more than
+/// [`MAX_TIP_LAG`](constant.MAX_TIP_LAG.html) blocks. The periodic check is performed
+/// on a background thread, so the requests never wait for the unreachable endpoints
+/// besides the active one. If the chain of the backend is specified, the endpoints
+/// connected to another chain are skipped until the next health check.
+#[derive(Debug)]
+pub struct FailoverRelay {
+    relays: Arc<Vec<Box<BitcoinRelay>>>,
+    chain: Option<btc::Chain>,
+    state: Arc<Mutex<FailoverState>>,
+}
+
+#[derive(Debug)]
+struct FailoverState {
+    active: usize,
+    last_check: Option<Instant>,
+    checking: bool,
+    // Endpoints connected to another chain.
+    foreign: Vec<bool>,
+}
+
+impl FailoverRelay {
+    /// Creates a backend with the given endpoints ordered by priority.
+    ///
+    /// # Panics
+    ///
+    /// If the `relays` list is empty.
+    pub fn new(relays: Vec<Box<BitcoinRelay>>) -> FailoverRelay {
+        assert!(!relays.is_empty(), "Bitcoin backend endpoints list is empty");
+        let foreign = vec![false; relays.len()];
+        FailoverRelay {
+            relays: Arc::new(relays),
+            chain: None,
+            state: Arc::new(Mutex::new(FailoverState {
+                active: 0,
+                last_check: None,
+                checking: false,
+                foreign,
+            })),
+        }
+    }
+
+    /// Sets the chain to which the endpoints must be connected.
+    pub fn with_chain(mut self, chain: btc::Chain) -> FailoverRelay {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Returns the index of the active endpoint.
+    pub fn active(&self) -> usize {
+        self.state.lock().unwrap().active
+    }
+
+    /// Checks the endpoints and selects the active one.
+    pub fn health_check(&self) {
+        health_check(&self.relays, self.chain, &self.state)
+    }
+
+    // Starts the health check on a background thread if it is time for the next one.
+    fn schedule_health_check(&self) {
+        if self.relays.len() < 2 {
+            return;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            let check_needed = match state.last_check {
+                Some(last_check) => {
+                    last_check.elapsed() >= Duration::from_secs(HEALTH_CHECK_INTERVAL)
+                }
+                None => true,
+            };
+            if !check_needed || state.checking {
+                return;
+            }
+            state.checking = true;
+        }
+        let relays = Arc::clone(&self.relays);
+        let chain = self.chain;
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || health_check(&relays, chain, &state));
+    }
+
+    // Every endpoint must watch the anchoring addresses to be ready for the failover.
+    fn watch_by_all<F>(&self, addr: &btc::Address, watch: F) -> Result<()>
+    where
+        F: Fn(&BitcoinRelay) -> Result<()>,
+    {
+        for relay in self.relays.iter() {
+            if let Err(e) = watch(relay.as_ref()) {
+                if !is_transient_error(&e) {
+                    return Err(e);
+                }
+                warn!(
+                    "Unable to watch address={} by host={}: {}",
+                    addr.to_string(),
+                    relay.config().host,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn request<T, F>(&self, f: F) -> Result<T>
+    where
+        F: Fn(&BitcoinRelay) -> Result<T>,
+    {
+        self.schedule_health_check();
+
+        let (active, foreign) = {
+            let state = self.state.lock().unwrap();
+            (state.active, state.foreign.clone())
+        };
+        let mut last_error = None;
+        for i in 0..self.relays.len() {
+            let index = (active + i) % self.relays.len();
+            if foreign[index] {
+                continue;
+            }
+            match f(self.relays[index].as_ref()) {
+                Ok(value) => {
+                    switch(&self.relays, &mut self.state.lock().unwrap(), index);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    if !is_transient_error(&e) {
+                        return Err(e);
+                    }
+                    warn!(
+                        "Bitcoin backend host={} is unreachable: {}",
+                        self.relays[index].config().host,
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            other_error("No bitcoin backend endpoint is connected to the anchoring chain")
+        }))
+    }
+}
+
+fn health_check(
+    relays: &[Box<BitcoinRelay>],
+    chain: Option<btc::Chain>,
+    state: &Mutex<FailoverState>,
+) {
+    let foreign = relays
+        .iter()
+        .map(|relay| is_foreign(relay.as_ref(), chain))
+        .collect::<Vec<_>>();
+    let heights = relays
+        .iter()
+        .zip(&foreign)
+        .map(|(relay, &foreign)| {
+            if foreign {
+                return None;
+            }
+            match relay.tip_height() {
+                Ok(height) => Some(height),
+                Err(e) => {
+                    warn!(
+                        "Bitcoin backend host={} is unhealthy: {}",
+                        relay.config().host,
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let best_height = heights.iter().filter_map(|h| h.and_then(|h| h)).max();
+
+    let healthy = heights.iter().position(|height| match (*height, best_height) {
+        (Some(Some(height)), Some(best_height)) => height + MAX_TIP_LAG >= best_height,
+        (Some(_), _) => true,
+        (None, _) => false,
+    });
+
+    let mut state = state.lock().unwrap();
+    state.last_check = Some(Instant::now());
+    state.checking = false;
+    state.foreign = foreign;
+    if let Some(healthy) = healthy {
+        switch(relays, &mut state, healthy);
+    }
+}
+
+// The endpoint which is unable to report its chain is not considered as a foreign one,
+// the unreachable endpoints are detected by the tip height request.
+fn is_foreign(relay: &BitcoinRelay, chain: Option<btc::Chain>) -> bool {
+    let chain = match chain {
+        Some(chain) => chain,
+        None => return false,
+    };
+    match relay.chain() {
+        Ok(Some(other)) if other != chain => {
+            warn!(
+                "Bitcoin backend host={} is connected to the {} chain instead of {}",
+                relay.config().host,
+                other,
+                chain
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+fn switch(relays: &[Box<BitcoinRelay>], state: &mut FailoverState, active: usize) {
+    if state.active != active {
+        warn!(
+            "Switching bitcoin backend from host={} to host={}",
+            relays[state.active].config().host,
+            relays[active].config().host
+        );
+        state.active = active;
+    }
+}
+
+impl BitcoinRelay for FailoverRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        self.request(|relay| relay.get_transaction(txid))
+    }
+
+    fn get_transactions(&self, txids: &[btc::TxId]) -> Result<Vec<Option<BitcoinTx>>> {
+        self.request(|relay| relay.get_transactions(txids))
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        self.request(|relay| relay.get_transaction_info(txid))
+    }
+
+    fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
+        self.watch_by_all(addr, |relay| relay.watch_address(addr, rescan))
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.watch_by_all(addr, |relay| {
+            relay.watch_descriptor(addr, descriptor, rescan_from)
+        })
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.request(|relay| relay.send_transaction(tx.clone()))
+    }
+
+    fn send_to_address(&self, addr: &btc::Address, satoshis: u64) -> Result<FundingTx> {
+        self.request(|relay| relay.send_to_address(addr, satoshis))
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        self.request(|relay| relay.unspent_transactions(addr))
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        self.request(|relay| relay.unspent_outputs(addr))
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        self.request(|relay| relay.estimate_fee_rate(conf_target))
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.request(|relay| relay.tip_height())
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        self.request(|relay| relay.chain())
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        self.request(|relay| relay.verification_progress())
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        self.request(|relay| relay.get_transaction_proof(txid))
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        self.request(|relay| relay.get_block_headers(block_hash, count))
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        self.relays[self.active()].config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc;
+    use details::mock::MockRelay;
+    use details::rpc::BitcoinRelay;
+
+    use super::FailoverRelay;
+
+    fn failover_relay(heights: &[Option<u64>]) -> FailoverRelay {
+        let hosts = ["primary", "secondary", "tertiary"];
+        let relays = heights
+            .iter()
+            .zip(hosts.iter())
+            .map(|(height, host)| {
+                Box::new(MockRelay::new(host).with_height(*height)) as Box<BitcoinRelay>
+            })
+            .collect();
+        FailoverRelay::new(relays)
+    }
+
+    #[test]
+    fn test_failover_unreachable_primary() {
+        let relay = failover_relay(&[None, Some(100)]);
+        assert_eq!(relay.tip_height().unwrap(), Some(100));
+        assert_eq!(relay.active(), 1);
+        assert_eq!(relay.config().host, "secondary");
+    }
+
+    #[test]
+    fn test_failover_lagging_primary() {
+        let relay = failover_relay(&[Some(90), Some(99), Some(100)]);
+        relay.health_check();
+        assert_eq!(relay.active(), 1);
+
+        let relay = failover_relay(&[Some(98), Some(100)]);
+        relay.health_check();
+        assert_eq!(relay.active(), 0);
+    }
+
+    fn chain_relay(host: &str, chain: btc::Chain) -> Box<BitcoinRelay> {
+        Box::new(MockRelay::new(host).with_chain(chain))
+    }
+
+    #[test]
+    fn test_failover_foreign_chain() {
+        let relays = vec![
+            chain_relay("primary", btc::Chain::Mainnet),
+            chain_relay("secondary", btc::Chain::Testnet),
+        ];
+        let relay = FailoverRelay::new(relays).with_chain(btc::Chain::Testnet);
+        relay.health_check();
+        assert_eq!(relay.active(), 1);
+        assert_eq!(relay.chain().unwrap(), Some(btc::Chain::Testnet));
+        assert_eq!(relay.active(), 1);
+
+        let relays = vec![chain_relay("primary", btc::Chain::Mainnet)];
+        let relay = FailoverRelay::new(relays).with_chain(btc::Chain::Testnet);
+        relay.health_check();
+        assert!(relay.tip_height().is_err());
+    }
+
+    #[test]
+    fn test_failover_all_unreachable() {
+        let relay = failover_relay(&[None, None]);
+        assert!(relay.tip_height().is_err());
+        assert_eq!(relay.active(), 0);
+    }
+}
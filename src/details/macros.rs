@@ -201,6 +201,12 @@ macro_rules! implement_tx_wrapper {
             self.write_hex(&mut out).unwrap();
             out
         }
+
+        /// Returns the virtual size of the transaction in bytes, that is its weight
+        /// divided by four, so the witness data is counted with a quarter of its size.
+        pub fn vsize(&self) -> u64 {
+            tx_vsize(&self.0)
+        }
     }
 
 
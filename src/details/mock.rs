@@ -0,0 +1,171 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory bitcoin relay shared by the tests of the relay wrappers.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use exonum::encoding::serialize::FromHex;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{io_error, other_error, AnchoringRpcConfig, BitcoinRelay, Result, TxInfo};
+
+/// Relay that keeps the known transactions in memory and counts the read requests.
+///
+/// The transactions sent to the relay become known, all of them are unspent.
+/// The relay has no wallet, so the funding transactions cannot be created by it.
+/// The relay without the tip height is unreachable and fails all requests.
+#[derive(Debug, Clone)]
+pub struct MockRelay {
+    host: String,
+    height: Option<u64>,
+    chain: Option<btc::Chain>,
+    txs: Arc<Mutex<Vec<BitcoinTx>>>,
+    requests: Arc<Mutex<u64>>,
+}
+
+impl MockRelay {
+    /// Creates the reachable relay with the given host and without the known transactions.
+    pub fn new(host: &str) -> MockRelay {
+        MockRelay {
+            host: host.to_string(),
+            height: Some(0),
+            chain: None,
+            txs: Arc::new(Mutex::new(Vec::new())),
+            requests: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Sets the tip height, the relay without it is unreachable.
+    pub fn with_height(mut self, height: Option<u64>) -> MockRelay {
+        self.height = height;
+        self
+    }
+
+    /// Sets the chain reported by the relay.
+    pub fn with_chain(mut self, chain: btc::Chain) -> MockRelay {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Adds the known transaction.
+    pub fn with_tx(self, tx: BitcoinTx) -> MockRelay {
+        self.txs.lock().unwrap().push(tx);
+        self
+    }
+
+    /// Returns the known transactions in the order of their receiving.
+    pub fn transactions(&self) -> Vec<BitcoinTx> {
+        self.txs.lock().unwrap().clone()
+    }
+
+    /// Returns the number of the received read requests.
+    pub fn requests(&self) -> u64 {
+        *self.requests.lock().unwrap()
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.height.is_none() {
+            let e = io::Error::new(io::ErrorKind::ConnectionRefused, "Connection refused");
+            return Err(io_error(e));
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<()> {
+        self.check()?;
+        *self.requests.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    fn unspent(&self) -> Vec<TxInfo> {
+        self.transactions()
+            .into_iter()
+            .map(|body| TxInfo {
+                body,
+                confirmations: Some(1),
+            })
+            .collect()
+    }
+}
+
+impl BitcoinRelay for MockRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        self.read()?;
+        Ok(self.transactions().into_iter().find(|tx| tx.id() == txid))
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        self.read()?;
+        Ok(self.unspent().into_iter().find(|info| info.body.id() == txid))
+    }
+
+    fn watch_address(&self, _addr: &btc::Address, _rescan: bool) -> Result<()> {
+        self.check()
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.check()?;
+        let mut txs = self.txs.lock().unwrap();
+        if txs.contains(&tx) {
+            return Err(other_error("transaction already in block chain"));
+        }
+        txs.push(tx);
+        Ok(())
+    }
+
+    fn send_to_address(&self, _addr: &btc::Address, _satoshis: u64) -> Result<FundingTx> {
+        self.check()?;
+        Err(other_error("mock relay has no wallet"))
+    }
+
+    fn unspent_transactions(&self, _addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        self.read()?;
+        Ok(self.unspent())
+    }
+
+    fn unspent_outputs(&self, _addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        self.check()?;
+        Ok(self.transactions().iter().map(|tx| (tx.id(), 0)).collect())
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.check().map(|_| self.height)
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        self.check().map(|_| self.chain)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        AnchoringRpcConfig {
+            host: self.host.clone(),
+            username: None,
+            password: None,
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the sample anchoring transaction.
+pub fn sample_tx() -> BitcoinTx {
+    BitcoinTx::from_hex(
+        "01000000019aaf09d7e73a5f9ab394f1358bfb3dbde7b15b983d715f\
+         5c98f369a3f0a288a70000000000ffffffff02b80b00000000000017a914f18eb74087f751109cc9052befd417\
+         7a52c9a30a8700000000000000002c6a2a012800000000000000007fab6f66a0f7a747c820cd01fa30d7bdebd2\
+         6b91c6e03f742abac0b3108134d900000000",
+    ).unwrap()
+}
@@ -17,7 +17,17 @@ mod macros;
 
 pub mod btc;
 pub mod rpc;
+pub mod electrum;
+pub mod esplora;
+pub mod background;
+pub mod failover;
+pub mod dry_run;
+pub mod notifications;
+pub mod webhook;
+pub mod signer;
 pub mod error;
 
+#[cfg(test)]
+pub mod mock;
 #[cfg(test)]
 pub mod tests;
@@ -0,0 +1,368 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Bitcoin backend which caches the responses until `bitcoind` notifies about changes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::mpsc;
+
+#[cfg(feature = "zmq")]
+use zmq;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{AnchoringRpcConfig, BitcoinRelay, Result, TxInfo};
+
+/// Configuration of the `bitcoind` [ZMQ notifications][1].
+///
+/// The endpoints are the ones given to `bitcoind` by the `zmqpubrawblock` and `zmqpubrawtx`
+/// options, for example, `tcp://127.0.0.1:28332`.
+///
+/// [1]: https://github.com/bitcoin/bitcoin/blob/master/doc/zmq.md
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ZmqConfig {
+    /// Endpoint of the `rawblock` notifications.
+    pub rawblock: String,
+    /// Endpoint of the `rawtx` notifications.
+    pub rawtx: String,
+}
+
+/// Notification about the changes in the bitcoin network.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// New block has been connected to the best chain.
+    Block,
+    /// Transaction has been accepted to the mempool or included into a block.
+    Transaction(BitcoinTx),
+}
+
+/// Bitcoin backend that caches the transactions and the unspent outputs of the wrapped
+/// backend and updates them only on the received notifications, so the handler does not
+/// poll the backend on every commit.
+///
+/// A new block invalidates the confirmations and the unspent outputs, a new transaction
+/// only invalidates the unspent outputs of the addresses it pays to or spends from.
+/// If the notifications sender is disconnected, the backend forwards all requests as is.
+#[derive(Debug)]
+pub struct NotifiedRelay {
+    relay: Box<BitcoinRelay>,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    notifications: mpsc::Receiver<Notification>,
+    connected: bool,
+    transactions: HashMap<String, Option<BitcoinTx>>,
+    infos: HashMap<String, Option<TxInfo>>,
+    unspent: HashMap<String, (btc::Address, Vec<TxInfo>)>,
+}
+
+impl NotifiedRelay {
+    /// Creates a backend that wraps the given `relay` and updates the cache
+    /// by the `notifications`.
+    pub fn new(
+        relay: Box<BitcoinRelay>,
+        notifications: mpsc::Receiver<Notification>,
+    ) -> NotifiedRelay {
+        let state = CacheState {
+            notifications,
+            connected: true,
+            transactions: HashMap::new(),
+            infos: HashMap::new(),
+            unspent: HashMap::new(),
+        };
+        NotifiedRelay {
+            relay,
+            state: Mutex::new(state),
+        }
+    }
+}
+
+impl CacheState {
+    // Applies the received notifications, returns false if there is no actual cache.
+    fn update(&mut self) -> bool {
+        if !self.connected {
+            return false;
+        }
+        loop {
+            match self.notifications.try_recv() {
+                Ok(Notification::Block) => self.handle_block(),
+                Ok(Notification::Transaction(tx)) => self.handle_transaction(tx),
+                Err(mpsc::TryRecvError::Empty) => return true,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    warn!("Bitcoin notifications are disconnected, switching to polling");
+                    self.connected = false;
+                    self.transactions.clear();
+                    self.infos.clear();
+                    self.unspent.clear();
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn handle_block(&mut self) {
+        self.transactions.retain(|_, tx| tx.is_some());
+        self.infos.clear();
+        self.unspent.clear();
+    }
+
+    fn handle_transaction(&mut self, tx: BitcoinTx) {
+        let txid = tx.txid();
+        self.infos.remove(&txid);
+        self.unspent.retain(|_, &mut (ref addr, ref infos)| {
+            let script_pubkey = addr.script_pubkey();
+            let pays_to = tx.output.iter().any(|out| out.script_pubkey == script_pubkey);
+            let spends_from = tx.input.iter().any(|input| {
+                let prev_txid = btc::TxId::from(input.prev_hash).to_string();
+                infos.iter().any(|info| info.body.txid() == prev_txid)
+            });
+            !pays_to && !spends_from
+        });
+        self.transactions.insert(txid, Some(tx));
+    }
+}
+
+impl BitcoinRelay for NotifiedRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        let key = txid.to_string();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.update() {
+                if let Some(tx) = state.transactions.get(&key) {
+                    return Ok(tx.clone());
+                }
+            }
+        }
+        let tx = self.relay.get_transaction(txid)?;
+        let mut state = self.state.lock().unwrap();
+        if state.update() {
+            state.transactions.insert(key, tx.clone());
+        }
+        Ok(tx)
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        let key = txid.to_string();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.update() {
+                if let Some(info) = state.infos.get(&key) {
+                    return Ok(info.clone());
+                }
+            }
+        }
+        let info = self.relay.get_transaction_info(txid)?;
+        let mut state = self.state.lock().unwrap();
+        if state.update() {
+            state.infos.insert(key, info.clone());
+        }
+        Ok(info)
+    }
+
+    fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
+        self.relay.watch_address(addr, rescan)?;
+        // The rescan may find the new outputs of the address.
+        self.state.lock().unwrap().unspent.remove(&addr.to_string());
+        Ok(())
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.relay.watch_descriptor(addr, descriptor, rescan_from)?;
+        self.state.lock().unwrap().unspent.remove(&addr.to_string());
+        Ok(())
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.relay.send_transaction(tx.clone())?;
+        // Do not wait for the notification about our own transaction.
+        let mut state = self.state.lock().unwrap();
+        if state.update() {
+            state.handle_transaction(tx);
+        }
+        Ok(())
+    }
+
+    fn send_to_address(&self, addr: &btc::Address, satoshis: u64) -> Result<FundingTx> {
+        self.relay.send_to_address(addr, satoshis)
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        let key = addr.to_string();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.update() {
+                if let Some(&(_, ref txs)) = state.unspent.get(&key) {
+                    return Ok(txs.clone());
+                }
+            }
+        }
+        let txs = self.relay.unspent_transactions(addr)?;
+        let mut state = self.state.lock().unwrap();
+        if state.update() {
+            state.unspent.insert(key, (addr.clone(), txs.clone()));
+        }
+        Ok(txs)
+    }
+
+    // The outputs are requested only to verify the proposals, so they are not cached.
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        self.relay.unspent_outputs(addr)
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        self.relay.estimate_fee_rate(conf_target)
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.relay.tip_height()
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        self.relay.chain()
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        self.relay.verification_progress()
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        self.relay.get_transaction_proof(txid)
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        self.relay.get_block_headers(block_hash, count)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        self.relay.config()
+    }
+}
+
+/// Subscribes to the `bitcoind` ZMQ notifications on the background threads.
+#[cfg(feature = "zmq")]
+pub fn subscribe(config: &ZmqConfig) -> ::std::io::Result<mpsc::Receiver<Notification>> {
+    use std::thread;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut endpoints = vec![(config.rawblock.clone(), vec!["rawblock"])];
+    if config.rawtx == config.rawblock {
+        endpoints[0].1.push("rawtx");
+    } else {
+        endpoints.push((config.rawtx.clone(), vec!["rawtx"]));
+    }
+
+    let context = zmq::Context::new();
+    for (endpoint, topics) in endpoints {
+        let socket = context.socket(zmq::SUB).map_err(zmq_error)?;
+        socket.connect(&endpoint).map_err(zmq_error)?;
+        for topic in topics {
+            socket.set_subscribe(topic.as_bytes()).map_err(zmq_error)?;
+        }
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            let message = match socket.recv_multipart(0) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Unable to receive notification from {}: {}", endpoint, e);
+                    return;
+                }
+            };
+            if let Some(notification) = parse_notification(&message) {
+                if sender.send(notification).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    Ok(receiver)
+}
+
+#[cfg(feature = "zmq")]
+fn zmq_error(e: zmq::Error) -> ::std::io::Error {
+    ::std::io::Error::new(::std::io::ErrorKind::Other, e)
+}
+
+// Notification consists of the topic, the body and the sequence number parts.
+#[cfg(feature = "zmq")]
+fn parse_notification(message: &[Vec<u8>]) -> Option<Notification> {
+    use bitcoin::network::serialize::deserialize;
+    use details::btc::transactions::RawBitcoinTx;
+
+    match (message.get(0).map(Vec::as_slice), message.get(1)) {
+        (Some(b"rawblock"), Some(_)) => Some(Notification::Block),
+        (Some(b"rawtx"), Some(body)) => {
+            match deserialize::<RawBitcoinTx>(body) {
+                Ok(tx) => Some(Notification::Transaction(BitcoinTx::from(tx))),
+                Err(e) => {
+                    warn!("Unable to parse notification transaction: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use details::btc;
+    use details::mock::{sample_tx, MockRelay};
+    use details::rpc::BitcoinRelay;
+
+    use super::{Notification, NotifiedRelay};
+
+    #[test]
+    fn test_notified_relay_cache() {
+        let tx = sample_tx();
+        let addr = "2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA".parse::<btc::Address>().unwrap();
+        let mock = MockRelay::new("mock").with_tx(tx.clone());
+        let (sender, receiver) = mpsc::channel();
+        let relay = NotifiedRelay::new(Box::new(mock.clone()), receiver);
+
+        // The responses are cached until the notifications.
+        relay.unspent_transactions(&addr).unwrap();
+        relay.unspent_transactions(&addr).unwrap();
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx.clone()));
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx.clone()));
+        assert_eq!(mock.requests(), 2);
+
+        // The transaction that pays to the address invalidates its unspent outputs.
+        sender.send(Notification::Transaction(tx.clone())).unwrap();
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx.clone()));
+        relay.unspent_transactions(&addr).unwrap();
+        assert_eq!(mock.requests(), 3);
+
+        sender.send(Notification::Block).unwrap();
+        relay.unspent_transactions(&addr).unwrap();
+        assert_eq!(relay.get_transaction(tx.id()).unwrap(), Some(tx.clone()));
+        assert_eq!(mock.requests(), 4);
+
+        // Without notifications all requests are forwarded.
+        drop(sender);
+        relay.unspent_transactions(&addr).unwrap();
+        relay.unspent_transactions(&addr).unwrap();
+        assert_eq!(mock.requests(), 6);
+    }
+}
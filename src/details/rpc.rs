@@ -12,17 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
 use std::string::ToString;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
+use base64;
 use bitcoinrpc;
+use bitcoinrpc::RpcError;
+use hyper;
+use hyper::header::{Authorization, Basic};
+use hyper::net::HttpsConnector;
+use hyper_native_tls::NativeTlsClient;
+use native_tls::{Certificate, TlsConnector};
+use serde::Deserialize;
+use serde::de::Error as DeError;
+use serde_json;
+use serde_json::value::{from_value, Value};
 
 use exonum::encoding::serialize::FromHex;
 
 use details::btc;
 use details::btc::transactions::{BitcoinTx, FundingTx, TxKind};
 
-pub use bitcoinrpc::Client as RpcClient;
-
 pub type Result<T> = bitcoinrpc::Result<T>;
 pub type Error = bitcoinrpc::Error;
 
@@ -32,25 +48,261 @@ pub type Error = bitcoinrpc::Error;
 /// which measures amounts in bitcoins (rather than satoshis).
 pub const SATOSHI_DIVISOR: f64 = 100_000_000.0;
 
+lazy_static! {
+    // The clients keep the connections alive, so they are shared by the requests
+    // with the same scheme and certificate authority.
+    static ref HTTP_CLIENTS: Mutex<HashMap<(bool, Option<String>), Arc<hyper::Client>>> =
+        Mutex::new(HashMap::new());
+}
+
 /// `Bitcoind` rpc configuration.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct AnchoringRpcConfig {
-    /// Rpc url.
+    /// Rpc url, the `https` scheme enables TLS.
     pub host: String,
     /// Rpc username.
     pub username: Option<String>,
     /// Rpc password.
     pub password: Option<String>,
+    /// Path to the `.cookie` file of `bitcoind` which is used for the authentication
+    /// instead of the `username` and `password`. The file is read by each request,
+    /// so the client keeps working after the `bitcoind` restart regenerates the cookie.
+    #[serde(default)]
+    pub cookie_file: Option<String>,
+    /// Path to the PEM or DER encoded certificate of the authority which has signed
+    /// the certificate of the `https` endpoint, in addition to the system ones.
+    #[serde(default)]
+    pub ca_certificate: Option<String>,
+    /// Name of the dedicated watch-only `bitcoind` wallet which tracks the anchoring
+    /// addresses, so they do not mix with the other funds of the node. The wallet is
+    /// loaded or created on the address import. If it is not set, the default wallet is used.
+    #[serde(default)]
+    pub wallet: Option<String>,
+    /// Rescans the blockchain from the block of the funding transaction after the anchoring
+    /// address import, so the wallet created after the funding finds its outputs.
+    /// Otherwise the wallet tracks only the new blocks.
+    #[serde(default)]
+    pub rescan: bool,
+    /// Height from which the blockchain is rescanned after the anchoring address import,
+    /// takes precedence over the height of the funding transaction.
+    #[serde(default)]
+    pub rescan_height: Option<u64>,
 }
 
-/// Client for the `Bitcoind` rpc api, for more information visit
-/// this [site](https://en.bitcoin.it/wiki/Original_Bitcoin_client/API_calls_list).
-#[derive(Debug)]
-pub struct AnchoringRpc(pub RpcClient);
+/// Client for the `bitcoind` rpc api.
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    config: AnchoringRpcConfig,
+    // Start height of the latest requested rescan.
+    rescan_height: Arc<Mutex<Option<u64>>>,
+}
 
 impl From<AnchoringRpcConfig> for RpcClient {
-    fn from(cfg: AnchoringRpcConfig) -> Self {
-        RpcClient::new(cfg.host, cfg.username, cfg.password)
+    fn from(config: AnchoringRpcConfig) -> Self {
+        RpcClient {
+            config,
+            rescan_height: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl RpcClient {
+    /// Loads the watch-only wallet specified in the configuration, the wallet is created
+    /// without private keys if it does not exist.
+    pub fn load_wallet(&self) -> Result<()> {
+        let wallet = match self.config.wallet {
+            Some(ref wallet) => wallet,
+            None => return Ok(()),
+        };
+        let error = match rpc_call(&self.config, "loadwallet", vec![json!(wallet)])? {
+            Ok(_) => {
+                info!("Loaded the bitcoind wallet {}", wallet);
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+        match error.get("code").and_then(Value::as_i64) {
+            Some(RPC_WALLET_ALREADY_LOADED) => Ok(()),
+            Some(RPC_WALLET_NOT_FOUND) => {
+                let params = json!({
+                    "wallet_name": wallet,
+                    "disable_private_keys": true,
+                    "blank": true,
+                    "descriptors": true,
+                });
+                raw_request::<Value>(&self.config, "createwallet", vec![params])?;
+                info!("Created the watch-only bitcoind wallet {}", wallet);
+                Ok(())
+            }
+            _ => Err(response_error(&error)),
+        }
+    }
+
+    /// Returns the hex-encoded transaction with the given `txid`.
+    pub fn getrawtransaction(&self, txid: &str) -> Result<String> {
+        raw_request(&self.config, "getrawtransaction", vec![json!(txid), json!(0)])
+    }
+
+    /// Returns the decoded transaction with the given `txid` and its confirmations.
+    pub fn getrawtransaction_verbose(&self, txid: &str) -> Result<bitcoinrpc::RawTransactionInfo> {
+        raw_request(&self.config, "getrawtransaction", vec![json!(txid), json!(1)])
+    }
+
+    /// Adds the address to the `bitcoind` wallet as watch-only.
+    pub fn importaddress(&self, addr: &str, label: &str, rescan: bool, p2sh: bool) -> Result<()> {
+        let params = vec![json!(addr), json!(label), json!(rescan), json!(p2sh)];
+        raw_request::<Value>(&self.wallet_config(), "importaddress", params)?;
+        Ok(())
+    }
+
+    /// Imports the output descriptor to the `bitcoind` descriptor wallet. The wallet rescans
+    /// the blocks since the given unix `timestamp` or tracks only the new blocks if it is `None`.
+    ///
+    /// Returns the error object of `bitcoind` if the import has failed.
+    pub fn importdescriptors(
+        &self,
+        descriptor: &str,
+        label: &str,
+        timestamp: Option<u64>,
+    ) -> Result<::std::result::Result<(), Value>> {
+        let request = json!({
+            "desc": descriptor,
+            "label": label,
+            "timestamp": timestamp.map_or_else(|| json!("now"), |timestamp| json!(timestamp)),
+        });
+        let params = vec![json!([request])];
+        let results = match rpc_call(&self.wallet_config(), "importdescriptors", params)? {
+            Ok(results) => results,
+            Err(error) => return Ok(Err(error)),
+        };
+        let results: Vec<Value> = from_value(results).map_err(json_error)?;
+        for result in results {
+            if result.get("success").and_then(Value::as_bool) != Some(true) {
+                return Ok(Err(result.get("error").cloned().unwrap_or(Value::Null)));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    /// Returns the height of the block which contains the transaction with the given `txid`
+    /// or `None` if the transaction is not confirmed yet.
+    pub fn transaction_height(&self, txid: &str) -> Result<Option<u64>> {
+        let params = vec![json!(txid), json!(1)];
+        let info: Value = raw_request(&self.config, "getrawtransaction", params)?;
+        let block_hash = match info.get("blockhash").and_then(Value::as_str) {
+            Some(block_hash) => block_hash.to_string(),
+            None => return Ok(None),
+        };
+        let header: Value = raw_request(&self.config, "getblockheader", vec![json!(block_hash)])?;
+        Ok(header.get("height").and_then(Value::as_u64))
+    }
+
+    /// Rescans the blockchain by the wallet starting from the given `start_height`.
+    ///
+    /// The rescan may take a long time, so it is performed on a separate thread and
+    /// does not block the following requests. The rescan is skipped if the previous one
+    /// has started from the same or the lower height.
+    pub fn rescan_blockchain(&self, start_height: u64) {
+        {
+            let mut rescan_height = self.rescan_height.lock().unwrap();
+            if rescan_height.map_or(false, |height| height <= start_height) {
+                return;
+            }
+            *rescan_height = Some(start_height);
+        }
+
+        let config = self.wallet_config();
+        let rescan_height = Arc::clone(&self.rescan_height);
+        thread::spawn(move || {
+            info!("Rescanning the blockchain from height={}", start_height);
+            let params = vec![json!(start_height)];
+            match raw_request::<Value>(&config, "rescanblockchain", params) {
+                Ok(result) => info!("Blockchain rescan is finished: {}", result),
+                Err(e) => {
+                    error!(
+                        "Unable to rescan the blockchain from height={}: {}",
+                        start_height,
+                        e
+                    );
+                    // The following import retries the rescan.
+                    let mut height = rescan_height.lock().unwrap();
+                    if *height == Some(start_height) {
+                        *height = None;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Submits the hex-encoded raw transaction to the bitcoin network.
+    pub fn sendrawtransaction(&self, txhex: &str) -> Result<String> {
+        raw_request(&self.config, "sendrawtransaction", vec![json!(txhex)])
+    }
+
+    /// Sends the `amount` in bitcoins from the `bitcoind` wallet to the given address.
+    pub fn sendtoaddress(&self, addr: &str, amount: &str) -> Result<String> {
+        raw_request(&self.config, "sendtoaddress", vec![json!(addr), json!(amount)])
+    }
+
+    /// Lists the unspent outputs of the given watched addresses.
+    pub fn listunspent<'a, V: AsRef<[&'a str]>>(
+        &self,
+        min_confirmations: u32,
+        max_confirmations: u32,
+        addresses: V,
+    ) -> Result<Vec<bitcoinrpc::UnspentTransactionInfo>> {
+        let params = vec![
+            json!(min_confirmations),
+            json!(max_confirmations),
+            json!(addresses.as_ref()),
+        ];
+        raw_request(&self.wallet_config(), "listunspent", params)
+    }
+
+    // The legacy wallets and the older `bitcoind` versions import the address itself.
+    fn import_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        timestamp: Option<u64>,
+    ) -> Result<()> {
+        self.load_wallet()?;
+        let error = match self.importdescriptors(descriptor, "multisig", timestamp)? {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+        match error.get("code").and_then(Value::as_i64) {
+            Some(RPC_METHOD_NOT_FOUND) | Some(RPC_WALLET_ERROR) => {
+                warn!(
+                    "Unable to import descriptor={}, importing the address itself: {}",
+                    descriptor,
+                    error
+                );
+                let rescan = timestamp.is_some();
+                self.importaddress(&addr.to_string(), "multisig", rescan, false)
+            }
+            _ => Err(response_error(&error)),
+        }
+    }
+
+    // The configured height takes precedence over the height of the funding transaction.
+    fn rescan_start_height(&self, rescan_from: Option<btc::TxId>) -> Result<Option<u64>> {
+        if self.config.rescan_height.is_some() {
+            return Ok(self.config.rescan_height);
+        }
+        match rescan_from {
+            Some(txid) if self.config.rescan => self.transaction_height(&txid.to_string()),
+            _ => Ok(None),
+        }
+    }
+
+    // The wallet rpc calls are performed by the wallet-scoped path.
+    fn wallet_config(&self) -> AnchoringRpcConfig {
+        let mut config = self.config.clone();
+        if let Some(ref wallet) = self.config.wallet {
+            config.host = format!("{}/wallet/{}", config.host.trim_right_matches('/'), wallet);
+        }
+        config
     }
 }
 
@@ -73,16 +325,47 @@ impl From<bitcoinrpc::RawTransactionInfo> for TxInfo {
     }
 }
 
+// `bitcoind` rpc error codes of the wallet loading.
+const RPC_METHOD_NOT_FOUND: i64 = -32_601;
+const PENDING_MARKER: &str = "is in progress";
+const RPC_WALLET_ERROR: i64 = -4;
+const RPC_WALLET_NOT_FOUND: i64 = -18;
+const RPC_WALLET_ALREADY_LOADED: i64 = -35;
+
+/// Backend that provides access to the bitcoin network.
+///
+/// The anchoring handler and the observer use only this trait to communicate with
+/// the bitcoin network, so that the `bitcoind` rpc client can be replaced by any other
+/// implementation, for example by the Electrum client or by a mock for tests.
 pub trait BitcoinRelay: 'static + ::std::fmt::Debug + Send + Sync {
     /// Retrieves transaction from the bitcoin blockchain.
     fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>>;
 
+    /// Retrieves transactions with the given ids in the same order.
+    /// Backends that support batch requests perform them at once.
+    fn get_transactions(&self, txids: &[btc::TxId]) -> Result<Vec<Option<BitcoinTx>>> {
+        txids.iter().map(|txid| self.get_transaction(*txid)).collect()
+    }
+
     /// Retrieves information about transaction with the given id.
     fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>>;
 
     /// Observes the changes on given address.
     fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()>;
 
+    /// Observes the changes on the address with the given output `descriptor`.
+    /// The backend may rescan the blockchain from the block of the `rescan_from`
+    /// transaction to find the earlier outputs of the address.
+    /// Backends without the descriptors support watch the address itself.
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        _descriptor: &str,
+        _rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.watch_address(addr, false)
+    }
+
     /// Sends raw transaction to the bitcoin network.
     fn send_transaction(&self, tx: BitcoinTx) -> Result<()>;
 
@@ -92,16 +375,60 @@ pub trait BitcoinRelay: 'static + ::std::fmt::Debug + Send + Sync {
     /// Lists unspent transactions for the given address.
     fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>>;
 
+    /// Lists the unspent outputs of the given address as the `(txid, vout)` pairs.
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>>;
+
     /// Retrieves information about confirmations for transaction with the given id.
     fn get_transaction_confirmations(&self, txid: btc::TxId) -> Result<Option<u64>> {
         let info = self.get_transaction_info(txid)?;
         Ok(info.and_then(|x| x.confirmations))
     }
 
+    /// Estimates the fee rate in satoshis per byte needed for a transaction
+    /// to be confirmed within `conf_target` blocks.
+    /// Returns `None` if the estimation is not available.
+    fn estimate_fee_rate(&self, _conf_target: u16) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Returns the height of the best block known by the backend.
+    /// Returns `None` if the backend is unable to report it.
+    fn tip_height(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Returns the bitcoin chain to which the backend is connected.
+    /// Returns `None` if the backend is unable to report it.
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        Ok(None)
+    }
+
+    /// Returns the estimated progress of the block chain verification by the backend
+    /// in the range from `0.0` to `1.0`.
+    /// Returns `None` if the backend is unable to report it.
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    /// Returns the hex-encoded merkle block that proves the inclusion of the transaction
+    /// with the given `txid` into the bitcoin block.
+    /// Returns `None` if the backend is unable to provide the proof.
+    fn get_transaction_proof(&self, _txid: btc::TxId) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Returns the hex-encoded headers of up to `count` blocks that follow the block
+    /// with the given `block_hash` in the best chain.
+    fn get_block_headers(&self, _block_hash: &str, _count: u64) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
     /// Returns an actual relay configuration.
     fn config(&self) -> AnchoringRpcConfig;
 }
 
+/// Client for the `Bitcoind` rpc api, for more information visit
+/// this [site](https://en.bitcoin.it/wiki/Original_Bitcoin_client/API_calls_list).
 impl BitcoinRelay for RpcClient {
     fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
         let r = self.getrawtransaction(&txid.to_string());
@@ -112,6 +439,27 @@ impl BitcoinRelay for RpcClient {
         }
     }
 
+    fn get_transactions(&self, txids: &[btc::TxId]) -> Result<Vec<Option<BitcoinTx>>> {
+        let config = BitcoinRelay::config(self);
+        let requests = txids
+            .iter()
+            .map(|txid| ("getrawtransaction", vec![json!(txid.to_string())]))
+            .collect::<Vec<_>>();
+        let mut txs = Vec::new();
+        for response in raw_batch_request(&config, &requests)? {
+            match response {
+                Ok(Value::String(hex)) => {
+                    let tx = BitcoinTx::from_hex(hex).map_err(other_error)?;
+                    txs.push(Some(tx));
+                }
+                Ok(other) => return Err(other_error(format!("Unexpected response={}", other))),
+                Err(Error::NoInformation(_)) => txs.push(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(txs)
+    }
+
     fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
         let info = match self.getrawtransaction_verbose(&txid.to_string()) {
             Ok(info) => Ok(info),
@@ -122,7 +470,27 @@ impl BitcoinRelay for RpcClient {
     }
 
     fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
-        self.importaddress(&addr.to_string(), "multisig", false, rescan)
+        if rescan {
+            return self.import_descriptor(addr, &btc::descriptor::address(addr), Some(0));
+        }
+        self.import_descriptor(addr, &btc::descriptor::address(addr), None)?;
+        if let Some(height) = self.rescan_start_height(None)? {
+            self.rescan_blockchain(height);
+        }
+        Ok(())
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.import_descriptor(addr, descriptor, None)?;
+        if let Some(height) = self.rescan_start_height(rescan_from)? {
+            self.rescan_blockchain(height);
+        }
+        Ok(())
     }
 
     fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
@@ -142,11 +510,15 @@ impl BitcoinRelay for RpcClient {
 
     fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
         let unspent_txs = self.listunspent(0, 9_999_999, [addr.to_string().as_ref()])?;
+        let txids = unspent_txs
+            .iter()
+            .map(|info| btc::TxId::from_hex(&info.txid).unwrap())
+            .collect::<Vec<_>>();
+        let raw_txs = self.get_transactions(&txids)?;
         let mut txs = Vec::new();
-        for info in unspent_txs {
-            let txid = btc::TxId::from_hex(&info.txid).unwrap();
+        for (info, raw_tx) in unspent_txs.into_iter().zip(raw_txs) {
             let confirmations = Some(info.confirmations);
-            if let Some(raw_tx) = self.get_transaction(txid)? {
+            if let Some(raw_tx) = raw_tx {
                 match TxKind::from(raw_tx) {
                     TxKind::Anchoring(tx) => {
                         txs.push(TxInfo {
@@ -167,12 +539,73 @@ impl BitcoinRelay for RpcClient {
         Ok(txs)
     }
 
-    fn config(&self) -> AnchoringRpcConfig {
-        AnchoringRpcConfig {
-            host: self.url().to_string(),
-            username: self.username().clone(),
-            password: self.password().clone(),
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let unspent_txs = self.listunspent(0, 9_999_999, [addr.to_string().as_ref()])?;
+        unspent_txs
+            .into_iter()
+            .map(|info| {
+                let txid = btc::TxId::from_hex(&info.txid).map_err(|e| {
+                    other_error(format!("Unexpected txid {}: {}", info.txid, e))
+                })?;
+                Ok((txid, info.vout))
+            })
+            .collect()
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        let config = BitcoinRelay::config(self);
+        let estimation: Value = raw_request(&config, "estimatesmartfee", vec![json!(conf_target)])?;
+        let fee_rate = estimation.get("feerate").and_then(Value::as_f64);
+        Ok(fee_rate.map(fee_rate_from_btc_per_kb))
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        let config = BitcoinRelay::config(self);
+        let height: u64 = raw_request(&config, "getblockcount", vec![])?;
+        Ok(Some(height))
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        let config = BitcoinRelay::config(self);
+        let info: Value = raw_request(&config, "getblockchaininfo", vec![])?;
+        let chain = info.get("chain").and_then(Value::as_str).ok_or_else(|| {
+            Error::NoInformation("Unable to get the bitcoind chain".to_string())
+        })?;
+        chain.parse().map(Some).map_err(Error::NoInformation)
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        let config = BitcoinRelay::config(self);
+        let info: Value = raw_request(&config, "getblockchaininfo", vec![])?;
+        Ok(info.get("verificationprogress").and_then(Value::as_f64))
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        let config = BitcoinRelay::config(self);
+        let params = vec![json!([txid.to_string()])];
+        let proof: String = raw_request(&config, "gettxoutproof", params)?;
+        Ok(Some(proof))
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        let config = BitcoinRelay::config(self);
+        let mut headers = Vec::new();
+        let mut block_hash = block_hash.to_string();
+        while (headers.len() as u64) < count {
+            let params = vec![json!(block_hash), json!(true)];
+            let info: Value = raw_request(&config, "getblockheader", params)?;
+            block_hash = match info.get("nextblockhash").and_then(Value::as_str) {
+                Some(next_hash) => next_hash.to_string(),
+                None => break,
+            };
+            let params = vec![json!(block_hash), json!(false)];
+            headers.push(raw_request(&config, "getblockheader", params)?);
         }
+        Ok(headers)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        self.config.clone()
     }
 }
 
@@ -181,3 +614,392 @@ impl<'a, T: BitcoinRelay + 'a> From<T> for Box<BitcoinRelay> {
         Box::new(t) as Box<BitcoinRelay>
     }
 }
+
+/// Converts an i/o error of the bitcoin backend into the rpc error.
+pub fn io_error(e: io::Error) -> Error {
+    Error::Other(RpcError::Json(serde_json::Error::io(e)))
+}
+
+/// Converts an error of the backend response decoding into the rpc error.
+pub fn json_error(e: serde_json::Error) -> Error {
+    Error::Other(RpcError::Json(e))
+}
+
+/// Creates the rpc error for the unexpected backend response.
+pub fn other_error<S: ToString>(msg: S) -> Error {
+    json_error(serde_json::Error::custom(msg.to_string()))
+}
+
+/// Returns `true` if the error is caused by the backend unavailability and the request
+/// can be retried later, for example, if the connection is refused or timed out.
+/// Errors returned by the backend itself, like the rejected transaction, are fatal.
+pub fn is_transient_error(e: &Error) -> bool {
+    match *e {
+        Error::Other(RpcError::Hyper(_)) => true,
+        Error::Other(RpcError::Json(ref e)) => e.is_io(),
+        _ => false,
+    }
+}
+
+/// Creates the error for the request whose result is not received from the backend yet.
+///
+/// The error is transient, but it is not a backend failure, see
+/// [`is_pending_error`](fn.is_pending_error.html).
+pub fn pending_error<S: fmt::Display>(request: S) -> Error {
+    let msg = format!("Request {} {}", request, PENDING_MARKER);
+    io_error(io::Error::new(io::ErrorKind::WouldBlock, msg))
+}
+
+/// Returns `true` if the result of the request is not received yet, so the request
+/// is repeated by the following commit without the retry backoff.
+pub fn is_pending_error(e: &Error) -> bool {
+    is_transient_error(e) && e.to_string().contains(PENDING_MARKER)
+}
+
+/// Reason for which the bitcoin node has rejected the sent transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastRejection {
+    /// The transaction spends the outputs already spent by another mempool transaction.
+    MempoolConflict,
+    /// The fee of the transaction is lower than the minimal relay fee or than the fee
+    /// required to replace the conflicting mempool transaction.
+    InsufficientFee,
+    /// The transaction is already included in a block.
+    AlreadyInChain,
+}
+
+/// Classifies the error of the transaction broadcast by the reject reason of `bitcoind`.
+/// Returns `None` for the other errors, including the transient ones.
+pub fn broadcast_rejection(e: &Error) -> Option<BroadcastRejection> {
+    let message = e.to_string().to_lowercase();
+    if message.contains("txn-mempool-conflict") {
+        Some(BroadcastRejection::MempoolConflict)
+    } else if message.contains("insufficient fee") || message.contains("min relay fee not met") ||
+               message.contains("mempool min fee not met")
+    {
+        Some(BroadcastRejection::InsufficientFee)
+    } else if message.contains("already in block chain") ||
+               message.contains("already in utxo set")
+    {
+        Some(BroadcastRejection::AlreadyInChain)
+    } else {
+        None
+    }
+}
+
+/// Converts the fee rate in bitcoins per kilobyte into satoshis per byte.
+pub fn fee_rate_from_btc_per_kb(fee_rate: f64) -> u64 {
+    (fee_rate * SATOSHI_DIVISOR / 1000.0) as u64
+}
+
+/// Performs the `bitcoind` rpc call which is not provided by the `RpcClient`.
+pub fn raw_request<T>(config: &AnchoringRpcConfig, method: &str, params: Vec<Value>) -> Result<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let result = rpc_call(config, method, params)?.map_err(
+        |error| response_error(&error),
+    )?;
+    from_value(result).map_err(json_error)
+}
+
+// Returns the result of the rpc call or the error object returned by `bitcoind`.
+fn rpc_call(
+    config: &AnchoringRpcConfig,
+    method: &str,
+    params: Vec<Value>,
+) -> Result<::std::result::Result<Value, Value>> {
+    let request = json!({
+        "jsonrpc": "1.0",
+        "id": "anchoring",
+        "method": method,
+        "params": params,
+    }).to_string();
+
+    let response: Value = serde_json::from_str(&post_request(config, &request)?)
+        .map_err(json_error)?;
+    if let Some(error) = response.get("error") {
+        if !error.is_null() {
+            return Ok(Err(error.clone()));
+        }
+    }
+    Ok(Ok(response.get("result").cloned().unwrap_or(Value::Null)))
+}
+
+// Performs the given `bitcoind` rpc calls by the single batch request and returns
+// their results in the same order.
+fn raw_batch_request(
+    config: &AnchoringRpcConfig,
+    requests: &[(&str, Vec<Value>)],
+) -> Result<Vec<Result<Value>>> {
+    if requests.is_empty() {
+        return Ok(Vec::new());
+    }
+    let batch = requests
+        .iter()
+        .enumerate()
+        .map(|(id, &(method, ref params))| {
+            json!({
+                "jsonrpc": "1.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            })
+        })
+        .collect::<Vec<_>>();
+    let body = post_request(config, &Value::Array(batch).to_string())?;
+    parse_batch_response(&body, requests.len())
+}
+
+// Responses of the batch request may arrive in any order, so they are sorted by ids.
+fn parse_batch_response(body: &str, count: usize) -> Result<Vec<Result<Value>>> {
+    let responses: Vec<Value> = serde_json::from_str(body).map_err(json_error)?;
+    let mut results = (0..count).map(|_| None).collect::<Vec<_>>();
+    for response in responses {
+        let id = match response.get("id").and_then(Value::as_u64) {
+            Some(id) if (id as usize) < count => id as usize,
+            _ => return Err(other_error(format!("Unexpected batch response={}", response))),
+        };
+        let result = match response.get("error") {
+            Some(error) if !error.is_null() => Err(response_error(error)),
+            _ => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        results[id] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| {
+            result.ok_or_else(|| other_error("Batch response is incomplete"))
+        })
+        .collect()
+}
+
+fn response_error(error: &Value) -> Error {
+    // The requested transaction is not found.
+    if error.get("code").and_then(Value::as_i64) == Some(-5) {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+        Error::NoInformation(message.to_string())
+    } else {
+        other_error(error)
+    }
+}
+
+fn post_request(config: &AnchoringRpcConfig, request: &str) -> Result<String> {
+    let client = http_client(config)?;
+    let mut builder = client.post(&config.host).body(request);
+    if let Some((username, password)) = credentials(config)? {
+        builder = builder.header(Authorization(Basic { username, password }));
+    }
+    let mut response = builder.send().map_err(|e| match e {
+        hyper::Error::Io(e) => io_error(e),
+        e => other_error(e),
+    })?;
+    let mut body = String::new();
+    response.read_to_string(&mut body).map_err(io_error)?;
+    Ok(body)
+}
+
+// The cookie file takes precedence over the username and password.
+fn credentials(config: &AnchoringRpcConfig) -> Result<Option<(String, Option<String>)>> {
+    let path = match config.cookie_file {
+        Some(ref path) => path,
+        None => {
+            let password = config.password.clone();
+            return Ok(config.username.clone().map(|username| (username, password)));
+        }
+    };
+    let mut cookie = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut cookie))
+        .map_err(io_error)?;
+    parse_cookie(&cookie).map(Some).ok_or_else(|| {
+        other_error(format!("Incorrect bitcoind cookie file {}", path))
+    })
+}
+
+// The cookie has the `__cookie__:<password>` format.
+fn parse_cookie(cookie: &str) -> Option<(String, Option<String>)> {
+    let mut parts = cookie.trim().splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(username), Some(password)) if !username.is_empty() => {
+            Some((username.to_string(), Some(password.to_string())))
+        }
+        _ => None,
+    }
+}
+
+fn http_client(config: &AnchoringRpcConfig) -> Result<Arc<hyper::Client>> {
+    let https = config.host.starts_with("https://");
+    let ca_certificate = if https {
+        config.ca_certificate.clone()
+    } else {
+        None
+    };
+    let key = (https, ca_certificate);
+    if let Some(client) = HTTP_CLIENTS.lock().unwrap().get(&key) {
+        return Ok(Arc::clone(client));
+    }
+    let client = Arc::new(new_http_client(config)?);
+    HTTP_CLIENTS.lock().unwrap().insert(key, Arc::clone(&client));
+    Ok(client)
+}
+
+fn new_http_client(config: &AnchoringRpcConfig) -> Result<hyper::Client> {
+    if !config.host.starts_with("https://") {
+        return Ok(hyper::Client::new());
+    }
+    let mut builder = TlsConnector::builder().map_err(other_error)?;
+    if let Some(ref path) = config.ca_certificate {
+        let mut content = Vec::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut content))
+            .map_err(io_error)?;
+        let der = decode_certificate(&content).ok_or_else(|| {
+            other_error(format!("Incorrect certificate file {}", path))
+        })?;
+        let certificate = Certificate::from_der(&der).map_err(other_error)?;
+        builder.add_root_certificate(certificate).map_err(
+            other_error,
+        )?;
+    }
+    let connector = builder.build().map_err(other_error)?;
+    let tls = NativeTlsClient::from(connector);
+    Ok(hyper::Client::with_connector(HttpsConnector::new(tls)))
+}
+
+// Returns the DER encoding of the PEM or DER encoded certificate.
+fn decode_certificate(content: &[u8]) -> Option<Vec<u8>> {
+    let pem = match ::std::str::from_utf8(content) {
+        Ok(pem) if pem.contains("-----BEGIN CERTIFICATE-----") => pem,
+        _ => return Some(content.to_vec()),
+    };
+    let body = pem.lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN CERTIFICATE-----"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END CERTIFICATE-----"))
+        .map(str::trim)
+        .collect::<String>();
+    base64::decode(&body).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use exonum::encoding::serialize::FromHex;
+
+    use details::btc;
+
+    use super::{broadcast_rejection, decode_certificate, http_client, parse_batch_response,
+                parse_cookie, response_error, AnchoringRpcConfig, BroadcastRejection, Error,
+                RpcClient};
+
+    #[test]
+    fn test_parse_batch_response() {
+        let body = r#"[
+            {"result": null, "error": {"code": -5, "message": "No such transaction"}, "id": 1},
+            {"result": "0100", "error": null, "id": 0},
+            {"result": null, "error": {"code": -1, "message": "Internal error"}, "id": 2}
+        ]"#;
+        let results = parse_batch_response(body, 3).unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &json!("0100"));
+        match results[1] {
+            Err(Error::NoInformation(ref msg)) => assert_eq!(msg, "No such transaction"),
+            ref other => panic!("Unexpected result={:?}", other),
+        }
+        assert!(results[2].is_err());
+
+        assert!(parse_batch_response(body, 4).is_err());
+        assert!(parse_batch_response(body, 2).is_err());
+    }
+
+    #[test]
+    fn test_broadcast_rejection() {
+        let rejection = |code: i64, message: &str| {
+            let error = response_error(&json!({"code": code, "message": message}));
+            broadcast_rejection(&error)
+        };
+        assert_eq!(
+            rejection(-26, "txn-mempool-conflict"),
+            Some(BroadcastRejection::MempoolConflict)
+        );
+        assert_eq!(
+            rejection(-26, "insufficient fee, rejecting replacement"),
+            Some(BroadcastRejection::InsufficientFee)
+        );
+        assert_eq!(
+            rejection(-26, "min relay fee not met, 100 < 226"),
+            Some(BroadcastRejection::InsufficientFee)
+        );
+        assert_eq!(
+            rejection(-27, "Transaction already in block chain"),
+            Some(BroadcastRejection::AlreadyInChain)
+        );
+        assert_eq!(rejection(-25, "Missing inputs"), None);
+        assert_eq!(rejection(-5, "No such transaction"), None);
+    }
+
+    #[test]
+    fn test_parse_cookie() {
+        assert_eq!(
+            parse_cookie("__cookie__:4c3b2a1\n"),
+            Some(("__cookie__".to_string(), Some("4c3b2a1".to_string())))
+        );
+        assert_eq!(parse_cookie("__cookie__"), None);
+        assert_eq!(parse_cookie(":password"), None);
+    }
+
+    #[test]
+    fn test_decode_certificate() {
+        let pem = "-----BEGIN CERTIFICATE-----\nAQID\nBAU=\n-----END CERTIFICATE-----\n";
+        assert_eq!(decode_certificate(pem.as_bytes()), Some(vec![1, 2, 3, 4, 5]));
+        // DER encoded certificates are used as is.
+        assert_eq!(decode_certificate(&[0x30, 0x82]), Some(vec![0x30, 0x82]));
+    }
+    #[test]
+    fn test_wallet_config() {
+        let mut config = AnchoringRpcConfig {
+            host: "http://127.0.0.1:18332/".to_string(),
+            ..Default::default()
+        };
+        let client = RpcClient::from(config.clone());
+        assert_eq!(client.wallet_config().host, "http://127.0.0.1:18332/");
+
+        config.wallet = Some("anchoring".to_string());
+        let client = RpcClient::from(config);
+        assert_eq!(client.wallet_config().host, "http://127.0.0.1:18332/wallet/anchoring");
+    }
+
+    #[test]
+    fn test_rescan_start_height() {
+        let mut config = AnchoringRpcConfig {
+            host: "http://127.0.0.1:18332/".to_string(),
+            ..Default::default()
+        };
+        let txid = btc::TxId::from_hex(
+            "a78a28f0a369f3985c5f713d985bb1e7bd3dfb8b35f194b39a5fe7e7d709af9a",
+        ).unwrap();
+        let client = RpcClient::from(config.clone());
+        assert_eq!(client.rescan_start_height(Some(txid)).unwrap(), None);
+
+        config.rescan = true;
+        config.rescan_height = Some(100);
+        let client = RpcClient::from(config);
+        assert_eq!(client.rescan_start_height(Some(txid)).unwrap(), Some(100));
+        assert_eq!(client.rescan_start_height(None).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn test_http_client_reuse() {
+        let config = AnchoringRpcConfig {
+            host: "http://127.0.0.1:18332/".to_string(),
+            ..Default::default()
+        };
+        let wallet_config = AnchoringRpcConfig {
+            host: "http://127.0.0.1:18332/wallet/anchoring".to_string(),
+            ..Default::default()
+        };
+        let client = http_client(&config).unwrap();
+        assert!(Arc::ptr_eq(&client, &http_client(&wallet_config).unwrap()));
+    }
+}
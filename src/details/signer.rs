@@ -0,0 +1,195 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signers of the anchoring transactions.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use secp256k1::Secp256k1;
+use serde_json;
+use serde_json::value::Value;
+
+use details::btc;
+use details::btc::RedeemScript;
+use details::btc::psbt::Psbt;
+use details::btc::signature::normalize_s;
+use details::btc::transactions::AnchoringTx;
+
+/// Default timeout of the external signer in seconds.
+pub const DEFAULT_EXTERNAL_SIGNER_TIMEOUT: u64 = 120;
+
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+/// Signer of the anchoring transaction inputs.
+pub trait Signer: fmt::Debug + Send {
+    /// Signs the inputs of the given `psbt` and returns the signatures by the input indexes.
+    fn sign(&self, psbt: &Psbt) -> io::Result<BTreeMap<u32, btc::Signature>>;
+}
+
+/// Signer with the private key stored in the node configuration.
+#[derive(Debug, Clone)]
+pub struct KeySigner {
+    priv_key: btc::PrivateKey,
+}
+
+/// External signer configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExternalSignerConfig {
+    /// Address of the signer local socket in the `host:port` format.
+    pub host: String,
+    /// Timeout in seconds of the connection to the signer and of each read or write.
+    /// The hardware wallets wait for the user confirmation, so it should not be too small.
+    #[serde(default = "default_external_signer_timeout")]
+    pub timeout: u64,
+}
+
+/// Signer which delegates signing to the external process, for example to the
+/// [HWI][1] bridge for a hardware wallet or to the HSM.
+///
+/// Signer receives the newline terminated `{"method": "signtx", "params": [psbt]}` request
+/// with the `base64` encoded psbt and replies with the `{"psbt": psbt}` object that contains
+/// the signed psbt, or with the `{"error": message}` object. Only the signatures made
+/// by the anchoring key of the node are taken from the signed psbt.
+///
+/// [1]: https://github.com/bitcoin-core/HWI
+#[derive(Debug, Clone)]
+pub struct ExternalSigner {
+    config: ExternalSignerConfig,
+    pub_key: btc::PublicKey,
+}
+
+impl KeySigner {
+    /// Creates signer with the given private key.
+    pub fn new(priv_key: btc::PrivateKey) -> KeySigner {
+        KeySigner { priv_key }
+    }
+}
+
+impl Signer for KeySigner {
+    fn sign(&self, psbt: &Psbt) -> io::Result<BTreeMap<u32, btc::Signature>> {
+        let tx = AnchoringTx(psbt.unsigned_tx.clone());
+        let mut signatures = BTreeMap::new();
+        for (input, psbt_input) in psbt.inputs.iter().enumerate() {
+            let input = input as u32;
+            let signature = if let Some(ref script) = psbt_input.witness_script {
+                let value = psbt_input
+                    .witness_utxo
+                    .as_ref()
+                    .map(|out| out.value)
+                    .ok_or_else(|| invalid_data("Spent output value is unknown"))?;
+                let redeem_script = RedeemScript(script.clone());
+                tx.sign_witness_input(&redeem_script, input, value, &self.priv_key)
+            } else if let Some(ref script) = psbt_input.redeem_script {
+                let redeem_script = RedeemScript(script.clone());
+                tx.sign_input(&redeem_script, input, &self.priv_key)
+            } else {
+                return Err(invalid_data("Psbt input has no redeem script"));
+            };
+            signatures.insert(input, signature);
+        }
+        Ok(signatures)
+    }
+}
+
+impl ExternalSigner {
+    /// Creates external signer with the given configuration for the given anchoring key.
+    pub fn new(config: ExternalSignerConfig, pub_key: btc::PublicKey) -> ExternalSigner {
+        ExternalSigner { config, pub_key }
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let timeout = Duration::from_secs(self.config.timeout);
+        let addr = self.config.host.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Signer host is not resolved")
+        })?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(stream)
+    }
+
+    fn sign_psbt(&self, psbt: &Psbt) -> io::Result<Psbt> {
+        let mut stream = self.connect()?;
+        let mut request = json!({
+            "method": "signtx",
+            "params": [psbt.to_base64()],
+        }).to_string();
+        request.push('\n');
+        stream.write_all(request.as_bytes())?;
+
+        // The response size is bounded, so the signer cannot make the node read forever.
+        let mut line = String::new();
+        BufReader::new(stream.take(MAX_RESPONSE_SIZE)).read_line(&mut line)?;
+        trace!("External signer response={}", line.trim());
+
+        let response: Value = serde_json::from_str(&line).map_err(
+            |e| invalid_data(e.to_string()),
+        )?;
+        if let Some(error) = response.get("error") {
+            if !error.is_null() {
+                return Err(io::Error::new(io::ErrorKind::Other, error.to_string()));
+            }
+        }
+        let signed_psbt = response.get("psbt").and_then(Value::as_str).ok_or_else(
+            || {
+                invalid_data("Signer response has no psbt")
+            },
+        )?;
+        Psbt::from_base64(signed_psbt)
+    }
+}
+
+impl Signer for ExternalSigner {
+    fn sign(&self, psbt: &Psbt) -> io::Result<BTreeMap<u32, btc::Signature>> {
+        let signed_psbt = self.sign_psbt(psbt)?;
+        if signed_psbt.unsigned_tx != psbt.unsigned_tx {
+            return Err(invalid_data("Signer has changed the transaction"));
+        }
+
+        // Only the signature of the anchoring key of the node is taken, the signer may
+        // hold the other keys as well.
+        let pub_key = self.pub_key.serialize_vec(&Secp256k1::without_caps(), true);
+        let mut signatures = BTreeMap::new();
+        for (input, signed_input) in signed_psbt.inputs.iter().enumerate() {
+            let signature = signed_input
+                .partial_sigs
+                .get(pub_key.as_slice())
+                .cloned()
+                .ok_or_else(|| invalid_data("Signer has not signed the input"))?;
+            // The signer may produce the high `s` signature which is rejected by the validators.
+            let (hash_type, der) = signature.split_last().ok_or_else(
+                || invalid_data("Signature is empty"),
+            )?;
+            let mut signature = normalize_s(der).ok_or_else(
+                || invalid_data("Signature is not DER encoded"),
+            )?;
+            signature.push(*hash_type);
+            signatures.insert(input as u32, signature);
+        }
+        Ok(signatures)
+    }
+}
+
+fn default_external_signer_timeout() -> u64 {
+    DEFAULT_EXTERNAL_SIGNER_TIMEOUT
+}
+
+fn invalid_data<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
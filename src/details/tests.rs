@@ -22,17 +22,28 @@ use bitcoin::network::constants::Network;
 use bitcoin::util::base58::{FromBase58, ToBase58};
 use bitcoin::util::address::Privkey as RawPrivateKey;
 use bitcoin::blockdata::transaction::SigHashType;
+use bitcoin::blockdata::script::{Instruction, Script};
 use secp256k1::key::PublicKey as RawPublicKey;
 use secp256k1::Secp256k1;
 
 use exonum::helpers::{self, Height};
-use exonum::crypto::Hash;
+use exonum::crypto::{hash, Hash};
 use exonum::storage::StorageValue;
 use exonum::encoding::serialize::{FromHex, encode_hex};
 use exonum::encoding::Field;
 
 use details::btc::transactions::{sign_tx_input, verify_tx_input, AnchoringTx, BitcoinTx,
-                                 FundingTx, TransactionBuilder, TxKind};
+                                 FundingTx, TransactionBuilder, TxKind, REPLACEABLE_SEQUENCE};
+use details::btc::transactions::{create_recovery_tx, estimate_anchoring_tx_size,
+                                 estimate_anchoring_tx_weight, max_anchoring_tx_inputs,
+                                 witness_signature_hash, MAX_STANDARD_TX_WEIGHT,
+                                 RECOVERY_TX_VERSION};
+use details::btc::canonical;
+use details::btc::psbt::Psbt;
+use details::btc::selection::{CoinSelection, FundingCandidate};
+use details::btc::sighash::SighashComponents;
+use details::signer::{ExternalSigner, ExternalSignerConfig, KeySigner, Signer,
+                      DEFAULT_EXTERNAL_SIGNER_TIMEOUT};
 use details::btc;
 use details::btc::HexValueEx;
 
@@ -351,6 +362,85 @@ fn test_redeem_script_from_pubkeys() {
     );
 }
 
+#[test]
+fn test_output_descriptors() {
+    let keys = [
+        "027db7837e51888e94c094703030d162c682c8dba312210f44ff440fbd5e5c2473",
+        "02bdd272891c9e4dfc3962b1fdffd5a59732019816f9db4833634dbdaf01a401a5",
+        "03280883dc31ccaee34218819aaa245480c35a33acd91283586ff6d1284ed681e5",
+        "03e2bc790a6e32bf5a766919ff55b1f9e9914e13aed84f502c0e4171976e19deb0",
+    ];
+    let pub_keys = keys.iter()
+        .map(|x| btc::PublicKey::from_hex(x).unwrap())
+        .collect::<Vec<_>>();
+    let multi = format!("multi(3,{})", keys.join(","));
+
+    assert_eq!(btc::descriptor::with_checksum("raw(deadbeef)"), "raw(deadbeef)#89f8spxm");
+    assert_eq!(btc::descriptor::checksum("raw(deadbeef)\u{e9}"), None);
+    assert_eq!(
+        btc::descriptor::multisig(3, &pub_keys, btc::ScriptType::P2sh),
+        format!("sh({})#fh7ts8sm", multi)
+    );
+    assert_eq!(
+        btc::descriptor::multisig(3, &pub_keys, btc::ScriptType::P2shP2wsh),
+        format!("sh(wsh({}))#lnrjlu7r", multi)
+    );
+    assert_eq!(
+        btc::descriptor::multisig(3, &pub_keys, btc::ScriptType::P2wsh),
+        format!("wsh({})#hk3th352", multi)
+    );
+    let addr = "2N1mHzwKTmjnC7JjqeGFBRKYE4WDTjTfop1".parse::<btc::Address>().unwrap();
+    assert_eq!(
+        btc::descriptor::address(&addr),
+        "addr(2N1mHzwKTmjnC7JjqeGFBRKYE4WDTjTfop1)#uf7xryxz"
+    );
+}
+
+#[test]
+fn test_hd_anchoring_keys() {
+    // Test vector 1 from the BIP 32.
+    let xpriv = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5k\
+                 ejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    let xpub = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrf\
+                p1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    let keys = [
+        "033171c5f58a4504363dba2ca6cb7d6275f743bc8dada02dffef75912eaeeacf13",
+        "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c",
+    ].iter()
+        .map(|x| btc::PublicKey::from_hex(x).unwrap())
+        .collect::<Vec<_>>();
+
+    let private = btc::hd::HdKeyConfig {
+        key: xpriv.to_string(),
+        path: "m/0'".to_string(),
+    };
+    let public = btc::hd::HdKeyConfig {
+        key: xpub.to_string(),
+        path: "m".to_string(),
+    };
+    for (index, key) in keys.iter().enumerate() {
+        assert_eq!(private.public_key(index as u32).unwrap(), *key);
+        assert_eq!(public.public_key(index as u32).unwrap(), *key);
+    }
+    assert_eq!(private.find_index(&keys[1..]), Some(1));
+    assert_eq!(public.find_index(&keys[1..]), Some(1));
+    assert_eq!(public.private_key(1).unwrap(), None);
+
+    let priv_key = private.private_key(1).unwrap().unwrap();
+    let context = Secp256k1::new();
+    let pub_key = btc::PublicKey::from_secret_key(&context, priv_key.secret_key()).unwrap();
+    assert_eq!(pub_key, keys[1]);
+
+    // The hardened children are not derived from the extended public key.
+    let hardened = btc::hd::HdKeyConfig {
+        key: xpub.to_string(),
+        path: "m/1h".to_string(),
+    };
+    assert!(hardened.public_key(0).is_err());
+    assert!(btc::hd::parse_path("0/1").is_err());
+    assert!(btc::hd::parse_path("m/2147483648").is_err());
+}
+
 #[test]
 fn test_sign_raw_transaction() {
     let unsigned_tx = BitcoinTx::from_hex(
@@ -390,6 +480,65 @@ fn test_sign_raw_transaction() {
         &pub_key,
         &actual_signature[0..actual_signature.len() - 1],
     ));
+
+    // The same signature with the non-minimal encoding of `s` is rejected.
+    let der_signature = &actual_signature[0..actual_signature.len() - 1];
+    let mut padded_signature = vec![0x30, der_signature[1] + 1];
+    padded_signature.extend_from_slice(&der_signature[2..37]);
+    padded_signature.extend_from_slice(&[0x02, 0x21, 0x00]);
+    padded_signature.extend_from_slice(&der_signature[39..]);
+    assert!(!verify_tx_input(
+        &unsigned_tx,
+        0,
+        &redeem_script,
+        &pub_key,
+        &padded_signature,
+    ));
+}
+
+#[test]
+fn test_sign_raw_transaction_low_s() {
+    let unsigned_tx = BitcoinTx::from_hex(
+        "01000000015d1b8ba33a162d8f6e7c5707fbb557e726c32f30f77f2\
+         ba348a48c3c5d71ee0b0000000000ffffffff02b80b00000000000017a914889fc9c82819c7a728974ffa78cc8\
+         84e3e9e68838700000000000000002c6a2a6a28020000000000000062467691cf583d4fa78b18fafaf9801f505\
+         e0ef03baf0603fd4b0cd004cd1e7500000000",
+    ).unwrap();
+    let priv_key = RawPrivateKey::from_base58check(
+        "cVC9eJN5peJemWn1byyWcWDevg6xLNXtACjHJWmrR5ynsCu8mkQE",
+    ).unwrap();
+    let pub_key = {
+        let context = Secp256k1::new();
+        RawPublicKey::from_secret_key(&context, priv_key.secret_key()).unwrap()
+    };
+    let redeem_script = btc::RedeemScript::from_hex(
+        "5321027db7837e51888e94c094703030d162c682c8dba\
+         312210f44ff440fbd5e5c24732102bdd272891c9e4dfc3962b1fdffd5a59732019816f9db4833634dbdaf01a40\
+         1a52103280883dc31ccaee34218819aaa245480c35a33acd91283586ff6d1284ed681e52103e2bc790a6e32bf5\
+         a766919ff55b1f9e9914e13aed84f502c0e4171976e19deb054ae",
+    ).unwrap();
+
+    // The signatures are deterministic and in the low `s` form.
+    let signature = sign_tx_input(&unsigned_tx, 0, &redeem_script, priv_key.secret_key());
+    assert_eq!(
+        sign_tx_input(&unsigned_tx, 0, &redeem_script, priv_key.secret_key()),
+        signature
+    );
+    assert!(btc::signature::is_low_s(&signature));
+    assert_eq!(btc::signature::normalize_s(&signature), Some(signature.clone()));
+
+    // The malleated signature with the high `s` is rejected.
+    let high_s_signature = btc::signature::negate_s(&signature).unwrap();
+    assert!(!btc::signature::is_low_s(&high_s_signature));
+    assert!(!verify_tx_input(
+        &unsigned_tx,
+        0,
+        &redeem_script,
+        &pub_key,
+        &high_s_signature,
+    ));
+    assert_eq!(btc::signature::normalize_s(&high_s_signature), Some(signature.clone()));
+    assert_eq!(btc::signature::negate_s(&high_s_signature), Some(signature));
 }
 
 #[test]
@@ -545,7 +694,7 @@ fn test_anchoring_tx_prev_chain() {
         .payload(Height::zero(), Hash::default())
         .prev_tx_chain(Some(prev_tx.id()))
         .send_to(
-            btc::Address::from_base58check("2N1mHzwKTmjnC7JjqeGFBRKYE4WDTjTfop1").unwrap(),
+            "2N1mHzwKTmjnC7JjqeGFBRKYE4WDTjTfop1".parse::<btc::Address>().unwrap(),
         )
         .into_transaction()
         .unwrap();
@@ -554,6 +703,338 @@ fn test_anchoring_tx_prev_chain() {
     assert_eq!(tx.payload().prev_tx_chain, Some(prev_tx.id()));
 }
 
+#[test]
+fn test_anchoring_tx_state_hash() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let prev_tx = dummy_anchoring_tx(&redeem_script);
+    let state_hash = hash(&[1, 2, 3]);
+
+    let tx = TransactionBuilder::with_prev_tx(&prev_tx, 0)
+        .fee(1000)
+        .payload(Height(10), Hash::zero())
+        .state_hash(Some(state_hash))
+        .send_to(btc::Address::from_script(&redeem_script, Network::Testnet))
+        .into_transaction()
+        .unwrap();
+    let payload = tx.payload();
+    assert_eq!(payload.block_height, Height(10));
+    assert_eq!(payload.state_hash, Some(state_hash));
+    assert_eq!(tx.output[1].script_pubkey.len(), 83);
+}
+
+#[test]
+fn test_anchoring_tx_replaceable() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script);
+    assert!(tx.input.iter().all(|input| input.sequence == 0xFFFF_FFFF));
+
+    let replacement = TransactionBuilder::with_prev_tx(&tx, 0)
+        .fee(2000)
+        .payload(Height::zero(), Hash::zero())
+        .send_to(btc::Address::from_script(&redeem_script, Network::Testnet))
+        .replaceable(true)
+        .into_transaction()
+        .unwrap();
+    assert!(replacement.input.iter().all(
+        |input| input.sequence == REPLACEABLE_SEQUENCE,
+    ));
+}
+
+#[test]
+fn test_anchoring_tx_consolidate_funds() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let prev_tx = dummy_anchoring_tx(&redeem_script);
+    let funding_tx = FundingTx::from_hex(
+        "01000000019532a4022a22226a6f694c3f21216b2c9f5c1c79007eb7\
+         d3be06bc2f1f9e52fb000000006a47304402203661efd05ca422fad958b534dbad2e1c7db42bbd1e73e9b91f43\
+         a2f7be2f92040220740cf883273978358f25ca5dd5700cce5e65f4f0a0be2e1a1e19a8f168095400012102ae1b\
+         03b0f596be41a247080437a50f4d8e825b170770dcb4e5443a2eb2ecab2afeffffff02a00f00000000000017a9\
+         14bff50e89fa259d83f78f2e796f57283ca10d6e678716e1ff05000000001976a91402f5d7475a10a9c24cea32\
+         575bd8993d3fabbfd388ac089e1000",
+    ).unwrap();
+    let candidates = (0..2)
+        .map(|out| {
+            FundingCandidate {
+                tx: funding_tx.clone(),
+                out,
+                confirmations: 1,
+            }
+        })
+        .collect::<Vec<_>>();
+    let consolidate = |max_inputs: usize, input_fee: u64| {
+        TransactionBuilder::with_prev_tx(&prev_tx, 0)
+            .consolidate_funds(&candidates, max_inputs, &|funds| 1000 + funds as u64 * input_fee)
+            .inputs_count()
+    };
+
+    // The smallest funds are consolidated first.
+    assert_eq!(consolidate(2, 500), 2);
+    assert_eq!(consolidate(10, 500), 3);
+    // The funds of the first output do not cover the fee of its input.
+    assert_eq!(consolidate(2, 5000), 2);
+    let tx = TransactionBuilder::with_prev_tx(&prev_tx, 0)
+        .consolidate_funds(&candidates, 2, &|funds| 1000 + funds as u64 * 5000)
+        .fee(6000)
+        .payload(Height::zero(), Hash::zero())
+        .send_to(btc::Address::from_script(&redeem_script, Network::Testnet))
+        .into_transaction()
+        .unwrap();
+    assert_eq!(tx.input[1].prev_index, 1);
+
+    // The inputs limit is shared with the coin selection.
+    let limited = |max_inputs: usize| {
+        TransactionBuilder::with_prev_tx(&prev_tx, 0)
+            .max_inputs(max_inputs)
+            .select_funds(CoinSelection::ConsolidateAll, &candidates, &|_| 0)
+            .consolidate_funds(&candidates, 10, &|funds| 1000 + funds as u64 * 500)
+            .inputs_count()
+    };
+    assert_eq!(limited(1), 1);
+    assert_eq!(limited(2), 2);
+    assert_eq!(limited(10), 3);
+}
+
+#[test]
+fn test_anchoring_tx_max_inputs() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let script_types = [
+        btc::ScriptType::P2sh,
+        btc::ScriptType::P2shP2wsh,
+        btc::ScriptType::P2wsh,
+    ];
+    for &script_type in &script_types {
+        let max_inputs = max_anchoring_tx_inputs(&redeem_script, script_type, 3);
+        assert!(max_inputs > 100);
+        let weight = |inputs| estimate_anchoring_tx_weight(inputs, &redeem_script, script_type, 3);
+        assert!(weight(max_inputs) <= MAX_STANDARD_TX_WEIGHT);
+        assert!(weight(max_inputs + 1) > MAX_STANDARD_TX_WEIGHT);
+    }
+    // The witness data is cheaper, so the segwit transaction may have more inputs.
+    assert!(
+        max_anchoring_tx_inputs(&redeem_script, btc::ScriptType::P2shP2wsh, 3) >
+            max_anchoring_tx_inputs(&redeem_script, btc::ScriptType::P2sh, 3)
+    );
+}
+
+#[test]
+fn test_anchoring_tx_psbt_finalize() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script);
+
+    let signatures = (1..4)
+        .map(|id| tx.sign_input(&redeem_script, 0, &priv_keys[id]))
+        .collect::<Vec<_>>();
+    let mut psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2sh);
+    for id in (2..4).rev() {
+        psbt.add_signature(0, &pub_keys[id], &signatures[id - 1]);
+    }
+    assert_eq!(psbt.finalize(3), None);
+
+    let mut other_psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2sh);
+    other_psbt.add_signature(0, &pub_keys[1], &signatures[0]);
+    psbt.combine(Psbt::from_base64(&other_psbt.to_base64()).unwrap());
+    assert_eq!(Psbt::from_bytes(&psbt.to_bytes()).unwrap(), psbt);
+
+    let mut expected_signatures = HashMap::new();
+    expected_signatures.insert(0, signatures);
+    let expected_tx = tx.clone().finalize(&redeem_script, expected_signatures);
+    assert_eq!(psbt.finalize(3), Some(expected_tx));
+}
+
+#[test]
+fn test_key_signer() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script);
+
+    let psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2sh);
+    let signatures = KeySigner::new(priv_keys[0].clone()).sign(&psbt).unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert_eq!(signatures[&0], tx.sign_input(&redeem_script, 0, &priv_keys[0]));
+
+    // The witness input is not signed while the value of its spent output is unknown.
+    let psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2shP2wsh);
+    assert!(KeySigner::new(priv_keys[0].clone()).sign(&psbt).is_err());
+}
+
+#[test]
+fn test_external_signer() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script);
+    let psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2sh);
+
+    // Emulates the hardware wallet bridge.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    let (device_pub_key, device_priv_key) = (pub_keys[2], priv_keys[2].clone());
+    let device = thread::spawn(move || for _ in 0..2 {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(request["method"], "signtx");
+        let mut psbt = Psbt::from_base64(request["params"][0].as_str().unwrap()).unwrap();
+        let signatures = KeySigner::new(device_priv_key.clone()).sign(&psbt).unwrap();
+        for (input, signature) in signatures {
+            psbt.add_signature(input, &device_pub_key, &signature);
+        }
+        let response = format!("{}\n", json!({ "psbt": psbt.to_base64() }));
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+    });
+
+    let config = ExternalSignerConfig {
+        host,
+        timeout: DEFAULT_EXTERNAL_SIGNER_TIMEOUT,
+    };
+    let signer = ExternalSigner::new(config.clone(), pub_keys[2]);
+    let signatures = signer.sign(&psbt).unwrap();
+    assert_eq!(signatures[&0], tx.sign_input(&redeem_script, 0, &priv_keys[2]));
+
+    // The signatures of the keys other than the anchoring key of the node are ignored.
+    let signer = ExternalSigner::new(config, pub_keys[1]);
+    assert!(signer.sign(&psbt).is_err());
+    device.join().unwrap();
+}
+
+#[test]
+fn test_anchoring_tx_size_estimation() {
+    let tx = AnchoringTx::from_hex(
+        "01000000014970bd8d76edf52886f62e3073714bddc6c33bccebb6b1d\
+         06db8c87fb1103ba000000000fd670100483045022100e6ef3de83437c8dc33a8099394b7434dfb40c73631fc4\
+         b0378bd6fb98d8f42b002205635b265f2bfaa6efc5553a2b9e98c2eabdfad8e8de6cdb5d0d74e37f1e19852014\
+         7304402203bb845566633b726e41322743677694c42b37a1a9953c5b0b44864d9b9205ca10220651b701271987\
+         1c36d0f89538304d3f358da12b02dab2b4d74f2981c8177b69601473044022052ad0d6c56aa6e971708f079073\
+         260856481aeee6a48b231bc07f43d6b02c77002203a957608e4fbb42b239dd99db4e243776cc55ed8644af21fa\
+         80fd9be77a59a60014c8b532103475ab0e9cfc6015927e662f6f8f088de12287cee1a3237aeb497d1763064690\
+         c2102a63948315dda66506faf4fecd54b085c08b13932a210fa5806e3691c69819aa0210230cb2805476bf984d\
+         2236b56ff5da548dfe116daf2982608d898d9ecb3dceb4921036e4777c8d19ccaa67334491e777f221d37fd85d\
+         5786a4e5214b281cf0133d65e54aeffffffff02b80b00000000000017a914bff50e89fa259d83f78f2e796f572\
+         83ca10d6e678700000000000000002c6a2a01280000000000000000f1cb806d27e367f1cac835c22c8cc24c402\
+         a019e2d3ea82f7f841c308d830a9600000000",
+    ).unwrap();
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let size = tx.to_hex().len() as u64 / 2;
+    assert_eq!(tx.vsize(), size);
+    let script_type = btc::ScriptType::P2sh;
+    let estimated_size = estimate_anchoring_tx_size(1, &redeem_script, script_type, 3);
+    assert!(estimated_size >= size);
+    assert!(estimated_size < size + 64);
+    assert!(estimate_anchoring_tx_size(2, &redeem_script, script_type, 3) > estimated_size);
+}
+
+#[test]
+fn test_anchoring_tx_witness_size_estimation() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script);
+    let value = 3000;
+    let signatures = priv_keys[0..3]
+        .iter()
+        .map(|priv_key| tx.sign_witness_input(&redeem_script, 0, value, priv_key))
+        .collect::<Vec<_>>();
+    let signatures = vec![(0, signatures)].into_iter().collect::<HashMap<_, _>>();
+    let tx = tx.finalize_witness(&redeem_script, signatures);
+
+    // The witness data is counted with a quarter of its size.
+    let vsize = tx.vsize();
+    assert!(vsize < tx.to_hex().len() as u64 / 2);
+    let estimated_size =
+        estimate_anchoring_tx_size(1, &redeem_script, btc::ScriptType::P2shP2wsh, 3);
+    assert!(estimated_size >= vsize);
+    assert!(estimated_size < vsize + 64);
+    let legacy_size = estimate_anchoring_tx_size(1, &redeem_script, btc::ScriptType::P2sh, 3);
+    assert!(estimated_size < legacy_size);
+}
+
+#[test]
+fn test_anchoring_tx_change_policy() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+    let (treasury_keys, _) = gen_anchoring_keys(2);
+    let treasury_script =
+        btc::RedeemScript::from_pubkeys(&treasury_keys, 2).compressed(Network::Testnet);
+    let treasury = btc::Address::from_script(&treasury_script, Network::Testnet);
+    let input_tx = dummy_anchoring_tx(&redeem_script);
+
+    let build = |retain| {
+        TransactionBuilder::with_prev_tx(&input_tx, 0)
+            .fee(500)
+            .payload(Height::zero(), Hash::zero())
+            .send_to(addr.clone())
+            .change_policy(Some((treasury.clone(), retain)))
+            .into_transaction()
+            .unwrap()
+    };
+
+    let tx = build(600);
+    assert_eq!(tx.amount(), 600);
+    assert_eq!(tx.total_amount(), 1500);
+    let surplus = tx.surplus_output().unwrap();
+    assert_eq!(surplus.value, 900);
+    assert_eq!(surplus.script_pubkey, treasury.script_pubkey());
+    assert!(canonical::is_canonical(&tx));
+
+    // The surplus below the dust threshold stays on the anchoring address.
+    let tx = build(1000);
+    assert_eq!(tx.amount(), 1500);
+    assert!(tx.surplus_output().is_none());
+}
+
+#[test]
+fn test_anchoring_tx_burn_dust() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+    let input_tx = dummy_anchoring_tx(&redeem_script);
+
+    let build = |fee, burn_dust| {
+        TransactionBuilder::with_prev_tx(&input_tx, 0)
+            .fee(fee)
+            .payload(Height(10), hash(&[1]))
+            .send_to(addr.clone())
+            .burn_dust(burn_dust)
+            .into_transaction()
+            .unwrap()
+    };
+
+    // The change above the dust threshold is kept anyway.
+    let tx = build(1000, true);
+    assert!(!tx.is_final());
+    assert_eq!(tx.amount(), 1000);
+
+    let tx = build(1600, false);
+    assert!(!tx.is_final());
+    assert_eq!(tx.amount(), 400);
+
+    let tx = build(1600, true);
+    assert!(tx.is_final());
+    assert_eq!(tx.amount(), 0);
+    assert_eq!(tx.total_amount(), 0);
+    assert_eq!(tx.output.len(), 1);
+    assert_eq!(tx.payload().block_height, Height(10));
+    assert!(canonical::is_canonical(&tx));
+    match TxKind::from(tx.0.clone()) {
+        TxKind::Anchoring(final_tx) => assert_eq!(final_tx, tx),
+        _ => panic!("Wrong tx kind!"),
+    }
+}
+
 #[test]
 fn test_tx_kind_funding() {
     let tx = BitcoinTx::from_hex(
@@ -601,6 +1082,58 @@ fn test_tx_kind_other() {
     }
 }
 
+#[test]
+fn test_tx_kind_unknown_payload_version() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let mut tx = dummy_anchoring_tx(&redeem_script).0;
+    // The payload of the dummy transaction with the version byte set to 255.
+    tx.output[1].script_pubkey = Script::from_hex(
+        "6a3045584f4e554dff0000000000000000000000000000000000000000000000000000000000000000\
+         000000000000000000",
+    ).unwrap();
+    match TxKind::from(tx) {
+        TxKind::Other(_) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+}
+
+#[test]
+fn test_tx_kind_malformed_anchoring_layout() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let tx = dummy_anchoring_tx(&redeem_script).0;
+
+    // The funds output with the script which is not `p2sh`.
+    let mut wrong_output = tx.clone();
+    wrong_output.output[0].script_pubkey = Script::from_hex("6a").unwrap();
+    // The transaction without inputs.
+    let mut no_inputs = tx.clone();
+    no_inputs.input.clear();
+    for tx in vec![wrong_output, no_inputs] {
+        match TxKind::from(tx) {
+            TxKind::Other(_) => {}
+            _ => panic!("Wrong tx kind!"),
+        }
+    }
+}
+
+#[test]
+fn test_tx_kind_from_bytes() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let bytes = dummy_anchoring_tx(&redeem_script).into_bytes();
+
+    match TxKind::from_bytes(&bytes) {
+        Some(TxKind::Anchoring(_)) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+    for len in 0..bytes.len() {
+        TxKind::from_bytes(&bytes[..len]);
+    }
+    assert_eq!(TxKind::from_bytes(&[]), None);
+}
+
 #[test]
 fn test_tx_verify_sighash_type_correct() {
     let (pub_keys, priv_keys) = gen_anchoring_keys(4);
@@ -678,6 +1211,317 @@ fn test_tx_verify_sighash_type_wrong() {
     assert!(tx.verify_input(&redeem_script, 0, &pub_key, &btc_signature));
 }
 
+#[test]
+fn test_tx_verify_witness_input() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let tx = dummy_anchoring_tx(&redeem_script);
+    let value = 3000;
+    for (pub_key, priv_key) in pub_keys.iter().zip(priv_keys.iter()) {
+        let btc_signature = tx.sign_witness_input(&redeem_script, 0, value, priv_key);
+
+        assert!(tx.verify_witness_input(
+            &redeem_script,
+            0,
+            value,
+            pub_key,
+            &btc_signature,
+        ));
+        assert!(!tx.verify_witness_input(
+            &redeem_script,
+            0,
+            value + 1,
+            pub_key,
+            &btc_signature,
+        ));
+        assert!(!tx.verify_input(&redeem_script, 0, pub_key, &btc_signature));
+    }
+}
+
+#[test]
+fn test_tx_verify_cached_signatures() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let tx = dummy_anchoring_tx(&redeem_script);
+    let value = 3000;
+    let components = SighashComponents::new(&tx);
+    assert_eq!(SighashComponents::cached(&tx), components);
+    assert_eq!(
+        components.sighash(&tx, 0, &redeem_script, value),
+        witness_signature_hash(&tx, 0, &redeem_script, value)
+    );
+
+    // The repeated checks use the cached results of the same signatures only.
+    let signature = tx.sign_input(&redeem_script, 0, &priv_keys[0]);
+    let witness_signature = tx.sign_witness_input(&redeem_script, 0, value, &priv_keys[0]);
+    for _ in 0..2 {
+        assert!(tx.verify_input(&redeem_script, 0, &pub_keys[0], &signature));
+        assert!(!tx.verify_input(&redeem_script, 0, &pub_keys[1], &signature));
+        assert!(tx.verify_witness_input(
+            &redeem_script,
+            0,
+            value,
+            &pub_keys[0],
+            &witness_signature,
+        ));
+        assert!(!tx.verify_witness_input(
+            &redeem_script,
+            0,
+            value,
+            &pub_keys[0],
+            &signature,
+        ));
+    }
+}
+
+#[test]
+fn test_redeem_script_witness_address() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let witness_program = redeem_script.witness_program().into_vec();
+    assert_eq!(witness_program.len(), 34);
+    assert_eq!(witness_program[0], 0);
+
+    let witness_addr = redeem_script.to_witness_address(Network::Testnet);
+    assert!(witness_addr.script_pubkey().is_p2sh());
+    assert!(witness_addr != redeem_script.to_address(Network::Testnet));
+}
+
+#[test]
+fn test_redeem_script_native_witness_address() {
+    let (pub_keys, _) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+
+    let address = |chain| redeem_script.to_native_witness_address(chain).to_string();
+    let mainnet_addr = address(btc::Chain::Mainnet);
+    let testnet_addr = address(btc::Chain::Testnet);
+    let regtest_addr = address(btc::Chain::Regtest);
+    assert!(mainnet_addr.starts_with("bc1q"));
+    assert!(testnet_addr.starts_with("tb1q"));
+    assert!(regtest_addr.starts_with("bcrt1q"));
+    assert_eq!(address(btc::Chain::Signet), testnet_addr);
+    // The program is the same for all chains.
+    let program = &mainnet_addr[2..mainnet_addr.len() - 6];
+    assert_eq!(&testnet_addr[2..testnet_addr.len() - 6], program);
+    assert_eq!(&regtest_addr[4..regtest_addr.len() - 6], program);
+
+    let addr = regtest_addr.parse::<btc::Address>().unwrap();
+    assert_eq!(addr, redeem_script.to_native_witness_address(btc::Chain::Regtest));
+    assert_eq!(addr.to_string(), regtest_addr);
+    assert_eq!(addr.script_pubkey(), redeem_script.witness_program());
+    assert!(addr.is_script_hash());
+    assert!(addr.is_valid_for(btc::Chain::Regtest));
+    assert!(!addr.is_valid_for(btc::Chain::Testnet));
+    assert_eq!(serde_json::to_string(&addr).unwrap(), format!("\"{}\"", regtest_addr));
+    assert!(testnet_addr.to_uppercase().parse::<btc::Address>().is_ok());
+    assert!(testnet_addr[..testnet_addr.len() - 1].parse::<btc::Address>().is_err());
+}
+
+#[test]
+fn test_anchoring_tx_native_witness() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(4);
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys, 3).compressed(Network::Testnet);
+    let addr = redeem_script.to_native_witness_address(btc::Chain::Testnet);
+    let prev_tx = dummy_anchoring_tx(&redeem_script);
+    let tx = TransactionBuilder::with_prev_tx(&prev_tx, 0)
+        .fee(1000)
+        .payload(Height(10), hash(&[1]))
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    assert_eq!(tx.output_address(btc::Chain::Testnet), addr);
+    assert_eq!(tx.find_out(&addr), Some(0));
+    match TxKind::from(tx.0.clone()) {
+        TxKind::Anchoring(_) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+
+    let value = prev_tx.amount();
+    let signatures = priv_keys[0..3]
+        .iter()
+        .map(|priv_key| tx.sign_witness_input(&redeem_script, 0, value, priv_key))
+        .collect::<Vec<_>>();
+    let mut psbt = Psbt::from_anchoring_tx(&tx, &redeem_script, btc::ScriptType::P2wsh);
+    for (id, signature) in signatures.iter().enumerate() {
+        psbt.add_signature(0, &pub_keys[id], signature);
+    }
+    let expected_signatures = vec![(0, signatures)].into_iter().collect::<HashMap<_, _>>();
+    let signed_tx = tx.finalize_native_witness(&redeem_script, expected_signatures);
+    assert_eq!(psbt.finalize(3), Some(signed_tx.clone()));
+    // The native witness inputs have the empty `scriptSig`.
+    assert_eq!(signed_tx.0.input[0].script_sig, Script::new());
+    assert_eq!(signed_tx.0.witness[0].len(), 5);
+
+    let vsize = signed_tx.vsize();
+    let estimated_size = estimate_anchoring_tx_size(1, &redeem_script, btc::ScriptType::P2wsh, 3);
+    assert!(estimated_size >= vsize);
+    assert!(estimated_size < vsize + 64);
+    assert!(
+        estimated_size <
+            estimate_anchoring_tx_size(1, &redeem_script, btc::ScriptType::P2shP2wsh, 3)
+    );
+}
+
+#[test]
+fn test_redeem_script_recovery_branch() {
+    let (pub_keys, _) = gen_anchoring_keys(5);
+    let redeem_script =
+        btc::RedeemScript::from_pubkeys(&pub_keys[0..4], 3).compressed(Network::Testnet);
+    assert_eq!(redeem_script.recovery_branch(), None);
+
+    for timeout in &[144, 1000, 65_535] {
+        let recovery = btc::RecoveryBranch {
+            key: pub_keys[4],
+            timeout: *timeout,
+        };
+        let recovery_script = redeem_script.with_recovery(&recovery);
+        assert_eq!(recovery_script.recovery_branch(), Some(recovery));
+        assert!(
+            recovery_script.to_address(Network::Testnet) !=
+                redeem_script.to_address(Network::Testnet)
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Recovery timeout 0 is less than the minimum")]
+fn test_redeem_script_recovery_branch_zero_timeout() {
+    let (pub_keys, _) = gen_anchoring_keys(5);
+    let recovery = btc::RecoveryBranch {
+        key: pub_keys[4],
+        timeout: 0,
+    };
+    btc::RedeemScript::from_pubkeys(&pub_keys[0..4], 3).with_recovery(&recovery);
+}
+
+#[test]
+fn test_anchoring_tx_finalize_recovery_branch() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(5);
+    let recovery = btc::RecoveryBranch {
+        key: pub_keys[4],
+        timeout: 144,
+    };
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys[0..4], 3)
+        .compressed(Network::Testnet)
+        .with_recovery(&recovery);
+    let tx = dummy_anchoring_tx(&redeem_script);
+
+    let signatures = make_signatures(&redeem_script, &tx, &[0], &priv_keys[0..4]);
+    let tx = tx.finalize(&redeem_script, signatures);
+    let script_sig = tx.0.input[0].script_sig.clone().into_vec();
+    let redeem_script_len = redeem_script.0.len();
+    // The multisig branch is selected by the `OP_TRUE` right before the redeem script push.
+    assert_eq!(script_sig[script_sig.len() - redeem_script_len - 3], 0x51);
+    let estimated_size = estimate_anchoring_tx_size(1, &redeem_script, btc::ScriptType::P2sh, 3);
+    assert!(estimated_size >= tx.to_hex().len() as u64 / 2);
+
+    match TxKind::from(tx.0) {
+        TxKind::Anchoring(_) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+}
+
+#[test]
+fn test_recovery_tx() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(5);
+    let recovery = btc::RecoveryBranch {
+        key: pub_keys[4],
+        timeout: 144,
+    };
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys[0..4], 3)
+        .compressed(Network::Testnet)
+        .with_recovery(&recovery);
+    let lect = dummy_anchoring_tx(&redeem_script);
+    let refund_addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+
+    let inputs = [(lect.0.clone(), 0)];
+    let tx = create_recovery_tx(
+        &redeem_script,
+        btc::ScriptType::P2sh,
+        &inputs,
+        &refund_addr,
+        1000,
+        &priv_keys[4],
+    ).unwrap();
+    assert_eq!(tx.0.version, RECOVERY_TX_VERSION);
+    assert_eq!(tx.0.input[0].sequence, 144);
+    assert_eq!(tx.0.output[0].value, lect.amount() - 1000);
+
+    // The recovery key signs the input, the empty push selects the recovery branch.
+    let pushes = tx.0.input[0]
+        .script_sig
+        .into_iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::PushBytes(bytes) => Some(bytes.to_vec()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(pushes.len(), 3);
+    assert!(pushes[1].is_empty());
+    let signature = &pushes[0][0..pushes[0].len() - 1];
+    assert!(verify_tx_input(&tx, 0, &redeem_script, &pub_keys[4], signature));
+    assert!(!verify_tx_input(&tx, 0, &redeem_script, &pub_keys[0], signature));
+
+    // The reclaimed funds are not taken for the funding transaction.
+    match TxKind::from(tx.clone()) {
+        TxKind::Other(_) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+    assert!(
+        create_recovery_tx(
+            &redeem_script,
+            btc::ScriptType::P2sh,
+            &inputs,
+            &refund_addr,
+            lect.amount() + 1,
+            &priv_keys[4],
+        ).is_err()
+    );
+}
+
+#[test]
+fn test_recovery_tx_witness() {
+    let (pub_keys, priv_keys) = gen_anchoring_keys(5);
+    let recovery = btc::RecoveryBranch {
+        key: pub_keys[4],
+        timeout: 144,
+    };
+    let redeem_script = btc::RedeemScript::from_pubkeys(&pub_keys[0..4], 3)
+        .compressed(Network::Testnet)
+        .with_recovery(&recovery);
+    let lect = dummy_anchoring_tx(&redeem_script);
+    let refund_addr = btc::Address::from_script(&redeem_script, Network::Testnet);
+
+    let tx = create_recovery_tx(
+        &redeem_script,
+        btc::ScriptType::P2shP2wsh,
+        &[(lect.0.clone(), 0)],
+        &refund_addr,
+        1000,
+        &priv_keys[4],
+    ).unwrap();
+    let witness = &tx.0.witness[0];
+    assert_eq!(witness.len(), 3);
+    assert!(witness[1].is_empty());
+    assert_eq!(witness[2], redeem_script.0.clone().into_vec());
+
+    let anchoring_tx = AnchoringTx(tx.0.clone());
+    assert!(anchoring_tx.verify_witness_input(
+        &redeem_script,
+        0,
+        lect.amount(),
+        &pub_keys[4],
+        &witness[0],
+    ));
+    match TxKind::from(tx) {
+        TxKind::Other(_) => {}
+        _ => panic!("Wrong tx kind!"),
+    }
+}
+
 // rpc tests. Works through `rpc` by given env variables.
 // See the `anchoring_client` method on top of this file.
 #[cfg(feature = "rpc_tests")]
@@ -685,7 +1529,6 @@ mod rpc {
     use super::*;
 
     use bitcoin::network::constants::Network;
-    use bitcoin::util::base58::ToBase58;
     use bitcoinrpc;
 
     use exonum::helpers::{self, Height};
@@ -704,6 +1547,7 @@ mod rpc {
                 .unwrap(),
             username: env::var("ANCHORING_USER").ok(),
             password: env::var("ANCHORING_PASSWORD").ok(),
+            ..Default::default()
         };
 
         RpcClient::from(rpc)
@@ -852,7 +1696,7 @@ mod rpc {
         };
 
         let utxos = client
-            .listunspent(0, 9999999, &[addr.to_base58check().as_ref()])
+            .listunspent(0, 9999999, &[addr.to_string().as_ref()])
             .unwrap();
         trace!("utxos={:#?}", utxos);
 
@@ -983,7 +1827,7 @@ mod rpc {
         };
 
         let utxos = client
-            .listunspent(0, 9999999, &[addr.to_base58check().as_ref()])
+            .listunspent(0, 9999999, &[addr.to_string().as_ref()])
             .unwrap();
         trace!("utxos={:#?}", utxos);
 
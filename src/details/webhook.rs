@@ -0,0 +1,88 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Delivery of the JSON notifications to the http endpoint.
+
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+
+use hyper;
+use hyper::header::ContentType;
+use hyper::net::HttpsConnector;
+use hyper_native_tls::NativeTlsClient;
+use serde_json::Value;
+
+/// Sender of the JSON notifications to the webhook `url`.
+///
+/// Notifications are posted one by one on a background thread, so an unreachable endpoint
+/// does not stall the block commit. Failed deliveries are logged and are not retried.
+pub struct Webhook {
+    url: String,
+    notifications: mpsc::Sender<Value>,
+}
+
+impl Webhook {
+    /// Creates a webhook for the given `url` and spawns its worker thread.
+    pub fn new<S: Into<String>>(url: S) -> Webhook {
+        let url = url.into();
+        let (sender, receiver) = mpsc::channel::<Value>();
+
+        let worker_url = url.clone();
+        thread::spawn(move || {
+            let tls = NativeTlsClient::new().expect("Unable to initialize TLS client");
+            let client = hyper::Client::with_connector(HttpsConnector::new(tls));
+            for notification in receiver {
+                let body = notification.to_string();
+                let result = client
+                    .post(&worker_url)
+                    .header(ContentType::json())
+                    .body(body.as_str())
+                    .send();
+                match result {
+                    Ok(ref response) if response.status.is_success() => {}
+                    Ok(response) => error!(
+                        "Webhook {} rejected the notification, status={}",
+                        worker_url,
+                        response.status
+                    ),
+                    Err(e) => error!("Unable to post the notification to {}: {}", worker_url, e),
+                }
+            }
+        });
+
+        Webhook {
+            url,
+            notifications: sender,
+        }
+    }
+
+    /// Returns the url of the webhook.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Enqueues the `notification` for the delivery.
+    pub fn notify(&self, notification: Value) {
+        if self.notifications.send(notification).is_err() {
+            error!("Webhook {} worker is stopped", self.url);
+        }
+    }
+}
+
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Webhook").field("url", &self.url).finish()
+    }
+}
@@ -12,29 +12,188 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::error;
+use std::fmt;
 use std::io;
 
+use exonum::helpers::Height;
+
+use bitcoinrpc::Error as RpcError;
+use details::btc::TxId;
+
 pub use details::error::Error as InternalError;
 pub use handler::error::Error as HandlerError;
-use bitcoinrpc::Error as RpcError;
 
 /// Anchoring btc service Error type.
-#[derive(Debug, Error)]
+///
+/// The failed bitcoin backend requests carry the context of the anchoring round,
+/// so it is possible to find out the reason of the failure from the logs.
+#[derive(Debug)]
 pub enum Error {
-    /// Internal error
-    Internal(InternalError),
-    /// Handler error.
+    /// Request to the bitcoin backend has failed, for example, the backend is unreachable.
+    Rpc(RpcFailure),
+    /// Anchoring transaction violates the bitcoin rules, for example, there are not enough
+    /// funds to pay the fee.
+    Bitcoin {
+        /// Reason of the error.
+        reason: String,
+        /// `Txid` of the transaction.
+        txid: Option<TxId>,
+    },
+    /// Anchoring data in the blockchain storage is inconsistent.
     Handler(HandlerError),
+    /// Node or anchoring configuration is incorrect.
+    Config {
+        /// Reason of the error.
+        reason: String,
+    },
+    /// An input output error, for example, of the external signer.
+    Io(io::Error),
+}
+
+/// Failed request to the bitcoin backend.
+#[derive(Debug)]
+pub struct RpcFailure {
+    /// Error returned by the bitcoin backend client.
+    pub error: RpcError,
+    /// Host of the bitcoin backend.
+    pub endpoint: Option<String>,
+    /// Height of the block whose commit has been handled.
+    pub height: Option<Height>,
+    /// `Txid` of the requested or sent transaction.
+    pub txid: Option<TxId>,
+}
+
+impl Error {
+    /// Returns the bitcoin backend client error if it has caused this error.
+    pub fn rpc_error(&self) -> Option<&RpcError> {
+        match *self {
+            Error::Rpc(ref failure) => Some(&failure.error),
+            _ => None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn with_txid(mut self, id: TxId) -> Error {
+        match self {
+            Error::Rpc(ref mut failure) => {
+                failure.txid = failure.txid.or(Some(id));
+            }
+            Error::Bitcoin { ref mut txid, .. } => {
+                *txid = txid.or(Some(id));
+            }
+            _ => {}
+        }
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn with_round(mut self, round_height: Height, host: Option<String>) -> Error {
+        if let Error::Rpc(ref mut failure) = self {
+            failure.height = failure.height.or(Some(round_height));
+            if failure.endpoint.is_none() {
+                failure.endpoint = host;
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for RpcFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bitcoin backend request failed: {}", self.error)?;
+        if let Some(ref endpoint) = self.endpoint {
+            write!(f, ", endpoint={}", endpoint)?;
+        }
+        if let Some(height) = self.height {
+            write!(f, ", height={}", height)?;
+        }
+        if let Some(ref txid) = self.txid {
+            write!(f, ", txid={}", txid.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Rpc(ref failure) => write!(f, "{}", failure),
+            Error::Bitcoin {
+                ref reason,
+                ref txid,
+            } => {
+                write!(f, "Incorrect bitcoin transaction: {}", reason)?;
+                if let Some(ref txid) = *txid {
+                    write!(f, ", txid={}", txid.to_string())?;
+                }
+                Ok(())
+            }
+            Error::Handler(ref e) => write!(f, "{}", e),
+            Error::Config { ref reason } => write!(f, "Incorrect configuration: {}", reason),
+            Error::Io(ref e) => write!(f, "An input output error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Rpc(_) => "Bitcoin backend request failed",
+            Error::Bitcoin { .. } => "Incorrect bitcoin transaction",
+            Error::Handler(ref e) => error::Error::description(e),
+            Error::Config { .. } => "Incorrect configuration",
+            Error::Io(ref e) => error::Error::description(e),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Rpc(ref failure) => Some(&failure.error),
+            Error::Handler(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::Bitcoin { .. } |
+            Error::Config { .. } => None,
+        }
+    }
 }
 
 impl From<RpcError> for Error {
-    fn from(err: RpcError) -> Error {
-        Error::Internal(InternalError::Rpc(err))
+    fn from(error: RpcError) -> Error {
+        Error::Rpc(RpcFailure {
+            error,
+            endpoint: None,
+            height: None,
+            txid: None,
+        })
+    }
+}
+
+impl From<InternalError> for Error {
+    fn from(err: InternalError) -> Error {
+        match err {
+            InternalError::Rpc(e) => Error::from(e),
+            InternalError::InsufficientFunds => Error::Bitcoin {
+                reason: "Insufficient funds to create the anchoring transaction".to_string(),
+                txid: None,
+            },
+            InternalError::PayloadHeightOverflow => Error::Bitcoin {
+                reason: "Block height does not fit the anchoring transaction payload".to_string(),
+                txid: None,
+            },
+            InternalError::Io(e) => Error::Io(e),
+        }
+    }
+}
+
+impl From<HandlerError> for Error {
+    fn from(err: HandlerError) -> Error {
+        Error::Handler(err)
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error::Internal(InternalError::Io(err))
+        Error::Io(err)
     }
 }
@@ -0,0 +1,85 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Alerts of the anchoring handler.
+
+use details::btc;
+
+/// Alert which requires the attention of the node maintainers.
+///
+/// Alerts are sent to the channel set by the
+/// [`set_alerts_sink`](../struct.AnchoringHandler.html#method.set_alerts_sink)
+/// and are posted to the `webhook` of the [`AlertsConfig`](struct.AlertsConfig.html).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "alert", rename_all = "snake_case")]
+pub enum AnchoringAlert {
+    /// Funds of the lect cover less than `low_funds_anchors` anchoring transactions,
+    /// so the anchoring address should be replenished.
+    LowFunds {
+        /// Actual anchoring address.
+        address: btc::Address,
+        /// Funds of the lect in satoshis.
+        funds: u64,
+        /// Number of the following anchoring transactions which can be funded.
+        anchors_left: u64,
+    },
+    /// The change of the lect is below the dust threshold, so the anchoring address
+    /// must be funded to continue the anchoring.
+    WalletEmpty {
+        /// Actual anchoring address.
+        address: btc::Address,
+        /// `Txid` of the lect.
+        lect: btc::TxId,
+        /// Whether the lect is the final anchoring transaction which has burned the change.
+        is_final: bool,
+    },
+    /// The anchoring rounds have been abandoned `failed_rounds` times in a row.
+    FailedRounds {
+        /// Number of the consecutive abandoned rounds.
+        count: u64,
+    },
+    /// The lect has no confirmations for longer than `unconfirmed_lect_timeout` seconds.
+    LectUnconfirmed {
+        /// `Txid` of the lect.
+        txid: btc::TxId,
+        /// Number of the seconds since the lect has been observed first.
+        seconds: u64,
+    },
+    /// The lect is not found in the unspent outputs of the anchoring address.
+    ChainBroken {
+        /// `Txid` of the lost lect.
+        lect: btc::TxId,
+    },
+}
+
+/// Conditions of the anchoring alerts and their delivery, it is a part of the node
+/// configuration.
+///
+/// The `low_funds`, `wallet_empty` and `chain_broken` alerts are always raised, the other ones
+/// are raised only if their thresholds are specified.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AlertsConfig {
+    /// Url to which the alerts are posted as JSON objects.
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// Number of the consecutive abandoned anchoring rounds after which
+    /// the `failed_rounds` alert is raised.
+    #[serde(default)]
+    pub failed_rounds: Option<u64>,
+    /// Time in seconds after which the `lect_unconfirmed` alert is raised for
+    /// the lect without confirmations, the alert is repeated while the lect stays unconfirmed.
+    #[serde(default)]
+    pub unconfirmed_lect_timeout: Option<u64>,
+}
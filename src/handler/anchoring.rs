@@ -12,21 +12,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bitcoin::util::base58::ToBase58;
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
 
 use exonum::blockchain::{Schema, ServiceContext};
+use exonum::crypto::{hash, Hash};
 use exonum::helpers::Height;
-use exonum::encoding::serialize::encode_hex;
+use exonum::encoding::serialize::{encode_hex, FromHex};
 
 use error::Error as ServiceError;
 use details::btc;
 use details::btc::HexValueEx;
-use details::btc::transactions::{AnchoringTx, TransactionBuilder};
-use blockchain::consensus_storage::AnchoringConfig;
+use details::btc::canonical::round_fee_rate;
+use details::btc::payload::commitments_root;
+use details::btc::transactions::{AnchoringTx, TransactionBuilder, DUST_THRESHOLD};
+use details::btc::psbt::Psbt;
+use details::btc::transactions::{estimate_anchoring_tx_size, max_anchoring_tx_inputs, tx_vsize};
+use details::rpc::{broadcast_rejection, BroadcastRejection};
+use blockchain::consensus_storage::{AnchoringConfig, CatchUpPolicy, DustPolicy};
 use blockchain::schema::AnchoringSchema;
-use blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest};
+use blockchain::dto::{MsgAnchoringFeeRate, MsgAnchoringSignature, MsgAnchoringUpdateLatest};
 
-use super::{AnchoringHandler, LectKind, MultisigAddress, collect_signatures};
+use super::{AnchoringHandler, LectKind, MultisigAddress, add_partial_signatures,
+            collect_partial_signatures};
+use super::alert::AnchoringAlert;
+use super::error::Error as HandlerError;
+use super::event::AnchoringEvent;
+use super::policy::{check_policies, PendingApproval, PolicyDecision, ProposalSummary};
+use super::state::RoundState;
 
 #[doc(hidden)]
 impl AnchoringHandler {
@@ -35,13 +48,18 @@ impl AnchoringHandler {
         cfg: &AnchoringConfig,
         context: &ServiceContext,
     ) -> Result<(), ServiceError> {
-        let multisig = self.multisig_address(cfg);
-        trace!("Anchoring state, addr={}", multisig.addr.to_base58check());
+        let multisig = self.multisig_address(cfg, context);
+        trace!("Anchoring state, addr={}", multisig.addr.to_string());
 
-        if context.height().0 % self.node.check_lect_frequency == 0 {
+        if self.retry.lect_update {
             // First of all we try to update our lect and actual configuration
-            self.update_our_lect(&multisig, context)?;
+            if self.update_our_lect(&multisig, context)?.is_none() &&
+                self.detect_broken_chain(&multisig, context)?
+            {
+                return Ok(());
+            }
         }
+        self.send_fee_rate_vote(&multisig, context)?;
         // Now if we have anchoring tx proposal we must try to finalize it
         if let Some(proposal) = self.proposal_tx.clone() {
             self.try_finalize_proposal_tx(proposal, &multisig, context)?;
@@ -62,16 +80,41 @@ impl AnchoringHandler {
             self.anchoring_key(multisig.common, context),
             multisig.common,
             context,
-        );
+        )?;
         match lect {
             LectKind::Funding(_) => self.try_create_anchoring_tx_chain(multisig, None, context),
+            LectKind::Anchoring(ref tx) if tx.is_final() => {
+                self.check_wallet_empty(tx, multisig);
+                // The anchoring chain is continued only by a new funding transaction.
+                self.try_create_anchoring_tx_chain(multisig, Some(tx.id()), context)
+            }
             LectKind::Anchoring(tx) => {
+                self.check_low_funds(&tx, multisig);
+                self.check_wallet_empty(&tx, multisig);
                 let anchored_height = tx.payload().block_height;
+                let latest_anchored_height = multisig.common.latest_anchoring_height_for_funds(
+                    context.height(),
+                    tx.amount(),
+                );
+                let latest_anchored_height =
+                    multisig.common.catch_up_height(anchored_height, latest_anchored_height);
                 let latest_anchored_height =
-                    multisig.common.latest_anchoring_height(context.height());
+                    match self.anchoring_trigger(anchored_height, context) {
+                        Some(height) if height > latest_anchored_height => height,
+                        _ => latest_anchored_height,
+                    };
                 if latest_anchored_height > anchored_height {
+                    if self.lect_needs_fee_bump(&tx, multisig, context)? {
+                        return self.create_replacement_tx(
+                            &tx,
+                            multisig,
+                            latest_anchored_height,
+                            context,
+                        );
+                    }
                     return self.create_proposal_tx(&tx, multisig, latest_anchored_height, context);
                 }
+                self.check_anchoring_interval(&tx, multisig);
                 Ok(())
             }
             LectKind::None => {
@@ -81,6 +124,96 @@ impl AnchoringHandler {
         }
     }
 
+    // Returns the height of the triggered block if it is not anchored yet.
+    fn anchoring_trigger(
+        &self,
+        anchored_height: Height,
+        context: &ServiceContext,
+    ) -> Option<Height> {
+        let trigger = AnchoringSchema::new(context.snapshot()).latest_anchoring_trigger();
+        match trigger {
+            Some(height) if height > anchored_height && height <= context.height() => Some(height),
+            _ => None,
+        }
+    }
+
+    // Schedules the anchoring trigger once the lect is older than the `max_anchoring_interval`
+    // by the local clock of the node.
+    fn check_anchoring_interval(&mut self, lect: &AnchoringTx, multisig: &MultisigAddress) {
+        let interval = match multisig.common.max_anchoring_interval {
+            Some(interval) => Duration::from_secs(interval),
+            None => return,
+        };
+        let now = Instant::now();
+        match self.anchored_since {
+            Some((txid, since)) if txid == lect.id() => {
+                if now.duration_since(since) < interval {
+                    return;
+                }
+            }
+            _ => {
+                self.anchored_since = Some((lect.id(), now));
+                return;
+            }
+        }
+        info!(
+            "Lect txid={} is older than {} seconds, trigger the anchoring",
+            lect.txid(),
+            interval.as_secs()
+        );
+        self.anchored_since = Some((lect.id(), now));
+        self.schedule_anchoring_trigger();
+    }
+
+    // Alerts once for each lect whose funds cover less than `low_funds_anchors` transactions.
+    pub fn check_low_funds(&mut self, lect: &AnchoringTx, multisig: &MultisigAddress) {
+        let funds = lect.amount();
+        if !multisig.common.is_low_funds(funds) || self.low_funds_lect == Some(lect.id()) {
+            return;
+        }
+        self.low_funds_lect = Some(lect.id());
+        let anchors_left = funds.checked_div(multisig.common.fee).unwrap_or(0);
+        self.send_alert(AnchoringAlert::LowFunds {
+            address: multisig.addr.clone(),
+            funds,
+            anchors_left,
+        });
+    }
+
+    // Alerts once for each lect whose change is below the dust threshold, so it cannot
+    // fund the following anchoring transactions.
+    pub fn check_wallet_empty(&mut self, lect: &AnchoringTx, multisig: &MultisigAddress) {
+        if lect.amount() >= DUST_THRESHOLD || self.wallet_empty_lect == Some(lect.id()) {
+            return;
+        }
+        self.wallet_empty_lect = Some(lect.id());
+        warn!(
+            "Lect txid={} has the change={} below the dust threshold, final={}",
+            lect.txid(),
+            lect.amount(),
+            lect.is_final()
+        );
+        self.send_alert(AnchoringAlert::WalletEmpty {
+            address: multisig.addr.clone(),
+            lect: lect.id(),
+            is_final: lect.is_final(),
+        });
+    }
+
+    // The round is given up without the anchoring transaction, the `failed_rounds` alert
+    // is raised once the number of the consecutive abandoned rounds reaches its threshold.
+    pub fn abandon_round(&mut self) {
+        self.proposal_tx = None;
+        if self.metrics.round_started.take().is_none() {
+            return;
+        }
+        self.failed_rounds += 1;
+        if Some(self.failed_rounds) == self.node.alerts.failed_rounds {
+            let count = self.failed_rounds;
+            self.send_alert(AnchoringAlert::FailedRounds { count });
+        }
+    }
+
     // Create first anchoring tx proposal from funding tx in AnchoringNodeConfig
     pub fn try_create_anchoring_tx_chain(
         &mut self,
@@ -92,17 +225,23 @@ impl AnchoringHandler {
         if let Some(funding_tx) = self.avaliable_funding_tx(multisig)? {
             // Create anchoring proposal
             let height = multisig.common.latest_anchoring_height(context.height());
-            let hash = Schema::new(context.snapshot())
-                .block_hashes_by_height()
-                .get(height.0)
-                .unwrap();
+            let (hash, state_hash) = self.anchored_block_hashes(multisig.common, height, context);
+            let commitments = self.anchored_commitments(None, height, context);
 
             let out = funding_tx.find_out(&multisig.addr).unwrap();
+            let fee_rate = self.proposal_fee_rate(multisig, context)?;
+            let fee = proposal_fee(multisig, fee_rate, 1);
             let proposal = TransactionBuilder::with_prev_tx(&funding_tx, out)
-                .fee(multisig.common.fee)
+                .fee(fee)
                 .payload(height, hash)
+                .state_hash(state_hash)
+                .commitments(commitments)
+                .chain_id(multisig.common.chain_id)
                 .prev_tx_chain(prev_tx_chain)
                 .send_to(multisig.addr.clone())
+                .change_policy(multisig.common.surplus_policy())
+                .burn_dust(multisig.common.dust_policy == DustPolicy::Burn)
+                .replaceable(multisig.common.max_fee_bumps > 0)
                 .into_transaction()?;
 
             trace!(
@@ -119,6 +258,36 @@ impl AnchoringHandler {
         Ok(())
     }
 
+    // Returns the hash of the block at the given height and its state hash if the anchoring
+    // transactions commit to it.
+    pub fn anchored_block_hashes(
+        &self,
+        cfg: &AnchoringConfig,
+        height: Height,
+        context: &ServiceContext,
+    ) -> (Hash, Option<Hash>) {
+        let schema = Schema::new(context.snapshot());
+        let hash = schema.block_hashes_by_height().get(height.0).unwrap();
+        let state_hash = if cfg.anchor_state_hash {
+            schema.blocks().get(&hash).map(|block| *block.state_hash())
+        } else {
+            None
+        };
+        (hash, state_hash)
+    }
+
+    // Returns the root of the application commitments anchored by the transaction which
+    // spends the `prev_tx` of the anchoring chain and anchors the block at the given height.
+    pub fn anchored_commitments(
+        &self,
+        prev_tx: Option<&AnchoringTx>,
+        height: Height,
+        context: &ServiceContext,
+    ) -> Option<Hash> {
+        let schema = AnchoringSchema::new(context.snapshot());
+        commitments_root(&schema.anchored_commitments(prev_tx, height))
+    }
+
     pub fn create_proposal_tx(
         &mut self,
         lect: &AnchoringTx,
@@ -126,24 +295,47 @@ impl AnchoringHandler {
         height: Height,
         context: &ServiceContext,
     ) -> Result<(), ServiceError> {
-        let hash = Schema::new(context.snapshot())
-            .block_hashes_by_height()
-            .get(height.0)
-            .unwrap();
+        let (hash, state_hash) = self.anchored_block_hashes(multisig.common, height, context);
+        let commitments = self.anchored_commitments(Some(lect), height, context);
 
+        let funds = self.avaliable_funds(multisig, context)?;
+        let fee_rate = self.proposal_fee_rate(multisig, context)?;
+        let cpfp_fee = self.cpfp_fee(lect, multisig, fee_rate, context)?;
         let proposal = {
+            let cfg = multisig.common;
+            let required = |funds: usize| {
+                proposal_fee(multisig, fee_rate, funds + 1) + cpfp_fee + cfg.min_change
+            };
             let mut builder = TransactionBuilder::with_prev_tx(lect, 0)
-                .fee(multisig.common.fee)
                 .payload(height, hash)
-                .send_to(multisig.addr.clone());
-            if let Some(funds) = self.avaliable_funding_tx(multisig)? {
-                let out = funds.find_out(&multisig.addr).expect(
-                    "Funding tx has proper \
-                     multisig output",
+                .state_hash(state_hash)
+                .commitments(commitments)
+                .chain_id(cfg.chain_id)
+                .send_to(multisig.addr.clone())
+                .change_policy(cfg.surplus_policy())
+                .burn_dust(cfg.dust_policy == DustPolicy::Burn)
+                .replaceable(cfg.max_fee_bumps > 0)
+                .max_inputs(max_proposal_inputs(multisig))
+                .select_funds(cfg.coin_selection, &funds, &required);
+            if cfg.is_consolidation_height(height) {
+                let max_inputs = cfg.max_consolidation_inputs as usize;
+                builder = builder.consolidate_funds(&funds, max_inputs, &required);
+                trace!(
+                    "Consolidating proposal for height={}, inputs={}",
+                    height,
+                    builder.inputs_count()
                 );
-                builder = builder.add_funds(&funds, out);
             }
-            builder.into_transaction()?
+            if funds.len() + 1 > builder.inputs_count() {
+                trace!(
+                    "Proposal for height={} spends {} of {} funding transactions",
+                    height,
+                    builder.inputs_count() - 1,
+                    funds.len()
+                );
+            }
+            let fee = proposal_fee(multisig, fee_rate, builder.inputs_count()) + cpfp_fee;
+            builder.fee(cfg.bounded_fee(fee)).into_transaction()?
         };
 
         trace!(
@@ -156,15 +348,285 @@ impl AnchoringHandler {
         self.sign_proposal_tx(proposal, multisig, context)
     }
 
+    // Returns the fee which the proposal spending the stuck lect pays in addition to its own
+    // fee, so the lect and the proposal are mined together with the proposal fee rate.
+    // The lect is paid for only if it cannot be replaced, that is it does not signal
+    // replaceability or the fee bumps limit is reached. The decision is based only on
+    // the blockchain state, so all validators build the same proposal: the fee bumps are
+    // counted by the recorded replacements and the lect is considered stuck while it pays
+    // less than the fee rate recorded in the blockchain, since its confirmations are known
+    // only to the bitcoin backend of each node. Without the fee estimation nothing is paid.
+    fn cpfp_fee(
+        &self,
+        lect: &AnchoringTx,
+        multisig: &MultisigAddress,
+        fee_rate: Option<u64>,
+        context: &ServiceContext,
+    ) -> Result<u64, ServiceError> {
+        let cfg = multisig.common;
+        let fee_rate = match fee_rate {
+            Some(fee_rate) if cfg.cpfp => fee_rate,
+            _ => return Ok(0),
+        };
+        let schema = AnchoringSchema::new(context.snapshot());
+        if cfg.max_fee_bumps > 0 && schema.fee_bumps(lect) < cfg.max_fee_bumps {
+            return Ok(0);
+        }
+
+        let lect_fee = match schema.anchoring_tx_fee(lect) {
+            Some(lect_fee) => lect_fee,
+            None => {
+                warn!(
+                    "Unable to pay for the stuck lect with unknown inputs, txid={}",
+                    lect.txid()
+                );
+                return Ok(0);
+            }
+        };
+        let fee = (fee_rate * lect.vsize()).saturating_sub(lect_fee);
+        if fee > 0 {
+            info!(
+                "Paying fee={} for the stuck lect txid={}, lect_fee={}",
+                fee,
+                lect.txid(),
+                lect_fee
+            );
+        }
+        Ok(fee)
+    }
+
+    // Returns the fee rate of the anchoring proposal if the fee estimation is enabled.
+    // It is the median of the estimations recorded in the blockchain, so all validators
+    // build the same proposal regardless of their bitcoin backends.
+    pub fn proposal_fee_rate(
+        &self,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<Option<u64>, ServiceError> {
+        let cfg = multisig.common;
+        if cfg.fee_conf_target.is_none() {
+            return Ok(None);
+        }
+        let fee_rate = AnchoringSchema::new(context.snapshot()).consensus_fee_rate();
+        if fee_rate.is_none() {
+            warn!("Majority of the validators have not estimated the fee rate yet");
+        }
+        Ok(fee_rate.map(
+            |fee_rate| ::std::cmp::min(fee_rate, cfg.max_fee_rate),
+        ))
+    }
+
+    // Sends the fee rate estimated by the bitcoin backend of this node once per
+    // `check_lect_frequency` blocks if it differs from the recorded one. The estimation
+    // is rounded, so the slight changes of it do not produce new transactions.
+    pub fn send_fee_rate_vote(
+        &mut self,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let conf_target = match multisig.common.fee_conf_target {
+            Some(conf_target) => conf_target,
+            None => return Ok(()),
+        };
+        if context.validator_id().is_none() ||
+            context.height().0 % self.node.check_lect_frequency != 0
+        {
+            return Ok(());
+        }
+
+        let fee_rate = match self.client().estimate_fee_rate(conf_target)? {
+            Some(fee_rate) => round_fee_rate(fee_rate),
+            None => {
+                warn!(
+                    "Fee rate estimation is not available, conf_target={}",
+                    conf_target
+                );
+                return Ok(());
+            }
+        };
+        let recorded = AnchoringSchema::new(context.snapshot())
+            .fee_rate_votes()
+            .get(&hash(context.public_key().as_ref()))
+            .map(|vote| vote.fee_rate());
+        if recorded == Some(fee_rate) {
+            return Ok(());
+        }
+        info!("Send fee rate estimation, fee_rate={}", fee_rate);
+        let msg = MsgAnchoringFeeRate::new(
+            context.public_key(),
+            self.validator_id(context),
+            fee_rate,
+            context.height().0,
+            context.secret_key(),
+        );
+        context.transaction_sender().send(Box::new(msg))?;
+        Ok(())
+    }
+
+    // Checks that the lect is stuck and can be replaced by the transaction with a higher fee.
+    // As in the `cpfp_fee`, the decision is based only on the blockchain state, so all
+    // validators choose the same way: the lect is considered stuck while it pays less than
+    // the fee rate recorded in the blockchain and the fee bumps are counted by the recorded
+    // replacements. Without the fee estimation the lect is never replaced.
+    pub fn lect_needs_fee_bump(
+        &self,
+        lect: &AnchoringTx,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<bool, ServiceError> {
+        if multisig.common.max_fee_bumps == 0 {
+            return Ok(false);
+        }
+        let fee_rate = match self.proposal_fee_rate(multisig, context)? {
+            Some(fee_rate) => fee_rate,
+            None => return Ok(false),
+        };
+
+        let schema = AnchoringSchema::new(context.snapshot());
+        match schema.anchoring_tx_fee(lect) {
+            Some(lect_fee) if lect_fee < fee_rate * lect.vsize() => {}
+            _ => return Ok(false),
+        }
+        let fee_bumps = schema.fee_bumps(lect);
+        if fee_bumps >= multisig.common.max_fee_bumps {
+            warn!(
+                "Fee bumps limit is reached for the stuck lect, txid={}",
+                lect.txid()
+            );
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // Creates the transaction that spends the same inputs as the stuck lect,
+    // but pays a higher fee. If the fee cannot be bumped, the regular proposal
+    // spending the lect is created instead.
+    pub fn create_replacement_tx(
+        &mut self,
+        lect: &AnchoringTx,
+        multisig: &MultisigAddress,
+        height: Height,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        match self.build_replacement_tx(lect, multisig, height, context)? {
+            Some(proposal) => self.sign_proposal_tx(proposal, multisig, context),
+            None => self.create_proposal_tx(lect, multisig, height, context),
+        }
+    }
+
+    // Builds the transaction that spends the same inputs as the given one, but pays
+    // twice its fee limited by the `max_fee_rate`. The inputs are taken from the blockchain,
+    // so all validators build the same replacement. Returns `None` if some of the inputs
+    // are unknown or the fee is already at the limit.
+    pub fn build_replacement_tx(
+        &self,
+        lect: &AnchoringTx,
+        multisig: &MultisigAddress,
+        height: Height,
+        context: &ServiceContext,
+    ) -> Result<Option<AnchoringTx>, ServiceError> {
+        let schema = AnchoringSchema::new(context.snapshot());
+        let mut prev_txs = Vec::new();
+        for (input, txin) in lect.input.iter().enumerate() {
+            match schema.spent_tx(lect, input as u32) {
+                Some(tx) => prev_txs.push((tx, txin.prev_index)),
+                None => {
+                    warn!(
+                        "Unable to find the input of the stuck lect, txid={}",
+                        btc::TxId::from(txin.prev_hash).to_string()
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+        let lect_fee = match schema.anchoring_tx_fee(lect) {
+            Some(lect_fee) => lect_fee,
+            None => {
+                warn!("Unable to find the spent outputs of the stuck lect, txid={}", lect.txid());
+                return Ok(None);
+            }
+        };
+        let max_fee = multisig.common.max_fee_rate * lect.vsize();
+        let max_fee = match multisig.common.max_fee {
+            Some(cfg_max_fee) => ::std::cmp::min(max_fee, cfg_max_fee),
+            None => max_fee,
+        };
+        let fee = ::std::cmp::min(lect_fee * 2, max_fee);
+        if fee <= lect_fee {
+            warn!(
+                "Unable to bump the fee of the stuck lect, txid={}, fee={}, max_fee={}",
+                lect.txid(),
+                lect_fee,
+                max_fee
+            );
+            return Ok(None);
+        }
+
+        let hash = Schema::new(context.snapshot())
+            .block_hashes_by_height()
+            .get(height.0)
+            .unwrap();
+        let proposal = {
+            let mut builder = TransactionBuilder::with_prev_tx(&prev_txs[0].0, prev_txs[0].1)
+                .fee(fee)
+                .payload(height, hash)
+                .prev_tx_chain(lect.payload().prev_tx_chain)
+                .state_hash(lect.payload().state_hash)
+                .commitments(lect.payload().commitments)
+                .chain_id(lect.payload().chain_id)
+                .send_to(multisig.addr.clone())
+                .change_policy(multisig.common.surplus_policy())
+                .burn_dust(multisig.common.dust_policy == DustPolicy::Burn)
+                .replaceable(true);
+            for &(ref tx, out) in &prev_txs[1..] {
+                builder = builder.add_funds(tx, out);
+            }
+            builder.into_transaction()?
+        };
+
+        trace!(
+            "replacement_proposal={:#?}, replaced_txid={}, fee={}",
+            proposal,
+            lect.txid(),
+            fee
+        );
+        Ok(Some(proposal))
+    }
+
     pub fn sign_proposal_tx(
         &mut self,
         proposal: AnchoringTx,
         multisig: &MultisigAddress,
         context: &ServiceContext,
     ) -> Result<(), ServiceError> {
-        for input in proposal.inputs() {
-            let signature = proposal.sign_input(&multisig.redeem_script, input, &multisig.priv_key);
+        self.check_proposal_tx(&proposal, multisig, context)?;
+        self.check_signing_policies(&proposal, context)?;
+        let psbt = {
+            let anchoring_schema = AnchoringSchema::new(context.snapshot());
+            collect_partial_signatures(&proposal, multisig, &anchoring_schema, Vec::new())
+        };
+        let signatures = multisig.signer.sign(&psbt)?;
+        self.proposal_psbt = Some(psbt.clone());
+        // The round is persisted before the signatures are sent, so the node resumes it
+        // after the crash instead of signing the proposal again.
+        self.state.round = Some(RoundState {
+            proposal: proposal.clone(),
+            sighashes: psbt_sighashes(&psbt),
+            signatures: signatures
+                .iter()
+                .map(|(input, signature)| (*input, encode_hex(signature)))
+                .collect(),
+            height: context.height().0,
+        });
+        self.resumed_round = false;
+        self.persist_state();
+        self.enter_round(&proposal);
+        self.emit_event(AnchoringEvent::ProposalCreated {
+            txid: proposal.id(),
+            height: proposal.payload().block_height.0,
+        });
 
+        for (input, signature) in signatures {
             let sign_msg = MsgAnchoringSignature::new(
                 context.public_key(),
                 self.validator_id(context),
@@ -179,9 +641,384 @@ impl AnchoringHandler {
                 sign_msg,
                 encode_hex(signature)
             );
-            context.transaction_sender().send(Box::new(sign_msg))?;
+            self.pending_signatures.push(sign_msg);
         }
         self.proposal_tx = Some(proposal);
+        self.state.broadcasted = None;
+        self.metrics.start_signature_round();
+        self.send_signatures(context)
+    }
+
+    // Checks the proposal by the validator-local signing policies. The proposal which
+    // requires the approval is kept in the `pending_approvals` until the node operator
+    // approves its anchored height. The approval covers the proposals rebuilt for the same
+    // height and is dropped once the following heights are anchored.
+    pub fn check_signing_policies(
+        &mut self,
+        proposal: &AnchoringTx,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let fee = AnchoringSchema::new(context.snapshot()).anchoring_tx_fee(proposal);
+        let summary = ProposalSummary::new(proposal, fee);
+        let decision = {
+            let config_policies = self.node.signing_policy.policies();
+            let policies = config_policies.iter().chain(&self.signing_policies);
+            check_policies(policies.map(|policy| policy.as_ref()), &summary)
+        };
+        // The approvals of the outdated rounds are not needed anymore.
+        let height = summary.height;
+        self.pending_approvals.retain(
+            |pending| pending.proposal.height >= height,
+        );
+        self.approved_proposals.retain(|&approved| approved >= height);
+        match decision {
+            PolicyDecision::Sign => Ok(()),
+            PolicyDecision::RequireApproval(reason) => {
+                if self.approved_proposals.contains(&height) {
+                    info!(
+                        "Sign the approved proposal txid={} for height={}",
+                        summary.txid.to_string(),
+                        height
+                    );
+                    return Ok(());
+                }
+                let txid = summary.txid;
+                if !self.pending_approvals.iter().any(
+                    |pending| pending.proposal.txid == txid,
+                )
+                {
+                    info!(
+                        "Proposal txid={} for height={} waits for the approval: {}",
+                        txid.to_string(),
+                        height,
+                        reason
+                    );
+                    // The rebuilt proposal replaces the previous one of the same height.
+                    self.pending_approvals.retain(
+                        |pending| pending.proposal.height != height,
+                    );
+                    self.pending_approvals.push(PendingApproval {
+                        proposal: summary,
+                        reason: reason.clone(),
+                    });
+                }
+                Err(refused_proposal(proposal, format!("Waiting for the approval: {}", reason)))
+            }
+            PolicyDecision::Refuse(reason) => Err(refused_proposal(proposal, reason)),
+        }
+    }
+
+    // Validates the proposal independently of the code that has built it, so the node
+    // does not sign the transaction which commits to a block unknown to this node, spends
+    // unknown transactions or outputs of a foreign address or pays a fee beyond the
+    // configured bounds. With the `verify_proposal_inputs` option the spent transactions
+    // may also be requested from the bitcoin backend and the spent outputs are checked
+    // to be unspent according to it.
+    pub fn check_proposal_tx(
+        &self,
+        proposal: &AnchoringTx,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let cfg = multisig.common;
+        let payload = proposal.payload();
+        let (hash, state_hash) = {
+            let schema = Schema::new(context.snapshot());
+            let hash = schema.block_hashes_by_height().get(payload.block_height.0);
+            let state_hash = hash.and_then(|hash| schema.blocks().get(&hash)).map(
+                |block| *block.state_hash(),
+            );
+            (hash, state_hash)
+        };
+        if hash != Some(payload.block_hash) {
+            let reason = format!(
+                "Payload block hash does not match the block at height={}",
+                payload.block_height
+            );
+            return Err(incorrect_proposal(proposal, reason));
+        }
+        if payload.state_hash.is_some() && payload.state_hash != state_hash {
+            let reason = format!(
+                "Payload state hash does not match the block at height={}",
+                payload.block_height
+            );
+            return Err(incorrect_proposal(proposal, reason));
+        }
+        if payload.commitments.is_some() {
+            let prev_tx = AnchoringSchema::new(context.snapshot()).prev_anchoring_tx(proposal);
+            let commitments =
+                self.anchored_commitments(prev_tx.as_ref(), payload.block_height, context);
+            if payload.commitments != commitments {
+                let reason = format!(
+                    "Payload commitments do not match the commitments registered up to height={}",
+                    payload.block_height
+                );
+                return Err(incorrect_proposal(proposal, reason));
+            }
+        }
+        if proposal.is_final() && cfg.dust_policy != DustPolicy::Burn {
+            let reason = "Proposal burns the anchoring funds, but the dust burning is disabled";
+            return Err(incorrect_proposal(proposal, reason.to_owned()));
+        }
+        if let Some(surplus) = proposal.surplus_output() {
+            let policy = match cfg.change_policy {
+                Some(ref policy) => policy,
+                None => {
+                    let reason = "Proposal has the surplus output without the change policy";
+                    return Err(incorrect_proposal(proposal, reason.to_owned()));
+                }
+            };
+            if surplus.script_pubkey != policy.address.script_pubkey() ||
+                proposal.amount() != policy.retain
+            {
+                let reason = format!(
+                    "Surplus output does not match the change policy, address={}, retain={}",
+                    policy.address.to_string(),
+                    policy.retain
+                );
+                return Err(incorrect_proposal(proposal, reason));
+            }
+            let allowed = &self.node.allowed_change_addresses;
+            if !allowed.is_empty() && !allowed.contains(&policy.address) {
+                let reason = format!(
+                    "Change address {} is not allowed by the node",
+                    policy.address.to_string()
+                );
+                return Err(incorrect_proposal(proposal, reason));
+            }
+        }
+
+        let max_inputs = max_proposal_inputs(multisig);
+        if proposal.input.len() > max_inputs {
+            let reason = format!(
+                "Proposal has {} inputs, but the limit is {}",
+                proposal.input.len(),
+                max_inputs
+            );
+            return Err(incorrect_proposal(proposal, reason));
+        }
+
+        let mut prev_txs = {
+            let anchoring_schema = AnchoringSchema::new(context.snapshot());
+            proposal
+                .inputs()
+                .map(|input| anchoring_schema.spent_tx(proposal, input))
+                .collect::<Vec<_>>()
+        };
+        let verify_inputs = self.node.verify_proposal_inputs;
+        if verify_inputs {
+            let unknown_txids = proposal
+                .input
+                .iter()
+                .zip(&prev_txs)
+                .filter(|&(_, tx)| tx.is_none())
+                .map(|(input, _)| btc::TxId::from(input.prev_hash))
+                .collect::<Vec<_>>();
+            if !unknown_txids.is_empty() {
+                let mut requested_txs = self.client().get_transactions(&unknown_txids)?.into_iter();
+                for tx in prev_txs.iter_mut().filter(|tx| tx.is_none()) {
+                    *tx = requested_txs.next().and_then(|tx| tx).map(|tx| tx.0);
+                }
+            }
+        }
+
+        // Inputs of the proposals always spend the address of the actual configuration,
+        // its funds are transferred to the following address during the transition.
+        let input_addr = cfg.redeem_script().1;
+        let script_pubkey = input_addr.script_pubkey();
+        let mut spent_value = 0;
+        for (index, (input, prev_tx)) in proposal.input.iter().zip(&prev_txs).enumerate() {
+            // The fee of the proposal cannot be checked without the spent transactions.
+            let prev_tx = match *prev_tx {
+                Some(ref prev_tx) => prev_tx,
+                None => {
+                    let reason = format!(
+                        "Input {} spends unknown transaction txid={}",
+                        index,
+                        btc::TxId::from(input.prev_hash).to_string()
+                    );
+                    return Err(incorrect_proposal(proposal, reason));
+                }
+            };
+            match prev_tx.output.get(input.prev_index as usize) {
+                Some(output) if output.script_pubkey == script_pubkey => {
+                    spent_value += output.value;
+                }
+                _ => {
+                    let reason = format!("Input {} does not spend the anchoring address", index);
+                    return Err(incorrect_proposal(proposal, reason));
+                }
+            }
+        }
+
+        let size = estimate_anchoring_tx_size(
+            proposal.input.len(),
+            &multisig.redeem_script,
+            cfg.script_type,
+            cfg.signature_threshold(),
+        );
+        let mut max_fee = ::std::cmp::max(cfg.fee, cfg.max_fee_rate * size);
+        // The proposal may also pay for the stuck lect spent by its first input.
+        if cfg.cpfp {
+            if let Some(ref prev_tx) = prev_txs[0] {
+                max_fee += cfg.max_fee_rate * tx_vsize(prev_tx);
+            }
+        }
+        if spent_value < proposal.total_amount() {
+            let reason = format!(
+                "Proposal spends {} satoshis, but its inputs have only {}",
+                proposal.total_amount(),
+                spent_value
+            );
+            return Err(incorrect_proposal(proposal, reason));
+        }
+        // The final proposal also burns the change below the dust threshold.
+        if proposal.is_final() {
+            max_fee += DUST_THRESHOLD - 1;
+        }
+        let fee = spent_value - proposal.total_amount();
+        if fee > max_fee {
+            let reason = format!("Proposal fee={} exceeds max_fee={}", fee, max_fee);
+            return Err(incorrect_proposal(proposal, reason));
+        }
+        let in_bounds = if proposal.is_final() {
+            cfg.is_final_fee_in_bounds(fee)
+        } else {
+            cfg.is_fee_in_bounds(fee)
+        };
+        if !in_bounds {
+            let reason = format!(
+                "Proposal fee={} is beyond bounds min_fee={}, max_fee={:?}",
+                fee,
+                cfg.min_fee,
+                cfg.max_fee
+            );
+            return Err(incorrect_proposal(proposal, reason));
+        }
+
+        if verify_inputs {
+            let unspent = self.client()
+                .unspent_outputs(&input_addr)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            // The replacement of the stuck lect spends the same outputs as the lect itself.
+            let lect_outpoints = AnchoringSchema::new(context.snapshot())
+                .collect_lects(cfg)
+                .map(|lect| {
+                    lect.input
+                        .iter()
+                        .map(|input| (input.prev_hash, input.prev_index))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            for (index, input) in proposal.input.iter().enumerate() {
+                let txid = btc::TxId::from(input.prev_hash);
+                let outpoint = (input.prev_hash, input.prev_index);
+                if !unspent.contains(&(txid, input.prev_index)) &&
+                    !lect_outpoints.contains(&outpoint)
+                {
+                    let reason = format!(
+                        "Input {} spends the output {}:{} which is not unspent",
+                        index,
+                        txid.to_string(),
+                        input.prev_index
+                    );
+                    return Err(incorrect_proposal(proposal, reason));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Resends the signatures of the round restored after the node restart which have not
+    // reached the blockchain. Returns `false` if the restored proposal no longer matches
+    // the persisted sighashes, in which case the round is dropped.
+    pub fn resume_round(
+        &mut self,
+        proposal: &AnchoringTx,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<bool, ServiceError> {
+        self.resumed_round = false;
+        let round = match self.state.round {
+            Some(ref round) if round.proposal == *proposal => round.clone(),
+            _ => return Ok(true),
+        };
+
+        let validator = self.validator_id(context);
+        let (psbt, sent_inputs) = {
+            let anchoring_schema = AnchoringSchema::new(context.snapshot());
+            let signatures = anchoring_schema.signatures(&proposal.id());
+            let sent_inputs = signatures
+                .iter()
+                .filter(|msg| msg.validator() == validator)
+                .map(|msg| msg.input())
+                .collect::<HashSet<_>>();
+            let psbt =
+                collect_partial_signatures(proposal, multisig, &anchoring_schema, &signatures);
+            (psbt, sent_inputs)
+        };
+        if psbt_sighashes(&psbt) != round.sighashes {
+            warn!(
+                "Restored proposal txid={} does not match its sighashes, dropping the round",
+                proposal.txid()
+            );
+            self.proposal_tx = None;
+            self.state.round = None;
+            self.persist_state();
+            return Ok(false);
+        }
+
+        for (input, signature) in &round.signatures {
+            if sent_inputs.contains(input) {
+                continue;
+            }
+            let signature = match Vec::<u8>::from_hex(signature) {
+                Ok(signature) => signature,
+                Err(e) => {
+                    error!("Unable to decode the restored signature: {}", e);
+                    continue;
+                }
+            };
+            let sign_msg = MsgAnchoringSignature::new(
+                context.public_key(),
+                validator,
+                proposal.clone(),
+                *input,
+                &signature,
+                context.secret_key(),
+            );
+            trace!("Resend restored input signature msg={:#?}", sign_msg);
+            self.pending_signatures.push(sign_msg);
+        }
+        self.metrics.start_signature_round();
+        self.send_signatures(context)?;
+        Ok(true)
+    }
+
+    // Sends the queued input signatures of the actual proposal, at most
+    // `max_signatures_per_commit` of them, so a proposal with many inputs does not flood
+    // the consensus with the signature messages. The signatures of the replaced proposals
+    // are dropped.
+    pub fn send_signatures(&mut self, context: &ServiceContext) -> Result<(), ServiceError> {
+        if self.pending_signatures.is_empty() {
+            return Ok(());
+        }
+        let proposal = self.proposal_tx.clone();
+        self.pending_signatures.retain(|msg| Some(msg.tx()) == proposal);
+        let count = match self.node.max_signatures_per_commit {
+            Some(limit) => ::std::cmp::min(limit as usize, self.pending_signatures.len()),
+            None => self.pending_signatures.len(),
+        };
+        for sign_msg in self.pending_signatures.drain(..count) {
+            context.transaction_sender().send(Box::new(sign_msg))?;
+        }
+        if !self.pending_signatures.is_empty() {
+            trace!(
+                "Postpone {} input signatures till the following commits",
+                self.pending_signatures.len()
+            );
+        }
         Ok(())
     }
 
@@ -194,36 +1031,78 @@ impl AnchoringHandler {
         trace!("Try finalize proposal tx");
         let txid = proposal.id();
 
+        // The catching up proposals anchor the heights below the latest one, so they are
+        // outdated once the interval in which their round has been started is over.
         let proposal_height = proposal.payload().block_height;
+        let round_height = match (multisig.common.catch_up, self.state.round.as_ref()) {
+            (CatchUpPolicy::AllMissed, Some(round)) if round.proposal == proposal => {
+                Height(round.height)
+            }
+            _ => proposal_height,
+        };
         if multisig.common.latest_anchoring_height(context.height()) !=
-            multisig.common.latest_anchoring_height(proposal_height)
+            multisig.common.latest_anchoring_height(round_height)
         {
             warn!(
                 "Unable to finalize anchoring tx for height={}",
                 proposal_height
             );
-            self.proposal_tx = None;
+            self.abandon_round();
+            return Ok(());
+        }
+        if self.resumed_round && !self.resume_round(&proposal, multisig, context)? {
             return Ok(());
         }
 
-        let collected_signatures = {
+        let (psbt, signatures_count) = {
             let anchoring_schema = AnchoringSchema::new(context.snapshot());
             let signatures = anchoring_schema.signatures(&txid);
-            collect_signatures(&proposal, multisig.common, &signatures)
+            // The spent outputs of the proposal are read from the storage only once,
+            // the following commits only add the new signatures to the cached `Psbt`.
+            if self.proposal_psbt.as_ref().map(|psbt| &psbt.unsigned_tx) != Some(&proposal.0) {
+                let psbt =
+                    collect_partial_signatures(&proposal, multisig, &anchoring_schema, Vec::new());
+                self.proposal_psbt = Some(psbt);
+            }
+            let mut psbt = self.proposal_psbt.clone().unwrap();
+            add_partial_signatures(&mut psbt, multisig, &signatures);
+            (psbt, signatures.len())
         };
-        if let Some(signatures) = collected_signatures {
-            let new_lect = proposal.finalize(&multisig.redeem_script, signatures);
+        trace!("Proposal psbt={}", psbt.to_base64());
+        if let Some(new_lect) = psbt.finalize(multisig.common.signature_threshold()) {
+            self.emit_event(AnchoringEvent::SignaturesCollected {
+                txid: proposal.id(),
+                signatures: signatures_count,
+            });
             // Send transaction if it needs
             if self.client().get_transaction(new_lect.id())?.is_none() {
-                self.client().send_transaction(new_lect.clone().into())?;
-                trace!(
-                    "Sended signed_tx={:#?}, to={}",
-                    new_lect,
-                    new_lect
-                        .output_address(multisig.common.network)
-                        .to_base58check()
-                );
+                let result = self.client().send_transaction(new_lect.clone().into());
+                if let Err(e) = result {
+                    match broadcast_rejection(&e) {
+                        Some(BroadcastRejection::AlreadyInChain) => {
+                            info!("Anchoring tx txid={} is already mined", new_lect.txid());
+                        }
+                        Some(BroadcastRejection::MempoolConflict) => {
+                            return self.handle_mempool_conflict(&new_lect, multisig, context);
+                        }
+                        Some(BroadcastRejection::InsufficientFee) => {
+                            let e = ServiceError::from(e).with_txid(new_lect.id());
+                            return self.handle_insufficient_fee(&new_lect, e, multisig, context);
+                        }
+                        None => return Err(ServiceError::from(e).with_txid(new_lect.id())),
+                    }
+                }
+                if !new_lect.is_final() {
+                    trace!(
+                        "Sended signed_tx={:#?}, to={}",
+                        new_lect,
+                        new_lect
+                            .output_address(multisig.common.network)
+                            .to_string()
+                    );
+                }
             }
+            self.emit_event(AnchoringEvent::AnchorBroadcast { txid: new_lect.id() });
 
             info!(
                 "ANCHORING ====== anchored_height={}, txid={}, remaining_funds={}",
@@ -241,6 +1120,12 @@ impl AnchoringHandler {
             );
 
             self.proposal_tx = None;
+            self.state.broadcasted = Some(new_lect.id());
+            let fee = AnchoringSchema::new(context.snapshot()).anchoring_tx_fee(&new_lect);
+            self.metrics.finish_signature_round(fee);
+            self.failed_rounds = 0;
+            self.metrics.anchored_height = Some(new_lect.payload().block_height.0);
+            self.metrics.balance = Some(new_lect.amount());
 
             let lects_count = AnchoringSchema::new(context.snapshot())
                 .lects(self.anchoring_key(multisig.common, context))
@@ -258,4 +1143,115 @@ impl AnchoringHandler {
         }
         Ok(())
     }
+
+    // The finalized proposal conflicts with another transaction in the mempool, for example,
+    // with the same proposal finalized by another set of signatures. The conflicting
+    // transaction is adopted as our lect if it continues the anchoring chain.
+    fn handle_mempool_conflict(
+        &mut self,
+        tx: &AnchoringTx,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        warn!(
+            "Anchoring tx txid={} conflicts with a mempool transaction",
+            tx.txid()
+        );
+        self.abandon_round();
+        if let Some(lect) = self.update_our_lect(multisig, context)? {
+            if lect.id() != tx.id() {
+                info!("Adopted conflicting tx txid={} as lect", lect.txid());
+            }
+        }
+        Ok(())
+    }
+
+    // The finalized proposal pays less than the minimal relay fee or than the conflicting
+    // mempool transaction, so it is replaced by the proposal with a higher fee if
+    // the fee bumps are allowed.
+    fn handle_insufficient_fee(
+        &mut self,
+        tx: &AnchoringTx,
+        e: ServiceError,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        self.abandon_round();
+        let fee_bumps = AnchoringSchema::new(context.snapshot()).fee_bumps(tx);
+        if fee_bumps >= multisig.common.max_fee_bumps {
+            return Err(e);
+        }
+        let height = tx.payload().block_height;
+        match self.build_replacement_tx(tx, multisig, height, context)? {
+            Some(proposal) => {
+                warn!(
+                    "Anchoring tx txid={} has insufficient fee, replacing it by txid={}",
+                    tx.txid(),
+                    proposal.txid()
+                );
+                self.sign_proposal_tx(proposal, multisig, context)
+            }
+            None => Err(e),
+        }
+    }
+}
+
+fn incorrect_proposal(proposal: &AnchoringTx, reason: String) -> ServiceError {
+    HandlerError::IncorrectProposal {
+        reason,
+        tx: proposal.clone().into(),
+    }.into()
+}
+
+fn refused_proposal(proposal: &AnchoringTx, reason: String) -> ServiceError {
+    HandlerError::ProposalRefused {
+        reason,
+        tx: proposal.clone().into(),
+    }.into()
+}
+
+// Returns the maximum number of the anchoring proposal inputs limited by
+// the `max_proposal_inputs` and by the size of the standard transaction.
+pub fn max_proposal_inputs(multisig: &MultisigAddress) -> usize {
+    let cfg = multisig.common;
+    let max_standard_inputs = max_anchoring_tx_inputs(
+        &multisig.redeem_script,
+        cfg.script_type,
+        cfg.signature_threshold(),
+    );
+    ::std::cmp::min(cfg.max_proposal_inputs as usize, max_standard_inputs)
+}
+
+// Returns the fee of the anchoring proposal with the given number of inputs.
+pub fn proposal_fee(multisig: &MultisigAddress, fee_rate: Option<u64>, inputs: usize) -> u64 {
+    let cfg = multisig.common;
+    let fee_rate = match fee_rate {
+        Some(fee_rate) => fee_rate,
+        None => return cfg.bounded_fee(cfg.fee),
+    };
+    let size = estimate_anchoring_tx_size(
+        inputs,
+        &multisig.redeem_script,
+        cfg.script_type,
+        cfg.signature_threshold(),
+    );
+    let fee = cfg.bounded_fee(::std::cmp::max(cfg.fee, fee_rate * size));
+    trace!(
+        "Estimated proposal fee={}, fee_rate={}, size={}",
+        fee,
+        fee_rate,
+        size
+    );
+    fee
+}
+
+// Returns the hex encoded sighashes of the `psbt` inputs by their indexes.
+fn psbt_sighashes(psbt: &Psbt) -> BTreeMap<u32, String> {
+    (0..psbt.inputs.len() as u32)
+        .filter_map(|input| {
+            psbt.signature_hash(input).map(
+                |sighash| (input, encode_hex(&sighash[..])),
+            )
+        })
+        .collect()
 }
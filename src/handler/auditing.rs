@@ -12,8 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bitcoin::util::base58::ToBase58;
-
 use exonum::blockchain::ServiceContext;
 
 use error::Error as ServiceError;
@@ -32,7 +30,7 @@ impl AnchoringHandler {
         state: &ServiceContext,
     ) -> Result<(), ServiceError> {
         trace!("Auditing state");
-        if state.height().0 % self.node.check_lect_frequency == 0 {
+        if self.retry.lect_update {
             let r = match self.collect_lects(state)? {
                 LectKind::Funding(tx) => self.check_funding_lect(tx, state),
                 LectKind::Anchoring(tx) => self.check_anchoring_lect(&tx),
@@ -66,7 +64,7 @@ impl AnchoringHandler {
             let e = HandlerError::IncorrectLect {
                 reason: format!(
                     "Initial funding_tx has no outputs with address={}",
-                    addr.to_base58check()
+                    addr.to_string()
                 ),
                 tx: tx.into(),
             };
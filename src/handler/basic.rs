@@ -12,36 +12,200 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashSet;
-use std::sync::mpsc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
 
-use bitcoin::util::base58::ToBase58;
+use log::LogLevel;
+use serde_json;
 
-use exonum::blockchain::ServiceContext;
+use exonum::blockchain::{Schema, ServiceContext};
 use exonum::storage::Snapshot;
 use exonum::helpers::{Height, ValidatorId};
+use exonum::encoding::serialize::ToHex;
 
 use error::Error as ServiceError;
 use handler::error::Error as HandlerError;
-use details::rpc::BitcoinRelay;
+use details::rpc::{is_pending_error, is_transient_error, BitcoinRelay, TxInfo};
+use details::signer::{ExternalSigner, KeySigner, Signer};
+use details::webhook::Webhook;
 use details::btc;
+use details::btc::selection::FundingCandidate;
 use details::btc::transactions::{AnchoringTx, BitcoinTx, FundingTx, TxKind};
 use local_storage::AnchoringNodeConfig;
 use blockchain::consensus_storage::AnchoringConfig;
 use blockchain::schema::AnchoringSchema;
-use blockchain::dto::MsgAnchoringUpdateLatest;
+use blockchain::dto::{MsgAnchoringFunding, MsgAnchoringKeyRotation, MsgAnchoringPause,
+                      MsgAnchoringSweep, MsgAnchoringTrigger, MsgAnchoringUpdateLatest};
 
-use super::{AnchoringHandler, AnchoringState, LectKind, MultisigAddress};
+use super::{AnchoringHandler, AnchoringState, CachedAddress, LectKind, MultisigAddress};
+use super::alert::AnchoringAlert;
+use super::event::{AnchoringEvent, AnchoringEventEntry};
+use super::metrics::AnchoringMetrics;
+use super::policy::SigningPolicy;
+use super::retry::RetryState;
+use super::shared::{HandlerRequest, HandlerSnapshot, SharedHandler};
+use super::span::RoundSpan;
+use super::state::{AnchoringPhase, HandlerState};
+
+// The maximum number of the errors kept for the status endpoint.
+const MAX_PENDING_ERRORS: usize = 16;
+// The maximum number of the events kept in the events log.
+const MAX_EVENTS: usize = 1024;
+// The maximum number of the anchoring configurations with the cached addresses,
+// the transition of the anchoring address uses two of them.
+const MAX_CACHED_ADDRESSES: usize = 4;
 
 impl AnchoringHandler {
     #[doc(hidden)]
     pub fn new(client: Option<Box<BitcoinRelay>>, node: AnchoringNodeConfig) -> AnchoringHandler {
-        AnchoringHandler {
+        let state = node.state_path
+            .as_ref()
+            .and_then(|path| match HandlerState::load(path) {
+                Ok(state) => state,
+                Err(e) => {
+                    error!("Unable to load the handler state from {}: {}", path, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let proposal_tx = state.round.as_ref().map(|round| round.proposal.clone());
+        if let Some(ref proposal) = proposal_tx {
+            info!("Resuming the anchoring round, proposal txid={}", proposal.txid());
+        }
+        let client: Option<Arc<BitcoinRelay>> = client.map(Arc::from);
+        let shared = Arc::new(SharedHandler::default());
+        shared.set_client(client.clone());
+        let webhook = node.alerts.webhook.as_ref().map(|url| Webhook::new(url.as_str()));
+        let mut handler = AnchoringHandler {
             client,
             node,
-            proposal_tx: None,
+            resumed_round: proposal_tx.is_some(),
+            proposal_tx,
             known_addresses: HashSet::new(),
+            pending_addresses: Vec::new(),
+            address_descriptors: HashMap::new(),
+            pending_funding_notices: Vec::new(),
+            pending_sweep_vote: None,
+            pending_pause_vote: None,
+            pending_anchoring_trigger: false,
+            anchored_since: None,
+            pending_key_rotation_vote: None,
+            pending_signatures: Vec::new(),
             errors_sink: None,
+            alerts_sink: None,
+            webhook,
+            events_subscribers: Vec::new(),
+            events: VecDeque::new(),
+            next_event_id: 0,
+            low_funds_lect: None,
+            wallet_empty_lect: None,
+            failed_rounds: 0,
+            unconfirmed_lect: None,
+            broken_lect: None,
+            lect_confirmations: None,
+            metrics: AnchoringMetrics::default(),
+            retry: RetryState::default(),
+            state,
+            pending_errors: Vec::new(),
+            address_cache: VecDeque::new(),
+            proposal_psbt: None,
+            signing_policies: Vec::new(),
+            pending_approvals: Vec::new(),
+            approved_proposals: HashSet::new(),
+            shared,
+            span: RoundSpan::default(),
+        };
+        handler.publish_snapshot();
+        handler
+    }
+
+    /// Returns the phase of the anchoring process on this node.
+    pub fn phase(&self) -> AnchoringPhase {
+        self.state.phase
+    }
+
+    #[doc(hidden)]
+    /// Keeps the error for the status endpoint until the following successful commit.
+    pub fn push_pending_error(&mut self, error: String) {
+        if self.pending_errors.len() >= MAX_PENDING_ERRORS {
+            self.pending_errors.remove(0);
+        }
+        self.pending_errors.push(error);
+    }
+
+    /// Replaces the bitcoin backend `client` and the node configuration without the node
+    /// restart, for example, to rotate the `bitcoind` credentials or the anchoring keys.
+    ///
+    /// The `observer` and `state_path` settings are kept, since they are used only on
+    /// the node start. The addresses watched by the previous backend are imported
+    /// to the new one by the following commit.
+    pub fn set_node_config(
+        &mut self,
+        node: AnchoringNodeConfig,
+        client: Option<Box<BitcoinRelay>>,
+    ) {
+        info!(
+            "Reloading the node configuration, bitcoin backend={:?}",
+            client.as_ref().map(|client| client.config().host)
+        );
+        let prev_node = mem::replace(&mut self.node, node);
+        self.node.observer = prev_node.observer;
+        self.node.state_path = prev_node.state_path;
+        self.webhook = self.node.alerts.webhook.as_ref().map(|url| Webhook::new(url.as_str()));
+        self.client = client.map(Arc::from);
+        self.shared.set_client(self.client.clone());
+        // The private keys may be changed.
+        self.address_cache.clear();
+
+        let known_addresses = mem::replace(&mut self.known_addresses, HashSet::new());
+        for addr_str in known_addresses {
+            match addr_str.parse::<btc::Address>() {
+                Ok(addr) => self.schedule_address_import(addr),
+                Err(e) => error!("Unable to parse the known address={}: {}", addr_str, e),
+            }
+        }
+        self.retry = RetryState::default();
+        self.publish_snapshot();
+    }
+
+    /// Returns the part of the handler which is available to the api without locking
+    /// the handler.
+    pub fn shared(&self) -> Arc<SharedHandler> {
+        Arc::clone(&self.shared)
+    }
+
+    #[doc(hidden)]
+    /// Publishes the copy of the handler state for the api.
+    pub fn publish_snapshot(&self) {
+        self.shared.publish(HandlerSnapshot {
+            metrics: self.metrics.clone(),
+            state: self.state.clone(),
+            lect_confirmations: self.lect_confirmations,
+            pending_errors: self.pending_errors.clone(),
+            events: self.events.clone(),
+            hd_key: self.node.hd_key.clone(),
+            watched_addresses: self.known_addresses.iter().cloned().collect(),
+            pending_approvals: self.pending_approvals.clone(),
+        });
+    }
+
+    #[doc(hidden)]
+    /// Applies the requests queued by the api since the previous commit.
+    pub fn apply_requests(&mut self) {
+        for request in self.shared.take_requests() {
+            match request {
+                HandlerRequest::FundingNotice(txid) => self.schedule_funding_notice(txid),
+                HandlerRequest::SweepVote(address) => self.schedule_sweep_vote(address),
+                HandlerRequest::PauseVote(reason) => self.schedule_pause_vote(reason),
+                HandlerRequest::AnchoringTrigger => self.schedule_anchoring_trigger(),
+                HandlerRequest::KeyRotationVote(target, key) => {
+                    self.schedule_key_rotation_vote(target, key)
+                }
+                HandlerRequest::NodeConfig(node, client) => self.set_node_config(node, client),
+                HandlerRequest::ApproveProposal(height) => self.approve_proposal(height),
+            }
         }
     }
 
@@ -74,27 +238,78 @@ impl AnchoringHandler {
     }
 
     #[doc(hidden)]
-    pub fn multisig_address<'a>(&self, common: &'a AnchoringConfig) -> MultisigAddress<'a> {
+    pub fn multisig_address<'a>(
+        &mut self,
+        common: &'a AnchoringConfig,
+        state: &ServiceContext,
+    ) -> MultisigAddress<'a> {
+        let cached = self.cached_address(common);
+        let signer: Box<Signer> = match cached.priv_key {
+            Some(priv_key) => Box::new(KeySigner::new(priv_key)),
+            None => {
+                let config = self.node.external_signer.clone().expect(&format!(
+                    "Expected private key or external signer for address={}",
+                    cached.addr.to_string()
+                ));
+                let pub_key = *self.anchoring_key(common, state);
+                Box::new(ExternalSigner::new(config, pub_key))
+            }
+        };
+        MultisigAddress {
+            common: common,
+            signer: signer,
+            redeem_script: cached.redeem_script,
+            addr: cached.addr,
+        }
+    }
+
+    // The redeem script and the private key are computed only once for the recently used
+    // configurations, the derivation of the private key from the extended key is especially
+    // expensive.
+    #[doc(hidden)]
+    pub fn cached_address(&mut self, common: &AnchoringConfig) -> CachedAddress {
+        if let Some(cached) = self.address_cache.iter().find(|cached| cached.common == *common) {
+            return cached.clone();
+        }
+
         let (redeem_script, addr) = common.redeem_script();
-        let addr_str = addr.to_base58check();
         let priv_key = self.node
             .private_keys
-            .get(&addr_str)
-            .expect(&format!("Expected private key for address={}", addr_str))
-            .clone();
-        MultisigAddress {
-            common: common,
-            priv_key: priv_key,
-            redeem_script: redeem_script,
-            addr: addr,
+            .get(&addr.to_string())
+            .cloned()
+            .or_else(|| self.node.derived_private_key(&common.anchoring_keys));
+        let cached = CachedAddress {
+            common: common.clone(),
+            redeem_script,
+            addr,
+            priv_key,
+        };
+        if self.address_cache.len() >= MAX_CACHED_ADDRESSES {
+            self.address_cache.pop_front();
         }
+        self.address_cache.push_back(cached.clone());
+        cached
+    }
+
+    /// Checks that the node is able to sign anchoring transactions for the given
+    /// configuration, that is it has either the private key, the extended key from which
+    /// the private key is derived or the external signer and also has the bitcoin backend.
+    pub fn can_sign(&mut self, common: &AnchoringConfig) -> bool {
+        let has_signer = self.cached_address(common).priv_key.is_some() ||
+            self.node.external_signer.is_some();
+        has_signer && self.client.is_some()
     }
 
     #[doc(hidden)]
     pub fn import_address(&mut self, addr: &btc::Address) -> Result<(), ServiceError> {
         let addr_str = addr.to_string();
         if !self.known_addresses.contains(&addr_str) {
-            self.client().watch_address(addr, false)?;
+            match self.address_descriptors.get(&addr_str) {
+                Some(&(ref descriptor, rescan_from)) => {
+                    self.client().watch_descriptor(addr, descriptor, rescan_from)?
+                }
+                None => self.client().watch_address(addr, false)?,
+            }
 
             trace!("Add address to known, addr={}", addr_str);
             self.known_addresses.insert(addr_str);
@@ -102,10 +317,216 @@ impl AnchoringHandler {
         Ok(())
     }
 
+    // Remembers the output descriptor of the anchoring address of the given configuration,
+    // so its import allows the wallet to track the address natively and to bound the rescan
+    // by the block of the funding transaction.
+    #[doc(hidden)]
+    pub fn describe_address(&mut self, cfg: &AnchoringConfig) -> btc::Address {
+        let addr = self.cached_address(cfg).addr;
+        let rescan_from = cfg.funding_tx.as_ref().map(|tx| tx.id());
+        self.address_descriptors.insert(
+            addr.to_string(),
+            (cfg.output_descriptor(), rescan_from),
+        );
+        addr
+    }
+
+    // The addresses are imported by the following commits, so the unavailable bitcoin backend
+    // does not prevent the node from start.
+    #[doc(hidden)]
+    pub fn schedule_address_import(&mut self, addr: btc::Address) {
+        let is_known = self.known_addresses.contains(&addr.to_string());
+        if !is_known && !self.pending_addresses.contains(&addr) {
+            trace!("Schedule address import, addr={}", addr.to_string());
+            self.pending_addresses.push(addr);
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn import_pending_addresses(&mut self) -> Result<(), ServiceError> {
+        if self.client.is_none() {
+            return Ok(());
+        }
+        while let Some(addr) = self.pending_addresses.first().cloned() {
+            self.import_address(&addr)?;
+            self.pending_addresses.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Schedules the notice of the funding transaction with the given `txid`, which is sent
+    /// to the other validators by the following commit.
+    pub fn schedule_funding_notice(&mut self, txid: btc::TxId) {
+        if !self.pending_funding_notices.contains(&txid) {
+            trace!("Schedule funding notice, txid={}", txid.to_string());
+            self.pending_funding_notices.push(txid);
+        }
+    }
+
+    // Sends the scheduled notices of the funding transactions to the anchoring addresses,
+    // other transactions are skipped. The unconfirmed transactions stay scheduled until
+    // they get enough confirmations.
+    #[doc(hidden)]
+    pub fn send_funding_notices(&mut self, context: &ServiceContext) -> Result<(), ServiceError> {
+        if self.client.is_none() || context.validator_id().is_none() ||
+            self.pending_funding_notices.is_empty()
+        {
+            return Ok(());
+        }
+        let required_confirmations = self.actual_config(context)?
+            .required_funding_confirmations();
+        let mut unconfirmed = Vec::new();
+        while let Some(txid) = self.pending_funding_notices.first().cloned() {
+            let info = self.client().get_transaction_info(txid)?;
+            self.pending_funding_notices.remove(0);
+            if let Some(ref info) = info {
+                if !is_enough_confirmations(required_confirmations, info.confirmations) {
+                    trace!(
+                        "Postpone notice of unconfirmed funding_tx={}, confirmations={:?}",
+                        txid.to_string(),
+                        info.confirmations
+                    );
+                    unconfirmed.push(txid);
+                    continue;
+                }
+            }
+            let tx = match info.map(|info| TxKind::from(info.body)) {
+                Some(TxKind::FundingTx(tx)) => tx,
+                _ => {
+                    warn!(
+                        "Noticed transaction is not a funding transaction, txid={}",
+                        txid.to_string()
+                    );
+                    continue;
+                }
+            };
+            info!("Send funding notice, txid={}", tx.txid());
+            let msg = MsgAnchoringFunding::new(
+                context.public_key(),
+                self.validator_id(context),
+                tx.into(),
+                context.secret_key(),
+            );
+            context.transaction_sender().send(Box::new(msg))?;
+        }
+        self.pending_funding_notices = unconfirmed;
+        Ok(())
+    }
+
+    /// Schedules the vote to sweep all anchoring funds to the given refund `address`,
+    /// the `None` address revokes the vote. The vote is sent by the following commit.
+    pub fn schedule_sweep_vote(&mut self, address: Option<btc::Address>) {
+        let address = address.map(|addr| addr.to_string()).unwrap_or_default();
+        trace!("Schedule sweep vote, address={}", address);
+        self.pending_sweep_vote = Some(address);
+    }
+
+    #[doc(hidden)]
+    pub fn send_sweep_vote(&mut self, context: &ServiceContext) -> Result<(), ServiceError> {
+        if context.validator_id().is_none() {
+            return Ok(());
+        }
+        if let Some(address) = self.pending_sweep_vote.take() {
+            info!("Send sweep vote, address={}", address);
+            let msg = MsgAnchoringSweep::new(
+                context.public_key(),
+                self.validator_id(context),
+                &address,
+                context.secret_key(),
+            );
+            context.transaction_sender().send(Box::new(msg))?;
+        }
+        Ok(())
+    }
+
+    /// Schedules the vote to pause the anchoring for the given `reason`, the `None` reason
+    /// revokes the vote. The vote is sent by the following commit.
+    pub fn schedule_pause_vote(&mut self, reason: Option<String>) {
+        let reason = reason.unwrap_or_default();
+        trace!("Schedule pause vote, reason={}", reason);
+        self.pending_pause_vote = Some(reason);
+    }
+
+    #[doc(hidden)]
+    pub fn send_pause_vote(&mut self, context: &ServiceContext) -> Result<(), ServiceError> {
+        if context.validator_id().is_none() {
+            return Ok(());
+        }
+        if let Some(reason) = self.pending_pause_vote.take() {
+            info!("Send pause vote, reason={}", reason);
+            let msg = MsgAnchoringPause::new(
+                context.public_key(),
+                self.validator_id(context),
+                &reason,
+                context.height().0,
+                context.secret_key(),
+            );
+            context.transaction_sender().send(Box::new(msg))?;
+        }
+        Ok(())
+    }
+
+    /// Schedules the request to anchor the block in which it is committed regardless
+    /// of the anchoring frequency. The request is sent by the following commit.
+    pub fn schedule_anchoring_trigger(&mut self) {
+        trace!("Schedule anchoring trigger");
+        self.pending_anchoring_trigger = true;
+    }
+
+    #[doc(hidden)]
+    pub fn send_anchoring_trigger(&mut self, context: &ServiceContext) -> Result<(), ServiceError> {
+        if context.validator_id().is_none() || !self.pending_anchoring_trigger {
+            return Ok(());
+        }
+        self.pending_anchoring_trigger = false;
+        info!("Send anchoring trigger");
+        let msg = MsgAnchoringTrigger::new(
+            context.public_key(),
+            self.validator_id(context),
+            context.height().0,
+            context.secret_key(),
+        );
+        context.transaction_sender().send(Box::new(msg))?;
+        Ok(())
+    }
+
+    /// Schedules the vote to replace the anchoring key of the `target` validator
+    /// with the given `key`, the `None` key revokes the vote. The vote is sent
+    /// by the following commit.
+    pub fn schedule_key_rotation_vote(&mut self, target: ValidatorId, key: Option<btc::PublicKey>) {
+        let key = key.map(|key| key.to_hex()).unwrap_or_default();
+        trace!("Schedule key rotation vote, target={}, key={}", target.0, key);
+        self.pending_key_rotation_vote = Some((target, key));
+    }
+
+    #[doc(hidden)]
+    pub fn send_key_rotation_vote(
+        &mut self,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        if context.validator_id().is_none() {
+            return Ok(());
+        }
+        if let Some((target, key)) = self.pending_key_rotation_vote.take() {
+            info!("Send key rotation vote, target={}, key={}", target.0, key);
+            let cfg_hash = AnchoringSchema::new(context.snapshot()).actual_config_hash();
+            let msg = MsgAnchoringKeyRotation::new(
+                context.public_key(),
+                self.validator_id(context),
+                target,
+                &cfg_hash,
+                &key,
+                context.secret_key(),
+            );
+            context.transaction_sender().send(Box::new(msg))?;
+        }
+        Ok(())
+    }
+
     /// Adds a `private_key` for the corresponding anchoring `address`.
     pub fn add_private_key(&mut self, address: &btc::Address, private_key: btc::PrivateKey) {
         self.node.private_keys.insert(
-            address.to_base58check(),
+            address.to_string(),
             private_key,
         );
     }
@@ -115,6 +536,82 @@ impl AnchoringHandler {
         self.errors_sink = sink;
     }
 
+    /// Sets the channel for the anchoring alerts, for example, about the low funds.
+    pub fn set_alerts_sink(&mut self, sink: Option<mpsc::Sender<AnchoringAlert>>) {
+        self.alerts_sink = sink;
+    }
+
+    #[doc(hidden)]
+    pub fn send_alert(&self, alert: AnchoringAlert) {
+        warn!("Anchoring alert: {:?}", alert);
+        if let Some(webhook) = self.webhook.as_ref() {
+            let fields = serde_json::to_value(&alert).unwrap_or_else(|_| json!({}));
+            webhook.notify(self.span.to_json("anchoring_alert", fields));
+        }
+        if let Some(sink) = self.alerts_sink.as_ref() {
+            if let Err(e) = sink.send(alert) {
+                error!("Can't send alert to channel: {}", e);
+            }
+        }
+    }
+
+    /// Adds the validator-local `policy` which is checked before the node signs
+    /// the anchoring proposal together with the policies from the node configuration.
+    pub fn add_signing_policy(&mut self, policy: Box<SigningPolicy>) {
+        self.signing_policies.push(policy);
+    }
+
+    /// Approves the signing of the proposals for the given anchored `height` which wait
+    /// for the approval, the proposal is signed by the following commit.
+    pub fn approve_proposal(&mut self, height: u64) {
+        if self.pending_approvals.iter().any(
+            |pending| pending.proposal.height == height,
+        )
+        {
+            info!("Proposals for height={} are approved", height);
+            self.approved_proposals.insert(height);
+        } else {
+            warn!("No proposal for height={} waits for the approval", height);
+        }
+    }
+
+    /// Returns the channel which receives the anchoring lifecycle events emitted
+    /// after the subscription.
+    pub fn subscribe_events(&mut self) -> mpsc::Receiver<AnchoringEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events_subscribers.push(sender);
+        receiver
+    }
+
+    /// Returns the events log entries starting from the event with the given `start` id.
+    pub fn events_since(&self, start: u64, limit: usize) -> Vec<AnchoringEventEntry> {
+        self.events
+            .iter()
+            .filter(|entry| entry.id >= start)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    #[doc(hidden)]
+    pub fn emit_event(&mut self, event: AnchoringEvent) {
+        let fields = serde_json::to_value(&event).unwrap_or_else(|_| json!({}));
+        let name = fields["event"].as_str().unwrap_or("anchoring_event").to_owned();
+        self.span.record(LogLevel::Info, &name, fields);
+        // Subscribers which have dropped their receivers are forgotten.
+        self.events_subscribers.retain(
+            |subscriber| subscriber.send(event.clone()).is_ok(),
+        );
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(AnchoringEventEntry {
+            id: self.next_event_id,
+            event,
+        });
+        self.next_event_id += 1;
+    }
+
     #[doc(hidden)]
     pub fn actual_config(&self, state: &ServiceContext) -> Result<AnchoringConfig, ServiceError> {
         let schema = AnchoringSchema::new(state.snapshot());
@@ -138,6 +635,10 @@ impl AnchoringHandler {
         state: &ServiceContext,
     ) -> Result<Option<(AnchoringConfig, btc::Address)>, ServiceError> {
         if let Some(following) = self.following_config(state)? {
+            // The funds are never transferred to the address with the unsafe threshold.
+            following.check_threshold().map_err(
+                |reason| ServiceError::Config { reason },
+            )?;
             let following_addr = following.redeem_script().1;
             if actual_addr != &following_addr {
                 return Ok(Some((following, following_addr)));
@@ -152,15 +653,32 @@ impl AnchoringHandler {
         state: &ServiceContext,
     ) -> Result<AnchoringState, ServiceError> {
         let actual = self.actual_config(state)?;
-        let actual_addr = actual.redeem_script().1;
+        let actual_addr = self.describe_address(&actual);
         let anchoring_schema = AnchoringSchema::new(state.snapshot());
 
         // Ensure that bitcoind watching for the current addr
-        self.import_address(&actual_addr)?;
+        if self.client.is_some() {
+            self.import_address(&actual_addr)?;
+        }
 
-        if state.validator_id().is_none() {
+        // Nodes without anchoring keys only observe the anchoring chain.
+        if state.validator_id().is_none() || !self.can_sign(&actual) {
             return Ok(AnchoringState::Auditing { cfg: actual });
         }
+        actual.check_threshold().map_err(
+            |reason| ServiceError::Config { reason },
+        )?;
+
+        // The agreed sweep takes precedence over the anchoring.
+        if let Some(refund_addr) = anchoring_schema.sweep_address() {
+            return Ok(AnchoringState::Sweeping {
+                cfg: actual,
+                refund_addr,
+            });
+        }
+        if anchoring_schema.is_paused() {
+            return Ok(AnchoringState::Paused { cfg: actual });
+        }
 
         let key = *self.anchoring_key(&actual, state);
 
@@ -174,8 +692,17 @@ impl AnchoringHandler {
                 if let Some(prev_lect) = anchoring_schema.collect_lects(&prev_cfg) {
                     match TxKind::from(prev_lect) {
                         TxKind::FundingTx(_) => prev_cfg.redeem_script().1 != actual_addr,
-                        TxKind::Anchoring(tx) => tx.output_address(actual.network) != actual_addr,
-                        TxKind::Other(tx) => panic!("Incorrect lect found={:#?}", tx),
+                        TxKind::Anchoring(tx) => {
+                            !tx.is_final() &&
+                                tx.output_address(actual.network) != actual_addr
+                        }
+                        TxKind::Other(tx) => {
+                            let e = HandlerError::IncorrectLect {
+                                reason: "Incorrect previous lect transaction".to_string(),
+                                tx,
+                            };
+                            return Err(e.into());
+                        }
                     }
                 } else {
                     true
@@ -196,18 +723,41 @@ impl AnchoringHandler {
         let result = self.following_config_is_transition(&actual_addr, state)?;
         let state = if let Some((following, following_addr)) = result {
             // Ensure that bitcoind watching for following addr.
+            self.describe_address(&following);
             self.import_address(&following_addr)?;
 
             match TxKind::from(actual_lect) {
                 TxKind::Anchoring(lect) => {
-                    let lect_addr = lect.output_address(actual.network);
-                    if lect_addr == following_addr {
-                        let confirmations = self.client().get_transaction_confirmations(lect.id())?;
-                        // Lect now is transition transaction
-                        AnchoringState::Waiting {
-                            lect: lect.into(),
-                            confirmations: confirmations,
+                    let is_transferred = !lect.is_final() &&
+                        lect.output_address(actual.network) == following_addr;
+                    if is_transferred {
+                        let confirmations = self.tx_confirmations(lect.id())?;
+                        let anchored_height = lect.payload().block_height;
+                        if actual.is_transfer_timed_out(
+                            anchored_height,
+                            confirmations,
+                            state.height(),
+                        )
+                        {
+                            // The stuck transition transaction is retried by the transition.
+                            AnchoringState::Transition {
+                                from: actual,
+                                to: following,
+                            }
+                        } else {
+                            // Lect now is transition transaction
+                            AnchoringState::Waiting {
+                                lect: lect.into(),
+                                confirmations: confirmations,
+                            }
                         }
+                    } else if anchoring_schema.following_config_height().map_or(
+                        false,
+                        |height| actual.is_transfer_postponed(height, state.height()),
+                    )
+                    {
+                        // The anchoring continues on the actual address till the handover.
+                        AnchoringState::Anchoring { cfg: actual }
                     } else {
                         AnchoringState::Transition {
                             from: actual,
@@ -222,7 +772,13 @@ impl AnchoringHandler {
                         to: following,
                     }
                 }
-                TxKind::Other(tx) => panic!("Incorrect lect found={:#?}", tx),
+                TxKind::Other(tx) => {
+                    let e = HandlerError::IncorrectLect {
+                        reason: "Incorrect lect transaction".to_string(),
+                        tx,
+                    };
+                    return Err(e.into());
+                }
             }
         } else {
             match TxKind::from(actual_lect) {
@@ -230,8 +786,9 @@ impl AnchoringHandler {
                     if tx.find_out(&actual_addr).is_some() {
                         trace!("Checking funding_tx={:#?}, txid={}", tx, tx.txid());
                         // Wait until funding_tx got enough confirmation
-                        let confirmations = self.client().get_transaction_confirmations(tx.id())?;
-                        if !is_enough_confirmations(&actual, confirmations) {
+                        let confirmations = self.tx_confirmations(tx.id())?;
+                        let required = actual.required_funding_confirmations();
+                        if !is_enough_confirmations(required, confirmations) {
                             let state = AnchoringState::Waiting {
                                 lect: tx.into(),
                                 confirmations: confirmations,
@@ -247,9 +804,11 @@ impl AnchoringHandler {
                     }
                 }
                 TxKind::Anchoring(actual_lect) => {
-                    let actual_lect_addr = actual_lect.output_address(actual.network);
-                    // Ensure that we did not miss transition lect
-                    if actual_lect_addr != actual_addr {
+                    // Ensure that we did not miss transition lect, the final lect
+                    // has spent the funds of the actual address.
+                    let is_missed_transition = !actual_lect.is_final() &&
+                        actual_lect.output_address(actual.network) != actual_addr;
+                    if is_missed_transition {
                         let state = AnchoringState::Recovering {
                             prev_cfg: anchoring_schema.previous_anchoring_config().unwrap(),
                             actual_cfg: actual,
@@ -258,11 +817,16 @@ impl AnchoringHandler {
                     }
                     // If the lect encodes a transition to a new anchoring address,
                     // we need to wait until it reaches enough confirmations.
-                    if actual_lect_is_transition(&actual, &actual_lect, &anchoring_schema) {
-                        let confirmations = self.client().get_transaction_confirmations(
-                            actual_lect.id(),
-                        )?;
-                        if !is_enough_confirmations(&actual, confirmations) {
+                    let is_pending_transfer = anchoring_schema
+                        .pending_transfer()
+                        .map(|transfer| transfer.id()) ==
+                        Some(actual_lect.id());
+                    if is_pending_transfer ||
+                        actual_lect_is_transition(&actual, &actual_lect, &anchoring_schema)?
+                    {
+                        let confirmations = self.tx_confirmations(actual_lect.id())?;
+                        let required = actual.required_anchor_confirmations();
+                        if !is_enough_confirmations(required, confirmations) {
                             let state = AnchoringState::Waiting {
                                 lect: actual_lect.into(),
                                 confirmations: confirmations,
@@ -270,10 +834,24 @@ impl AnchoringHandler {
                             return Ok(state);
                         }
                     }
+                    // None of the unspent outputs continues the chain.
+                    if self.broken_lect == Some(actual_lect.id()) {
+                        let state = AnchoringState::Broken {
+                            cfg: actual,
+                            lect: actual_lect,
+                        };
+                        return Ok(state);
+                    }
 
                     AnchoringState::Anchoring { cfg: actual }
                 }
-                TxKind::Other(tx) => panic!("Incorrect lect found={:#?}", tx),
+                TxKind::Other(tx) => {
+                    let e = HandlerError::IncorrectLect {
+                        reason: "Incorrect lect transaction".to_string(),
+                        tx,
+                    };
+                    return Err(e.into());
+                }
             }
         };
         Ok(state)
@@ -281,7 +859,115 @@ impl AnchoringHandler {
 
     #[doc(hidden)]
     pub fn handle_commit(&mut self, state: &ServiceContext) -> Result<(), ServiceError> {
-        match self.current_state(state)? {
+        let height = state.height();
+        self.enter_span(state);
+        if height.0 % self.node.check_lect_frequency == 0 {
+            self.retry.lect_update = true;
+        }
+        if !self.retry.is_ready(height) {
+            self.span.record(
+                LogLevel::Trace,
+                "commit_skipped",
+                json!({ "next_attempt": self.retry.next_attempt.0 }),
+            );
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        self.span.record(LogLevel::Trace, "commit_started", json!({}));
+
+        let result = self.import_pending_addresses()
+            .and_then(|_| self.send_funding_notices(state))
+            .and_then(|_| self.send_sweep_vote(state))
+            .and_then(|_| self.send_pause_vote(state))
+            .and_then(|_| self.send_anchoring_trigger(state))
+            .and_then(|_| self.send_key_rotation_vote(state))
+            .and_then(|_| self.send_signatures(state))
+            .and_then(|_| self.handle_current_state(state));
+        let endpoint = self.client.as_ref().map(|client| client.config().host);
+        let result = result.map_err(|e| e.with_round(height, endpoint));
+        match result {
+            // The background requests are not completed yet, so the round is repeated
+            // by the next commit without the backoff and the lect update is kept pending.
+            Err(ServiceError::Rpc(ref failure)) if is_pending_error(&failure.error) => {
+                self.span.record(
+                    LogLevel::Debug,
+                    "pending_request",
+                    json!({ "error": failure.to_string() }),
+                );
+            }
+            Err(ServiceError::Rpc(ref failure)) if is_transient_error(&failure.error) => {
+                let delay = self.retry.fail(height);
+                self.span.record(
+                    LogLevel::Warn,
+                    "transient_error",
+                    json!({ "error": failure.to_string(), "retry_after": delay }),
+                );
+            }
+            _ => self.retry.reset(),
+        }
+        // The pending requests are not the failures of the commit.
+        let result = match result {
+            Err(ServiceError::Rpc(ref failure)) if is_pending_error(&failure.error) => Ok(()),
+            result => result,
+        };
+        let elapsed = started.elapsed();
+        let duration_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos() / 1_000_000);
+        self.span.record(
+            LogLevel::Debug,
+            "commit_finished",
+            json!({ "duration_ms": duration_ms, "ok": result.is_ok() }),
+        );
+        result
+    }
+
+    // The span of the commit is filled at its start and is updated once the node
+    // starts a new anchoring round.
+    #[doc(hidden)]
+    pub fn enter_span(&mut self, state: &ServiceContext) {
+        self.span = RoundSpan {
+            commit_height: state.height().0,
+            validator: state.validator_id().map(|id| id.0),
+            phase: self.state.phase,
+            round: None,
+            txid: None,
+        };
+        if let Some(proposal) = self.proposal_tx.clone() {
+            self.enter_round(&proposal);
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn enter_round(&mut self, proposal: &AnchoringTx) {
+        self.span.round = Some(proposal.payload().block_height.0);
+        self.span.txid = Some(proposal.id());
+    }
+
+    // The handler inspects the anchoring state, performs the actions of the corresponding
+    // phase and then moves the state machine to the phase reached by these actions.
+    #[doc(hidden)]
+    pub fn handle_current_state(&mut self, state: &ServiceContext) -> Result<(), ServiceError> {
+        let current = self.current_state(state)?;
+        let phase = state_phase(&current);
+        let anchoring_cfg = match current {
+            AnchoringState::Anchoring { ref cfg } => Some(cfg.clone()),
+            _ => None,
+        };
+        let result = self.perform_state_actions(current, state);
+        let phase = match anchoring_cfg {
+            Some(cfg) => self.round_phase(&cfg, state),
+            None => phase,
+        };
+        self.set_phase(phase, state.height());
+        result
+    }
+
+    fn perform_state_actions(
+        &mut self,
+        current: AnchoringState,
+        state: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        match current {
             AnchoringState::Anchoring { cfg } => self.handle_anchoring_state(&cfg, state),
             AnchoringState::Transition { from, to } => {
                 self.handle_transition_state(&from, &to, state)
@@ -295,7 +981,98 @@ impl AnchoringHandler {
                 confirmations,
             } => self.handle_waiting_state(lect, confirmations),
             AnchoringState::Auditing { cfg } => self.handle_auditing_state(&cfg, state),
-            AnchoringState::Broken => panic!("Broken anchoring state detected!"),
+            AnchoringState::Broken { cfg, lect } => self.handle_broken_state(&cfg, &lect, state),
+            AnchoringState::Sweeping { cfg, refund_addr } => {
+                self.handle_sweeping_state(&cfg, &refund_addr, state)
+            }
+            AnchoringState::Paused { cfg } => self.handle_paused_state(&cfg, state),
+        }
+    }
+
+    // The phase of the anchoring round is defined by the proposal and the sent transaction,
+    // the latter is broadcasting until it becomes our lect.
+    fn round_phase(&self, cfg: &AnchoringConfig, state: &ServiceContext) -> AnchoringPhase {
+        if self.proposal_tx.is_some() {
+            return AnchoringPhase::CollectingSignatures;
+        }
+        let lect = AnchoringSchema::new(state.snapshot()).lect(self.anchoring_key(cfg, state));
+        if let Some(txid) = self.state.broadcasted {
+            if lect.as_ref().map(|lect| lect.id()) != Some(txid) {
+                return AnchoringPhase::Broadcasting;
+            }
+        }
+        match lect.map(TxKind::from) {
+            Some(TxKind::Anchoring(ref lect)) if lect.is_final() => AnchoringPhase::WalletEmpty,
+            _ => AnchoringPhase::Proposing,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn set_phase(&mut self, phase: AnchoringPhase, height: Height) {
+        let prev_state = self.state.clone();
+        // The round is dropped together with its proposal.
+        if self.proposal_tx.is_none() {
+            self.state.round = None;
+        }
+        if self.state.enter(phase, height.0) {
+            self.span.record(
+                LogLevel::Info,
+                "phase_changed",
+                json!({ "from": prev_state.phase, "to": phase }),
+            );
+            if phase == AnchoringPhase::Transferring {
+                self.emit_event(AnchoringEvent::TransferStarted { height: height.0 });
+            }
+        }
+        if self.state != prev_state {
+            self.persist_state();
+        }
+    }
+
+    #[doc(hidden)]
+    /// Returns the confirmations of the transaction, the confirmations of the deeply
+    /// confirmed transactions are taken from the cache instead of the bitcoin backend.
+    pub fn tx_confirmations(&mut self, txid: btc::TxId) -> Result<Option<u64>, ServiceError> {
+        if let Some(confirmations) = self.state.cached_confirmations(&txid) {
+            return Ok(Some(confirmations));
+        }
+        let confirmations = self.client().get_transaction_confirmations(txid)?;
+        if let Some(confirmations) = confirmations {
+            self.cache_confirmations(txid, confirmations);
+        }
+        Ok(confirmations)
+    }
+
+    #[doc(hidden)]
+    pub fn cache_confirmations(&mut self, txid: btc::TxId, confirmations: u64) {
+        let depth = match self.node.confirmations_cache_depth {
+            Some(depth) => depth,
+            None => return,
+        };
+        if confirmations >= depth && self.state.cached_confirmations(&txid).is_none() {
+            trace!("Caching confirmations={} of txid={}", confirmations, txid);
+            self.state.cache_confirmations(txid, confirmations);
+            self.persist_state();
+        }
+    }
+
+    #[doc(hidden)]
+    /// Drops the cached confirmations, since the deeply confirmed transactions
+    /// may be orphaned by the detected reorg.
+    pub fn invalidate_confirmations(&mut self) {
+        if !self.state.confirmed_txs.is_empty() {
+            info!("Dropping {} cached confirmations", self.state.confirmed_txs.len());
+            self.state.confirmed_txs.clear();
+            self.persist_state();
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn persist_state(&self) {
+        if let Some(ref path) = self.node.state_path {
+            if let Err(e) = self.state.save(path) {
+                error!("Unable to save the handler state to {}: {}", path, e);
+            }
         }
     }
 
@@ -305,13 +1082,13 @@ impl AnchoringHandler {
         anchoring_key: &btc::PublicKey,
         anchoring_cfg: &AnchoringConfig,
         state: &ServiceContext,
-    ) -> LectKind {
+    ) -> Result<LectKind, ServiceError> {
         let anchoring_schema = AnchoringSchema::new(state.snapshot());
 
         let our_lect = if let Some(lect) = anchoring_schema.lect(anchoring_key) {
             lect
         } else {
-            return LectKind::None;
+            return Ok(LectKind::None);
         };
 
         let mut count = 0;
@@ -324,15 +1101,22 @@ impl AnchoringHandler {
             }
         }
 
-        if count >= ::majority_count(validators_count as u8) {
+        let kind = if count >= ::majority_count(validators_count as u8) {
             match TxKind::from(our_lect) {
                 TxKind::Anchoring(tx) => LectKind::Anchoring(tx),
                 TxKind::FundingTx(tx) => LectKind::Funding(tx),
-                TxKind::Other(tx) => panic!("Found incorrect lect transaction, content={:#?}", tx),
+                TxKind::Other(tx) => {
+                    let e = HandlerError::IncorrectLect {
+                        reason: "Incorrect lect transaction".to_string(),
+                        tx,
+                    };
+                    return Err(e.into());
+                }
             }
         } else {
             LectKind::None
-        }
+        };
+        Ok(kind)
     }
 
     #[doc(hidden)]
@@ -365,13 +1149,10 @@ impl AnchoringHandler {
         &self,
         multisig: &MultisigAddress,
         state: &ServiceContext,
-    ) -> Result<Option<BitcoinTx>, ServiceError> {
-        let lects = self.client()
-            .unspent_transactions(&multisig.addr)?
-            .into_iter()
-            .map(|tx| tx.body);
+    ) -> Result<Option<TxInfo>, ServiceError> {
+        let lects = self.client().unspent_transactions(&multisig.addr)?;
         for lect in lects {
-            if self.transaction_is_lect(&lect, multisig, state)? {
+            if self.transaction_is_lect(&lect.body, multisig, state)? {
                 return Ok(Some(lect));
             }
         }
@@ -386,7 +1167,24 @@ impl AnchoringHandler {
     ) -> Result<Option<BitcoinTx>, ServiceError> {
         let key = self.anchoring_key(multisig.common, state);
         trace!("Update our lect");
-        if let Some(lect) = self.find_lect(multisig, state)? {
+        if let Some(info) = self.find_lect(multisig, state)? {
+            // The re-broadcast orphaned lect is still our lect in the blockchain.
+            if let Some(restored) = self.check_lect_reorg(&info, multisig, state) {
+                return Ok(Some(restored));
+            }
+            let lect = info.body;
+            match TxKind::from(lect.clone()) {
+                TxKind::Anchoring(tx) => {
+                    self.metrics.anchored_height = Some(tx.payload().block_height.0);
+                    self.metrics.balance = Some(tx.amount());
+                }
+                TxKind::FundingTx(tx) => {
+                    self.metrics.balance = tx.find_out(&multisig.addr).map(
+                        |out| tx.output[out as usize].value,
+                    );
+                }
+                TxKind::Other(_) => {}
+            }
             // New lect with different signatures set.
             let (our_lect, lects_count) = {
                 let schema = AnchoringSchema::new(state.snapshot());
@@ -419,7 +1217,7 @@ impl AnchoringHandler {
         trace!(
             "Checking funding_tx={:#?}, addr={} availability",
             funding_tx,
-            multisig.addr.to_base58check()
+            multisig.addr.to_string()
         );
         if let Some(info) = self.client()
             .unspent_transactions(&multisig.addr)?
@@ -436,6 +1234,100 @@ impl AnchoringHandler {
         Ok(None)
     }
 
+    #[doc(hidden)]
+    /// Returns all funding transactions to the anchoring address that can be spent
+    /// by the next anchoring proposal. The funding transactions are taken only from
+    /// the blockchain state, so all validators build the same proposal regardless of
+    /// their bitcoin backends: the funding tx from the actual configuration unless it is
+    /// spent by the anchoring chain and the funding transactions noticed by the validators.
+    /// The unspent funding transactions discovered by the bitcoin backend of this node
+    /// are noticed once they get enough confirmations.
+    pub fn avaliable_funds(
+        &self,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<Vec<FundingCandidate>, ServiceError> {
+        self.notice_unspent_funds(multisig, context)?;
+
+        let schema = AnchoringSchema::new(context.snapshot());
+        let actual_from = Schema::new(context.snapshot())
+            .actual_configuration()
+            .actual_from;
+        let cfg_funding_tx = multisig.common.funding_tx();
+        let mut funds = Vec::new();
+        if !schema.spent_funding().contains(&cfg_funding_tx.id()) {
+            if let Some(out) = cfg_funding_tx.find_out(&multisig.addr) {
+                funds.push(FundingCandidate {
+                    tx: cfg_funding_tx.clone(),
+                    out,
+                    recorded_height: actual_from.0,
+                });
+            }
+        }
+        let notice_heights = schema.funding_notice_heights();
+        for (txid, tx) in schema.funding_notices().iter() {
+            let tx = match TxKind::from(tx) {
+                TxKind::FundingTx(tx) => tx,
+                _ => continue,
+            };
+            let out = match tx.find_out(&multisig.addr) {
+                Some(out) => out,
+                None => continue,
+            };
+            if &tx == cfg_funding_tx {
+                continue;
+            }
+            funds.push(FundingCandidate {
+                tx,
+                out,
+                recorded_height: notice_heights.get(&txid).unwrap_or(0),
+            });
+        }
+        trace!("avaliable_funds={:#?}", funds);
+        Ok(funds)
+    }
+
+    // Sends the notices of the funding transactions to the anchoring address which are listed
+    // as unspent by the bitcoin backend, have enough confirmations and are not known
+    // to the blockchain yet, so the following proposals of all validators spend them.
+    fn notice_unspent_funds(
+        &self,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let unspent = self.client().unspent_transactions(&multisig.addr)?;
+        let schema = AnchoringSchema::new(context.snapshot());
+        let required_confirmations = multisig.common.required_funding_confirmations();
+        for info in unspent {
+            let tx = match TxKind::from(info.body) {
+                TxKind::FundingTx(tx) => tx,
+                _ => continue,
+            };
+            if &tx == multisig.common.funding_tx() || schema.known_txs().contains(&tx.id()) ||
+                tx.find_out(&multisig.addr).is_none()
+            {
+                continue;
+            }
+            if !is_enough_confirmations(required_confirmations, info.confirmations) {
+                trace!(
+                    "Skip unconfirmed funding_tx={}, confirmations={:?}",
+                    tx.txid(),
+                    info.confirmations
+                );
+                continue;
+            }
+            info!("Send funding notice, txid={}", tx.txid());
+            let msg = MsgAnchoringFunding::new(
+                context.public_key(),
+                self.validator_id(context),
+                tx.into(),
+                context.secret_key(),
+            );
+            context.transaction_sender().send(Box::new(msg))?;
+        }
+        Ok(())
+    }
+
     #[doc(hidden)]
     fn transaction_is_lect(
         &self,
@@ -516,24 +1408,44 @@ impl AnchoringHandler {
     }
 }
 
+// The phase of the anchoring round is refined by the handler after the round actions.
+fn state_phase(state: &AnchoringState) -> AnchoringPhase {
+    match *state {
+        AnchoringState::Auditing { .. } => AnchoringPhase::Auditing,
+        AnchoringState::Anchoring { .. } => AnchoringPhase::Proposing,
+        AnchoringState::Transition { .. } => AnchoringPhase::Transferring,
+        AnchoringState::Recovering { .. } |
+        AnchoringState::Broken { .. } => AnchoringPhase::Recovering,
+        AnchoringState::Sweeping { .. } => AnchoringPhase::Sweeping,
+        AnchoringState::Paused { .. } => AnchoringPhase::Paused,
+        AnchoringState::Waiting { ref lect, .. } => {
+            match TxKind::from(lect.clone()) {
+                TxKind::FundingTx(_) => AnchoringPhase::WaitingForFunding,
+                // The lect is the transfer transaction.
+                _ => AnchoringPhase::WaitingForTransfer,
+            }
+        }
+    }
+}
+
 /// Transition lects cannot be recovered without breaking of current anchoring chain.
 fn actual_lect_is_transition<T>(
     actual: &AnchoringConfig,
     actual_lect: &AnchoringTx,
     schema: &AnchoringSchema<T>,
-) -> bool
+) -> Result<bool, ServiceError>
 where
     T: AsRef<Snapshot>,
 {
     // If tx contains prev_tx_chain it can not be a transition
-    if actual_lect.payload().prev_tx_chain.is_some() {
-        return false;
+    if actual_lect.payload().prev_tx_chain.is_some() || actual_lect.is_final() {
+        return Ok(false);
     }
 
     let prev_lect_id = actual_lect.prev_hash();
     let actual_lect_addr = actual_lect.output_address(actual.network);
 
-    if let Some(prev_lect) = schema.known_txs().get(&prev_lect_id) {
+    let is_transition = if let Some(prev_lect) = schema.known_txs().get(&prev_lect_id) {
         match TxKind::from(prev_lect) {
             TxKind::Anchoring(prev_lect) => {
                 let prev_lect_addr = prev_lect.output_address(actual.network);
@@ -548,16 +1460,23 @@ where
                     false
                 }
             }
-            TxKind::Other(tx) => panic!("Incorrect prev_lect found={:#?}", tx),
+            TxKind::Other(tx) => {
+                let e = HandlerError::IncorrectLect {
+                    reason: "Incorrect previous lect transaction".to_string(),
+                    tx,
+                };
+                return Err(e.into());
+            }
         }
     } else {
         false
-    }
+    };
+    Ok(is_transition)
 }
 
-fn is_enough_confirmations(cfg: &AnchoringConfig, confirmations: Option<u64>) -> bool {
+fn is_enough_confirmations(required: u64, confirmations: Option<u64>) -> bool {
     if let Some(confirmations) = confirmations {
-        confirmations >= cfg.utxo_confirmations
+        confirmations >= required
     } else {
         false
     }
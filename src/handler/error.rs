@@ -22,7 +22,10 @@ use details::btc::transactions::BitcoinTx;
 #[derive(Debug, PartialEq)]
 pub enum Error {
     IncorrectLect { reason: String, tx: BitcoinTx },
+    IncorrectProposal { reason: String, tx: BitcoinTx },
+    ProposalRefused { reason: String, tx: BitcoinTx },
     LectNotFound { height: Height },
+    BrokenChain { lect: BitcoinTx },
 }
 
 impl fmt::Display for Error {
@@ -31,9 +34,22 @@ impl fmt::Display for Error {
             Error::IncorrectLect { ref reason, ref tx } => {
                 write!(f, "Incorrect lect: {}, tx={:#?}", reason, tx)
             }
+            Error::IncorrectProposal { ref reason, ref tx } => {
+                write!(f, "Incorrect proposal: {}, tx={:#?}", reason, tx)
+            }
+            Error::ProposalRefused { ref reason, ref tx } => {
+                write!(f, "Proposal is refused by the signing policy: {}, tx={:#?}", reason, tx)
+            }
             Error::LectNotFound { height } => {
                 write!(f, "Suitable lect not found for height={}", height)
             }
+            Error::BrokenChain { ref lect } => {
+                write!(
+                    f,
+                    "Anchoring chain is broken, a new funding_tx is required, lect={:#?}",
+                    lect
+                )
+            }
         }
     }
 }
@@ -42,7 +58,10 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::IncorrectLect { .. } => "Incorrect lect",
+            Error::IncorrectProposal { .. } => "Incorrect proposal",
+            Error::ProposalRefused { .. } => "Proposal is refused by the signing policy",
             Error::LectNotFound { .. } => "Suitable lect not found",
+            Error::BrokenChain { .. } => "Anchoring chain is broken",
         }
     }
 
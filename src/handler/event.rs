@@ -0,0 +1,72 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lifecycle events of the anchoring handler.
+
+use details::btc;
+
+/// Event of the anchoring process on this node.
+///
+/// Events are sent to the channels returned by the
+/// [`subscribe_events`](../struct.AnchoringHandler.html#method.subscribe_events)
+/// and are kept in the events log available through the private api.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AnchoringEvent {
+    /// The node has signed the anchoring transaction proposal.
+    ProposalCreated {
+        /// `Txid` of the proposal.
+        txid: btc::TxId,
+        /// Height of the anchored block.
+        height: u64,
+    },
+    /// The majority of the validators has signed the proposal.
+    SignaturesCollected {
+        /// `Txid` of the proposal.
+        txid: btc::TxId,
+        /// Number of the collected input signatures.
+        signatures: u64,
+    },
+    /// The finalized anchoring transaction has been sent to the bitcoin network.
+    AnchorBroadcast {
+        /// `Txid` of the anchoring transaction.
+        txid: btc::TxId,
+    },
+    /// The lect has reached the `anchor_confirmations` depth.
+    AnchorConfirmed {
+        /// `Txid` of the lect.
+        txid: btc::TxId,
+        /// Number of the lect confirmations.
+        confirmations: u64,
+    },
+    /// The anchoring chain is transferred to the address of the following configuration.
+    TransferStarted {
+        /// Height of the block after which the transfer has started.
+        height: u64,
+    },
+    /// The lect is not found in the unspent outputs of the anchoring address.
+    ChainBroken {
+        /// `Txid` of the lost lect.
+        lect: btc::TxId,
+    },
+}
+
+/// Entry of the events log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnchoringEventEntry {
+    /// Sequence number of the event since the node start.
+    pub id: u64,
+    /// The event itself.
+    pub event: AnchoringEvent,
+}
@@ -0,0 +1,178 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime metrics of the anchoring handler.
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Anchoring metrics which are exported in the [Prometheus][1] text format.
+///
+/// [1]: https://prometheus.io/docs/instrumenting/exposition_formats/
+#[derive(Debug, Default, Clone)]
+pub struct AnchoringMetrics {
+    /// Height of the latest anchored `Exonum` block.
+    pub anchored_height: Option<u64>,
+    /// Number of confirmations of the lect.
+    pub lect_confirmations: Option<u64>,
+    /// Funds available on the anchoring address in satoshis.
+    pub balance: Option<u64>,
+    /// Duration of the latest round from the proposal signing up to its finalization.
+    pub signature_round_duration: Option<Duration>,
+    /// Total number of errors returned by the bitcoin backend.
+    pub rpc_errors: u64,
+    /// Fee of the latest anchoring transaction in satoshis.
+    pub anchor_fee: Option<u64>,
+    /// Total fee spent by the finalized anchoring transactions in satoshis.
+    pub total_fee: u64,
+    /// Total number of the finalized anchoring transactions.
+    pub anchors: u64,
+    /// Total number of the detected bitcoin reorgs.
+    pub reorgs: u64,
+    #[doc(hidden)]
+    pub round_started: Option<Instant>,
+}
+
+impl AnchoringMetrics {
+    #[doc(hidden)]
+    pub fn start_signature_round(&mut self) {
+        if self.round_started.is_none() {
+            self.round_started = Some(Instant::now());
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn finish_signature_round(&mut self, fee: Option<u64>) {
+        if let Some(started) = self.round_started.take() {
+            self.signature_round_duration = Some(started.elapsed());
+        }
+        if let Some(fee) = fee {
+            self.total_fee += fee;
+        }
+        self.anchor_fee = fee;
+        self.anchors += 1;
+    }
+
+    /// Returns metrics in the Prometheus text format.
+    pub fn to_prometheus(&self) -> String {
+        let round_duration = self.signature_round_duration.map(|duration| {
+            duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+        });
+
+        let mut text = String::new();
+        write_metric(
+            &mut text,
+            "btc_anchoring_anchored_height",
+            "gauge",
+            "Height of the latest anchored block.",
+            self.anchored_height,
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_lect_confirmations",
+            "gauge",
+            "Number of confirmations of the lect.",
+            self.lect_confirmations,
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_balance_satoshis",
+            "gauge",
+            "Funds available on the anchoring address.",
+            self.balance,
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_signature_round_duration_seconds",
+            "gauge",
+            "Duration of the latest signature round.",
+            round_duration,
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_rpc_errors_total",
+            "counter",
+            "Total number of the bitcoin backend errors.",
+            Some(self.rpc_errors),
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_anchor_fee_satoshis",
+            "gauge",
+            "Fee of the latest anchoring transaction.",
+            self.anchor_fee,
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_fee_satoshis_total",
+            "counter",
+            "Total fee spent by the anchoring transactions.",
+            Some(self.total_fee),
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_anchors_total",
+            "counter",
+            "Total number of the anchoring transactions.",
+            Some(self.anchors),
+        );
+        write_metric(
+            &mut text,
+            "btc_anchoring_reorgs_total",
+            "counter",
+            "Total number of the detected bitcoin reorgs.",
+            Some(self.reorgs),
+        );
+        text
+    }
+}
+
+// Unknown values are skipped, so the alerts expressions can use `absent`.
+fn write_metric<T: ToString>(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: Option<T>,
+) {
+    if let Some(value) = value {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+        writeln!(text, "{} {}", name, value.to_string()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnchoringMetrics;
+
+    #[test]
+    fn test_metrics_prometheus_format() {
+        let mut metrics = AnchoringMetrics::default();
+        metrics.anchored_height = Some(10);
+        metrics.rpc_errors = 2;
+        metrics.start_signature_round();
+        metrics.finish_signature_round(Some(1000));
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains(
+            "# TYPE btc_anchoring_anchored_height gauge\nbtc_anchoring_anchored_height 10\n",
+        ));
+        assert!(text.contains("\nbtc_anchoring_rpc_errors_total 2\n"));
+        assert!(text.contains("\nbtc_anchoring_anchor_fee_satoshis 1000\n"));
+        assert!(text.contains("\nbtc_anchoring_anchors_total 1\n"));
+        assert!(text.contains("\nbtc_anchoring_signature_round_duration_seconds "));
+        assert!(!text.contains("btc_anchoring_balance_satoshis"));
+    }
+}
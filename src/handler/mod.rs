@@ -17,25 +17,54 @@
 mod anchoring;
 mod auditing;
 mod transition;
+mod recovery;
+mod sweep;
+mod pause;
 mod basic;
+pub mod alert;
 pub mod error;
+pub mod event;
+pub mod metrics;
+pub mod policy;
+pub mod retry;
+pub mod shared;
+pub mod span;
+pub mod state;
 
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::collections::HashSet;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
+
+use exonum::storage::Snapshot;
+use exonum::helpers::ValidatorId;
 
 use details::rpc::BitcoinRelay;
+use details::signer::Signer;
+use details::webhook::Webhook;
 use details::btc;
 use details::btc::transactions::{AnchoringTx, BitcoinTx, FundingTx};
+use details::btc::psbt::Psbt;
 use local_storage::AnchoringNodeConfig;
 use blockchain::consensus_storage::AnchoringConfig;
 use blockchain::dto::MsgAnchoringSignature;
+use blockchain::schema::AnchoringSchema;
+
+use self::alert::AnchoringAlert;
+use self::event::{AnchoringEvent, AnchoringEventEntry};
+use self::metrics::AnchoringMetrics;
+use self::policy::{PendingApproval, SigningPolicy};
+use self::retry::RetryState;
+use self::shared::SharedHandler;
+use self::span::RoundSpan;
+use self::state::HandlerState;
 
 /// Internal anchoring service handler. Can be used to manage the service.
 #[derive(Debug)]
 pub struct AnchoringHandler {
     #[doc(hidden)]
-    pub client: Option<Box<BitcoinRelay>>,
+    pub client: Option<Arc<BitcoinRelay>>,
     #[doc(hidden)]
     pub node: AnchoringNodeConfig,
     #[doc(hidden)]
@@ -43,14 +72,89 @@ pub struct AnchoringHandler {
     #[doc(hidden)]
     pub errors_sink: Option<mpsc::Sender<error::Error>>,
     #[doc(hidden)]
+    pub alerts_sink: Option<mpsc::Sender<AnchoringAlert>>,
+    #[doc(hidden)]
+    pub events_subscribers: Vec<mpsc::Sender<AnchoringEvent>>,
+    #[doc(hidden)]
+    pub events: VecDeque<AnchoringEventEntry>,
+    #[doc(hidden)]
+    pub next_event_id: u64,
+    #[doc(hidden)]
+    pub webhook: Option<Webhook>,
+    #[doc(hidden)]
+    pub low_funds_lect: Option<btc::TxId>,
+    #[doc(hidden)]
+    pub wallet_empty_lect: Option<btc::TxId>,
+    #[doc(hidden)]
+    pub failed_rounds: u64,
+    #[doc(hidden)]
+    pub unconfirmed_lect: Option<(btc::TxId, Instant)>,
+    #[doc(hidden)]
     pub known_addresses: HashSet<String>,
+    #[doc(hidden)]
+    pub pending_addresses: Vec<btc::Address>,
+    #[doc(hidden)]
+    pub address_descriptors: HashMap<String, (String, Option<btc::TxId>)>,
+    #[doc(hidden)]
+    pub pending_funding_notices: Vec<btc::TxId>,
+    #[doc(hidden)]
+    pub pending_sweep_vote: Option<String>,
+    #[doc(hidden)]
+    pub pending_pause_vote: Option<String>,
+    #[doc(hidden)]
+    pub pending_anchoring_trigger: bool,
+    #[doc(hidden)]
+    pub anchored_since: Option<(btc::TxId, Instant)>,
+    #[doc(hidden)]
+    pub pending_signatures: Vec<MsgAnchoringSignature>,
+    #[doc(hidden)]
+    pub pending_key_rotation_vote: Option<(ValidatorId, String)>,
+    #[doc(hidden)]
+    pub broken_lect: Option<btc::TxId>,
+    #[doc(hidden)]
+    pub lect_confirmations: Option<(btc::TxId, u64)>,
+    #[doc(hidden)]
+    pub metrics: AnchoringMetrics,
+    #[doc(hidden)]
+    pub retry: RetryState,
+    #[doc(hidden)]
+    pub state: HandlerState,
+    #[doc(hidden)]
+    pub resumed_round: bool,
+    #[doc(hidden)]
+    pub pending_errors: Vec<String>,
+    #[doc(hidden)]
+    pub address_cache: VecDeque<CachedAddress>,
+    #[doc(hidden)]
+    pub proposal_psbt: Option<Psbt>,
+    #[doc(hidden)]
+    pub signing_policies: Vec<Box<SigningPolicy>>,
+    #[doc(hidden)]
+    pub pending_approvals: Vec<PendingApproval>,
+    #[doc(hidden)]
+    pub approved_proposals: HashSet<u64>,
+    #[doc(hidden)]
+    pub shared: Arc<SharedHandler>,
+    #[doc(hidden)]
+    pub span: RoundSpan,
+}
+
+#[doc(hidden)]
+/// The redeem script, the address and the private key of the anchoring configuration
+/// which are computed once instead of each commit.
+#[derive(Debug, Clone)]
+pub struct CachedAddress {
+    pub common: AnchoringConfig,
+    pub redeem_script: btc::RedeemScript,
+    pub addr: btc::Address,
+    pub priv_key: Option<btc::PrivateKey>,
 }
 
 #[doc(hidden)]
 #[derive(Debug)]
 pub struct MultisigAddress<'a> {
     pub common: &'a AnchoringConfig,
-    pub priv_key: btc::PrivateKey,
+    pub signer: Box<Signer>,
     pub addr: btc::Address,
     pub redeem_script: btc::RedeemScript,
 }
@@ -72,7 +176,15 @@ pub enum AnchoringState {
         confirmations: Option<u64>,
     },
     Auditing { cfg: AnchoringConfig },
-    Broken,
+    Broken {
+        cfg: AnchoringConfig,
+        lect: AnchoringTx,
+    },
+    Sweeping {
+        cfg: AnchoringConfig,
+        refund_addr: btc::Address,
+    },
+    Paused { cfg: AnchoringConfig },
 }
 
 #[doc(hidden)]
@@ -106,7 +218,7 @@ where
         signatures_by_input[validator] = Some(msg.signature().to_vec());
     }
 
-    let majority_count = common.majority_count() as usize;
+    let threshold = common.signature_threshold() as usize;
 
     // remove "holes" from signatures preserve order
     let mut actual_signatures = HashMap::new();
@@ -114,19 +226,65 @@ where
         let signatures = signatures
             .into_iter()
             .filter_map(|x| x)
-            .take(majority_count)
+            .take(threshold)
             .collect::<Vec<_>>();
 
         trace!(
-            "signatures for input={}, count={}, majority_count={}",
+            "signatures for input={}, count={}, threshold={}",
             input,
             signatures.len(),
-            majority_count
+            threshold
         );
-        if signatures.len() < majority_count {
+        if signatures.len() < threshold {
             return None;
         }
         actual_signatures.insert(input, signatures);
     }
     Some(actual_signatures)
 }
+
+#[doc(hidden)]
+/// The function adds signatures from messages to the `Psbt` of the proposal
+/// as the partial signatures of the corresponding anchoring keys.
+pub fn collect_partial_signatures<T, I>(
+    proposal: &AnchoringTx,
+    multisig: &MultisigAddress,
+    schema: &AnchoringSchema<T>,
+    msgs: I,
+) -> Psbt
+where
+    T: AsRef<Snapshot>,
+    I: IntoIterator<Item = MsgAnchoringSignature>,
+{
+    let common = multisig.common;
+    let mut psbt = Psbt::from_anchoring_tx(proposal, &multisig.redeem_script, common.script_type);
+    for input in proposal.inputs() {
+        if let Some(prev_tx) = schema.spent_tx(proposal, input) {
+            let psbt_input = &mut psbt.inputs[input as usize];
+            match common.script_type {
+                btc::ScriptType::P2sh => psbt_input.non_witness_utxo = Some(prev_tx),
+                btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                    let prev_index = proposal.input[input as usize].prev_index;
+                    psbt_input.witness_utxo = prev_tx.output.get(prev_index as usize).cloned();
+                }
+            }
+        }
+    }
+
+    add_partial_signatures(&mut psbt, multisig, msgs);
+    psbt
+}
+
+#[doc(hidden)]
+/// The function adds signatures from messages to the given `Psbt` of the proposal.
+pub fn add_partial_signatures<I>(psbt: &mut Psbt, multisig: &MultisigAddress, msgs: I)
+where
+    I: IntoIterator<Item = MsgAnchoringSignature>,
+{
+    for msg in msgs {
+        let validator = msg.validator().0 as usize;
+        if let Some(pub_key) = multisig.common.anchoring_keys.get(validator) {
+            psbt.add_signature(msg.input(), pub_key, msg.signature());
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exonum::blockchain::ServiceContext;
+
+use error::Error as ServiceError;
+use blockchain::consensus_storage::AnchoringConfig;
+
+use super::AnchoringHandler;
+
+#[doc(hidden)]
+impl AnchoringHandler {
+    // The validators have agreed to pause the anchoring, so the node only keeps its lect
+    // up to date. After the resume the following proposal anchors the latest anchoring
+    // height and thus covers all blocks committed during the pause.
+    pub fn handle_paused_state(
+        &mut self,
+        cfg: &AnchoringConfig,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let multisig = self.multisig_address(cfg, context);
+        trace!("Paused state, addr={}", multisig.addr.to_string());
+
+        if let Some(proposal) = self.proposal_tx.take() {
+            info!("Anchoring is paused, drop the proposal txid={}", proposal.txid());
+        }
+        if self.retry.lect_update {
+            self.update_our_lect(&multisig, context)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validator-local policies of signing the anchoring proposals.
+//!
+//! Before the node signs the anchoring proposal, it is checked by the policies from
+//! the [`SigningPolicyConfig`](struct.SigningPolicyConfig.html) of the node configuration
+//! and by the custom policies added by the
+//! [`add_signing_policy`](../struct.AnchoringHandler.html#method.add_signing_policy).
+//! The proposal refused by any of them is not signed by this node, the proposal which
+//! requires the approval is signed once the node operator approves its anchored height by
+//! the private api.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use details::btc;
+use details::btc::transactions::AnchoringTx;
+
+/// Anchoring proposal checked by the signing policies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposalSummary {
+    /// `Txid` of the proposal.
+    pub txid: btc::TxId,
+    /// Height of the anchored block.
+    pub height: u64,
+    /// Value in satoshis which leaves the anchoring multisig, i.e. the outputs to other
+    /// addresses and the fee if it is known.
+    pub amount: u64,
+    /// Fee of the proposal in satoshis if the values of all spent outputs are known.
+    pub fee: Option<u64>,
+    /// Number of the proposal inputs.
+    pub inputs: usize,
+}
+
+impl ProposalSummary {
+    /// Creates the summary of the `proposal` with the given `fee`.
+    pub fn new(proposal: &AnchoringTx, fee: Option<u64>) -> ProposalSummary {
+        ProposalSummary {
+            txid: proposal.id(),
+            height: proposal.payload().block_height.0,
+            amount: proposal.total_amount() - proposal.amount() + fee.unwrap_or(0),
+            fee,
+            inputs: proposal.input.len(),
+        }
+    }
+}
+
+/// Decision of the signing policy about the proposal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyDecision {
+    /// The proposal may be signed.
+    Sign,
+    /// The proposal must not be signed for the given reason.
+    Refuse(String),
+    /// The proposal is signed only after the manual approval, the reason is shown
+    /// to the node operator.
+    RequireApproval(String),
+}
+
+/// Predicate over the anchoring proposal which is checked before the node signs it.
+pub trait SigningPolicy: fmt::Debug + Send {
+    /// Returns the decision about signing of the given `proposal`.
+    fn check(&self, proposal: &ProposalSummary) -> PolicyDecision;
+}
+
+/// Refuses to sign the proposals with the fee above `max_fee` or with the unknown fee.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxFeePolicy {
+    /// The maximum fee in satoshis.
+    pub max_fee: u64,
+}
+
+impl SigningPolicy for MaxFeePolicy {
+    fn check(&self, proposal: &ProposalSummary) -> PolicyDecision {
+        match proposal.fee {
+            Some(fee) if fee <= self.max_fee => PolicyDecision::Sign,
+            Some(fee) => {
+                PolicyDecision::Refuse(format!("Fee {} exceeds the limit {}", fee, self.max_fee))
+            }
+            None => PolicyDecision::Refuse("Fee of the proposal is unknown".to_owned()),
+        }
+    }
+}
+
+/// Hours of the day in UTC during which the proposals are signed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SigningHoursPolicy {
+    /// The first hour of the signing, inclusive.
+    pub from: u8,
+    /// The hour at which the signing stops, exclusive. If it is less than `from`,
+    /// the signing hours pass midnight.
+    pub to: u8,
+}
+
+impl SigningHoursPolicy {
+    /// Returns true if the given hour of the day is within the signing hours.
+    pub fn contains(&self, hour: u8) -> bool {
+        if self.from <= self.to {
+            hour >= self.from && hour < self.to
+        } else {
+            hour >= self.from || hour < self.to
+        }
+    }
+}
+
+impl SigningPolicy for SigningHoursPolicy {
+    fn check(&self, _: &ProposalSummary) -> PolicyDecision {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let hour = (secs / 3600 % 24) as u8;
+        if self.contains(hour) {
+            PolicyDecision::Sign
+        } else {
+            PolicyDecision::Refuse(format!(
+                "Current hour {} is beyond the signing hours [{}, {}) UTC",
+                hour,
+                self.from,
+                self.to
+            ))
+        }
+    }
+}
+
+/// Requires the manual approval of the proposals which move more than `amount` satoshis
+/// out of the anchoring multisig or whose fee is unknown.
+///
+/// The proposals waiting for the approval are listed by the private api endpoint
+/// `GET /{api_prefix}/v1/approvals` and are approved by the
+/// `POST /{api_prefix}/v1/approvals/:height` one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalPolicy {
+    /// The maximum value leaving the anchoring multisig signed without the approval.
+    pub amount: u64,
+}
+
+impl SigningPolicy for ApprovalPolicy {
+    fn check(&self, proposal: &ProposalSummary) -> PolicyDecision {
+        if proposal.fee.is_none() {
+            PolicyDecision::RequireApproval("Fee of the proposal is unknown".to_owned())
+        } else if proposal.amount > self.amount {
+            PolicyDecision::RequireApproval(format!(
+                "Proposal moves {} satoshis, more than {}",
+                proposal.amount,
+                self.amount
+            ))
+        } else {
+            PolicyDecision::Sign
+        }
+    }
+}
+
+/// Signing policies from the node configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SigningPolicyConfig {
+    /// The maximum fee of the signed proposals, see [`MaxFeePolicy`](struct.MaxFeePolicy.html).
+    #[serde(default)]
+    pub max_fee: Option<u64>,
+    /// Hours of the signing, see [`SigningHoursPolicy`](struct.SigningHoursPolicy.html).
+    #[serde(default)]
+    pub signing_hours: Option<SigningHoursPolicy>,
+    /// The maximum amount of the proposals signed without the manual approval,
+    /// see [`ApprovalPolicy`](struct.ApprovalPolicy.html).
+    #[serde(default)]
+    pub approval_amount: Option<u64>,
+}
+
+impl SigningPolicyConfig {
+    /// Returns the policies specified by this configuration.
+    pub fn policies(&self) -> Vec<Box<SigningPolicy>> {
+        let mut policies: Vec<Box<SigningPolicy>> = Vec::new();
+        if let Some(max_fee) = self.max_fee {
+            policies.push(Box::new(MaxFeePolicy { max_fee }));
+        }
+        if let Some(ref signing_hours) = self.signing_hours {
+            policies.push(Box::new(signing_hours.clone()));
+        }
+        if let Some(amount) = self.approval_amount {
+            policies.push(Box::new(ApprovalPolicy { amount }));
+        }
+        policies
+    }
+}
+
+/// Proposal which waits for the manual approval of the node operator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingApproval {
+    /// The proposal.
+    pub proposal: ProposalSummary,
+    /// Reasons of the policies which require the approval.
+    pub reason: String,
+}
+
+/// Returns the combined decision of the `policies`: the refusal of any policy takes
+/// precedence over the required approvals, which are collected from all policies.
+pub fn check_policies<'a, I>(policies: I, proposal: &ProposalSummary) -> PolicyDecision
+where
+    I: IntoIterator<Item = &'a SigningPolicy>,
+{
+    let mut approvals = Vec::new();
+    for policy in policies {
+        match policy.check(proposal) {
+            PolicyDecision::Sign => {}
+            PolicyDecision::Refuse(reason) => return PolicyDecision::Refuse(reason),
+            PolicyDecision::RequireApproval(reason) => approvals.push(reason),
+        }
+    }
+    if approvals.is_empty() {
+        PolicyDecision::Sign
+    } else {
+        PolicyDecision::RequireApproval(approvals.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::crypto::hash;
+
+    use details::btc;
+    use details::btc::HexValueEx;
+    use details::btc::transactions::AnchoringTx;
+
+    use super::{check_policies, ApprovalPolicy, MaxFeePolicy, PolicyDecision, ProposalSummary,
+                SigningHoursPolicy, SigningPolicy, SigningPolicyConfig};
+
+    fn summary(amount: u64, fee: Option<u64>) -> ProposalSummary {
+        ProposalSummary {
+            txid: btc::TxId::from_slice(hash(&[1]).as_ref()).unwrap(),
+            height: 10,
+            amount,
+            fee,
+            inputs: 1,
+        }
+    }
+
+    #[test]
+    fn test_signing_policies() {
+        let max_fee = MaxFeePolicy { max_fee: 1000 };
+        assert_eq!(max_fee.check(&summary(0, Some(1000))), PolicyDecision::Sign);
+        assert!(match max_fee.check(&summary(0, Some(1001))) {
+            PolicyDecision::Refuse(_) => true,
+            _ => false,
+        });
+        assert!(match max_fee.check(&summary(0, None)) {
+            PolicyDecision::Refuse(_) => true,
+            _ => false,
+        });
+
+        let hours = SigningHoursPolicy { from: 9, to: 18 };
+        assert!(hours.contains(9));
+        assert!(!hours.contains(18));
+        assert!(!hours.contains(3));
+        let night = SigningHoursPolicy { from: 22, to: 6 };
+        assert!(night.contains(23));
+        assert!(night.contains(0));
+        assert!(!night.contains(6));
+        assert!(!night.contains(12));
+
+        let approval = ApprovalPolicy { amount: 50_000 };
+        assert_eq!(approval.check(&summary(50_000, Some(100))), PolicyDecision::Sign);
+        assert!(match approval.check(&summary(50_001, Some(100))) {
+            PolicyDecision::RequireApproval(_) => true,
+            _ => false,
+        });
+        assert!(match approval.check(&summary(0, None)) {
+            PolicyDecision::RequireApproval(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_proposal_summary_amount() {
+        // The proposal sends all its funds back to the anchoring multisig.
+        let proposal = AnchoringTx::from_hex(
+            "01000000019aaf09d7e73a5f9ab394f1358bfb3dbde7b15b983d715f\
+             5c98f369a3f0a288a70000000000ffffffff02b80b00000000000017a914f18eb74087f751109cc9052befd417\
+             7a52c9a30a8700000000000000002c6a2a012800000000000000007fab6f66a0f7a747c820cd01fa30d7bdebd2\
+             6b91c6e03f742abac0b3108134d900000000",
+        ).unwrap();
+        assert_eq!(ProposalSummary::new(&proposal, Some(1000)).amount, 1000);
+        assert_eq!(ProposalSummary::new(&proposal, None).amount, 0);
+    }
+
+    #[test]
+    fn test_check_policies() {
+        let cfg = SigningPolicyConfig {
+            max_fee: Some(1000),
+            signing_hours: None,
+            approval_amount: Some(50_000),
+        };
+        let policies = cfg.policies();
+        assert_eq!(policies.len(), 2);
+        let check = |proposal| {
+            check_policies(policies.iter().map(|policy| policy.as_ref()), proposal)
+        };
+        assert_eq!(check(&summary(10_000, Some(100))), PolicyDecision::Sign);
+        assert!(match check(&summary(100_000, Some(100))) {
+            PolicyDecision::RequireApproval(_) => true,
+            _ => false,
+        });
+        // The refusal takes precedence over the approval.
+        assert!(match check(&summary(100_000, Some(2000))) {
+            PolicyDecision::Refuse(_) => true,
+            _ => false,
+        });
+        assert!(SigningPolicyConfig::default().policies().is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use exonum::blockchain::ServiceContext;
+
+use error::Error as ServiceError;
+use details::rpc::{broadcast_rejection, BroadcastRejection, TxInfo};
+use details::btc;
+use details::btc::transactions::{AnchoringTx, BitcoinTx};
+use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::schema::AnchoringSchema;
+
+use super::{AnchoringHandler, LectKind, MultisigAddress};
+use super::alert::AnchoringAlert;
+use super::error::Error as HandlerError;
+use super::event::AnchoringEvent;
+
+#[doc(hidden)]
+impl AnchoringHandler {
+    // None of the unspent outputs of the anchoring address continues the anchoring chain,
+    // so the actual lect has been spent by an unexpected transaction or orphaned by a reorg.
+    pub fn detect_broken_chain(
+        &mut self,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Result<bool, ServiceError> {
+        let lect = self.collect_lects_for_validator(
+            self.anchoring_key(multisig.common, context),
+            multisig.common,
+            context,
+        )?;
+        if let LectKind::Anchoring(lect) = lect {
+            error!(
+                "Anchoring chain is broken, lect is not found in the unspent outputs, txid={}",
+                lect.txid()
+            );
+            if self.broken_lect != Some(lect.id()) {
+                self.emit_event(AnchoringEvent::ChainBroken { lect: lect.id() });
+                self.send_alert(AnchoringAlert::ChainBroken { lect: lect.id() });
+            }
+            self.broken_lect = Some(lect.id());
+            self.proposal_tx = None;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn handle_broken_state(
+        &mut self,
+        cfg: &AnchoringConfig,
+        lect: &AnchoringTx,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let multisig = self.multisig_address(cfg, context);
+        trace!("Broken state, lect={:#?}", lect);
+
+        // Try to finalize the first transaction of the new chain.
+        if let Some(proposal) = self.proposal_tx.clone() {
+            return self.try_finalize_proposal_tx(proposal, &multisig, context);
+        }
+        if !self.retry.lect_update {
+            return Ok(());
+        }
+
+        // The chain may be restored, for example, if the lect has been rebroadcasted.
+        if self.update_our_lect(&multisig, context)?.is_some() {
+            info!("Anchoring chain is restored, lect txid={}", lect.txid());
+            self.broken_lect = None;
+            return Ok(());
+        }
+
+        // Start a new chain from the funding transaction approved by the configuration update.
+        if let Some(funding_tx) = self.avaliable_funding_tx(&multisig)? {
+            let confirmations = self.tx_confirmations(funding_tx.id())?.unwrap_or(0);
+            if confirmations >= cfg.required_funding_confirmations() {
+                info!(
+                    "Starting a new anchoring chain from funding_tx={}, broken lect={}",
+                    funding_tx.txid(),
+                    lect.txid()
+                );
+                return self.try_create_anchoring_tx_chain(&multisig, Some(lect.id()), context);
+            }
+            warn!(
+                "Insufficient confirmations for the funding_tx={}, confirmations={}",
+                funding_tx.txid(),
+                confirmations
+            );
+            return Ok(());
+        }
+
+        let e = HandlerError::BrokenChain { lect: lect.clone().into() };
+        Err(e.into())
+    }
+
+    // Tracks the confirmations of our lect. If the confirmed lect has lost its confirmations
+    // or has been replaced by one of its ancestors, then its block has been orphaned
+    // by a reorg. In the latter case the orphaned lects are re-broadcast on top of the
+    // deepest still unspent one and the restored lect is returned. If the bitcoin node
+    // rejects them, the subsequent anchoring transactions are rebuilt on top of that
+    // ancestor instead. Any detected reorg drops the cached confirmations of the deeply
+    // confirmed transactions.
+    pub fn check_lect_reorg(
+        &mut self,
+        lect: &TxInfo,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Option<BitcoinTx> {
+        let lect_id = lect.body.id();
+        let confirmations = lect.confirmations.unwrap_or(0);
+        let required_confirmations = multisig.common.required_anchor_confirmations();
+        let was_confirmed = match self.lect_confirmations {
+            Some((prev_lect_id, prev_confirmations)) => {
+                prev_lect_id == lect_id && prev_confirmations >= required_confirmations
+            }
+            None => false,
+        };
+        if !was_confirmed && confirmations >= required_confirmations {
+            self.emit_event(AnchoringEvent::AnchorConfirmed {
+                txid: lect_id,
+                confirmations,
+            });
+        }
+        if let Some((prev_lect_id, prev_confirmations)) = self.lect_confirmations {
+            if prev_confirmations > confirmations && prev_lect_id == lect_id {
+                warn!(
+                    "Reorg detected, lect txid={} confirmations decreased from {} to {}",
+                    lect.body.txid(),
+                    prev_confirmations,
+                    confirmations
+                );
+                self.metrics.reorgs += 1;
+                self.invalidate_confirmations();
+            } else if prev_confirmations > 0 && prev_lect_id != lect_id {
+                let (prev_position, position) = {
+                    let schema = AnchoringSchema::new(context.snapshot());
+                    let key = self.anchoring_key(multisig.common, context);
+                    (
+                        schema.find_lect_position(key, &prev_lect_id),
+                        schema.find_lect_position(key, &lect_id),
+                    )
+                };
+                if let (Some(prev_position), Some(position)) = (prev_position, position) {
+                    if position < prev_position {
+                        warn!(
+                            "Reorg detected, lect txid={} with {} confirmations disappeared, \
+                             the deepest unspent lect is txid={}",
+                            prev_lect_id.to_string(),
+                            prev_confirmations,
+                            lect.body.txid()
+                        );
+                        self.metrics.reorgs += 1;
+                        self.invalidate_confirmations();
+                        let restored = self.rebroadcast_orphaned_lects(
+                            position + 1..prev_position + 1,
+                            multisig,
+                            context,
+                        );
+                        if let Some(restored) = restored {
+                            self.lect_confirmations = Some((restored.id(), 0));
+                            self.metrics.lect_confirmations = Some(0);
+                            return Some(restored);
+                        }
+                        info!("Rolling back to the lect txid={}", lect.body.txid());
+                        self.proposal_tx = None;
+                    }
+                }
+            }
+        }
+        self.lect_confirmations = Some((lect_id, confirmations));
+        self.metrics.lect_confirmations = Some(confirmations);
+        self.cache_confirmations(lect_id, confirmations);
+        self.check_unconfirmed_lect(lect_id, confirmations);
+        None
+    }
+
+    // Sends the orphaned lects at the given positions to the bitcoin node in the chain
+    // order and returns the last one, or `None` if any of them is rejected.
+    fn rebroadcast_orphaned_lects(
+        &self,
+        positions: Range<u64>,
+        multisig: &MultisigAddress,
+        context: &ServiceContext,
+    ) -> Option<BitcoinTx> {
+        let orphaned = {
+            let schema = AnchoringSchema::new(context.snapshot());
+            let lects = schema.lects(self.anchoring_key(multisig.common, context));
+            positions
+                .map(|position| lects.get(position).map(|content| content.tx()))
+                .collect::<Option<Vec<_>>>()
+        };
+        let orphaned = match orphaned {
+            Some(orphaned) => orphaned,
+            None => return None,
+        };
+        for tx in &orphaned {
+            info!("Re-broadcast orphaned lect txid={}", tx.txid());
+            if let Err(e) = self.client().send_transaction(tx.clone()) {
+                if broadcast_rejection(&e) != Some(BroadcastRejection::AlreadyInChain) {
+                    warn!("Unable to re-broadcast lect txid={}, error={}", tx.txid(), e);
+                    return None;
+                }
+            }
+        }
+        orphaned.last().cloned()
+    }
+
+    // Alerts each `unconfirmed_lect_timeout` seconds by the local clock of the node while
+    // the lect has no confirmations.
+    fn check_unconfirmed_lect(&mut self, lect_id: btc::TxId, confirmations: u64) {
+        let timeout = match self.node.alerts.unconfirmed_lect_timeout {
+            Some(timeout) if confirmations == 0 => Duration::from_secs(timeout),
+            _ => {
+                self.unconfirmed_lect = None;
+                return;
+            }
+        };
+        let now = Instant::now();
+        let since = match self.unconfirmed_lect {
+            Some((txid, since)) if txid == lect_id => since,
+            _ => {
+                self.unconfirmed_lect = Some((lect_id, now));
+                return;
+            }
+        };
+        let elapsed = now.duration_since(since);
+        if elapsed < timeout {
+            return;
+        }
+        self.unconfirmed_lect = Some((lect_id, now));
+        self.send_alert(AnchoringAlert::LectUnconfirmed {
+            txid: lect_id,
+            seconds: elapsed.as_secs(),
+        });
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retry policy for the transient bitcoin backend failures.
+
+use std::cmp;
+
+use exonum::helpers::Height;
+
+/// Maximal delay between the retries in blocks.
+pub const MAX_RETRY_DELAY: u64 = 64;
+
+/// State of the retries after the transient bitcoin backend failures.
+///
+/// After each successive failure the handler skips exponentially growing number of blocks
+/// up to the [`MAX_RETRY_DELAY`](constant.MAX_RETRY_DELAY.html). The lect update missed
+/// because of the failure is performed on the next attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryState {
+    /// Number of the successive transient failures.
+    pub failures: u32,
+    /// Height from which the next attempt can be made.
+    pub next_attempt: Height,
+    /// Lect update is requested and not performed yet.
+    pub lect_update: bool,
+}
+
+impl Default for RetryState {
+    fn default() -> RetryState {
+        RetryState {
+            failures: 0,
+            next_attempt: Height::zero(),
+            lect_update: false,
+        }
+    }
+}
+
+impl RetryState {
+    /// Returns `true` if the handler can make an attempt at the given `height`.
+    pub fn is_ready(&self, height: Height) -> bool {
+        height >= self.next_attempt
+    }
+
+    /// Records the transient failure at the given `height` and returns the delay
+    /// before the next attempt.
+    pub fn fail(&mut self, height: Height) -> u64 {
+        let delay = if self.failures >= 6 {
+            MAX_RETRY_DELAY
+        } else {
+            cmp::min(1 << self.failures, MAX_RETRY_DELAY)
+        };
+        self.failures += 1;
+        self.next_attempt = Height(height.0 + delay);
+        delay
+    }
+
+    /// Resets the state after the successful attempt or the fatal error,
+    /// which can not be fixed by the retries.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.lect_update = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::helpers::Height;
+
+    use super::{RetryState, MAX_RETRY_DELAY};
+
+    #[test]
+    fn test_retry_state_backoff() {
+        let mut retry = RetryState::default();
+        assert!(retry.is_ready(Height(0)));
+
+        let delays = (0..9).map(|_| retry.fail(Height(10))).collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 64, 64, 64]);
+        assert!(!retry.is_ready(Height(10 + MAX_RETRY_DELAY - 1)));
+        assert!(retry.is_ready(Height(10 + MAX_RETRY_DELAY)));
+
+        retry.lect_update = true;
+        retry.reset();
+        assert_eq!(retry.failures, 0);
+        assert!(!retry.lect_update);
+        assert_eq!(retry.fail(Height(80)), 1);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State of the anchoring handler shared with the api.
+//!
+//! The handler is locked for the whole commit handling, which includes the requests
+//! to the bitcoin backend. Instead of the handler the api uses the snapshot of its state
+//! published after each commit, the bitcoin backend locked separately for the replacement
+//! only, and the queue of the requests applied by the following commit. So the api calls
+//! never wait for a slow bitcoin backend used by the commit handling.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Mutex, RwLock};
+
+use exonum::helpers::ValidatorId;
+
+use details::btc;
+use details::btc::hd::HdKeyConfig;
+use details::rpc::BitcoinRelay;
+use local_storage::AnchoringNodeConfig;
+
+use super::event::AnchoringEventEntry;
+use super::metrics::AnchoringMetrics;
+use super::policy::PendingApproval;
+use super::state::{AnchoringPhase, HandlerState};
+
+/// Copy of the handler state which is read by the api.
+#[derive(Debug, Clone, Default)]
+pub struct HandlerSnapshot {
+    /// Handler metrics.
+    pub metrics: AnchoringMetrics,
+    /// State of the handler state machine.
+    pub state: HandlerState,
+    /// Confirmations of the latest known lect.
+    pub lect_confirmations: Option<(btc::TxId, u64)>,
+    /// Errors since the latest successful commit.
+    pub pending_errors: Vec<String>,
+    /// Latest entries of the events log.
+    pub events: VecDeque<AnchoringEventEntry>,
+    /// Extended key of the anchoring keys from the node configuration.
+    pub hd_key: Option<HdKeyConfig>,
+    /// Addresses imported to the wallet of the bitcoin backend.
+    pub watched_addresses: Vec<String>,
+    /// Proposals which wait for the approval of the node operator.
+    pub pending_approvals: Vec<PendingApproval>,
+}
+
+impl HandlerSnapshot {
+    /// Returns the phase of the anchoring process on this node.
+    pub fn phase(&self) -> AnchoringPhase {
+        self.state.phase
+    }
+
+    /// Returns the events log entries starting from the event with the given `start` id.
+    pub fn events_since(&self, start: u64, limit: usize) -> Vec<AnchoringEventEntry> {
+        self.events
+            .iter()
+            .filter(|entry| entry.id >= start)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Request to the handler which is applied by the following commit.
+#[derive(Debug)]
+pub enum HandlerRequest {
+    /// Notice of the funding transaction.
+    FundingNotice(btc::TxId),
+    /// Vote to sweep the funds to the address, `None` revokes the vote.
+    SweepVote(Option<btc::Address>),
+    /// Vote to pause the anchoring, `None` revokes the vote.
+    PauseVote(Option<String>),
+    /// Request to anchor the block regardless of the anchoring frequency.
+    AnchoringTrigger,
+    /// Vote to replace the anchoring key of the validator, `None` revokes the vote.
+    KeyRotationVote(ValidatorId, Option<btc::PublicKey>),
+    /// Replacement of the node configuration and the bitcoin backend.
+    NodeConfig(AnchoringNodeConfig, Option<Box<BitcoinRelay>>),
+    /// Approval of the proposals for the given anchored height which wait for it.
+    ApproveProposal(u64),
+}
+
+/// Part of the handler which is available without locking the handler itself.
+#[derive(Debug, Default)]
+pub struct SharedHandler {
+    snapshot: RwLock<HandlerSnapshot>,
+    client: RwLock<Option<Arc<BitcoinRelay>>>,
+    requests: Mutex<Vec<HandlerRequest>>,
+}
+
+impl SharedHandler {
+    /// Returns the handler state published by the latest commit.
+    pub fn snapshot(&self) -> HandlerSnapshot {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// Returns the result of `f` for the handler state published by the latest commit
+    /// without copying the state.
+    pub fn with_snapshot<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&HandlerSnapshot) -> T,
+    {
+        f(&self.snapshot.read().unwrap())
+    }
+
+    /// Returns the actual bitcoin backend of the handler.
+    pub fn client(&self) -> Option<Arc<BitcoinRelay>> {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Queues the request which is applied by the following commit.
+    pub fn push_request(&self, request: HandlerRequest) {
+        self.requests.lock().unwrap().push(request);
+    }
+
+    #[doc(hidden)]
+    pub fn publish(&self, snapshot: HandlerSnapshot) {
+        *self.snapshot.write().unwrap() = snapshot;
+    }
+
+    #[doc(hidden)]
+    pub fn set_client(&self, client: Option<Arc<BitcoinRelay>>) {
+        *self.client.write().unwrap() = client;
+    }
+
+    #[doc(hidden)]
+    pub fn take_requests(&self) -> Vec<HandlerRequest> {
+        mem::replace(&mut *self.requests.lock().unwrap(), Vec::new())
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured logging of the anchoring rounds.
+//!
+//! The records of the anchoring rounds are logged with the
+//! [`LOG_TARGET`](constant.LOG_TARGET.html) target, each of them is a single line JSON object
+//! which contains the name of the event, its own fields and the fields of the span of
+//! the current commit, so the round can be followed across the logs of the different nodes
+//! by its `round` or `txid`.
+
+use log::LogLevel;
+use serde_json::{self, Value};
+
+use details::btc;
+
+use super::state::AnchoringPhase;
+
+/// Target of the structured log records.
+pub const LOG_TARGET: &str = "exonum_btc_anchoring::round";
+
+/// Context of the commit handled by the anchoring handler.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoundSpan {
+    /// Height of the handled block.
+    pub commit_height: u64,
+    /// Identifier of this validator, it is `None` for the auditor nodes.
+    pub validator: Option<u16>,
+    /// Phase of the anchoring process at the commit start.
+    pub phase: AnchoringPhase,
+    /// Height of the block anchored by the round in progress.
+    pub round: Option<u64>,
+    /// `Txid` of the proposal of the round in progress.
+    pub txid: Option<btc::TxId>,
+}
+
+impl Default for RoundSpan {
+    fn default() -> RoundSpan {
+        RoundSpan {
+            commit_height: 0,
+            validator: None,
+            phase: AnchoringPhase::Proposing,
+            round: None,
+            txid: None,
+        }
+    }
+}
+
+impl RoundSpan {
+    /// Logs the `event` with the given `fields`, the fields of the event take precedence
+    /// over the fields of the span.
+    pub fn record(&self, level: LogLevel, event: &str, fields: Value) {
+        if log_enabled!(target: LOG_TARGET, level) {
+            log!(target: LOG_TARGET, level, "{}", self.to_json(event, fields));
+        }
+    }
+
+    /// Returns the JSON object of the log record.
+    pub fn to_json(&self, event: &str, fields: Value) -> Value {
+        let mut record = serde_json::to_value(self).unwrap_or_else(|_| json!({}));
+        if let Value::Object(ref mut record) = record {
+            record.insert("event".to_owned(), Value::String(event.to_owned()));
+            if let Value::Object(fields) = fields {
+                record.extend(fields);
+            }
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::crypto::hash;
+
+    use details::btc;
+    use handler::state::AnchoringPhase;
+
+    use super::RoundSpan;
+
+    #[test]
+    fn test_round_span_record() {
+        let txid = btc::TxId::from_slice(hash(&[1]).as_ref()).unwrap();
+        let span = RoundSpan {
+            commit_height: 11,
+            validator: Some(2),
+            phase: AnchoringPhase::CollectingSignatures,
+            round: Some(10),
+            txid: Some(txid),
+        };
+        let record = span.to_json("commit_started", json!({}));
+        assert_eq!(
+            record,
+            json!({
+                "event": "commit_started",
+                "commit_height": 11,
+                "validator": 2,
+                "phase": "collecting_signatures",
+                "round": 10,
+                "txid": txid,
+            })
+        );
+
+        let record = span.to_json("anchor_broadcast", json!({ "txid": null, "fee": 1000 }));
+        assert_eq!(record["event"], "anchor_broadcast");
+        assert_eq!(record["txid"], json!(null));
+        assert_eq!(record["fee"], 1000);
+        assert_eq!(record["round"], 10);
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State machine of the anchoring handler.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_json;
+
+use details::btc;
+use details::btc::transactions::AnchoringTx;
+
+/// The maximum number of the deeply confirmed transactions kept in the confirmations cache.
+pub const MAX_CONFIRMED_TXS: usize = 64;
+
+/// Phase of the anchoring process on this node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnchoringPhase {
+    /// The node has no anchoring keys and only audits the anchoring chain.
+    Auditing,
+    /// The funding transaction of the anchoring chain waits for enough confirmations.
+    WaitingForFunding,
+    /// The node waits for the next anchoring height to propose the anchoring transaction.
+    Proposing,
+    /// The proposal is signed by the node, the signatures of the other validators
+    /// are collected.
+    CollectingSignatures,
+    /// The finalized anchoring transaction is sent to the bitcoin network,
+    /// but it is not the lect of the node yet.
+    Broadcasting,
+    /// The anchoring chain is transferred to the address of the following configuration.
+    Transferring,
+    /// The transfer transaction waits for enough confirmations, the anchoring continues
+    /// on the address of the following configuration once it gets them.
+    WaitingForTransfer,
+    /// The anchoring chain is restarted after the lect has been lost.
+    Recovering,
+    /// All anchoring funds are swept to the refund address agreed by the validators.
+    Sweeping,
+    /// The anchoring is paused by the majority of the validators.
+    Paused,
+    /// The final anchoring transaction has burned the rest of the funds, the anchoring
+    /// continues once the configuration has a new funding transaction.
+    WalletEmpty,
+}
+
+/// State of the anchoring handler, it is persisted on the node if the `state_path`
+/// is specified in the node configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandlerState {
+    /// Current phase of the anchoring process.
+    pub phase: AnchoringPhase,
+    /// Height of the block after which the handler has entered the current phase.
+    pub since: u64,
+    /// `Txid` of the anchoring transaction sent in the `Broadcasting` phase.
+    pub broadcasted: Option<btc::TxId>,
+    /// Anchoring round in progress, it is resumed after the node restart.
+    #[serde(default)]
+    pub round: Option<RoundState>,
+    /// Deeply confirmed transactions whose confirmations are not requested
+    /// from the bitcoin backend anymore.
+    #[serde(default)]
+    pub confirmed_txs: Vec<ConfirmedTx>,
+}
+
+/// Transaction which has reached the `confirmations_cache_depth` from the node configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfirmedTx {
+    /// `Txid` of the transaction.
+    pub txid: btc::TxId,
+    /// Number of the confirmations at the moment the transaction has reached the depth.
+    pub confirmations: u64,
+}
+
+/// Anchoring round signed by the node but not finalized yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundState {
+    /// Anchoring transaction proposal.
+    pub proposal: AnchoringTx,
+    /// Hex encoded signature hashes of the proposal inputs.
+    pub sighashes: BTreeMap<u32, String>,
+    /// Hex encoded signatures of the proposal inputs made by the node.
+    pub signatures: BTreeMap<u32, String>,
+    /// Height of the block after which the round has been started.
+    #[serde(default)]
+    pub height: u64,
+}
+
+impl Default for HandlerState {
+    fn default() -> HandlerState {
+        HandlerState {
+            phase: AnchoringPhase::WaitingForFunding,
+            since: 0,
+            broadcasted: None,
+            round: None,
+            confirmed_txs: Vec::new(),
+        }
+    }
+}
+
+impl HandlerState {
+    /// Moves the state machine to the given `phase` after the block with the given `height`.
+    /// Returns `true` if the phase has been changed.
+    pub fn enter(&mut self, phase: AnchoringPhase, height: u64) -> bool {
+        if self.phase == phase {
+            return false;
+        }
+        self.phase = phase;
+        self.since = height;
+        if phase != AnchoringPhase::Broadcasting {
+            self.broadcasted = None;
+        }
+        true
+    }
+
+    /// Returns the cached confirmations of the deeply confirmed transaction, the actual number
+    /// of its confirmations is not less than the returned one unless a reorg has happened.
+    pub fn cached_confirmations(&self, txid: &btc::TxId) -> Option<u64> {
+        self.confirmed_txs
+            .iter()
+            .find(|tx| &tx.txid == txid)
+            .map(|tx| tx.confirmations)
+    }
+
+    /// Adds the deeply confirmed transaction to the confirmations cache,
+    /// the oldest transactions are evicted once `MAX_CONFIRMED_TXS` is reached.
+    pub fn cache_confirmations(&mut self, txid: btc::TxId, confirmations: u64) {
+        if self.cached_confirmations(&txid).is_some() {
+            return;
+        }
+        if self.confirmed_txs.len() >= MAX_CONFIRMED_TXS {
+            self.confirmed_txs.remove(0);
+        }
+        self.confirmed_txs.push(ConfirmedTx {
+            txid,
+            confirmations,
+        });
+    }
+
+    /// Loads the state from the file at the given `path`, returns `None` if there is no file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<HandlerState>> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        serde_json::from_str(&content).map(Some).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+        })
+    }
+
+    /// Saves the state to the file at the given `path`.
+    ///
+    /// The state is written to the temporary file which then replaces the previous one,
+    /// so the node crash does not leave the partially written state.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            let content = serde_json::to_string_pretty(self).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+            })?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use exonum::crypto::hash;
+    use exonum::encoding::serialize::FromHex;
+
+    use details::btc;
+    use details::btc::transactions::AnchoringTx;
+
+    use super::{AnchoringPhase, HandlerState, RoundState, MAX_CONFIRMED_TXS};
+
+    #[test]
+    fn test_handler_state_transitions() {
+        let mut state = HandlerState::default();
+        assert!(state.enter(AnchoringPhase::Proposing, 10));
+        assert!(!state.enter(AnchoringPhase::Proposing, 11));
+        assert_eq!(state.since, 10);
+
+        assert!(state.enter(AnchoringPhase::Broadcasting, 12));
+        state.broadcasted = btc::TxId::from_slice(hash(&[1]).as_ref());
+        assert!(!state.enter(AnchoringPhase::Broadcasting, 13));
+        assert!(state.broadcasted.is_some());
+        assert!(state.enter(AnchoringPhase::Proposing, 14));
+        assert_eq!(state.broadcasted, None);
+    }
+
+    #[test]
+    fn test_handler_state_persistence() {
+        let dir = TempDir::new("btc_anchoring_state").unwrap();
+        let path = dir.path().join("state.json");
+        assert_eq!(HandlerState::load(&path).unwrap(), None);
+
+        let state = HandlerState {
+            phase: AnchoringPhase::Broadcasting,
+            since: 20,
+            broadcasted: btc::TxId::from_slice(hash(&[1]).as_ref()),
+            round: None,
+            confirmed_txs: Vec::new(),
+        };
+        state.save(&path).unwrap();
+        assert_eq!(HandlerState::load(&path).unwrap(), Some(state));
+    }
+
+    #[test]
+    fn test_handler_state_round_persistence() {
+        let dir = TempDir::new("btc_anchoring_state").unwrap();
+        let path = dir.path().join("state.json");
+
+        let proposal = AnchoringTx::from_hex(
+            "01000000019aaf09d7e73a5f9ab394f1358bfb3dbde7b15b983d715f\
+             5c98f369a3f0a288a70000000000ffffffff02b80b00000000000017a914f18eb74087f751109cc9052befd417\
+             7a52c9a30a8700000000000000002c6a2a012800000000000000007fab6f66a0f7a747c820cd01fa30d7bdebd2\
+             6b91c6e03f742abac0b3108134d900000000",
+        ).unwrap();
+        let mut state = HandlerState::default();
+        state.enter(AnchoringPhase::CollectingSignatures, 40);
+        state.round = Some(RoundState {
+            proposal,
+            sighashes: vec![(0, "00".repeat(32))].into_iter().collect(),
+            signatures: vec![(0, "3044".to_string())].into_iter().collect(),
+            height: 10,
+        });
+        state.save(&path).unwrap();
+        assert_eq!(HandlerState::load(&path).unwrap(), Some(state));
+    }
+
+    #[test]
+    fn test_handler_state_without_round() {
+        // The state saved by the previous versions has no round.
+        let state: HandlerState =
+            ::serde_json::from_str(r#"{"phase": "proposing", "since": 5, "broadcasted": null}"#)
+                .unwrap();
+        assert_eq!(state.phase, AnchoringPhase::Proposing);
+        assert_eq!(state.round, None);
+        assert!(state.confirmed_txs.is_empty());
+    }
+
+    #[test]
+    fn test_handler_state_confirmations_cache() {
+        let txid = |i: u8| btc::TxId::from_slice(hash(&[i]).as_ref()).unwrap();
+        let mut state = HandlerState::default();
+        assert_eq!(state.cached_confirmations(&txid(0)), None);
+
+        state.cache_confirmations(txid(0), 100);
+        state.cache_confirmations(txid(0), 200);
+        assert_eq!(state.cached_confirmations(&txid(0)), Some(100));
+
+        for i in 1..MAX_CONFIRMED_TXS as u8 + 1 {
+            state.cache_confirmations(txid(i), 100);
+        }
+        assert_eq!(state.confirmed_txs.len(), MAX_CONFIRMED_TXS);
+        assert_eq!(state.cached_confirmations(&txid(0)), None);
+        assert_eq!(state.cached_confirmations(&txid(1)), Some(100));
+    }
+}
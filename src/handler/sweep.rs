@@ -0,0 +1,130 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use exonum::blockchain::ServiceContext;
+
+use error::Error as ServiceError;
+use details::btc;
+use details::btc::transactions::{AnchoringTx, TransactionBuilder};
+use blockchain::consensus_storage::AnchoringConfig;
+
+use super::{AnchoringHandler, LectKind, MultisigAddress};
+use super::anchoring::{max_proposal_inputs, proposal_fee};
+
+#[doc(hidden)]
+impl AnchoringHandler {
+    // The validators have agreed to sweep all funds of the anchoring address to the refund
+    // address, so the anchoring chain is finished by the transaction to this address.
+    pub fn handle_sweeping_state(
+        &mut self,
+        cfg: &AnchoringConfig,
+        refund_addr: &btc::Address,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let multisig = self.multisig_address(cfg, context);
+        trace!(
+            "Sweeping state, addr={}, refund_addr={}",
+            multisig.addr.to_string(),
+            refund_addr.to_string()
+        );
+
+        if self.retry.lect_update {
+            self.update_our_lect(&multisig, context)?;
+        }
+        if let Some(proposal) = self.proposal_tx.clone() {
+            return self.try_finalize_proposal_tx(proposal, &multisig, context);
+        }
+
+        let lect = match self.collect_lects_for_validator(
+            self.anchoring_key(multisig.common, context),
+            multisig.common,
+            context,
+        )? {
+            // The final lect has no funds to sweep.
+            LectKind::Anchoring(ref lect) if lect.is_final() => None,
+            LectKind::Anchoring(lect) => {
+                if lect.output_address(multisig.common.network) == *refund_addr {
+                    // The sweep transaction with the maximum number of inputs
+                    // may leave some funds to the following one.
+                    let is_limited = lect.input.len() >= max_proposal_inputs(multisig);
+                    if !is_limited || self.avaliable_funds(multisig, context)?.is_empty() {
+                        trace!("Anchoring funds are swept by txid={}", lect.txid());
+                        return Ok(());
+                    }
+                    None
+                } else {
+                    Some(lect)
+                }
+            }
+            LectKind::Funding(_) => None,
+            LectKind::None => {
+                warn!("Unable to reach consensus in the lect");
+                return Ok(());
+            }
+        };
+        self.create_sweep_tx(lect.as_ref(), &multisig, refund_addr, context)
+    }
+
+    // Creates the transaction that spends the lect together with all available funds
+    // of the anchoring address and sends them to the refund address.
+    fn create_sweep_tx(
+        &mut self,
+        lect: Option<&AnchoringTx>,
+        multisig: &MultisigAddress,
+        refund_addr: &btc::Address,
+        context: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let mut funds = self.avaliable_funds(multisig, context)?.into_iter();
+        let builder = match lect {
+            Some(lect) => TransactionBuilder::with_prev_tx(lect, 0),
+            None => {
+                match funds.next() {
+                    Some(first) => TransactionBuilder::with_prev_tx(&first.tx, first.out),
+                    None => {
+                        warn!("There are no funds to sweep");
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        // The rest of the funds is swept by the following transactions.
+        let max_funds = max_proposal_inputs(multisig).saturating_sub(builder.inputs_count());
+        let mut builder = funds.take(max_funds).fold(builder, |builder, candidate| {
+            builder.add_funds(&candidate.tx, candidate.out)
+        });
+
+        let cfg = multisig.common;
+        let height = cfg.latest_anchoring_height(context.height());
+        let (hash, state_hash) = self.anchored_block_hashes(cfg, height, context);
+        let commitments = self.anchored_commitments(lect, height, context);
+        let fee_rate = self.proposal_fee_rate(multisig, context)?;
+        let fee = proposal_fee(multisig, fee_rate, builder.inputs_count());
+        builder = builder
+            .fee(fee)
+            .payload(height, hash)
+            .state_hash(state_hash)
+            .commitments(commitments)
+            .chain_id(cfg.chain_id)
+            .send_to(refund_addr.clone());
+        let proposal = builder.into_transaction()?;
+
+        info!(
+            "Sweeping anchoring funds to refund_addr={}, amount={}, inputs={}",
+            refund_addr.to_string(),
+            proposal.amount(),
+            proposal.input.len()
+        );
+        self.sign_proposal_tx(proposal, multisig, context)
+    }
+}
@@ -12,12 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bitcoin::util::base58::ToBase58;
-
 use exonum::blockchain::ServiceContext;
 
 use error::Error as ServiceError;
-use details::btc::transactions::BitcoinTx;
+use details::btc::transactions::{AnchoringTx, BitcoinTx, FundingTx, TransactionBuilder, TxKind};
+use details::rpc::{broadcast_rejection, BroadcastRejection};
 use blockchain::consensus_storage::AnchoringConfig;
 use blockchain::schema::AnchoringSchema;
 
@@ -32,18 +31,18 @@ impl AnchoringHandler {
         state: &ServiceContext,
     ) -> Result<(), ServiceError> {
         let multisig: MultisigAddress = {
-            let mut multisig = self.multisig_address(from);
+            let mut multisig = self.multisig_address(from, state);
             multisig.addr = to.redeem_script().1;
             multisig
         };
         trace!(
             "Transition state, addr={}, following_config={:#?}",
-            multisig.addr.to_base58check(),
+            multisig.addr.to_string(),
             to
         );
 
         // Similar we update lect each n blocks
-        if state.height().0 % self.node.check_lect_frequency == 0 {
+        if self.retry.lect_update {
             // First of all we try to update our lect and actual configuration
             self.update_our_lect(&multisig, state)?;
         }
@@ -57,16 +56,20 @@ impl AnchoringHandler {
                 self.anchoring_key(multisig.common, state),
                 multisig.common,
                 state,
-            ) {
+            )? {
+                LectKind::Anchoring(ref lect) if lect.is_final() => {
+                    warn!(
+                        "Unable to transfer the funds of the final lect, txid={}",
+                        lect.txid()
+                    );
+                }
                 LectKind::Anchoring(lect) => {
                     if lect.output_address(multisig.common.network) == multisig.addr {
-                        return Ok(());
+                        return self.retry_transfer_tx(&lect, &multisig, state);
                     }
                     // check that we have enougth confirmations
-                    let confirmations = self.client()
-                        .get_transaction_confirmations(lect.id())?
-                        .unwrap_or_else(|| 0);
-                    if confirmations >= multisig.common.utxo_confirmations {
+                    let confirmations = self.tx_confirmations(lect.id())?.unwrap_or(0);
+                    if confirmations >= multisig.common.required_anchor_confirmations() {
                         let height = multisig.common.latest_anchoring_height(state.height());
                         self.create_proposal_tx(&lect, &multisig, height, state)?;
                     } else {
@@ -78,7 +81,9 @@ impl AnchoringHandler {
                         );
                     }
                 }
-                LectKind::Funding(_) => panic!("We must not to change genesis configuration!"),
+                LectKind::Funding(funding_tx) => {
+                    self.create_transition_from_funding_tx(&funding_tx, &multisig, state)?;
+                }
                 LectKind::None => {
                     warn!("Unable to reach consensus in a lect");
                 }
@@ -87,6 +92,107 @@ impl AnchoringHandler {
         Ok(())
     }
 
+    // Replaces the transfer transaction without confirmations for longer than
+    // the `transfer_timeout` by the one with the bumped fee. If the fee cannot be bumped,
+    // the transfer is restarted from the previous lect and the actual unspent outputs.
+    fn retry_transfer_tx(
+        &mut self,
+        lect: &AnchoringTx,
+        multisig: &MultisigAddress,
+        state: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let confirmations = self.tx_confirmations(lect.id())?;
+        let anchored_height = lect.payload().block_height;
+        if !multisig.common.is_transfer_timed_out(
+            anchored_height,
+            confirmations,
+            state.height(),
+        )
+        {
+            return Ok(());
+        }
+        warn!(
+            "Transfer transaction txid={} is not confirmed since height={}, retry it",
+            lect.txid(),
+            anchored_height
+        );
+
+        let height = multisig.common.latest_anchoring_height(state.height());
+        if self.lect_needs_fee_bump(lect, multisig, state)? {
+            if let Some(proposal) = self.build_replacement_tx(lect, multisig, height, state)? {
+                return self.sign_proposal_tx(proposal, multisig, state);
+            }
+        }
+        let prev_tx = AnchoringSchema::new(state.snapshot()).known_txs().get(
+            &lect.prev_hash(),
+        );
+        match prev_tx.map(TxKind::from) {
+            Some(TxKind::Anchoring(prev_lect)) => {
+                self.create_proposal_tx(&prev_lect, multisig, height, state)
+            }
+            Some(TxKind::FundingTx(funding_tx)) => {
+                self.create_transition_from_funding_tx(&funding_tx, multisig, state)
+            }
+            _ => {
+                warn!(
+                    "Unable to find the transaction spent by the transfer txid={}",
+                    lect.txid()
+                );
+                Ok(())
+            }
+        }
+    }
+
+    // The anchoring chain has not been started yet, so we transfer funds
+    // directly from the funding tx to the following address.
+    fn create_transition_from_funding_tx(
+        &mut self,
+        funding_tx: &FundingTx,
+        multisig: &MultisigAddress,
+        state: &ServiceContext,
+    ) -> Result<(), ServiceError> {
+        let out = match funding_tx.find_out(&multisig.common.redeem_script().1) {
+            Some(out) => out,
+            None => {
+                warn!(
+                    "Funding tx has no output to the actual address, tx={:#?}",
+                    funding_tx
+                );
+                return Ok(());
+            }
+        };
+
+        let confirmations = self.tx_confirmations(funding_tx.id())?.unwrap_or(0);
+        if confirmations < multisig.common.required_funding_confirmations() {
+            warn!(
+                "Insufficient confirmations for create transition transaction, \
+                 tx={:#?}, confirmations={}",
+                funding_tx,
+                confirmations
+            );
+            return Ok(());
+        }
+
+        let height = multisig.common.latest_anchoring_height(state.height());
+        let (hash, state_hash) = self.anchored_block_hashes(multisig.common, height, state);
+        let commitments = self.anchored_commitments(None, height, state);
+        let proposal = TransactionBuilder::with_prev_tx(funding_tx, out)
+            .fee(multisig.common.bounded_fee(multisig.common.fee))
+            .payload(height, hash)
+            .state_hash(state_hash)
+            .commitments(commitments)
+            .chain_id(multisig.common.chain_id)
+            .send_to(multisig.addr.clone())
+            .into_transaction()?;
+
+        trace!(
+            "transition_proposal={:#?}, to={}",
+            proposal,
+            multisig.addr.to_string()
+        );
+        self.sign_proposal_tx(proposal, multisig, state)
+    }
+
     pub fn handle_waiting_state(
         &mut self,
         lect: BitcoinTx,
@@ -99,7 +205,13 @@ impl AnchoringHandler {
         );
         if confirmations.is_none() {
             trace!("Resend transition transaction, txid={}", lect.txid());
-            self.client().send_transaction(lect)?;
+            let txid = lect.id();
+            if let Err(e) = self.client().send_transaction(lect) {
+                if broadcast_rejection(&e) != Some(BroadcastRejection::AlreadyInChain) {
+                    return Err(ServiceError::from(e).with_txid(txid));
+                }
+                trace!("Transition transaction is already mined, txid={}", txid.to_string());
+            }
         }
         Ok(())
     }
@@ -110,16 +222,16 @@ impl AnchoringHandler {
         actual_cfg: &AnchoringConfig,
         state: &ServiceContext,
     ) -> Result<(), ServiceError> {
-        let multisig: MultisigAddress = self.multisig_address(actual_cfg);
+        let multisig: MultisigAddress = self.multisig_address(actual_cfg, state);
 
-        if state.height().0 % self.node.check_lect_frequency == 0 {
+        if self.retry.lect_update {
             // First of all we try to update our lect and actual configuration
             self.update_our_lect(&multisig, state)?;
         }
 
         trace!(
             "Starting a new tx chain to addr={} from scratch",
-            multisig.addr.to_base58check()
+            multisig.addr.to_string()
         );
 
         let lect_txid = {
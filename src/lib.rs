@@ -59,20 +59,32 @@
 
 #![deny(missing_docs, missing_debug_implementations)]
 
+extern crate base64;
 extern crate bitcoin;
 extern crate byteorder;
 #[macro_use]
 extern crate derive_error;
 extern crate exonum_bitcoinrpc as bitcoinrpc;
+pub extern crate exonum_btc_anchoring_light as light;
+extern crate hyper;
+extern crate hyper_native_tls;
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
+extern crate native_tls;
 extern crate secp256k1;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate tempdir;
 extern crate toml;
+#[cfg(feature = "zmq")]
+extern crate zmq;
+#[cfg(feature = "testkit")]
+extern crate exonum_testkit;
 
 #[macro_use]
 extern crate exonum;
@@ -98,10 +110,25 @@ pub mod error;
 pub mod api;
 pub mod observer;
 pub mod cmd;
+pub mod tool;
+pub mod certificate;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 
-pub use details::btc::{gen_btc_keypair, gen_btc_keypair_with_rng, Network as BitcoinNetwork};
+pub use details::btc::{gen_btc_keypair, gen_btc_keypair_with_rng, Chain as BitcoinChain,
+                       Network as BitcoinNetwork, ScriptType};
 pub use details::rpc::{RpcClient, AnchoringRpcConfig, BitcoinRelay};
+pub use details::electrum::{ElectrumClient, ElectrumConfig};
+pub use details::esplora::{EsploraClient, EsploraConfig};
+pub use details::background::BackgroundRelay;
+pub use details::failover::FailoverRelay;
+pub use details::dry_run::DryRunRelay;
+pub use details::notifications::{NotifiedRelay, ZmqConfig};
+pub use details::signer::{ExternalSigner, ExternalSignerConfig, KeySigner, Signer};
+pub use details::btc::hd::HdKeyConfig;
 pub use blockchain::consensus_storage::AnchoringConfig;
+pub use blockchain::verification::{verify_anchoring_chain, verify_anchoring_chain_by_relay,
+                                   verify_anchoring_txs, AnchoringChainReport};
 pub use local_storage::AnchoringNodeConfig;
 pub use service::{gen_anchoring_testnet_config, gen_anchoring_testnet_config_with_rng,
                   AnchoringService, ANCHORING_SERVICE_ID, ANCHORING_SERVICE_NAME};
@@ -15,8 +15,16 @@
 use std::default::Default;
 use std::collections::BTreeMap;
 
-use details::rpc::AnchoringRpcConfig;
+use details::rpc::{AnchoringRpcConfig, BitcoinRelay, RpcClient};
+use details::electrum::{ElectrumClient, ElectrumConfig};
+use details::esplora::{EsploraClient, EsploraConfig};
+use details::failover::FailoverRelay;
+use details::notifications::ZmqConfig;
+use details::signer::ExternalSignerConfig;
 use details::btc;
+use details::btc::hd::HdKeyConfig;
+use handler::alert::AlertsConfig;
+use handler::policy::SigningPolicyConfig;
 use observer::AnchoringObserverConfig;
 
 /// Private part of anchoring service configuration stored on a local machine.
@@ -25,12 +33,72 @@ pub struct AnchoringNodeConfig {
     /// Rpc configuration. Must exist if node is validator.
     /// Otherwise node can only check `lect` payload without any checks with `bitcoind`.
     pub rpc: Option<AnchoringRpcConfig>,
+    /// Backup `bitcoind` endpoints ordered by priority. The service fails over to them
+    /// if the `rpc` endpoint is unreachable or lags behind in the block height.
+    #[serde(default)]
+    pub rpc_backups: Vec<AnchoringRpcConfig>,
+    /// Electrum server configuration. Used instead of `bitcoind` if `rpc` is not specified.
+    #[serde(default)]
+    pub electrum: Option<ElectrumConfig>,
+    /// Esplora API configuration. Used if neither `rpc` nor `electrum` is specified.
+    #[serde(default)]
+    pub esplora: Option<EsploraConfig>,
     /// Set of private keys for each anchoring address.
+    /// If there is no private key for the anchoring address, the `external_signer` is used.
     pub private_keys: BTreeMap<String, btc::PrivateKey>,
+    /// External signer configuration. Allows to keep anchoring keys on a hardware wallet
+    /// or HSM instead of this configuration file.
+    #[serde(default)]
+    pub external_signer: Option<ExternalSignerConfig>,
+    /// Extended key from which the anchoring keys of this node are derived,
+    /// it is used if there is no private key for the anchoring address.
+    #[serde(default)]
+    pub hd_key: Option<HdKeyConfig>,
     /// Frequency of lect check in blocks.
     pub check_lect_frequency: u64,
+    /// Performs the bitcoin backend requests on a background thread, so that a slow backend
+    /// does not stall the block commit. The handler uses the results on the following commits.
+    #[serde(default)]
+    pub background_requests: bool,
+    /// Subscribes to the `bitcoind` ZMQ notifications and polls the backend only
+    /// after the new blocks and the relevant transactions.
+    /// Requires the `zmq` feature.
+    #[serde(default)]
+    pub zmq: Option<ZmqConfig>,
+    /// Checks before signing that the inputs of the anchoring proposal are unspent outputs
+    /// of the anchoring address known to the bitcoin backend.
+    #[serde(default)]
+    pub verify_proposal_inputs: bool,
+    /// Addresses to which the node allows to send the surplus of the anchoring funds
+    /// by the change policy. Any address of the actual configuration is allowed if it is empty.
+    #[serde(default)]
+    pub allowed_change_addresses: Vec<btc::Address>,
+    /// Validator-local policies checked before the node signs the anchoring proposal.
+    #[serde(default)]
+    pub signing_policy: SigningPolicyConfig,
     /// Anchoring observer config.
     pub observer: AnchoringObserverConfig,
+    /// Path to the file in which the anchoring handler persists its state,
+    /// so the restarted node knows the phase of the anchoring process.
+    #[serde(default)]
+    pub state_path: Option<String>,
+    /// Performs the whole anchoring process but never broadcasts the transactions
+    /// to the bitcoin network, their raw bodies are logged instead.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Number of the confirmations after which the transaction is considered deeply confirmed,
+    /// so its confirmations are persisted together with the handler state and are not requested
+    /// from the bitcoin backend anymore. The cache is dropped once a reorg is detected.
+    #[serde(default)]
+    pub confirmations_cache_depth: Option<u64>,
+    /// Maximum number of the input signatures sent by the node on each commit, the rest
+    /// of the signatures of a large proposal are sent by the following commits.
+    /// All signatures are sent at once if it is not specified.
+    #[serde(default)]
+    pub max_signatures_per_commit: Option<u64>,
+    /// Conditions of the anchoring alerts and the webhook to which they are posted.
+    #[serde(default)]
+    pub alerts: AlertsConfig,
 }
 
 impl AnchoringNodeConfig {
@@ -41,15 +109,91 @@ impl AnchoringNodeConfig {
             ..Default::default()
         }
     }
+
+    /// Returns the private key of one of the given anchoring keys derived from the `hd_key`.
+    pub fn derived_private_key(
+        &self,
+        anchoring_keys: &[btc::PublicKey],
+    ) -> Option<btc::PrivateKey> {
+        let hd_key = match self.hd_key {
+            Some(ref hd_key) => hd_key,
+            None => return None,
+        };
+        let index = match hd_key.find_index(anchoring_keys) {
+            Some(index) => index,
+            None => return None,
+        };
+        match hd_key.private_key(index) {
+            Ok(priv_key) => priv_key,
+            Err(e) => {
+                error!("Unable to derive the anchoring key with index={}: {}", index, e);
+                None
+            }
+        }
+    }
+
+    /// Creates a client for the bitcoin backend specified in this configuration.
+    pub fn bitcoin_relay(&self) -> Option<Box<BitcoinRelay>> {
+        self.failover_relay(None)
+    }
+
+    /// Same as [`bitcoin_relay`](#method.bitcoin_relay), but the endpoints connected
+    /// to another chain than the given one are skipped by the failover.
+    pub fn bitcoin_relay_for(&self, chain: btc::Chain) -> Option<Box<BitcoinRelay>> {
+        self.failover_relay(Some(chain))
+    }
+
+    fn failover_relay(&self, chain: Option<btc::Chain>) -> Option<Box<BitcoinRelay>> {
+        if let Some(ref rpc) = self.rpc {
+            if self.rpc_backups.is_empty() {
+                return Some(RpcClient::from(rpc.clone()).into());
+            }
+            let relays: Vec<Box<BitcoinRelay>> = ::std::iter::once(rpc)
+                .chain(&self.rpc_backups)
+                .map(|rpc| RpcClient::from(rpc.clone()).into())
+                .collect();
+            let relay = match chain {
+                Some(chain) => {
+                    // Endpoints connected to another chain are detected before the first request.
+                    let relay = FailoverRelay::new(relays).with_chain(chain);
+                    relay.health_check();
+                    relay
+                }
+                None => FailoverRelay::new(relays),
+            };
+            Some(relay.into())
+        } else if let Some(ref electrum) = self.electrum {
+            Some(ElectrumClient::from(electrum.clone()).into())
+        } else if let Some(ref esplora) = self.esplora {
+            Some(EsploraClient::from(esplora.clone()).into())
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for AnchoringNodeConfig {
     fn default() -> AnchoringNodeConfig {
         AnchoringNodeConfig {
             rpc: None,
+            rpc_backups: Vec::new(),
+            electrum: None,
+            esplora: None,
             observer: AnchoringObserverConfig::default(),
             private_keys: BTreeMap::new(),
+            external_signer: None,
+            hd_key: None,
             check_lect_frequency: 30,
+            background_requests: false,
+            zmq: None,
+            verify_proposal_inputs: false,
+            allowed_change_addresses: Vec::new(),
+            signing_policy: SigningPolicyConfig::default(),
+            state_path: None,
+            dry_run: false,
+            confirmations_cache_depth: None,
+            max_signatures_per_commit: None,
+            alerts: AlertsConfig::default(),
         }
     }
 }
@@ -14,11 +14,9 @@
 
 //! Anchoring transactions' chain observer.
 
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::thread::sleep;
 
-use bitcoin::util::base58::ToBase58;
-
 use exonum::blockchain::{Blockchain, Schema};
 use exonum::storage::Fork;
 
@@ -26,7 +24,7 @@ use details::rpc::{RpcClient, AnchoringRpcConfig, BitcoinRelay};
 use details::btc::transactions::{AnchoringTx, BitcoinTx, TxKind};
 use blockchain::schema::AnchoringSchema;
 use blockchain::consensus_storage::AnchoringConfig;
-use error::Error as ServiceError;
+use error::{Error as ServiceError, HandlerError};
 
 /// Type alias for milliseconds.
 pub type Milliseconds = u64;
@@ -74,7 +72,8 @@ impl AnchoringChainObserver {
         }
     }
 
-    #[doc(hidden)]
+    /// Constructs observer for the given `blockchain` that uses the given `client`
+    /// as the bitcoin backend.
     pub fn new_with_client(
         blockchain: Blockchain,
         client: Box<BitcoinRelay>,
@@ -144,6 +143,10 @@ impl AnchoringChainObserver {
         mut lect: AnchoringTx,
     ) -> Result<(), ServiceError> {
         let mut anchoring_schema = AnchoringSchema::new(fork);
+        // The latencies of the transactions found by the first check of the chain
+        // are unknown, because they may have been confirmed long before.
+        let is_synced = anchoring_schema.anchoring_tx_chain().iter().next().is_some();
+        anchoring_schema.reindex_anchoring_tx_chain();
 
         loop {
             let payload = lect.payload();
@@ -158,17 +161,25 @@ impl AnchoringChainObserver {
             }
 
             let confirmations = self.client.get_transaction_confirmations(lect.id())?;
-            if confirmations.as_ref() >= Some(&actual_cfg.utxo_confirmations) {
+            if confirmations >= Some(actual_cfg.required_anchor_confirmations()) {
                 trace!(
                     "Adds transaction to chain, height={}, content={:#?}",
                     payload.block_height,
                     lect
                 );
 
-                anchoring_schema.anchoring_tx_chain_mut().put(
-                    &height,
-                    lect.clone(),
-                );
+                anchoring_schema.add_to_anchoring_tx_chain(height, lect.clone());
+                if anchoring_schema.confirmation_times().get(&lect.id()).is_none() {
+                    let now = unix_time(SystemTime::now());
+                    anchoring_schema.confirmation_times_mut().put(&lect.id(), now);
+
+                    let commit_time = anchoring_schema.block_commit_time(payload.block_height);
+                    if let (true, Some(commit_time)) = (is_synced, commit_time) {
+                        let latency = now.saturating_sub(unix_time(commit_time));
+                        trace!("Anchoring latency for height={} is {}s", height, latency);
+                        anchoring_schema.anchoring_latencies_mut().put(&height, latency);
+                    }
+                }
             }
 
             let prev_txid = payload.prev_tx_chain.unwrap_or_else(|| lect.prev_hash());
@@ -177,7 +188,11 @@ impl AnchoringChainObserver {
                     TxKind::Anchoring(lect) => lect,
                     TxKind::FundingTx(_) => return Ok(()),
                     TxKind::Other(tx) => {
-                        panic!("Found incorrect lect transaction, content={:#?}", tx)
+                        let e = HandlerError::IncorrectLect {
+                            reason: "Incorrect lect transaction".to_string(),
+                            tx,
+                        };
+                        return Err(e.into());
                     }
                 }
             } else {
@@ -195,7 +210,7 @@ impl AnchoringChainObserver {
 
         trace!(
             "Tries to find lect for the addr: {}",
-            actual_addr.to_base58check()
+            actual_addr.to_string()
         );
 
         let unspent_txs: Vec<_> = self.client.unspent_transactions(&actual_addr)?;
@@ -231,7 +246,16 @@ impl AnchoringChainObserver {
         let core_schema = Schema::new(fork);
         let payload = lect.payload();
         let block_hash = core_schema.block_hash_by_height(payload.block_height);
-        block_hash == Some(payload.block_hash)
+        if block_hash != Some(payload.block_hash) {
+            return false;
+        }
+        match payload.state_hash {
+            Some(state_hash) => {
+                let block = core_schema.blocks().get(&payload.block_hash);
+                block.map(|block| *block.state_hash()) == Some(state_hash)
+            }
+            None => true,
+        }
     }
 
     fn is_blockchain_inited(&self, fork: &Fork) -> bool {
@@ -240,3 +264,9 @@ impl AnchoringChainObserver {
         len > 0
     }
 }
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
@@ -16,7 +16,7 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::ops::Drop;
 
-use bitcoin::util::base58::ToBase58;
+use log::LogLevel;
 use iron::{Handler, Request, Response};
 use iron::prelude::IronResult;
 use serde_json;
@@ -31,15 +31,24 @@ use exonum::encoding::Error as StreamStructError;
 use exonum::storage::{Fork, Snapshot};
 use exonum::api::Api;
 
-use api::PublicApi;
+use api::{PrivateApi, PublicApi};
 use details::btc;
-use details::rpc::{BitcoinRelay, RpcClient};
+use details::rpc::BitcoinRelay;
+use details::background::BackgroundRelay;
+use details::dry_run::DryRunRelay;
+use details::notifications::ZmqConfig;
 use local_storage::AnchoringNodeConfig;
 use handler::AnchoringHandler;
+use handler::shared::SharedHandler;
 use blockchain::consensus_storage::AnchoringConfig;
 use blockchain::schema::AnchoringSchema;
-use blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest, ANCHORING_MESSAGE_LATEST,
-                      ANCHORING_MESSAGE_SIGNATURE};
+use blockchain::dto::{MsgAnchoringFeeRate, MsgAnchoringFunding, MsgAnchoringKeyRotation,
+                      MsgAnchoringPause, MsgAnchoringSignature, MsgAnchoringSweep,
+                      MsgAnchoringTrigger, MsgAnchoringUpdateLatest, ANCHORING_MESSAGE_FEE_RATE,
+                      ANCHORING_MESSAGE_FUNDING, ANCHORING_MESSAGE_KEY_ROTATION,
+                      ANCHORING_MESSAGE_LATEST, ANCHORING_MESSAGE_PAUSE,
+                      ANCHORING_MESSAGE_SIGNATURE, ANCHORING_MESSAGE_SWEEP,
+                      ANCHORING_MESSAGE_TRIGGER};
 use error::Error as ServiceError;
 use handler::error::Error as HandlerError;
 use observer::AnchoringChainObserver;
@@ -59,14 +68,18 @@ pub struct AnchoringService {
 impl AnchoringService {
     /// Creates a new service instance with the given `consensus` and `local` configurations.
     pub fn new(consensus: AnchoringConfig, local: AnchoringNodeConfig) -> AnchoringService {
-        let client = local.rpc.clone().map(RpcClient::from).map(Into::into);
+        let client = match bitcoin_relay(&local, consensus.network) {
+            Ok(client) => client,
+            Err(e) => panic!("{}", e),
+        };
         AnchoringService {
             genesis: consensus,
             handler: Arc::new(Mutex::new(AnchoringHandler::new(client, local))),
         }
     }
 
-    #[doc(hidden)]
+    /// Creates a new service instance that uses the given `client` as the bitcoin backend
+    /// instead of the one specified in the `local_cfg`.
     pub fn new_with_client(
         client: Box<BitcoinRelay>,
         genesis: AnchoringConfig,
@@ -102,6 +115,14 @@ impl Service for AnchoringService {
         match raw.message_type() {
             ANCHORING_MESSAGE_LATEST => Ok(Box::new(MsgAnchoringUpdateLatest::from_raw(raw)?)),
             ANCHORING_MESSAGE_SIGNATURE => Ok(Box::new(MsgAnchoringSignature::from_raw(raw)?)),
+            ANCHORING_MESSAGE_FUNDING => Ok(Box::new(MsgAnchoringFunding::from_raw(raw)?)),
+            ANCHORING_MESSAGE_SWEEP => Ok(Box::new(MsgAnchoringSweep::from_raw(raw)?)),
+            ANCHORING_MESSAGE_KEY_ROTATION => Ok(Box::new(
+                MsgAnchoringKeyRotation::from_raw(raw)?,
+            )),
+            ANCHORING_MESSAGE_PAUSE => Ok(Box::new(MsgAnchoringPause::from_raw(raw)?)),
+            ANCHORING_MESSAGE_TRIGGER => Ok(Box::new(MsgAnchoringTrigger::from_raw(raw)?)),
+            ANCHORING_MESSAGE_FEE_RATE => Ok(Box::new(MsgAnchoringFeeRate::from_raw(raw)?)),
             _ => Err(StreamStructError::IncorrectMessageType {
                 message_type: raw.message_type(),
             }),
@@ -111,22 +132,23 @@ impl Service for AnchoringService {
     fn initialize(&self, fork: &mut Fork) -> Value {
         let mut handler = self.handler.lock().unwrap();
         let cfg = self.genesis.clone();
-        let (_, addr) = cfg.redeem_script();
-        if handler.client.is_some() {
-            handler.import_address(&addr).unwrap();
-        }
+        let addr = handler.describe_address(&cfg);
+        handler.schedule_address_import(addr);
         AnchoringSchema::new(fork).create_genesis_config(&cfg);
         serde_json::to_value(cfg).unwrap()
     }
 
     fn handle_commit(&self, state: &ServiceContext) {
         let mut handler = self.handler.lock().unwrap();
+        handler.apply_requests();
         match handler.handle_commit(state) {
             Err(ServiceError::Handler(e @ HandlerError::IncorrectLect { .. })) => {
                 panic!("A critical error occured: {}", e)
             }
             Err(ServiceError::Handler(e)) => {
-                error!("An error in handler occured: {}", e);
+                let fields = json!({ "error": e.to_string() });
+                handler.span.record(LogLevel::Error, "handler_error", fields);
+                handler.push_pending_error(e.to_string());
                 if let Some(sink) = handler.errors_sink.as_ref() {
                     let res = sink.send(e);
                     if let Err(err) = res {
@@ -135,30 +157,112 @@ impl Service for AnchoringService {
                 }
             }
             Err(e) => {
-                error!("An error occured: {:?}", e);
+                if e.rpc_error().is_some() {
+                    handler.metrics.rpc_errors += 1;
+                }
+                let fields = json!({ "error": e.to_string() });
+                handler.span.record(LogLevel::Error, "commit_error", fields);
+                handler.push_pending_error(e.to_string());
             }
-            Ok(()) => (),
+            Ok(()) => handler.pending_errors.clear(),
         }
+        handler.publish_snapshot();
     }
 
     /// Public api implementation.
     /// See [`PublicApi`](api/struct.PublicApi.html) for details.
     fn public_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
-        let handler = self.handler.lock().unwrap();
-        let router = PublicApiHandler::new(context.blockchain(), &handler.node);
+        let (shared, node) = {
+            let handler = self.handler.lock().unwrap();
+            (handler.shared(), handler.node.clone())
+        };
+        let router = PublicApiHandler::new(context.blockchain(), shared, &node);
+        Some(Box::new(router))
+    }
+
+    /// Private api implementation.
+    /// See [`PrivateApi`](api/struct.PrivateApi.html) for details.
+    fn private_api_handler(&self, context: &ApiContext) -> Option<Box<Handler>> {
+        let mut router = Router::new();
+        let api = PrivateApi {
+            blockchain: context.blockchain().clone(),
+            shared: self.handler.lock().unwrap().shared(),
+        };
+        api.wire(&mut router);
         Some(Box::new(router))
     }
 }
 
 
+#[cfg(feature = "zmq")]
+fn subscribe_notifications(client: Box<BitcoinRelay>, config: &ZmqConfig) -> Box<BitcoinRelay> {
+    use details::notifications::{subscribe, NotifiedRelay};
+
+    match subscribe(config) {
+        Ok(notifications) => Box::new(NotifiedRelay::new(client, notifications)),
+        Err(e) => {
+            error!("Unable to subscribe to the ZMQ notifications: {}", e);
+            client
+        }
+    }
+}
+
+#[cfg(not(feature = "zmq"))]
+fn subscribe_notifications(client: Box<BitcoinRelay>, config: &ZmqConfig) -> Box<BitcoinRelay> {
+    warn!(
+        "ZMQ notifications from {} are ignored, the service is built without the `zmq` feature",
+        config.rawblock
+    );
+    client
+}
+
+/// Creates the bitcoin backend specified in the `local` configuration and checks that
+/// it is connected to the given `network`.
+#[doc(hidden)]
+pub fn bitcoin_relay(
+    local: &AnchoringNodeConfig,
+    network: btc::Chain,
+) -> Result<Option<Box<BitcoinRelay>>, ServiceError> {
+    let client = match local.bitcoin_relay_for(network) {
+        Some(client) => client,
+        None => return Ok(None),
+    };
+    check_bitcoin_chain(client.as_ref(), network)?;
+    let client = if local.dry_run {
+        warn!("Dry run mode, the anchoring transactions are not broadcast");
+        Box::new(DryRunRelay::new(client)) as Box<BitcoinRelay>
+    } else {
+        client
+    };
+    let client = match local.zmq {
+        Some(ref zmq) => subscribe_notifications(client, zmq),
+        None => client,
+    };
+    let client = if local.background_requests {
+        Box::new(BackgroundRelay::new(client)) as Box<BitcoinRelay>
+    } else {
+        client
+    };
+    Ok(Some(client))
+}
+
 /// Generates testnet configuration by given rpc for given nodes amount
 /// using given random number generator.
 ///
+/// The anchoring address is derived from the generated keys by the given `script_type`
+/// and signature `threshold`, like the address of the resulting genesis configuration.
+///
 /// Note: Bitcoin node that is used by rpc should have enough bitcoin amount to generate
 /// funding transaction by given `total_funds`.
+///
+/// # Panics
+///
+/// If the `threshold` is unsafe for the given nodes amount.
 pub fn gen_anchoring_testnet_config_with_rng<R>(
     client: &BitcoinRelay,
-    network: btc::Network,
+    network: btc::Chain,
+    script_type: btc::ScriptType,
+    threshold: Option<u8>,
     count: u8,
     total_funds: u64,
     rng: &mut R,
@@ -172,24 +276,31 @@ where
     let mut priv_keys = Vec::new();
 
     for _ in 0..count as usize {
-        let (pub_key, priv_key) = btc::gen_btc_keypair_with_rng(network, rng);
+        let (pub_key, priv_key) = btc::gen_btc_keypair_with_rng(network.base58_network(), rng);
 
         pub_keys.push(pub_key);
         node_cfgs.push(AnchoringNodeConfig::new(Some(rpc.clone())));
         priv_keys.push(priv_key.clone());
     }
 
-    let majority_count = ::majority_count(count);
-    let address = btc::RedeemScript::from_pubkeys(&pub_keys, majority_count)
-        .compressed(network)
-        .to_address(network);
+    let mut genesis_cfg = AnchoringConfig {
+        network,
+        anchoring_keys: pub_keys,
+        threshold,
+        script_type,
+        ..Default::default()
+    };
+    if let Err(e) = genesis_cfg.check_threshold() {
+        panic!("Incorrect testnet anchoring config: {}", e);
+    }
+    let (_, address) = genesis_cfg.redeem_script();
     client.watch_address(&address, false).unwrap();
     let tx = client.send_to_address(&address, total_funds).unwrap();
 
-    let genesis_cfg = AnchoringConfig::new_with_funding_tx(network, pub_keys, tx);
+    genesis_cfg.funding_tx = Some(tx);
     for (idx, node_cfg) in node_cfgs.iter_mut().enumerate() {
         node_cfg.private_keys.insert(
-            address.to_base58check(),
+            address.to_string(),
             priv_keys[idx].clone(),
         );
     }
@@ -201,12 +312,44 @@ where
 /// but it uses default random number generator.
 pub fn gen_anchoring_testnet_config(
     client: &BitcoinRelay,
-    network: btc::Network,
+    network: btc::Chain,
+    script_type: btc::ScriptType,
+    threshold: Option<u8>,
     count: u8,
     total_funds: u64,
 ) -> (AnchoringConfig, Vec<AnchoringNodeConfig>) {
     let mut rng = thread_rng();
-    gen_anchoring_testnet_config_with_rng(client, network, count, total_funds, &mut rng)
+    gen_anchoring_testnet_config_with_rng(
+        client,
+        network,
+        script_type,
+        threshold,
+        count,
+        total_funds,
+        &mut rng,
+    )
+}
+
+// Refuses to start the node if the bitcoin backend is connected to another chain,
+// otherwise the anchoring transactions are sent to the wrong chain.
+fn check_bitcoin_chain(client: &BitcoinRelay, network: btc::Chain) -> Result<(), ServiceError> {
+    match client.chain() {
+        Ok(Some(chain)) if chain != network => {
+            let reason = format!(
+                "Bitcoin backend host={} is connected to the {} chain, \
+                 but the anchoring network is {}",
+                client.config().host,
+                chain,
+                network
+            );
+            Err(ServiceError::Config { reason })
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("Unable to check the chain of the bitcoin backend: {}", e);
+            Ok(())
+        }
+    }
 }
 
 /// Helper class that combines `Router` for public api with the observer thread.
@@ -216,17 +359,29 @@ struct PublicApiHandler {
 }
 
 impl PublicApiHandler {
-    /// Creates public api handler instance for the given `blockchain`
-    /// and anchoring node `config`.
-    pub fn new(blockchain: &Blockchain, config: &AnchoringNodeConfig) -> PublicApiHandler {
+    /// Creates public api handler instance for the given `blockchain`, the `shared` state
+    /// of the anchoring handler and the node `config`.
+    pub fn new(
+        blockchain: &Blockchain,
+        shared: Arc<SharedHandler>,
+        config: &AnchoringNodeConfig,
+    ) -> PublicApiHandler {
         let mut router = Router::new();
-        let api = PublicApi { blockchain: blockchain.clone() };
+        let api = PublicApi {
+            blockchain: blockchain.clone(),
+            shared,
+        };
         api.wire(&mut router);
 
         let observer = if config.observer.enabled {
-            let rpc_cfg = config.rpc.clone().expect("Rpc config is not setted");
-            let mut observer =
-                AnchoringChainObserver::new(blockchain.clone(), rpc_cfg, &config.observer);
+            let client = config.bitcoin_relay().expect(
+                "Bitcoin backend config is not setted",
+            );
+            let mut observer = AnchoringChainObserver::new_with_client(
+                blockchain.clone(),
+                client,
+                config.observer.check_interval,
+            );
 
             Some(thread::spawn(move || { observer.run().unwrap(); }))
         } else {
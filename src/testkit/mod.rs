@@ -0,0 +1,452 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Testing helpers for the applications with the anchoring service.
+//!
+//! The [`FakeBitcoinRelay`](struct.FakeBitcoinRelay.html) keeps the bitcoin blockchain
+//! in memory and the [`AnchoringTestNetwork`](struct.AnchoringTestNetwork.html) drives
+//! the anchoring service on top of the `exonum-testkit` through the anchoring rounds,
+//! so the anchoring behavior can be tested without the regtest `bitcoind`.
+//!
+//! The same network can be driven on top of the [`RegtestRelay`](struct.RegtestRelay.html)
+//! connected to the real `bitcoind` in the regtest mode, and
+//! [`check_lifecycle`](struct.AnchoringTestNetwork.html#method.check_lifecycle) asserts
+//! the whole anchoring lifecycle against any of the backends.
+//!
+//! The module is available with the `testkit` feature.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rand::{SeedableRng, StdRng};
+
+use exonum::blockchain::Transaction;
+use exonum::helpers::{Height, ValidatorId};
+use exonum::messages::{Message, RawMessage};
+use exonum_testkit::{TestKit, TestKitBuilder};
+
+use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest,
+                      ANCHORING_MESSAGE_LATEST, ANCHORING_MESSAGE_SIGNATURE};
+use blockchain::schema::AnchoringSchema;
+use details::btc;
+use details::btc::transactions::{AnchoringTx, TxKind};
+use details::rpc::BitcoinRelay;
+use handler::AnchoringHandler;
+use local_storage::AnchoringNodeConfig;
+use service::{gen_anchoring_testnet_config_with_rng, AnchoringService, ANCHORING_SERVICE_ID,
+              ANCHORING_SERVICE_NAME};
+
+pub use self::regtest::{RegtestRelay, COINBASE_MATURITY};
+pub use self::relay::FakeBitcoinRelay;
+
+mod regtest;
+mod relay;
+
+/// The maximum number of blocks in which the anchoring round should complete.
+pub const MAX_ROUND_BLOCKS: u64 = 100;
+
+/// Bitcoin backend which blockchain is controlled by the test.
+pub trait TestBitcoinBackend: BitcoinRelay + Clone {
+    /// Includes the mempool transactions into the next block and mines `count` blocks.
+    fn mine_blocks(&self, count: u64);
+    /// Orphans the latest `depth` blocks, their transactions return to the mempool.
+    fn reorg(&self, depth: u64);
+}
+
+/// Exonum testkit network with the anchoring service connected to the test bitcoin backend,
+/// by default to the [`FakeBitcoinRelay`](struct.FakeBitcoinRelay.html).
+///
+/// Only the first validator runs the anchoring handler, the messages of the other validators
+/// mirror its signatures and lects. After each block commit the bitcoin backend mines
+/// a new block, unless the test drives the bitcoin blockchain on its own with
+/// [`create_block_without_mining`](#method.create_block_without_mining).
+#[derive(Debug)]
+pub struct AnchoringTestNetwork<R = FakeBitcoinRelay> {
+    testkit: TestKit,
+    relay: R,
+    handler: Arc<Mutex<AnchoringHandler>>,
+    nodes: Vec<AnchoringNodeConfig>,
+}
+
+impl AnchoringTestNetwork<FakeBitcoinRelay> {
+    /// Creates the network of `validators` which anchoring address is funded with
+    /// the `funds` satoshis. The genesis configuration can be adjusted by `configure`.
+    pub fn new<F>(validators: u8, funds: u64, configure: F) -> AnchoringTestNetwork
+    where
+        F: FnOnce(&mut AnchoringConfig),
+    {
+        AnchoringTestNetwork::with_backend(FakeBitcoinRelay::new(), validators, funds, configure)
+    }
+}
+
+impl<R: TestBitcoinBackend> AnchoringTestNetwork<R> {
+    /// Creates the network on top of the given bitcoin backend, the funding transaction
+    /// is sent by the backend and confirmed by the `utxo_confirmations` mined blocks.
+    pub fn with_backend<F>(
+        relay: R,
+        validators: u8,
+        funds: u64,
+        configure: F,
+    ) -> AnchoringTestNetwork<R>
+    where
+        F: FnOnce(&mut AnchoringConfig),
+    {
+        // The anchoring address depends on the configured chain, script type and threshold,
+        // so the funding transaction is sent after the configuration is adjusted.
+        let mut genesis = AnchoringConfig::default();
+        configure(&mut genesis);
+        let mut rng: StdRng = SeedableRng::from_seed([1, 2, 3, 4].as_ref());
+        let (generated, nodes) = gen_anchoring_testnet_config_with_rng(
+            &relay,
+            genesis.network,
+            genesis.script_type,
+            genesis.threshold,
+            validators,
+            funds,
+            &mut rng,
+        );
+        genesis.anchoring_keys = generated.anchoring_keys;
+        genesis.funding_tx = generated.funding_tx;
+        relay.mine_blocks(genesis.utxo_confirmations);
+
+        let service =
+            AnchoringService::new_with_client(Box::new(relay.clone()), genesis, nodes[0].clone());
+        let handler = service.handler();
+        let testkit = TestKitBuilder::validator()
+            .with_validators(u16::from(validators))
+            .with_service(service)
+            .create();
+        AnchoringTestNetwork {
+            testkit,
+            relay,
+            handler,
+            nodes,
+        }
+    }
+
+    /// Returns the underlying testkit.
+    pub fn testkit(&mut self) -> &mut TestKit {
+        &mut self.testkit
+    }
+
+    /// Returns the bitcoin backend of the network.
+    pub fn relay(&self) -> &R {
+        &self.relay
+    }
+
+    /// Returns the anchoring handler of the first validator.
+    pub fn handler(&self) -> MutexGuard<AnchoringHandler> {
+        self.handler.lock().unwrap()
+    }
+
+    /// Returns the local configurations of the validators.
+    pub fn nodes(&self) -> &[AnchoringNodeConfig] {
+        &self.nodes
+    }
+
+    /// Returns the actual anchoring configuration.
+    pub fn actual_config(&self) -> AnchoringConfig {
+        AnchoringSchema::new(self.testkit.snapshot()).actual_anchoring_config()
+    }
+
+    /// Returns the anchoring transaction agreed by the majority of the validators, if any.
+    pub fn actual_lect(&self) -> Option<AnchoringTx> {
+        let schema = AnchoringSchema::new(self.testkit.snapshot());
+        let cfg = schema.actual_anchoring_config();
+        match schema.collect_lects(&cfg).map(TxKind::from) {
+            Some(TxKind::Anchoring(tx)) => Some(tx),
+            _ => None,
+        }
+    }
+
+    /// Commits the block with the anchoring messages of the validators and mines
+    /// the bitcoin block.
+    pub fn create_block(&mut self) {
+        self.create_block_without_mining();
+        self.relay.mine_blocks(1);
+    }
+
+    /// Commits the block with the anchoring messages of the validators.
+    pub fn create_block_without_mining(&mut self) {
+        let txs = self.mirrored_messages();
+        if txs.is_empty() {
+            self.testkit.create_block();
+        } else {
+            self.testkit.create_block_with_transactions(txs);
+        }
+    }
+
+    /// Creates blocks until the given `height`.
+    pub fn create_blocks_until(&mut self, height: Height) {
+        while self.testkit.height() < height {
+            self.create_block();
+        }
+    }
+
+    /// Creates blocks until the validators agree on the anchoring transaction of the next
+    /// anchoring height and returns this transaction.
+    ///
+    /// # Panics
+    ///
+    /// If the round is not completed within `MAX_ROUND_BLOCKS` blocks.
+    pub fn anchor_next_block(&mut self) -> AnchoringTx {
+        let anchored_height = self.actual_lect().map(|tx| tx.payload().block_height);
+        for _ in 0..MAX_ROUND_BLOCKS {
+            self.create_block();
+            if let Some(lect) = self.actual_lect() {
+                if Some(lect.payload().block_height) != anchored_height {
+                    return lect;
+                }
+            }
+        }
+        panic!(
+            "Anchoring round is not completed within {} blocks",
+            MAX_ROUND_BLOCKS
+        );
+    }
+
+    /// Proposes the configuration which moves the anchoring to the address of the given
+    /// validators `keys` since the `actual_from` height and commits the proposal.
+    /// Returns the proposed anchoring configuration.
+    pub fn transfer_to_keys(
+        &mut self,
+        actual_from: Height,
+        keys: Vec<(btc::PublicKey, btc::PrivateKey)>,
+    ) -> AnchoringConfig {
+        assert_eq!(keys.len(), self.nodes.len());
+
+        let mut cfg_proposal = self.testkit.configuration_change_proposal();
+        cfg_proposal.set_actual_from(actual_from);
+        let mut anchoring_cfg: AnchoringConfig =
+            cfg_proposal.service_config(ANCHORING_SERVICE_NAME);
+        anchoring_cfg.anchoring_keys = keys.iter().map(|&(ref pub_key, _)| *pub_key).collect();
+
+        let (_, following_addr) = anchoring_cfg.redeem_script();
+        for (node, &(_, ref priv_key)) in self.nodes.iter_mut().zip(&keys) {
+            node.private_keys.insert(
+                following_addr.to_string(),
+                priv_key.clone(),
+            );
+        }
+        self.handler().add_private_key(
+            &following_addr,
+            keys[0].1.clone(),
+        );
+
+        cfg_proposal.set_service_config(ANCHORING_SERVICE_NAME, anchoring_cfg.clone());
+        self.testkit.commit_configuration_change(cfg_proposal);
+        anchoring_cfg
+    }
+
+    /// Drives the network through the anchoring lifecycle and asserts its every stage:
+    ///
+    /// - the genesis funding transaction is spent by the first anchoring transaction;
+    /// - the following anchoring transactions continue the chain;
+    /// - the chain is recovered after the bitcoin reorg of the latest block;
+    /// - the anchoring is transferred to the address of the freshly generated keys.
+    ///
+    /// # Panics
+    ///
+    /// If any of the stages is not completed.
+    pub fn check_lifecycle(&mut self) {
+        let genesis = self.actual_config();
+        let funding_tx = genesis.funding_tx.clone().expect("Funding tx is not set");
+
+        // Genesis -> anchor.
+        let first = self.anchor_next_block();
+        assert_eq!(first.prev_hash(), funding_tx.id());
+        let second = self.anchor_next_block();
+        assert_eq!(second.prev_hash(), first.id());
+        assert!(second.payload().block_height > first.payload().block_height);
+
+        // Recovery after the orphaned block.
+        self.relay.reorg(1);
+        let recovered = self.anchor_next_block();
+        assert!(recovered.payload().block_height > second.payload().block_height);
+        assert!(
+            self.relay
+                .get_transaction_confirmations(recovered.id())
+                .unwrap()
+                .is_some()
+        );
+
+        // Transfer to the new anchoring address.
+        let network = genesis.network.base58_network();
+        let mut rng: StdRng = SeedableRng::from_seed([5, 6, 7, 8].as_ref());
+        let keys = (0..self.nodes.len())
+            .map(|_| btc::gen_btc_keypair_with_rng(network, &mut rng))
+            .collect::<Vec<_>>();
+        let actual_from = Height(self.testkit.height().0 + 2);
+        let following_cfg = self.transfer_to_keys(actual_from, keys);
+        let (_, following_addr) = following_cfg.redeem_script();
+        self.create_blocks_until(actual_from);
+
+        let transferred = self.anchor_next_block();
+        assert_eq!(transferred.0.output[0].script_pubkey, following_addr.script_pubkey());
+        assert_eq!(self.actual_config(), following_cfg);
+    }
+
+    // The messages of the first validator in the pool followed by the same messages
+    // of the other validators.
+    fn mirrored_messages(&self) -> Vec<Box<Transaction>> {
+        let raw_messages = self.testkit
+            .mempool()
+            .values()
+            .map(|tx| tx.raw().clone())
+            .filter(|raw| raw.service_id() == ANCHORING_SERVICE_ID)
+            .collect::<Vec<_>>();
+
+        let mut txs = Vec::new();
+        for raw in raw_messages {
+            txs.extend(self.mirror_message(raw));
+        }
+        txs
+    }
+
+    fn mirror_message(&self, raw: RawMessage) -> Vec<Box<Transaction>> {
+        let snapshot = self.testkit.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        let cfg = schema.actual_anchoring_config();
+        let (redeem_script, addr) = cfg.redeem_script();
+
+        let mut txs = Vec::new();
+        match raw.message_type() {
+            ANCHORING_MESSAGE_SIGNATURE => {
+                let msg = MsgAnchoringSignature::from_raw(raw).unwrap();
+                if msg.validator() != ValidatorId(0) {
+                    return txs;
+                }
+                let tx = msg.tx();
+                for (validator, node) in self.nodes.iter().enumerate().skip(1) {
+                    let priv_key = &node.private_keys[&addr.to_string()];
+                    let signature = match cfg.script_type {
+                        btc::ScriptType::P2sh => {
+                            tx.sign_input(&redeem_script, msg.input(), priv_key)
+                        }
+                        btc::ScriptType::P2shP2wsh | btc::ScriptType::P2wsh => {
+                            let value = schema.spent_output_value(&tx, msg.input()).unwrap();
+                            tx.sign_witness_input(&redeem_script, msg.input(), value, priv_key)
+                        }
+                    };
+                    let validator = ValidatorId(validator as u16);
+                    let keypair = self.testkit.validator(validator).service_keypair();
+                    let mirrored = MsgAnchoringSignature::new(
+                        keypair.0,
+                        validator,
+                        tx.clone(),
+                        msg.input(),
+                        &signature,
+                        keypair.1,
+                    );
+                    txs.push(Box::new(mirrored) as Box<Transaction>);
+                }
+                txs.insert(0, Box::new(msg) as Box<Transaction>);
+            }
+            ANCHORING_MESSAGE_LATEST => {
+                let msg = MsgAnchoringUpdateLatest::from_raw(raw).unwrap();
+                if msg.validator() != ValidatorId(0) {
+                    return txs;
+                }
+                for (validator, key) in cfg.anchoring_keys.iter().enumerate().skip(1) {
+                    let validator = ValidatorId(validator as u16);
+                    let keypair = self.testkit.validator(validator).service_keypair();
+                    let mirrored = MsgAnchoringUpdateLatest::new(
+                        keypair.0,
+                        validator,
+                        msg.tx(),
+                        schema.lects(key).len(),
+                        keypair.1,
+                    );
+                    txs.push(Box::new(mirrored) as Box<Transaction>);
+                }
+                txs.insert(0, Box::new(msg) as Box<Transaction>);
+            }
+            _ => {}
+        }
+        txs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc::ScriptType;
+    use details::rpc::BitcoinRelay;
+
+    use super::AnchoringTestNetwork;
+
+    #[test]
+    fn test_anchoring_test_network_funds_configured_address() {
+        let network = AnchoringTestNetwork::new(4, 100_000, |cfg| {
+            cfg.script_type = ScriptType::P2shP2wsh;
+            cfg.threshold = Some(4);
+        });
+
+        let cfg = network.actual_config();
+        let (_, addr) = cfg.redeem_script();
+        assert_eq!(cfg.signature_threshold(), 4);
+        assert!(cfg.funding_tx().find_out(&addr).is_some());
+        assert!(
+            network.nodes()[0]
+                .private_keys
+                .contains_key(&addr.to_string())
+        );
+    }
+
+    #[test]
+    fn test_anchoring_test_network_rounds() {
+        let mut network = AnchoringTestNetwork::new(4, 100_000, |cfg| {
+            cfg.frequency = 10;
+            cfg.utxo_confirmations = 2;
+        });
+
+        let first = network.anchor_next_block();
+        assert_eq!(first.payload().block_height.0, 0);
+        assert!(network.relay().get_transaction_confirmations(first.id()).unwrap() > Some(0));
+
+        let second = network.anchor_next_block();
+        assert_eq!(second.payload().block_height.0, 10);
+        assert_eq!(second.prev_hash(), first.id());
+    }
+
+    #[test]
+    fn test_anchoring_test_network_lifecycle() {
+        let mut network = AnchoringTestNetwork::new(4, 100_000, |cfg| {
+            cfg.frequency = 10;
+            cfg.utxo_confirmations = 2;
+        });
+        network.check_lifecycle();
+    }
+
+    #[test]
+    fn test_anchoring_test_network_lifecycle_p2wsh() {
+        let mut network = AnchoringTestNetwork::new(4, 100_000, |cfg| {
+            cfg.frequency = 10;
+            cfg.utxo_confirmations = 2;
+            cfg.script_type = ScriptType::P2shP2wsh;
+        });
+        network.check_lifecycle();
+    }
+
+    #[test]
+    fn test_anchoring_test_network_lifecycle_native_p2wsh() {
+        let mut network = AnchoringTestNetwork::new(4, 100_000, |cfg| {
+            cfg.frequency = 10;
+            cfg.utxo_confirmations = 2;
+            cfg.script_type = ScriptType::P2wsh;
+        });
+        let (_, addr) = network.actual_config().redeem_script();
+        assert!(addr.to_string().starts_with("tb1q"));
+        network.check_lifecycle();
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Regtest `bitcoind` backend for the end-to-end tests.
+
+use std::io;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+use tempdir::TempDir;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx};
+use details::rpc::{raw_request, AnchoringRpcConfig, BitcoinRelay, Result, RpcClient, TxInfo};
+
+use super::TestBitcoinBackend;
+
+/// The number of blocks after which the coinbase outputs can be spent.
+pub const COINBASE_MATURITY: u64 = 101;
+
+const RPC_USER: &str = "regtest";
+const RPC_PASSWORD: &str = "regtest";
+
+/// Bitcoin backend connected to the `bitcoind` in the regtest mode, the blocks are mined
+/// on demand by the test.
+#[derive(Debug, Clone)]
+pub struct RegtestRelay {
+    client: Arc<RpcClient>,
+    process: Option<Arc<RegtestProcess>>,
+}
+
+// The spawned `bitcoind` is stopped together with the last clone of the relay.
+#[derive(Debug)]
+struct RegtestProcess {
+    child: Mutex<Child>,
+    _datadir: TempDir,
+}
+
+impl RegtestRelay {
+    /// Connects to the running regtest `bitcoind` with the given rpc configuration.
+    /// Its wallet must have enough mature funds to fund the anchoring address.
+    pub fn connect(config: AnchoringRpcConfig) -> RegtestRelay {
+        RegtestRelay {
+            client: Arc::new(RpcClient::from(config)),
+            process: None,
+        }
+    }
+
+    /// Spawns the `bitcoind` executable in the regtest mode with the empty data directory,
+    /// waits until it accepts the rpc requests on the given port, and mines
+    /// `COINBASE_MATURITY` blocks to fund its wallet.
+    pub fn spawn(bitcoind: &str, rpc_port: u16) -> io::Result<RegtestRelay> {
+        let datadir = TempDir::new("btc_anchoring_regtest")?;
+        let child = Command::new(bitcoind)
+            .arg("-regtest")
+            .arg("-server")
+            .arg("-txindex")
+            .arg("-listen=0")
+            .arg("-fallbackfee=0.0002")
+            .arg(format!("-datadir={}", datadir.path().display()))
+            .arg(format!("-rpcport={}", rpc_port))
+            .arg(format!("-rpcuser={}", RPC_USER))
+            .arg(format!("-rpcpassword={}", RPC_PASSWORD))
+            .stdout(Stdio::null())
+            .spawn()?;
+        let process = RegtestProcess {
+            child: Mutex::new(child),
+            _datadir: datadir,
+        };
+
+        let relay = RegtestRelay {
+            client: Arc::new(RpcClient::from(AnchoringRpcConfig {
+                host: format!("http://127.0.0.1:{}", rpc_port),
+                username: Some(RPC_USER.to_string()),
+                password: Some(RPC_PASSWORD.to_string()),
+                ..Default::default()
+            })),
+            process: Some(Arc::new(process)),
+        };
+        relay.wait_until_ready()?;
+        // The recent `bitcoind` versions start without the default wallet.
+        let _ = relay.request::<Value>("createwallet", vec![json!("anchoring")]);
+        relay.try_mine_blocks(COINBASE_MATURITY).map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, e.to_string())
+        })?;
+        Ok(relay)
+    }
+
+    /// Mines `count` blocks to the new address of the `bitcoind` wallet.
+    pub fn try_mine_blocks(&self, count: u64) -> Result<Vec<String>> {
+        let addr: String = self.request("getnewaddress", vec![])?;
+        self.request("generatetoaddress", vec![json!(count), json!(addr)])
+    }
+
+    /// Invalidates the latest `depth` blocks, their transactions return to the mempool.
+    pub fn try_reorg(&self, depth: u64) -> Result<()> {
+        let count: u64 = self.request("getblockcount", vec![])?;
+        if depth == 0 || depth > count {
+            return Ok(());
+        }
+        let block_hash: String = self.request("getblockhash", vec![json!(count - depth + 1)])?;
+        self.request::<Value>("invalidateblock", vec![json!(block_hash)])?;
+        Ok(())
+    }
+
+    fn request<T>(&self, method: &str, params: Vec<Value>) -> Result<T>
+    where
+        for<'de> T: ::serde::Deserialize<'de>,
+    {
+        raw_request(&BitcoinRelay::config(&*self.client), method, params)
+    }
+
+    fn wait_until_ready(&self) -> io::Result<()> {
+        for _ in 0..100 {
+            if self.request::<u64>("getblockcount", vec![]).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Regtest bitcoind does not respond",
+        ))
+    }
+}
+
+impl Drop for RegtestProcess {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        if let Err(e) = child.kill() {
+            warn!("Unable to stop the regtest bitcoind: {}", e);
+        }
+        let _ = child.wait();
+    }
+}
+
+impl TestBitcoinBackend for RegtestRelay {
+    fn mine_blocks(&self, count: u64) {
+        self.try_mine_blocks(count).expect("Unable to mine regtest blocks");
+    }
+
+    fn reorg(&self, depth: u64) {
+        self.try_reorg(depth).expect("Unable to invalidate regtest blocks");
+    }
+}
+
+impl BitcoinRelay for RegtestRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        self.client.get_transaction(txid)
+    }
+
+    fn get_transactions(&self, txids: &[btc::TxId]) -> Result<Vec<Option<BitcoinTx>>> {
+        self.client.get_transactions(txids)
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        self.client.get_transaction_info(txid)
+    }
+
+    fn watch_address(&self, addr: &btc::Address, rescan: bool) -> Result<()> {
+        self.client.watch_address(addr, rescan)
+    }
+
+    fn watch_descriptor(
+        &self,
+        addr: &btc::Address,
+        descriptor: &str,
+        rescan_from: Option<btc::TxId>,
+    ) -> Result<()> {
+        self.client.watch_descriptor(addr, descriptor, rescan_from)
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        self.client.send_transaction(tx)
+    }
+
+    fn send_to_address(&self, addr: &btc::Address, satoshis: u64) -> Result<FundingTx> {
+        self.client.send_to_address(addr, satoshis)
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        self.client.unspent_transactions(addr)
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        self.client.unspent_outputs(addr)
+    }
+
+    fn estimate_fee_rate(&self, conf_target: u16) -> Result<Option<u64>> {
+        self.client.estimate_fee_rate(conf_target)
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        self.client.tip_height()
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        self.client.chain()
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        self.client.verification_progress()
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        self.client.get_transaction_proof(txid)
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        self.client.get_block_headers(block_hash, count)
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        BitcoinRelay::config(&*self.client)
+    }
+}
+
+// Spawns the `bitcoind` from the `ANCHORING_BITCOIND` env variable or from the `PATH`.
+#[cfg(all(test, feature = "rpc_tests"))]
+mod tests {
+    use std::env;
+
+    use details::btc;
+    use testkit::AnchoringTestNetwork;
+
+    use super::RegtestRelay;
+
+    #[test]
+    fn test_regtest_anchoring_lifecycle() {
+        let bitcoind = env::var("ANCHORING_BITCOIND").unwrap_or_else(|_| "bitcoind".to_string());
+        let relay = RegtestRelay::spawn(&bitcoind, 18_600).unwrap();
+        let mut network = AnchoringTestNetwork::with_backend(relay, 4, 100_000, |cfg| {
+            cfg.network = btc::Chain::Regtest;
+            cfg.frequency = 10;
+            cfg.utxo_confirmations = 2;
+        });
+        network.check_lifecycle();
+    }
+}
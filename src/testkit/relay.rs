@@ -0,0 +1,349 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! In-memory bitcoin backend.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::util::hash::Sha256dHash;
+use bitcoin::network::serialize::BitcoinHash;
+
+use details::btc;
+use details::btc::transactions::{BitcoinTx, FundingTx, RawBitcoinTx, TxKind};
+use details::rpc::{other_error, AnchoringRpcConfig, BitcoinRelay, Result, TxInfo};
+
+use super::TestBitcoinBackend;
+
+/// Bitcoin backend which keeps the fake bitcoin blockchain in memory.
+///
+/// Transactions sent to the backend are kept in the mempool until the next
+/// [`mine_blocks`](trait.TestBitcoinBackend.html#tymethod.mine_blocks) call, a transaction which double spends
+/// the mempool ones replaces them. Clones of the backend share the same blockchain,
+/// so the test can mine blocks and inject reorgs while the anchoring service uses it.
+#[derive(Debug, Clone, Default)]
+pub struct FakeBitcoinRelay {
+    chain: Arc<Mutex<FakeChain>>,
+}
+
+#[derive(Debug, Default)]
+struct FakeChain {
+    txs: HashMap<btc::TxId, BitcoinTx>,
+    // Indexes of the blocks which include the transactions.
+    included: HashMap<btc::TxId, u64>,
+    mempool: Vec<btc::TxId>,
+    blocks: Vec<Vec<btc::TxId>>,
+    watched: HashSet<String>,
+    fee_rate: Option<u64>,
+    nonce: u64,
+}
+
+impl FakeBitcoinRelay {
+    /// Creates the backend with the empty blockchain.
+    pub fn new() -> FakeBitcoinRelay {
+        FakeBitcoinRelay::default()
+    }
+
+    /// Removes the transaction with the given `txid` from the mempool, as if it has been
+    /// evicted or orphaned by a reorg without returning to the mempool.
+    pub fn drop_transaction(&self, txid: btc::TxId) {
+        let mut chain = self.chain.lock().unwrap();
+        if !chain.included.contains_key(&txid) {
+            chain.mempool.retain(|id| *id != txid);
+            chain.txs.remove(&txid);
+        }
+    }
+
+    /// Returns the transactions in the mempool in the order of their arrival.
+    pub fn mempool(&self) -> Vec<BitcoinTx> {
+        let chain = self.chain.lock().unwrap();
+        chain.mempool.iter().map(|txid| chain.txs[txid].clone()).collect()
+    }
+
+    /// Returns the number of the mined blocks.
+    pub fn height(&self) -> u64 {
+        self.chain.lock().unwrap().blocks.len() as u64
+    }
+
+    /// Returns true if the given address is watched by the backend.
+    pub fn is_watched(&self, addr: &btc::Address) -> bool {
+        self.chain.lock().unwrap().watched.contains(&addr.to_string())
+    }
+
+    /// Sets the fee rate in satoshis per byte returned by the fee estimation.
+    pub fn set_fee_rate(&self, fee_rate: Option<u64>) {
+        self.chain.lock().unwrap().fee_rate = fee_rate;
+    }
+}
+
+impl TestBitcoinBackend for FakeBitcoinRelay {
+    fn mine_blocks(&self, count: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        for _ in 0..count {
+            let index = chain.blocks.len() as u64;
+            let block = ::std::mem::replace(&mut chain.mempool, Vec::new());
+            for txid in &block {
+                chain.included.insert(*txid, index);
+            }
+            chain.blocks.push(block);
+        }
+    }
+
+    fn reorg(&self, depth: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        let mut orphaned_blocks = Vec::new();
+        for _ in 0..depth {
+            match chain.blocks.pop() {
+                Some(block) => orphaned_blocks.push(block),
+                None => break,
+            }
+        }
+        orphaned_blocks.reverse();
+        let mut orphaned = orphaned_blocks.concat();
+        for txid in &orphaned {
+            chain.included.remove(txid);
+        }
+        orphaned.extend(chain.mempool.drain(..));
+        chain.mempool = orphaned;
+    }
+}
+
+impl FakeChain {
+    fn confirmations(&self, txid: &btc::TxId) -> Option<u64> {
+        if let Some(index) = self.included.get(txid) {
+            Some(self.blocks.len() as u64 - index)
+        } else if self.txs.contains_key(txid) {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    // Transactions in the order of the blocks followed by the mempool.
+    fn ordered_txids(&self) -> Vec<btc::TxId> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.iter())
+            .chain(self.mempool.iter())
+            .cloned()
+            .collect()
+    }
+
+    // Returns the known transactions which spend the same outputs as the given one.
+    fn conflicts(&self, tx: &RawBitcoinTx) -> Vec<btc::TxId> {
+        let txid = btc::TxId::from(tx.bitcoin_hash());
+        self.txs
+            .iter()
+            .filter(|&(id, other)| {
+                *id != txid &&
+                    other.input.iter().any(|other_input| {
+                        tx.input.iter().any(|input| {
+                            input.prev_hash == other_input.prev_hash &&
+                                input.prev_index == other_input.prev_index
+                        })
+                    })
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn is_spent(&self, txid: &btc::TxId, out: u32) -> bool {
+        self.txs.values().any(|tx| {
+            tx.input.iter().any(|input| {
+                btc::TxId::from(input.prev_hash) == *txid && input.prev_index == out
+            })
+        })
+    }
+}
+
+impl BitcoinRelay for FakeBitcoinRelay {
+    fn get_transaction(&self, txid: btc::TxId) -> Result<Option<BitcoinTx>> {
+        Ok(self.chain.lock().unwrap().txs.get(&txid).cloned())
+    }
+
+    fn get_transaction_info(&self, txid: btc::TxId) -> Result<Option<TxInfo>> {
+        let chain = self.chain.lock().unwrap();
+        Ok(chain.txs.get(&txid).map(|tx| {
+            TxInfo {
+                body: tx.clone(),
+                confirmations: chain.confirmations(&txid),
+            }
+        }))
+    }
+
+    fn watch_address(&self, addr: &btc::Address, _rescan: bool) -> Result<()> {
+        self.chain.lock().unwrap().watched.insert(addr.to_string());
+        Ok(())
+    }
+
+    fn send_transaction(&self, tx: BitcoinTx) -> Result<()> {
+        let mut chain = self.chain.lock().unwrap();
+        let txid = tx.id();
+        if chain.txs.contains_key(&txid) {
+            return Ok(());
+        }
+
+        let conflicts = chain.conflicts(&tx);
+        if conflicts.iter().any(|id| chain.included.contains_key(id)) {
+            return Err(other_error(
+                format!("Transaction txid={} spends the mined outputs", tx.txid()),
+            ));
+        }
+        // The replaced transactions are removed from the mempool.
+        for id in &conflicts {
+            chain.txs.remove(id);
+        }
+        chain.mempool.retain(|id| !conflicts.contains(id));
+        chain.txs.insert(txid, tx);
+        chain.mempool.push(txid);
+        Ok(())
+    }
+
+    fn send_to_address(&self, addr: &btc::Address, satoshis: u64) -> Result<FundingTx> {
+        let tx = {
+            let mut chain = self.chain.lock().unwrap();
+            chain.nonce += 1;
+            // The funding transaction spends an output of the unknown transaction.
+            let prev_hash = Sha256dHash::from_data(&chain.nonce.to_string().into_bytes());
+            RawBitcoinTx {
+                version: 1,
+                lock_time: 0,
+                input: vec![
+                    TxIn {
+                        prev_hash,
+                        prev_index: 0,
+                        script_sig: Script::new(),
+                        sequence: 0xFFFF_FFFF,
+                    },
+                ],
+                output: vec![
+                    TxOut {
+                        value: satoshis,
+                        script_pubkey: addr.script_pubkey(),
+                    },
+                ],
+                witness: vec![],
+            }
+        };
+        self.send_transaction(BitcoinTx::from(tx.clone()))?;
+        Ok(FundingTx::from(tx))
+    }
+
+    fn unspent_transactions(&self, addr: &btc::Address) -> Result<Vec<TxInfo>> {
+        let chain = self.chain.lock().unwrap();
+        let script_pubkey = addr.script_pubkey();
+
+        let mut txs = Vec::new();
+        for txid in chain.ordered_txids() {
+            let tx = &chain.txs[&txid];
+            let has_unspent_output = tx.output.iter().enumerate().any(|(out, output)| {
+                output.script_pubkey == script_pubkey && !chain.is_spent(&txid, out as u32)
+            });
+            if !has_unspent_output {
+                continue;
+            }
+            match TxKind::from(tx.clone()) {
+                TxKind::Anchoring(_) |
+                TxKind::FundingTx(_) => {
+                    txs.push(TxInfo {
+                        body: tx.clone(),
+                        confirmations: chain.confirmations(&txid),
+                    })
+                }
+                TxKind::Other(_) => {}
+            }
+        }
+        Ok(txs)
+    }
+
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let chain = self.chain.lock().unwrap();
+        let script_pubkey = addr.script_pubkey();
+
+        let mut outputs = Vec::new();
+        for txid in chain.ordered_txids() {
+            let tx = &chain.txs[&txid];
+            for (out, output) in tx.output.iter().enumerate() {
+                let out = out as u32;
+                if output.script_pubkey == script_pubkey && !chain.is_spent(&txid, out) {
+                    outputs.push((txid, out));
+                }
+            }
+        }
+        Ok(outputs)
+    }
+
+    fn estimate_fee_rate(&self, _conf_target: u16) -> Result<Option<u64>> {
+        Ok(self.chain.lock().unwrap().fee_rate)
+    }
+
+    fn tip_height(&self) -> Result<Option<u64>> {
+        Ok(Some(self.height()))
+    }
+
+    fn chain(&self) -> Result<Option<btc::Chain>> {
+        Ok(Some(btc::Chain::Testnet))
+    }
+
+    fn verification_progress(&self) -> Result<Option<f64>> {
+        Ok(Some(1.0))
+    }
+
+    fn config(&self) -> AnchoringRpcConfig {
+        AnchoringRpcConfig {
+            host: "fake".to_string(),
+            username: None,
+            password: None,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use details::btc;
+    use details::btc::transactions::BitcoinTx;
+    use details::rpc::BitcoinRelay;
+    use testkit::TestBitcoinBackend;
+
+    use super::FakeBitcoinRelay;
+
+    #[test]
+    fn test_fake_relay_confirmations_and_reorg() {
+        let relay = FakeBitcoinRelay::new();
+        let addr = "2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA".parse::<btc::Address>().unwrap();
+
+        let funding_tx = relay.send_to_address(&addr, 4000).unwrap();
+        assert_eq!(relay.get_transaction_confirmations(funding_tx.id()).unwrap(), Some(0));
+        assert_eq!(relay.unspent_transactions(&addr).unwrap().len(), 1);
+
+        relay.mine_blocks(3);
+        assert_eq!(relay.height(), 3);
+        assert_eq!(relay.get_transaction_confirmations(funding_tx.id()).unwrap(), Some(3));
+
+        relay.reorg(3);
+        assert_eq!(relay.get_transaction_confirmations(funding_tx.id()).unwrap(), Some(0));
+        assert_eq!(relay.mempool(), vec![BitcoinTx::from(funding_tx.0.clone())]);
+
+        relay.mine_blocks(1);
+        assert!(relay.mempool().is_empty());
+        assert_eq!(relay.get_transaction_confirmations(funding_tx.id()).unwrap(), Some(1));
+
+        relay.send_to_address(&addr, 1000).unwrap();
+        assert_eq!(relay.unspent_transactions(&addr).unwrap().len(), 2);
+    }
+}
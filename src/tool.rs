@@ -0,0 +1,320 @@
+// Copyright 2017 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Maintenance tools of the anchoring deployments used by the `anchoring_tool` binary.
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use exonum::crypto::Hash;
+use exonum::encoding::serialize::ToHex;
+use rand::{thread_rng, Rng};
+
+use details::btc;
+use details::btc::transactions::{AnchoringTx, FundingTx, TxKind};
+use details::rpc::{other_error, AnchoringRpcConfig, BitcoinRelay, Result as RpcResult};
+use blockchain::consensus_storage::AnchoringConfig;
+use blockchain::verification::find_latest_anchoring_tx;
+use local_storage::AnchoringNodeConfig;
+
+/// Anchoring transaction of the inspected anchoring chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnchorInfo {
+    /// `Txid` of the anchoring transaction.
+    pub txid: btc::TxId,
+    /// Anchored block height.
+    pub block_height: u64,
+    /// Anchored block hash.
+    pub block_hash: Hash,
+    /// Fee of the anchoring transaction in satoshis if all its inputs are found.
+    pub fee: Option<u64>,
+    /// Number of the confirmations, `None` if the transaction is not known
+    /// by the bitcoin backend.
+    pub confirmations: Option<u64>,
+}
+
+/// Anchoring chain followed from the funding transaction up to the latest
+/// anchoring transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainInfo {
+    /// `Txid` of the funding transaction which starts the chain, `None` if it is not reached.
+    pub funding_tx: Option<btc::TxId>,
+    /// Anchoring transactions from the first one up to the latest one.
+    pub anchors: Vec<AnchorInfo>,
+}
+
+/// Follows the anchoring chain of the anchoring address `addr` by the given bitcoin `relay`
+/// from the latest anchoring transaction back to the funding transaction.
+pub fn inspect_anchoring_chain(
+    relay: &BitcoinRelay,
+    addr: &btc::Address,
+) -> RpcResult<ChainInfo> {
+    let mut chain = ChainInfo {
+        funding_tx: None,
+        anchors: Vec::new(),
+    };
+    let mut tx = match find_latest_anchoring_tx(relay, addr)? {
+        Some(tx) => tx,
+        None => return Ok(chain),
+    };
+
+    let mut visited = HashSet::new();
+    while visited.insert(tx.id()) {
+        let payload = tx.payload();
+        let confirmations = match relay.get_transaction_info(tx.id())? {
+            Some(info) => info.confirmations,
+            None => None,
+        };
+        chain.anchors.push(AnchorInfo {
+            txid: tx.id(),
+            block_height: payload.block_height.0,
+            block_hash: payload.block_hash,
+            fee: anchoring_tx_fee(relay, &tx)?,
+            confirmations,
+        });
+
+        match relay.get_transaction(tx.prev_hash())?.map(TxKind::from) {
+            Some(TxKind::Anchoring(prev_tx)) => tx = prev_tx,
+            Some(TxKind::FundingTx(funding_tx)) => {
+                // The recovering transaction continues the chain which has been lost.
+                let lost_tx = match payload.prev_tx_chain {
+                    Some(txid) => relay.get_transaction(txid)?,
+                    None => None,
+                };
+                match lost_tx.map(TxKind::from) {
+                    Some(TxKind::Anchoring(lost_tx)) => tx = lost_tx,
+                    _ => {
+                        chain.funding_tx = Some(funding_tx.id());
+                        break;
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+    chain.anchors.reverse();
+    Ok(chain)
+}
+
+/// Formats the inspected anchoring chain as a text table.
+pub fn format_chain_table(chain: &ChainInfo) -> String {
+    let mut table = format!(
+        "{:<64}  {:>10}  {:<64}  {:>8}  {:>13}\n",
+        "txid",
+        "height",
+        "block_hash",
+        "fee",
+        "confirmations"
+    );
+    for anchor in &chain.anchors {
+        let fee = anchor.fee.map(|fee| fee.to_string()).unwrap_or_default();
+        let confirmations = anchor
+            .confirmations
+            .map(|confirmations| confirmations.to_string())
+            .unwrap_or_default();
+        table.push_str(&format!(
+            "{:<64}  {:>10}  {:<64}  {:>8}  {:>13}\n",
+            anchor.txid.to_string(),
+            anchor.block_height,
+            anchor.block_hash.to_hex(),
+            fee,
+            confirmations
+        ));
+    }
+    match chain.funding_tx {
+        Some(txid) => table.push_str(&format!("funding_tx: {}\n", txid.to_string())),
+        None => table.push_str("funding_tx: not found\n"),
+    }
+    table
+}
+
+/// Configuration of a new anchoring deployment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedConfig {
+    /// Common anchoring configuration without the funding transaction,
+    /// it has to be funded before the genesis block is created.
+    pub genesis: AnchoringConfig,
+    /// Anchoring address of the common configuration.
+    pub address: btc::Address,
+    /// Redeem script of the anchoring address.
+    pub redeem_script: btc::RedeemScript,
+    /// Configurations of the validator nodes in the order of the anchoring keys.
+    pub nodes: Vec<AnchoringNodeConfig>,
+}
+
+/// Generates the anchoring keys of `count` validators and the configuration
+/// of a new anchoring deployment with the given `fee` and `frequency`
+/// using given random number generator.
+///
+/// Each node configuration contains the private key of the validator and the given `rpc`.
+pub fn gen_anchoring_config_with_rng<R: Rng>(
+    network: btc::Chain,
+    count: u8,
+    fee: u64,
+    frequency: u64,
+    rpc: Option<AnchoringRpcConfig>,
+    rng: &mut R,
+) -> GeneratedConfig {
+    let mut pub_keys = Vec::new();
+    let mut priv_keys = Vec::new();
+    for _ in 0..count {
+        let (pub_key, priv_key) = btc::gen_btc_keypair_with_rng(network.base58_network(), rng);
+        pub_keys.push(pub_key);
+        priv_keys.push(priv_key);
+    }
+
+    let mut genesis = AnchoringConfig::new(network, pub_keys);
+    genesis.fee = fee;
+    genesis.frequency = frequency;
+    let (redeem_script, address) = genesis.redeem_script();
+
+    let nodes = priv_keys
+        .into_iter()
+        .map(|priv_key| {
+            let mut node = AnchoringNodeConfig::new(rpc.clone());
+            node.private_keys.insert(address.to_string(), priv_key);
+            node
+        })
+        .collect();
+    GeneratedConfig {
+        genesis,
+        address,
+        redeem_script,
+        nodes,
+    }
+}
+
+/// Same as [`gen_anchoring_config_with_rng`](fn.gen_anchoring_config_with_rng.html)
+/// but it uses default random number generator.
+pub fn gen_anchoring_config(
+    network: btc::Chain,
+    count: u8,
+    fee: u64,
+    frequency: u64,
+    rpc: Option<AnchoringRpcConfig>,
+) -> GeneratedConfig {
+    let mut rng = thread_rng();
+    gen_anchoring_config_with_rng(network, count, fee, frequency, rpc, &mut rng)
+}
+
+/// Sends `satoshis` from the wallet of the bitcoin `relay` to the anchoring address `addr`
+/// and waits until the funding transaction gets `confirmations` confirmations, checking them
+/// with the given `poll_interval`.
+///
+/// The address is watched by the relay beforehand, so the service is able to find
+/// the funding transaction among the unspent outputs of the address.
+pub fn fund_anchoring_address(
+    relay: &BitcoinRelay,
+    addr: &btc::Address,
+    satoshis: u64,
+    confirmations: u64,
+    poll_interval: Duration,
+) -> RpcResult<FundingTx> {
+    relay.watch_address(addr, false)?;
+    let tx = relay.send_to_address(addr, satoshis)?;
+    if tx.find_out(addr).is_none() {
+        let msg = format!(
+            "Funding transaction {} has no output to the anchoring address",
+            tx.id()
+        );
+        return Err(other_error(msg));
+    }
+    info!("Sent the funding transaction {}", tx.id());
+
+    loop {
+        let current = relay.get_transaction_confirmations(tx.id())?.unwrap_or(0);
+        if current >= confirmations {
+            return Ok(tx);
+        }
+        info!(
+            "Funding transaction {} has {} of {} confirmations",
+            tx.id(),
+            current,
+            confirmations
+        );
+        thread::sleep(poll_interval);
+    }
+}
+
+// The spent outputs are requested from the relay, so the fee of the transaction
+// with an unknown input is unknown too.
+fn anchoring_tx_fee(relay: &BitcoinRelay, tx: &AnchoringTx) -> RpcResult<Option<u64>> {
+    let mut spent_value = 0;
+    for input in &tx.input {
+        let prev_tx = match relay.get_transaction(btc::TxId::from(input.prev_hash))? {
+            Some(prev_tx) => prev_tx,
+            None => return Ok(None),
+        };
+        match prev_tx.output.get(input.prev_index as usize) {
+            Some(out) => spent_value += out.value,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(spent_value.saturating_sub(tx.total_amount())))
+}
+
+#[cfg(test)]
+mod tests {
+    use exonum::crypto::hash;
+    use exonum::encoding::serialize::FromHex;
+    use secp256k1::Secp256k1;
+
+    use details::btc;
+
+    use super::{format_chain_table, gen_anchoring_config, AnchorInfo, ChainInfo};
+
+    #[test]
+    fn test_format_chain_table() {
+        let txid = btc::TxId::from_hex(
+            "8e9aa2bbe5f8357f4a3886bae4b1e85b0b7e83939bbbe9450e5ab2bf3defb12b",
+        ).unwrap();
+        let chain = ChainInfo {
+            funding_tx: None,
+            anchors: vec![
+                AnchorInfo {
+                    txid,
+                    block_height: 10,
+                    block_hash: hash(&[1, 2, 3]),
+                    fee: Some(1000),
+                    confirmations: None,
+                },
+            ],
+        };
+        let table = format_chain_table(&chain);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(&txid.to_string()));
+        assert!(lines[1].contains("  1000  "));
+        assert_eq!(lines[2], "funding_tx: not found");
+    }
+
+    #[test]
+    fn test_gen_anchoring_config() {
+        let config = gen_anchoring_config(btc::Chain::Testnet, 4, 1000, 20, None);
+        assert_eq!(config.genesis.anchoring_keys.len(), 4);
+        assert_eq!(config.genesis.fee, 1000);
+        assert_eq!(config.genesis.frequency, 20);
+        assert_eq!(config.genesis.funding_tx, None);
+        assert_eq!(config.genesis.redeem_script(), (config.redeem_script, config.address));
+        assert_eq!(config.nodes.len(), 4);
+
+        let context = Secp256k1::new();
+        for (node, pub_key) in config.nodes.iter().zip(&config.genesis.anchoring_keys) {
+            let priv_key = &node.private_keys[&config.address.to_string()];
+            let key = btc::PublicKey::from_secret_key(&context, priv_key.secret_key()).unwrap();
+            assert_eq!(&key, pub_key);
+        }
+    }
+}
@@ -33,22 +33,31 @@ extern crate exonum_testkit;
 #[macro_use]
 pub mod testkit_extras;
 
+use std::io;
 use std::ops::Deref;
 
 use bitcoin::blockdata::transaction::SigHashType;
 use bitcoin::network::constants::Network;
 use bitcoin::blockdata::script::Script;
 
-use exonum::blockchain::Transaction;
+use exonum::blockchain::{Schema, Transaction};
 use exonum::messages::Message;
 use exonum::helpers::{Height, ValidatorId};
-use exonum::encoding::serialize::FromHex;
+use exonum::encoding::serialize::{FromHex, ToHex};
 use exonum::crypto::Hash;
 
-use exonum_btc_anchoring::blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest};
+use exonum_btc_anchoring::{AnchoringConfig, ANCHORING_SERVICE_NAME};
+use exonum_btc_anchoring::handler::alert::AnchoringAlert;
+use exonum_btc_anchoring::handler::error::Error as HandlerError;
+use exonum_btc_anchoring::handler::event::AnchoringEvent;
+use exonum_btc_anchoring::handler::state::AnchoringPhase;
+use exonum_btc_anchoring::blockchain::dto::{MisbehaviorKind, MsgAnchoringSignature,
+                                            MsgAnchoringUpdateLatest};
+use exonum_btc_anchoring::details::btc;
 use exonum_btc_anchoring::details::btc::transactions::{verify_tx_input, AnchoringTx, FundingTx,
                                                        TransactionBuilder};
-use testkit_extras::AnchoringTestKit;
+use exonum_btc_anchoring::details::rpc::other_error;
+use testkit_extras::{AnchoringTestKit, TestClient};
 use testkit_extras::helpers::*;
 
 // We anchor first block
@@ -60,6 +69,100 @@ fn test_anchoring_first_block_simple() {
     anchor_first_block(&mut testkit);
 }
 
+// We anchor first block and check the handler metrics
+// problems: None
+// result: success
+#[test]
+fn test_anchoring_first_block_metrics() {
+    let mut testkit = AnchoringTestKit::default();
+    anchor_first_block(&mut testkit);
+    let metrics = testkit.handler().metrics.clone();
+    assert_eq!(metrics.anchored_height, Some(0));
+    assert_eq!(metrics.anchors, 1);
+    assert_eq!(metrics.anchor_fee, Some(testkit.current_cfg().fee));
+    assert!(metrics.signature_round_duration.is_some());
+
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+    let metrics = testkit.handler().metrics.clone();
+    assert_eq!(metrics.lect_confirmations, Some(0));
+    assert_eq!(metrics.balance, Some(anchored_tx.amount()));
+    assert_eq!(metrics.rpc_errors, 0);
+}
+
+// We anchor first block and check the phases of the handler state machine
+// problems: None
+// result: success
+#[test]
+fn test_anchoring_first_block_phases() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    requests.expect(vec![confirmations_request(&testkit.current_funding_tx(), 0)]);
+    testkit.create_block();
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::WaitingForFunding);
+
+    requests.expect(vec![
+        confirmations_request(&testkit.current_funding_tx(), 50),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.current_funding_tx(), &anchoring_addr, 50)
+            ]
+        },
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    let hash = Schema::new(testkit.snapshot())
+        .block_hashes_by_height()
+        .get(0)
+        .unwrap();
+    let (_, signatures) =
+        testkit.gen_anchoring_tx_with_signatures(Height::zero(), hash, &[], None, &anchoring_addr);
+    let anchored_tx = testkit.latest_anchored_tx();
+    testkit.create_block();
+    assert_eq!(
+        testkit.handler().phase(),
+        AnchoringPhase::CollectingSignatures
+    );
+    {
+        let handler = testkit.handler();
+        let round = handler.state.round.as_ref().unwrap();
+        assert_eq!(Some(&round.proposal), handler.proposal_tx.as_ref());
+        assert_eq!(round.sighashes.keys().collect::<Vec<_>>(), vec![&0]);
+        assert_eq!(round.signatures.keys().collect::<Vec<_>>(), vec![&0]);
+    }
+
+    requests.expect(vec![
+        confirmations_request(&testkit.current_funding_tx(), 50),
+        request! {
+            method: "getrawtransaction",
+            params: [&anchored_tx.txid(), 0],
+            error: RpcError::NoInformation("Unable to find tx".to_string())
+        },
+        request! {
+            method: "sendrawtransaction",
+            params: [anchored_tx.to_hex()],
+            response: anchored_tx.to_hex()
+        },
+    ]);
+    testkit.create_block_with_transactions(signatures);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Broadcasting);
+    assert_eq!(testkit.handler().state.broadcasted, Some(anchored_tx.id()));
+    assert_eq!(testkit.handler().state.round, None);
+
+    let txs = (0..4)
+        .map(|idx| {
+            gen_service_tx_lect(&testkit, ValidatorId(idx), &anchored_tx, 1)
+        })
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(txs);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Proposing);
+    assert_eq!(testkit.handler().state.broadcasted, None);
+}
+
 // We wait until `funding_tx` have got enough confirmations.
 // problems: None
 // result: success
@@ -80,6 +183,217 @@ fn test_anchoring_funding_tx_waiting() {
 }
 
 
+// We anchor first block with the verification of the proposal inputs
+// problems: None
+// result: success
+#[test]
+fn test_anchoring_first_block_verify_inputs() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+    let funding_tx = testkit.current_funding_tx();
+    testkit.handler().node.verify_proposal_inputs = true;
+
+    requests.expect(vec![
+        confirmations_request(&funding_tx, 50),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&funding_tx, &anchoring_addr, 50)
+            ]
+        },
+        get_transaction_request(&funding_tx),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&funding_tx, &anchoring_addr, 50)
+            ]
+        },
+    ]);
+
+    let hash = testkit.last_block_hash();
+    let (_, signatures) =
+        testkit.gen_anchoring_tx_with_signatures(Height::zero(), hash, &[], None, &anchoring_addr);
+    testkit.create_block();
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+}
+
+// We anchor first block with the verification of the proposal inputs
+// problems: `funding_tx` disappears from the unspent outputs before the proposal is signed
+// result: we do not sign the proposal and report error IncorrectProposal
+#[test]
+fn test_anchoring_first_block_spent_input() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+    let funding_tx = testkit.current_funding_tx();
+    testkit.handler().node.verify_proposal_inputs = true;
+
+    requests.expect(vec![
+        confirmations_request(&funding_tx, 50),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&funding_tx, &anchoring_addr, 50)
+            ]
+        },
+        get_transaction_request(&funding_tx),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: []
+        },
+    ]);
+    testkit.create_block();
+
+    assert!(testkit.mempool().is_empty());
+    assert!(testkit.handler().proposal_tx.is_none());
+    let errors = testkit.take_handler_errors();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        HandlerError::IncorrectProposal { ref tx, .. } => {
+            assert_eq!(btc::TxId::from(tx.input[0].prev_hash), funding_tx.id());
+        }
+        ref e => panic!("Unexpected error={}", e),
+    }
+}
+
+
+// We anchor first block with the verification of the proposal inputs
+// problems: the unspent outputs contain another output of `funding_tx` than the spent one
+// result: we do not sign the proposal and report error IncorrectProposal
+#[test]
+fn test_anchoring_first_block_spent_output() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+    let funding_tx = testkit.current_funding_tx();
+    testkit.handler().node.verify_proposal_inputs = true;
+
+    let mut other_output = listunspent_entry(&funding_tx, &anchoring_addr, 50);
+    other_output["vout"] = json!(1);
+    requests.expect(vec![
+        confirmations_request(&funding_tx, 50),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&funding_tx, &anchoring_addr, 50)
+            ]
+        },
+        get_transaction_request(&funding_tx),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [other_output]
+        },
+    ]);
+    testkit.create_block();
+
+    assert!(testkit.mempool().is_empty());
+    assert!(testkit.handler().proposal_tx.is_none());
+    let errors = testkit.take_handler_errors();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        HandlerError::IncorrectProposal { ref tx, .. } => {
+            assert_eq!(btc::TxId::from(tx.input[0].prev_hash), funding_tx.id());
+            assert_eq!(tx.input[0].prev_index, 0);
+        }
+        ref e => panic!("Unexpected error={}", e),
+    }
+}
+
+
+// We anchor first block, but the anchoring transaction is already mined
+// problems: `sendrawtransaction` fails with the "already in block chain" error
+// result: we consider the transaction as broadcasted and update our lect
+#[test]
+fn test_anchoring_first_block_already_in_chain() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+    let funding_tx = testkit.current_funding_tx();
+
+    requests.expect(vec![
+        confirmations_request(&funding_tx, 50),
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&funding_tx, &anchoring_addr, 50)
+            ]
+        },
+        get_transaction_request(&funding_tx),
+    ]);
+    let hash = testkit.last_block_hash();
+    let (_, signatures) =
+        testkit.gen_anchoring_tx_with_signatures(Height::zero(), hash, &[], None, &anchoring_addr);
+    let anchored_tx = testkit.latest_anchored_tx();
+    testkit.create_block();
+
+    requests.expect(vec![
+        confirmations_request(&funding_tx, 50),
+        request! {
+            method: "getrawtransaction",
+            params: [&anchored_tx.txid(), 0],
+            error: RpcError::NoInformation("Unable to find tx".to_string())
+        },
+        request! {
+            method: "sendrawtransaction",
+            params: [anchored_tx.to_hex()],
+            error: other_error(json!({
+                "code": -27,
+                "message": "Transaction already in block chain"
+            }))
+        },
+    ]);
+    testkit.create_block_with_transactions(signatures);
+
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), &anchored_tx, 1);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
+    assert!(testkit.take_handler_errors().is_empty());
+}
+
+
+// We start the node while the bitcoin backend is unavailable
+// problems: the genesis anchoring address is not imported
+// result: the address import is retried on the next block
+#[test]
+fn test_anchoring_genesis_import_address_retry() {
+    let mut testkit = AnchoringTestKit::new_with_pending_import();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+    let funding_tx = testkit.current_funding_tx();
+
+    requests.expect(vec![
+        request! {
+            method: "importaddress",
+            params: [&anchoring_addr, "multisig", false, false],
+            error: RpcError::Other(JsonRpcError::Json(serde_json::Error::io(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "Connection refused",
+            ))))
+        },
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.handler().retry.failures, 1);
+    assert_eq!(testkit.handler().pending_addresses, vec![anchoring_addr.clone()]);
+
+    requests.expect(vec![
+        request! {
+            method: "importaddress",
+            params: [&anchoring_addr, "multisig", false, false]
+        },
+        confirmations_request(&funding_tx, 0),
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.handler().retry.failures, 0);
+    assert!(testkit.handler().pending_addresses.is_empty());
+}
+
 // We anchor first block and receive lect
 // problems: None
 // result: success
@@ -90,6 +404,50 @@ fn test_anchoring_update_lect_normal() {
     anchor_first_block_lect_normal(&mut testkit);
 }
 
+// We anchor first block and fail to update lect
+// problems: bitcoin backend is unavailable during the lect check
+// result: we retry lect update on the next block
+#[test]
+fn test_anchoring_update_lect_retry() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    anchor_first_block(&mut testkit);
+
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
+
+    let anchored_tx = testkit.latest_anchored_tx();
+    let anchoring_addr = testkit.current_addr();
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            error: RpcError::Other(JsonRpcError::Json(serde_json::Error::io(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "Connection refused",
+            ))))
+        },
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.handler().retry.failures, 1);
+    assert!(testkit.handler().retry.lect_update);
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&anchored_tx, &anchoring_addr, 0)
+            ]
+        },
+        get_transaction_request(&anchored_tx),
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.handler().retry.failures, 0);
+    assert!(!testkit.handler().retry.lect_update);
+    assert_eq!(testkit.handler().metrics.lect_confirmations, Some(0));
+}
+
 // We anchor first block and receive lect with different but correct signatures
 // problems: lect with a different signature set
 // result: success with a new lect
@@ -108,72 +466,897 @@ fn test_anchoring_first_block_lect_lost() {
     anchor_first_block_lect_lost(&mut testkit);
 }
 
-// We anchor second block after successfuly anchored first
-// problems: none
-// result: success
+// We anchor second block after successfuly anchored first
+// problems: none
+// result: success
+#[test]
+fn test_anchoring_second_block_normal() {
+    let mut testkit = AnchoringTestKit::default();
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    anchor_second_block_normal(&mut testkit);
+}
+
+// We change the anchoring frequency and fee by the configuration update
+// problems: none
+// result: the next anchoring transaction anchors the block at the height multiple
+// of the new frequency and pays the new fee
+#[test]
+fn test_anchoring_change_frequency_and_fee() {
+    let cfg_change_height = Height(8);
+
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let mut cfg_proposal = testkit.configuration_change_proposal();
+    cfg_proposal.set_actual_from(cfg_change_height);
+    let mut following_cfg: AnchoringConfig =
+        cfg_proposal.service_config(ANCHORING_SERVICE_NAME);
+    following_cfg.frequency = 8;
+    following_cfg.fee = 2000;
+    cfg_proposal.set_service_config(ANCHORING_SERVICE_NAME, following_cfg.clone());
+    testkit.commit_configuration_change(cfg_proposal);
+    testkit.create_blocks_until(cfg_change_height.previous());
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 2)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.current_cfg(), following_cfg);
+
+    let last_block_hash = testkit.last_block_hash();
+    let (proposal, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        cfg_change_height,
+        last_block_hash,
+        &[],
+        None,
+        &anchoring_addr,
+    );
+    assert_eq!(proposal.payload().block_height, cfg_change_height);
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+}
+
+// We enable the low funds alerts with the stretched anchoring frequency
+// by the configuration update
+// problems: the lect funds cover less anchoring transactions than required
+// result: the handler alerts once and postpones the anchoring up to the height
+// multiple of the stretched frequency
+#[test]
+fn test_anchoring_low_funds() {
+    let cfg_change_height = Height(8);
+
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let mut cfg_proposal = testkit.configuration_change_proposal();
+    cfg_proposal.set_actual_from(cfg_change_height);
+    let mut following_cfg: AnchoringConfig =
+        cfg_proposal.service_config(ANCHORING_SERVICE_NAME);
+    following_cfg.frequency = 8;
+    following_cfg.low_funds_anchors = 10;
+    following_cfg.low_funds_frequency = Some(16);
+    cfg_proposal.set_service_config(ANCHORING_SERVICE_NAME, following_cfg.clone());
+    testkit.commit_configuration_change(cfg_proposal);
+    testkit.create_blocks_until(cfg_change_height.previous());
+    assert!(testkit.take_handler_alerts().is_empty());
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 2)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+    ]);
+    testkit.create_block();
+    assert_eq!(testkit.current_cfg(), following_cfg);
+
+    let funds = testkit.latest_anchored_tx().amount();
+    assert_eq!(
+        testkit.take_handler_alerts(),
+        vec![
+            AnchoringAlert::LowFunds {
+                address: anchoring_addr.clone(),
+                funds,
+                anchors_left: funds / following_cfg.fee,
+            },
+        ]
+    );
+    // The height multiple of the frequency is not anchored.
+    assert!(testkit.mempool().is_empty());
+
+    testkit.create_block();
+    assert!(testkit.mempool().is_empty());
+    assert!(testkit.take_handler_alerts().is_empty());
+}
+
+// We subscribe to the handler events and anchor the first block
+// problems: none
+// result: success with the proposal, signatures and broadcast events
+#[test]
+fn test_anchoring_lifecycle_events() {
+    let mut testkit = AnchoringTestKit::default();
+    let events = testkit.handler().subscribe_events();
+
+    anchor_first_block(&mut testkit);
+
+    let anchored_tx = testkit.latest_anchored_tx();
+    let received = events.try_iter().collect::<Vec<_>>();
+    assert_eq!(
+        received,
+        vec![
+            AnchoringEvent::ProposalCreated {
+                txid: anchored_tx.id(),
+                height: 0,
+            },
+            AnchoringEvent::SignaturesCollected {
+                txid: anchored_tx.id(),
+                signatures: 4,
+            },
+            AnchoringEvent::AnchorBroadcast { txid: anchored_tx.id() },
+        ]
+    );
+
+    let entries = testkit.handler().events_since(1, 10);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, 1);
+    assert_eq!(entries[1].event, received[2]);
+}
+
+// We reload the node configuration with the new bitcoin backend
+// problems: none
+// result: success with the anchoring address imported to the new backend
+#[test]
+fn test_anchoring_reload_node_config() {
+    let mut testkit = AnchoringTestKit::default();
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let client = TestClient::default();
+    let requests = client.requests();
+    let node = testkit.nodes()[0].clone();
+    testkit.handler().set_node_config(node, Some(Box::new(client)));
+
+    let anchoring_addr = testkit.current_addr();
+    requests.expect(vec![
+        request! {
+            method: "importaddress",
+            params: [&anchoring_addr, "multisig", false, false]
+        },
+    ]);
+    testkit.create_block();
+    assert!(testkit.handler().pending_addresses.is_empty());
+    assert!(testkit.take_handler_errors().is_empty());
+}
+
+// We anchor second block after successfuly anchored first with additional funds
+// problems: none
+// result: success
+#[test]
+fn test_anchoring_second_block_additional_funds() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let funds = {
+        let mut funds = testkit.current_funding_tx();
+        funds.0.lock_time += 1;
+        funds
+    };
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(1), &funds);
+    testkit.create_block_with_transactions(txvec![notice]);
+
+    let height = testkit.next_anchoring_height();
+    testkit.create_blocks_until(height);
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1),
+                listunspent_entry(&funds, &anchoring_addr, 75)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+        get_transaction_request(&funds),
+    ]);
+    testkit.create_block();
+
+    let block_hash = testkit.block_hash_on_height(Height(10));
+    let (_, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        Height(10),
+        block_hash,
+        &[funds],
+        None,
+        &anchoring_addr,
+    );
+
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+    assert!(testkit.mempool().contains_key(&signatures[1].hash()));
+
+    let anchored_tx = &mut testkit.latest_anchored_tx();
+    requests.expect(send_raw_transaction_requests(anchored_tx));
+
+    testkit.create_block_with_transactions(signatures);
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), anchored_tx, 2);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
+}
+
+// We anchor second block after successfuly anchored first with additional funds
+// problems: the node sends only one input signature on each commit
+// result: success, the second signature is sent by the following commit
+#[test]
+fn test_anchoring_second_block_paced_signatures() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    testkit.handler().node.max_signatures_per_commit = Some(1);
+
+    let funds = {
+        let mut funds = testkit.current_funding_tx();
+        funds.0.lock_time += 1;
+        funds
+    };
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(1), &funds);
+    testkit.create_block_with_transactions(txvec![notice]);
+
+    let height = testkit.next_anchoring_height();
+    testkit.create_blocks_until(height);
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1),
+                listunspent_entry(&funds, &anchoring_addr, 75)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+        get_transaction_request(&funds),
+    ]);
+    testkit.create_block();
+
+    let block_hash = testkit.block_hash_on_height(Height(10));
+    let (_, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        Height(10),
+        block_hash,
+        &[funds],
+        None,
+        &anchoring_addr,
+    );
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+    assert!(!testkit.mempool().contains_key(&signatures[1].hash()));
+    assert_eq!(testkit.handler().pending_signatures.len(), 1);
+
+    testkit.create_block();
+    assert!(testkit.mempool().contains_key(&signatures[1].hash()));
+    assert!(testkit.handler().pending_signatures.is_empty());
+}
+
+// We anchor second block after successfuly anchored first
+// with several additional funding transactions listed by the bitcoin backend
+// problems: the funding transactions are not recorded in the blockchain yet
+// and one of them is not confirmed
+// result: we notice only the confirmed funding transaction and the proposal
+// spends only the funds recorded in the blockchain
+#[test]
+fn test_anchoring_second_block_multiple_funds() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let height = testkit.next_anchoring_height();
+    testkit.create_blocks_until(height);
+
+    let funds = testkit.current_funding_tx();
+    let (extra_funds, unconfirmed_funds) = {
+        let mut extra_funds = funds.clone();
+        extra_funds.0.lock_time += 1;
+        let mut unconfirmed_funds = funds.clone();
+        unconfirmed_funds.0.lock_time += 2;
+        (extra_funds, unconfirmed_funds)
+    };
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1),
+                listunspent_entry(&unconfirmed_funds, &anchoring_addr, 0),
+                listunspent_entry(&extra_funds, &anchoring_addr, 75)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+        get_transaction_request(&unconfirmed_funds),
+        get_transaction_request(&extra_funds),
+    ]);
+    testkit.create_block();
+
+    let extra_notice = gen_service_tx_funding(&testkit, ValidatorId(0), &extra_funds);
+    let unconfirmed_notice = gen_service_tx_funding(&testkit, ValidatorId(0), &unconfirmed_funds);
+    assert!(testkit.mempool().contains_key(&extra_notice.hash()));
+    assert!(!testkit.mempool().contains_key(&unconfirmed_notice.hash()));
+
+    let block_hash = testkit.block_hash_on_height(Height(10));
+    let (proposal, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        Height(10),
+        block_hash,
+        &[],
+        None,
+        &anchoring_addr,
+    );
+    assert_eq!(proposal.input.len(), 1);
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+
+    let anchored_tx = &mut testkit.latest_anchored_tx();
+    requests.expect(send_raw_transaction_requests(anchored_tx));
+
+    testkit.create_block_with_transactions(signatures);
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), anchored_tx, 2);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
+    assert_eq!(dump_funding_notices(&testkit), vec![extra_funds.id()]);
+}
+
+// We anchor second block after successfuly anchored first with the funding transaction
+// noticed by the validator
+// problems: the bitcoin backend does not list the noticed funding transaction as unspent
+// result: success, we spend the noticed funding transaction
+#[test]
+fn test_anchoring_second_block_noticed_funds() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let noticed_funds = {
+        let mut noticed_funds = testkit.current_funding_tx();
+        noticed_funds.0.lock_time += 1;
+        noticed_funds
+    };
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(1), &noticed_funds);
+    testkit.create_block_with_transactions(txvec![notice]);
+    assert_eq!(dump_funding_notices(&testkit), vec![noticed_funds.id()]);
+
+    let height = testkit.next_anchoring_height();
+    testkit.create_blocks_until(height);
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+    ]);
+    testkit.create_block();
+
+    let block_hash = testkit.block_hash_on_height(Height(10));
+    let (proposal, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        Height(10),
+        block_hash,
+        &[noticed_funds],
+        None,
+        &anchoring_addr,
+    );
+    assert_eq!(proposal.input.len(), 2);
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+    assert!(testkit.mempool().contains_key(&signatures[1].hash()));
+
+    let anchored_tx = &mut testkit.latest_anchored_tx();
+    requests.expect(send_raw_transaction_requests(anchored_tx));
+    testkit.create_block_with_transactions(signatures);
+
+    // The noticed funds are spent by the new lect.
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), anchored_tx, 2);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
+    testkit.create_block_with_transactions(txvec![lect]);
+    assert!(dump_funding_notices(&testkit).is_empty());
+}
+
+// The operator notices the funding transaction through the private api
+// problems: the funding transaction is not confirmed yet
+// result: we send the funding notice to the other validators once it is confirmed
+#[test]
+fn test_anchoring_send_funding_notice() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let noticed_funds = {
+        let mut noticed_funds = testkit.current_funding_tx();
+        noticed_funds.0.lock_time += 1;
+        noticed_funds
+    };
+    testkit.handler().schedule_funding_notice(noticed_funds.id());
+    requests.expect(vec![confirmations_request(&noticed_funds, 0)]);
+    testkit.create_block();
+
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(0), &noticed_funds);
+    assert!(!testkit.mempool().contains_key(&notice.hash()));
+    assert_eq!(testkit.handler().pending_funding_notices, vec![noticed_funds.id()]);
+
+    requests.expect(vec![confirmations_request(&noticed_funds, 75)]);
+    testkit.create_block();
+
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(0), &noticed_funds);
+    assert!(testkit.mempool().contains_key(&notice.hash()));
+    assert!(testkit.handler().pending_funding_notices.is_empty());
+}
+
+// We received the notice of the funding transaction which is already known
+// problems: None
+// result: we ignore it
+#[test]
+fn test_anchoring_funding_notice_known_tx() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let funding_tx = testkit.current_funding_tx();
+    let notice = gen_service_tx_funding(&testkit, ValidatorId(1), &funding_tx);
+    testkit.create_block_with_transactions(txvec![notice]);
+    assert!(dump_funding_notices(&testkit).is_empty());
+}
+
+// The validators vote to sweep the anchoring funds to the refund address
+// problems: None
+// result: we send all funds to the refund address
+#[test]
+fn test_anchoring_sweep_funds() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let refund_addr = {
+        let mut anchoring_cfg = testkit.current_cfg().clone();
+        anchoring_cfg.anchoring_keys.swap(1, 2);
+        anchoring_cfg.redeem_script().1
+    };
+    let votes = (0..3)
+        .map(|id| {
+            gen_service_tx_sweep(&testkit, ValidatorId(id), &refund_addr.to_string())
+        })
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+    ]);
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Sweeping);
+
+    let proposal = testkit.handler().proposal_tx.clone().unwrap();
+    assert_eq!(proposal.output_address(Network::Testnet), refund_addr);
+    assert_eq!(proposal.prev_hash(), testkit.latest_anchored_tx().id());
+    let signatures = testkit.gen_anchoring_signatures(&proposal);
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+
+    let swept_tx = testkit.finalize_tx(proposal, signatures.clone());
+    requests.expect(send_raw_transaction_requests(&swept_tx));
+    testkit.create_block_with_transactions(
+        signatures.into_iter().map(Box::<Transaction>::from),
+    );
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), &swept_tx, 2);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
+}
+
+// The validators vote to sweep the anchoring funds to the refund address
+// problems: the votes are not supported by the majority of validators
+// result: we continue anchoring
+#[test]
+fn test_anchoring_sweep_without_majority() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let refund_addr = {
+        let mut anchoring_cfg = testkit.current_cfg().clone();
+        anchoring_cfg.anchoring_keys.swap(1, 2);
+        anchoring_cfg.redeem_script().1
+    };
+    let votes = (0..2)
+        .map(|id| {
+            gen_service_tx_sweep(&testkit, ValidatorId(id), &refund_addr.to_string())
+        })
+        .chain(vec![gen_service_tx_sweep(&testkit, ValidatorId(2), "")])
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Proposing);
+    assert_eq!(testkit.handler().proposal_tx, None);
+}
+
+// One of the validators triggers the anchoring before the next anchoring height
+// problems: None
+// result: we anchor the block with the trigger
+#[test]
+fn test_anchoring_trigger() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let trigger = gen_service_tx_trigger(&testkit, ValidatorId(1));
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1)
+            ]
+        },
+        get_transaction_request(&testkit.latest_anchored_tx()),
+    ]);
+    testkit.create_block_with_transactions(txvec![trigger]);
+
+    let trigger_height = testkit.height();
+    assert_eq!(
+        AnchoringSchema::new(&testkit.snapshot()).latest_anchoring_trigger(),
+        Some(trigger_height)
+    );
+    let last_block_hash = testkit.last_block_hash();
+    let (_, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        trigger_height,
+        last_block_hash,
+        &[],
+        None,
+        &anchoring_addr,
+    );
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+}
+
+// The validators vote to pause the anchoring and then revoke their votes
+// problems: None
+// result: the proposals are not created during the pause, the anchoring is resumed
+#[test]
+fn test_anchoring_pause_and_resume() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let votes = (0..3)
+        .map(|id| gen_service_tx_pause(&testkit, ValidatorId(id), "fee spike"))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Paused);
+    assert_eq!(testkit.handler().proposal_tx, None);
+    assert!(AnchoringSchema::new(&testkit.snapshot()).is_paused());
+
+    let revokes = (0..2)
+        .map(|id| gen_service_tx_pause(&testkit, ValidatorId(id), ""))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(revokes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Proposing);
+    assert!(!AnchoringSchema::new(&testkit.snapshot()).is_paused());
+
+    // The repeated votes with the same reason are not the duplicates of the previous ones.
+    let votes = (0..2)
+        .map(|id| gen_service_tx_pause(&testkit, ValidatorId(id), "fee spike"))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Paused);
+    assert!(AnchoringSchema::new(&testkit.snapshot()).is_paused());
+}
+
+// The validators record the fee rates estimated by their bitcoin nodes
+// problems: None
+// result: the consensus fee rate is the median of the estimations of the majority
+#[test]
+fn test_anchoring_consensus_fee_rate() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let votes = vec![(0, 32), (1, 8)]
+        .into_iter()
+        .map(|(id, fee_rate)| gen_service_tx_fee_rate(&testkit, ValidatorId(id), fee_rate))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(AnchoringSchema::new(&testkit.snapshot()).consensus_fee_rate(), None);
+
+    let vote = gen_service_tx_fee_rate(&testkit, ValidatorId(2), 16);
+    testkit.create_block_with_transactions(txvec![vote]);
+    assert_eq!(AnchoringSchema::new(&testkit.snapshot()).consensus_fee_rate(), Some(16));
+
+    // The updated estimation replaces the previous one of the validator.
+    let vote = gen_service_tx_fee_rate(&testkit, ValidatorId(1), 64);
+    testkit.create_block_with_transactions(txvec![vote]);
+    assert_eq!(AnchoringSchema::new(&testkit.snapshot()).consensus_fee_rate(), Some(32));
+}
+
+// The validator proposes the rotation of its anchoring key and the validators approve it
+// problems: None
+// result: the rotated configuration is proposed with the delayed activation
+#[test]
+fn test_anchoring_key_rotation() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let new_key = btc::gen_btc_keypair(Network::Testnet).0.to_hex();
+    let votes = (0..3)
+        .map(|id| {
+            gen_service_tx_key_rotation(&testkit, ValidatorId(id), ValidatorId(1), &new_key)
+        })
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+    assert_eq!(testkit.handler().phase(), AnchoringPhase::Proposing);
+
+    let snapshot = testkit.snapshot();
+    let schema = AnchoringSchema::new(&snapshot);
+    let proposal = schema.key_rotation_proposal().unwrap();
+    let mut expected_cfg = testkit.current_cfg();
+    expected_cfg.anchoring_keys[1] = btc::PublicKey::from_hex(&new_key).unwrap();
+    let rotated_cfg: AnchoringConfig =
+        serde_json::from_value(proposal.services[ANCHORING_SERVICE_NAME].clone()).unwrap();
+    assert_eq!(rotated_cfg, expected_cfg);
+    assert_eq!(proposal.previous_cfg_hash, schema.actual_config_hash());
+    assert_eq!(
+        proposal.actual_from,
+        Height(testkit.height().0 + expected_cfg.key_rotation_delay)
+    );
+}
+
+// The validators vote to rotate the anchoring key of the validator
+// problems: the target validator has not voted for the rotation
+// result: the rotation is not approved
+#[test]
+fn test_anchoring_key_rotation_without_target() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let new_key = btc::gen_btc_keypair(Network::Testnet).0.to_hex();
+    let votes = vec![0, 2, 3]
+        .into_iter()
+        .map(|id| {
+            gen_service_tx_key_rotation(&testkit, ValidatorId(id), ValidatorId(1), &new_key)
+        })
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    testkit.create_block_with_transactions(votes);
+
+    let snapshot = testkit.snapshot();
+    let schema = AnchoringSchema::new(&snapshot);
+    assert!(schema.approved_key_rotation().is_none());
+    assert!(schema.key_rotation_proposal().is_none());
+}
+
+// We anchor second block after successfuly anchored first
+// problems: second anchoring tx is lost
+// result: we have lost anchoring tx
+#[test]
+fn test_anchoring_second_block_lect_lost() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let prev_anchored_tx = testkit.latest_anchored_tx().clone();
+    let prev_tx_signatures = testkit.latest_anchored_tx_signatures();
+
+    anchor_second_block_normal(&mut testkit);
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
+
+    requests.expect(vec![
+        request! {
+        method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&prev_anchored_tx, &anchoring_addr, 0)
+            ]
+        },
+        get_transaction_request(&prev_anchored_tx),
+    ]);
+
+    testkit.create_block();
+
+    let txs = (0..4)
+        .map(ValidatorId)
+        .map(|id| gen_service_tx_lect(&testkit, id, &prev_anchored_tx, 3))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    assert!(testkit.mempool().contains_key(&txs[0].hash()));
+
+    // Trying to resend lost lect tx
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&prev_anchored_tx, &anchoring_addr, 0)
+            ]
+        },
+        get_transaction_request(&prev_anchored_tx),
+    ]);
+    testkit.create_block_with_transactions(txs);
+    testkit.set_latest_anchored_tx(Some((prev_anchored_tx, prev_tx_signatures)));
+}
+
+// Our lect disappears from the unspent outputs of the anchoring address
+// problems: anchoring chain is broken and there is no new funding_tx
+// result: we stop anchoring and report error BrokenChain
+#[test]
+fn test_anchoring_broken_chain() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    anchor_second_block_normal(&mut testkit);
+
+    let broken_lect = testkit.latest_anchored_tx().clone();
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: []
+        },
+    ]);
+    testkit.create_block();
+    assert!(testkit.handler().broken_lect.is_some());
+    assert_eq!(
+        testkit.take_handler_alerts(),
+        vec![AnchoringAlert::ChainBroken { lect: broken_lect.id() }]
+    );
+
+    // Anchoring is stopped until the chain is recovered
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
+    assert!(testkit.mempool().is_empty());
+
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: []
+        },
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: []
+        },
+    ]);
+    testkit.create_block();
+
+    assert!(testkit.mempool().is_empty());
+    assert!(testkit.take_handler_alerts().is_empty());
+    assert_eq!(
+        testkit.take_handler_errors(),
+        vec![HandlerError::BrokenChain { lect: broken_lect.into() }]
+    );
+}
+
+// The anchoring rounds are abandoned three times in a row
+// problems: the rounds end without the anchoring transaction
+// result: the failed rounds alert is raised once the threshold is reached
 #[test]
-fn test_anchoring_second_block_normal() {
+fn test_anchoring_failed_rounds_alert() {
     let mut testkit = AnchoringTestKit::default();
-    anchor_first_block(&mut testkit);
-    anchor_first_block_lect_normal(&mut testkit);
-    anchor_second_block_normal(&mut testkit);
+    testkit.handler().node.alerts.failed_rounds = Some(2);
+
+    for _ in 0..3 {
+        let mut handler = testkit.handler();
+        handler.metrics.start_signature_round();
+        handler.abandon_round();
+    }
+    assert_eq!(testkit.handler().failed_rounds, 3);
+    assert_eq!(
+        testkit.take_handler_alerts(),
+        vec![AnchoringAlert::FailedRounds { count: 2 }]
+    );
 }
 
-// We anchor second block after successfuly anchored first with additional funds
-// problems: none
-// result: success
+// Our lect loses its confirmations
+// problems: block with the lect is orphaned by a reorg
+// result: we detect reorg and keep the lect
 #[test]
-fn test_anchoring_second_block_additional_funds() {
+fn test_anchoring_lect_reorg_confirmations_lost() {
     let mut testkit = AnchoringTestKit::default();
     let requests = testkit.requests();
     let anchoring_addr = testkit.current_addr();
 
     anchor_first_block(&mut testkit);
     anchor_first_block_lect_normal(&mut testkit);
+    anchor_second_block_normal(&mut testkit);
 
-    let height = testkit.next_anchoring_height();
+    let anchored_tx = testkit.latest_anchored_tx().clone();
+    assert_eq!(
+        testkit.handler().lect_confirmations,
+        Some((anchored_tx.id(), 100))
+    );
+
+    let height = testkit.next_check_lect_height();
     testkit.create_blocks_until(height);
 
-    let funds = testkit.current_funding_tx();
     requests.expect(vec![
         request! {
             method: "listunspent",
             params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
-                listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1),
-                listunspent_entry(&funds, &anchoring_addr, 75)
+                listunspent_entry(&anchored_tx, &anchoring_addr, 0)
             ]
         },
-        get_transaction_request(&testkit.latest_anchored_tx()),
-        get_transaction_request(&funds),
+        get_transaction_request(&anchored_tx),
     ]);
     testkit.create_block();
 
-    let block_hash = testkit.block_hash_on_height(Height(10));
-    let (_, signatures) = testkit.gen_anchoring_tx_with_signatures(
-        Height(10),
-        block_hash,
-        &[funds],
-        None,
-        &anchoring_addr,
+    assert_eq!(testkit.handler().metrics.reorgs, 1);
+    assert_eq!(
+        testkit.handler().lect_confirmations,
+        Some((anchored_tx.id(), 0))
     );
-
-    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
-    assert!(testkit.mempool().contains_key(&signatures[1].hash()));
-
-    let anchored_tx = &mut testkit.latest_anchored_tx();
-    requests.expect(send_raw_transaction_requests(anchored_tx));
-
-    testkit.create_block_with_transactions(signatures);
-    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), anchored_tx, 2);
-    assert!(testkit.mempool().contains_key(&lect.hash()));
+    assert!(testkit.mempool().is_empty());
 }
 
-// We anchor second block after successfuly anchored first
-// problems: second anchoring tx is lost
-// result: we have lost anchoring tx
+// Our confirmed lect disappears and its ancestor becomes unspent
+// problems: block with the lect is orphaned by a reorg and the lect is lost
+// result: we re-broadcast the orphaned lect and keep it
 #[test]
-fn test_anchoring_second_block_lect_lost() {
+fn test_anchoring_lect_reorg_rebroadcast() {
     let mut testkit = AnchoringTestKit::default();
     let requests = testkit.requests();
     let anchoring_addr = testkit.current_addr();
@@ -182,33 +1365,56 @@ fn test_anchoring_second_block_lect_lost() {
     anchor_first_block_lect_normal(&mut testkit);
 
     let prev_anchored_tx = testkit.latest_anchored_tx().clone();
-    let prev_tx_signatures = testkit.latest_anchored_tx_signatures();
 
     anchor_second_block_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx().clone();
     let height = testkit.next_check_lect_height();
     testkit.create_blocks_until(height);
 
     requests.expect(vec![
         request! {
-        method: "listunspent",
+            method: "listunspent",
             params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&prev_anchored_tx, &anchoring_addr, 0)
             ]
         },
         get_transaction_request(&prev_anchored_tx),
+        request! {
+            method: "sendrawtransaction",
+            params: [anchored_tx.to_hex()],
+            response: anchored_tx.to_hex()
+        },
     ]);
-
     testkit.create_block();
 
-    let txs = (0..4)
-        .map(ValidatorId)
-        .map(|id| gen_service_tx_lect(&testkit, id, &prev_anchored_tx, 3))
-        .map(Box::<Transaction>::from)
-        .collect::<Vec<_>>();
-    assert!(testkit.mempool().contains_key(&txs[0].hash()));
+    assert_eq!(testkit.handler().metrics.reorgs, 1);
+    assert_eq!(
+        testkit.handler().lect_confirmations,
+        Some((anchored_tx.id(), 0))
+    );
+    assert!(testkit.mempool().is_empty());
+}
+
+// Our confirmed lect disappears and its ancestor becomes unspent
+// problems: block with the lect is orphaned by a reorg and the lect cannot be re-broadcast
+// result: we roll back to the ancestor and update lect
+#[test]
+fn test_anchoring_lect_reorg_rollback() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let prev_anchored_tx = testkit.latest_anchored_tx().clone();
+
+    anchor_second_block_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx().clone();
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
 
-    // Trying to resend lost lect tx
     requests.expect(vec![
         request! {
             method: "listunspent",
@@ -218,9 +1424,24 @@ fn test_anchoring_second_block_lect_lost() {
             ]
         },
         get_transaction_request(&prev_anchored_tx),
+        request! {
+            method: "sendrawtransaction",
+            params: [anchored_tx.to_hex()],
+            error: other_error(json!({
+                "code": -26,
+                "message": "txn-mempool-conflict"
+            }))
+        },
     ]);
-    testkit.create_block_with_transactions(txs);
-    testkit.set_latest_anchored_tx(Some((prev_anchored_tx, prev_tx_signatures)));
+    testkit.create_block();
+
+    assert_eq!(testkit.handler().metrics.reorgs, 1);
+    assert_eq!(
+        testkit.handler().lect_confirmations,
+        Some((prev_anchored_tx.id(), 0))
+    );
+    let lect = gen_service_tx_lect(&testkit, ValidatorId(0), &prev_anchored_tx, 3);
+    assert!(testkit.mempool().contains_key(&lect.hash()));
 }
 
 // We find lect, whose prev_hash is not known
@@ -506,6 +1727,86 @@ fn test_anchoring_signature_nonexistent_tx() {
     assert_eq!(signs_after[0], msg_sign);
 }
 
+// We received correct signature message for the proposal which spends unknown transaction
+// problems: None
+// result: we ignore it
+#[test]
+fn test_anchoring_signature_unknown_proposal() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let (redeem_script, addr) = testkit.current_cfg().redeem_script();
+    let block_hash = testkit.block_hash_on_height(Height::zero());
+    let unknown_tx = TransactionBuilder::with_prev_tx(&testkit.latest_anchored_tx(), 0)
+        .fee(100)
+        .payload(Height::zero(), block_hash)
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    let tx = TransactionBuilder::with_prev_tx(&unknown_tx, 0)
+        .fee(100)
+        .payload(Height::zero(), block_hash)
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    let signature = tx.sign_input(&redeem_script, 0, &testkit.priv_keys(&addr)[1]);
+    let validator_1 = ValidatorId(1);
+    let msg_sign = {
+        let keypair = testkit.validator(validator_1).service_keypair();
+        MsgAnchoringSignature::new(
+            keypair.0,
+            validator_1,
+            tx.clone(),
+            0,
+            signature.as_ref(),
+            keypair.1,
+        )
+    };
+
+    testkit.create_block_with_transactions(txvec![msg_sign]);
+    assert!(dump_signatures(&testkit, &tx.id()).is_empty());
+}
+
+// We received correct signature message for the proposal which anchors older block
+// than the common lect
+// problems: None
+// result: we ignore it
+#[test]
+fn test_anchoring_signature_stale_proposal() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    anchor_second_block_normal(&mut testkit);
+
+    let (redeem_script, addr) = testkit.current_cfg().redeem_script();
+    let block_hash = testkit.block_hash_on_height(Height::zero());
+    let tx = TransactionBuilder::with_prev_tx(&testkit.latest_anchored_tx(), 0)
+        .fee(100)
+        .payload(Height::zero(), block_hash)
+        .send_to(addr.clone())
+        .into_transaction()
+        .unwrap();
+    let signature = tx.sign_input(&redeem_script, 0, &testkit.priv_keys(&addr)[1]);
+    let validator_1 = ValidatorId(1);
+    let msg_sign = {
+        let keypair = testkit.validator(validator_1).service_keypair();
+        MsgAnchoringSignature::new(
+            keypair.0,
+            validator_1,
+            tx.clone(),
+            0,
+            signature.as_ref(),
+            keypair.1,
+        )
+    };
+
+    testkit.create_block_with_transactions(txvec![msg_sign]);
+    assert!(dump_signatures(&testkit, &tx.id()).is_empty());
+}
+
 // We received correct signature message with incorrect payload
 // problems: None
 // result: we ignore it
@@ -546,6 +1847,89 @@ fn test_anchoring_signature_incorrect_payload() {
     assert!(signatures_after.is_empty());
 }
 
+// We received signature message with the signature of another validator
+// problems: None
+// result: we ignore it and record the misbehavior evidence
+#[test]
+fn test_anchoring_signature_misbehavior_evidence() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let signatures = testkit.latest_anchored_tx_signatures();
+    let tx = {
+        let mut tx = testkit.latest_anchored_tx().clone();
+        tx.0.input[0].script_sig = Script::new();
+        tx
+    };
+
+    let validator_1 = ValidatorId(1);
+    let msg_signature_wrong = {
+        let keypair = testkit.validator(validator_1).service_keypair();
+        MsgAnchoringSignature::new(
+            keypair.0,
+            validator_1,
+            tx.clone(),
+            0,
+            signatures[0].signature(),
+            keypair.1,
+        )
+    };
+    testkit.create_block_with_transactions(txvec![msg_signature_wrong.clone()]);
+
+    let snapshot = testkit.snapshot();
+    let evidence = AnchoringSchema::new(&snapshot)
+        .misbehavior_evidence()
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(evidence.len(), 1);
+    assert_eq!(evidence[0].validator(), validator_1);
+    assert_eq!(
+        MisbehaviorKind::from_code(evidence[0].kind()),
+        Some(MisbehaviorKind::InvalidSignature)
+    );
+    assert_eq!(evidence[0].msg_hash(), &msg_signature_wrong.hash());
+    assert_eq!(evidence[0].height(), testkit.height().0);
+}
+
+// We received lect with the payload which does not match the anchored block
+// problems: None
+// result: we ignore it and record the misbehavior evidence
+#[test]
+fn test_anchoring_lect_misbehavior_evidence() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let addr = testkit.current_addr();
+    let tx = TransactionBuilder::with_prev_tx(&testkit.latest_anchored_tx(), 0)
+        .fee(100)
+        .payload(Height::zero(), Hash::zero())
+        .send_to(addr)
+        .into_transaction()
+        .unwrap();
+    let validator_1 = ValidatorId(1);
+    let lects_before = dump_lects(&testkit, validator_1);
+    let lect = gen_service_tx_lect(&testkit, validator_1, &tx, lects_before.len() as u64);
+    testkit.create_block_with_transactions(txvec![lect.clone()]);
+    assert_eq!(dump_lects(&testkit, validator_1), lects_before);
+
+    let snapshot = testkit.snapshot();
+    let evidence = AnchoringSchema::new(&snapshot)
+        .misbehavior_evidence()
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(evidence.len(), 1);
+    assert_eq!(evidence[0].validator(), validator_1);
+    assert_eq!(
+        MisbehaviorKind::from_code(evidence[0].kind()),
+        Some(MisbehaviorKind::InvalidLect)
+    );
+    assert_eq!(evidence[0].msg_hash(), &lect.hash());
+}
+
 // We received correct lect with the current funding_tx
 // problems: None
 // result: we add it
@@ -624,6 +2008,32 @@ fn test_anchoring_lect_incorrect_anchoring_payload() {
     assert_eq!(lects_before, lects_after);
 }
 
+// We received lect of the other blockchain sharing the anchoring address
+// problems: lect payload carries the foreign chain identifier
+// result: we ignore it
+#[test]
+fn test_anchoring_lect_foreign_chain_id() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let tx = TransactionBuilder::with_prev_tx(&testkit.current_funding_tx(), 0)
+        .fee(1000)
+        .payload(Height::zero(), testkit.block_hash_on_height(Height::zero()))
+        .chain_id(Some(42))
+        .send_to(testkit.current_addr())
+        .into_transaction()
+        .unwrap();
+    let msg_lect = gen_service_tx_lect(&testkit, ValidatorId(0), &tx, 2);
+    let lects_before = dump_lects(&testkit, ValidatorId(0));
+    // Commit `msg_lect` into blockchain
+    testkit.create_block_with_transactions(txvec![msg_lect.clone()]);
+    // Ensure that service ignores it
+    let lects_after = dump_lects(&testkit, ValidatorId(0));
+    assert_eq!(lects_before, lects_after);
+}
+
 // We received correct lect with the unknown prev_hash
 // problems: None
 // result: we ignore it
@@ -796,6 +2206,50 @@ fn test_anchoring_signature_input_from_different_validator() {
     assert_eq!(signs_before, signs_after);
 }
 
+// We received signature message with correct signature for the input
+// which does not spend the anchoring address
+// problems: None
+// result: we ignore it
+#[test]
+fn test_anchoring_signature_input_foreign_output() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let genesis_hash = Schema::new(&testkit.snapshot())
+        .block_hashes_by_height()
+        .get(0)
+        .unwrap();
+    let latest_tx = testkit.latest_anchored_tx();
+    // The second output of the anchoring transaction contains its payload.
+    let tx = TransactionBuilder::with_prev_tx(&latest_tx, 0)
+        .add_funds(&latest_tx, 1)
+        .fee(1000)
+        .payload(Height::zero(), genesis_hash)
+        .send_to(testkit.current_addr())
+        .into_transaction()
+        .unwrap();
+    let (redeem_script, _) = testkit.current_cfg().redeem_script();
+    let priv_key = &mut testkit.current_priv_keys()[0];
+    let signature = tx.sign_input(&redeem_script, 1, priv_key);
+    assert!(tx.verify_input(
+        &redeem_script,
+        1,
+        &testkit.current_cfg().anchoring_keys[0],
+        &signature,
+    ));
+
+    let msg_signature_wrong = {
+        let validator_0 = ValidatorId(0);
+        let keypair = testkit.validator(validator_0).service_keypair();
+        MsgAnchoringSignature::new(keypair.0, validator_0, tx.clone(), 1, &signature, keypair.1)
+    };
+
+    testkit.create_block_with_transactions(txvec![msg_signature_wrong]);
+    assert!(dump_signatures(&testkit, &tx.id()).is_empty());
+}
+
 // We received signature message for anchoring tx with unknown output_address
 // problems: None
 // result: we ignore it
@@ -34,11 +34,22 @@ pub mod testkit_extras;
 
 use exonum::messages::Message;
 use exonum::helpers::{Height, ValidatorId};
-use exonum::encoding::serialize::FromHex;
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::network::serialize::{deserialize, BitcoinHash};
+use exonum::crypto::Hash;
+use exonum::encoding::serialize::{FromHex, ToHex};
+use exonum::blockchain::Schema;
+use exonum::storage::StorageValue;
 use exonum_testkit::{ApiKind, TestKitApi};
 
 use exonum_btc_anchoring::ANCHORING_SERVICE_NAME;
-use exonum_btc_anchoring::api::{AnchoringInfo, LectInfo};
+use exonum_btc_anchoring::api::{AnchoringBalance, AnchoringInfo, AnchoringProof, LectInfo,
+                                AnchoringCosts, AnchoringHealth, AnchoringLatency, AnchoringStatus,
+                                CoveringAnchor, SpvProof, TxExistenceProof, TxLogInfo,
+                                TxLogKind};
+use exonum_btc_anchoring::certificate::{CertificateError, NotarizationCertificate};
+use exonum_btc_anchoring::light;
+use exonum_btc_anchoring::handler::state::AnchoringPhase;
 use exonum_btc_anchoring::observer::AnchoringChainObserver;
 use exonum_btc_anchoring::blockchain::dto::MsgAnchoringUpdateLatest;
 use exonum_btc_anchoring::details::btc;
@@ -55,7 +66,31 @@ trait AnchoringApi {
 
     fn following_address(&self) -> Option<btc::Address>;
 
+    fn actual_balance(&self) -> AnchoringBalance;
+
     fn nearest_lect(&self, height: u64) -> Option<AnchoringTx>;
+
+    fn anchoring_proof(&self, height: u64) -> Option<AnchoringProof>;
+
+    fn spv_proof(&self, txid: &btc::TxId) -> Option<SpvProof>;
+
+    fn covering_anchor(&self, height: u64) -> Option<CoveringAnchor>;
+
+    fn anchor_by_txid(&self, txid: &btc::TxId) -> Option<CoveringAnchor>;
+
+    fn tx_existence_proof(&self, tx_hash: &Hash) -> Option<TxExistenceProof>;
+
+    fn certificate(&self, tx_hash: &Hash) -> Option<NotarizationCertificate>;
+
+    fn tx_log(&self, start: u64) -> Vec<TxLogInfo>;
+
+    fn anchoring_latency(&self) -> AnchoringLatency;
+
+    fn anchoring_costs(&self) -> AnchoringCosts;
+
+    fn status(&self) -> AnchoringStatus;
+
+    fn health(&self) -> AnchoringHealth;
 }
 
 impl AnchoringApi for TestKitApi {
@@ -84,12 +119,84 @@ impl AnchoringApi for TestKitApi {
         )
     }
 
+    fn actual_balance(&self) -> AnchoringBalance {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            "/v1/address/balance",
+        )
+    }
+
     fn nearest_lect(&self, height: u64) -> Option<AnchoringTx> {
         self.get(
             ApiKind::Service(ANCHORING_SERVICE_NAME),
             &format!("/v1/nearest_lect/{}", height),
         )
     }
+
+    fn anchoring_proof(&self, height: u64) -> Option<AnchoringProof> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/anchoring_proof/{}", height),
+        )
+    }
+
+    fn spv_proof(&self, txid: &btc::TxId) -> Option<SpvProof> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/spv_proof/{}", txid.to_string()),
+        )
+    }
+
+    fn covering_anchor(&self, height: u64) -> Option<CoveringAnchor> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/anchor/{}", height),
+        )
+    }
+
+    fn anchor_by_txid(&self, txid: &btc::TxId) -> Option<CoveringAnchor> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/anchor/txid/{}", txid.to_string()),
+        )
+    }
+
+    fn tx_existence_proof(&self, tx_hash: &Hash) -> Option<TxExistenceProof> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/tx_proof/{}", tx_hash.to_hex()),
+        )
+    }
+
+    fn certificate(&self, tx_hash: &Hash) -> Option<NotarizationCertificate> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/certificate/{}", tx_hash.to_hex()),
+        )
+    }
+
+    fn tx_log(&self, start: u64) -> Vec<TxLogInfo> {
+        self.get(
+            ApiKind::Service(ANCHORING_SERVICE_NAME),
+            &format!("/v1/tx_log/{}", start),
+        )
+    }
+
+    fn anchoring_latency(&self) -> AnchoringLatency {
+        self.get(ApiKind::Service(ANCHORING_SERVICE_NAME), "/v1/latency")
+    }
+
+    fn anchoring_costs(&self) -> AnchoringCosts {
+        self.get(ApiKind::Service(ANCHORING_SERVICE_NAME), "/v1/costs")
+    }
+
+    fn status(&self) -> AnchoringStatus {
+        self.get(ApiKind::Service(ANCHORING_SERVICE_NAME), "/v1/status")
+    }
+
+    fn health(&self) -> AnchoringHealth {
+        self.get(ApiKind::Service(ANCHORING_SERVICE_NAME), "/v1/health")
+    }
 }
 
 // Test normal api usage
@@ -256,3 +363,685 @@ fn test_api_anchoring_observer_normal() {
     // Check that there are no anchoring transactions for heights that greater than 10
     assert_eq!(api.nearest_lect(11), None);
 }
+
+// Testing the anchoring proof for the blocks covered by the anchoring chain.
+#[test]
+fn test_api_anchoring_proof() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    let api = testkit.api();
+
+    let proof = api.anchoring_proof(0).unwrap();
+    assert_eq!(proof.txid, first_anchored_tx.id());
+    assert_eq!(proof.tx, first_anchored_tx);
+    assert_eq!(proof.block.height(), Height(0));
+    assert!(proof.path.is_empty());
+    assert!(proof.verify());
+
+    // Path for the block at height 3 consists of the blocks from 4 to 10.
+    let proof = api.anchoring_proof(3).unwrap();
+    assert_eq!(proof.txid, second_anchored_tx.id());
+    assert_eq!(proof.block.height(), Height(3));
+    assert_eq!(proof.path.len(), 7);
+    assert!(proof.verify());
+    let blocks = proof.blocks();
+    for pair in blocks.windows(2) {
+        assert_eq!(pair[1].prev_hash(), &pair[0].hash());
+    }
+    let payload = proof.tx.payload();
+    let anchored_block = blocks.last().unwrap();
+    assert_eq!(anchored_block.height(), payload.block_height);
+    assert_eq!(anchored_block.hash(), payload.block_hash);
+
+    // The path does not lead to the anchored block from a forged one.
+    let mut forged_proof = api.anchoring_proof(3).unwrap();
+    forged_proof.path[0].tx_count += 1;
+    assert!(!forged_proof.verify());
+
+    assert_eq!(api.anchoring_proof(11), None);
+}
+
+// Testing the search of the anchoring transaction which covers the given height.
+#[test]
+fn test_api_covering_anchor() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let observer_requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    observer_requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    let api = testkit.api();
+
+    requests.expect(vec![confirmations_request(&second_anchored_tx, 120)]);
+    let anchor = api.covering_anchor(3).unwrap();
+    assert_eq!(anchor.txid, second_anchored_tx.id());
+    assert_eq!(anchor.anchored_height, 10);
+    assert_eq!(anchor.confirmations, Some(120));
+    assert!(anchor.confirmed_at.is_some());
+
+    requests.expect(vec![confirmations_request(&first_anchored_tx, 220)]);
+    assert_eq!(api.covering_anchor(0).unwrap().txid, first_anchored_tx.id());
+    assert_eq!(api.covering_anchor(11), None);
+
+    requests.expect(vec![confirmations_request(&first_anchored_tx, 220)]);
+    let anchor = api.anchor_by_txid(&first_anchored_tx.id()).unwrap();
+    assert_eq!(anchor.anchored_height, 0);
+    assert_eq!(anchor.confirmations, Some(220));
+    assert_eq!(api.anchor_by_txid(&testkit.current_funding_tx().id()), None);
+}
+
+// Testing the fees paid by the observed anchoring chain.
+#[test]
+fn test_api_anchoring_costs() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_addr = testkit.current_addr();
+
+    let costs = testkit.api().anchoring_costs();
+    assert_eq!(costs.anchors, 0);
+    assert_eq!(costs.fee_per_anchor, None);
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let observer_requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    observer_requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    let (first_fee, second_fee) = {
+        let snapshot = testkit.snapshot();
+        let schema = AnchoringSchema::new(&snapshot);
+        (
+            schema.anchoring_tx_fee(&first_anchored_tx).unwrap(),
+            schema.anchoring_tx_fee(&second_anchored_tx).unwrap(),
+        )
+    };
+    let total_fee = first_fee + second_fee;
+
+    let costs = testkit.api().anchoring_costs();
+    assert_eq!(costs.anchors, 2);
+    assert_eq!(costs.total_fee, total_fee);
+    assert_eq!(costs.fee_per_anchor, Some(total_fee / 2));
+    assert_eq!(costs.fee_per_block, Some(total_fee as f64 / 11.0));
+    // The transactions are found confirmed by the observer just now.
+    assert_eq!(costs.last_day_fee, total_fee);
+    assert_eq!(costs.last_week_fee, total_fee);
+}
+
+// Testing the proof of existence of the `Exonum` transaction.
+#[test]
+fn test_api_tx_existence_proof() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let observer_requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    observer_requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    // The first block with the transactions which is anchored by the second transaction.
+    let (height, tx_hash) = {
+        let snapshot = testkit.snapshot();
+        let schema = Schema::new(&snapshot);
+        (1..11)
+            .filter_map(|height| {
+                schema.block_txs(Height(height)).get(0).map(|hash| (height, hash))
+            })
+            .next()
+            .unwrap()
+    };
+
+    let api = testkit.api();
+    requests.expect(vec![confirmations_request(&second_anchored_tx, 120)]);
+    let proof = api.tx_existence_proof(&tx_hash).unwrap();
+    assert_eq!(proof.tx_hash, tx_hash);
+    assert_eq!(proof.block.height(), Height(height));
+    assert_eq!(proof.position, 0);
+    let entries = proof
+        .tx_proof
+        .validate(*proof.block.tx_hash(), u64::from(proof.block.tx_count()))
+        .unwrap();
+    assert_eq!(entries, vec![(0, &tx_hash)]);
+
+    let anchoring_proof = proof.anchoring_proof.unwrap();
+    assert_eq!(anchoring_proof.txid, second_anchored_tx.id());
+    assert_eq!(anchoring_proof.block, proof.block);
+    assert!(anchoring_proof.verify());
+    assert_eq!(proof.confirmations, Some(120));
+    assert!(proof.confirmed_at.is_some());
+
+    assert_eq!(api.tx_existence_proof(&Hash::zero()), None);
+}
+
+// Testing the notarization certificate of the `Exonum` transaction.
+#[test]
+fn test_api_certificate() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let observer_requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    observer_requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    let (height, tx_hash) = {
+        let snapshot = testkit.snapshot();
+        let schema = Schema::new(&snapshot);
+        (1..11)
+            .filter_map(|height| {
+                schema.block_txs(Height(height)).get(0).map(|hash| (height, hash))
+            })
+            .next()
+            .unwrap()
+    };
+
+    // The bitcoin block includes only the anchoring transaction, so its merkle root
+    // is the `txid`. Almost any hash satisfies the maximal target of the headers.
+    let raw_tx = second_anchored_tx.clone().into_bytes();
+    let txid = light::RawTx::parse(&raw_tx).unwrap().txid();
+    let mut header_bytes = vec![1, 0, 0, 0];
+    header_bytes.extend_from_slice(&[0; 32]);
+    header_bytes.extend_from_slice(&txid);
+    header_bytes.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0x00, 0x21, 0, 0, 0, 0]);
+    let header = header_bytes.to_hex();
+    let block_hash = deserialize::<BlockHeader>(&header_bytes)
+        .unwrap()
+        .bitcoin_hash()
+        .be_hex_string();
+    let merkle_block = format!("{}01000000{}{}0101", header, "01", txid.to_hex());
+    let mut next_header_bytes = vec![2, 0, 0, 0];
+    next_header_bytes.extend_from_slice(&light::sha256d(&header_bytes));
+    next_header_bytes.extend_from_slice(&[0; 36]);
+    next_header_bytes.extend_from_slice(&[0xff, 0xff, 0x00, 0x21, 0, 0, 0, 0]);
+    let next_header = next_header_bytes.to_hex();
+    let next_hash = deserialize::<BlockHeader>(&next_header_bytes)
+        .unwrap()
+        .bitcoin_hash()
+        .be_hex_string();
+
+    let api = testkit.api();
+    requests.expect(vec![
+        confirmations_request(&second_anchored_tx, 2),
+        request! {
+            method: "gettxoutproof",
+            params: [[&second_anchored_tx.txid()]],
+            response: &merkle_block
+        },
+        request! {
+            method: "getblockheader",
+            params: [&block_hash, true],
+            response: {
+                "hash": &block_hash,
+                "confirmations": 2,
+                "nextblockhash": &next_hash
+            }
+        },
+        request! {
+            method: "getblockheader",
+            params: [&next_hash, false],
+            response: &next_header
+        },
+        request! {
+            method: "getblockheader",
+            params: [&next_hash, true],
+            response: {
+                "hash": &next_hash,
+                "confirmations": 1
+            }
+        },
+    ]);
+    let certificate = api.certificate(&tx_hash).unwrap();
+    let verified = certificate.verify(&anchoring_addr).unwrap();
+    assert_eq!(verified.block_height, height);
+    assert_eq!(verified.anchored_height, 10);
+    assert_eq!(verified.txid, second_anchored_tx.id());
+    assert_eq!(verified.bitcoin_block_hash, light::sha256d(&header_bytes));
+    assert_eq!(verified.confirmations, 2);
+
+    let json = certificate.to_json();
+    assert_eq!(NotarizationCertificate::from_json(&json).unwrap(), certificate);
+    let bytes = certificate.to_bytes().unwrap();
+    assert_eq!(NotarizationCertificate::from_bytes(&bytes).unwrap(), certificate);
+    assert!(NotarizationCertificate::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut forged = NotarizationCertificate::from_bytes(&bytes).unwrap();
+    forged.tx_hash = Hash::zero();
+    assert_eq!(forged.verify(&anchoring_addr), Err(CertificateError::TxNotIncluded));
+    let mut forged = NotarizationCertificate::from_bytes(&bytes).unwrap();
+    forged.anchoring_tx = first_anchored_tx;
+    assert_eq!(forged.verify(&anchoring_addr), Err(CertificateError::PayloadMismatch));
+    let other_addr = {
+        let mut anchoring_cfg = testkit.current_cfg().clone();
+        anchoring_cfg.anchoring_keys.swap(1, 2);
+        anchoring_cfg.redeem_script().1
+    };
+    assert_eq!(certificate.verify(&other_addr), Err(CertificateError::AddressMismatch));
+}
+
+// Testing the SPV proof for the confirmed anchoring transaction.
+#[test]
+fn test_api_spv_proof() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+
+    // The bitcoin block includes only the anchoring transaction.
+    let raw_tx = anchored_tx.clone().into_bytes();
+    let txid = light::RawTx::parse(&raw_tx).unwrap().txid();
+    let mut header_bytes = vec![1, 0, 0, 0];
+    header_bytes.extend_from_slice(&[0; 32]);
+    header_bytes.extend_from_slice(&txid);
+    header_bytes.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0x00, 0x21, 0, 0, 0, 0]);
+    let header = header_bytes.to_hex();
+    let block_hash = deserialize::<BlockHeader>(&header_bytes)
+        .unwrap()
+        .bitcoin_hash()
+        .be_hex_string();
+    let merkle_block = format!("{}0100000001{}0101", header, txid.to_hex());
+    let next_hash = "00000000000000ad3a5d1ec7a6f8bbaffdba64bd2cbc1e91d662e0bc61bbd046";
+    let next_header = format!("02000000{}", "00".repeat(76));
+
+    let api = testkit.api();
+    requests.expect(vec![
+        confirmations_request(&anchored_tx, 0),
+    ]);
+    assert_eq!(api.spv_proof(&anchored_tx.id()), None);
+
+    requests.expect(vec![
+        confirmations_request(&anchored_tx, 2),
+        request! {
+            method: "gettxoutproof",
+            params: [[&anchored_tx.txid()]],
+            response: &merkle_block
+        },
+        request! {
+            method: "getblockheader",
+            params: [&block_hash, true],
+            response: {
+                "hash": &block_hash,
+                "confirmations": 2,
+                "nextblockhash": next_hash
+            }
+        },
+        request! {
+            method: "getblockheader",
+            params: [next_hash, false],
+            response: &next_header
+        },
+        request! {
+            method: "getblockheader",
+            params: [next_hash, true],
+            response: {
+                "hash": next_hash,
+                "confirmations": 1
+            }
+        },
+    ]);
+    let proof = api.spv_proof(&anchored_tx.id()).unwrap();
+    assert_eq!(proof.txid, anchored_tx.id());
+    assert_eq!(proof.tx, anchored_tx);
+    assert_eq!(proof.block_hash, block_hash);
+    assert_eq!(proof.merkle_block, merkle_block);
+    assert_eq!(proof.headers, vec![next_header]);
+}
+
+// Testing the SPV proof with the merkle block which does not include the anchoring transaction.
+#[test]
+#[should_panic(expected = "Merkle block does not prove the inclusion")]
+fn test_api_spv_proof_foreign_merkle_block() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+
+    let header = format!("01000000{}", "00".repeat(76));
+    let merkle_block = format!("{}0100000001{}0101", header, "11".repeat(32));
+
+    let api = testkit.api();
+    requests.expect(vec![
+        confirmations_request(&anchored_tx, 2),
+        request! {
+            method: "gettxoutproof",
+            params: [[&anchored_tx.txid()]],
+            response: &merkle_block
+        },
+    ]);
+    api.spv_proof(&anchored_tx.id());
+}
+
+// Testing the balance of the anchoring address.
+#[test]
+fn test_api_actual_balance() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+    let anchoring_addr = testkit.current_addr();
+    let fee = testkit.current_cfg().fee;
+
+    let api = testkit.api();
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
+            response: [
+                listunspent_entry(&anchored_tx, &anchoring_addr, 1)
+            ]
+        },
+        get_transaction_request(&anchored_tx),
+    ]);
+    let balance = api.actual_balance();
+    assert_eq!(balance.address, anchoring_addr);
+    assert_eq!(balance.balance, anchored_tx.amount());
+    assert_eq!(balance.anchor_fee, fee);
+    assert_eq!(balance.anchors_left, anchored_tx.amount() / fee);
+}
+
+// Testing the log of the observed anchoring transactions.
+#[test]
+fn test_api_tx_log() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+    let funding_tx = testkit.current_funding_tx();
+    let fee = testkit.current_cfg().fee;
+
+    let api = testkit.api();
+    let entries = api.tx_log(0);
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0].index, 0);
+    assert_eq!(entries[0].height, 0);
+    assert_eq!(entries[0].txid, funding_tx.id());
+    assert_eq!(entries[0].kind, TxLogKind::Funding);
+    assert_eq!(entries[0].payload, None);
+
+    assert_eq!(entries[1].index, 1);
+    assert!(entries[1].height > 0);
+    assert_eq!(entries[1].txid, anchored_tx.id());
+    assert_eq!(entries[1].kind, TxLogKind::Anchoring);
+    assert_eq!(entries[1].payload, Some(anchored_tx.payload()));
+    assert_eq!(entries[1].fee, Some(fee));
+    assert_eq!(entries[1].confirmed_at, None);
+
+    assert_eq!(api.tx_log(1).len(), 1);
+    assert!(api.tx_log(2).is_empty());
+}
+
+// Testing the anchoring latency measured by the observer.
+#[test]
+fn test_api_anchoring_latency() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_addr = testkit.current_addr();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let first_anchored_tx = testkit.latest_anchored_tx();
+
+    let client = TestClient::default();
+    let requests = client.requests();
+    let mut observer = AnchoringChainObserver::new_with_client(
+        testkit.blockchain_mut().clone(),
+        Box::new(client),
+        0,
+    );
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&first_anchored_tx, &anchoring_addr, 200)
+            ]
+        },
+        get_transaction_request(&first_anchored_tx),
+        confirmations_request(&first_anchored_tx, 200),
+        get_transaction_request(&testkit.current_funding_tx()),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    // The latency of the transactions found by the first check is unknown.
+    let latency = testkit.api().anchoring_latency();
+    assert_eq!(latency.count, 0);
+    assert_eq!(latency.p50, None);
+
+    anchor_second_block_normal(&mut testkit);
+    let second_anchored_tx = testkit.latest_anchored_tx();
+    requests.expect(vec![
+        request! {
+            method: "listunspent",
+            params: [0, 9_999_999, [&anchoring_addr]],
+            response: [
+                listunspent_entry(&second_anchored_tx, &anchoring_addr, 10)
+            ]
+        },
+        get_transaction_request(&second_anchored_tx),
+        confirmations_request(&second_anchored_tx, 100),
+        get_transaction_request(&first_anchored_tx),
+    ]);
+    observer.check_anchoring_chain().unwrap();
+
+    let latency = testkit.api().anchoring_latency();
+    assert_eq!(latency.count, 1);
+    assert!(latency.p50.is_some());
+    assert_eq!(latency.p50, latency.max);
+}
+
+// Testing the status of the anchoring process.
+#[test]
+fn test_api_status() {
+    let mut testkit = AnchoringTestKit::default();
+
+    let status = testkit.api().status();
+    assert_eq!(status.phase, AnchoringPhase::WaitingForFunding);
+    assert_eq!(status.lect, Some(testkit.current_funding_tx().id()));
+    assert_eq!(status.next_anchoring_height, 0);
+    assert!(status.signers.is_empty());
+    assert!(!status.paused);
+    assert!(status.pending_transfer.is_none());
+    assert!(!status.wallet_empty);
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+
+    let status = testkit.api().status();
+    assert_eq!(status.phase, AnchoringPhase::Proposing);
+    assert_eq!(status.lect, Some(anchored_tx.id()));
+    assert_eq!(status.lect_confirmations, Some(0));
+    assert_eq!(status.next_anchoring_height, testkit.current_cfg().frequency);
+    assert_eq!(status.signers, vec![0, 1, 2, 3]);
+    assert!(status.errors.is_empty());
+}
+
+// Testing that the status is available while the handler is busy with the commit.
+#[test]
+fn test_api_status_handler_locked() {
+    let mut testkit = AnchoringTestKit::default();
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    let anchored_tx = testkit.latest_anchored_tx();
+
+    let handler = testkit.handler();
+    let status = testkit.api().status();
+    assert_eq!(status.phase, handler.phase());
+    assert_eq!(status.lect, Some(anchored_tx.id()));
+    assert_eq!(handler.shared().snapshot().state, handler.state);
+}
+
+// Testing the health of the node with the reachable bitcoin backend.
+#[test]
+fn test_api_health() {
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    requests.expect(vec![
+        request! {
+            method: "getblockcount",
+            params: [],
+            response: 1000
+        },
+        request! {
+            method: "getblockchaininfo",
+            params: [],
+            response: {
+                "chain": "test",
+                "blocks": 1000,
+                "verificationprogress": 0.5
+            }
+        },
+    ]);
+    let health = testkit.api().health();
+    assert!(health.healthy);
+    assert!(health.bitcoind_reachable);
+    assert_eq!(health.tip_height, Some(1000));
+    assert_eq!(health.verification_progress, Some(0.5));
+    assert_eq!(health.address, testkit.current_addr());
+    assert!(health.address_watched);
+    assert!(health.last_round_completed);
+    assert!(health.errors.is_empty());
+}
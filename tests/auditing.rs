@@ -33,12 +33,16 @@ extern crate exonum_testkit;
 #[macro_use]
 pub mod testkit_extras;
 
+use std::collections::BTreeMap;
+
 use exonum::helpers::{Height, ValidatorId};
 use exonum::blockchain::Transaction;
 use exonum::encoding::serialize::FromHex;
 use exonum_testkit::TestNetworkConfiguration;
 
-use exonum_btc_anchoring::{AnchoringConfig, ANCHORING_SERVICE_NAME};
+use exonum_btc_anchoring::{verify_anchoring_chain, verify_anchoring_txs, AnchoringConfig,
+                           ANCHORING_SERVICE_NAME};
+use exonum_btc_anchoring::blockchain::AnchoringChainIssue;
 use exonum_btc_anchoring::handler::error::Error as HandlerError;
 use exonum_btc_anchoring::blockchain::dto::MsgAnchoringUpdateLatest;
 use exonum_btc_anchoring::details::btc::transactions::BitcoinTx;
@@ -141,6 +145,31 @@ fn test_auditing_exclude_node_from_validators() {
     exclude_node_from_validators(&mut testkit);
 }
 
+// Validator node has no anchoring private keys and external signer
+// problems: none
+// result: node observes the anchoring chain without signing proposals
+#[test]
+fn test_auditing_validator_without_private_keys() {
+    let mut testkit = AnchoringTestKit::default();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+    testkit.handler().node.private_keys.clear();
+
+    // Anchoring height passes without proposal signatures.
+    let height = testkit.next_check_lect_height();
+    testkit.create_blocks_until(height);
+    assert!(testkit.mempool().is_empty());
+
+    let anchored_tx = testkit.latest_anchored_tx();
+    testkit.requests().expect(
+        vec![get_transaction_request(&anchored_tx)],
+    );
+    testkit.create_block();
+    assert!(testkit.mempool().is_empty());
+    assert_eq!(testkit.take_handler_errors(), Vec::new());
+}
+
 // There is no consensus in `exonum` about current `lect`.
 // result: Error LectNotFound occured
 #[test]
@@ -284,3 +313,71 @@ fn test_auditing_lect_lost_current_lect() {
         HandlerError::LectNotFound { height: Height(0) }
     );
 }
+
+// Auditor verifies the anchoring chain
+// problems: none
+// result: the chain from the funding tx up to the lect is correct
+#[test]
+fn test_auditing_verify_anchoring_chain() {
+    let mut testkit = AnchoringTestKit::default();
+
+    let report = verify_anchoring_chain(&*testkit.snapshot());
+    assert!(report.is_valid());
+    assert_eq!(report.funding_tx, Some(testkit.current_funding_tx().id()));
+    assert!(report.anchors.is_empty());
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let anchored_tx = testkit.latest_anchored_tx();
+    let report = verify_anchoring_chain(&*testkit.snapshot());
+    assert_eq!(report.issues, Vec::new());
+    assert_eq!(report.funding_tx, Some(testkit.current_funding_tx().id()));
+    assert_eq!(report.anchors.len(), 1);
+    assert_eq!(report.anchors[0].txid, anchored_tx.id());
+    assert_eq!(report.anchors[0].block_height, Height::zero());
+    assert_eq!(
+        report.anchors[0].block_hash,
+        testkit.block_hash_on_height(Height::zero())
+    );
+}
+
+// Auditor verifies the anchoring chain by the bitcoin transactions only
+// problems: the hash of the anchored block differs from the trusted one
+// result: the chain is correct only with the matching block hashes
+#[test]
+fn test_auditing_verify_anchoring_txs() {
+    let mut testkit = AnchoringTestKit::default();
+    let genesis_cfg = testkit.current_cfg();
+
+    anchor_first_block(&mut testkit);
+    anchor_first_block_lect_normal(&mut testkit);
+
+    let anchored_tx = testkit.latest_anchored_tx();
+    let txs = vec![
+        BitcoinTx::from(testkit.current_funding_tx()),
+        BitcoinTx::from(anchored_tx.clone()),
+    ];
+    let mut block_hashes = BTreeMap::new();
+    block_hashes.insert(0, testkit.block_hash_on_height(Height::zero()));
+    let report = verify_anchoring_txs(&genesis_cfg, &anchored_tx.id(), &txs, &block_hashes);
+    assert_eq!(report.issues, Vec::new());
+    assert_eq!(report.funding_tx, Some(testkit.current_funding_tx().id()));
+    assert_eq!(report.anchors.len(), 1);
+    assert_eq!(report.anchors[0].txid, anchored_tx.id());
+
+    let other_hash = testkit.block_hash_on_height(Height(1));
+    block_hashes.insert(0, other_hash);
+    let report = verify_anchoring_txs(&genesis_cfg, &anchored_tx.id(), &txs, &block_hashes);
+    assert_eq!(
+        report.issues,
+        vec![
+            AnchoringChainIssue::BlockHashMismatch {
+                txid: anchored_tx.id(),
+                block_height: Height::zero(),
+                block_hash: testkit.block_hash_on_height(Height::zero()),
+                expected: Some(other_hash),
+            },
+        ]
+    );
+}
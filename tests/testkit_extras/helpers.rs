@@ -15,7 +15,6 @@
 // FIXME: Sometimes clippy incorrectly calculates lifetimes.
 #![cfg_attr(feature="cargo-clippy", allow(let_and_return))]
 
-use bitcoin::util::base58::ToBase58;
 use serde_json::Value;
 
 use exonum::messages::Message;
@@ -27,7 +26,10 @@ use exonum_testkit::{TestKit, TestNetworkConfiguration};
 use exonum_btc_anchoring::{AnchoringConfig, ANCHORING_SERVICE_NAME};
 use exonum_btc_anchoring::details::btc;
 use exonum_btc_anchoring::details::btc::transactions::{BitcoinTx, RawBitcoinTx, TxFromRaw};
-use exonum_btc_anchoring::blockchain::dto::{MsgAnchoringSignature, MsgAnchoringUpdateLatest};
+use exonum_btc_anchoring::blockchain::dto::{MsgAnchoringFeeRate, MsgAnchoringFunding,
+                                            MsgAnchoringKeyRotation, MsgAnchoringPause,
+                                            MsgAnchoringSignature, MsgAnchoringSweep,
+                                            MsgAnchoringTrigger, MsgAnchoringUpdateLatest};
 use exonum_btc_anchoring::blockchain::schema::AnchoringSchema;
 
 use super::{AnchoringTestKit, TestRequest};
@@ -69,6 +71,67 @@ pub fn gen_service_tx_lect_wrong(
     )
 }
 
+pub fn gen_service_tx_funding(
+    testkit: &TestKit,
+    validator: ValidatorId,
+    tx: &RawBitcoinTx,
+) -> MsgAnchoringFunding {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    MsgAnchoringFunding::new(keypair.0, validator, BitcoinTx::from(tx.clone()), keypair.1)
+}
+
+pub fn gen_service_tx_sweep(
+    testkit: &TestKit,
+    validator: ValidatorId,
+    address: &str,
+) -> MsgAnchoringSweep {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    MsgAnchoringSweep::new(keypair.0, validator, address, keypair.1)
+}
+
+pub fn gen_service_tx_pause(
+    testkit: &TestKit,
+    validator: ValidatorId,
+    reason: &str,
+) -> MsgAnchoringPause {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    let height = testkit.height().0;
+    MsgAnchoringPause::new(keypair.0, validator, reason, height, keypair.1)
+}
+
+pub fn gen_service_tx_fee_rate(
+    testkit: &TestKit,
+    validator: ValidatorId,
+    fee_rate: u64,
+) -> MsgAnchoringFeeRate {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    let height = testkit.height().0;
+    MsgAnchoringFeeRate::new(keypair.0, validator, fee_rate, height, keypair.1)
+}
+
+pub fn gen_service_tx_trigger(testkit: &TestKit, validator: ValidatorId) -> MsgAnchoringTrigger {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    let height = testkit.height().0;
+    MsgAnchoringTrigger::new(keypair.0, validator, height, keypair.1)
+}
+
+pub fn gen_service_tx_key_rotation(
+    testkit: &TestKit,
+    validator: ValidatorId,
+    target: ValidatorId,
+    key: &str,
+) -> MsgAnchoringKeyRotation {
+    let keypair = testkit.network().validators()[validator.0 as usize].service_keypair();
+    let cfg_hash = AnchoringSchema::new(&testkit.snapshot()).actual_config_hash();
+    MsgAnchoringKeyRotation::new(keypair.0, validator, target, &cfg_hash, key, keypair.1)
+}
+
+pub fn dump_funding_notices(testkit: &TestKit) -> Vec<btc::TxId> {
+    let snapshot = testkit.snapshot();
+    let keys = AnchoringSchema::new(&snapshot).funding_notices().keys().collect();
+    keys
+}
+
 pub fn dump_lects(testkit: &TestKit, id: ValidatorId) -> Vec<BitcoinTx> {
     let anchoring_schema = AnchoringSchema::new(testkit.snapshot());
     let key = &anchoring_schema.actual_anchoring_config().anchoring_keys[id.0 as usize];
@@ -193,7 +256,7 @@ pub fn listunspent_entry(raw: &RawBitcoinTx, addr: &btc::Address, confirmations:
     let tx = BitcoinTx::from_raw(raw.clone()).unwrap();
     json!({
         "txid": &tx.txid(),
-        "address": &addr.to_base58check(),
+        "address": &addr.to_string(),
         "confirmations": confirmations,
         "vout": 0,
         "account": "multisig",
@@ -213,7 +276,7 @@ pub fn anchor_first_block(testkit: &mut AnchoringTestKit) {
         confirmations_request(&testkit.current_funding_tx(), 50),
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&testkit.current_funding_tx(), &anchoring_addr, 50)
             ]
@@ -302,7 +365,7 @@ pub fn anchor_first_block_lect_different(testkit: &mut AnchoringTestKit) {
     requests.expect(vec![
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&other_lect, &anchoring_addr, 0)
             ]
@@ -337,7 +400,7 @@ pub fn anchor_first_block_lect_lost(testkit: &mut AnchoringTestKit) {
     requests.expect(vec![
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&other_lect, &anchoring_addr, 0)
             ]
@@ -358,7 +421,7 @@ pub fn anchor_first_block_lect_lost(testkit: &mut AnchoringTestKit) {
         confirmations_request(&testkit.current_funding_tx(), 50),
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&other_lect, &anchoring_addr, 100)
             ]
@@ -396,7 +459,7 @@ pub fn anchor_second_block_normal(testkit: &mut AnchoringTestKit) {
     requests.expect(vec![
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&testkit.latest_anchored_tx(), &anchoring_addr, 1)
             ]
@@ -429,7 +492,7 @@ pub fn anchor_second_block_normal(testkit: &mut AnchoringTestKit) {
     requests.expect(vec![
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&anchored_tx, &anchoring_addr, 100)
             ]
@@ -448,7 +511,7 @@ pub fn anchor_first_block_without_other_signatures(testkit: &mut AnchoringTestKi
         confirmations_request(&testkit.current_funding_tx(), 50),
         request! {
             method: "listunspent",
-            params: [0, 9_999_999, [&anchoring_addr.to_base58check()]],
+            params: [0, 9_999_999, [&anchoring_addr.to_string()]],
             response: [
                 listunspent_entry(&testkit.current_funding_tx(), &anchoring_addr, 50)
             ]
@@ -16,33 +16,33 @@
 macro_rules! request {
     (
         method: $method:expr,
-        params: [$($params:tt)+]
+        params: [$($params:tt)*]
     ) => {
         $crate::testkit_extras::TestRequest {
             method: $method,
-            params: json!([$($params)+]).as_array().unwrap().clone(),
+            params: json!([$($params)*]).as_array().unwrap().clone(),
             response: Ok(::serde_json::Value::Null)
         }
     };
     (
         method: $method:expr,
-        params: [$($params:tt)+],
+        params: [$($params:tt)*],
         response: $($response:tt)+
     ) => {
         $crate::testkit_extras::TestRequest {
             method: $method,
-            params: json!([$($params)+]).as_array().unwrap().clone(),
+            params: json!([$($params)*]).as_array().unwrap().clone(),
             response: Ok(json!($($response)+)),
         }
     };
     (
         method: $method:expr,
-        params: [$($params:tt)+],
+        params: [$($params:tt)*],
         error: $($err:tt)+
     ) => {
         $crate::testkit_extras::TestRequest {
             method: $method,
-            params: json!([$($params)+]).as_array().unwrap().clone(),
+            params: json!([$($params)*]).as_array().unwrap().clone(),
             response: Err($($err)+)
         }
     };
@@ -30,6 +30,7 @@ use exonum_btc_anchoring::details::btc;
 use exonum_btc_anchoring::details::btc::transactions::{AnchoringTx, FundingTx, TransactionBuilder};
 use exonum_btc_anchoring::blockchain::dto::MsgAnchoringSignature;
 use exonum_btc_anchoring::handler::{collect_signatures, AnchoringHandler};
+use exonum_btc_anchoring::handler::alert::AnchoringAlert;
 use exonum_btc_anchoring::error::HandlerError;
 pub use self::rpc::{TestClient, TestRequest, TestRequests};
 
@@ -50,6 +51,7 @@ pub struct AnchoringTestKit {
     requests: TestRequests,
     handler: Arc<Mutex<AnchoringHandler>>,
     errors_receiver: mpsc::Receiver<HandlerError>,
+    alerts_receiver: mpsc::Receiver<AnchoringAlert>,
     nodes: Vec<AnchoringNodeConfig>,
     latest_anchored_tx: Option<(AnchoringTx, Vec<MsgAnchoringSignature>)>,
 }
@@ -76,6 +78,20 @@ impl Default for AnchoringTestKit {
 
 impl AnchoringTestKit {
     pub fn new() -> AnchoringTestKit {
+        let testkit = AnchoringTestKit::new_with_pending_import();
+        // The genesis address is imported by the first commit, so import it beforehand
+        // to keep the requests of the first anchoring round unchanged.
+        testkit.requests().expect(vec![
+            request! {
+                method: "importaddress",
+                params: ["2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA", "multisig", false, false]
+            },
+        ]);
+        testkit.handler().import_pending_addresses().unwrap();
+        testkit
+    }
+
+    pub fn new_with_pending_import() -> AnchoringTestKit {
         let mut client = TestClient::default();
         let (mut common, mut nodes) = gen_sandbox_anchoring_config(&mut client);
         // Change default anchoring configs
@@ -85,12 +101,6 @@ impl AnchoringTestKit {
             node.check_lect_frequency = CHECK_LECT_FREQUENCY;
         }
 
-        client.requests().expect(vec![
-            request! {
-                method: "importaddress",
-                params: ["2NFGToas8B6sXqsmtGwL1H4kC5fGWSpTcYA", "multisig", false, false]
-            },
-        ]);
         let requests = client.requests();
         let service =
             AnchoringService::new_with_client(Box::new(client), common.clone(), nodes[0].clone());
@@ -102,6 +112,8 @@ impl AnchoringTestKit {
 
         let (sender, receiver) = mpsc::channel();
         handler.lock().unwrap().set_errors_sink(Some(sender));
+        let (alerts_sender, alerts_receiver) = mpsc::channel();
+        handler.lock().unwrap().set_alerts_sink(Some(alerts_sender));
 
         AnchoringTestKit {
             inner: testkit,
@@ -110,6 +122,7 @@ impl AnchoringTestKit {
             nodes,
             latest_anchored_tx: None,
             errors_receiver: receiver,
+            alerts_receiver,
         }
     }
 
@@ -125,6 +138,10 @@ impl AnchoringTestKit {
         self.errors_receiver.try_iter().collect()
     }
 
+    pub fn take_handler_alerts(&mut self) -> Vec<AnchoringAlert> {
+        self.alerts_receiver.try_iter().collect()
+    }
+
     pub fn priv_keys(&self, addr: &btc::Address) -> Vec<btc::PrivateKey> {
         self.nodes()
             .iter()
@@ -227,7 +244,7 @@ impl AnchoringTestKit {
                 .payload(height, block_hash)
                 .prev_tx_chain(prev_tx_chain)
                 .send_to(addr.clone())
-                .fee(1000);
+                .fee(self.current_cfg().fee);
             for fund in funds {
                 let out = fund.find_out(addr).unwrap();
                 builder = builder.add_funds(fund, out);
@@ -316,7 +333,9 @@ fn gen_sandbox_anchoring_config(
     let mut rng: StdRng = SeedableRng::from_seed([1, 2, 3, 4].as_ref());
     gen_anchoring_testnet_config_with_rng(
         client,
-        btc::Network::Testnet,
+        btc::Chain::Testnet,
+        btc::ScriptType::P2sh,
+        None,
         4,
         ANCHORING_FUNDS,
         &mut rng,
@@ -234,6 +234,36 @@ impl BitcoinRelay for TestClient {
         Ok(txs)
     }
 
+    fn unspent_outputs(&self, addr: &btc::Address) -> Result<Vec<(btc::TxId, u32)>> {
+        let unspent_txs = self.listunspent(0, 9_999_999, [addr.to_string().as_ref()])?;
+        let outputs = unspent_txs
+            .into_iter()
+            .map(|info| (btc::TxId::from_hex(&info.txid).unwrap(), info.vout))
+            .collect();
+        Ok(outputs)
+    }
+
+    fn get_transaction_proof(&self, txid: btc::TxId) -> Result<Option<String>> {
+        let params = json!([[txid.to_string()]]).as_array().cloned().unwrap();
+        self.request("gettxoutproof", params).map(Some)
+    }
+
+    fn get_block_headers(&self, block_hash: &str, count: u64) -> Result<Vec<String>> {
+        let mut headers = Vec::new();
+        let mut block_hash = block_hash.to_string();
+        while (headers.len() as u64) < count {
+            let params = json!([block_hash, true]).as_array().cloned().unwrap();
+            let info: Value = self.request("getblockheader", params)?;
+            block_hash = match info.get("nextblockhash").and_then(Value::as_str) {
+                Some(next_hash) => next_hash.to_string(),
+                None => break,
+            };
+            let params = json!([block_hash, false]).as_array().cloned().unwrap();
+            headers.push(self.request("getblockheader", params)?);
+        }
+        Ok(headers)
+    }
+
     fn config(&self) -> AnchoringRpcConfig {
         self.rpc.clone()
     }
@@ -247,6 +277,7 @@ impl Default for TestClient {
                 host: "127.0.0.1:1024".into(),
                 username: None,
                 password: None,
+                ..Default::default()
             },
         }
     }
@@ -270,6 +270,10 @@ fn test_transit_changed_self_key_normal() {
 
     requests.expect(vec![confirmations_request(&transition_tx, 0)]);
     testkit.create_block_with_transactions(lects);
+    assert_eq!(
+        AnchoringSchema::new(&testkit.snapshot()).pending_transfer(),
+        Some(transition_tx.clone())
+    );
 
     for i in testkit.height().next().0..cfg_change_height.previous().0 {
         requests.expect(vec![confirmations_request(&transition_tx, 15 + i)]);
@@ -344,6 +348,59 @@ fn test_transit_changed_self_key_normal() {
         .collect::<Vec<_>>();
     assert!(testkit.mempool().contains_key(&lects[0].hash()));
     testkit.create_block_with_transactions(lects);
+    assert_eq!(AnchoringSchema::new(&testkit.snapshot()).pending_transfer(), None);
+}
+
+// We commit a new configuration with the changed self key before the first anchoring tx
+// problems:
+// - none
+// result: funds are transferred to the following address directly from the funding tx
+#[test]
+fn test_transit_changed_self_key_before_first_anchoring_tx() {
+    let cfg_change_height = Height(16);
+
+    let mut testkit = AnchoringTestKit::default();
+    let requests = testkit.requests();
+
+    let (cfg_proposal, following_cfg) = gen_following_cfg(&mut testkit, cfg_change_height, None);
+    testkit.commit_configuration_change(cfg_proposal);
+    let (_, following_addr) = following_cfg.redeem_script();
+
+    requests.expect(vec![
+        request! {
+            method: "importaddress",
+            params: [&following_addr, "multisig", false, false]
+        },
+        confirmations_request(&testkit.current_funding_tx(), 50),
+    ]);
+    let hash = testkit.last_block_hash();
+    let (_, signatures) = testkit.gen_anchoring_tx_with_signatures(
+        Height::zero(),
+        hash,
+        &[],
+        None,
+        &following_addr,
+    );
+    let transition_tx = testkit.latest_anchored_tx();
+    testkit.create_block();
+    assert!(testkit.mempool().contains_key(&signatures[0].hash()));
+
+    requests.expect(send_raw_transaction_requests(&transition_tx));
+    testkit.create_block_with_transactions(signatures);
+
+    let lects = (0..4)
+        .map(ValidatorId)
+        .map(|id| gen_service_tx_lect(&testkit, id, &transition_tx, 1))
+        .map(Box::<Transaction>::from)
+        .collect::<Vec<_>>();
+    assert!(testkit.mempool().contains_key(&lects[0].hash()));
+
+    requests.expect(vec![confirmations_request(&transition_tx, 0)]);
+    testkit.create_block_with_transactions(lects);
+    assert_eq!(
+        transition_tx.output_address(btc::Network::Testnet),
+        following_addr
+    );
 }
 
 // We commit a new configuration and take actions to transit tx chain to the new address